@@ -0,0 +1,59 @@
+//! Mirrors the raw archive of every document in an account into a directory,
+//! a few at a time.
+//!
+//! Usage:
+//! `cargo run --example download_all -- <client_state.json> <output dir>`
+
+use std::fs;
+use std::path::PathBuf;
+
+use futures_util::stream::{self, StreamExt};
+use remarkable_cloud_api::{Client, ClientState};
+
+const CONCURRENCY: usize = 4;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args_os().skip(1);
+    let (state_path, out_dir) = match (args.next(), args.next()) {
+        (Some(s), Some(o)) => (PathBuf::from(s), PathBuf::from(o)),
+        _ => {
+            return Err(
+                "usage: download_all <client_state.json> <output dir>".into()
+            )
+        }
+    };
+
+    let mut client = Client::new(ClientState::new(), reqwest::Client::new());
+    client.state().load_from_path(&state_path)?;
+    client.refresh_token().await?;
+    fs::create_dir_all(&out_dir)?;
+
+    let docs = client.get_documents().await?;
+    let wanted: Vec<_> = docs.iter().filter(|d| d.is_document()).collect();
+    let total = wanted.len();
+
+    let client = &client;
+    let out_dir = &out_dir;
+    let mut results = stream::iter(wanted)
+        .map(|doc| async move {
+            let blobdoc = client.get_document_by_id(&doc.id).await?;
+            let bytes = client.download_blob(&blobdoc).await?;
+            fs::write(out_dir.join(format!("{}.zip", doc.id)), &bytes)?;
+            Ok::<_, remarkable_cloud_api::Error>((doc, bytes.len()))
+        })
+        .buffer_unordered(CONCURRENCY);
+
+    let mut done = 0;
+    while let Some(result) = results.next().await {
+        done += 1;
+        match result {
+            Ok((doc, len)) => println!(
+                "[{}/{}] {} ({} bytes)",
+                done, total, doc.visible_name, len
+            ),
+            Err(e) => println!("[{}/{}] failed: {}", done, total, e),
+        }
+    }
+    Ok(())
+}
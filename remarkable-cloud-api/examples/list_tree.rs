@@ -0,0 +1,34 @@
+//! Prints the whole document tree of an account.
+//!
+//! Usage: `cargo run --example list_tree -- <client_state.json>`, where the
+//! state file is the one saved by the `remarkable-cloud` CLI.
+
+use std::path::PathBuf;
+
+use remarkable_cloud_api::{Client, ClientState, Documents};
+use uuid::Uuid;
+
+fn print_tree(docs: &Documents, parent: &Option<Uuid>, depth: usize) {
+    let mut children = docs.get_children(parent);
+    children.sort_by(|a, b| a.visible_name.cmp(&b.visible_name));
+    for doc in children {
+        println!("{}{}", "  ".repeat(depth), doc.visible_name);
+        print_tree(docs, &Some(doc.id), depth + 1);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let state_path = match std::env::args_os().nth(1) {
+        Some(p) => PathBuf::from(p),
+        None => return Err("usage: list_tree <client_state.json>".into()),
+    };
+
+    let mut client = Client::new(ClientState::new(), reqwest::Client::new());
+    client.state().load_from_path(&state_path)?;
+    client.refresh_token().await?;
+
+    let docs = client.get_documents().await?;
+    print_tree(&docs, &None, 0);
+    Ok(())
+}
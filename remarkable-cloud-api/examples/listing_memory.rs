@@ -0,0 +1,82 @@
+//! Compares reading a large listing whole with streaming it, by the time
+//! to the first document, the time to the last, and the peak memory of the
+//! process, on a synthetic account in the fake cloud.
+//!
+//! Usage: `cargo run --release --example listing_memory --features testing
+//! -- <whole|stream> [documents]`, with 20000 documents unless given. Run
+//! each way in a process of its own, as the peak is for the whole process;
+//! the fake cloud's own copy of the listing counts towards both.
+
+use std::time::Instant;
+
+use futures_util::StreamExt;
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::{Documents, ListingEntry};
+
+// The most memory the process has held, in KiB, where Linux says.
+fn peak_rss_kib() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let mode = args.next().unwrap_or_default();
+    let count: usize = match args.next() {
+        Some(n) => n.parse()?,
+        None => 20_000,
+    };
+
+    let cloud = FakeCloud::start().await;
+    let mut folders = vec![];
+    for n in 0..count / 100 {
+        folders.push(cloud.add_folder(&format!("Folder {}", n), None));
+    }
+    for n in 0..count - folders.len() {
+        let parent = folders.get(n % folders.len().max(1)).copied();
+        cloud.add_document(&format!("Document {}", n), parent, vec![]);
+    }
+    let mut client = cloud.client();
+    client.refresh_token().await?;
+    let before = peak_rss_kib();
+
+    let start = Instant::now();
+    let (first, seen) = match mode.as_str() {
+        "whole" => {
+            let documents = client.get_documents().await?;
+            (start.elapsed(), documents.len())
+        }
+        "stream" => {
+            let mut stream = client.stream_documents().await?;
+            let mut first = None;
+            let mut seen = 0;
+            while let Some(entry) = stream.next().await {
+                if let ListingEntry::Document(_) = entry? {
+                    first.get_or_insert_with(|| start.elapsed());
+                    seen += 1;
+                }
+            }
+            (first.unwrap_or_default(), seen)
+        }
+        "collect" => {
+            let stream = client.stream_documents().await?;
+            let documents = Documents::from_stream(stream).await?;
+            (start.elapsed(), documents.len())
+        }
+        _ => return Err("usage: listing_memory <whole|stream|collect>".into()),
+    };
+    let last = start.elapsed();
+
+    println!("{} documents read {}", seen, mode);
+    println!("first document after {:?}, last after {:?}", first, last);
+    if let (Some(before), Some(after)) = (before, peak_rss_kib()) {
+        println!(
+            "peak memory {} KiB, {} KiB more than with the account set up",
+            after,
+            after.saturating_sub(before)
+        );
+    }
+    Ok(())
+}
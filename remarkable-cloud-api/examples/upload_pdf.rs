@@ -0,0 +1,59 @@
+//! Uploads a PDF to the fake cloud in the `testing` module a stage at a
+//! time, as a client that saves its progress between stages would, then
+//! reads it back.
+//!
+//! Usage: `cargo run --example upload_pdf --features testing [-- <file.pdf>]`;
+//! without a file, a one-line PDF is made up.
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::{DocType, DocumentArchiveBuilder, Upload};
+use remarkable_data_formats::content::Content;
+use uuid::Uuid;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (name, pdf) = match std::env::args_os().nth(1) {
+        Some(path) => {
+            let path = std::path::PathBuf::from(path);
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            (name.into_owned(), std::fs::read(&path)?)
+        }
+        None => ("Hello".to_string(), b"%PDF-1.4\n%%EOF\n".to_vec()),
+    };
+
+    let cloud = FakeCloud::start().await;
+    let mut client = cloud.client();
+    client.refresh_token().await?;
+
+    // The archive holds the PDF and a .content saying what it is.
+    let id = Uuid::new_v4();
+    let zip = DocumentArchiveBuilder::new()
+        .content(Content {
+            file_type: "pdf".to_string(),
+            ..Default::default()
+        })
+        .payload_pdf(pdf)
+        .build(id)?;
+
+    // `Client::upload_zip` does all of this at once; going a stage at a
+    // time lets `upload` be saved in between and finished after a crash.
+    let mut upload = Upload::new(id, 1, None, &name, DocType::Document);
+    while !upload.is_done() {
+        client.advance_upload(&mut upload, &zip).await?;
+        println!("{:?}", upload.stage);
+    }
+
+    let doc = client.get_document_by_id(&id).await?;
+    let stored = client.download_blob(&doc).await?;
+    println!(
+        "Uploaded {} as version {}, {} bytes",
+        doc.visible_name,
+        doc.version,
+        stored.len()
+    );
+
+    if stored != zip {
+        return Err("fake cloud stored something else".into());
+    }
+    Ok(())
+}
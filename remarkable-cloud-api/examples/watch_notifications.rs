@@ -0,0 +1,65 @@
+//! Watches the fake cloud in the `testing` module for changes another client
+//! makes, printing what was added, updated and removed.
+//!
+//! The cloud says whether it notifies clients of changes in its
+//! capabilities. Either way, a listing cache makes polling cheap: an
+//! unchanged listing is revalidated with its `ETag` rather than sent again.
+//!
+//! Usage: `cargo run --example watch_notifications --features testing`
+
+use std::time::Duration;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::ListingCache;
+
+const POLLS: usize = 3;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+
+    let mut client = cloud.client();
+    client.refresh_token().await?;
+    let capabilities = client.capabilities().await?;
+    println!("Notifications offered: {}", capabilities.notifications);
+    // Revalidate on every poll, rather than reusing a listing fetched
+    // only just now.
+    client.set_listing_cache(Some(
+        ListingCache::new().with_ttl(Duration::from_secs(0)),
+    ));
+
+    let mut seen = client.get_documents().await?;
+    let mut changes = 0;
+    for poll in 0..POLLS {
+        // What another client might do between polls.
+        match poll {
+            0 => {
+                cloud.add_document("Hyperion", Some(books), vec![]);
+            }
+            1 => cloud.modify(&dune, |d| d.version += 1),
+            _ => {}
+        }
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+
+        let current = client.get_documents().await?;
+        let diff = current.diff(&seen);
+        for doc in &diff.added {
+            println!("Added {}", doc.visible_name);
+        }
+        for doc in &diff.updated {
+            println!("Updated {} to version {}", doc.visible_name, doc.version);
+        }
+        for id in &diff.removed {
+            println!("Removed {}", id);
+        }
+        changes += diff.added.len() + diff.updated.len() + diff.removed.len();
+        seen = current;
+    }
+
+    if changes != 2 {
+        return Err(format!("saw {} changes rather than 2", changes).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,881 @@
+//! Putting together the zip archive the cloud stores a document as, hashing
+//! what's in one, checking one is whole, and summing one up.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use remarkable_data_formats::content::Content;
+use remarkable_data_formats::metadata::Metadata;
+use remarkable_data_formats::pagedata::PageData;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::details::read_entry;
+use crate::error::{Error, Result};
+use crate::pages::Pages;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Payload {
+    Pdf(PayloadData),
+    Epub(PayloadData),
+}
+
+// Where the payload is: given, or to be copied from a file as the archive
+// is written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PayloadData {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+/// Builds a document's archive from its parts.
+///
+/// The archive is the same for the same parts, whatever order they were
+/// given in: entries are written in a fixed order with no timestamps, so
+/// archives can be compared byte for byte.
+#[derive(Clone, Debug, Default)]
+pub struct DocumentArchiveBuilder {
+    content: Option<Content>,
+    metadata: Option<Metadata>,
+    pagedata: Option<PageData>,
+    payload: Option<Payload>,
+    pages: BTreeMap<usize, Vec<u8>>,
+    thumbnails: BTreeMap<usize, Vec<u8>>,
+    extra: BTreeMap<String, Vec<u8>>,
+}
+
+impl DocumentArchiveBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The `.content` file, which every archive needs.
+    pub fn content(mut self, content: Content) -> Self {
+        self.content = Some(content);
+        self
+    }
+
+    pub fn metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn pagedata(mut self, pagedata: PageData) -> Self {
+        self.pagedata = Some(pagedata);
+        self
+    }
+
+    /// The imported PDF. The content must say `pdf`.
+    pub fn payload_pdf(mut self, pdf: Vec<u8>) -> Self {
+        self.payload = Some(Payload::Pdf(PayloadData::Bytes(pdf)));
+        self
+    }
+
+    /// The imported EPUB. The content must say `epub`.
+    pub fn payload_epub(mut self, epub: Vec<u8>) -> Self {
+        self.payload = Some(Payload::Epub(PayloadData::Bytes(epub)));
+        self
+    }
+
+    /// The imported PDF, copied from the file at `path` as the archive is
+    /// written rather than held in memory.
+    pub fn payload_pdf_file(mut self, path: &Path) -> Self {
+        self.payload = Some(Payload::Pdf(PayloadData::File(path.into())));
+        self
+    }
+
+    /// The imported EPUB, copied from the file at `path` as the archive is
+    /// written.
+    pub fn payload_epub_file(mut self, path: &Path) -> Self {
+        self.payload = Some(Payload::Epub(PayloadData::File(path.into())));
+        self
+    }
+
+    /// The strokes drawn on page `index`, counting from 0, in the `.rm`
+    /// format.
+    pub fn page_rm(mut self, index: usize, rm: Vec<u8>) -> Self {
+        self.pages.insert(index, rm);
+        self
+    }
+
+    /// The JPEG the tablet shows for page `index`.
+    pub fn thumbnail(mut self, index: usize, jpeg: Vec<u8>) -> Self {
+        self.thumbnails.insert(index, jpeg);
+        self
+    }
+
+    /// A file the tablet doesn't know about, named `{id}.{suffix}` in the
+    /// archive.
+    pub(crate) fn extra(mut self, suffix: &str, data: Vec<u8>) -> Self {
+        self.extra.insert(suffix.to_string(), data);
+        self
+    }
+
+    // Checks that the parts agree with each other, returning the content.
+    fn check(&self) -> Result<&Content> {
+        let invalid = |reason: String| Err(Error::InvalidArchive { reason });
+        let content = match &self.content {
+            Some(content) => content,
+            None => return invalid("there's no .content".to_string()),
+        };
+        let declared = content.file_type.as_str();
+        match (&self.payload, declared) {
+            (Some(Payload::Pdf(_)), "pdf")
+            | (Some(Payload::Epub(_)), "epub") => {}
+            (None, "pdf") | (None, "epub") => {
+                return invalid(format!(
+                    "the content says {} but there's none",
+                    declared
+                ))
+            }
+            (Some(payload), _) => {
+                let kind = match payload {
+                    Payload::Pdf(_) => "pdf",
+                    Payload::Epub(_) => "epub",
+                };
+                return invalid(format!(
+                    "the payload is {} but the content says {:?}",
+                    kind, declared
+                ));
+            }
+            (None, _) => {}
+        }
+        if let Some(count) = content.page_count {
+            let count = count as usize;
+            if let Some(pagedata) = &self.pagedata {
+                if pagedata.templates.len() != count {
+                    return invalid(format!(
+                        "the content has {} pages but the pagedata {}",
+                        count,
+                        pagedata.templates.len()
+                    ));
+                }
+            }
+            let last = self.pages.keys().chain(self.thumbnails.keys()).max();
+            if let Some(last) = last.filter(|i| **i >= count) {
+                return invalid(format!(
+                    "there's a page {} but the content has {} pages",
+                    last, count
+                ));
+            }
+        }
+        Ok(content)
+    }
+
+    /// Writes the archive of document `id`, or fails with
+    /// `Error::InvalidArchive` if the parts don't fit together.
+    pub fn build(&self, id: Uuid) -> Result<Vec<u8>> {
+        Ok(self.build_to(id, io::Cursor::new(vec![]))?.into_inner())
+    }
+
+    /// Writes the archive of document `id` to `writer`, as `build` does,
+    /// giving the writer back.
+    pub fn build_to<W: Write + Seek>(&self, id: Uuid, writer: W) -> Result<W> {
+        let content = self.check()?;
+        let mut entries: Vec<(String, &[u8])> = vec![];
+        let content = content.to_vec();
+        entries.push((format!("{}.content", id), &content));
+        let metadata = self.metadata.as_ref().map(Metadata::to_vec);
+        if let Some(metadata) = &metadata {
+            entries.push((format!("{}.metadata", id), metadata));
+        }
+        let pagedata = self.pagedata.as_ref().map(PageData::to_vec);
+        if let Some(pagedata) = &pagedata {
+            entries.push((format!("{}.pagedata", id), pagedata));
+        }
+        // The payload comes next, and may have to be read from its file.
+        let payload = match &self.payload {
+            Some(Payload::Pdf(pdf)) => Some((format!("{}.pdf", id), pdf)),
+            Some(Payload::Epub(epub)) => Some((format!("{}.epub", id), epub)),
+            None => None,
+        };
+        let before_payload = entries.len();
+        for (index, rm) in &self.pages {
+            entries.push((format!("{}/{}.rm", id, index), rm));
+        }
+        for (index, jpeg) in &self.thumbnails {
+            entries.push((format!("{}.thumbnails/{}.jpg", id, index), jpeg));
+        }
+        for (suffix, data) in &self.extra {
+            entries.push((format!("{}.{}", id, suffix), data));
+        }
+
+        let options = zip::write::FileOptions::default()
+            .last_modified_time(zip::DateTime::default());
+        let mut zip = zip::ZipWriter::new(writer);
+        let (first, rest) = entries.split_at(before_payload);
+        for (name, data) in first {
+            zip.start_file(name, options)?;
+            zip.write_all(data)?;
+        }
+        if let Some((name, payload)) = payload {
+            zip.start_file(name, options)?;
+            match payload {
+                PayloadData::Bytes(data) => zip.write_all(data)?,
+                PayloadData::File(path) => {
+                    io::copy(&mut fs::File::open(path)?, &mut zip)?;
+                }
+            }
+        }
+        for (name, data) in rest {
+            zip.start_file(name, options)?;
+            zip.write_all(data)?;
+        }
+        Ok(zip.finish()?)
+    }
+}
+
+/// The version of the construction `content_hash` uses, which is hashed
+/// first. It changes if the construction ever does, so hashes made one way
+/// never match hashes made another.
+pub const CONTENT_HASH_VERSION: u8 = 1;
+
+/// Builds the hash of an archive's contents from its entries, given in any
+/// order. See `content_hash` for how it's made.
+///
+/// Each entry's contents are hashed as they're read, so only the names and
+/// their hashes are kept, not the contents.
+#[derive(Clone, Debug, Default)]
+pub struct ContentHasher {
+    entries: Vec<(String, [u8; 32])>,
+}
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds the file `name`, reading its decompressed contents from `data`.
+    pub fn add_entry(&mut self, name: &str, data: &mut dyn Read) -> Result<()> {
+        let mut hasher = Sha256::new();
+        io::copy(data, &mut hasher)?;
+        self.entries
+            .push((name.to_string(), hasher.finalize().into()));
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        self.entries.sort();
+        let mut hasher = Sha256::new();
+        hasher.update([CONTENT_HASH_VERSION]);
+        for (name, digest) in &self.entries {
+            hasher.update((name.len() as u64).to_le_bytes());
+            hasher.update(name.as_bytes());
+            hasher.update(digest);
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// A hash of what's in a document's archive, which stays the same however
+/// the archive is written.
+///
+/// The cloud doesn't give back archives byte for byte as they were put, so
+/// their raw hashes can't be compared. This hashes only the names and
+/// contents of the files: timestamps, compression, comments and the order
+/// of entries make no difference, and directory entries are left out.
+///
+/// It's the SHA-256 of the byte `CONTENT_HASH_VERSION`, followed, for each
+/// file in order of its name's bytes, by the length of the name as a
+/// little-endian `u64`, the name in UTF-8, and the SHA-256 of the file's
+/// decompressed contents.
+pub fn content_hash<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+) -> Result<[u8; 32]> {
+    let mut hasher = ContentHasher::new();
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if !file.is_dir() {
+            let name = file.name().to_string();
+            hasher.add_entry(&name, &mut file)?;
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// `content_hash` of an archive read from start to end, as it's being
+/// downloaded, without needing it all at once.
+///
+/// This relies on the size of each file being given before its contents,
+/// so archives written by streaming writers, which only give it after,
+/// fail with `Error::ZipError`; hash those with `content_hash` once they're
+/// complete.
+pub fn content_hash_stream<R: Read>(reader: &mut R) -> Result<[u8; 32]> {
+    let mut hasher = ContentHasher::new();
+    while let Some(mut file) = zip::read::read_zipfile_from_stream(reader)? {
+        if !file.is_dir() {
+            let name = file.name().to_string();
+            hasher.add_entry(&name, &mut file)?;
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// How one of the checks `verify` makes went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckResult {
+    Passed,
+    Failed(String),
+    /// Not made, as a check it depends on failed.
+    Skipped,
+}
+
+/// One of the checks `verify` makes, named for reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveCheck {
+    pub name: &'static str,
+    pub result: CheckResult,
+}
+
+/// What `verify` found of an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveVerification {
+    /// Each check, in the order made.
+    pub checks: Vec<ArchiveCheck>,
+    /// The archive's `content_hash`, if every entry could be read.
+    pub content_hash: Option<[u8; 32]>,
+}
+
+impl ArchiveVerification {
+    /// Whether no check failed.
+    pub fn is_ok(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|c| !matches!(c.result, CheckResult::Failed(_)))
+    }
+
+    fn push(&mut self, name: &'static str, result: CheckResult) {
+        self.checks.push(ArchiveCheck { name, result });
+    }
+}
+
+/// Checks the archive of document `id` end to end, as far as the tablet
+/// would read it:
+///
+/// - `zip`: the central directory can be read,
+/// - `entries`: every entry can be read and matches its CRC,
+/// - `content`: there's a `.content` and it parses,
+/// - `pages`: the files the content refers to are there, the PDF or EPUB
+///   it's a view of, or each notebook page's `.rm` file.
+///
+/// A corrupt archive is what this is for, so it's reported in the checks
+/// rather than as an error.
+pub fn verify(id: Uuid, zip: &[u8]) -> ArchiveVerification {
+    let mut verification = ArchiveVerification {
+        checks: vec![],
+        content_hash: None,
+    };
+    let mut archive = match zip::ZipArchive::new(io::Cursor::new(zip)) {
+        Ok(archive) => archive,
+        Err(e) => {
+            verification.push("zip", CheckResult::Failed(e.to_string()));
+            for name in &["entries", "content", "pages"] {
+                verification.push(name, CheckResult::Skipped);
+            }
+            return verification;
+        }
+    };
+    verification.push("zip", CheckResult::Passed);
+
+    let mut hasher = ContentHasher::new();
+    let mut unreadable = vec![];
+    for i in 0..archive.len() {
+        let result =
+            archive
+                .by_index(i)
+                .map_err(Error::from)
+                .and_then(|mut file| {
+                    let name = file.name().to_string();
+                    if file.is_dir() {
+                        return Ok(());
+                    }
+                    hasher.add_entry(&name, &mut file).map_err(|e| {
+                        Error::InvalidArchive {
+                            reason: format!("{}: {}", name, e),
+                        }
+                    })
+                });
+        if let Err(e) = result {
+            unreadable.push(e.to_string());
+        }
+    }
+    if unreadable.is_empty() {
+        verification.content_hash = Some(hasher.finish());
+        verification.push("entries", CheckResult::Passed);
+    } else {
+        verification.push(
+            "entries",
+            CheckResult::Failed(format!(
+                "{} of {} unreadable: {}",
+                unreadable.len(),
+                archive.len(),
+                unreadable.join("; ")
+            )),
+        );
+    }
+
+    let content = match read_entry(&mut archive, &format!("{}.content", id)) {
+        Ok(Some(data)) => Content::parse(&data).map_err(|e| e.to_string()),
+        Ok(None) => Err("there's no .content".to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let file_type = match content {
+        Ok(content) => {
+            verification.push("content", CheckResult::Passed);
+            content.file_type
+        }
+        Err(e) => {
+            verification.push("content", CheckResult::Failed(e));
+            verification.push("pages", CheckResult::Skipped);
+            return verification;
+        }
+    };
+
+    let wanted: Vec<String> = match file_type.as_str() {
+        "pdf" | "epub" => vec![format!("{}.{}", id, file_type)],
+        _ => match Pages::read(id, &mut archive) {
+            Ok(pages) => pages
+                .keys
+                .iter()
+                .map(|key| format!("{}/{}.rm", id, key))
+                .collect(),
+            Err(e) => {
+                verification.push("pages", CheckResult::Failed(e.to_string()));
+                return verification;
+            }
+        },
+    };
+    let names: HashSet<&str> = archive.file_names().collect();
+    let missing: Vec<&String> = wanted
+        .iter()
+        .filter(|name| !names.contains(name.as_str()))
+        .collect();
+    let result = match missing.first() {
+        None => CheckResult::Passed,
+        Some(first) => CheckResult::Failed(format!(
+            "{} of {} missing, such as {}",
+            missing.len(),
+            wanted.len(),
+            first
+        )),
+    };
+    verification.push("pages", result);
+    verification
+}
+
+/// What a document's archive says of the document, from its `.metadata`
+/// and `.content` alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveSummary {
+    /// The document's id, which the archive's files are named after.
+    pub id: Uuid,
+    pub metadata: Option<Metadata>,
+    pub content: Option<Content>,
+}
+
+impl ArchiveSummary {
+    /// Reads the summary of the archive in `reader`, without reading the
+    /// pages, PDF or EPUB in it.
+    pub fn read<R: Read + Seek>(reader: R) -> Result<ArchiveSummary> {
+        let mut archive = zip::ZipArchive::new(reader)?;
+        let id = archive
+            .file_names()
+            .filter_map(|name| {
+                let end = name.find(['.', '/'])?;
+                Uuid::parse_str(&name[..end]).ok()
+            })
+            .next()
+            .ok_or_else(|| Error::InvalidArchive {
+                reason: "none of its files is named after a document"
+                    .to_string(),
+            })?;
+        let mut read = |suffix: &str| -> Result<Option<Vec<u8>>> {
+            let mut file = match archive.by_name(&format!("{}{}", id, suffix)) {
+                Ok(file) => file,
+                Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+            let mut data = vec![];
+            file.read_to_end(&mut data)?;
+            Ok(Some(data))
+        };
+        let metadata = match read(".metadata")? {
+            Some(data) => Some(Metadata::parse(&data)?),
+            None => None,
+        };
+        let content = match read(".content")? {
+            Some(data) => Some(Content::parse(&data)?),
+            None => None,
+        };
+        Ok(ArchiveSummary {
+            id,
+            metadata,
+            content,
+        })
+    }
+
+    /// The version of the document the archive was saved at, if its
+    /// `.metadata` says.
+    pub fn version(&self) -> Option<u64> {
+        self.metadata.as_ref().map(|m| m.version).filter(|v| *v > 0)
+    }
+
+    /// When the document was last modified before the archive was saved,
+    /// if its `.metadata` says.
+    pub fn modified(&self) -> Option<DateTime<Utc>> {
+        self.metadata.as_ref().and_then(|m| m.last_modified)
+    }
+
+    /// The document's name, if its `.metadata` gives one.
+    pub fn visible_name(&self) -> Option<&str> {
+        self.metadata
+            .as_ref()
+            .map(|m| m.visible_name.as_str())
+            .filter(|n| !n.is_empty())
+    }
+}
+
+/// The document archives in `dir`, as `pull --raw-zip` saves them, each
+/// with its summary, oldest version first. Files which aren't archives of
+/// a document are left out.
+pub fn summarize_archives(
+    dir: &Path,
+) -> Result<Vec<(PathBuf, ArchiveSummary)>> {
+    let mut found = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if let Ok(summary) = ArchiveSummary::read(fs::File::open(&path)?) {
+            found.push((path, summary));
+        }
+    }
+    found.sort_by(|a, b| {
+        (a.1.version(), a.1.modified(), &a.0).cmp(&(
+            b.1.version(),
+            b.1.modified(),
+            &b.0,
+        ))
+    });
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id() -> Uuid {
+        Uuid::from_u128(1)
+    }
+
+    fn pdf_content(pages: Option<u64>) -> Content {
+        Content {
+            file_type: "pdf".to_string(),
+            page_count: pages,
+            ..Default::default()
+        }
+    }
+
+    fn names(zip: &[u8]) -> Vec<String> {
+        let mut za = zip::ZipArchive::new(io::Cursor::new(zip)).unwrap();
+        (0..za.len())
+            .map(|i| {
+                let f = za.by_index(i).unwrap();
+                let t = f.last_modified();
+                assert_eq!((t.year(), t.month(), t.day()), (1980, 1, 1));
+                assert_eq!((t.hour(), t.minute(), t.second()), (0, 0, 0));
+                f.name().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deterministic() {
+        let pagedata = PageData {
+            templates: vec!["Blank".to_string(), "Blank".to_string()],
+        };
+        let forwards = DocumentArchiveBuilder::new()
+            .content(pdf_content(Some(2)))
+            .pagedata(pagedata.clone())
+            .payload_pdf(b"%PDF-1.4".to_vec())
+            .page_rm(0, b"first".to_vec())
+            .page_rm(1, b"second".to_vec())
+            .thumbnail(1, b"jpeg".to_vec())
+            .build(id())
+            .unwrap();
+        let backwards = DocumentArchiveBuilder::new()
+            .thumbnail(1, b"jpeg".to_vec())
+            .page_rm(1, b"second".to_vec())
+            .page_rm(0, b"first".to_vec())
+            .payload_pdf(b"%PDF-1.4".to_vec())
+            .pagedata(pagedata)
+            .content(pdf_content(Some(2)))
+            .build(id())
+            .unwrap();
+        assert_eq!(
+            Sha256::digest(&forwards),
+            Sha256::digest(&backwards),
+            "archives of the same parts differ"
+        );
+        let prefix = id().to_string();
+        assert_eq!(
+            names(&forwards),
+            vec![
+                format!("{}.content", prefix),
+                format!("{}.pagedata", prefix),
+                format!("{}.pdf", prefix),
+                format!("{}/0.rm", prefix),
+                format!("{}/1.rm", prefix),
+                format!("{}.thumbnails/1.jpg", prefix),
+            ]
+        );
+
+        // A payload read from its file makes the same archive.
+        let dir = std::env::temp_dir()
+            .join(format!("archive-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let pdf = dir.join("a.pdf");
+        fs::write(&pdf, b"%PDF-1.4").unwrap();
+        let file = dir.join("a.zip");
+        DocumentArchiveBuilder::new()
+            .content(pdf_content(Some(2)))
+            .pagedata(PageData {
+                templates: vec!["Blank".to_string(), "Blank".to_string()],
+            })
+            .payload_pdf_file(&pdf)
+            .page_rm(0, b"first".to_vec())
+            .page_rm(1, b"second".to_vec())
+            .thumbnail(1, b"jpeg".to_vec())
+            .build_to(id(), fs::File::create(&file).unwrap())
+            .unwrap();
+        assert_eq!(fs::read(&file).unwrap(), forwards);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut za =
+            zip::ZipArchive::new(io::Cursor::new(&forwards[..])).unwrap();
+        let mut pdf = vec![];
+        za.by_name(&format!("{}.pdf", prefix))
+            .unwrap()
+            .read_to_end(&mut pdf)
+            .unwrap();
+        assert_eq!(pdf, b"%PDF-1.4");
+    }
+
+    #[test]
+    fn consistency() {
+        let reason = |builder: DocumentArchiveBuilder| match builder.build(id())
+        {
+            Err(Error::InvalidArchive { reason }) => reason,
+            other => panic!("{:?}", other.map(|_| ())),
+        };
+        let pdf = || b"%PDF-1.4".to_vec();
+        let base = DocumentArchiveBuilder::new;
+
+        assert!(reason(base().payload_pdf(pdf())).contains(".content"));
+        assert!(reason(base().content(pdf_content(None))).contains("none"));
+        let epub_as_pdf = base().content(pdf_content(None)).payload_epub(pdf());
+        assert!(reason(epub_as_pdf).contains("payload is epub"));
+        let pdf_in_notebook =
+            base().content(Content::default()).payload_pdf(pdf());
+        assert!(reason(pdf_in_notebook).contains("payload is pdf"));
+        let short_pagedata = base()
+            .content(pdf_content(Some(2)))
+            .payload_pdf(pdf())
+            .pagedata(PageData {
+                templates: vec!["Blank".to_string()],
+            });
+        assert!(reason(short_pagedata).contains("pagedata 1"));
+        let extra_page = base()
+            .content(pdf_content(Some(2)))
+            .payload_pdf(pdf())
+            .thumbnail(2, vec![]);
+        assert!(reason(extra_page).contains("page 2"));
+
+        // A notebook needs nothing but its content.
+        let notebook = base().content(Content::default()).build(id()).unwrap();
+        assert_eq!(names(&notebook), vec![format!("{}.content", id())]);
+    }
+
+    fn zip_of(
+        files: &[(&str, &[u8])],
+        options: zip::write::FileOptions,
+    ) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn hash(zip: &[u8]) -> [u8; 32] {
+        let mut za = zip::ZipArchive::new(io::Cursor::new(zip)).unwrap();
+        let hash = content_hash(&mut za).unwrap();
+        let streamed = content_hash_stream(&mut io::Cursor::new(zip)).unwrap();
+        assert_eq!(hash, streamed);
+        hash
+    }
+
+    #[test]
+    fn content_hashes() {
+        let files: [(&str, &[u8]); 3] = [
+            ("a.content", b"{}"),
+            ("a.pdf", b"%PDF-1.4"),
+            ("a/0.rm", b"strokes"),
+        ];
+        let stored = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored)
+            .last_modified_time(zip::DateTime::default());
+        let reversed: Vec<(&str, &[u8])> =
+            files.iter().rev().copied().collect();
+        let deflated = zip::write::FileOptions::default().last_modified_time(
+            zip::DateTime::from_date_and_time(2021, 3, 4, 5, 6, 7).unwrap(),
+        );
+        let first = zip_of(&files, stored);
+        let second = zip_of(&reversed, deflated);
+        assert_ne!(first, second);
+        assert_eq!(hash(&first), hash(&second));
+
+        // Directory entries don't count.
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        zip.add_directory("a/", deflated).unwrap();
+        for (name, data) in &files {
+            zip.start_file(*name, deflated).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        let with_dir = zip.finish().unwrap().into_inner();
+        assert_eq!(hash(&first), hash(&with_dir));
+
+        let changed = [files[0], ("a.pdf", b"%PDF-1.5"), files[2]];
+        assert_ne!(hash(&first), hash(&zip_of(&changed, stored)));
+        let renamed = [files[0], ("b.pdf", files[1].1), files[2]];
+        assert_ne!(hash(&first), hash(&zip_of(&renamed, stored)));
+
+        // The construction is fixed, as documented.
+        let mut expected = vec![CONTENT_HASH_VERSION];
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"a.content");
+        expected.extend_from_slice(&Sha256::digest(b"{}"));
+        assert_eq!(
+            hash(&zip_of(&files[..1], stored)),
+            <[u8; 32]>::from(Sha256::digest(&expected))
+        );
+    }
+
+    // What each check of a fixture archive came to, with failures cut
+    // down to whether they did.
+    fn verified(fixture: &[u8]) -> (Vec<(&'static str, String)>, bool) {
+        let verification = verify(id(), fixture);
+        let checks = verification
+            .checks
+            .iter()
+            .map(|c| {
+                let result = match &c.result {
+                    CheckResult::Passed => "passed".to_string(),
+                    CheckResult::Failed(reason) => {
+                        format!("failed: {}", reason)
+                    }
+                    CheckResult::Skipped => "skipped".to_string(),
+                };
+                (c.name, result)
+            })
+            .collect();
+        (checks, verification.content_hash.is_some())
+    }
+
+    #[test]
+    fn verified_archives() {
+        let passed = "passed".to_string();
+        let (checks, hashed) =
+            verified(include_bytes!("../tests/fixtures/archives/intact.zip"));
+        assert_eq!(
+            checks,
+            [
+                ("zip", passed.clone()),
+                ("entries", passed.clone()),
+                ("content", passed.clone()),
+                ("pages", passed.clone()),
+            ]
+        );
+        assert!(hashed);
+        let intact = include_bytes!("../tests/fixtures/archives/intact.zip");
+        let mut za =
+            zip::ZipArchive::new(io::Cursor::new(&intact[..])).unwrap();
+        assert_eq!(
+            verify(id(), intact).content_hash,
+            Some(content_hash(&mut za).unwrap())
+        );
+
+        let (checks, hashed) =
+            verified(include_bytes!("../tests/fixtures/archives/bad-crc.zip"));
+        assert_eq!(checks[0], ("zip", passed.clone()));
+        assert!(checks[1].1.starts_with("failed: 1 of 4 unreadable"));
+        assert!(checks[1].1.contains("102.rm"), "{}", checks[1].1);
+        assert_eq!(
+            checks[2..],
+            [("content", passed.clone()), ("pages", passed.clone())]
+        );
+        assert!(!hashed);
+
+        let (checks, hashed) = verified(include_bytes!(
+            "../tests/fixtures/archives/truncated.zip"
+        ));
+        assert!(checks[0].1.starts_with("failed"));
+        assert!(checks[1..].iter().all(|c| c.1 == "skipped"));
+        assert!(!hashed);
+
+        let (checks, _) = verified(include_bytes!(
+            "../tests/fixtures/archives/missing-page.zip"
+        ));
+        assert_eq!(
+            checks[3].1,
+            format!(
+                "failed: 1 of 2 missing, such as {}/{}.rm",
+                id(),
+                Uuid::from_u128(0x102)
+            )
+        );
+
+        let (checks, hashed) = verified(include_bytes!(
+            "../tests/fixtures/archives/bad-content.zip"
+        ));
+        assert!(checks[2].1.starts_with("failed"));
+        assert_eq!(checks[3], ("pages", "skipped".to_string()));
+        // The entries are whole, however wrong what's in them.
+        assert!(hashed);
+        assert!(!verify(id(), b"not a zip").is_ok());
+    }
+
+    #[test]
+    fn summary() {
+        let metadata = Metadata {
+            visible_name: "Dune".to_string(),
+            version: 3,
+            ..Default::default()
+        };
+        let zip = DocumentArchiveBuilder::new()
+            .content(pdf_content(Some(1)))
+            .metadata(metadata)
+            .payload_pdf(b"%PDF-1.4".to_vec())
+            .build(id())
+            .unwrap();
+        let summary = ArchiveSummary::read(io::Cursor::new(zip)).unwrap();
+        assert_eq!(summary.id, id());
+        assert_eq!(summary.version(), Some(3));
+        assert_eq!(summary.visible_name(), Some("Dune"));
+        assert_eq!(summary.content.unwrap().file_type, "pdf");
+
+        let bare = DocumentArchiveBuilder::new()
+            .content(Content::default())
+            .build(id())
+            .unwrap();
+        let summary = ArchiveSummary::read(io::Cursor::new(bare)).unwrap();
+        assert_eq!((summary.version(), summary.visible_name()), (None, None));
+        assert!(ArchiveSummary::read(io::Cursor::new(b"nope")).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Bounding a client's operations from outside: a token to cancel them and
+//! a deadline to give up at, see `Client::with_cancellation` and
+//! `Client::with_deadline`.
+//!
+//! Every request is raced against both, as are reading responses, streaming
+//! blobs and waiting to retry, so an operation stops at the next of those
+//! once it's cancelled or out of time. Requests already answered aren't
+//! undone: an upload stopped before its status is set leaves nothing
+//! visible behind, as with any interrupted upload.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use futures_util::future::{self, Either};
+use futures_util::stream::Stream;
+use tokio::sync::watch;
+
+use crate::error::{Error, Result};
+
+/// Cancels the operations of the clients it's given to, all at once. Clones
+/// share the same state, so one can be kept to cancel with while another
+/// goes to the client.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        CancellationToken {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Makes every operation using the token fail with `Error::Cancelled`,
+    /// now and from now on.
+    pub fn cancel(&self) {
+        // There's always a receiver, in `self`.
+        self.sender.broadcast(true).ok();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Waits until the token is cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        loop {
+            if *receiver.borrow() {
+                return;
+            }
+            if receiver.recv().await.is_none() {
+                return future::pending().await;
+            }
+        }
+    }
+}
+
+/// What a client's operations are raced against.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limits {
+    pub token: Option<CancellationToken>,
+    pub deadline: Option<Instant>,
+}
+
+impl Limits {
+    fn is_unlimited(&self) -> bool {
+        self.token.is_none() && self.deadline.is_none()
+    }
+
+    /// Fails if the operation should already have stopped.
+    pub fn check(&self) -> Result<()> {
+        if self.token.as_ref().is_some_and(|t| t.is_cancelled()) {
+            return Err(Error::Cancelled);
+        }
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Err(Error::DeadlineExceeded);
+        }
+        Ok(())
+    }
+
+    /// Resolves to the error to stop with, once it's time to.
+    fn stopped(&self) -> impl Future<Output = Error> + Send + 'static {
+        let token = self.token.clone();
+        let deadline = self.deadline;
+        async move {
+            let cancelled = async {
+                match &token {
+                    Some(token) => token.cancelled().await,
+                    None => future::pending().await,
+                }
+            };
+            let expired = async {
+                match deadline {
+                    Some(deadline) => {
+                        let deadline = tokio::time::Instant::from_std(deadline);
+                        tokio::time::delay_until(deadline).await
+                    }
+                    None => future::pending().await,
+                }
+            };
+            futures_util::pin_mut!(cancelled, expired);
+            match future::select(cancelled, expired).await {
+                Either::Left(_) => Error::Cancelled,
+                Either::Right(_) => Error::DeadlineExceeded,
+            }
+        }
+    }
+
+    /// Runs `f` unless or until it's time to stop.
+    pub async fn guard<T, F>(&self, f: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.check()?;
+        if self.is_unlimited() {
+            return f.await;
+        }
+        let stopped = self.stopped();
+        futures_util::pin_mut!(f, stopped);
+        match future::select(f, stopped).await {
+            Either::Left((result, _)) => result,
+            Either::Right((e, _)) => Err(e),
+        }
+    }
+
+    /// Waits for `delay`, unless it's time to stop first.
+    pub async fn delay(&self, delay: std::time::Duration) -> Result<()> {
+        self.guard(async {
+            tokio::time::delay_for(delay).await;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Ends `stream` with an error once it's time to stop.
+    pub fn stream<S>(&self, stream: S) -> Guarded<S> {
+        Guarded {
+            stream,
+            stopped: if self.is_unlimited() {
+                None
+            } else {
+                Some(Box::pin(self.stopped()))
+            },
+            done: false,
+        }
+    }
+}
+
+/// A stream which ends with an error once its client's operations should
+/// stop.
+pub(crate) struct Guarded<S> {
+    stream: S,
+    stopped: Option<Pin<Box<dyn Future<Output = Error> + Send>>>,
+    done: bool,
+}
+
+impl<S, T> Stream for Guarded<S>
+where
+    S: Stream<Item = Result<T>> + Unpin,
+{
+    type Item = Result<T>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if let Some(stopped) = this.stopped.as_mut() {
+            if let Poll::Ready(e) = stopped.as_mut().poll(cx) {
+                this.done = true;
+                return Poll::Ready(Some(Err(e)));
+            }
+        }
+        Pin::new(&mut this.stream).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn limits() {
+        let unlimited = Limits::default();
+        assert_eq!(unlimited.guard(async { Ok(1) }).await.unwrap(), 1);
+
+        let token = CancellationToken::new();
+        let limits = Limits {
+            token: Some(token.clone()),
+            deadline: None,
+        };
+        let waiting = limits.guard(async {
+            tokio::time::delay_for(Duration::from_secs(5)).await;
+            Ok(())
+        });
+        let canceller = async {
+            tokio::time::delay_for(Duration::from_millis(20)).await;
+            token.cancel();
+        };
+        let (result, _) = future::join(waiting, canceller).await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        // Anything after fails straight away.
+        let after = limits.guard(async { Ok(()) }).await;
+        assert!(matches!(after, Err(Error::Cancelled)));
+
+        let limits = Limits {
+            token: None,
+            deadline: Some(Instant::now() + Duration::from_millis(20)),
+        };
+        let result = limits.delay(Duration::from_secs(5)).await;
+        assert!(matches!(result, Err(Error::DeadlineExceeded)));
+    }
+}
@@ -0,0 +1,165 @@
+//! What an account can do with this client, as `Client::capabilities`
+//! finds out, so that callers can ask rather than try and fail.
+//!
+//! Most of it follows from the protocol generation the account is on and
+//! the dialect its server speaks. Both are noted from the answers to
+//! whatever requests the client makes anyway, so asking usually costs
+//! nothing; otherwise a listing without blob URLs is fetched to tell.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::client::WireDialect;
+use crate::error::{Error, Result};
+
+/// The largest blob known to be taken by the official cloud. Bigger ones
+/// may well be, but nobody has said so.
+pub const OFFICIAL_MAX_BLOB_SIZE: u64 = 100 * 1024 * 1024;
+
+/// The protocol an account's documents are served over.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Generation {
+    /// The document storage API this crate speaks.
+    Legacy,
+    /// reMarkable's newer sync service, which accounts have been moved to,
+    /// and which this crate can't use yet.
+    Sync15,
+}
+
+impl fmt::Display for Generation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Generation::Legacy => "legacy",
+            Generation::Sync15 => "sync15",
+        })
+    }
+}
+
+/// Something `Error::Unsupported` says an account can't do.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// Uploading documents, and the other changes which go through the
+    /// same API: moving, renaming and deleting them.
+    Uploads,
+    /// Being told of changes as they're made.
+    Notifications,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Uploads => "uploading or changing documents",
+            Capability::Notifications => "notifications",
+        })
+    }
+}
+
+/// What an account can do with this client, as returned by
+/// `Client::capabilities`.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub generation: Generation,
+    pub dialect: WireDialect,
+    /// Whether the cloud offers notifications of changes to clients
+    /// speaking this generation's protocol.
+    pub notifications: bool,
+    /// Whether this client can upload and change documents.
+    pub uploads: bool,
+    /// The largest blob known to be taken, if anything is known.
+    pub max_blob_size: Option<u64>,
+}
+
+impl Capabilities {
+    /// What an account on `generation`, served in `dialect`, can do.
+    pub fn of(generation: Generation, dialect: WireDialect) -> Self {
+        let legacy = generation == Generation::Legacy;
+        Capabilities {
+            generation,
+            dialect,
+            notifications: legacy,
+            uploads: legacy,
+            max_blob_size: match (generation, dialect) {
+                (Generation::Legacy, WireDialect::Official) => {
+                    Some(OFFICIAL_MAX_BLOB_SIZE)
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// Fails with `Error::Unsupported` unless the account can do
+    /// `capability`.
+    pub fn require(&self, capability: Capability) -> Result<()> {
+        let supported = match capability {
+            Capability::Uploads => self.uploads,
+            Capability::Notifications => self.notifications,
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(Error::Unsupported { capability })
+        }
+    }
+}
+
+/// What the answers so far have shown about the account, shared between a
+/// client and those made from it.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Detected {
+    pub generation: Option<Generation>,
+    /// Only known once a listing with something in it has been seen.
+    pub dialect: Option<WireDialect>,
+    /// Whether a listing was fetched just to find out, which needn't be
+    /// done again even if it showed no dialect.
+    pub probed: bool,
+}
+
+/// The dialect a listing is in, by how it spells the names of documents,
+/// or `None` if it has none.
+pub(crate) fn dialect_of(body: &str) -> Option<WireDialect> {
+    if body.contains("\"VissibleName\"") {
+        Some(WireDialect::Official)
+    } else if body.contains("\"VisibleName\"") {
+        Some(WireDialect::Rmfakecloud)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_generation() {
+        let official =
+            Capabilities::of(Generation::Legacy, WireDialect::Official);
+        assert!(official.require(Capability::Uploads).is_ok());
+        assert_eq!(official.max_blob_size, Some(OFFICIAL_MAX_BLOB_SIZE));
+        let fake =
+            Capabilities::of(Generation::Legacy, WireDialect::Rmfakecloud);
+        assert!(fake.uploads && fake.notifications);
+        assert_eq!(fake.max_blob_size, None);
+
+        let moved = Capabilities::of(Generation::Sync15, WireDialect::Official);
+        assert!(!moved.notifications);
+        match moved.require(Capability::Uploads).unwrap_err() {
+            Error::Unsupported { capability } => {
+                assert_eq!(capability, Capability::Uploads)
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn dialects() {
+        let official = r#"[{"ID": "a", "VissibleName": "Dune"}]"#;
+        assert_eq!(dialect_of(official), Some(WireDialect::Official));
+        let fake = r#"[{"ID": "a", "VisibleName": "Dune"}]"#;
+        assert_eq!(dialect_of(fake), Some(WireDialect::Rmfakecloud));
+        assert_eq!(dialect_of("[]"), None);
+    }
+}
@@ -1,14 +1,44 @@
-use std::fs;
+use std::collections::{HashMap, HashSet};
 use std::io;
 use std::path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
+use futures_util::stream::{Stream, StreamExt, TryStreamExt};
+use remarkable_data_formats::lines::Page;
+use remarkable_data_formats::stats::PageStats;
 use uuid::Uuid;
 
-use crate::documents::{Document, Documents};
+use crate::archive::{self, ArchiveVerification};
+use crate::cancel::{CancellationToken, Limits};
+use crate::capabilities::{
+    self, Capabilities, Capability, Detected, Generation,
+};
+use crate::clock::{ClockSource, SystemClock};
+use crate::content_cache::ContentCache;
+use crate::delete::{self, DeleteOutcome, DeleteReport};
+use crate::details::{self, DocumentDetails};
+use crate::diagnostics::{self, ClientDiagnostics, SchemaDrift, Shape};
+use crate::documents::{DocType, Document, Documents};
+use crate::listing_cache::{self, ListingCache};
+use crate::listing_stream::{self, DocumentStream};
+use crate::metrics::{MeteredStream, Metrics, Operation, Outcome};
+use crate::names::{normalize_name, NamePolicy};
+use crate::pages::{self, PageInfo};
+use crate::ratelimit::{RateLimitedStream, RateLimiter};
+use crate::requests::{
+    DeleteRequest, MetadataChange, MetadataPatch, Parent, RegisterRequest,
+    StatusResponse, UpdateOutcome, UpdateStatusRequest, UploadRequest,
+    UploadResponse,
+};
+use crate::state_store::StateStore;
 
 use crate::error::{Error, Result};
+use crate::upload::{
+    BlobChunks, BlobSource, ResumableSession, Upload, UploadStage,
+};
 
-#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Default, Debug)]
 pub struct ClientState {
     device_token: String,
     user_token: String,
@@ -29,7 +59,7 @@ impl ClientState {
     }
 
     pub fn load_from_path(&mut self, p: &path::Path) -> Result<()> {
-        Ok(self.load(io::BufReader::new(fs::File::open(p)?))?)
+        self.load(&crate::read_locked(p)?[..])
     }
 
     pub fn save<W>(&self, f: W) -> Result<()>
@@ -39,18 +69,177 @@ impl ClientState {
         Ok(serde_json::to_writer_pretty(f, self)?)
     }
 
-    pub fn save_to_path(self, p: &path::Path) -> Result<()> {
-        // TODO: Make this be properly atomic
-        Ok(self.save(io::BufWriter::new(fs::File::create(p)?))?)
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    /// Sets the storage host the document API is served from.
+    pub fn set_endpoint(&mut self, endpoint: String) {
+        self.endpoint = endpoint;
+    }
+
+    pub fn set_device_token(&mut self, device_token: String) {
+        self.device_token = device_token;
+    }
+
+    /// Writes the state to `p`, by way of a file beside it, so that a
+    /// reader never sees half of it, holding its lock so that other
+    /// processes saving it take turns.
+    pub fn save_to_path(&self, p: &path::Path) -> Result<()> {
+        let mut data = vec![];
+        self.save(&mut data)?;
+        crate::write_atomically(p, &data)?;
+        Ok(())
+    }
+}
+
+// The number of bytes stored, from the `Range` of an answer to a chunk of
+// a resumable upload, such as "bytes=0-262143".
+fn parse_received(range: &str) -> Result<u64> {
+    let last = range
+        .strip_prefix("bytes=0-")
+        .and_then(|last| last.parse::<u64>().ok());
+    match last {
+        Some(last) => Ok(last + 1),
+        None => Err(Error::Rejected {
+            message: format!("the cloud answered a chunk with {:?}", range),
+        }),
+    }
+}
+
+// Whether an answer from the legacy storage API is one it gives accounts
+// moved to the newer sync service. Such accounts still get valid tokens, but
+// the listing is refused with a 400, which a request without a body can't
+// otherwise earn, or answered with an error instead of documents. The error
+// has to mention the migration, so an ordinary empty listing never counts.
+fn looks_migrated(
+    status: reqwest::StatusCode,
+    body: &str,
+    listing: bool,
+) -> bool {
+    if listing && status == reqwest::StatusCode::BAD_REQUEST {
+        return true;
+    }
+    let value: serde_json::Value = match serde_json::from_str(body) {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    let errors: Vec<&serde_json::Value> = match &value {
+        serde_json::Value::Object(_) => vec![&value],
+        serde_json::Value::Array(entries) => entries
+            .iter()
+            .filter(|e| e.get("Success") == Some(&false.into()))
+            .collect(),
+        _ => vec![],
+    };
+    errors.iter().any(|e| {
+        ["message", "Message", "error"].iter().any(|field| {
+            e.get(field).and_then(|m| m.as_str()).is_some_and(|m| {
+                let m = m.to_lowercase();
+                m.contains("migrat") || m.contains("sync15")
+            })
+        })
+    })
+}
+
+// Reads the body of a response from the legacy storage API, failing on
+// error statuses, and with `Error::AccountMigrated` where that's the cause.
+async fn storage_body(
+    response: reqwest::Response,
+    listing: bool,
+) -> Result<String> {
+    let status = response.status();
+    let error = response.error_for_status_ref().err();
+    let body = response.text().await?;
+    if looks_migrated(status, &body, listing) {
+        return Err(Error::AccountMigrated);
+    }
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(body),
+    }
+}
+
+/// The flavour of the cloud API being spoken to, for the places where servers
+/// disagree on the wire format.
+#[derive(serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WireDialect {
+    /// reMarkable's own cloud.
+    #[default]
+    Official,
+    /// [rmfakecloud](https://github.com/ddvk/rmfakecloud).
+    Rmfakecloud,
+}
+
+impl WireDialect {
+    /// The JSON field name used for a document's visible name when sending
+    /// requests. Responses are accepted in either spelling.
+    pub fn visible_name_field(self) -> &'static str {
+        match self {
+            WireDialect::Official => "VissibleName",
+            WireDialect::Rmfakecloud => "VisibleName",
+        }
     }
 }
 
 const USER_TOKEN_URL: &str = "https://my.remarkable.com/token/json/2/user/new";
+const DEVICE_TOKEN_URL: &str =
+    "https://my.remarkable.com/token/json/2/device/new";
+/// The storage host of the official cloud, for states without one.
+pub const DEFAULT_ENDPOINT: &str =
+    "https://document-storage-production-dot-remarkable-production.appspot.com";
+// How this client describes itself when registering.
+const DEVICE_DESC: &str = "desktop-linux";
 const DOCUMENT_LIST_PATH: &str = "document-storage/json/2/docs";
+const UPLOAD_REQUEST_PATH: &str = "document-storage/json/2/upload/request";
+const UPDATE_STATUS_PATH: &str = "document-storage/json/2/upload/update-status";
+const DELETE_PATH: &str = "document-storage/json/2/delete";
+
+// Uploads are sent in chunks of this size so they can be rate limited.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+/// Blobs of at least this many bytes are sent in chunks over a resumable
+/// session, where the cloud offers one, unless set otherwise with
+/// `Client::set_resumable_threshold`.
+pub const DEFAULT_RESUMABLE_THRESHOLD: u64 = 32 * 1024 * 1024;
+// The size of the chunks sent over a resumable session, unless set. Google
+// Cloud Storage takes chunks in multiples of 256 KiB.
+const RESUMABLE_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+// How many times each stage of an upload, or batch of deletions, is tried
+// before giving up on a transient error, and how long to wait before the
+// first retry. The wait doubles after each attempt.
+const UPLOAD_ATTEMPTS: u32 = 4;
+const UPLOAD_RETRY_DELAY: std::time::Duration =
+    std::time::Duration::from_millis(250);
+// The most documents `delete_subtree` asks the cloud to delete at once.
+const DELETE_BATCH_SIZE: usize = 50;
+// The most changes `update_metadata_bulk` sends at once, unless set.
+const METADATA_BATCH_SIZE: usize = 50;
+
+/// The contents of a document's blob, as a stream of chunks.
+pub type BlobStream = Pin<Box<dyn Stream<Item = Result<bytes::Bytes>> + Send>>;
 
 pub struct Client {
     client_state: ClientState,
     http_client: reqwest::Client,
+    wire_dialect: WireDialect,
+    rate_limiter: Option<RateLimiter>,
+    metrics: Option<Arc<dyn Metrics>>,
+    user_token_url: String,
+    device_token_url: String,
+    allow_trash: bool,
+    name_policy: Option<NamePolicy>,
+    read_only: bool,
+    listing_cache: Option<ListingCache>,
+    content_cache: Option<ContentCache>,
+    state_store: Option<Arc<dyn StateStore>>,
+    schema_drift: Option<Arc<Mutex<SchemaDrift>>>,
+    metadata_batch_size: usize,
+    resumable_threshold: Option<u64>,
+    resumable_chunk_size: usize,
+    clock: Arc<dyn ClockSource>,
+    detected: Arc<Mutex<Detected>>,
+    limits: Limits,
 }
 
 impl Client {
@@ -61,9 +250,60 @@ impl Client {
         Client {
             client_state,
             http_client,
+            wire_dialect: Default::default(),
+            rate_limiter: None,
+            metrics: None,
+            user_token_url: USER_TOKEN_URL.to_string(),
+            device_token_url: DEVICE_TOKEN_URL.to_string(),
+            allow_trash: false,
+            name_policy: Some(NamePolicy::default()),
+            read_only: false,
+            listing_cache: None,
+            content_cache: None,
+            state_store: None,
+            schema_drift: None,
+            metadata_batch_size: METADATA_BATCH_SIZE,
+            resumable_threshold: Some(DEFAULT_RESUMABLE_THRESHOLD),
+            resumable_chunk_size: RESUMABLE_CHUNK_SIZE,
+            clock: Arc::new(SystemClock),
+            detected: Default::default(),
+            limits: Limits::default(),
         }
     }
 
+    /// A client with the state loaded from `state_store`, which it saves
+    /// back there whenever `refresh_token` gets a new user token.
+    pub async fn from_state_store(
+        state_store: Arc<dyn StateStore>,
+        http_client: reqwest::Client,
+    ) -> Result<Self> {
+        let mut client = Client::new(state_store.load().await?, http_client);
+        client.state_store = Some(state_store);
+        Ok(client)
+    }
+
+    /// Overrides the URL user tokens are requested from, for talking to
+    /// something other than the official cloud.
+    pub fn set_user_token_url(&mut self, url: String) {
+        self.user_token_url = url;
+    }
+
+    /// Overrides the URL one-time codes are traded for device tokens at,
+    /// as `set_user_token_url` does for user tokens.
+    pub fn set_device_token_url(&mut self, url: String) {
+        self.device_token_url = url;
+    }
+
+    pub fn wire_dialect(&self) -> WireDialect {
+        self.wire_dialect
+    }
+
+    /// Sets the dialect requests are sent in, until a listing shows the
+    /// server speaks another.
+    pub fn set_wire_dialect(&mut self, wire_dialect: WireDialect) {
+        self.wire_dialect = wire_dialect;
+    }
+
     pub fn state(&mut self) -> &mut ClientState {
         &mut self.client_state
     }
@@ -72,15 +312,402 @@ impl Client {
         &self.http_client
     }
 
+    pub fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    /// Throttles all blob transfers made by this client. The limiter may be
+    /// shared with other clients to cap their combined throughput.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    /// Tells `metrics` of every request this client, and any view of it
+    /// such as `with_cancellation` gives, makes from now on.
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn Metrics>>) {
+        self.metrics = metrics;
+    }
+
+    /// Lets `update_status` create documents directly in the trash, which
+    /// it otherwise refuses: neither the tablet nor the apps can do much
+    /// with a document that has never been anywhere else.
+    pub fn set_allow_trash(&mut self, allow_trash: bool) {
+        self.allow_trash = allow_trash;
+    }
+
+    /// Sets how the names new documents are uploaded with, or documents are
+    /// renamed to, are checked, see `normalize_name`, or with `None` sends
+    /// them as they are. By default they're checked with
+    /// `NamePolicy::default()`.
+    pub fn set_name_policy(&mut self, name_policy: Option<NamePolicy>) {
+        self.name_policy = name_policy;
+    }
+
+    // `name` as the name policy has it sent.
+    fn checked_name(&self, name: &str) -> Result<String> {
+        match &self.name_policy {
+            Some(policy) => normalize_name(name, policy),
+            None => Ok(name.to_string()),
+        }
+    }
+
+    /// Makes every method which would change anything in the cloud fail
+    /// with `Error::ReadOnly` instead, before sending a request.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Sets how many changes `update_metadata_bulk` sends in one request.
+    pub fn set_metadata_batch_size(&mut self, size: usize) {
+        self.metadata_batch_size = size.max(1);
+    }
+
+    /// Sets the size from which blobs are sent in chunks over a resumable
+    /// session, or with `None` never to. Blobs are sent in one piece when
+    /// the cloud doesn't offer a session whatever this is.
+    pub fn set_resumable_threshold(&mut self, threshold: Option<u64>) {
+        self.resumable_threshold = threshold;
+    }
+
+    /// Sets the size of the chunks sent over a resumable session. Google
+    /// Cloud Storage wants a multiple of 256 KiB.
+    pub fn set_resumable_chunk_size(&mut self, size: usize) {
+        self.resumable_chunk_size = size.max(1);
+    }
+
+    /// Sets where the client gets the time it stamps on changes from.
+    pub fn set_clock(&mut self, clock: Arc<dyn ClockSource>) {
+        self.clock = clock;
+    }
+
+    /// The time, as the client's clock has it.
+    pub fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        self.clock.now()
+    }
+
+    pub fn listing_cache(&self) -> Option<&ListingCache> {
+        self.listing_cache.as_ref()
+    }
+
+    /// Keeps the last listing fetched by `get_documents` in `listing_cache`,
+    /// asking the server only whether it has changed the next time.
+    pub fn set_listing_cache(&mut self, listing_cache: Option<ListingCache>) {
+        self.listing_cache = listing_cache;
+    }
+
+    pub fn content_cache(&self) -> Option<&ContentCache> {
+        self.content_cache.as_ref()
+    }
+
+    /// Keeps what's read from the `.content` of every blob downloaded in
+    /// `content_cache`.
+    pub fn set_content_cache(&mut self, content_cache: Option<ContentCache>) {
+        self.content_cache = content_cache;
+    }
+
+    pub fn state_store(&self) -> Option<&Arc<dyn StateStore>> {
+        self.state_store.as_ref()
+    }
+
+    /// Saves the state to `state_store` after each `refresh_token`. The
+    /// state isn't loaded from it; see `from_state_store` for that.
+    pub fn set_state_store(
+        &mut self,
+        state_store: Option<Arc<dyn StateStore>>,
+    ) {
+        self.state_store = state_store;
+    }
+
+    /// Has the client check the fields of each response entry it gets,
+    /// collecting any it doesn't know or misses for `take_schema_drift`.
+    /// Off by default, as it means parsing every response twice.
+    pub fn set_schema_diagnostics(&mut self, enabled: bool) {
+        self.schema_drift = if enabled {
+            Some(Arc::new(Mutex::new(SchemaDrift::default())))
+        } else {
+            None
+        };
+    }
+
+    /// The drift seen since the last call, or `None` unless
+    /// `set_schema_diagnostics` is on.
+    pub fn take_schema_drift(&self) -> Option<SchemaDrift> {
+        let drift = self.schema_drift.as_ref()?;
+        Some(std::mem::take(&mut *drift.lock().unwrap()))
+    }
+
+    fn check_shape(&self, shape: &Shape, body: &str) {
+        if let Some(drift) = &self.schema_drift {
+            drift.lock().unwrap().record(shape, body);
+        }
+    }
+
+    /// A view of this client whose operations fail with `Error::Cancelled`
+    /// once `token` is cancelled, and stop then at the next request, chunk
+    /// of a blob or wait. The view shares everything else, the listing
+    /// cache and schema drift included, with this client, which is left as
+    /// it was.
+    pub fn with_cancellation(&self, token: CancellationToken) -> Client {
+        self.scoped(Limits {
+            token: Some(token),
+            ..self.limits.clone()
+        })
+    }
+
+    /// A view of this client as `with_cancellation` gives, whose operations
+    /// fail with `Error::DeadlineExceeded` once `deadline` has passed.
+    pub fn with_deadline(&self, deadline: std::time::Instant) -> Client {
+        self.scoped(Limits {
+            deadline: Some(deadline),
+            ..self.limits.clone()
+        })
+    }
+
+    fn scoped(&self, limits: Limits) -> Client {
+        Client {
+            client_state: self.client_state.clone(),
+            http_client: self.http_client.clone(),
+            wire_dialect: self.wire_dialect,
+            rate_limiter: self.rate_limiter.clone(),
+            metrics: self.metrics.clone(),
+            user_token_url: self.user_token_url.clone(),
+            device_token_url: self.device_token_url.clone(),
+            allow_trash: self.allow_trash,
+            name_policy: self.name_policy,
+            read_only: self.read_only,
+            listing_cache: self.listing_cache.clone(),
+            content_cache: self.content_cache.clone(),
+            state_store: self.state_store.clone(),
+            schema_drift: self.schema_drift.clone(),
+            metadata_batch_size: self.metadata_batch_size,
+            resumable_threshold: self.resumable_threshold,
+            resumable_chunk_size: self.resumable_chunk_size,
+            clock: self.clock.clone(),
+            detected: self.detected.clone(),
+            limits,
+        }
+    }
+}
+
+/// A clone is a view of the client as `with_cancellation` gives, with the
+/// same cancellation and deadline, for work spawned off on its own.
+impl Clone for Client {
+    fn clone(&self) -> Client {
+        self.scoped(self.limits.clone())
+    }
+}
+
+impl Client {
+    // Sends `request`, unless or until the client's operations should stop,
+    // and tells the metrics, if any, how it went. A download that gets under
+    // way is left to the stream of its blob to tell of.
+    async fn send(
+        &self,
+        op: Operation,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let request = request.build()?;
+        let sent = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| b.len() as u64)
+            .or_else(|| {
+                let length =
+                    request.headers().get(reqwest::header::CONTENT_LENGTH);
+                length?.to_str().ok()?.parse().ok()
+            })
+            .unwrap_or(0);
+        let start = std::time::Instant::now();
+        let response = self
+            .limits
+            .guard(async { Ok(self.http_client.execute(request).await?) })
+            .await;
+        if let Some(metrics) = &self.metrics {
+            let (status, received) = match &response {
+                Ok(r)
+                    if op == Operation::Download && r.status().is_success() =>
+                {
+                    return response
+                }
+                Ok(r) => (
+                    Outcome::Status(r.status().as_u16()),
+                    r.content_length().unwrap_or(0),
+                ),
+                Err(_) => (Outcome::Failed, 0),
+            };
+            metrics.record_request(
+                op,
+                status,
+                start.elapsed(),
+                sent + received,
+            );
+        }
+        response
+    }
+
+    // Reads a response from the storage API as `storage_body` does, unless
+    // or until the client's operations should stop.
+    async fn storage_body(
+        &self,
+        response: reqwest::Response,
+        listing: bool,
+    ) -> Result<String> {
+        let result = self.limits.guard(storage_body(response, listing)).await;
+        let mut detected = self.detected.lock().unwrap();
+        match &result {
+            Ok(body) => {
+                detected.generation = Some(Generation::Legacy);
+                if listing {
+                    detected.dialect =
+                        capabilities::dialect_of(body).or(detected.dialect);
+                }
+            }
+            Err(Error::AccountMigrated) => {
+                detected.generation = Some(Generation::Sync15)
+            }
+            Err(_) => (),
+        }
+        result
+    }
+
+    // The dialect requests are sent in: the one the server was seen to
+    // speak, or failing that the one set.
+    fn dialect(&self) -> WireDialect {
+        self.detected
+            .lock()
+            .unwrap()
+            .dialect
+            .unwrap_or(self.wire_dialect)
+    }
+
+    // What the answers so far have shown the account can do, if they
+    // showed its generation.
+    fn known_capabilities(&self) -> Option<Capabilities> {
+        let generation = self.detected.lock().unwrap().generation?;
+        Some(Capabilities::of(generation, self.dialect()))
+    }
+
+    /// What the account can do with this client. It's worked out from the
+    /// answers to earlier requests where they tell, and otherwise from
+    /// those to a listing without blob URLs, fetched once, and kept for
+    /// this client and those made from it.
+    pub async fn capabilities(&self) -> Result<Capabilities> {
+        let detected = *self.detected.lock().unwrap();
+        let known = detected.generation == Some(Generation::Sync15)
+            || detected.dialect.is_some()
+            || detected.probed;
+        if let (true, Some(capabilities)) = (known, self.known_capabilities()) {
+            return Ok(capabilities);
+        }
+        let request = self
+            .http_client
+            .get(&self.get_document_list_url())
+            .bearer_auth(&self.client_state.user_token);
+        let response = self.send(Operation::Listing, request).await?;
+        match self.storage_body(response, true).await {
+            Ok(_) | Err(Error::AccountMigrated) => (),
+            Err(e) => return Err(e),
+        }
+        self.detected.lock().unwrap().probed = true;
+        Ok(self.known_capabilities().unwrap())
+    }
+
+    // Drops the cached listing, which a change made by this client may have
+    // outdated, whether or not the change went through.
+    fn invalidate_listing(&self) {
+        if let Some(cache) = &self.listing_cache {
+            cache.invalidate();
+        }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        if let Some(capabilities) = self.known_capabilities() {
+            capabilities.require(Capability::Uploads)?;
+        }
+        Ok(())
+    }
+
+    /// What the client is set up to talk to, and as whom, read from its
+    /// state without any requests.
+    pub fn diagnostics(&self) -> ClientDiagnostics {
+        let state = &self.client_state;
+        let claims = diagnostics::claims(&state.user_token).unwrap_or_default();
+        let device_token = Some(&state.device_token).filter(|t| !t.is_empty());
+        ClientDiagnostics {
+            endpoint: state.endpoint.clone(),
+            user_token_expires: claims.exp.and_then(|exp| {
+                chrono::TimeZone::timestamp_opt(&chrono::Utc, exp, 0).single()
+            }),
+            user_id: claims.sub,
+            device_token_fingerprint: device_token
+                .map(|t| diagnostics::fingerprint(t)),
+            read_only: self.read_only,
+            upload_attempts: UPLOAD_ATTEMPTS,
+            upload_retry_delay: UPLOAD_RETRY_DELAY,
+        }
+    }
+
+    /// Makes one small authenticated request, returning how long the
+    /// answer took.
+    pub async fn ping(&self) -> Result<std::time::Duration> {
+        let start = std::time::Instant::now();
+        self.get_listing_of(&Uuid::nil(), false).await?;
+        Ok(start.elapsed())
+    }
+
+    /// Gets a new user token for the device token, failing if the cloud
+    /// refuses, and saves the state to the state store, if any.
     pub async fn refresh_token(&mut self) -> Result<()> {
         let request = self
             .http_client
-            .post(USER_TOKEN_URL)
+            .post(&self.user_token_url)
             .bearer_auth(&self.client_state.device_token)
             .body("")
             .header(reqwest::header::CONTENT_LENGTH, "0");
-        let response = request.send().await?;
-        self.client_state.user_token = response.text().await?;
+        let response = self
+            .send(Operation::Token, request)
+            .await?
+            .error_for_status()?;
+        self.client_state.user_token = self
+            .limits
+            .guard(async { Ok(response.text().await?) })
+            .await?;
+        if let Some(store) = &self.state_store {
+            store.save(&self.client_state).await?;
+        }
+        Ok(())
+    }
+
+    /// Registers this client with the account a one-time code, from
+    /// my.remarkable.com, was made for, keeping the device token the cloud
+    /// gives for it in place of any other. The state is saved to the state
+    /// store, if any; `refresh_token` then gets a user token as usual.
+    pub async fn register(&mut self, code: &str) -> Result<()> {
+        let body = RegisterRequest {
+            code: code.trim().to_string(),
+            device_desc: DEVICE_DESC.to_string(),
+            device_id: Uuid::new_v4(),
+        };
+        let request = self.http_client.post(&self.device_token_url).json(&body);
+        let response = self
+            .send(Operation::Token, request)
+            .await?
+            .error_for_status()?;
+        self.client_state.device_token = self
+            .limits
+            .guard(async { Ok(response.text().await?) })
+            .await?;
+        self.client_state.user_token = String::new();
+        if let Some(store) = &self.state_store {
+            store.save(&self.client_state).await?;
+        }
         Ok(())
     }
 
@@ -88,37 +715,1109 @@ impl Client {
         format!("{}/{}", self.client_state.endpoint, DOCUMENT_LIST_PATH)
     }
 
+    fn storage_url(&self, path: &str) -> String {
+        format!("{}/{}", self.client_state.endpoint, path)
+    }
+
+    /// Fetches the listing of every document. With a listing cache, the
+    /// cached listing is returned if the server says it is unchanged, or if
+    /// the server gives no `ETag` and it was fetched only just now.
     pub async fn get_documents(&self) -> Result<Documents> {
-        let request = self
+        let cache = self.listing_cache.as_ref();
+        let cached = cache.and_then(|c| c.get());
+        let mut request = self
             .http_client
             .get(&self.get_document_list_url())
             .bearer_auth(&self.client_state.user_token);
-        let response = request.send().await?;
-        let body = response.text().await?;
-        let docs = serde_json::from_str::<Documents>(&body)?;
-        Ok(docs)
+        match &cached {
+            Some(c) if c.fresh => return Ok(c.documents.clone()),
+            Some(c) => {
+                if let Some(etag) = &c.etag {
+                    request =
+                        request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+            }
+            None => (),
+        }
+        let response = self.send(Operation::Listing, request).await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            if let (Some(cache), Some(cached)) = (cache, cached) {
+                cache.touch();
+                return Ok(cached.documents);
+            }
+        }
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let body = self.storage_body(response, true).await?;
+        self.check_shape(&diagnostics::LISTING_ENTRY, &body);
+        match cache {
+            Some(cache) => cache.store(etag, body),
+            None => listing_cache::parse(&body),
+        }
     }
 
-    pub async fn get_document_by_id(&self, id: &Uuid) -> Result<Document> {
+    /// Streams the listing of every document, an entry at a time as it
+    /// arrives, so that it can be gone through without holding all of it;
+    /// `Documents::from_stream` collects it. The listing cache isn't used,
+    /// as it keeps the whole body.
+    pub async fn stream_documents(&self) -> Result<DocumentStream> {
         let request = self
             .http_client
             .get(&self.get_document_list_url())
-            .bearer_auth(&self.client_state.user_token)
-            .query(&[("withBlob", "1"), ("doc", &id.to_string())]);
-        let response = request.send().await?;
-        let body = response.text().await?;
-        let mut docs = serde_json::from_str::<Documents>(&body)?;
+            .bearer_auth(&self.client_state.user_token);
+        let response = self.send(Operation::Listing, request).await?;
+        if !response.status().is_success() {
+            // Fails, saying why.
+            self.storage_body(response, true).await?;
+            return Err(Error::EmptyResult);
+        }
+        let detected = self.detected.clone();
+        detected.lock().unwrap().generation = Some(Generation::Legacy);
+        let body = response.bytes_stream().map_err(Error::from);
+        let entries = listing_stream::entries(body, move |entry| {
+            let mut detected = detected.lock().unwrap();
+            detected.dialect =
+                capabilities::dialect_of(entry).or(detected.dialect);
+        });
+        Ok(Box::pin(self.limits.stream(entries)))
+    }
+
+    pub async fn get_document_by_id(&self, id: &Uuid) -> Result<Document> {
+        let mut docs = self.get_listing_of(id, true).await?;
         match docs.remove(id) {
             Some(d) => Ok(d),
             None => Err(Error::EmptyResult),
         }
     }
+
+    /// The listing of just the document `id`, which may be in the trash.
+    async fn get_listing_of(
+        &self,
+        id: &Uuid,
+        with_blob: bool,
+    ) -> Result<Documents> {
+        let mut request = self
+            .http_client
+            .get(&self.get_document_list_url())
+            .bearer_auth(&self.client_state.user_token)
+            .query(&[("doc", &id.to_string())]);
+        if with_blob {
+            request = request.query(&[("withBlob", "1")]);
+        }
+        let response = self.send(Operation::Listing, request).await?;
+        let body = self.storage_body(response, true).await?;
+        self.check_shape(&diagnostics::LISTING_ENTRY, &body);
+        Documents::parse_listing(&body, with_blob)
+    }
+
+    /// Streams the contents of a document's blob. The document must carry a
+    /// blob URL, as those returned by `get_document_by_id` do.
+    pub async fn blob_stream(&self, doc: &Document) -> Result<BlobStream> {
+        let url = doc.blob_url_get.as_deref().ok_or(Error::EmptyResult)?;
+        let start = std::time::Instant::now();
+        let response = self
+            .send(Operation::Download, self.http_client.get(url))
+            .await?
+            .error_for_status()?;
+        let status = Outcome::Status(response.status().as_u16());
+        let stream: BlobStream =
+            Box::pin(response.bytes_stream().map_err(Error::from));
+        let stream: BlobStream = match &self.metrics {
+            Some(metrics) => Box::pin(MeteredStream::new(
+                stream,
+                metrics.clone(),
+                status,
+                start,
+            )),
+            None => stream,
+        };
+        let stream: BlobStream = match &self.rate_limiter {
+            Some(limiter) => {
+                Box::pin(RateLimitedStream::new(stream, limiter.clone()))
+            }
+            None => Box::pin(stream),
+        };
+        Ok(Box::pin(self.limits.stream(stream)))
+    }
+
+    /// Downloads the whole of a document's blob into memory.
+    pub async fn download_blob(&self, doc: &Document) -> Result<Vec<u8>> {
+        let mut stream = self.blob_stream(doc).await?;
+        let mut buf = vec![];
+        while let Some(chunk) = stream.next().await {
+            buf.extend_from_slice(&chunk?);
+        }
+        if let Some(cache) = &self.content_cache {
+            cache.record(doc, &buf);
+        }
+        Ok(buf)
+    }
+
+    /// Reserves upload URLs for new versions of documents.
+    pub async fn upload_request(
+        &self,
+        requests: &[UploadRequest],
+    ) -> Result<Vec<UploadResponse>> {
+        self.check_writable()?;
+        let request = self
+            .http_client
+            .put(&self.storage_url(UPLOAD_REQUEST_PATH))
+            .bearer_auth(&self.client_state.user_token)
+            .json(requests);
+        let response = self.send(Operation::UploadRequest, request).await?;
+        let body = self.storage_body(response, false).await?;
+        self.check_shape(&diagnostics::UPLOAD_ENTRY, &body);
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Uploads a blob to a URL obtained from `upload_request`.
+    pub async fn put_blob(&self, url: &str, blob: Vec<u8>) -> Result<()> {
+        self.put_blob_from(url, BlobSource::Memory(&blob)).await
+    }
+
+    async fn put_blob_from(
+        &self,
+        url: &str,
+        blob: BlobSource<'_>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let len = blob.len();
+        let chunks = blob.chunks(0, len, UPLOAD_CHUNK_SIZE)?;
+        let request = self
+            .http_client
+            .put(url)
+            .header(reqwest::header::CONTENT_LENGTH, len)
+            .body(self.blob_body(chunks));
+        self.send(Operation::UploadBlob, request)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // The body sending `chunks`, at no more than the rate limit.
+    fn blob_body(&self, chunks: BlobChunks) -> reqwest::Body {
+        match &self.rate_limiter {
+            Some(limiter) => reqwest::Body::wrap_stream(
+                RateLimitedStream::new(chunks, limiter.clone()),
+            ),
+            None => reqwest::Body::wrap_stream(chunks),
+        }
+    }
+
+    /// Starts a resumable session for a blob of `size` bytes at a URL
+    /// obtained from `upload_request`, by the Google Cloud Storage
+    /// protocol, returning where to send the chunks. `None` if the cloud
+    /// won't start one, so the blob has to be sent whole with `put_blob`.
+    pub async fn start_resumable(
+        &self,
+        url: &str,
+        size: u64,
+    ) -> Result<Option<ResumableSession>> {
+        self.check_writable()?;
+        let request = self
+            .http_client
+            .post(url)
+            .header("x-goog-resumable", "start")
+            .header(reqwest::header::CONTENT_LENGTH, 0);
+        let response = self.send(Operation::UploadBlob, request).await?;
+        let status = response.status();
+        // Only a refusal means there's no session to be had.
+        let transient = status.is_server_error()
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        let response = if transient {
+            response.error_for_status()?
+        } else {
+            response
+        };
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|l| l.to_str().ok());
+        Ok(match location {
+            Some(location) if status.is_success() => Some(ResumableSession {
+                url: location.to_string(),
+                size,
+                received: 0,
+                confirmed: true,
+            }),
+            _ => {
+                log::info!(
+                    "No resumable session ({}); sending the blob whole",
+                    status
+                );
+                None
+            }
+        })
+    }
+
+    /// Sends the next chunk of `blob` over `session`, first asking how much
+    /// has arrived if that isn't known, and records what the cloud says it
+    /// has. Returns whether the whole blob is stored, or fails with
+    /// `Error::SessionExpired` if the cloud has forgotten the session.
+    pub async fn put_chunk(
+        &self,
+        session: &mut ResumableSession,
+        blob: &[u8],
+    ) -> Result<bool> {
+        self.put_chunk_from(session, BlobSource::Memory(blob)).await
+    }
+
+    async fn put_chunk_from(
+        &self,
+        session: &mut ResumableSession,
+        blob: BlobSource<'_>,
+    ) -> Result<bool> {
+        self.check_writable()?;
+        if !session.confirmed {
+            let request = self
+                .http_client
+                .put(&session.url)
+                .header(
+                    reqwest::header::CONTENT_RANGE,
+                    format!("bytes */{}", session.size),
+                )
+                .header(reqwest::header::CONTENT_LENGTH, 0);
+            let response = self.send(Operation::UploadBlob, request).await?;
+            if self.chunk_response(session, response)? {
+                return Ok(true);
+            }
+        }
+        let start = session.received;
+        let end = (start + self.resumable_chunk_size as u64).min(blob.len());
+        let body =
+            self.blob_body(blob.chunks(start, end, UPLOAD_CHUNK_SIZE)?);
+        let request = self
+            .http_client
+            .put(&session.url)
+            .header(
+                reqwest::header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end - 1, session.size),
+            )
+            .header(reqwest::header::CONTENT_LENGTH, end - start)
+            .body(body);
+        session.confirmed = false;
+        let response = self.send(Operation::UploadBlob, request).await?;
+        self.chunk_response(session, response)
+    }
+
+    // Reads what the cloud says it has of a resumable upload into
+    // `session`, returning whether it has all of it.
+    fn chunk_response(
+        &self,
+        session: &mut ResumableSession,
+        response: reqwest::Response,
+    ) -> Result<bool> {
+        match response.status().as_u16() {
+            200 | 201 => {
+                session.received = session.size;
+                session.confirmed = true;
+                Ok(true)
+            }
+            // "Resume Incomplete", with the range stored so far if any.
+            308 => {
+                let range = response
+                    .headers()
+                    .get(reqwest::header::RANGE)
+                    .and_then(|r| r.to_str().ok());
+                session.received = match range {
+                    Some(range) => parse_received(range)?,
+                    None => 0,
+                };
+                session.confirmed = true;
+                Ok(false)
+            }
+            404 | 410 => Err(Error::SessionExpired),
+            _ => {
+                response.error_for_status()?;
+                Err(Error::Rejected {
+                    message: "the chunk wasn't taken".to_string(),
+                })
+            }
+        }
+    }
+
+    /// Sets documents' metadata, making uploaded blobs visible. Creating a
+    /// document in `Parent::Trash` fails with `Error::InvalidDestination`
+    /// unless allowed with `set_allow_trash`; moving one there is fine.
+    pub async fn update_status(
+        &self,
+        requests: &[UpdateStatusRequest],
+    ) -> Result<Vec<StatusResponse>> {
+        self.check_writable()?;
+        let creates_in_trash = |r: &&UpdateStatusRequest| {
+            r.parent == Parent::Trash && r.version == 1
+        };
+        if let Some(r) = requests.iter().find(creates_in_trash) {
+            if !self.allow_trash {
+                return Err(Error::InvalidDestination {
+                    parent: "the trash".to_string(),
+                    reason: "new documents can't be created there",
+                });
+            }
+            log::warn!("Creating {} directly in the trash", r.id);
+        }
+        let body: Vec<serde_json::Value> =
+            requests.iter().map(|r| r.to_json(self.dialect())).collect();
+        let request = self
+            .http_client
+            .put(&self.storage_url(UPDATE_STATUS_PATH))
+            .bearer_auth(&self.client_state.user_token)
+            .json(&body);
+        let response = self.send(Operation::UpdateStatus, request).await;
+        self.invalidate_listing();
+        let response = response?;
+        let body = self.storage_body(response, false).await?;
+        self.check_shape(&diagnostics::STATUS_ENTRY, &body);
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Deletes documents outright.
+    pub async fn delete(
+        &self,
+        requests: &[DeleteRequest],
+    ) -> Result<Vec<StatusResponse>> {
+        self.check_writable()?;
+        let request = self
+            .http_client
+            .put(&self.storage_url(DELETE_PATH))
+            .bearer_auth(&self.client_state.user_token)
+            .json(requests);
+        let response = self.send(Operation::Delete, request).await;
+        self.invalidate_listing();
+        let response = response?;
+        let body = self.storage_body(response, false).await?;
+        self.check_shape(&diagnostics::STATUS_ENTRY, &body);
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Changes some of a document's metadata, keeping the rest as it is.
+    ///
+    /// The cloud resets any field an update leaves out, so the document is
+    /// fetched afresh and every field `f` doesn't set is sent back as it
+    /// was, at the next version.
+    pub async fn modify_metadata<F>(
+        &self,
+        id: Uuid,
+        f: F,
+    ) -> Result<MetadataChange>
+    where
+        F: FnOnce(&mut MetadataPatch),
+    {
+        self.modify_metadata_if(id, None, f).await
+    }
+
+    /// As `modify_metadata`, but only if the document is still at
+    /// `version`, failing with `Error::VersionMismatch` otherwise.
+    pub async fn modify_metadata_at<F>(
+        &self,
+        id: Uuid,
+        version: u64,
+        f: F,
+    ) -> Result<MetadataChange>
+    where
+        F: FnOnce(&mut MetadataPatch),
+    {
+        self.modify_metadata_if(id, Some(version), f).await
+    }
+
+    async fn modify_metadata_if<F>(
+        &self,
+        id: Uuid,
+        version: Option<u64>,
+        f: F,
+    ) -> Result<MetadataChange>
+    where
+        F: FnOnce(&mut MetadataPatch),
+    {
+        self.check_writable()?;
+        let mut docs = self.get_listing_of(&id, false).await?;
+        let (doc, parent) = match docs.remove(&id) {
+            Some(doc) => {
+                let parent = doc.parent.into();
+                (doc, parent)
+            }
+            None => match docs.trashed().find(|d| d.id == id) {
+                Some(doc) => (doc.clone(), Parent::Trash),
+                None => return Err(Error::EmptyResult),
+            },
+        };
+        if let Some(expected) = version.filter(|v| *v != doc.version) {
+            return Err(Error::VersionMismatch {
+                id,
+                expected,
+                found: doc.version,
+            });
+        }
+        let mut patch = MetadataPatch::default();
+        f(&mut patch);
+        if let Some(name) = &patch.visible_name {
+            patch.visible_name = Some(self.checked_name(name)?);
+        }
+        let before = patch.current(&doc, parent);
+        let after = patch.clone();
+        let status = self
+            .update_status(&[patch.apply(&doc, parent, self.now())])
+            .await?
+            .pop()
+            .ok_or(Error::EmptyResult)?;
+        if !status.success {
+            return Err(Error::Rejected {
+                message: status.message,
+            });
+        }
+        Ok(MetadataChange {
+            id,
+            before,
+            after,
+            version: doc.version + 1,
+        })
+    }
+
+    /// Makes many changes as `modify_metadata` does, but reading every
+    /// document from one listing and sending the changes in batches, a
+    /// request for each. The outcomes are in the order of `changes`. The
+    /// cloud refusing some changes, or a whole batch failing, doesn't stop
+    /// the rest.
+    pub async fn update_metadata_bulk(
+        &self,
+        changes: Vec<(Uuid, MetadataPatch)>,
+    ) -> Result<Vec<(Uuid, UpdateOutcome)>> {
+        self.check_writable()?;
+        let docs = self.get_documents().await?;
+        let mut outcomes = vec![];
+        for batch in changes.chunks(self.metadata_batch_size) {
+            let mut requests = vec![];
+            let mut planned = vec![];
+            for (id, patch) in batch {
+                let mut patch = patch.clone();
+                if let Some(name) = &patch.visible_name {
+                    match self.checked_name(name) {
+                        Ok(name) => patch.visible_name = Some(name),
+                        Err(e) => {
+                            planned.push((*id, Err(e.to_string())));
+                            continue;
+                        }
+                    }
+                }
+                let found = match docs.get(id) {
+                    Some(doc) => Some((doc, doc.parent.into())),
+                    None => docs
+                        .trashed()
+                        .find(|d| d.id == *id)
+                        .map(|doc| (doc, Parent::Trash)),
+                };
+                let change = match found {
+                    Some((doc, parent)) => {
+                        requests.push(patch.clone().apply(
+                            doc,
+                            parent,
+                            self.now(),
+                        ));
+                        Ok(MetadataChange {
+                            id: *id,
+                            before: patch.current(doc, parent),
+                            after: patch,
+                            version: doc.version + 1,
+                        })
+                    }
+                    None => Err("not in the cloud".to_string()),
+                };
+                planned.push((*id, change));
+            }
+            let statuses = if requests.is_empty() {
+                Ok(vec![])
+            } else {
+                self.update_status(&requests).await
+            };
+            for (id, change) in planned {
+                let status = statuses
+                    .as_ref()
+                    .map(|statuses| statuses.iter().find(|s| s.id == id));
+                let outcome = match (change, status) {
+                    (Err(message), _) => UpdateOutcome::Failed(message),
+                    (Ok(_), Err(e)) => UpdateOutcome::Failed(e.to_string()),
+                    (Ok(change), Ok(Some(s))) if s.success => {
+                        UpdateOutcome::Updated(change)
+                    }
+                    (Ok(_), Ok(Some(s))) => {
+                        UpdateOutcome::Failed(s.message.clone())
+                    }
+                    (Ok(_), Ok(None)) => {
+                        UpdateOutcome::Failed("not in the response".to_string())
+                    }
+                };
+                outcomes.push((id, outcome));
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Moves and renames a document, keeping the rest of its metadata. Move
+    /// it to `Parent::Trash` to trash it.
+    pub async fn move_document(
+        &self,
+        doc: &Document,
+        parent: Parent,
+        visible_name: &str,
+    ) -> Result<MetadataChange> {
+        self.modify_metadata(doc.id, |patch| {
+            patch.parent = Some(parent);
+            patch.visible_name = Some(visible_name.to_string());
+        })
+        .await
+    }
+
+    /// Bookmarks a document or removes its bookmark, as the listing records
+    /// it. See `set_pinned` for newer firmware.
+    pub async fn set_bookmarked(
+        &self,
+        id: Uuid,
+        bookmarked: bool,
+    ) -> Result<MetadataChange> {
+        self.modify_metadata(id, |patch| patch.bookmarked = Some(bookmarked))
+            .await
+    }
+
+    /// Sets the page a document opens at.
+    pub async fn set_current_page(
+        &self,
+        id: Uuid,
+        page: i32,
+    ) -> Result<MetadataChange> {
+        self.modify_metadata(id, |patch| patch.current_page = Some(page))
+            .await
+    }
+
+    /// Deletes a single document, which must be at its current version.
+    pub async fn delete_document(&self, doc: &Document) -> Result<()> {
+        let status = self
+            .delete(&[DeleteRequest {
+                id: doc.id,
+                version: doc.version,
+            }])
+            .await?
+            .pop()
+            .ok_or(Error::EmptyResult)?;
+        if !status.success {
+            return Err(Error::Rejected {
+                message: status.message,
+            });
+        }
+        Ok(())
+    }
+
+    /// Deletes `root` and everything below it in `docs`, which should be a
+    /// recent listing. Contents are deleted before the folders holding
+    /// them, and a folder only once all of its contents are gone, so no
+    /// document is ever left in a folder which has been deleted.
+    ///
+    /// Documents are deleted in batches, each retried on transient errors
+    /// as uploads are. `progress` is told what became of each document as
+    /// it's known. Failures don't fail the call but are in the report; a
+    /// request which keeps failing stops the run, skipping the rest.
+    pub async fn delete_subtree<F>(
+        &self,
+        root: &Document,
+        docs: &Documents,
+        mut progress: F,
+    ) -> Result<DeleteReport>
+    where
+        F: FnMut(&Document, &DeleteOutcome),
+    {
+        self.check_writable()?;
+        let mut report = DeleteReport::default();
+        // Folders holding something which is still in the cloud.
+        let mut kept = HashSet::new();
+        let mut stopped = false;
+        for level in delete::levels(root, docs) {
+            let ready: Vec<&Document> = level
+                .iter()
+                .copied()
+                .filter(|d| !kept.contains(&d.id))
+                .collect();
+            let mut outcomes = HashMap::new();
+            for batch in ready.chunks(DELETE_BATCH_SIZE) {
+                if stopped {
+                    break;
+                }
+                match self.delete_batch(batch).await {
+                    Ok(o) => outcomes.extend(batch.iter().map(|d| d.id).zip(o)),
+                    Err(e) => {
+                        stopped = true;
+                        let message = e.to_string();
+                        outcomes.extend(batch.iter().map(|d| {
+                            (d.id, DeleteOutcome::Failed(message.clone()))
+                        }));
+                    }
+                }
+            }
+            for doc in level {
+                let outcome =
+                    outcomes.remove(&doc.id).unwrap_or(DeleteOutcome::Skipped);
+                if !outcome.is_gone() {
+                    kept.extend(doc.parent);
+                }
+                progress(doc, &outcome);
+                report.outcomes.push((doc.id, outcome));
+            }
+        }
+        Ok(report)
+    }
+
+    // Deletes `batch` with one request, retried on transient errors. Those
+    // the cloud refuses are looked up, as a retried request will be refused
+    // any it carried out before its response was lost.
+    async fn delete_batch(
+        &self,
+        batch: &[&Document],
+    ) -> Result<Vec<DeleteOutcome>> {
+        let requests: Vec<DeleteRequest> = batch
+            .iter()
+            .map(|d| DeleteRequest {
+                id: d.id,
+                version: d.version,
+            })
+            .collect();
+        let mut delay = UPLOAD_RETRY_DELAY;
+        let mut attempt = 1;
+        let statuses = loop {
+            match self.delete(&requests).await {
+                Err(e) if e.is_transient() && attempt < UPLOAD_ATTEMPTS => {
+                    log::warn!("Retrying deletion after: {}", e);
+                    self.limits.delay(delay).await?;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => break result?,
+            }
+        };
+        let mut outcomes = vec![];
+        for doc in batch {
+            let status = statuses.iter().find(|s| s.id == doc.id);
+            let outcome = match status {
+                Some(s) if s.success => DeleteOutcome::Deleted,
+                _ => {
+                    let message = status.map_or_else(
+                        || "not in the response".to_string(),
+                        |s| s.message.clone(),
+                    );
+                    // Trashed documents are listed apart.
+                    match self.get_listing_of(&doc.id, false).await {
+                        Ok(docs)
+                            if !docs
+                                .iter()
+                                .chain(docs.trashed())
+                                .any(|d| d.id == doc.id) =>
+                        {
+                            DeleteOutcome::AlreadyGone
+                        }
+                        Ok(_) => DeleteOutcome::Failed(message),
+                        Err(e) => DeleteOutcome::Failed(e.to_string()),
+                    }
+                }
+            };
+            outcomes.push(outcome);
+        }
+        Ok(outcomes)
+    }
+
+    /// Uploads `zip` as version `version` of the document `id`, then sets
+    /// its metadata. Pass version 1 to create a new document.
+    pub async fn upload_zip(
+        &self,
+        id: Uuid,
+        version: u64,
+        parent: Option<Uuid>,
+        visible_name: &str,
+        doc_type: DocType,
+        zip: Vec<u8>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let mut upload =
+            Upload::new(id, version, parent, visible_name, doc_type);
+        while !upload.is_done() {
+            self.advance_upload(&mut upload, &zip).await?;
+        }
+        Ok(())
+    }
+
+    /// Carries out the next stage of `upload`, retrying it on transient
+    /// errors, and records the progress in `upload`. Call this until the
+    /// upload is done, saving `upload` in between to be able to finish it
+    /// from another process should this one die.
+    ///
+    /// A blob of at least the resumable threshold is sent a chunk at each
+    /// call, over a session the cloud keeps for it, so that what has been
+    /// sent survives the process dying, and each chunk is retried on its
+    /// own. If the cloud won't start a session, the blob is sent whole.
+    pub async fn advance_upload(
+        &self,
+        upload: &mut Upload,
+        zip: &[u8],
+    ) -> Result<()> {
+        self.advance_upload_from(upload, BlobSource::Memory(zip))
+            .await
+    }
+
+    /// As `advance_upload`, but with the archive wherever `zip` says, so
+    /// that one kept in a file needn't be read into memory to be sent.
+    pub async fn advance_upload_from(
+        &self,
+        upload: &mut Upload,
+        zip: BlobSource<'_>,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let mut delay = UPLOAD_RETRY_DELAY;
+        let mut attempt = 1;
+        loop {
+            match self.try_upload_stage(upload, zip).await {
+                Err(e) if e.is_transient() && attempt < UPLOAD_ATTEMPTS => {
+                    log::warn!(
+                        "Retrying {:?} upload of {} after: {}",
+                        upload.stage,
+                        upload.id,
+                        e
+                    );
+                    self.limits.delay(delay).await?;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_upload_stage(
+        &self,
+        upload: &mut Upload,
+        zip: BlobSource<'_>,
+    ) -> Result<()> {
+        match upload.stage {
+            UploadStage::Started => {
+                // Later versions keep the name the document already has.
+                if upload.version == 1 {
+                    upload.visible_name =
+                        self.checked_name(&upload.visible_name)?;
+                }
+                let response = self
+                    .upload_request(&[UploadRequest {
+                        id: upload.id,
+                        doc_type: upload.doc_type.clone(),
+                        version: upload.version,
+                    }])
+                    .await?
+                    .pop()
+                    .ok_or(Error::EmptyResult)?;
+                if !response.success {
+                    return Err(Error::Rejected {
+                        message: response.message,
+                    });
+                }
+                upload.blob_url_put = Some(response.blob_url_put);
+                upload.stage = UploadStage::Reserved;
+            }
+            UploadStage::Reserved => {
+                let url = upload.blob_url_put.clone().ok_or_else(|| {
+                    Error::Rejected {
+                        message: "no blob URL was handed out".to_string(),
+                    }
+                })?;
+                let size = zip.len();
+                let resumable =
+                    self.resumable_threshold.is_some_and(|t| size >= t);
+                // A session for a blob that has since changed is no use.
+                if upload.session.as_ref().is_some_and(|s| s.size != size) {
+                    upload.session = None;
+                }
+                if resumable && upload.session.is_none() {
+                    // Starting the session is a step of its own, so that
+                    // it's saved before any chunk is sent.
+                    if let Some(session) =
+                        self.start_resumable(&url, size).await?
+                    {
+                        upload.session = Some(session);
+                        return Ok(());
+                    }
+                }
+                match &mut upload.session {
+                    Some(session) => {
+                        match self.put_chunk_from(session, zip).await {
+                            Ok(false) => return Ok(()),
+                            Ok(true) => {}
+                            Err(Error::SessionExpired) => {
+                                log::warn!(
+                                    "Upload session of {} expired; starting \
+                                     again",
+                                    upload.id
+                                );
+                                upload.session = None;
+                                return Ok(());
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    None => self.put_blob_from(&url, zip).await?,
+                }
+                upload.session = None;
+                upload.stage = UploadStage::BlobPut;
+            }
+            UploadStage::BlobPut => {
+                let status = self
+                    .update_status(&[UpdateStatusRequest {
+                        bookmarked: upload.bookmarked,
+                        current_page: upload.current_page,
+                        ..UpdateStatusRequest::after_upload(
+                            upload.id,
+                            upload.version,
+                            upload.parent,
+                            &upload.visible_name,
+                            upload.doc_type.clone(),
+                            self.now(),
+                        )
+                    }])
+                    .await?
+                    .pop()
+                    .ok_or(Error::EmptyResult)?;
+                // An earlier attempt may have gone through with only its
+                // response lost, in which case the version is already ours.
+                if !status.success && !self.has_version(upload).await? {
+                    return Err(Error::Rejected {
+                        message: status.message,
+                    });
+                }
+                upload.stage = UploadStage::Done;
+            }
+            UploadStage::Done => {}
+        }
+        Ok(())
+    }
+
+    /// Fetches everything known about a document: its listing entry, and
+    /// the `.metadata` and `.content` files in its archive.
+    pub async fn document_details(&self, id: &Uuid) -> Result<DocumentDetails> {
+        let doc = self.get_document_by_id(id).await?;
+        let blob = self.download_blob(&doc).await?;
+        DocumentDetails::from_archive(doc, &blob)
+    }
+
+    /// The `archive::content_hash` of a document, downloading its archive.
+    pub async fn content_hash(&self, id: &Uuid) -> Result<[u8; 32]> {
+        let doc = self.get_document_by_id(id).await?;
+        let blob = self.download_blob(&doc).await?;
+        archive::content_hash(&mut zip::ZipArchive::new(io::Cursor::new(blob))?)
+    }
+
+    /// Downloads a document's archive and checks it with
+    /// `archive::verify`. Only a failure to download is an error.
+    pub async fn verify_archive(
+        &self,
+        id: &Uuid,
+    ) -> Result<ArchiveVerification> {
+        let doc = self.get_document_by_id(id).await?;
+        let blob = self.download_blob(&doc).await?;
+        Ok(archive::verify(doc.id, &blob))
+    }
+
+    /// Stars or unstars a document on the home screen, by rewriting its
+    /// `.metadata` and uploading the archive as a new version. The listing's
+    /// bookmark flag is set to match, for older firmware.
+    pub async fn set_pinned(&self, doc: &Document, pinned: bool) -> Result<()> {
+        self.check_writable()?;
+        let blobdoc = self.get_document_by_id(&doc.id).await?;
+        let blob = self.download_blob(&blobdoc).await?;
+        let zip = details::with_pinned(&blobdoc, &blob, pinned)?;
+        let mut upload = Upload::next_version(&blobdoc);
+        upload.bookmarked = pinned;
+        while !upload.is_done() {
+            self.advance_upload(&mut upload, &zip).await?;
+        }
+        Ok(())
+    }
+
+    /// Replaces what's drawn on page `page_index` of `doc`, counting from 0,
+    /// with the `.rm` data `rm_bytes`, and uploads the archive as a new
+    /// version. The data has to parse and the page has to exist, or nothing
+    /// is uploaded. Returns the number of strokes now on the page.
+    pub async fn replace_page_strokes(
+        &self,
+        doc: &Document,
+        page_index: usize,
+        rm_bytes: &[u8],
+    ) -> Result<u32> {
+        self.check_writable()?;
+        let page = Page::parse(rm_bytes)?;
+        let blobdoc = self.get_document_by_id(&doc.id).await?;
+        let blob = self.download_blob(&blobdoc).await?;
+        let zip = details::with_page(&blobdoc, &blob, page_index, rm_bytes)?;
+        let mut upload = Upload::next_version(&blobdoc);
+        while !upload.is_done() {
+            self.advance_upload(&mut upload, &zip).await?;
+        }
+        Ok(page.stroke_count() as u32)
+    }
+
+    /// Lists the pages of `doc`, downloading its archive.
+    pub async fn pages(&self, doc: &Document) -> Result<Vec<PageInfo>> {
+        let blobdoc = self.get_document_by_id(&doc.id).await?;
+        let blob = self.download_blob(&blobdoc).await?;
+        pages::list_pages(doc.id, &blob)
+    }
+
+    /// What's drawn on each page of the document `id`, downloading its
+    /// archive. See `pages::ink_stats`.
+    pub async fn ink_stats(&self, id: &Uuid) -> Result<Vec<Option<PageStats>>> {
+        let doc = self.get_document_by_id(id).await?;
+        let blob = self.download_blob(&doc).await?;
+        pages::ink_stats(doc.id, &blob)
+    }
+
+    /// The thumbnail of a page of `doc`, with the page's index, downloading
+    /// its archive. Without `index`, it's the page the document is open at
+    /// if that has a thumbnail, or else the first page. `None` if there's no
+    /// thumbnail.
+    pub async fn thumbnail(
+        &self,
+        doc: &Document,
+        index: Option<usize>,
+    ) -> Result<Option<(usize, Vec<u8>)>> {
+        let blobdoc = self.get_document_by_id(&doc.id).await?;
+        let blob = self.download_blob(&blobdoc).await?;
+        if let Some(index) = index {
+            let thumbnail = pages::page_thumbnail(doc.id, &blob, index)?;
+            return Ok(thumbnail.map(|t| (index, t)));
+        }
+        let current = blobdoc.current_page.max(0) as usize;
+        match pages::page_thumbnail(doc.id, &blob, current) {
+            Ok(Some(thumbnail)) => return Ok(Some((current, thumbnail))),
+            Ok(None) | Err(Error::NoSuchPage { .. }) => (),
+            Err(e) => return Err(e),
+        }
+        match pages::page_thumbnail(doc.id, &blob, 0) {
+            Ok(thumbnail) => Ok(thumbnail.map(|t| (0, t))),
+            Err(Error::NoSuchPage { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Puts the pages of the notebook `doc` in a new order, given as their
+    /// current indexes counting from 0, deleting any left out, and uploads
+    /// the archive as a new version. See `rearrange_pages`; nothing is
+    /// uploaded if it fails.
+    pub async fn set_page_order(
+        &self,
+        doc: &Document,
+        order: &[usize],
+    ) -> Result<()> {
+        self.check_writable()?;
+        let blobdoc = self.get_document_by_id(&doc.id).await?;
+        let blob = self.download_blob(&blobdoc).await?;
+        let zip = pages::rearrange_pages(doc.id, &blob, order)?;
+        let mut upload = Upload::next_version(&blobdoc);
+        while !upload.is_done() {
+            self.advance_upload(&mut upload, &zip).await?;
+        }
+        Ok(())
+    }
+
+    /// Fetches the details of each of `ids`, with at most `concurrency`
+    /// downloads at once, yielding them in the order of `ids`. A document
+    /// which can't be fetched or read yields an error in its place without
+    /// stopping the rest. Dropping the stream stops any downloads still
+    /// going.
+    pub fn document_details_bulk<'a>(
+        &'a self,
+        ids: &'a [Uuid],
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<DocumentDetails>> + 'a {
+        futures_util::stream::iter(ids)
+            .map(move |id| self.document_details(id))
+            .buffered(concurrency.max(1))
+    }
+
+    /// Hashes each of `ids` with `content_hash`, in the same way as
+    /// `document_details_bulk`.
+    pub fn content_hash_bulk<'a>(
+        &'a self,
+        ids: &'a [Uuid],
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<[u8; 32]>> + 'a {
+        futures_util::stream::iter(ids)
+            .map(move |id| self.content_hash(id))
+            .buffered(concurrency.max(1))
+    }
+
+    /// Checks each of `ids` with `verify_archive`, in the same way as
+    /// `document_details_bulk`.
+    pub fn verify_archive_bulk<'a>(
+        &'a self,
+        ids: &'a [Uuid],
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<ArchiveVerification>> + 'a {
+        futures_util::stream::iter(ids)
+            .map(move |id| self.verify_archive(id))
+            .buffered(concurrency.max(1))
+    }
+
+    /// Reads each of `ids` with `ink_stats`, in the same way as
+    /// `document_details_bulk`.
+    pub fn ink_stats_bulk<'a>(
+        &'a self,
+        ids: &'a [Uuid],
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<Vec<Option<PageStats>>>> + 'a {
+        futures_util::stream::iter(ids)
+            .map(move |id| self.ink_stats(id))
+            .buffered(concurrency.max(1))
+    }
+
+    async fn has_version(&self, upload: &Upload) -> Result<bool> {
+        match self.get_document_by_id(&upload.id).await {
+            Ok(doc) => Ok(doc.version == upload.version
+                && *doc.visible_name == upload.visible_name),
+            Err(Error::EmptyResult) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn migrated_responses() {
+        use reqwest::StatusCode;
+
+        let bad_request =
+            include_str!("../tests/fixtures/migrated_listing_400.json");
+        assert!(looks_migrated(StatusCode::BAD_REQUEST, bad_request, true));
+        assert!(looks_migrated(StatusCode::BAD_REQUEST, "", true));
+        // Elsewhere a 400 may be our own fault, so the body has to say.
+        assert!(looks_migrated(StatusCode::BAD_REQUEST, bad_request, false));
+        assert!(!looks_migrated(StatusCode::BAD_REQUEST, "", false));
+        let error =
+            include_str!("../tests/fixtures/migrated_listing_error.json");
+        assert!(looks_migrated(StatusCode::OK, error, true));
+
+        // Ordinary listings, empty or not, and unrelated failures don't count.
+        for listing in &[
+            include_str!("../tests/fixtures/listing_empty.json"),
+            include_str!("../tests/fixtures/listing_official.json"),
+            include_str!("../tests/fixtures/listing_poisoned.json"),
+        ] {
+            assert!(!looks_migrated(StatusCode::OK, listing, true));
+        }
+        let rejected =
+            r#"[{"ID": "x", "Success": false, "Message": "wrong version"}]"#;
+        assert!(!looks_migrated(StatusCode::OK, rejected, false));
+        assert!(!looks_migrated(StatusCode::UNAUTHORIZED, "", true));
+    }
 }
@@ -0,0 +1,95 @@
+//! The time the client stamps on what it changes, and what to make of the
+//! times the cloud hands back.
+//!
+//! A document's `modified_client` is whatever the last client to change it
+//! said the time was. A machine whose clock is ahead leaves documents
+//! modified in the future, which the tablet then sorts above everything
+//! else; one whose clock is behind would stamp a change as older than what
+//! it changed. So stamps are never moved backwards, see [`stamp`], and a
+//! time too far in the future, see [`is_future`], isn't to be trusted to
+//! tell anything by; versions still can be.
+
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+/// How far ahead of now a time the cloud gives can be before it's taken to
+/// come from a clock which is wrong.
+pub const DEFAULT_SKEW_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// Where the client gets the time from.
+pub trait ClockSource: fmt::Debug + Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's clock, which clients use unless given another.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl ClockSource for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock stopped at one time, for tests.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl ClockSource for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+/// The time to stamp on a change to something last modified at `current`:
+/// `now`, unless that's before `current`, as when this clock is behind the
+/// one which made the last change, in which case `current`.
+pub fn stamp(now: DateTime<Utc>, current: DateTime<Utc>) -> DateTime<Utc> {
+    now.max(current)
+}
+
+/// Whether `time` is more than `tolerance` after `now`, so that whatever
+/// stamped it had a clock which was ahead.
+pub fn is_future(
+    time: DateTime<Utc>,
+    now: DateTime<Utc>,
+    tolerance: Duration,
+) -> bool {
+    match (time - now).to_std() {
+        Ok(ahead) => ahead > tolerance,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn stamps() {
+        let current = at("2024-06-01T12:00:00Z");
+        // This clock is ahead of the last one, as it should be.
+        let later = at("2024-06-01T12:30:00Z");
+        assert_eq!(stamp(later, current), later);
+        // This clock is a day behind: the change isn't dated before what
+        // it changed.
+        let behind = at("2024-05-31T12:00:00Z");
+        assert_eq!(stamp(behind, current), current);
+        assert_eq!(FixedClock(behind).now(), behind);
+    }
+
+    #[test]
+    fn skewed() {
+        let now = at("2024-06-01T12:00:00Z");
+        let tolerance = DEFAULT_SKEW_TOLERANCE;
+        assert!(!is_future(at("2024-06-01T12:04:00Z"), now, tolerance));
+        assert!(is_future(at("2024-06-01T13:00:00Z"), now, tolerance));
+        assert!(!is_future(at("2020-01-01T00:00:00Z"), now, tolerance));
+    }
+}
@@ -0,0 +1,441 @@
+//! The grammar for naming a document, a folder, the root or the trash, as
+//! everything which takes a path from a user reads it:
+//!
+//! * names separated by `/`, from the root: `Books/Dune`. A leading `/` is
+//!   allowed, and the root itself is `/` or nothing at all. `\` separates
+//!   as `/` does, empty and `.` components are skipped, and `..` is an
+//!   error. Surrounding whitespace is trimmed.
+//! * `\/` is a `/` inside a name: `Books/A\/B` is the document "A/B" in the
+//!   folder "Books". A `\` anywhere else separates, so a name containing
+//!   one can only be reached by its id.
+//! * `trash:` followed by names, from the trash rather than the root:
+//!   `trash:/Old notes`. `trash:/` is the trash itself.
+//! * `uuid:` followed by an id: `uuid:8d2c7f44-1b39-4e57-a0f2-5b6a9c0d7e83`,
+//!   wherever the document is, including in the trash.
+//!
+//! A name starting with `trash:` or `uuid:` at the root is written with a
+//! leading `/`, which always means a path from the root.
+
+use std::fmt;
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use crate::documents::{join_path, Document, Documents};
+use crate::error::{Error, Result};
+use crate::requests::Parent;
+
+const TRASH_PREFIX: &str = "trash:";
+const ID_PREFIX: &str = "uuid:";
+
+/// A parsed path; see the module documentation for how one is written.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CloudPath {
+    /// The names from the root to a document, none for the root.
+    Tree(Vec<String>),
+    /// The names from the trash to a document, none for the trash.
+    Trash(Vec<String>),
+    /// A document by its id.
+    Id(Uuid),
+}
+
+/// What a `CloudPath` is in a listing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolved<'a> {
+    Root,
+    Trash,
+    Document(&'a Document),
+}
+
+impl CloudPath {
+    pub fn root() -> Self {
+        CloudPath::Tree(vec![])
+    }
+
+    /// Whether this is the root or the trash, rather than a document.
+    pub fn is_top(&self) -> bool {
+        match self {
+            CloudPath::Tree(names) | CloudPath::Trash(names) => {
+                names.is_empty()
+            }
+            CloudPath::Id(_) => false,
+        }
+    }
+
+    /// The path to the folder this is in and the name this has there, or
+    /// `None` for the root, the trash, or an id.
+    pub fn split_last(&self) -> Option<(CloudPath, &str)> {
+        let (names, wrap): (_, fn(Vec<String>) -> CloudPath) = match self {
+            CloudPath::Tree(names) => (names, CloudPath::Tree),
+            CloudPath::Trash(names) => (names, CloudPath::Trash),
+            CloudPath::Id(_) => return None,
+        };
+        let (last, parent) = names.split_last()?;
+        Some((wrap(parent.to_vec()), last))
+    }
+}
+
+impl FromStr for CloudPath {
+    type Err = Error;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let start = path.len() - path.trim_start().len();
+        let trimmed = path.trim();
+        if let Some(id) = trimmed.strip_prefix(ID_PREFIX) {
+            return Uuid::parse_str(id).map(CloudPath::Id).map_err(|_| {
+                Error::InvalidPath {
+                    path: path.to_string(),
+                    position: char_index(path, start + ID_PREFIX.len()),
+                    reason: "this isn't a document id; write a name \
+                             starting with uuid: as /uuid:…",
+                }
+            });
+        }
+        if let Some(rest) = trimmed.strip_prefix(TRASH_PREFIX) {
+            let offset = start + TRASH_PREFIX.len();
+            return names(path, rest, offset).map(CloudPath::Trash);
+        }
+        names(path, trimmed, start).map(CloudPath::Tree)
+    }
+}
+
+/// Written so that parsing it gives the same path back.
+impl fmt::Display for CloudPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CloudPath::Tree(names) => write!(f, "/{}", join_path(names)),
+            CloudPath::Trash(names) => {
+                write!(f, "{}/{}", TRASH_PREFIX, join_path(names))
+            }
+            CloudPath::Id(id) => write!(f, "{}{}", ID_PREFIX, id),
+        }
+    }
+}
+
+// The names in `names`, which starts at byte `offset` of `path`.
+pub(crate) fn names(
+    path: &str,
+    names: &str,
+    offset: usize,
+) -> Result<Vec<String>> {
+    let mut components = vec![];
+    let mut component = String::new();
+    let mut component_start = offset;
+    let mut chars = names.char_indices().peekable();
+    loop {
+        let next = chars.next();
+        match next {
+            Some((_, '\\')) if chars.peek().map(|&(_, c)| c) == Some('/') => {
+                component.push(chars.next().unwrap().1)
+            }
+            Some((_, c)) if c != '/' && c != '\\' => component.push(c),
+            end => {
+                match component.as_str() {
+                    "" | "." => (),
+                    ".." => {
+                        return Err(Error::InvalidPath {
+                            path: path.to_string(),
+                            position: char_index(path, component_start),
+                            reason: "\"..\" is not supported",
+                        })
+                    }
+                    _ => components.push(std::mem::take(&mut component)),
+                }
+                component.clear();
+                match end {
+                    Some((i, c)) => component_start = offset + i + c.len_utf8(),
+                    None => return Ok(components),
+                }
+            }
+        }
+    }
+}
+
+fn char_index(s: &str, byte: usize) -> usize {
+    s[..byte].chars().count()
+}
+
+/// Looks `path` up in `documents`, finding the documents named something
+/// in a folder with `named`, which is given `None` for the root. Callers
+/// with an index of names pass a lookup in it; `Documents::lookup` scans.
+///
+/// Names may contain `/`, so each run of names is also tried as one name,
+/// and a path more than one document fits is refused as ambiguous. A path
+/// nothing fits is `Error::PathNotFound`, saying where the path stopped
+/// matching.
+pub fn lookup_with<'a>(
+    documents: &'a Documents,
+    path: &CloudPath,
+    named: &dyn Fn(Option<Uuid>, &str) -> Vec<&'a Document>,
+) -> Result<Resolved<'a>> {
+    let (top, names) = match path {
+        CloudPath::Id(id) => {
+            return documents
+                .get(id)
+                .or_else(|| documents.trashed().find(|d| d.id == *id))
+                .map(Resolved::Document)
+                .ok_or_else(|| Error::PathNotFound {
+                    path: path.to_string(),
+                    reason: format!("no document has the id {}", id),
+                })
+        }
+        CloudPath::Tree(names) if names.is_empty() => {
+            return Ok(Resolved::Root)
+        }
+        CloudPath::Trash(names) if names.is_empty() => {
+            return Ok(Resolved::Trash)
+        }
+        CloudPath::Tree(names) => (Parent::Root, names),
+        CloudPath::Trash(names) => (Parent::Trash, names),
+    };
+    let in_folder = |parent: Parent, name: &str| match parent {
+        Parent::Root => named(None, name),
+        Parent::Folder(id) => named(Some(id), name),
+        Parent::Trash => documents
+            .trashed()
+            .filter(|d| &*d.visible_name == name)
+            .collect(),
+    };
+    let mut found = vec![];
+    find_below(&in_folder, top, names, &mut vec![], &mut found);
+    match found.len() {
+        0 => Err(Error::PathNotFound {
+            path: path.to_string(),
+            reason: not_found(&in_folder, top, names),
+        }),
+        1 => Ok(Resolved::Document(found.remove(0).1)),
+        _ => {
+            let mut matches: Vec<String> = found
+                .iter()
+                .map(|(names, _)| match top {
+                    Parent::Trash => {
+                        CloudPath::Trash(names.clone()).to_string()
+                    }
+                    _ => join_path(names),
+                })
+                .collect();
+            matches.sort();
+            Err(Error::AmbiguousPath {
+                path: path.to_string(),
+                matches,
+            })
+        }
+    }
+}
+
+// Collects the documents below `parent` at `names`, each with the names
+// they were found by, taking each run of names as a name containing `/` as
+// well as one at a time.
+fn find_below<'a>(
+    in_folder: &dyn Fn(Parent, &str) -> Vec<&'a Document>,
+    parent: Parent,
+    names: &[String],
+    by: &mut Vec<String>,
+    found: &mut Vec<(Vec<String>, &'a Document)>,
+) {
+    for len in 1..=names.len() {
+        let name = names[..len].join("/");
+        for d in in_folder(parent, &name) {
+            by.push(name.clone());
+            if len == names.len() {
+                found.push((by.clone(), d));
+            } else {
+                find_below(
+                    in_folder,
+                    Parent::Folder(d.id),
+                    &names[len..],
+                    by,
+                    found,
+                );
+            }
+            by.pop();
+        }
+    }
+}
+
+// Why nothing is at `names` below `top`, found by following the names one
+// at a time until one can't be.
+fn not_found<'a>(
+    in_folder: &dyn Fn(Parent, &str) -> Vec<&'a Document>,
+    top: Parent,
+    names: &[String],
+) -> String {
+    let wrap = |names: &[String]| match top {
+        Parent::Trash => CloudPath::Trash(names.to_vec()).to_string(),
+        _ => join_path(names),
+    };
+    let mut at: Vec<&Document> = vec![];
+    for (i, name) in names.iter().enumerate() {
+        let next: Vec<&Document> = if i == 0 {
+            in_folder(top, name)
+        } else {
+            at.iter()
+                .filter(|d| d.is_folder())
+                .flat_map(|d| in_folder(Parent::Folder(d.id), name))
+                .collect()
+        };
+        if next.is_empty() {
+            return if i == 0 && top == Parent::Trash {
+                format!("nothing named {:?} is in the trash", name)
+            } else if i == 0 {
+                format!("nothing named {:?} is at the root", name)
+            } else if at.iter().all(|d| !d.is_folder()) {
+                format!("{} is a document, not a folder", wrap(&names[..i]))
+            } else {
+                format!("nothing named {:?} is in {}", name, wrap(&names[..i]))
+            };
+        }
+        at = next;
+    }
+    // Every name was found one at a time, so what's missing is a document
+    // whose name has a `/` in it.
+    "nothing has that name".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> CloudPath {
+        s.parse().unwrap()
+    }
+
+    fn tree(names: &[&str]) -> CloudPath {
+        CloudPath::Tree(names.iter().map(|n| n.to_string()).collect())
+    }
+
+    fn trash(names: &[&str]) -> CloudPath {
+        CloudPath::Trash(names.iter().map(|n| n.to_string()).collect())
+    }
+
+    #[test]
+    fn grammar() {
+        let id =
+            Uuid::parse_str("8d2c7f44-1b39-4e57-a0f2-5b6a9c0d7e83").unwrap();
+        let cases = [
+            ("", tree(&[])),
+            ("/", tree(&[])),
+            ("Books/Dune", tree(&["Books", "Dune"])),
+            (" /Books\\Dune/ ", tree(&["Books", "Dune"])),
+            ("Books/A\\/B", tree(&["Books", "A/B"])),
+            ("/trash:x", tree(&["trash:x"])),
+            ("/uuid:x", tree(&["uuid:x"])),
+            ("trash:", trash(&[])),
+            ("trash:/", trash(&[])),
+            ("trash:Old/Notes", trash(&["Old", "Notes"])),
+            ("trash:/Old/./Notes/", trash(&["Old", "Notes"])),
+            (
+                "uuid:8d2c7f44-1b39-4e57-a0f2-5b6a9c0d7e83",
+                CloudPath::Id(id),
+            ),
+            (
+                " uuid:8d2c7f44-1b39-4e57-a0f2-5b6a9c0d7e83\n",
+                CloudPath::Id(id),
+            ),
+        ];
+        for (written, path) in &cases {
+            assert_eq!(parse(written), *path, "{:?}", written);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let paths = [
+            tree(&[]),
+            tree(&["Books", "Dune"]),
+            tree(&["A/B", "C"]),
+            tree(&["trash:x"]),
+            tree(&["uuid:x", "y"]),
+            tree(&[" spaced ", "é/ü"]),
+            trash(&[]),
+            trash(&["Old", "A/B"]),
+            CloudPath::Id(Uuid::new_v4()),
+        ];
+        for path in &paths {
+            let written = path.to_string();
+            assert_eq!(parse(&written), *path, "{:?}", written);
+        }
+        assert_eq!(tree(&[]).to_string(), "/");
+        assert_eq!(tree(&["A/B", "C"]).to_string(), "/A\\/B/C");
+        assert_eq!(trash(&[]).to_string(), "trash:/");
+        assert_eq!(trash(&["Old"]).to_string(), "trash:/Old");
+    }
+
+    #[test]
+    fn error_positions() {
+        let cases = [
+            ("..", 0),
+            ("Books/../Dune", 6),
+            ("  Books\\..", 8),
+            ("é/..", 2),
+            ("trash:/Old/..", 11),
+            ("uuid:nope", 5),
+            (" uuid:", 6),
+        ];
+        for (written, at) in &cases {
+            match written.parse::<CloudPath>() {
+                Err(Error::InvalidPath { path, position, .. }) => {
+                    assert_eq!(path, *written);
+                    assert_eq!(position, *at, "{:?}", written);
+                }
+                other => panic!("{:?} parsed as {:?}", written, other),
+            }
+        }
+        let err = "Books/../Dune".parse::<CloudPath>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid path \"Books/../Dune\" at character 7: \"..\" is not \
+             supported"
+        );
+    }
+
+    #[test]
+    fn lookups() {
+        let mut listing: serde_json::Value = serde_json::from_str(
+            include_str!("../tests/fixtures/listing_official.json"),
+        )
+        .unwrap();
+        let mut trashed = listing[1].clone();
+        let trashed_id = Uuid::new_v4();
+        trashed["ID"] = trashed_id.to_string().into();
+        trashed["VissibleName"] = "Old".into();
+        trashed["Parent"] = "trash".into();
+        listing.as_array_mut().unwrap().push(trashed);
+        let docs: Documents = serde_json::from_value(listing).unwrap();
+        let lookup = |s: &str| docs.lookup(&parse(s));
+
+        let dune = match lookup("Books/Dune").unwrap() {
+            Resolved::Document(d) => d,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(lookup("/").unwrap(), Resolved::Root);
+        assert_eq!(lookup("trash:/").unwrap(), Resolved::Trash);
+        assert_eq!(
+            lookup(&format!("uuid:{}", dune.id)).unwrap(),
+            Resolved::Document(dune)
+        );
+        let old = lookup(&format!("uuid:{}", trashed_id)).unwrap();
+        assert_eq!(old, lookup("trash:/Old").unwrap());
+        assert!(lookup("Old").is_err());
+
+        let reason = |s: &str| match lookup(s) {
+            Err(Error::PathNotFound { reason, .. }) => reason,
+            other => panic!("{:?} gave {:?}", s, other),
+        };
+        assert_eq!(reason("Dnue"), "nothing named \"Dnue\" is at the root");
+        assert_eq!(reason("Books/Dnue"), "nothing named \"Dnue\" is in Books");
+        assert_eq!(
+            reason("Books/Dune/Chapter 1"),
+            "Books/Dune is a document, not a folder"
+        );
+        assert_eq!(
+            reason("trash:/New"),
+            "nothing named \"New\" is in the trash"
+        );
+        assert!(reason(&format!("uuid:{}", Uuid::nil())).contains("the id"));
+        assert_eq!(
+            lookup("Books/Dnue").unwrap_err().to_string(),
+            "Couldn't find \"/Books/Dnue\": nothing named \"Dnue\" is in Books"
+        );
+    }
+}
@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use remarkable_data_formats::content::Content;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::details::read_entry;
+use crate::documents::{Document, Documents};
+use crate::error::Result;
+use crate::pages::bookmarks;
+
+/// What a document's `.content` says about it, as kept in a
+/// `ContentCache`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CachedContent {
+    /// The version of the document this was read from.
+    pub version: u64,
+    /// `pdf`, `epub` or `notebook`.
+    pub file_type: String,
+    /// From `.content` if it says, and otherwise the number of drawn pages
+    /// in the archive.
+    pub page_count: Option<u64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// How many pages are bookmarked, or `None` if that wasn't read, as for
+    /// entries cached before it was.
+    #[serde(default)]
+    pub bookmarked_pages: Option<usize>,
+}
+
+impl CachedContent {
+    /// Reads what's cached of the archive of `doc`.
+    pub fn from_archive(doc: &Document, zip: &[u8]) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+        let content =
+            match read_entry(&mut archive, &format!("{}.content", doc.id))? {
+                Some(data) => Some(Content::parse(&data)?),
+                None => None,
+            };
+        let drawn = archive.file_names().filter(|n| n.ends_with(".rm"));
+        let drawn = Some(drawn.count() as u64).filter(|n| *n > 0);
+        let file_type = match content.as_ref().map(|c| c.file_type.as_str()) {
+            Some(t @ "pdf") | Some(t @ "epub") => t,
+            _ => "notebook",
+        };
+        Ok(CachedContent {
+            version: doc.version,
+            file_type: file_type.to_string(),
+            page_count: content.as_ref().and_then(|c| c.page_count).or(drawn),
+            tags: content
+                .iter()
+                .flat_map(|c| c.tags())
+                .map(String::from)
+                .collect(),
+            bookmarked_pages: Some(
+                content.as_ref().map_or(0, |c| bookmarks(c).len()),
+            ),
+        })
+    }
+}
+
+struct Inner {
+    path: Option<PathBuf>,
+    entries: BTreeMap<Uuid, CachedContent>,
+    // Whether the file, if any, has been read yet.
+    loaded: bool,
+    // Whether there are changes the file doesn't have yet.
+    dirty: bool,
+}
+
+impl Inner {
+    fn load(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+        let read = self.path.as_ref().and_then(|p| crate::read_locked(p).ok());
+        if let Some(entries) =
+            read.and_then(|d| serde_json::from_slice(&d).ok())
+        {
+            self.entries = entries;
+        }
+    }
+
+    // Writes the entries aside and renames them into place, so readers
+    // never see half of them.
+    fn save(&mut self) -> io::Result<()> {
+        let path = match (&self.path, self.dirty) {
+            (Some(path), true) => path,
+            _ => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        crate::write_atomically(path, &serde_json::to_vec(&self.entries)?)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            log::warn!("Couldn't save the content cache: {}", e);
+        }
+    }
+}
+
+/// What's been read from the `.content` of documents downloaded before, by
+/// id and version, so their type, page count and tags can be known without
+/// downloading them again. A `Client` given one adds to it whenever it
+/// downloads a blob.
+///
+/// An entry is only ever returned for the version it was read from, so a
+/// document updated since is as good as uncached. Changes are written out
+/// with `flush`, or when the last handle to the cache goes; processes
+/// writing it at once may lose each other's additions, which are only
+/// downloaded again. Cloning a `ContentCache` produces a handle to the same
+/// cache.
+#[derive(Clone)]
+pub struct ContentCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ContentCache {
+    /// A cache held in memory, lasting as long as the handles to it.
+    pub fn new() -> Self {
+        ContentCache {
+            inner: Arc::new(Mutex::new(Inner {
+                path: None,
+                entries: BTreeMap::new(),
+                loaded: true,
+                dirty: false,
+            })),
+        }
+    }
+
+    /// A cache also kept in the file at `path`. The file is read when first
+    /// needed; one which can't be read is ignored.
+    pub fn at_path(path: PathBuf) -> Self {
+        let cache = ContentCache::new();
+        {
+            let mut inner = cache.inner.lock().unwrap();
+            inner.path = Some(path);
+            inner.loaded = false;
+        }
+        cache
+    }
+
+    /// What's cached of `doc`, if it was read from the version it's at.
+    pub fn get(&self, doc: &Document) -> Option<CachedContent> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        inner
+            .entries
+            .get(&doc.id)
+            .filter(|c| c.version == doc.version)
+            .cloned()
+    }
+
+    /// Keeps `content` for the document `id`, replacing what was kept of
+    /// any other version of it.
+    pub fn insert(&self, id: Uuid, content: CachedContent) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        if inner.entries.get(&id) != Some(&content) {
+            inner.entries.insert(id, content);
+            inner.dirty = true;
+        }
+    }
+
+    /// Keeps what the archive of `doc` says, if it can be read.
+    pub fn record(&self, doc: &Document, zip: &[u8]) {
+        if !doc.is_document() {
+            return;
+        }
+        match CachedContent::from_archive(doc, zip) {
+            Ok(content) => self.insert(doc.id, content),
+            Err(e) => {
+                log::debug!("Not caching the contents of {}: {}", doc.id, e)
+            }
+        }
+    }
+
+    /// Drops what's kept of documents which aren't in `documents`, or are
+    /// at another version there, returning how many were dropped.
+    pub fn prune(&self, documents: &Documents) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        let before = inner.entries.len();
+        inner.entries.retain(|id, content| {
+            documents
+                .get(id)
+                .is_some_and(|d| d.version == content.version)
+        });
+        let pruned = before - inner.entries.len();
+        inner.dirty |= pruned > 0;
+        pruned
+    }
+
+    /// How many documents are cached, of any version.
+    pub fn len(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.load();
+        inner.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes out what's changed, if the cache is kept in a file.
+    pub fn flush(&self) -> io::Result<()> {
+        self.inner.lock().unwrap().save()
+    }
+}
+
+impl Default for ContentCache {
+    fn default() -> Self {
+        ContentCache::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn listing(docs: &[(u128, u64)]) -> Documents {
+        let entries: Vec<_> = docs
+            .iter()
+            .map(|(id, version)| {
+                serde_json::json!({
+                    "ID": Uuid::from_u128(*id),
+                    "Version": version,
+                    "Type": "DocumentType",
+                    "VissibleName": "Doc",
+                    "Parent": "",
+                    "CurrentPage": 0,
+                    "Bookmarked": false,
+                    "Message": "",
+                    "ModifiedClient": "2020-01-01T00:00:00Z",
+                    "BlobURLGet": "",
+                    "BlobURLGetExpires": "0001-01-01T00:00:00Z",
+                })
+            })
+            .collect();
+        serde_json::from_value(entries.into()).unwrap()
+    }
+
+    fn archive(id: Uuid, content: &str, pages: usize) -> Vec<u8> {
+        let mut za = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        za.start_file(format!("{}.content", id), Default::default())
+            .unwrap();
+        za.write_all(content.as_bytes()).unwrap();
+        for n in 0..pages {
+            za.start_file(format!("{}/{}.rm", id, n), Default::default())
+                .unwrap();
+        }
+        za.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn keyed_on_version() {
+        let id = Uuid::from_u128(1);
+        let v1 = listing(&[(1, 1)]);
+        let cache = ContentCache::new();
+        let tagged = r#"{"fileType": "pdf", "pageCount": 12,
+            "tags": [{"name": "Work", "timestamp": 1}]}"#;
+        cache.record(v1.get(&id).unwrap(), &archive(id, tagged, 0));
+        let cached = cache.get(v1.get(&id).unwrap()).unwrap();
+        assert_eq!(
+            cached,
+            CachedContent {
+                version: 1,
+                file_type: "pdf".into(),
+                page_count: Some(12),
+                tags: vec!["Work".into()],
+                bookmarked_pages: Some(0),
+            }
+        );
+
+        // Once the document is updated, what was read of it is no use.
+        let v2 = listing(&[(1, 2)]);
+        assert_eq!(cache.get(v2.get(&id).unwrap()), None);
+        cache.record(v2.get(&id).unwrap(), &archive(id, "{}", 3));
+        let cached = cache.get(v2.get(&id).unwrap()).unwrap();
+        assert_eq!(
+            (cached.file_type.as_str(), cached.page_count),
+            ("notebook", Some(3))
+        );
+        assert_eq!(cache.get(v1.get(&id).unwrap()), None);
+        assert_eq!(cache.len(), 1);
+
+        // Something that isn't an archive isn't kept.
+        let other = listing(&[(2, 1)]);
+        cache.record(other.get(&Uuid::from_u128(2)).unwrap(), b"not a zip");
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn prunes_and_persists() {
+        let path = std::env::temp_dir()
+            .join(format!("content-cache-{}.json", Uuid::new_v4()));
+        let documents = listing(&[(1, 1), (2, 1), (3, 4)]);
+        {
+            let cache = ContentCache::at_path(path.clone());
+            for doc in documents.iter() {
+                cache.record(
+                    doc,
+                    &archive(doc.id, r#"{"fileType": "epub"}"#, 0),
+                );
+            }
+            cache.flush().unwrap();
+            // Dropping it writes out what's changed since.
+            let stale = CachedContent {
+                version: 3,
+                ..cache
+                    .get(documents.get(&Uuid::from_u128(3)).unwrap())
+                    .unwrap()
+            };
+            cache.insert(Uuid::from_u128(3), stale);
+            cache.insert(Uuid::from_u128(4), Default::default());
+        }
+
+        let cache = ContentCache::at_path(path.clone());
+        assert_eq!(cache.len(), 4);
+        let now = listing(&[(1, 1), (3, 4)]);
+        assert_eq!(cache.prune(&now), 3);
+        cache.flush().unwrap();
+        let cache = ContentCache::at_path(path.clone());
+        let cached = cache.get(now.get(&Uuid::from_u128(1)).unwrap());
+        assert_eq!(cached.unwrap().file_type, "epub");
+        assert_eq!(cache.len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
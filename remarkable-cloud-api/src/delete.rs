@@ -0,0 +1,121 @@
+//! Deleting a folder along with everything in it.
+//!
+//! The cloud deletes exactly what it is asked to, so a folder deleted before
+//! its contents leaves them pointing at a parent which is gone. Everything
+//! is therefore deleted a level at a time, deepest first, and a folder is
+//! only deleted once all of its contents are.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::documents::{Document, Documents};
+use crate::requests::Parent;
+
+/// What became of one document in `Client::delete_subtree`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeleteOutcome {
+    Deleted,
+    /// It was no longer in the cloud, as after an earlier run which was cut
+    /// short.
+    AlreadyGone,
+    /// The cloud refused to delete it, or couldn't be asked to, with why.
+    /// One changed since it was listed is refused.
+    Failed(String),
+    /// It wasn't asked about: a folder still holding something which
+    /// couldn't be deleted, or anything left once the run had to stop.
+    Skipped,
+}
+
+impl DeleteOutcome {
+    /// Whether the document is out of the cloud now.
+    pub fn is_gone(&self) -> bool {
+        matches!(self, DeleteOutcome::Deleted | DeleteOutcome::AlreadyGone)
+    }
+}
+
+/// What `Client::delete_subtree` did with each document, in the order they
+/// were dealt with. Running it again with a fresh listing picks up where an
+/// incomplete run left off.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeleteReport {
+    pub outcomes: Vec<(Uuid, DeleteOutcome)>,
+}
+
+impl DeleteReport {
+    /// Whether every document is out of the cloud.
+    pub fn is_complete(&self) -> bool {
+        self.outcomes.iter().all(|(_, o)| o.is_gone())
+    }
+
+    /// The documents still in the cloud, with what became of them.
+    pub fn remaining(&self) -> impl Iterator<Item = &(Uuid, DeleteOutcome)> {
+        self.outcomes.iter().filter(|(_, o)| !o.is_gone())
+    }
+}
+
+/// `root` and everything below it in `docs`, a level at a time, deepest
+/// first, ending with `root` alone.
+pub(crate) fn levels<'a>(
+    root: &'a Document,
+    docs: &'a Documents,
+) -> Vec<Vec<&'a Document>> {
+    let mut by_depth: HashMap<usize, Vec<&Document>> = HashMap::new();
+    for (depth, doc) in docs.descendants(Parent::Folder(root.id)) {
+        by_depth.entry(depth + 1).or_default().push(doc);
+    }
+    let mut levels = vec![vec![root]];
+    for depth in 1..=by_depth.len() {
+        levels.push(by_depth.remove(&depth).unwrap_or_default());
+    }
+    levels.reverse();
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A folder like the first one in the fixture listing.
+    fn folder(
+        name: &str,
+        parent: Option<&serde_json::Value>,
+    ) -> serde_json::Value {
+        let listing: serde_json::Value = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let mut folder = listing[0].clone();
+        folder["ID"] = Uuid::new_v4().to_string().into();
+        folder["VissibleName"] = name.into();
+        folder["Type"] = "CollectionType".into();
+        folder["Parent"] = parent.map_or("".into(), |p| p["ID"].clone());
+        folder
+    }
+
+    fn names(root: &serde_json::Value, docs: &Documents) -> Vec<Vec<String>> {
+        let root = docs.get(&root["ID"].as_str().unwrap().parse().unwrap());
+        levels(root.unwrap(), docs)
+            .iter()
+            .map(|l| l.iter().map(|d| d.visible_name.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn deepest_first() {
+        let books = folder("Books", None);
+        let scifi = folder("Sci-fi", Some(&books));
+        let dune = folder("Dune", Some(&scifi));
+        let notes = folder("Notes", Some(&books));
+        let other = folder("Other", None);
+        let docs: Documents = serde_json::from_value(serde_json::json!([
+            books, scifi, dune, notes, other
+        ]))
+        .unwrap();
+        assert_eq!(
+            names(&books, &docs),
+            [vec!["Dune"], vec!["Notes", "Sci-fi"], vec!["Books"]]
+        );
+        assert_eq!(names(&dune, &docs), [vec!["Dune"]]);
+    }
+}
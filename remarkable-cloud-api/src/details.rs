@@ -0,0 +1,381 @@
+use std::io::{self, Read, Write};
+
+use remarkable_data_formats::content::Content;
+use remarkable_data_formats::lines::Page;
+use remarkable_data_formats::metadata::Metadata;
+use serde::Serialize;
+
+use crate::archive::ArchiveSummary;
+use crate::documents::{DocType, Document};
+use crate::error::{Error, Result};
+use crate::pages::Pages;
+
+/// Where a document's pinned state was read from.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PinnedSource {
+    /// The `pinned` field of its `.metadata`, as written by newer firmware.
+    Metadata,
+    /// The listing's bookmark flag, which older firmware uses instead.
+    Listing,
+}
+
+/// A document's listing entry together with the files describing it inside
+/// its archive, as returned by `Client::document_details`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentDetails {
+    pub document: Document,
+    /// `None` if the archive has no `.metadata`.
+    pub metadata: Option<Metadata>,
+    /// `None` if the archive has no `.content`.
+    pub content: Option<Content>,
+    /// From `.content` if it says, and otherwise the number of drawn pages
+    /// in the archive.
+    pub page_count: Option<u64>,
+    /// Strokes drawn across all pages, or `None` if a page is in a format
+    /// that can't be read.
+    pub stroke_count: Option<usize>,
+    /// The size of the archive in bytes.
+    pub blob_size: u64,
+}
+
+pub(crate) fn read_entry(
+    archive: &mut zip::ZipArchive<io::Cursor<&[u8]>>,
+    name: &str,
+) -> Result<Option<Vec<u8>>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut data = vec![];
+    file.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+impl DocumentDetails {
+    /// Reads the details of `document` from its archive.
+    pub fn from_archive(document: Document, zip: &[u8]) -> Result<Self> {
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+        let metadata = match read_entry(
+            &mut archive,
+            &format!("{}.metadata", document.id),
+        )? {
+            Some(data) => Some(Metadata::parse(&data)?),
+            None => None,
+        };
+        let content = match read_entry(
+            &mut archive,
+            &format!("{}.content", document.id),
+        )? {
+            Some(data) => Some(Content::parse(&data)?),
+            None => None,
+        };
+        let pages: Vec<String> = archive
+            .file_names()
+            .filter(|n| n.ends_with(".rm"))
+            .map(String::from)
+            .collect();
+        let mut stroke_count = Some(0);
+        for name in &pages {
+            let data = read_entry(&mut archive, name)?.unwrap_or_default();
+            stroke_count = match (stroke_count, Page::parse(&data)) {
+                (Some(n), Ok(page)) => Some(n + page.stroke_count()),
+                _ => None,
+            };
+        }
+        let page_count = content
+            .as_ref()
+            .and_then(|c| c.page_count)
+            .or_else(|| Some(pages.len() as u64).filter(|n| *n > 0));
+        Ok(DocumentDetails {
+            document,
+            metadata,
+            content,
+            page_count,
+            stroke_count,
+            blob_size: zip.len() as u64,
+        })
+    }
+
+    /// Reads the details of a document from an archive saved locally, with
+    /// no listing to go with it. What the listing would say is taken from
+    /// the archive's `.metadata`; without one, the document is called
+    /// `name` and is at the top level.
+    pub fn from_local_archive(zip: &[u8], name: &str) -> Result<Self> {
+        let summary = ArchiveSummary::read(io::Cursor::new(zip))?;
+        let metadata = summary.metadata.as_ref();
+        let modified = summary
+            .modified()
+            .unwrap_or_else(|| std::time::UNIX_EPOCH.into());
+        let document = Document {
+            id: summary.id,
+            version: summary.version().unwrap_or(0),
+            visible_name: summary.visible_name().unwrap_or(name).into(),
+            parent: metadata.and_then(|m| m.parent.parse().ok()),
+            doc_type: metadata
+                .map(|m| m.doc_type.clone())
+                .filter(|t| !t.is_empty())
+                .map_or(DocType::Document, DocType::from),
+            current_page: 0,
+            bookmarked: metadata.and_then(|m| m.pinned).unwrap_or(false),
+            message: String::new(),
+            modified_client: modified,
+            blob_url_get: None,
+            blob_url_get_expires: None,
+        };
+        DocumentDetails::from_archive(document, zip)
+    }
+
+    /// Whether the document is a notebook, rather than an imported PDF or
+    /// EPUB.
+    pub fn is_notebook(&self) -> bool {
+        match &self.content {
+            Some(c) => !matches!(c.file_type.as_str(), "pdf" | "epub"),
+            None => true,
+        }
+    }
+
+    /// Whether the document is starred on the home screen.
+    pub fn pinned(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .and_then(|m| m.pinned)
+            .unwrap_or(self.document.bookmarked)
+    }
+
+    pub fn pinned_source(&self) -> PinnedSource {
+        match self.metadata.as_ref().and_then(|m| m.pinned) {
+            Some(_) => PinnedSource::Metadata,
+            None => PinnedSource::Listing,
+        }
+    }
+}
+
+/// Rewrites the archive of `document` with its `.metadata` marked pinned or
+/// not, creating the file from the listing entry if the archive has none.
+/// Everything else in the archive is copied as it is.
+pub(crate) fn with_pinned(
+    document: &Document,
+    zip: &[u8],
+    pinned: bool,
+) -> Result<Vec<u8>> {
+    let mut src = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let name = format!("{}.metadata", document.id);
+    let mut metadata = match read_entry(&mut src, &name)? {
+        Some(data) => Metadata::parse(&data)?,
+        None => Metadata {
+            visible_name: document.visible_name.to_string(),
+            doc_type: document.doc_type.to_string(),
+            parent: document.parent.map(|p| p.to_string()).unwrap_or_default(),
+            last_modified: Some(document.modified_client),
+            ..Default::default()
+        },
+    };
+    metadata.version = document.version + 1;
+    metadata.pinned = Some(pinned);
+    replace_entry(&mut src, &name, &metadata.to_vec())
+}
+
+// Copies the archive `src` with the file `name` replaced by `data`, or
+// added if it isn't there.
+fn replace_entry(
+    src: &mut zip::ZipArchive<io::Cursor<&[u8]>>,
+    name: &str,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let mut dst = zip::ZipWriter::new(io::Cursor::new(vec![]));
+    for i in 0..src.len() {
+        let mut file = src.by_index(i)?;
+        if file.name() == name {
+            continue;
+        }
+        let options = zip::write::FileOptions::default()
+            .compression_method(file.compression());
+        if file.is_dir() {
+            dst.add_directory(file.name(), options)?;
+            continue;
+        }
+        dst.start_file(file.name(), options)?;
+        io::copy(&mut file, &mut dst)?;
+    }
+    dst.start_file(name, zip::write::FileOptions::default())?;
+    dst.write_all(data)?;
+    Ok(dst.finish()?.into_inner())
+}
+
+// The name of the `.rm` file for page `index`.
+fn page_entry(
+    document: &Document,
+    archive: &mut zip::ZipArchive<io::Cursor<&[u8]>>,
+    index: usize,
+) -> Result<String> {
+    let keys = Pages::read(document.id, archive)?.keys;
+    match keys.get(index) {
+        Some(key) => Ok(format!("{}/{}.rm", document.id, key)),
+        None => Err(Error::NoSuchPage {
+            index,
+            page_count: keys.len(),
+        }),
+    }
+}
+
+/// Rewrites the archive of `document` with page `index` replaced by the
+/// `.rm` data `rm`. Everything else in the archive is copied as it is.
+pub(crate) fn with_page(
+    document: &Document,
+    zip: &[u8],
+    index: usize,
+    rm: &[u8],
+) -> Result<Vec<u8>> {
+    let mut src = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let name = page_entry(document, &mut src, index)?;
+    replace_entry(&mut src, &name, rm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dune() -> Document {
+        let docs: crate::documents::Documents = serde_json::from_str(
+            include_str!("../tests/fixtures/listing_official.json"),
+        )
+        .unwrap();
+        let dune = docs.iter().find(|d| &*d.visible_name == "Dune").cloned();
+        dune.unwrap()
+    }
+
+    fn archive(files: &[(String, &[u8])]) -> Vec<u8> {
+        let mut za = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in files {
+            za.start_file(name, zip::write::FileOptions::default())
+                .unwrap();
+            za.write_all(data).unwrap();
+        }
+        za.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn pinned_sources() {
+        let mut doc = dune();
+        doc.bookmarked = true;
+        let content = (format!("{}.content", doc.id), &b"{}"[..]);
+
+        let old = archive(std::slice::from_ref(&content));
+        let details = DocumentDetails::from_archive(doc.clone(), &old).unwrap();
+        assert!(details.metadata.is_none());
+        assert!(details.pinned());
+        assert_eq!(details.pinned_source(), PinnedSource::Listing);
+
+        let new = archive(&[
+            content,
+            (format!("{}.metadata", doc.id), br#"{"pinned": false}"#),
+        ]);
+        let details = DocumentDetails::from_archive(doc, &new).unwrap();
+        assert!(!details.pinned());
+        assert_eq!(details.pinned_source(), PinnedSource::Metadata);
+    }
+
+    fn page(strokes: u32) -> Vec<u8> {
+        let mut buf =
+            format!("{:<43}", "reMarkable .lines file, version=5").into_bytes();
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&strokes.to_le_bytes());
+        for _ in 0..strokes {
+            buf.extend_from_slice(&[0; 20]);
+            buf.extend_from_slice(&0u32.to_le_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn pages_and_strokes() {
+        let doc = dune();
+        let details = |files: &[(String, &[u8])]| {
+            DocumentDetails::from_archive(doc.clone(), &archive(files)).unwrap()
+        };
+        let (blank, drawn) = (page(0), page(2));
+
+        let notebook = details(&[
+            (format!("{}/0.rm", doc.id), &blank),
+            (format!("{}/1.rm", doc.id), &drawn),
+        ]);
+        assert!(notebook.is_notebook());
+        assert_eq!(notebook.page_count, Some(2));
+        assert_eq!(notebook.stroke_count, Some(2));
+        assert!(notebook.blob_size > 0);
+
+        let empty = details(&[(format!("{}.content", doc.id), b"{}")]);
+        assert!(empty.is_notebook());
+        assert_eq!(empty.page_count, None);
+        assert_eq!(empty.stroke_count, Some(0));
+
+        let pdf = details(&[(
+            format!("{}.content", doc.id),
+            br#"{"fileType": "pdf", "pageCount": 412}"#,
+        )]);
+        assert!(!pdf.is_notebook());
+        assert_eq!(pdf.page_count, Some(412));
+
+        let newer = details(&[(
+            format!("{}/0.rm", doc.id),
+            b"reMarkable .lines file, version=7          ",
+        )]);
+        assert_eq!(newer.stroke_count, None);
+    }
+
+    #[test]
+    fn rewrite_metadata() {
+        let doc = dune();
+        let pdf = (format!("{}.pdf", doc.id), &b"%PDF-1.4"[..]);
+        let old = archive(std::slice::from_ref(&pdf));
+
+        let pinned = with_pinned(&doc, &old, true).unwrap();
+        let details =
+            DocumentDetails::from_archive(doc.clone(), &pinned).unwrap();
+        assert_eq!(details.pinned_source(), PinnedSource::Metadata);
+        assert!(details.pinned());
+        let metadata = details.metadata.unwrap();
+        assert_eq!(metadata.visible_name, "Dune");
+        assert_eq!(metadata.version, doc.version + 1);
+
+        let unpinned = with_pinned(&doc, &pinned, false).unwrap();
+        let mut za =
+            zip::ZipArchive::new(io::Cursor::new(&unpinned[..])).unwrap();
+        assert_eq!(za.len(), 2);
+        assert_eq!(read_entry(&mut za, &pdf.0).unwrap().unwrap(), pdf.1);
+        let details = DocumentDetails::from_archive(doc, &unpinned).unwrap();
+        assert!(!details.pinned());
+    }
+
+    #[test]
+    fn local_archives() {
+        let doc = dune();
+        let metadata = br#"{"visibleName": "Dune", "type": "DocumentType",
+            "parent": "", "version": 4, "pinned": true,
+            "lastModified": "1606813364402"}"#;
+        let content = (format!("{}.content", doc.id), &br#"{}"#[..]);
+        let described = archive(&[
+            (format!("{}.metadata", doc.id), &metadata[..]),
+            content.clone(),
+        ]);
+        let details =
+            DocumentDetails::from_local_archive(&described, "x").unwrap();
+        let local = &details.document;
+        assert_eq!((local.id, local.version), (doc.id, 4));
+        assert_eq!((&*local.visible_name, local.parent), ("Dune", None));
+        assert_eq!(local.modified_client, doc.modified_client);
+        assert!(local.is_document() && details.pinned());
+
+        let bare = archive(&[content]);
+        let details =
+            DocumentDetails::from_local_archive(&bare, "dune-v3").unwrap();
+        assert_eq!(&*details.document.visible_name, "dune-v3");
+        assert_eq!(details.document.version, 0);
+        assert!(details.metadata.is_none() && details.content.is_some());
+
+        let unnamed = archive(&[("p/p.pdf".to_string(), &b"pdf"[..])]);
+        assert!(DocumentDetails::from_local_archive(&unnamed, "p").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// What a `Client` is set up to talk to, for status displays, as returned
+/// by `Client::diagnostics`.
+///
+/// It never holds the tokens themselves, only what can be read from them,
+/// so it is safe to log or show.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ClientDiagnostics {
+    /// The storage host the document API is served from.
+    pub endpoint: String,
+    /// When the user token stops being accepted, or `None` if there isn't
+    /// one or it doesn't say.
+    pub user_token_expires: Option<chrono::DateTime<chrono::Utc>>,
+    /// The account the user token was issued to, if it says.
+    pub user_id: Option<String>,
+    /// A short hash telling device tokens apart, or `None` if there isn't
+    /// one.
+    pub device_token_fingerprint: Option<String>,
+    pub read_only: bool,
+    /// How many times each stage of an upload is tried.
+    pub upload_attempts: u32,
+    /// The wait before retrying an upload stage the first time, which
+    /// doubles after each attempt.
+    pub upload_retry_delay: Duration,
+}
+
+/// The shapes of response entries whose fields are checked for drift. Each
+/// lists the fields expected in every entry, alternative spellings joined
+/// by `|`, followed by those which may be there or not.
+pub(crate) struct Shape {
+    pub required: &'static [&'static str],
+    pub optional: &'static [&'static str],
+}
+
+/// A document in a listing.
+pub(crate) const LISTING_ENTRY: Shape = Shape {
+    required: &[
+        "ID",
+        "Version",
+        "VissibleName|VisibleName",
+        "Parent",
+        "Type",
+        "CurrentPage",
+        "Bookmarked",
+        "Message",
+        "ModifiedClient",
+        "BlobURLGet",
+        "BlobURLGetExpires",
+    ],
+    optional: &["Success"],
+};
+
+/// The answer about one document to an update or deletion.
+pub(crate) const STATUS_ENTRY: Shape = Shape {
+    required: &["ID", "Version", "Message", "Success"],
+    optional: &[],
+};
+
+/// The answer about one document to a request to upload.
+pub(crate) const UPLOAD_ENTRY: Shape = Shape {
+    required: &["ID", "Version", "Message", "Success", "BlobURLPut"],
+    optional: &["BlobURLPutExpires"],
+};
+
+/// Fields which responses had but this version of the client doesn't know,
+/// or lacked though it expects them, as collected by a `Client` with
+/// `set_schema_diagnostics`. Either is a sign the cloud has changed.
+#[derive(Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SchemaDrift {
+    /// How many response entries were looked at.
+    pub entries: usize,
+    /// Each unknown field, with how many entries had it.
+    pub unknown_fields: BTreeMap<String, usize>,
+    /// Each missing field, with how many entries lacked it.
+    pub missing_fields: BTreeMap<String, usize>,
+}
+
+impl SchemaDrift {
+    /// Whether every entry looked at was as expected.
+    pub fn is_empty(&self) -> bool {
+        self.unknown_fields.is_empty() && self.missing_fields.is_empty()
+    }
+
+    /// Checks each entry of the JSON array `body` against `shape`. Bodies
+    /// which aren't arrays of objects are left to fail to parse.
+    pub(crate) fn record(&mut self, shape: &Shape, body: &str) {
+        let entries: Vec<serde_json::Value> = match serde_json::from_str(body) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in &entries {
+            let fields = match entry.as_object() {
+                Some(fields) => fields,
+                None => continue,
+            };
+            self.entries += 1;
+            for required in shape.required {
+                if !required.split('|').any(|f| fields.contains_key(f)) {
+                    let name = required.split('|').next().unwrap_or_default();
+                    *self
+                        .missing_fields
+                        .entry(name.to_string())
+                        .or_default() += 1;
+                }
+            }
+            let known = |field: &str| {
+                shape.optional.contains(&field)
+                    || shape
+                        .required
+                        .iter()
+                        .any(|r| r.split('|').any(|f| f == field))
+            };
+            for field in fields.keys().filter(|f| !known(f)) {
+                *self.unknown_fields.entry(field.clone()).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// The claims of a JSON web token which are of interest here.
+#[derive(serde::Deserialize, Default, Debug, PartialEq, Eq)]
+pub(crate) struct Claims {
+    pub sub: Option<String>,
+    pub exp: Option<i64>,
+}
+
+/// Reads the claims of `token` without checking its signature, or `None`
+/// if it isn't a JSON web token.
+pub(crate) fn claims(token: &str) -> Option<Claims> {
+    let payload = token.split('.').nth(1)?;
+    let json = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// The first 8 bytes of the SHA-256 of `token`, in hex.
+pub(crate) fn fingerprint(token: &str) -> String {
+    Sha256::digest(token.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_drift() {
+        let mut drift = SchemaDrift::default();
+        drift.record(
+            &LISTING_ENTRY,
+            include_str!("../tests/fixtures/listing_official.json"),
+        );
+        drift.record(
+            &LISTING_ENTRY,
+            include_str!("../tests/fixtures/listing_rmfakecloud.json"),
+        );
+        assert_eq!(drift.entries, 4);
+        assert!(drift.is_empty(), "{:?}", drift);
+
+        let body = r#"[
+            {"ID": "a", "Version": 1, "Message": "", "Success": true,
+             "Tags": []},
+            {"ID": "b", "Message": "", "Success": true, "Tags": []},
+            "not an entry"
+        ]"#;
+        drift.record(&STATUS_ENTRY, body);
+        drift.record(&STATUS_ENTRY, "not json");
+        assert_eq!(drift.entries, 6);
+        assert_eq!(drift.unknown_fields["Tags"], 2);
+        assert_eq!(drift.missing_fields["Version"], 1);
+        assert_eq!(drift.missing_fields.len(), 1);
+    }
+
+    #[test]
+    fn token_claims() {
+        let token = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                     eyJzdWIiOiJhdXRoMHxmYWtlLXVzZXIiLCJleHAiOjQxMDI0NDQ4MDB9.\
+                     c2lnbmF0dXJl";
+        assert_eq!(
+            claims(token),
+            Some(Claims {
+                sub: Some("auth0|fake-user".to_string()),
+                exp: Some(4102444800),
+            })
+        );
+        assert_eq!(claims("not-a-jwt"), None);
+        assert_eq!(claims("a.!!!.c"), None);
+        assert_eq!(fingerprint(token).len(), 16);
+        assert_ne!(fingerprint(token), fingerprint("other"));
+    }
+}
@@ -1,34 +1,176 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
 use std::path;
 use std::result;
 
+use futures_util::{Stream, StreamExt};
 use serde::de::Deserialize;
 use uuid::Uuid;
 
-#[derive(serde::Deserialize, Debug)]
+use crate::cloudpath::{self, CloudPath, Resolved};
+use crate::error::{Error, Result};
+use crate::natural::NameOrder;
+use crate::requests::{Parent, TRASH_PARENT};
+
+/// What kind of entry a `Document` is, as the cloud's `Type` field says.
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash,
+)]
+#[serde(from = "String", into = "String")]
+pub enum DocType {
+    /// A notebook, PDF or EPUB: `DocumentType`.
+    Document,
+    /// A folder: `CollectionType`.
+    Collection,
+    /// A type this crate doesn't know, kept as given so it survives being
+    /// sent back.
+    Unknown(String),
+}
+
+impl DocType {
+    /// The type as the cloud spells it.
+    pub fn as_str(&self) -> &str {
+        match self {
+            DocType::Document => "DocumentType",
+            DocType::Collection => "CollectionType",
+            DocType::Unknown(s) => s,
+        }
+    }
+}
+
+impl From<String> for DocType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "DocumentType" => DocType::Document,
+            "CollectionType" => DocType::Collection,
+            _ => DocType::Unknown(s),
+        }
+    }
+}
+
+impl From<DocType> for String {
+    fn from(doc_type: DocType) -> Self {
+        match doc_type {
+            DocType::Unknown(s) => s,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DocType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq, Hash,
+)]
 pub struct Document {
     // The serde renames are to map rust-style names to the JSON api.
     #[serde(rename = "ID")]
     pub id: Uuid,
-    #[serde(rename = "VissibleName")]
-    pub visible_name: String,
-    #[serde(rename = "Parent", deserialize_with = "deserialize_optional_uuid")]
+    #[serde(rename = "Version")]
+    pub version: u64,
+    // The official cloud misspells this, but rmfakecloud and some newer
+    // responses don't.
+    #[serde(rename = "VissibleName", alias = "VisibleName")]
+    pub visible_name: Box<str>,
+    #[serde(
+        rename = "Parent",
+        serialize_with = "serialize_optional_uuid",
+        deserialize_with = "deserialize_optional_uuid"
+    )]
     pub parent: Option<Uuid>,
     #[serde(rename = "Type")]
-    pub doc_type: String,
+    pub doc_type: DocType,
     #[serde(rename = "CurrentPage")]
     pub current_page: i32,
     #[serde(rename = "Bookmarked")]
     pub bookmarked: bool,
     #[serde(rename = "Message")]
     pub message: String,
-    #[serde(rename = "ModifiedClient")]
+    #[serde(
+        rename = "ModifiedClient",
+        with = "remarkable_data_formats::timefmt::rfc3339"
+    )]
     pub modified_client: chrono::DateTime<chrono::Utc>,
-    #[serde(rename = "BlobURLGet")]
-    pub blob_url_get: String,
-    #[serde(rename = "BlobURLGetExpires")]
-    pub blob_url_get_expires: chrono::DateTime<chrono::Utc>,
+    /// Where to download the document from, only given when asked for, as
+    /// `Client::get_document_by_id` does. Listings of every document leave
+    /// it out, so as not to hold a long URL for each.
+    #[serde(
+        rename = "BlobURLGet",
+        serialize_with = "serialize_blob_url",
+        deserialize_with = "deserialize_blob_url"
+    )]
+    pub blob_url_get: Option<String>,
+    /// When `blob_url_get` stops working, given with it.
+    #[serde(
+        rename = "BlobURLGetExpires",
+        serialize_with = "serialize_blob_url_expires",
+        deserialize_with = "deserialize_blob_url_expires"
+    )]
+    pub blob_url_get_expires: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Document {
+    pub fn is_folder(&self) -> bool {
+        self.doc_type == DocType::Collection
+    }
+
+    pub fn is_document(&self) -> bool {
+        self.doc_type == DocType::Document
+    }
+
+    // The bytes held apart from the document itself.
+    fn heap_size(&self) -> usize {
+        let doc_type = match &self.doc_type {
+            DocType::Unknown(s) => s.capacity(),
+            _ => 0,
+        };
+        self.visible_name.len()
+            + self.message.capacity()
+            + self.blob_url_get.as_ref().map_or(0, String::capacity)
+            + doc_type
+    }
+}
+
+/// Splits a document path from the root into the names along it, as
+/// `CloudPath` reads one, without its `trash:` and `uuid:` forms. Both `/`
+/// and `\\` are separators, empty and `.` components are ignored, and
+/// surrounding whitespace is trimmed. `..` is rejected rather than guessed
+/// at. The root is an empty list.
+///
+/// Names may themselves contain `/`, which is written `\\/` to keep it from
+/// separating: "Notes/A\\/B" is the document "A/B" in the folder "Notes".
+pub fn split_path(path: &str) -> Result<Vec<String>> {
+    let start = path.len() - path.trim_start().len();
+    cloudpath::names(path, path.trim(), start)
+}
+
+/// Joins names into a path, escaping any `/` in them so that `split_path`
+/// gives the same names back, unless they contain `\\`.
+pub fn join_path<S: AsRef<str>>(names: &[S]) -> String {
+    names
+        .iter()
+        .map(|n| n.as_ref().replace('/', "\\/"))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn serialize_optional_uuid<S>(
+    uuid: &Option<Uuid>,
+    serializer: S,
+) -> result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match uuid {
+        Some(uuid) => serializer.collect_str(uuid),
+        None => serializer.serialize_str(""),
+    }
 }
 
 // Extends UUID parsing by representing empty string as None
@@ -40,7 +182,12 @@ where
 {
     let buf = String::deserialize(deserializer)?;
 
-    if buf == "" {
+    if buf.is_empty() {
+        Ok(None)
+    } else if buf == "root" || buf == "Root" {
+        // Never sent by the official cloud, but seen from other servers;
+        // it can only mean the top level.
+        log::warn!("Read a parent of {:?} as the top level", buf);
         Ok(None)
     } else {
         Uuid::parse_str(&buf)
@@ -49,11 +196,89 @@ where
     }
 }
 
-#[derive(Default)]
+// Writes a missing blob URL as the cloud does, as an empty string.
+fn serialize_blob_url<S>(
+    url: &Option<String>,
+    serializer: S,
+) -> result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(url.as_deref().unwrap_or_default())
+}
+
+fn deserialize_blob_url<'de, D>(
+    deserializer: D,
+) -> result::Result<Option<String>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let url = String::deserialize(deserializer)?;
+    Ok(Some(url).filter(|url| !url.is_empty()))
+}
+
+// The time the cloud gives for a blob URL it hasn't given, the first instant
+// of the year 1.
+fn no_expiry() -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::parse_from_rfc3339("0001-01-01T00:00:00Z")
+        .unwrap()
+        .into()
+}
+
+fn serialize_blob_url_expires<S>(
+    expires: &Option<chrono::DateTime<chrono::Utc>>,
+    serializer: S,
+) -> result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serde::Serialize::serialize(&expires.unwrap_or_else(no_expiry), serializer)
+}
+
+fn deserialize_blob_url_expires<'de, D>(
+    deserializer: D,
+) -> result::Result<Option<chrono::DateTime<chrono::Utc>>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let expires = chrono::DateTime::<chrono::Utc>::deserialize(deserializer)?;
+    Ok(Some(expires).filter(|expires| *expires > no_expiry()))
+}
+
+/// Why the folders a document is in couldn't be worked out, as found by
+/// `Documents::ancestors`.
+#[derive(Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum PathError {
+    /// The document, or a folder it's in, isn't in the listing.
+    #[display(fmt = "{} isn't in the listing", id)]
+    Missing { id: Uuid },
+    /// The folders the document is in lead back round to this one.
+    #[display(fmt = "{} is inside itself", id)]
+    Cycle { id: Uuid },
+}
+
+impl std::error::Error for PathError {}
+
+#[derive(Clone, Debug, Default)]
 pub struct Documents {
     by_id: HashMap<Uuid, Document>,
+    /// The ids of the documents in each folder, or at the root for `None`,
+    /// in name order. Kept up to date with `by_id`.
+    children: HashMap<Option<Uuid>, Vec<Uuid>>,
+    trash: HashMap<Uuid, Document>,
+    parse_warnings: Vec<(usize, String)>,
+}
+
+/// Listings are equal when they hold the same documents, whatever order they
+/// were listed in. Parse warnings aren't compared.
+impl PartialEq for Documents {
+    fn eq(&self, other: &Self) -> bool {
+        self.by_id == other.by_id && self.trash == other.trash
+    }
 }
 
+impl Eq for Documents {}
+
 impl Documents {
     pub fn len(&self) -> usize {
         self.by_id.len()
@@ -63,52 +288,689 @@ impl Documents {
         self.len() == 0
     }
 
+    /// The entries of the listing which couldn't be parsed and were left out,
+    /// as their index in the listing and what was wrong with them.
+    pub fn parse_warnings(&self) -> &[(usize, String)] {
+        &self.parse_warnings
+    }
+
+    /// Iterates over every document, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &Document> {
+        self.by_id.values()
+    }
+
     pub fn get(&self, uuid: &Uuid) -> Option<&Document> {
         self.by_id.get(uuid)
     }
 
+    /// Iterates over the documents in the trash, which are otherwise left
+    /// out of the listing. They're given no parent.
+    pub fn trashed(&self) -> impl Iterator<Item = &Document> {
+        self.trash.values()
+    }
+
+    /// The documents in the trash last modified before `cutoff`, oldest
+    /// first. As moving a document to the trash modifies it, that's those
+    /// trashed before then, unless they were changed again in the trash.
+    pub fn trashed_older_than(
+        &self,
+        cutoff: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<&Document> {
+        let mut older: Vec<&Document> = self
+            .trash
+            .values()
+            .filter(|d| d.modified_client < cutoff)
+            .collect();
+        older.sort_by_key(|d| (d.modified_client, d.id));
+        older
+    }
+
+    /// The documents, out of the trash, which could have pages bookmarked
+    /// since `since`, or at all without it: as tagging a page modifies its
+    /// document, those modified since, oldest first. Which pages are
+    /// bookmarked is only known from their archives, so any of these may
+    /// have none.
+    pub fn bookmark_candidates(
+        &self,
+        since: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Vec<&Document> {
+        let mut found: Vec<&Document> = self
+            .iter()
+            .filter(|d| d.is_document())
+            .filter(|d| since.is_none_or(|since| d.modified_client >= since))
+            .collect();
+        found.sort_by_key(|d| (d.modified_client, d.id));
+        found
+    }
+
     pub fn get_by_path(&self, path: &path::Path) -> Option<&Document> {
-        // TODO: The runtime of this is O(n^m) where n is the total number of
-        // documents and m is the number of path components. Since we have O(1)
-        // lookup by id this should be doable in O(n).
-        for d in self.by_id.values() {
-            if d.visible_name
-                == path
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_str()
-                    .unwrap_or_default()
-            {
-                match path.parent().zip(d.parent) {
-                    None => return Some(d),
-                    Some((parent_path, parent_id)) => {
-                        match self.get_by_path(parent_path) {
-                            None => continue,
-                            Some(parent) => {
-                                if parent.id == parent_id {
-                                    return Some(d);
-                                }
-                            }
-                        }
-                    }
-                }
+        self.resolve(path.to_string_lossy()).ok().flatten()
+    }
+
+    /// Finds the document at a path, as `CloudPath` reads it, so
+    /// "Books/Dune", "/Books//Dune/" and "Books\\Dune" all name the same
+    /// document. Returns `None` if nothing is at the path, including for the
+    /// root and the trash themselves.
+    ///
+    /// As names may contain `/`, "A/B" could be "B" in the folder "A" or a
+    /// document named "A/B"; if more than one document fits, the path is
+    /// refused as ambiguous. Writing "A\\/B" means only the latter.
+    pub fn resolve<S: AsRef<str>>(&self, path: S) -> Result<Option<&Document>> {
+        let written = path.as_ref();
+        let found = written.parse().and_then(|path| self.lookup(&path));
+        match found {
+            Ok(Resolved::Document(d)) => Ok(Some(d)),
+            Ok(_) | Err(Error::PathNotFound { .. }) => Ok(None),
+            Err(Error::AmbiguousPath { matches, .. }) => {
+                Err(Error::AmbiguousPath {
+                    path: written.to_string(),
+                    matches,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Finds what `path` names, failing with `Error::PathNotFound` saying
+    /// why if nothing is there; see `lookup_with`.
+    pub fn lookup(&self, path: &CloudPath) -> Result<Resolved<'_>> {
+        cloudpath::lookup_with(self, path, &|parent, name| {
+            self.children_of(&parent)
+                .filter(|d| &*d.visible_name == name)
+                .collect()
+        })
+    }
+
+    /// Returns the path from the root to a document, as `join_path` writes
+    /// it. Returns `None` if the document or one of its ancestors isn't
+    /// known, or if its ancestors form a cycle.
+    pub fn path_of(&self, uuid: &Uuid) -> Option<String> {
+        let mut components = vec![];
+        let mut current = self.get(uuid)?;
+        loop {
+            components.push(&*current.visible_name);
+            if components.len() > self.len() {
+                return None;
+            }
+            match current.parent {
+                None => break,
+                Some(parent) => current = self.get(&parent)?,
+            }
+        }
+        components.reverse();
+        Some(join_path(&components))
+    }
+
+    /// The folders `uuid` is in, from its parent up to one at the root, so
+    /// none for a document at the root. A document in the trash has no
+    /// parent, so the folders of one in a trashed folder end at that one.
+    ///
+    /// Fails if the document or one of its ancestors isn't known, or if its
+    /// ancestors form a cycle, naming the document where that was found.
+    pub fn ancestors(
+        &self,
+        uuid: &Uuid,
+    ) -> result::Result<Vec<&Document>, PathError> {
+        let find = |id: &Uuid| {
+            self.get(id)
+                .or_else(|| self.trash.get(id))
+                .ok_or(PathError::Missing { id: *id })
+        };
+        let mut ancestors = vec![];
+        let mut seen = HashSet::new();
+        seen.insert(*uuid);
+        let mut current = find(uuid)?;
+        while let Some(parent) = current.parent {
+            if !seen.insert(parent) {
+                return Err(PathError::Cycle { id: parent });
             }
+            current = find(&parent)?;
+            ancestors.push(current);
         }
-        None
+        Ok(ancestors)
+    }
+
+    /// Whether `b` is somewhere inside the folder `a`, failing as
+    /// `ancestors` does for `b`.
+    pub fn is_ancestor_of(
+        &self,
+        a: &Uuid,
+        b: &Uuid,
+    ) -> result::Result<bool, PathError> {
+        Ok(self.ancestors(b)?.iter().any(|d| d.id == *a))
+    }
+
+    // The documents in the folder `uuid`, or at the root for `None`, in
+    // name order.
+    fn children_of<'a>(
+        &'a self,
+        uuid: &Option<Uuid>,
+    ) -> impl Iterator<Item = &'a Document> + 'a {
+        let ids = self.children.get(uuid).map(Vec::as_slice);
+        ids.unwrap_or_default()
+            .iter()
+            .map(move |id| &self.by_id[id])
     }
 
+    /// The documents in the folder `uuid`, or at the root for `None`, in
+    /// name order.
     pub fn get_children(&self, uuid: &Option<Uuid>) -> Vec<&Document> {
-        let mut acc: Vec<&Document> = vec![];
-        for d in self.by_id.values() {
-            if d.parent == *uuid {
-                acc.push(&d);
+        self.children_of(uuid).collect()
+    }
+
+    /// The folders at the root, in name order.
+    pub fn root_folders(&self) -> Vec<&Document> {
+        self.grouped_children(Parent::Root).folders
+    }
+
+    /// The documents other than folders at the root, in name order.
+    pub fn root_documents(&self) -> Vec<&Document> {
+        self.grouped_children(Parent::Root).documents
+    }
+
+    /// What's in `parent`, the folders apart from the rest, each in name
+    /// order. Nothing is in a folder that isn't in the listing.
+    pub fn grouped_children(&self, parent: Parent) -> GroupedChildren<'_> {
+        let (folders, documents) = self
+            .children_in(parent)
+            .into_iter()
+            .partition(|d| d.is_folder());
+        GroupedChildren { folders, documents }
+    }
+
+    /// `grouped_children`, with names in `order` rather than by code point.
+    pub fn grouped_children_by<'a>(
+        &'a self,
+        parent: Parent,
+        order: NameOrder<'_>,
+    ) -> GroupedChildren<'a> {
+        let mut grouped = self.grouped_children(parent);
+        sort_siblings_by(&mut grouped.folders, order);
+        sort_siblings_by(&mut grouped.documents, order);
+        grouped
+    }
+
+    // What's directly in `parent`, in name order.
+    fn children_in(&self, parent: Parent) -> Vec<&Document> {
+        match parent {
+            Parent::Root => self.get_children(&None),
+            Parent::Folder(id) => self.get_children(&Some(id)),
+            Parent::Trash => {
+                let mut trashed: Vec<&Document> = self.trash.values().collect();
+                sort_siblings(&mut trashed);
+                trashed
             }
         }
-        acc
+    }
+
+    /// How many documents are directly in each folder, or at the root for
+    /// `None`, counting folders. Folders with nothing in them are left out,
+    /// as is the trash.
+    pub fn counts_by_parent(&self) -> HashMap<Option<Uuid>, usize> {
+        self.children
+            .iter()
+            .map(|(parent, ids)| (*parent, ids.len()))
+            .collect()
+    }
+
+    /// Walks everything below `parent` depth first, yielding each document
+    /// with how far below `parent` it is, 0 for its children. Siblings come
+    /// in name order. The listing is indexed once, so the walk is linear in
+    /// its size, and a cycle of parents is walked around only once.
+    pub fn descendants(&self, parent: Parent) -> Descendants<'_> {
+        self.walk(parent, None)
+    }
+
+    /// `descendants`, with siblings in `order` of their names rather than
+    /// by code point. Those of the same name come in id order.
+    pub fn descendants_by<'a>(
+        &'a self,
+        parent: Parent,
+        order: NameOrder<'a>,
+    ) -> Descendants<'a> {
+        self.walk(parent, Some(order))
+    }
+
+    fn walk<'a>(
+        &'a self,
+        parent: Parent,
+        order: Option<NameOrder<'a>>,
+    ) -> Descendants<'a> {
+        let mut roots = self.children_in(parent);
+        if let Some(order) = order {
+            sort_siblings_by(&mut roots, order);
+        }
+        let mut seen = HashSet::new();
+        if let Parent::Folder(id) = parent {
+            seen.insert(id);
+        }
+        Descendants {
+            documents: self,
+            stack: roots.into_iter().rev().map(|d| (0, d)).collect(),
+            seen,
+            order,
+        }
+    }
+
+    /// Looks for a document named `name` in `parent`, where uploading one
+    /// of the same name would make a second. Folders of that name don't
+    /// count. The trash never has conflicts, as nothing goes there by name.
+    pub fn conflict_for(
+        &self,
+        parent: Parent,
+        name: &str,
+    ) -> Option<Conflict<'_>> {
+        let folder = match parent {
+            Parent::Root => None,
+            Parent::Folder(id) => Some(id),
+            Parent::Trash => return None,
+        };
+        let siblings = self.get_children(&folder);
+        // Siblings are in name order, so the first has the lowest id.
+        let existing = siblings
+            .iter()
+            .find(|d| &*d.visible_name == name && !d.is_folder())?;
+        let taken: HashSet<&str> =
+            siblings.iter().map(|d| &*d.visible_name).collect();
+        let free_name = (2..)
+            .map(|n| format!("{} ({})", name, n))
+            .find(|n| !taken.contains(n.as_str()))
+            .unwrap();
+        Some(Conflict {
+            existing,
+            free_name,
+        })
+    }
+
+    /// Checks that documents can be put in `parent`: that it's the root, or
+    /// a folder in the listing. The trash is refused, as is a folder which
+    /// is missing, trashed, or actually a document.
+    pub fn validate_parent(&self, parent: Parent) -> Result<ValidatedParent> {
+        let invalid = |parent: String, reason| {
+            Err(Error::InvalidDestination { parent, reason })
+        };
+        let id = match parent {
+            Parent::Root => return Ok(ValidatedParent(parent)),
+            Parent::Trash => {
+                return invalid(
+                    "the trash".to_string(),
+                    "documents can only be moved there once they exist",
+                )
+            }
+            Parent::Folder(id) => id,
+        };
+        match self.by_id.get(&id) {
+            Some(d) if d.is_folder() => Ok(ValidatedParent(parent)),
+            Some(d) => invalid(
+                self.path_of(&id)
+                    .unwrap_or_else(|| d.visible_name.to_string()),
+                "it isn't a folder",
+            ),
+            None if self.trash.contains_key(&id) => {
+                invalid(id.to_string(), "the folder is in the trash")
+            }
+            None => invalid(id.to_string(), "there's no such folder"),
+        }
+    }
+
+    /// Adds `doc` to the listing, replacing any document with its id.
+    pub fn insert(&mut self, doc: Document) {
+        self.remove(&doc.id);
+        let by_id = &self.by_id;
+        let siblings = self.children.entry(doc.parent).or_default();
+        let key = (&doc.visible_name, doc.id);
+        let at = siblings.partition_point(|id| {
+            let sibling = &by_id[id];
+            (&sibling.visible_name, sibling.id) < key
+        });
+        siblings.insert(at, doc.id);
+        self.by_id.insert(doc.id, doc);
     }
 
     pub fn remove(&mut self, uuid: &Uuid) -> Option<Document> {
-        self.by_id.remove(uuid)
+        let doc = self.by_id.remove(uuid)?;
+        if let Some(siblings) = self.children.get_mut(&doc.parent) {
+            siblings.retain(|id| id != uuid);
+            if siblings.is_empty() {
+                self.children.remove(&doc.parent);
+            }
+        }
+        Some(doc)
+    }
+
+    /// Gives back the room kept for documents that aren't there, as after
+    /// removing many, for programs holding the listing a long while.
+    /// Listings are already this small as parsed.
+    pub fn shrink_to_fit(&mut self) {
+        self.by_id.shrink_to_fit();
+        self.trash.shrink_to_fit();
+        self.children.shrink_to_fit();
+        for siblings in self.children.values_mut() {
+            siblings.shrink_to_fit();
+        }
+        self.parse_warnings.shrink_to_fit();
+    }
+
+    /// About how many bytes the listing takes, with its documents' names
+    /// and so on, for programs holding it a long while to report. What the
+    /// allocator keeps for itself isn't counted.
+    pub fn approx_memory(&self) -> usize {
+        // A map's slots, an eighth more than it has room for, each with a
+        // byte for the table to find them with.
+        fn table<K, V>(map: &HashMap<K, V>) -> usize {
+            map.capacity() * 8 / 7 * (mem::size_of::<(K, V)>() + 1)
+        }
+        let documents = self.by_id.values().chain(self.trash.values());
+        let children: usize = self
+            .children
+            .values()
+            .map(|ids| ids.capacity() * mem::size_of::<Uuid>())
+            .sum();
+        let warnings: usize = self
+            .parse_warnings
+            .iter()
+            .map(|(_, message)| message.capacity())
+            .sum();
+        mem::size_of::<Self>()
+            + table(&self.by_id)
+            + table(&self.trash)
+            + table(&self.children)
+            + documents.map(Document::heap_size).sum::<usize>()
+            + children
+            + self.parse_warnings.capacity() * mem::size_of::<(usize, String)>()
+            + warnings
+    }
+
+    // Rebuilds `children` from `by_id`, which is quicker than inserting
+    // documents one at a time, and lets go of the room left from building
+    // the listing up.
+    fn index(&mut self) {
+        let mut children: HashMap<Option<Uuid>, Vec<&Document>> =
+            HashMap::new();
+        for d in self.by_id.values() {
+            children.entry(d.parent).or_default().push(d);
+        }
+        self.children = children
+            .into_iter()
+            .map(|(parent, mut siblings)| {
+                sort_siblings(&mut siblings);
+                (parent, siblings.iter().map(|d| d.id).collect())
+            })
+            .collect();
+        self.shrink_to_fit();
+    }
+
+    /// What's been added, updated and removed since `earlier`, a listing of
+    /// the same account.
+    pub fn diff(&self, earlier: &Documents) -> DocumentsDiff<'_> {
+        let mut diff = DocumentsDiff::default();
+        for d in self.by_id.values() {
+            match earlier.by_id.get(&d.id) {
+                None => diff.added.push(d),
+                Some(old) if d.version > old.version => diff.updated.push(d),
+                Some(_) => {}
+            }
+        }
+        diff.removed = earlier
+            .by_id
+            .keys()
+            .filter(|id| !self.by_id.contains_key(id))
+            .copied()
+            .collect();
+        diff.added.sort_by_key(|d| d.id);
+        diff.updated.sort_by_key(|d| d.id);
+        diff.removed.sort();
+        diff
+    }
+
+    /// The documents, trashed or not, which are at a higher version here
+    /// than in `cached`, an earlier listing of the same account: those
+    /// changed by someone else since. Documents missing from `cached` aren't
+    /// included. Sorted by id.
+    pub fn newer_than(&self, cached: &Documents) -> Vec<&Document> {
+        let mut newer: Vec<&Document> = self
+            .by_id
+            .values()
+            .chain(self.trash.values())
+            .filter(|d| {
+                let old = cached.by_id.get(&d.id).or(cached.trash.get(&d.id));
+                old.is_some_and(|old| d.version > old.version)
+            })
+            .collect();
+        newer.sort_by_key(|d| d.id);
+        newer
+    }
+}
+
+/// What's in a folder, as returned by `Documents::grouped_children`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GroupedChildren<'a> {
+    /// In name order.
+    pub folders: Vec<&'a Document>,
+    /// Everything other than folders, in name order.
+    pub documents: Vec<&'a Document>,
+}
+
+/// How a listing differs from an earlier one of the same account, as found
+/// by `Documents::diff`. The trash is left out of both, so a document
+/// trashed since counts as removed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DocumentsDiff<'a> {
+    /// Those not in the earlier listing, sorted by id.
+    pub added: Vec<&'a Document>,
+    /// Those at a higher version than in the earlier listing, sorted by id.
+    pub updated: Vec<&'a Document>,
+    /// The ids of those only in the earlier listing, sorted.
+    pub removed: Vec<Uuid>,
+}
+
+impl DocumentsDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.updated.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+/// A document in the way of an upload, as found by `Documents::conflict_for`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict<'a> {
+    /// The document with the same name. If there are several, the one with
+    /// the lowest id.
+    pub existing: &'a Document,
+    /// The first name of the form "name (2)", "name (3)" and so on that's
+    /// free in the same folder.
+    pub free_name: String,
+}
+
+/// A destination checked by `Documents::validate_parent`: the root or an
+/// existing folder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidatedParent(Parent);
+
+impl ValidatedParent {
+    pub fn parent(self) -> Parent {
+        self.0
+    }
+
+    /// The folder's id, or `None` for the root.
+    pub fn folder(self) -> Option<Uuid> {
+        match self.0 {
+            Parent::Folder(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+// Sorts siblings by visible name, then by id.
+fn sort_siblings(siblings: &mut [&Document]) {
+    siblings
+        .sort_by(|a, b| (&a.visible_name, a.id).cmp(&(&b.visible_name, b.id)));
+}
+
+// Sorts siblings by name as `order` compares them, then by id.
+fn sort_siblings_by(siblings: &mut [&Document], order: NameOrder<'_>) {
+    siblings.sort_by(|a, b| {
+        order(&a.visible_name, &b.visible_name).then(a.id.cmp(&b.id))
+    });
+}
+
+/// The iterator returned by `Documents::descendants`.
+pub struct Descendants<'a> {
+    documents: &'a Documents,
+    stack: Vec<(usize, &'a Document)>,
+    seen: HashSet<Uuid>,
+    order: Option<NameOrder<'a>>,
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = (usize, &'a Document);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (depth, doc) = self.stack.pop()?;
+            if !self.seen.insert(doc.id) {
+                continue;
+            }
+            let mut children = self.documents.get_children(&Some(doc.id));
+            if let Some(order) = self.order {
+                sort_siblings_by(&mut children, order);
+            }
+            self.stack
+                .extend(children.into_iter().rev().map(|d| (depth + 1, d)));
+            return Some((depth, doc));
+        }
+    }
+}
+
+/// Writes the listing back in the form it was read from, so that it can be
+/// saved and parsed again later.
+impl serde::Serialize for Documents {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::{Error as _, SerializeSeq};
+
+        let mut seq = serializer
+            .serialize_seq(Some(self.by_id.len() + self.trash.len()))?;
+        for doc in self.by_id.values() {
+            seq.serialize_element(doc)?;
+        }
+        for doc in self.trash.values() {
+            let mut value =
+                serde_json::to_value(doc).map_err(S::Error::custom)?;
+            value["Parent"] = TRASH_PARENT.into();
+            seq.serialize_element(&value)?;
+        }
+        seq.end()
+    }
+}
+
+/// An entry of the listing, as `Client::stream_documents` gives them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListingEntry {
+    Document(Document),
+    /// A document in the trash, which is given no parent.
+    Trashed(Document),
+    /// An entry which couldn't be parsed, as its index in the listing and
+    /// what was wrong with it.
+    Unreadable {
+        index: usize,
+        message: String,
+    },
+}
+
+impl ListingEntry {
+    /// Parses the `index`th entry of a listing, keeping its blob URL only
+    /// `with_blob`, when it was asked for.
+    pub(crate) fn parse(
+        index: usize,
+        mut value: serde_json::Value,
+        with_blob: bool,
+    ) -> Self {
+        let id = value.get("ID").and_then(|id| id.as_str());
+        let id = id.unwrap_or("unknown id").to_string();
+        // Trashed documents are kept apart from the tree, as their parent
+        // isn't a folder.
+        let trashed =
+            value.get("Parent").and_then(|p| p.as_str()) == Some(TRASH_PARENT);
+        if trashed {
+            value["Parent"] = "".into();
+        }
+        let mut doc = match serde_json::from_value::<Document>(value) {
+            Ok(doc) => doc,
+            Err(e) => {
+                return ListingEntry::Unreadable {
+                    index,
+                    message: format!("{}: {}", id, e),
+                }
+            }
+        };
+        if !with_blob {
+            doc.blob_url_get = None;
+            doc.blob_url_get_expires = None;
+        }
+        if trashed {
+            ListingEntry::Trashed(doc)
+        } else {
+            ListingEntry::Document(doc)
+        }
+    }
+}
+
+impl Documents {
+    /// Parses a listing as the cloud sends it, keeping blob URLs only
+    /// `with_blob`, as when they were asked for. Deserializing `Documents`
+    /// is the same without them.
+    pub(crate) fn parse_listing(body: &str, with_blob: bool) -> Result<Self> {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(body)?;
+        Ok(entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| ListingEntry::parse(index, value, with_blob))
+            .collect())
+    }
+
+    /// Collects the entries of a listing from `stream`, as
+    /// `Client::stream_documents` gives them, stopping at the first error.
+    pub async fn from_stream<S>(stream: S) -> Result<Documents>
+    where
+        S: Stream<Item = Result<ListingEntry>>,
+    {
+        futures_util::pin_mut!(stream);
+        let mut documents: Documents = Default::default();
+        while let Some(entry) = stream.next().await {
+            documents.add(entry?);
+        }
+        documents.index();
+        Ok(documents)
+    }
+
+    // Adds `entry` without indexing it, which is left to `index`.
+    fn add(&mut self, entry: ListingEntry) {
+        match entry {
+            ListingEntry::Document(doc) => {
+                self.by_id.insert(doc.id, doc);
+            }
+            ListingEntry::Trashed(doc) => {
+                self.trash.insert(doc.id, doc);
+            }
+            ListingEntry::Unreadable { index, message } => {
+                self.parse_warnings.push((index, message))
+            }
+        }
+    }
+}
+
+impl FromIterator<ListingEntry> for Documents {
+    fn from_iter<I: IntoIterator<Item = ListingEntry>>(entries: I) -> Self {
+        let mut documents: Documents = Default::default();
+        for entry in entries {
+            documents.add(entry);
+        }
+        documents.index();
+        documents
     }
 }
 
@@ -135,10 +997,17 @@ impl<'de> serde::de::Deserialize<'de> for Documents {
             {
                 let mut documents: Documents = Default::default();
 
-                while let Some(doc) = visitor.next_element::<Document>()? {
-                    documents.by_id.insert(doc.id, doc);
+                // Entries are parsed one at a time so that a single malformed
+                // one doesn't make the whole listing unusable.
+                let mut index = 0;
+                while let Some(value) =
+                    visitor.next_element::<serde_json::Value>()?
+                {
+                    documents.add(ListingEntry::parse(index, value, false));
+                    index += 1;
                 }
 
+                documents.index();
                 Ok(documents)
             }
         }
@@ -146,3 +1015,649 @@ impl<'de> serde::de::Deserialize<'de> for Documents {
         deserializer.deserialize_any(DocumentsVisitor)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dune_id() -> Uuid {
+        Uuid::parse_str("8d2c7f44-1b39-4e57-a0f2-5b6a9c0d7e83").unwrap()
+    }
+
+    #[test]
+    fn parses_official_listing() {
+        let docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        assert_eq!(docs.len(), 2);
+        let dune = docs.get(&dune_id()).unwrap();
+        assert_eq!(&*dune.visible_name, "Dune");
+        assert_eq!(
+            dune.modified_client.to_rfc3339(),
+            "2020-12-01T09:02:44.402+00:00"
+        );
+    }
+
+    #[test]
+    fn parses_rmfakecloud_listing() {
+        let docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_rmfakecloud.json"
+        ))
+        .unwrap();
+        assert_eq!(docs.len(), 2);
+        let dune = docs.get(&dune_id()).unwrap();
+        assert_eq!(&*dune.visible_name, "Dune");
+        assert_eq!(
+            dune.modified_client.to_rfc3339(),
+            "2020-12-01T09:02:44.402+00:00"
+        );
+        let books = docs.get(&dune.parent.unwrap()).unwrap();
+        assert_eq!(&*books.visible_name, "Books");
+        assert_eq!(
+            books.modified_client.to_rfc3339(),
+            "2020-11-05T18:23:01+00:00"
+        );
+    }
+
+    #[test]
+    fn doc_types() {
+        let docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let dune = docs.get(&dune_id()).unwrap();
+        assert!(dune.is_document() && !dune.is_folder());
+        let books = docs.get(&dune.parent.unwrap()).unwrap();
+        assert_eq!(books.doc_type, DocType::Collection);
+        assert!(books.is_folder());
+
+        // Types from newer firmware survive a round trip.
+        let mut json = serde_json::to_value(dune).unwrap();
+        json["Type"] = "TemplateType".into();
+        let template: Document = serde_json::from_value(json).unwrap();
+        let unknown = DocType::Unknown("TemplateType".to_string());
+        assert_eq!(template.doc_type, unknown);
+        assert!(!template.is_document() && !template.is_folder());
+        let json = serde_json::to_value(&template).unwrap();
+        assert_eq!(json["Type"], "TemplateType");
+        assert_eq!(DocType::Document.to_string(), "DocumentType");
+    }
+
+    #[test]
+    fn path_of() {
+        let docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        assert_eq!(docs.path_of(&dune_id()).unwrap(), "Books/Dune");
+        assert!(docs.path_of(&Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn ancestors() {
+        let mut docs = Documents::default();
+        let folder = |n: u128, parent: Option<u128>| {
+            let mut d: Document = serde_json::from_value(serde_json::json!({
+                "ID": Uuid::from_u128(n),
+                "Version": 1,
+                "VissibleName": format!("F{}", n),
+                "Parent": "",
+                "Type": "CollectionType",
+                "CurrentPage": 0,
+                "Bookmarked": false,
+                "Message": "",
+                "ModifiedClient": "2020-01-01T00:00:00Z",
+                "BlobURLGet": "",
+                "BlobURLGetExpires": "0001-01-01T00:00:00Z",
+            }))
+            .unwrap();
+            d.parent = parent.map(Uuid::from_u128);
+            d
+        };
+        let id = Uuid::from_u128;
+        let names = |docs: &Documents, n| -> Vec<String> {
+            let ancestors = docs.ancestors(&id(n)).unwrap();
+            ancestors
+                .iter()
+                .map(|d| d.visible_name.to_string())
+                .collect()
+        };
+
+        // Twenty deep, each inside the one before.
+        docs.insert(folder(1, None));
+        for n in 2..=20 {
+            docs.insert(folder(n, Some(n - 1)));
+        }
+        assert!(names(&docs, 1).is_empty());
+        let chain = names(&docs, 20);
+        assert_eq!(chain.len(), 19);
+        assert_eq!(chain[0], "F19");
+        assert_eq!(chain[18], "F1");
+        assert!(docs.is_ancestor_of(&id(1), &id(20)).unwrap());
+        assert!(docs.is_ancestor_of(&id(19), &id(20)).unwrap());
+        assert!(!docs.is_ancestor_of(&id(20), &id(1)).unwrap());
+        assert!(!docs.is_ancestor_of(&id(20), &id(20)).unwrap());
+
+        // A trashed folder has no parent, so ends the chain.
+        let mut trashed = docs.remove(&id(10)).unwrap();
+        trashed.parent = None;
+        docs.trash.insert(trashed.id, trashed);
+        assert_eq!(names(&docs, 12), ["F11", "F10"]);
+
+        let missing = Documents::default();
+        assert_eq!(
+            missing.ancestors(&id(1)).unwrap_err(),
+            PathError::Missing { id: id(1) }
+        );
+        docs.insert(folder(30, Some(99)));
+        docs.insert(folder(31, Some(30)));
+        assert_eq!(
+            docs.ancestors(&id(31)).unwrap_err(),
+            PathError::Missing { id: id(99) }
+        );
+
+        docs.insert(folder(1, Some(5)));
+        assert_eq!(
+            docs.ancestors(&id(7)).unwrap_err(),
+            PathError::Cycle { id: id(5) }
+        );
+        assert!(docs.is_ancestor_of(&id(3), &id(7)).is_err());
+        docs.insert(folder(40, Some(40)));
+        assert_eq!(
+            docs.ancestors(&id(40)).unwrap_err(),
+            PathError::Cycle { id: id(40) }
+        );
+        assert_eq!(
+            PathError::Cycle { id: id(40) }.to_string(),
+            format!("{} is inside itself", id(40))
+        );
+    }
+
+    #[test]
+    fn equality_ignores_order() {
+        let listing: serde_json::Value = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let mut reversed = listing.clone();
+        reversed.as_array_mut().unwrap().reverse();
+        let a: Documents = serde_json::from_value(listing).unwrap();
+        let b: Documents = serde_json::from_value(reversed).unwrap();
+        assert_eq!(a, b);
+
+        let mut c = b.clone();
+        assert_eq!(a, c);
+        c.remove(&dune_id());
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn trash_is_kept_apart() {
+        let mut listing: serde_json::Value = serde_json::from_str(
+            include_str!("../tests/fixtures/listing_official.json"),
+        )
+        .unwrap();
+        listing[1]["Parent"] = "trash".into();
+        let docs: Documents = serde_json::from_value(listing).unwrap();
+        assert!(docs.parse_warnings().is_empty());
+        assert!(docs.get(&dune_id()).is_none());
+        assert!(docs.resolve("Books/Dune").unwrap().is_none());
+        let trashed: Vec<&Document> = docs.trashed().collect();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].id, dune_id());
+        assert_eq!(trashed[0].parent, None);
+    }
+
+    #[test]
+    fn skips_malformed_entries() {
+        let docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_poisoned.json"
+        ))
+        .unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs.path_of(&dune_id()).unwrap(), "Books/Dune");
+        let warnings = docs.parse_warnings();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(warnings[0].0, 1);
+        assert!(warnings[0].1.starts_with("5b0e9c1a-"), "{}", warnings[0].1);
+        assert_eq!(warnings[1].0, 3);
+        assert!(warnings[1].1.starts_with("c4e7a2d9-"), "{}", warnings[1].1);
+
+        let clean: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        assert!(clean.parse_warnings().is_empty());
+        assert!(serde_json::from_str::<Documents>("{}").is_err());
+    }
+
+    #[test]
+    fn resolve_spellings() {
+        let docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let spellings = [
+            "Books/Dune",
+            "/Books/Dune",
+            "Books/Dune/",
+            "/Books/Dune/",
+            "Books//Dune",
+            "//Books///Dune//",
+            "./Books/Dune",
+            "Books/./Dune",
+            "Books/Dune/.",
+            "/./Books/./Dune/.",
+            "Books\\Dune",
+            "\\Books\\Dune",
+            "Books\\\\Dune\\",
+            ".\\Books\\Dune",
+            "./Books\\./Dune",
+            "\\Books/\\Dune",
+            "Books/Dune//./",
+            " Books/Dune ",
+            "\tBooks/Dune\n",
+            "  /Books\\Dune/  ",
+        ];
+        for spelling in &spellings {
+            let found = docs.resolve(spelling).unwrap();
+            assert_eq!(found.map(|d| d.id), Some(dune_id()), "{:?}", spelling);
+            assert_eq!(
+                docs.get_by_path(path::Path::new(spelling)).map(|d| d.id),
+                Some(dune_id()),
+                "{:?}",
+                spelling
+            );
+        }
+
+        for root in &["", "/", "\\", "./", " / "] {
+            assert!(split_path(root).unwrap().is_empty());
+            assert!(docs.resolve(root).unwrap().is_none());
+        }
+        assert!(docs.resolve("Dune").unwrap().is_none());
+        assert!(docs.resolve("Books/Dune/Chapter 1").unwrap().is_none());
+        let books = docs.resolve(String::from("Books")).unwrap().unwrap();
+        assert_eq!(&*books.visible_name, "Books");
+        for bad in &["../Books/Dune", "Books/../Books/Dune", "Books\\.."] {
+            match docs.resolve(bad) {
+                Err(Error::InvalidPath { path, .. }) => assert_eq!(path, *bad),
+                other => panic!("{:?} resolved to {:?}", bad, other.is_ok()),
+            }
+        }
+    }
+
+    #[test]
+    fn names_with_slashes() {
+        // "A/B" both as a document's name, and as "B" in the folder "A".
+        let mut listing: serde_json::Value = serde_json::from_str(
+            include_str!("../tests/fixtures/listing_official.json"),
+        )
+        .unwrap();
+        let mut slashed = listing[1].clone();
+        slashed["ID"] = Uuid::new_v4().to_string().into();
+        slashed["VissibleName"] = "Books/Dune".into();
+        slashed["Parent"] = "".into();
+        listing.as_array_mut().unwrap().push(slashed.clone());
+        let docs: Documents = serde_json::from_value(listing).unwrap();
+        let slashed: Uuid = slashed["ID"].as_str().unwrap().parse().unwrap();
+
+        match docs.resolve("Books/Dune") {
+            Err(Error::AmbiguousPath { matches, .. }) => {
+                assert_eq!(matches, vec!["Books/Dune", "Books\\/Dune"])
+            }
+            other => panic!("{:?}", other.map(|d| d.map(|d| d.id))),
+        }
+        let err = docs.resolve("Books/Dune").unwrap_err().to_string();
+        assert!(
+            err.starts_with(
+                "\"Books/Dune\" is ambiguous, it could be any of Books/Dune, \
+                 Books\\/Dune;"
+            ),
+            "{}",
+            err
+        );
+        let escaped = docs.resolve("Books\\/Dune").unwrap().unwrap();
+        assert_eq!(escaped.id, slashed);
+        assert_eq!(docs.resolve("/Books\\/Dune/").unwrap(), Some(escaped));
+        assert_eq!(docs.path_of(&slashed).unwrap(), "Books\\/Dune");
+        assert_eq!(
+            split_path(&docs.path_of(&slashed).unwrap()).unwrap(),
+            vec!["Books/Dune"]
+        );
+
+        // Without the slashed name, the same path is the nested document.
+        let mut docs = docs;
+        docs.remove(&slashed);
+        let nested = docs.resolve("Books/Dune").unwrap().unwrap();
+        assert_eq!(nested.id, dune_id());
+        assert!(docs.resolve("Books\\/Dune").unwrap().is_none());
+        assert_eq!(join_path(&["a/b", "c"]), "a\\/b/c");
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        for listing in &[
+            include_str!("../tests/fixtures/listing_official.json"),
+            include_str!("../tests/fixtures/listing_rmfakecloud.json"),
+        ] {
+            let docs: Documents = serde_json::from_str(listing).unwrap();
+            let json = serde_json::to_string(&docs).unwrap();
+            assert_eq!(serde_json::from_str::<Documents>(&json).unwrap(), docs);
+        }
+
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let mut dune = docs.remove(&dune_id()).unwrap();
+        dune.parent = None;
+        docs.trash.insert(dune.id, dune);
+        let json = serde_json::to_string(&docs).unwrap();
+        let parsed: Documents = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.trashed().next().unwrap().id, dune_id());
+        assert_eq!(parsed, docs);
+    }
+
+    #[test]
+    fn newer_than() {
+        let cached: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        assert!(cached.newer_than(&cached).is_empty());
+
+        let mut current = cached.clone();
+        let mut dune = current.remove(&dune_id()).unwrap();
+        dune.version += 2;
+        current.trash.insert(dune.id, dune);
+        let newer = current.newer_than(&cached);
+        assert_eq!(newer.len(), 1);
+        assert_eq!(newer[0].id, dune_id());
+        // Going back a version isn't newer.
+        assert!(cached.newer_than(&current).is_empty());
+        assert!(cached.newer_than(&Documents::default()).is_empty());
+    }
+
+    #[test]
+    fn descendants() {
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let books = docs.resolve("Books").unwrap().unwrap().id;
+        let walk = |docs: &Documents, parent| -> Vec<(usize, String)> {
+            docs.descendants(parent)
+                .map(|(depth, d)| (depth, d.visible_name.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            walk(&docs, Parent::Root),
+            vec![(0, "Books".to_string()), (1, "Dune".to_string())]
+        );
+        assert_eq!(
+            walk(&docs, Parent::Folder(books)),
+            vec![(0, "Dune".to_string())]
+        );
+        assert!(walk(&docs, Parent::Trash).is_empty());
+
+        // A cycle of parents is walked once, from wherever it's entered.
+        let mut moved = docs.get(&books).cloned().unwrap();
+        moved.parent = Some(dune_id());
+        docs.insert(moved.clone());
+        assert!(walk(&docs, Parent::Root).is_empty());
+        assert_eq!(
+            walk(&docs, Parent::Folder(books)),
+            vec![(0, "Dune".to_string())]
+        );
+        moved.parent = None;
+        docs.insert(moved);
+
+        let mut dune = docs.remove(&dune_id()).unwrap();
+        dune.parent = None;
+        docs.trash.insert(dune.id, dune);
+        assert_eq!(walk(&docs, Parent::Trash), vec![(0, "Dune".to_string())]);
+    }
+
+    #[test]
+    fn descendants_by() {
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let dune = docs.get(&dune_id()).cloned().unwrap();
+        for (n, name) in
+            ["Meeting 10", "Meeting 2", "Meeting 2"].iter().enumerate()
+        {
+            let mut doc = dune.clone();
+            doc.id = Uuid::from_u128(n as u128 + 1);
+            doc.parent = None;
+            doc.visible_name = (*name).into();
+            docs.insert(doc);
+        }
+        let names = |walk: Descendants| -> Vec<(usize, String)> {
+            walk.map(|(depth, d)| (depth, d.visible_name.to_string()))
+                .collect()
+        };
+        let expected = |names: &[(usize, &str)]| -> Vec<(usize, String)> {
+            names
+                .iter()
+                .map(|(depth, n)| (*depth, n.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            names(docs.descendants(Parent::Root)),
+            expected(&[
+                (0, "Books"),
+                (1, "Dune"),
+                (0, "Meeting 10"),
+                (0, "Meeting 2"),
+                (0, "Meeting 2")
+            ])
+        );
+        let natural = docs.descendants_by(Parent::Root, &crate::natural_cmp);
+        let ids: Vec<u128> =
+            natural.map(|(_, d)| d.id.as_u128()).skip(2).collect();
+        // The same name in id order.
+        assert_eq!(ids, [2, 3, 1]);
+        assert_eq!(
+            names(docs.descendants_by(Parent::Root, &crate::natural_cmp)),
+            expected(&[
+                (0, "Books"),
+                (1, "Dune"),
+                (0, "Meeting 2"),
+                (0, "Meeting 2"),
+                (0, "Meeting 10")
+            ])
+        );
+        let backwards = |a: &str, b: &str| b.cmp(a);
+        assert_eq!(
+            names(docs.descendants_by(Parent::Root, &backwards)),
+            expected(&[
+                (0, "Meeting 2"),
+                (0, "Meeting 2"),
+                (0, "Meeting 10"),
+                (0, "Books"),
+                (1, "Dune")
+            ])
+        );
+        let grouped = docs.grouped_children_by(Parent::Root, &backwards);
+        let documents: Vec<u128> =
+            grouped.documents.iter().map(|d| d.id.as_u128()).collect();
+        assert_eq!(documents, [2, 3, 1]);
+        assert_eq!(&*grouped.folders[0].visible_name, "Books");
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let books = docs.resolve("Books").unwrap().unwrap().id;
+        let in_books = Parent::Folder(books);
+        assert!(docs.conflict_for(in_books, "Emma").is_none());
+        // Only documents count, not folders.
+        assert!(docs.conflict_for(Parent::Root, "Books").is_none());
+        assert!(docs.conflict_for(Parent::Root, "Dune").is_none());
+        assert!(docs.conflict_for(Parent::Trash, "Dune").is_none());
+
+        let conflict = docs.conflict_for(in_books, "Dune").unwrap();
+        assert_eq!(conflict.existing.id, dune_id());
+        assert_eq!(conflict.free_name, "Dune (2)");
+
+        // Suffixes already taken are skipped, whatever they name.
+        let mut copy = docs.get(&dune_id()).unwrap().clone();
+        for (n, name) in [(2, "Dune (2)"), (3, "Dune (3)")].iter() {
+            copy.id = Uuid::from_u128(*n);
+            copy.visible_name = (*name).into();
+            copy.doc_type = DocType::Collection;
+            docs.insert(copy.clone());
+        }
+        let conflict = docs.conflict_for(in_books, "Dune").unwrap();
+        assert_eq!(conflict.free_name, "Dune (4)");
+        let conflict = docs.conflict_for(in_books, "Dune (2)");
+        assert!(conflict.is_none());
+    }
+
+    #[test]
+    fn grouping() {
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let books = docs.resolve("Books").unwrap().unwrap().id;
+        let mut add = |n: u128, name: &str, parent, doc_type| {
+            let mut doc = docs.get(&dune_id()).cloned().unwrap();
+            doc.id = Uuid::from_u128(n);
+            doc.visible_name = name.into();
+            doc.parent = parent;
+            doc.doc_type = doc_type;
+            docs.insert(doc);
+        };
+        add(1, "Atlas", None, DocType::Document);
+        add(2, "Archive", None, DocType::Collection);
+        add(3, "Series", Some(books), DocType::Collection);
+        add(4, "Arrakis", Some(books), DocType::Document);
+        let names = |docs: Vec<&Document>| -> Vec<String> {
+            docs.iter().map(|d| d.visible_name.to_string()).collect()
+        };
+        assert_eq!(names(docs.root_folders()), ["Archive", "Books"]);
+        assert_eq!(names(docs.root_documents()), ["Atlas"]);
+        let in_books = docs.grouped_children(Parent::Folder(books));
+        assert_eq!(names(in_books.folders), ["Series"]);
+        assert_eq!(names(in_books.documents), ["Arrakis", "Dune"]);
+        let nowhere = docs.grouped_children(Parent::Folder(Uuid::nil()));
+        assert_eq!(nowhere, GroupedChildren::default());
+        let counts = docs.counts_by_parent();
+        assert_eq!(counts[&None], 3);
+        assert_eq!(counts[&Some(books)], 3);
+        assert_eq!(counts.get(&Some(Uuid::from_u128(3))), None);
+
+        // Renaming and moving keep the index in step.
+        let mut dune = docs.get(&dune_id()).cloned().unwrap();
+        dune.parent = None;
+        dune.visible_name = "Aardvark".into();
+        docs.insert(dune);
+        docs.remove(&Uuid::from_u128(4));
+        assert_eq!(names(docs.root_documents()), ["Aardvark", "Atlas"]);
+        assert!(docs
+            .grouped_children(Parent::Folder(books))
+            .documents
+            .is_empty());
+        let mut reindexed = docs.clone();
+        reindexed.index();
+        assert_eq!(reindexed.children, docs.children);
+    }
+
+    #[test]
+    fn trashed_older_than() {
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let dune = docs.remove(&dune_id()).unwrap();
+        let trashed_at = dune.modified_client;
+        for n in 0..3 {
+            let mut copy = dune.clone();
+            copy.id = Uuid::from_u128(n);
+            copy.parent = None;
+            copy.modified_client =
+                trashed_at + chrono::Duration::milliseconds(n as i64);
+            docs.trash.insert(copy.id, copy);
+        }
+        let ids = |cutoff| -> Vec<Uuid> {
+            let older = docs.trashed_older_than(cutoff);
+            older.iter().map(|d| d.id).collect()
+        };
+        assert!(ids(trashed_at).is_empty());
+        // Trashed at the cutoff itself is not older.
+        let one_ms = chrono::Duration::milliseconds(1);
+        assert_eq!(ids(trashed_at + one_ms), [Uuid::from_u128(0)]);
+        assert_eq!(
+            ids(trashed_at + one_ms * 3),
+            [0, 1, 2]
+                .iter()
+                .map(|n| Uuid::from_u128(*n))
+                .collect::<Vec<_>>()
+        );
+        // Books, outside the trash, isn't included however old.
+        assert_eq!(ids(chrono::Utc::now()).len(), 3);
+    }
+
+    #[test]
+    fn bookmark_candidates() {
+        let mut docs: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        let dune = docs.get(&dune_id()).cloned().unwrap();
+        let modified = dune.modified_client;
+        let mut trashed = dune.clone();
+        trashed.id = Uuid::from_u128(1);
+        docs.trash.insert(trashed.id, trashed);
+        let ids = |since| -> Vec<Uuid> {
+            let found = docs.bookmark_candidates(since);
+            found.iter().map(|d| d.id).collect()
+        };
+        // Folders and the trash are left out.
+        assert_eq!(ids(None), [dune_id()]);
+        assert_eq!(ids(Some(modified)), [dune_id()]);
+        let one_ms = chrono::Duration::milliseconds(1);
+        assert!(ids(Some(modified + one_ms)).is_empty());
+    }
+
+    #[test]
+    fn diff() {
+        let earlier: Documents = serde_json::from_str(include_str!(
+            "../tests/fixtures/listing_official.json"
+        ))
+        .unwrap();
+        assert!(earlier.diff(&earlier).is_empty());
+        let books = earlier.resolve("Books").unwrap().unwrap().id;
+
+        let mut current = earlier.clone();
+        let mut dune = current.get(&dune_id()).cloned().unwrap();
+        dune.version += 1;
+        current.insert(dune.clone());
+        let mut emma = dune.clone();
+        emma.id = Uuid::from_u128(1);
+        emma.visible_name = "Emma".into();
+        current.insert(emma);
+        let books = current.remove(&books).unwrap();
+        current.trash.insert(books.id, books.clone());
+
+        let diff = current.diff(&earlier);
+        let ids = |docs: &[&Document]| -> Vec<Uuid> {
+            docs.iter().map(|d| d.id).collect()
+        };
+        assert_eq!(ids(&diff.added), [Uuid::from_u128(1)]);
+        assert_eq!(ids(&diff.updated), [dune_id()]);
+        assert_eq!(diff.removed, [books.id]);
+        // Going back, a lower version isn't an update.
+        let back = earlier.diff(&current);
+        assert_eq!(ids(&back.added), [books.id]);
+        assert!(back.updated.is_empty());
+        assert_eq!(back.removed, [Uuid::from_u128(1)]);
+    }
+}
@@ -3,12 +3,251 @@ use std::result;
 
 use derive_more::{Display, Error, From};
 
+use crate::capabilities::Capability;
+
 pub type Result<T> = result::Result<T, Error>;
 
+/// Where to follow progress on supporting accounts moved to the newer sync
+/// service.
+pub const MIGRATION_ISSUES_URL: &str =
+    "https://github.com/reacocard/remarkable-rs/issues";
+
 #[derive(Debug, Display, Error, From)]
 pub enum Error {
     EmptyResult,
-    IoError { source: io::Error },
-    HttpError { source: reqwest::Error },
-    JsonError { source: serde_json::Error },
+    /// The cloud answered but reported that it did not do what was asked.
+    #[display(fmt = "Request rejected: {}", message)]
+    #[from(ignore)]
+    Rejected {
+        message: String,
+    },
+    /// A document path that can't be resolved however the tree looks.
+    /// `position` counts characters from the start of `path`, from 0.
+    #[display(
+        fmt = "Invalid path {:?} at character {}: {}",
+        path,
+        "position + 1",
+        reason
+    )]
+    #[from(ignore)]
+    InvalidPath {
+        path: String,
+        position: usize,
+        reason: &'static str,
+    },
+    /// Nothing in the listing is at a path.
+    #[display(fmt = "Couldn't find {:?}: {}", path, reason)]
+    #[from(ignore)]
+    PathNotFound {
+        path: String,
+        reason: String,
+    },
+    /// More than one document fits a path, as names may contain `/`.
+    #[display(
+        fmt = "{:?} is ambiguous, it could be any of {}; write a / that is \
+               part of a name as \\/",
+        path,
+        "matches.join(\", \")"
+    )]
+    #[from(ignore)]
+    AmbiguousPath {
+        path: String,
+        matches: Vec<String>,
+    },
+    /// Somewhere a document can't be created or moved to.
+    #[display(fmt = "Can't put anything in {}: {}", parent, reason)]
+    #[from(ignore)]
+    InvalidDestination {
+        parent: String,
+        reason: &'static str,
+    },
+    /// A name a document can't be given, as checked by `normalize_name`.
+    #[display(fmt = "Invalid name {:?}: {}", name, reason)]
+    #[from(ignore)]
+    InvalidName {
+        name: String,
+        reason: &'static str,
+    },
+    /// A change was asked of a client made read-only with
+    /// `Client::set_read_only`.
+    #[display(fmt = "Refusing to change anything, as the client is read-only")]
+    ReadOnly,
+    /// Parts of a document's archive which don't fit together, as found by
+    /// `DocumentArchiveBuilder::build`.
+    #[display(fmt = "Invalid document archive: {}", reason)]
+    #[from(ignore)]
+    InvalidArchive {
+        reason: String,
+    },
+    /// A page asked for by its index, counting from 0, which the document
+    /// doesn't have.
+    #[display(
+        fmt = "There's no page {} in a document of {} pages",
+        "index + 1",
+        page_count
+    )]
+    #[from(ignore)]
+    NoSuchPage {
+        index: usize,
+        page_count: usize,
+    },
+    /// A document which was to be changed only at a certain version has
+    /// since moved on, or back, to another.
+    #[display(
+        fmt = "{} has changed since: it is at version {}, not {}",
+        id,
+        found,
+        expected
+    )]
+    #[from(ignore)]
+    VersionMismatch {
+        id: uuid::Uuid,
+        expected: u64,
+        found: u64,
+    },
+    /// The account has been moved to reMarkable's newer sync service, and
+    /// the legacy document API this crate speaks no longer serves it.
+    #[display(
+        fmt = "This account has been moved to reMarkable's newer sync \
+               service, which isn't supported yet; see {}",
+        MIGRATION_ISSUES_URL
+    )]
+    AccountMigrated,
+    /// Something the account can't do with this version of the client, as
+    /// `Client::capabilities` tells before anything is tried.
+    #[display(
+        fmt = "This account doesn't support {} with this version of the \
+               client; see {}",
+        capability,
+        MIGRATION_ISSUES_URL
+    )]
+    #[from(ignore)]
+    Unsupported {
+        #[error(not(source))]
+        capability: Capability,
+    },
+    /// The cloud no longer knows a resumable upload session, as they
+    /// expire after a while. Another has to be started and the blob sent
+    /// again from the start.
+    #[display(fmt = "The upload session has expired")]
+    SessionExpired,
+    /// The operation was stopped with the client's `CancellationToken`.
+    #[display(fmt = "Cancelled")]
+    Cancelled,
+    /// The operation was stopped at the client's deadline, as set with
+    /// `Client::with_deadline`. Timeouts of single requests are
+    /// `Error::HttpError`s.
+    #[display(fmt = "Gave up, as the time allowed ran out")]
+    DeadlineExceeded,
+    /// The cloud couldn't be reached at all: its name didn't resolve, or
+    /// nothing answered, as when there's no network connection.
+    #[display(
+        fmt = "Can't reach the reMarkable cloud, check the network \
+               connection ({})",
+        source
+    )]
+    #[from(ignore)]
+    Offline {
+        source: reqwest::Error,
+    },
+    IoError {
+        source: io::Error,
+    },
+    #[from(ignore)]
+    HttpError {
+        source: reqwest::Error,
+    },
+    JsonError {
+        source: serde_json::Error,
+    },
+    ZipError {
+        source: zip::result::ZipError,
+    },
+    /// A file inside a document's archive that couldn't be parsed.
+    FormatError {
+        source: remarkable_data_formats::Error,
+    },
+}
+
+/// Failures to connect are `Error::Offline`, anything else about a request
+/// is `Error::HttpError`.
+impl From<reqwest::Error> for Error {
+    fn from(source: reqwest::Error) -> Self {
+        if source.is_connect() {
+            Error::Offline { source }
+        } else {
+            Error::HttpError { source }
+        }
+    }
+}
+
+impl Error {
+    /// Whether the error is likely to go away if the same request is tried
+    /// again: a dropped connection, a timeout, or the server being busy.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Offline { .. } => true,
+            Error::HttpError { source } => {
+                source.is_timeout()
+                    || source.status().is_some_and(|s| {
+                        s.is_server_error()
+                            || s == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    })
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::FakeCloud;
+
+    async fn get(url: &str) -> Error {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(500))
+            .build()
+            .unwrap();
+        let result = async { client.get(url).send().await?.error_for_status() };
+        Error::from(result.await.unwrap_err())
+    }
+
+    #[tokio::test]
+    async fn offline_errors() {
+        // Nothing listening.
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let refused = get(&format!("http://127.0.0.1:{}/", port)).await;
+        assert!(matches!(refused, Error::Offline { .. }), "{:?}", refused);
+        assert!(refused.is_transient());
+
+        // A name in a top-level domain reserved never to resolve.
+        let unresolved = get("http://remarkable.invalid/").await;
+        assert!(
+            matches!(unresolved, Error::Offline { .. }),
+            "{:?}",
+            unresolved
+        );
+
+        // Connected, but with no answer in time.
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", silent.local_addr().unwrap());
+        let timeout = get(&url).await;
+        assert!(matches!(timeout, Error::HttpError { .. }), "{:?}", timeout);
+        assert!(timeout.is_transient());
+
+        let cloud = FakeCloud::start().await;
+        let status = get(&format!("{}/nowhere", cloud.url())).await;
+        assert!(matches!(status, Error::HttpError { .. }), "{:?}", status);
+        assert!(!status.is_transient());
+
+        let invalid = get("not a url").await;
+        assert!(matches!(invalid, Error::HttpError { .. }), "{:?}", invalid);
+    }
 }
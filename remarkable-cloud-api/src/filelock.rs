@@ -0,0 +1,212 @@
+//! Advisory locks on files, so that processes sharing state files take turns
+//! with them rather than writing over each other.
+//!
+//! A file is locked by way of a `.lock` file beside it rather than itself,
+//! as files are replaced by renaming a new one over them, which would leave
+//! a lock on the old one behind. The locks only keep out those who take
+//! them too, and do nothing on platforms without `flock`. Within a process,
+//! a lock taken twice on the same file waits on itself, so code holding one
+//! mustn't call anything that takes it again.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// A lock on a file, held until dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    file: fs::File,
+}
+
+impl FileLock {
+    /// Waits for a lock on `path` which no one else can have at the same
+    /// time, creating the file if need be.
+    pub fn exclusive(path: &Path) -> io::Result<FileLock> {
+        let file = open(path)?;
+        sys::lock(&file, true, true)?;
+        Ok(FileLock { file })
+    }
+
+    /// Waits for a lock on `path` which others can share, but not with an
+    /// exclusive one.
+    pub fn shared(path: &Path) -> io::Result<FileLock> {
+        let file = open(path)?;
+        sys::lock(&file, false, true)?;
+        Ok(FileLock { file })
+    }
+
+    /// An exclusive lock on `path`, or `None` if someone else has a lock on
+    /// it.
+    pub fn try_exclusive(path: &Path) -> io::Result<Option<FileLock>> {
+        let file = open(path)?;
+        Ok(sys::lock(&file, true, false)?.then_some(FileLock { file }))
+    }
+
+    /// A shared lock on `path`, or `None` if someone else has an exclusive
+    /// one.
+    pub fn try_shared(path: &Path) -> io::Result<Option<FileLock>> {
+        let file = open(path)?;
+        Ok(sys::lock(&file, false, false)?.then_some(FileLock { file }))
+    }
+
+    /// The locked file, open for reading and writing.
+    pub fn file(&self) -> &fs::File {
+        &self.file
+    }
+}
+
+fn open(path: &Path) -> io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+// `path` with `suffix` added to its file name.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// The file locked to lock `path`.
+pub fn lock_path(path: &Path) -> PathBuf {
+    sibling(path, ".lock")
+}
+
+/// Replaces the contents of `path` with `data`, holding its lock. The data
+/// is written aside and renamed into place, so readers never see half of
+/// it.
+pub fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let _lock = FileLock::exclusive(&lock_path(path))?;
+    replace_locked(path, data)
+}
+
+/// Replaces the contents of `path` as `write_atomically` does, for a caller
+/// already holding its lock.
+pub fn replace_locked(path: &Path, data: &[u8]) -> io::Result<()> {
+    let partial = sibling(path, ".partial");
+    {
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(data)?;
+        file.sync_data()?;
+    }
+    fs::rename(partial, path)
+}
+
+/// Reads `path` while holding a shared lock on it, or without one if the
+/// lock can't be had, as in a directory which can't be written to.
+pub fn read_locked(path: &Path) -> io::Result<Vec<u8>> {
+    let _lock = FileLock::shared(&lock_path(path)).ok();
+    fs::read(path)
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+
+    // Locks `file`, returning whether it was locked, which is always so
+    // when `wait`ing.
+    pub fn lock(
+        file: &fs::File,
+        exclusive: bool,
+        wait: bool,
+    ) -> io::Result<bool> {
+        let mut op = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        if !wait {
+            op |= libc::LOCK_NB;
+        }
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+                return Ok(true);
+            }
+            let e = io::Error::last_os_error();
+            match e.kind() {
+                io::ErrorKind::Interrupted => continue,
+                io::ErrorKind::WouldBlock => return Ok(false),
+                _ => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::fs;
+    use std::io;
+
+    pub fn lock(
+        _file: &fs::File,
+        _exclusive: bool,
+        _wait: bool,
+    ) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn exclusion() {
+        let dir = tempfile_dir();
+        let path = dir.join("state.json.lock");
+        let held = FileLock::exclusive(&path).unwrap();
+        assert!(FileLock::try_shared(&path).unwrap().is_none());
+        assert!(FileLock::try_exclusive(&path).unwrap().is_none());
+        drop(held);
+        let shared = FileLock::try_shared(&path).unwrap().unwrap();
+        assert!(FileLock::try_shared(&path).unwrap().is_some());
+        assert!(FileLock::try_exclusive(&path).unwrap().is_none());
+        drop(shared);
+        assert!(FileLock::try_exclusive(&path).unwrap().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_writes() {
+        let dir = Arc::new(tempfile_dir());
+        let path = Arc::new(dir.join("state.json"));
+        let writers: Vec<_> = (0..8u8)
+            .map(|n| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    let data = serde_json::to_vec(&vec![n; 4096]).unwrap();
+                    for _ in 0..20 {
+                        write_atomically(&path, &data).unwrap();
+                        let read = read_locked(&path).unwrap();
+                        let _: Vec<u8> = serde_json::from_slice(&read).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let values: Vec<u8> =
+            serde_json::from_slice(&fs::read(&*path).unwrap()).unwrap();
+        assert!(values.iter().all(|v| *v == values[0]));
+        assert!(!dir.join("state.json.partial").exists());
+        fs::remove_dir_all(&*dir).unwrap();
+    }
+
+    fn tempfile_dir() -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("filelock-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+}
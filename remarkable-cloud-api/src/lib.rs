@@ -1,11 +1,122 @@
+mod archive;
+pub use crate::archive::{
+    content_hash, content_hash_stream, summarize_archives,
+    verify as verify_archive, ArchiveCheck, ArchiveSummary,
+    ArchiveVerification, CheckResult, ContentHasher, DocumentArchiveBuilder,
+    CONTENT_HASH_VERSION,
+};
+
+mod capabilities;
+pub use crate::capabilities::{
+    Capabilities, Capability, Generation, OFFICIAL_MAX_BLOB_SIZE,
+};
+
+mod cancel;
+pub use crate::cancel::CancellationToken;
+
 mod client;
-pub use crate::client::{Client, ClientState};
+pub use crate::client::{
+    BlobStream, Client, ClientState, WireDialect, DEFAULT_ENDPOINT,
+    DEFAULT_RESUMABLE_THRESHOLD,
+};
+
+mod clock;
+pub use crate::clock::{
+    is_future, stamp, ClockSource, FixedClock, SystemClock,
+    DEFAULT_SKEW_TOLERANCE,
+};
+
+mod cloudpath;
+pub use crate::cloudpath::{lookup_with, CloudPath, Resolved};
+
+mod delete;
+pub use crate::delete::{DeleteOutcome, DeleteReport};
+
+mod details;
+pub use crate::details::{DocumentDetails, PinnedSource};
+
+mod diagnostics;
+pub use crate::diagnostics::{ClientDiagnostics, SchemaDrift};
+
+mod content_cache;
+pub use crate::content_cache::{CachedContent, ContentCache};
 
 mod documents;
-pub use crate::documents::{Document, Documents};
+pub use crate::documents::{
+    join_path, split_path, Conflict, Descendants, DocType, Document, Documents,
+    DocumentsDiff, GroupedChildren, ListingEntry, PathError, ValidatedParent,
+};
 
 mod error;
-pub use crate::error::{Error, Result};
+pub use crate::error::{Error, Result, MIGRATION_ISSUES_URL};
+
+mod filelock;
+pub use crate::filelock::{
+    lock_path, read_locked, replace_locked, write_atomically, FileLock,
+};
+
+mod limits;
+pub use crate::limits::{
+    TreeLimits, Violation, DEFAULT_MAX_CHILDREN, DEFAULT_MAX_DEPTH,
+};
+
+mod listing_cache;
+pub use crate::listing_cache::{ListingCache, DEFAULT_LISTING_TTL};
+
+mod listing_stream;
+pub use crate::listing_stream::DocumentStream;
+
+mod meta;
+pub use crate::meta::{MetaStore, Note, META_FOLDER};
+
+mod metrics;
+pub use crate::metrics::{Metrics, Operation, Outcome};
+
+#[cfg(feature = "metrics-prometheus")]
+mod prometheus;
+#[cfg(feature = "metrics-prometheus")]
+pub use crate::prometheus::PrometheusMetrics;
+
+mod names;
+pub use crate::names::{
+    name_key, normalize_name, tidy_name, NamePolicy, DEFAULT_MAX_NAME_LEN,
+};
+
+mod natural;
+pub use crate::natural::{digit_value, natural_cmp, NameOrder};
+
+mod nfc;
+
+mod pages;
+pub use crate::pages::{
+    bookmarked_pages, highlights, ink_stats, list_pages, page_thumbnail,
+    rearrange_pages, render_ink_only, render_ink_pdf, typed_text,
+    BookmarkedPage, PageInfo,
+};
+
+mod ratelimit;
+pub use crate::ratelimit::{RateLimitedStream, RateLimiter};
+
+#[cfg(test)]
+mod roundtrip;
+
+mod requests;
+pub use crate::requests::{
+    DeleteRequest, MetadataChange, MetadataPatch, Parent, RegisterRequest,
+    StatusResponse, UpdateOutcome, UpdateStatusRequest, UploadRequest,
+    UploadResponse,
+};
+
+mod state_store;
+pub use crate::state_store::{
+    FileStateStore, MemoryStateStore, StateFuture, StateStore,
+};
+
+mod upload;
+pub use crate::upload::{BlobSource, ResumableSession, Upload, UploadStage};
+
+#[cfg(feature = "testing")]
+pub mod testing;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,350 @@
+//! How deep and how full the folder tree can get before the tablet copes
+//! badly.
+//!
+//! The cloud stores any tree at all, but the tablet's file browser slows to
+//! a crawl, and sometimes gets lost, in folders nested many deep or holding
+//! thousands of items. `TreeLimits` finds where a listing already goes past
+//! its limits, and where adding to it would.
+
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::documents::{join_path, Documents};
+
+/// The most folders anything should be inside.
+pub const DEFAULT_MAX_DEPTH: usize = 10;
+/// The most items a folder, or the root, should hold.
+pub const DEFAULT_MAX_CHILDREN: usize = 2000;
+
+/// The limits the tree is checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeLimits {
+    /// Anything inside more folders than this is too deep.
+    pub max_depth: usize,
+    /// A folder holding more items than this is too full.
+    pub max_children: usize,
+}
+
+impl Default for TreeLimits {
+    fn default() -> Self {
+        TreeLimits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_children: DEFAULT_MAX_CHILDREN,
+        }
+    }
+}
+
+/// Somewhere the tree goes past its limits. Paths are as `join_path`
+/// writes them, with "/" for the root.
+#[derive(Clone, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum Violation {
+    /// What's at `path` is inside `depth` folders. Only the top of a
+    /// branch which is too deep is given, not everything in it.
+    #[display(
+        fmt = "{} is inside {} folders, more than the {} the tablet handles well",
+        path,
+        depth,
+        limit
+    )]
+    TooDeep {
+        path: String,
+        depth: usize,
+        limit: usize,
+    },
+    /// The folder at `path` holds `children` items.
+    #[display(
+        fmt = "{} holds {} items, more than the {} the tablet handles well",
+        path,
+        children,
+        limit
+    )]
+    TooWide {
+        path: String,
+        children: usize,
+        limit: usize,
+    },
+}
+
+// A folder something is planned to go in: one in the listing, or the root
+// for `None`, or one still to be made, by its names below `parent`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Folder {
+    Listed(Option<Uuid>),
+    Planned(Vec<String>),
+}
+
+impl TreeLimits {
+    /// Where `documents` already goes past the limits, in path order.
+    /// Documents whose folders can't be worked out are left out.
+    pub fn check(&self, documents: &Documents) -> Vec<Violation> {
+        let mut found = vec![];
+        let mut folders = vec![None];
+        for doc in documents.iter() {
+            if doc.is_folder() {
+                folders.push(Some(doc.id));
+            }
+            let depth = match documents.ancestors(&doc.id) {
+                Ok(ancestors) => ancestors.len(),
+                Err(_) => continue,
+            };
+            if depth == self.max_depth + 1 {
+                found.push(Violation::TooDeep {
+                    path: documents.path_of(&doc.id).unwrap_or_default(),
+                    depth,
+                    limit: self.max_depth,
+                });
+            }
+        }
+        for folder in folders {
+            let children = documents.get_children(&folder).len();
+            if children > self.max_children {
+                found.push(Violation::TooWide {
+                    path: listed_path(documents, &folder),
+                    children,
+                    limit: self.max_children,
+                });
+            }
+        }
+        found.sort_by(|a, b| path(a).cmp(path(b)));
+        found
+    }
+
+    /// Where adding `planned` to `parent` in `documents` would go past the
+    /// limits, in path order.
+    ///
+    /// Each of `planned` is the names of the folders it's to go in, below
+    /// `parent`, then its own name. Folders already there are reused, as
+    /// `mkdir -p` does, as are documents of the same name, which are taken
+    /// to be replaced; so only what's new counts, and only limits which
+    /// something new goes past are reported. A `parent` which isn't in
+    /// `documents`, such as one just made, is taken to be empty, and as
+    /// there's no knowing how deep it is, only widths are checked in it.
+    pub fn check_planned(
+        &self,
+        documents: &Documents,
+        parent: Option<Uuid>,
+        planned: &[Vec<String>],
+    ) -> Vec<Violation> {
+        let base = match parent {
+            None => Some(0),
+            Some(id) => documents.ancestors(&id).ok().map(|a| a.len() + 1),
+        };
+        let mut found = vec![];
+        let mut added: HashMap<Folder, Vec<&str>> = HashMap::new();
+        for names in planned {
+            let mut folder = Folder::Listed(parent);
+            let mut deep = false;
+            for (i, name) in names.iter().enumerate() {
+                let existing = match &folder {
+                    Folder::Listed(id) => documents
+                        .get_children(id)
+                        .into_iter()
+                        .find(|d| *d.visible_name == **name),
+                    Folder::Planned(_) => None,
+                };
+                let next = match existing {
+                    Some(d) if d.is_folder() => Folder::Listed(Some(d.id)),
+                    _ => Folder::Planned(names[..=i].to_vec()),
+                };
+                if existing.is_none() {
+                    let siblings = added.entry(folder).or_default();
+                    if !siblings.contains(&name.as_str()) {
+                        siblings.push(name);
+                    }
+                    // Only the top of a new branch which is too deep.
+                    let depth = base.map(|b| b + i);
+                    if !deep && depth.is_some_and(|d| d > self.max_depth) {
+                        found.push(Violation::TooDeep {
+                            path: planned_path(documents, parent, &names[..=i]),
+                            depth: depth.unwrap_or_default(),
+                            limit: self.max_depth,
+                        });
+                        deep = true;
+                    }
+                }
+                folder = next;
+            }
+        }
+        for (folder, names) in &added {
+            let children = match folder {
+                Folder::Listed(id) => documents.get_children(id).len(),
+                Folder::Planned(_) => 0,
+            } + names.len();
+            if children > self.max_children {
+                found.push(Violation::TooWide {
+                    path: match folder {
+                        Folder::Listed(id) => listed_path(documents, id),
+                        Folder::Planned(names) => {
+                            planned_path(documents, parent, names)
+                        }
+                    },
+                    children,
+                    limit: self.max_children,
+                });
+            }
+        }
+        found.sort_by(|a, b| path(a).cmp(path(b)));
+        found.dedup();
+        found
+    }
+}
+
+fn path(violation: &Violation) -> &str {
+    match violation {
+        Violation::TooDeep { path, .. } | Violation::TooWide { path, .. } => {
+            path
+        }
+    }
+}
+
+// The path of the folder `id`, or "/" for the root.
+fn listed_path(documents: &Documents, id: &Option<Uuid>) -> String {
+    match id {
+        None => "/".to_string(),
+        Some(id) => documents.path_of(id).unwrap_or_else(|| id.to_string()),
+    }
+}
+
+// The path of `names` below `parent`.
+fn planned_path(
+    documents: &Documents,
+    parent: Option<Uuid>,
+    names: &[String],
+) -> String {
+    let below = join_path(names);
+    match parent {
+        None => below,
+        Some(_) => format!("{}/{}", listed_path(documents, &parent), below),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::documents::Document;
+
+    fn doc(n: u128, parent: Option<u128>, folder: bool) -> Document {
+        let mut d: Document = serde_json::from_value(serde_json::json!({
+            "ID": Uuid::from_u128(n),
+            "Version": 1,
+            "VissibleName": format!("F{}", n),
+            "Parent": "",
+            "Type": if folder { "CollectionType" } else { "DocumentType" },
+            "CurrentPage": 0,
+            "Bookmarked": false,
+            "Message": "",
+            "ModifiedClient": "2020-01-01T00:00:00Z",
+            "BlobURLGet": "",
+            "BlobURLGetExpires": "0001-01-01T00:00:00Z",
+        }))
+        .unwrap();
+        d.parent = parent.map(Uuid::from_u128);
+        d
+    }
+
+    // Folders F1 to F5, each inside the one before, with documents F100 and
+    // up in F1.
+    fn tree(documents: u128) -> Documents {
+        let mut docs = Documents::default();
+        docs.insert(doc(1, None, true));
+        for n in 2..=5 {
+            docs.insert(doc(n, Some(n - 1), true));
+        }
+        for n in 100..100 + documents {
+            docs.insert(doc(n, Some(1), false));
+        }
+        docs
+    }
+
+    fn names(path: &str) -> Vec<String> {
+        path.split('/').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn existing() {
+        let limits = TreeLimits {
+            max_depth: 3,
+            max_children: 4,
+        };
+        assert_eq!(TreeLimits::default().check(&tree(10)), vec![]);
+        assert_eq!(
+            limits.check(&tree(3)),
+            vec![Violation::TooDeep {
+                path: "F1/F2/F3/F4/F5".to_string(),
+                depth: 4,
+                limit: 3,
+            }]
+        );
+        assert_eq!(
+            limits.check(&tree(5))[0],
+            Violation::TooWide {
+                path: "F1".to_string(),
+                children: 6,
+                limit: 4,
+            }
+        );
+        assert_eq!(
+            limits.check(&tree(5))[0].to_string(),
+            "F1 holds 6 items, more than the 4 the tablet handles well"
+        );
+    }
+
+    #[test]
+    fn planned() {
+        let limits = TreeLimits {
+            max_depth: 3,
+            max_children: 5,
+        };
+        let docs = tree(2);
+        let id = Uuid::from_u128;
+
+        // Into F3, which is inside two folders: a new folder in it, and a
+        // document in that, is as deep as can be.
+        let planned = [names("A"), names("A/B"), names("A/B/C")];
+        assert_eq!(
+            limits.check_planned(&docs, Some(id(3)), &planned),
+            vec![Violation::TooDeep {
+                path: "F1/F2/F3/A/B".to_string(),
+                depth: 4,
+                limit: 3,
+            }]
+        );
+        // Going through folders already there counts them too.
+        let planned = [names("F1/F2/F3/F4/new")];
+        assert_eq!(
+            limits.check_planned(&docs, None, &planned),
+            vec![Violation::TooDeep {
+                path: "F1/F2/F3/F4/new".to_string(),
+                depth: 4,
+                limit: 3,
+            }]
+        );
+
+        // F1 holds F2 and two documents; two more fill it, and a third
+        // is one too many, but replacing one already there isn't.
+        let planned = [names("x"), names("y"), names("F100")];
+        assert_eq!(limits.check_planned(&docs, Some(id(1)), &planned), vec![]);
+        let planned = [names("x"), names("y"), names("z")];
+        assert_eq!(
+            limits.check_planned(&docs, Some(id(1)), &planned),
+            vec![Violation::TooWide {
+                path: "F1".to_string(),
+                children: 6,
+                limit: 5,
+            }]
+        );
+        // A new folder is as full as what's planned for it, even where it's
+        // in one that isn't listed.
+        let planned: Vec<Vec<String>> =
+            (0..6).map(|n| names(&format!("new/{}", n))).collect();
+        assert_eq!(
+            limits.check_planned(&docs, Some(id(99)), &planned),
+            vec![Violation::TooWide {
+                path: format!("{}/new", id(99)),
+                children: 6,
+                limit: 5,
+            }]
+        );
+    }
+}
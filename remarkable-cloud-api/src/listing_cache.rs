@@ -0,0 +1,207 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::documents::Documents;
+use crate::error::Result;
+
+/// How long a listing from a server which sends no `ETag` is reused for
+/// without asking again, unless set otherwise with `ListingCache::with_ttl`.
+pub const DEFAULT_LISTING_TTL: Duration = Duration::from_secs(5);
+
+// What is kept of the last full listing, and written out when file-backed.
+#[derive(Serialize, Deserialize)]
+struct Saved {
+    etag: Option<String>,
+    body_sha256: String,
+    fetched_at: DateTime<Utc>,
+    body: String,
+}
+
+struct Entry {
+    saved: Saved,
+    documents: Documents,
+}
+
+struct Inner {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    entry: Option<Entry>,
+    // Whether the file, if any, has been read yet.
+    loaded: bool,
+}
+
+/// The last full listing a `Client` fetched, with its `ETag`, so that asking
+/// for it again costs a `304 Not Modified` rather than the whole listing.
+///
+/// Servers which don't send an `ETag` get no conditional requests; their
+/// listing is instead reused as it was for a short while, see
+/// `DEFAULT_LISTING_TTL`. A client drops its cache whenever it changes
+/// anything in the cloud. Cloning a `ListingCache` produces a handle to the
+/// same cache.
+#[derive(Clone)]
+pub struct ListingCache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// A listing found in a `ListingCache`.
+pub(crate) struct Cached {
+    pub etag: Option<String>,
+    /// Whether it may be used without asking the server.
+    pub fresh: bool,
+    pub documents: Documents,
+}
+
+fn sha256_hex(body: &str) -> String {
+    Sha256::digest(body.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl ListingCache {
+    /// A cache held in memory, lasting as long as the handles to it.
+    pub fn new() -> Self {
+        ListingCache {
+            inner: Arc::new(Mutex::new(Inner {
+                path: None,
+                ttl: DEFAULT_LISTING_TTL,
+                entry: None,
+                loaded: true,
+            })),
+        }
+    }
+
+    /// A cache also kept in the file at `path`, so later processes can
+    /// revalidate the listing rather than fetching it whole. The file is read
+    /// when first needed; one which can't be read is ignored.
+    pub fn at_path(path: PathBuf) -> Self {
+        let cache = ListingCache::new();
+        {
+            let mut inner = cache.inner.lock().unwrap();
+            inner.path = Some(path);
+            inner.loaded = false;
+        }
+        cache
+    }
+
+    /// Sets how long a listing without an `ETag` is reused for.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        self.inner.lock().unwrap().ttl = ttl;
+        self
+    }
+
+    /// Forgets the cached listing, so the next one is fetched whole.
+    pub fn invalidate(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entry = None;
+        inner.loaded = true;
+        if let Some(path) = &inner.path {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("Couldn't remove {}: {}", path.display(), e)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<Cached> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.loaded {
+            inner.loaded = true;
+            inner.entry = inner.path.as_ref().and_then(|p| load(p));
+        }
+        let ttl = inner.ttl;
+        let entry = inner.entry.as_ref()?;
+        let age = (Utc::now() - entry.saved.fetched_at).to_std().ok();
+        Some(Cached {
+            etag: entry.saved.etag.clone(),
+            fresh: entry.saved.etag.is_none() && age.is_some_and(|a| a < ttl),
+            documents: entry.documents.clone(),
+        })
+    }
+
+    /// Notes that the server confirmed the cached listing is current.
+    pub(crate) fn touch(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(entry) = &mut inner.entry {
+            entry.saved.fetched_at = Utc::now();
+        }
+        save(&inner);
+    }
+
+    /// Keeps a freshly fetched listing, returning it parsed. A body the same
+    /// as the cached one isn't parsed again.
+    pub(crate) fn store(
+        &self,
+        etag: Option<String>,
+        body: String,
+    ) -> Result<Documents> {
+        let mut inner = self.inner.lock().unwrap();
+        let body_sha256 = sha256_hex(&body);
+        let documents = match inner.entry.take() {
+            Some(e) if e.saved.body_sha256 == body_sha256 => e.documents,
+            _ => parse(&body)?,
+        };
+        inner.entry = Some(Entry {
+            saved: Saved {
+                etag,
+                body_sha256,
+                fetched_at: Utc::now(),
+                body,
+            },
+            documents: documents.clone(),
+        });
+        save(&inner);
+        Ok(documents)
+    }
+}
+
+impl Default for ListingCache {
+    fn default() -> Self {
+        ListingCache::new()
+    }
+}
+
+/// Parses the body of a full listing, logging the entries left out.
+pub(crate) fn parse(body: &str) -> Result<Documents> {
+    let docs = serde_json::from_str::<Documents>(body)?;
+    for (index, warning) in docs.parse_warnings() {
+        log::warn!("Skipped listing entry {}: {}", index, warning);
+    }
+    Ok(docs)
+}
+
+fn load(path: &std::path::Path) -> Option<Entry> {
+    let saved: Saved =
+        serde_json::from_slice(&crate::read_locked(path).ok()?).ok()?;
+    if sha256_hex(&saved.body) != saved.body_sha256 {
+        return None;
+    }
+    let documents = serde_json::from_str(&saved.body).ok()?;
+    Some(Entry { saved, documents })
+}
+
+// Writes the entry aside and renames it into place, so readers never see
+// half of it, holding its lock so that processes sharing it take turns.
+fn save(inner: &Inner) {
+    let (path, entry) = match (&inner.path, &inner.entry) {
+        (Some(path), Some(entry)) => (path, entry),
+        _ => return,
+    };
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        crate::write_atomically(path, &serde_json::to_vec(&entry.saved)?)
+    };
+    if let Err(e) = write() {
+        log::warn!("Couldn't save the listing to {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,250 @@
+//! Reading a listing an entry at a time as it arrives, for
+//! `Client::stream_documents`, rather than holding the whole of it.
+//!
+//! The body is split into the elements of its JSON array by scanning for
+//! the commas between them, keeping only the element being read, and each
+//! is parsed as it's completed.
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use serde::de::Error as _;
+
+use crate::documents::ListingEntry;
+use crate::error::{Error, Result};
+
+/// The entries of a listing, as `Client::stream_documents` returns them.
+pub type DocumentStream =
+    Pin<Box<dyn Stream<Item = Result<ListingEntry>> + Send>>;
+
+/// Splits a JSON array given in pieces into its elements.
+#[derive(Debug, Default)]
+pub(crate) struct ArraySplitter {
+    /// The element being read.
+    element: Vec<u8>,
+    /// How deep in objects and arrays the element being read is.
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    started: bool,
+    ended: bool,
+}
+
+impl ArraySplitter {
+    /// Takes the next piece of the array, returning the elements it
+    /// completes.
+    pub fn feed(&mut self, bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut elements = vec![];
+        for &b in bytes {
+            if self.ended {
+                if !b.is_ascii_whitespace() {
+                    return Err(malformed("trailing characters"));
+                }
+                continue;
+            }
+            if !self.started {
+                match b {
+                    b'[' => self.started = true,
+                    _ if b.is_ascii_whitespace() => (),
+                    _ => return Err(malformed("not a JSON array")),
+                }
+                continue;
+            }
+            if self.in_string {
+                self.element.push(b);
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => (),
+                }
+                continue;
+            }
+            match b {
+                b',' | b']' if self.depth == 0 => {
+                    if !self.element.iter().all(u8::is_ascii_whitespace) {
+                        elements.push(std::mem::take(&mut self.element));
+                    }
+                    self.element.clear();
+                    self.ended = b == b']';
+                    continue;
+                }
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth = self
+                        .depth
+                        .checked_sub(1)
+                        .ok_or_else(|| malformed("unbalanced brackets"))?
+                }
+                _ => (),
+            }
+            self.element.push(b);
+        }
+        Ok(elements)
+    }
+
+    /// Checks the array was all there once there's no more of it.
+    pub fn finish(&self) -> Result<()> {
+        if self.ended {
+            Ok(())
+        } else {
+            Err(malformed("the listing ended early"))
+        }
+    }
+}
+
+fn malformed(message: &str) -> Error {
+    serde_json::Error::custom(format!("Malformed listing: {}", message)).into()
+}
+
+// What `entries` keeps between the pieces of the body.
+struct State<S> {
+    body: S,
+    splitter: ArraySplitter,
+    parsed: VecDeque<ListingEntry>,
+    next_index: usize,
+    done: bool,
+}
+
+/// The entries of the listing `body`, each parsed once it has arrived.
+/// `first` is given the text of the first entry, as soon as there is one.
+pub(crate) fn entries<S, F>(body: S, first: F) -> DocumentStream
+where
+    S: Stream<Item = Result<Bytes>> + Send + Unpin + 'static,
+    F: FnOnce(&str) + Send + 'static,
+{
+    let state = State {
+        body,
+        splitter: ArraySplitter::default(),
+        parsed: VecDeque::new(),
+        next_index: 0,
+        done: false,
+    };
+    let mut first = Some(first);
+    Box::pin(futures_util::stream::unfold(state, move |mut state| {
+        let first = if state.next_index == 0 {
+            first.take()
+        } else {
+            None
+        };
+        async move {
+            let mut first = first;
+            loop {
+                if let Some(entry) = state.parsed.pop_front() {
+                    return Some((Ok(entry), state));
+                }
+                if state.done {
+                    return None;
+                }
+                let elements = match state.body.next().await {
+                    Some(Ok(bytes)) => state.splitter.feed(&bytes),
+                    Some(Err(e)) => Err(e),
+                    None => {
+                        state.done = true;
+                        state.splitter.finish().map(|_| vec![])
+                    }
+                };
+                let elements = match elements {
+                    Ok(elements) => elements,
+                    Err(e) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    }
+                };
+                for element in elements {
+                    if let Some(first) = first.take() {
+                        first(&String::from_utf8_lossy(&element));
+                    }
+                    let index = state.next_index;
+                    state.next_index += 1;
+                    state.parsed.push_back(
+                        match serde_json::from_slice(&element) {
+                            Ok(value) => {
+                                ListingEntry::parse(index, value, false)
+                            }
+                            Err(e) => ListingEntry::Unreadable {
+                                index,
+                                message: e.to_string(),
+                            },
+                        },
+                    );
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `listing` split into elements, fed `piece` bytes at a time.
+    fn split(listing: &str, piece: usize) -> Result<Vec<String>> {
+        let mut splitter = ArraySplitter::default();
+        let mut elements = vec![];
+        for bytes in listing.as_bytes().chunks(piece) {
+            elements.extend(splitter.feed(bytes)?);
+        }
+        splitter.finish()?;
+        Ok(elements
+            .into_iter()
+            .map(|e| String::from_utf8(e).unwrap().trim().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn splits() {
+        let listing = r#" [{"ID": "a", "Name": "x, ]\"}"},
+            {"ID": "b", "Tags": [1, {"c": []}]}, 3 ] "#;
+        let whole = split(listing, listing.len()).unwrap();
+        assert_eq!(
+            whole,
+            vec![
+                r#"{"ID": "a", "Name": "x, ]\"}"}"#,
+                r#"{"ID": "b", "Tags": [1, {"c": []}]}"#,
+                "3",
+            ]
+        );
+        for piece in 1..8 {
+            assert_eq!(split(listing, piece).unwrap(), whole);
+        }
+        assert!(split("[]", 1).unwrap().is_empty());
+        assert!(split("", 1).is_err());
+        assert!(split("[{}", 1).is_err());
+        assert!(split("{}", 1).is_err());
+        assert!(split("[] x", 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn entries_as_they_come() {
+        let listing = r#"[{"ID": "not a uuid"}, {"ID": "#;
+        let pieces = vec![Ok(Bytes::from(listing)), Ok(Bytes::from("2}]"))];
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut stream =
+            entries(futures_util::stream::iter(pieces), move |e| {
+                tx.send(e.to_string()).unwrap()
+            });
+        match stream.next().await.unwrap().unwrap() {
+            ListingEntry::Unreadable { index, message } => {
+                assert_eq!(index, 0);
+                assert!(message.starts_with("not a uuid: "), "{}", message);
+            }
+            e => panic!("{:?}", e),
+        }
+        assert_eq!(rx.recv().unwrap(), r#"{"ID": "not a uuid"}"#);
+        assert!(matches!(
+            stream.next().await.unwrap().unwrap(),
+            ListingEntry::Unreadable { index: 1, .. }
+        ));
+        assert!(stream.next().await.is_none());
+
+        let pieces = vec![Ok(Bytes::from("[{}, {"))];
+        let mut stream = entries(futures_util::stream::iter(pieces), |_| ());
+        assert!(stream.next().await.unwrap().is_ok());
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+    }
+}
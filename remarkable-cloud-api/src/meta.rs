@@ -0,0 +1,376 @@
+//! Notes attached to documents, kept in the cloud as ordinary documents.
+//!
+//! The cloud has nowhere to put a description of a document, so notes live
+//! in a folder named [`META_FOLDER`] at the root. Each annotated document
+//! has one document there, named with the annotated document's id, whose
+//! archive holds an empty notebook and, beside it, `{id}.note.json`:
+//!
+//! ```json
+//! {"target": "<annotated id>", "text": "...", "updated": "<RFC 3339>"}
+//! ```
+//!
+//! Clients which don't know the convention see a folder of empty notebooks,
+//! which they can move, rename or delete like any other. So the convention
+//! is read loosely: only a document in the folder whose name is an id is a
+//! note, the last modified wins if two are for the same document, and a
+//! note whose archive was replaced by something without a `.note.json`
+//! reads as no note at all.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use remarkable_data_formats::content::Content;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::archive::DocumentArchiveBuilder;
+use crate::client::Client;
+use crate::details::read_entry;
+use crate::documents::{DocType, Document, Documents};
+use crate::error::{Error, Result};
+
+/// The name of the folder at the root which holds the notes.
+pub const META_FOLDER: &str = ".remarkable-cloud-meta";
+
+/// The note on one document.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Note {
+    /// The id of the document the note is about.
+    pub target: Uuid,
+    pub text: String,
+    pub updated: DateTime<Utc>,
+}
+
+/// Reads and writes the notes of one account.
+///
+/// Notes are found from a listing passed to each call, and notes read are
+/// cached by the id and version of the document holding them, so reading
+/// them all again only downloads those which changed. What this store
+/// writes is remembered too, so a listing from before a write can still be
+/// used after it.
+pub struct MetaStore<'a> {
+    client: &'a Client,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    // The folder, once this store has made it.
+    folder: Option<Uuid>,
+    // The notes this store has written or removed, by target, as the id and
+    // version of the document holding them.
+    written: HashMap<Uuid, Option<(Uuid, u64)>>,
+    read: HashMap<(Uuid, u64), Option<Note>>,
+}
+
+impl<'a> MetaStore<'a> {
+    pub fn new(client: &'a Client) -> Self {
+        MetaStore {
+            client,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// The folder of notes in `documents`, if there is one.
+    pub fn folder(documents: &Documents) -> Option<&Document> {
+        documents
+            .get_children(&None)
+            .into_iter()
+            .filter(|d| d.is_folder() && &*d.visible_name == META_FOLDER)
+            .max_by_key(|d| d.modified_client)
+    }
+
+    /// The documents holding notes in `documents`, by the id of the document
+    /// each is about.
+    pub fn annotated(documents: &Documents) -> HashMap<Uuid, &Document> {
+        let mut notes: HashMap<Uuid, &Document> = HashMap::new();
+        for (target, doc) in Self::notes(documents) {
+            let newer = notes
+                .get(&target)
+                .is_none_or(|d| d.modified_client < doc.modified_client);
+            if newer {
+                notes.insert(target, doc);
+            }
+        }
+        notes
+    }
+
+    // Every document holding a note, with the id it's about.
+    fn notes(documents: &Documents) -> Vec<(Uuid, &Document)> {
+        let folder = match Self::folder(documents) {
+            Some(folder) => folder.id,
+            None => return vec![],
+        };
+        documents
+            .get_children(&Some(folder))
+            .into_iter()
+            .filter(|d| d.is_document())
+            .filter_map(|d| Some((d.visible_name.parse().ok()?, d)))
+            .collect()
+    }
+
+    // The id and version of the document holding the note on `target`.
+    fn holder(
+        &self,
+        documents: &Documents,
+        target: &Uuid,
+    ) -> Option<(Uuid, u64)> {
+        let state = self.state.lock().unwrap();
+        match state.written.get(target) {
+            Some(written) => *written,
+            None => Self::annotated(documents)
+                .get(target)
+                .map(|d| (d.id, d.version)),
+        }
+    }
+
+    /// The note on the document `target`, if it has one.
+    pub async fn get(
+        &self,
+        documents: &Documents,
+        target: &Uuid,
+    ) -> Result<Option<Note>> {
+        match self.holder(documents, target) {
+            Some((id, version)) => self.read(id, version).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Every note in `documents`, in no particular order.
+    pub async fn all(&self, documents: &Documents) -> Result<Vec<Note>> {
+        let mut targets: Vec<Uuid> =
+            Self::annotated(documents).keys().copied().collect();
+        {
+            let state = self.state.lock().unwrap();
+            for target in state.written.keys() {
+                if !targets.contains(target) {
+                    targets.push(*target);
+                }
+            }
+        }
+        let mut notes = vec![];
+        for target in targets {
+            notes.extend(self.get(documents, &target).await?);
+        }
+        Ok(notes)
+    }
+
+    /// Sets the note on the document `target` to `text`, making the folder
+    /// of notes first if there isn't one. An empty `text` removes the note.
+    pub async fn set(
+        &self,
+        documents: &Documents,
+        target: Uuid,
+        text: &str,
+    ) -> Result<Option<Note>> {
+        if text.is_empty() {
+            self.remove(documents, &target).await?;
+            return Ok(None);
+        }
+        let folder = self.make_folder(documents).await?;
+        let note = Note {
+            target,
+            text: text.to_string(),
+            updated: self.client.now(),
+        };
+        let (id, version) = match self.holder(documents, &target) {
+            Some((id, version)) => (id, version + 1),
+            None => (Uuid::new_v4(), 1),
+        };
+        let content = Content {
+            file_type: "notebook".to_string(),
+            ..Content::default()
+        };
+        let zip = DocumentArchiveBuilder::new()
+            .content(content)
+            .extra("note.json", serde_json::to_vec_pretty(&note)?)
+            .build(id)?;
+        self.client
+            .upload_zip(
+                id,
+                version,
+                Some(folder),
+                &target.to_string(),
+                DocType::Document,
+                zip,
+            )
+            .await?;
+        let mut state = self.state.lock().unwrap();
+        state.written.insert(target, Some((id, version)));
+        state.read.insert((id, version), Some(note.clone()));
+        Ok(Some(note))
+    }
+
+    /// Removes the note on the document `target`, returning whether it had
+    /// one.
+    pub async fn remove(
+        &self,
+        documents: &Documents,
+        target: &Uuid,
+    ) -> Result<bool> {
+        let id = match self.holder(documents, target) {
+            Some((id, _)) => id,
+            None => return Ok(false),
+        };
+        self.delete(id).await?;
+        let mut state = self.state.lock().unwrap();
+        state.written.insert(*target, None);
+        Ok(true)
+    }
+
+    /// Removes the notes on documents which are no longer in `documents`,
+    /// not even in the trash, and those left over from two clients writing
+    /// a note at once. Returns the documents which held them.
+    pub async fn prune(&self, documents: &Documents) -> Result<Vec<Document>> {
+        let current = Self::annotated(documents);
+        let exists = |id: &Uuid| {
+            documents.get(id).is_some()
+                || documents.trashed().any(|d| d.id == *id)
+        };
+        let mut pruned = vec![];
+        for (target, doc) in Self::notes(documents) {
+            if exists(&target) && current[&target].id == doc.id {
+                continue;
+            }
+            self.delete(doc.id).await?;
+            if current[&target].id == doc.id {
+                let mut state = self.state.lock().unwrap();
+                state.written.insert(target, None);
+            }
+            pruned.push(doc.clone());
+        }
+        Ok(pruned)
+    }
+
+    // The id of the folder of notes, making it if there isn't one.
+    async fn make_folder(&self, documents: &Documents) -> Result<Uuid> {
+        if let Some(folder) = Self::folder(documents) {
+            return Ok(folder.id);
+        }
+        if let Some(folder) = self.state.lock().unwrap().folder {
+            return Ok(folder);
+        }
+        let id = Uuid::new_v4();
+        let zip = DocumentArchiveBuilder::new()
+            .content(Content::default())
+            .build(id)?;
+        self.client
+            .upload_zip(id, 1, None, META_FOLDER, DocType::Collection, zip)
+            .await?;
+        self.state.lock().unwrap().folder = Some(id);
+        Ok(id)
+    }
+
+    // Deletes the document `id`, as it is now rather than as listed, in
+    // case another client has written the note since.
+    async fn delete(&self, id: Uuid) -> Result<()> {
+        let doc = self.client.get_document_by_id(&id).await?;
+        self.client.delete_document(&doc).await
+    }
+
+    // The note held by version `version` of the document `id`.
+    async fn read(&self, id: Uuid, version: u64) -> Result<Option<Note>> {
+        if let Some(note) = self.state.lock().unwrap().read.get(&(id, version))
+        {
+            return Ok(note.clone());
+        }
+        let doc = self.client.get_document_by_id(&id).await?;
+        let zip = self.client.download_blob(&doc).await?;
+        let note = parse_note(id, &zip)?;
+        self.state
+            .lock()
+            .unwrap()
+            .read
+            .insert((doc.id, doc.version), note.clone());
+        Ok(note)
+    }
+}
+
+// The note in the archive of the document `id`.
+fn parse_note(id: Uuid, zip: &[u8]) -> Result<Option<Note>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    match read_entry(&mut archive, &format!("{}.note.json", id))? {
+        Some(data) => match serde_json::from_slice(&data) {
+            Ok(note) => Ok(Some(note)),
+            Err(e) => Err(Error::InvalidArchive {
+                reason: format!("the note isn't readable: {}", e),
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeCloud;
+
+    #[tokio::test(threaded_scheduler)]
+    async fn notes() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let gone = cloud.add_document("Gone", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let documents = client.get_documents().await.unwrap();
+        let store = MetaStore::new(&client);
+        assert_eq!(store.get(&documents, &dune).await.unwrap(), None);
+
+        store.set(&documents, dune, "signed copy").await.unwrap();
+        store.set(&documents, dune, "sent back").await.unwrap();
+        store.set(&documents, gone, "lost").await.unwrap();
+        let note = store.get(&documents, &dune).await.unwrap().unwrap();
+        assert_eq!(note.text, "sent back");
+
+        // A fresh store reads them back from the cloud.
+        let documents = client.get_documents().await.unwrap();
+        let folder = MetaStore::folder(&documents).unwrap();
+        assert_eq!(documents.get_children(&Some(folder.id)).len(), 2);
+        let store = MetaStore::new(&client);
+        let mut texts: Vec<String> = store
+            .all(&documents)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|n| n.text)
+            .collect();
+        texts.sort();
+        assert_eq!(texts, ["lost", "sent back"]);
+
+        let holder = MetaStore::annotated(&documents)[&gone].id;
+        cloud.modify(&gone, |d| d.trashed = true);
+        let documents = client.get_documents().await.unwrap();
+        assert!(store.prune(&documents).await.unwrap().is_empty());
+        let doc = documents.trashed().find(|d| d.id == gone).unwrap();
+        client.delete_document(doc).await.unwrap();
+        let documents = client.get_documents().await.unwrap();
+        let pruned = store.prune(&documents).await.unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].id, holder);
+        assert!(cloud.document(&holder).is_none());
+
+        assert!(store.remove(&documents, &dune).await.unwrap());
+        assert_eq!(store.get(&documents, &dune).await.unwrap(), None);
+    }
+
+    #[test]
+    fn foreign_archives() {
+        let id = Uuid::new_v4();
+        let notebook = DocumentArchiveBuilder::new()
+            .content(Content::default())
+            .build(id)
+            .unwrap();
+        assert_eq!(parse_note(id, &notebook).unwrap(), None);
+        let garbled = DocumentArchiveBuilder::new()
+            .content(Content::default())
+            .extra("note.json", b"{".to_vec())
+            .build(id)
+            .unwrap();
+        assert!(matches!(
+            parse_note(id, &garbled),
+            Err(Error::InvalidArchive { .. })
+        ));
+    }
+}
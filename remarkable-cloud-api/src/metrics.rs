@@ -0,0 +1,277 @@
+//! Hooks for counting what a client asks of the cloud, for services that
+//! embed one and monitor it, see `Client::set_metrics`.
+//!
+//! Every request is one observation, made where it's sent, so each retry of
+//! an upload stage or batch of deletions is counted on its own. Most are
+//! observed once the response's headers are in, with the bytes of the
+//! request's body and of the response's as it declares them. Downloads are
+//! observed once their blob has been streamed, or given up on, with the
+//! bytes actually received and the time it took.
+
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::ready;
+use futures_util::stream::Stream;
+
+use crate::error::Result;
+
+/// What a request was for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Operation {
+    /// Getting a user token.
+    Token,
+    /// Fetching the listing, or one document's entry in it.
+    Listing,
+    /// Streaming a document's blob.
+    Download,
+    /// Reserving an upload URL.
+    UploadRequest,
+    /// Sending a blob to an upload URL.
+    UploadBlob,
+    /// Setting documents' metadata.
+    UpdateStatus,
+    /// Deleting documents.
+    Delete,
+}
+
+impl Operation {
+    /// The operation's name, as used in metric labels.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Operation::Token => "token",
+            Operation::Listing => "listing",
+            Operation::Download => "download",
+            Operation::UploadRequest => "upload_request",
+            Operation::UploadBlob => "upload_blob",
+            Operation::UpdateStatus => "update_status",
+            Operation::Delete => "delete",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How a request ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Outcome {
+    /// The cloud answered with this HTTP status, whether success or not.
+    Status(u16),
+    /// There was no answer, or it broke off: the connection failed, or the
+    /// client was cancelled or ran out of time.
+    Failed,
+}
+
+impl Outcome {
+    pub fn is_success(self) -> bool {
+        matches!(self, Outcome::Status(s) if (200..400).contains(&s))
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Status(status) => write!(f, "{}", status),
+            Outcome::Failed => f.write_str("failed"),
+        }
+    }
+}
+
+/// Told of every request a client makes. Called from whichever task made
+/// the request, so it should be quick.
+pub trait Metrics: Send + Sync {
+    fn record_request(
+        &self,
+        op: Operation,
+        status: Outcome,
+        duration: Duration,
+        bytes: u64,
+    );
+}
+
+/// A blob stream that records the download as one observation when it
+/// ends, fails or is dropped, whichever is first.
+pub(crate) struct MeteredStream<S> {
+    inner: S,
+    metrics: Arc<dyn Metrics>,
+    status: Outcome,
+    start: Instant,
+    bytes: u64,
+    recorded: bool,
+}
+
+impl<S> MeteredStream<S> {
+    pub fn new(
+        inner: S,
+        metrics: Arc<dyn Metrics>,
+        status: Outcome,
+        start: Instant,
+    ) -> Self {
+        MeteredStream {
+            inner,
+            metrics,
+            status,
+            start,
+            bytes: 0,
+            recorded: false,
+        }
+    }
+
+    fn record(&mut self, status: Outcome) {
+        if !self.recorded {
+            self.recorded = true;
+            self.metrics.record_request(
+                Operation::Download,
+                status,
+                self.start.elapsed(),
+                self.bytes,
+            );
+        }
+    }
+}
+
+impl<S> Stream for MeteredStream<S>
+where
+    S: Stream<Item = Result<bytes::Bytes>> + Unpin,
+{
+    type Item = Result<bytes::Bytes>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let item = ready!(Pin::new(&mut self.inner).poll_next(cx));
+        match &item {
+            Some(Ok(chunk)) => self.bytes += chunk.len() as u64,
+            Some(Err(_)) => self.record(Outcome::Failed),
+            None => {
+                let status = self.status;
+                self.record(status);
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl<S> Drop for MeteredStream<S> {
+    fn drop(&mut self) {
+        let status = self.status;
+        self.record(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::documents::DocType;
+    use crate::testing::FakeCloud;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(Operation, Outcome, u64)>>);
+
+    impl Metrics for Recorder {
+        fn record_request(
+            &self,
+            op: Operation,
+            status: Outcome,
+            _duration: Duration,
+            bytes: u64,
+        ) {
+            self.0.lock().unwrap().push((op, status, bytes));
+        }
+    }
+
+    impl Recorder {
+        fn take(&self) -> Vec<(Operation, Outcome)> {
+            let recorded = std::mem::take(&mut *self.0.lock().unwrap());
+            recorded.into_iter().map(|(op, s, _)| (op, s)).collect()
+        }
+
+        fn bytes(&self, op: Operation) -> Vec<u64> {
+            let recorded = self.0.lock().unwrap();
+            recorded.iter().filter(|r| r.0 == op).map(|r| r.2).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn observed() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, b"a blob".to_vec());
+        let recorder = Arc::new(Recorder::default());
+        let mut client = cloud.client();
+        client.set_metrics(Some(recorder.clone()));
+        client.refresh_token().await.unwrap();
+        client.get_documents().await.unwrap();
+        assert_eq!(
+            recorder.take(),
+            [
+                (Operation::Token, Outcome::Status(200)),
+                (Operation::Listing, Outcome::Status(200)),
+            ]
+        );
+
+        // Downloads are counted by what's streamed.
+        let doc = client.get_document_by_id(&dune).await.unwrap();
+        let mut stream = client.blob_stream(&doc).await.unwrap();
+        assert_eq!(
+            recorder.take(),
+            [(Operation::Listing, Outcome::Status(200))]
+        );
+        while futures_util::StreamExt::next(&mut stream).await.is_some() {}
+        assert_eq!(recorder.bytes(Operation::Download), [6]);
+        drop(stream);
+        assert_eq!(
+            recorder.take(),
+            [(Operation::Download, Outcome::Status(200))]
+        );
+        cloud.fail_next("/blob/", false);
+        assert!(client.download_blob(&doc).await.is_err());
+        assert_eq!(
+            recorder.take(),
+            [(Operation::Download, Outcome::Status(503))]
+        );
+
+        // Each retry on its own.
+        cloud.fail_next("/document-storage/json/2/upload/request", false);
+        let zip = b"zip".to_vec();
+        client
+            .upload_zip(
+                Uuid::new_v4(),
+                1,
+                None,
+                "Notes",
+                DocType::Document,
+                zip,
+            )
+            .await
+            .unwrap();
+        assert_eq!(recorder.bytes(Operation::UploadBlob), [3]);
+        assert_eq!(
+            recorder.take(),
+            [
+                (Operation::UploadRequest, Outcome::Status(503)),
+                (Operation::UploadRequest, Outcome::Status(200)),
+                (Operation::UploadBlob, Outcome::Status(200)),
+                (Operation::UpdateStatus, Outcome::Status(200)),
+            ]
+        );
+
+        // Views of the client count too.
+        let token = crate::CancellationToken::new();
+        let scoped = client.with_cancellation(token.clone());
+        token.cancel();
+        assert!(scoped.get_documents().await.is_err());
+        assert_eq!(recorder.take(), [(Operation::Listing, Outcome::Failed)]);
+    }
+}
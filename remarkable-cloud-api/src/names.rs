@@ -0,0 +1,150 @@
+//! Checking the visible names documents are given.
+//!
+//! The cloud takes any name at all, but the tablet shows an empty or blank
+//! one as an entry that can't be seen, and a very long one as one that
+//! can't be read. Names are tidied, and those that tidy away to nothing are
+//! refused, by `Client` when uploading or renaming (see
+//! `Client::set_name_policy`) and by anything deriving names itself.
+
+use crate::error::{Error, Result};
+use crate::nfc;
+
+/// The longest name, in characters, the tablet shows well.
+pub const DEFAULT_MAX_NAME_LEN: usize = 250;
+
+/// What `normalize_name` does with names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NamePolicy {
+    /// Names longer than this, in characters, are warned about.
+    pub max_len: usize,
+    /// Cut names longer than `max_len` down to it, rather than only warn.
+    pub truncate: bool,
+}
+
+impl Default for NamePolicy {
+    fn default() -> Self {
+        NamePolicy {
+            max_len: DEFAULT_MAX_NAME_LEN,
+            truncate: false,
+        }
+    }
+}
+
+/// `name` without control characters such as tabs and newlines, without
+/// whitespace at either end, and in Unicode Normalization Form C, so that
+/// a name typed or taken from a file name on any system comes out the
+/// same.
+pub fn tidy_name(name: &str) -> String {
+    let printable: String = name.chars().filter(|c| !c.is_control()).collect();
+    nfc::compose(printable.trim())
+}
+
+/// What `name` is told apart from others by, where names count as the same
+/// if they differ only in Unicode normalization, as a name typed on macOS
+/// and the same name typed on Linux do, and with `fold_case`, only in case,
+/// as on filesystems which ignore it.
+pub fn name_key(name: &str, fold_case: bool) -> String {
+    let composed = nfc::compose(name);
+    if fold_case {
+        composed.to_lowercase()
+    } else {
+        composed
+    }
+}
+
+/// `name` tidied as `tidy_name` does, and checked against `policy`. Fails
+/// with `Error::InvalidName` if nothing is left of it.
+pub fn normalize_name(name: &str, policy: &NamePolicy) -> Result<String> {
+    let mut tidied = tidy_name(name);
+    if tidied.is_empty() {
+        return Err(Error::InvalidName {
+            name: name.to_string(),
+            reason: if name.is_empty() {
+                "names can't be empty"
+            } else {
+                "names need something other than spaces and control characters"
+            },
+        });
+    }
+    let len = tidied.chars().count();
+    if len > policy.max_len {
+        if policy.truncate {
+            tidied = tidied.chars().take(policy.max_len).collect();
+            tidied.truncate(tidied.trim_end().len());
+            log::warn!(
+                "Shortened a name of {} characters to {:?}",
+                len,
+                tidied
+            );
+        } else {
+            log::warn!(
+                "{:?} is {} characters long, more than the {} the tablet shows well",
+                tidied,
+                len,
+                policy.max_len
+            );
+        }
+    }
+    Ok(tidied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tidied() {
+        let policy = NamePolicy::default();
+        let normalized = |name: &str| normalize_name(name, &policy).unwrap();
+        assert_eq!(normalized("Dune"), "Dune");
+        assert_eq!(normalized("  Dune \t"), "Dune");
+        assert_eq!(normalized("Du\nne\u{7}"), "Dune");
+        assert_eq!(normalized("Dune\u{200B}"), "Dune\u{200B}");
+        assert_eq!(normalized("Notes: 1/2 (draft)"), "Notes: 1/2 (draft)");
+        // The same name from macOS, decomposed, and from Linux.
+        assert_eq!(normalized("Re\u{301}sume\u{301}"), "Résumé");
+        assert_eq!(normalized("Résumé"), "Résumé");
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(name_key("Re\u{301}sume\u{301}", false), "Résumé");
+        assert_eq!(name_key("Résumé", false), "Résumé");
+        assert_eq!(name_key("Re\u{301}sume\u{301}", true), "résumé");
+        // Marks typed in either order are the same name.
+        assert_eq!(
+            name_key("Vie\u{302}\u{323}t", false),
+            name_key("Vie\u{323}\u{302}t", false)
+        );
+        assert_ne!(name_key("Notes", false), name_key("notes", false));
+        assert_eq!(name_key("Notes", true), name_key("NOTES", true));
+        // Kept as they are otherwise, spaces and all.
+        assert_eq!(name_key(" Dune ", false), " Dune ");
+    }
+
+    #[test]
+    fn refused() {
+        let policy = NamePolicy::default();
+        for name in &["", " ", "\t\n", "\u{3000}", "\u{1b}\u{7f}"] {
+            let e = normalize_name(name, &policy).unwrap_err();
+            assert!(matches!(e, Error::InvalidName { .. }), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn long() {
+        let long = "x".repeat(300);
+        let kept = normalize_name(&long, &NamePolicy::default()).unwrap();
+        assert_eq!(kept, long);
+        let policy = NamePolicy {
+            max_len: 4,
+            truncate: true,
+        };
+        let cut = |name: &str| normalize_name(name, &policy).unwrap();
+        assert_eq!(cut("Dune Messiah"), "Dune");
+        assert_eq!(cut("Du  ne"), "Du");
+        // Counted in characters, not bytes.
+        assert_eq!(cut("Ébène"), "Ébèn");
+        assert_eq!(cut("E\u{301}be\u{300}ne"), "Ébèn");
+    }
+}
@@ -0,0 +1,201 @@
+//! Comparing names as people read them, so "Meeting 2" comes before
+//! "Meeting 10".
+//!
+//! Runs of digits are compared by their value, whatever script they're
+//! written in, and everything else character by character. Names equal but
+//! for how their numbers are written, such as "Take 7" and "Take 07", are
+//! told apart by how many leading zeros come first, fewer before more, and
+//! then by their code points, so distinct names are never equal.
+
+use std::cmp::Ordering;
+
+/// An order for sibling names, as `Documents::descendants_by` takes it.
+pub type NameOrder<'a> = &'a dyn Fn(&str, &str) -> Ordering;
+
+// The zero of each script's decimal digits, each followed by the other
+// nine in order.
+const ZEROS: &[u32] = &[
+    0x0030, 0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66,
+    0x0BE6, 0x0C66, 0x0CE6, 0x0D66, 0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040,
+    0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90, 0x1B50, 0x1BB0,
+    0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0,
+    0xFF10, 0x104A0, 0x11066, 0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450,
+    0x114D0, 0x11650, 0x116C0, 0x11730, 0x118E0, 0x16A60, 0x16B50, 0x1D7CE,
+    0x1D7D8, 0x1D7E2, 0x1D7EC, 0x1D7F6, 0x1E950,
+];
+
+/// The value of `c` if it's a decimal digit.
+pub fn digit_value(c: char) -> Option<u32> {
+    let c = c as u32;
+    let at = match ZEROS.binary_search(&c) {
+        Ok(at) => at,
+        Err(0) => return None,
+        Err(above) => above - 1,
+    };
+    Some(c - ZEROS[at]).filter(|value| *value < 10)
+}
+
+/// Compares `a` and `b` in natural order.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    // How the first numbers of equal value differed, if any did.
+    let mut zeros = Ordering::Equal;
+    loop {
+        let (x, y) = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (*x, *y),
+        };
+        if digit_value(x).is_none() || digit_value(y).is_none() {
+            match x.cmp(&y) {
+                Ordering::Equal => {
+                    a_chars.next();
+                    b_chars.next();
+                    continue;
+                }
+                unequal => return unequal,
+            }
+        }
+        let x = number(&mut a_chars);
+        let y = number(&mut b_chars);
+        // Without their leading zeros, the longer number is the larger, and
+        // those as long compare digit by digit.
+        let by_value =
+            (x.digits.len(), &x.digits).cmp(&(y.digits.len(), &y.digits));
+        if by_value != Ordering::Equal {
+            return by_value;
+        }
+        if zeros == Ordering::Equal {
+            zeros = x.zeros.cmp(&y.zeros);
+        }
+    }
+    zeros.then_with(|| a.cmp(b))
+}
+
+// A run of digits: how many leading zeros it has, and the values of the
+// rest.
+struct Number {
+    zeros: usize,
+    digits: Vec<u32>,
+}
+
+// Takes the run of digits `chars` starts with.
+fn number<I: Iterator<Item = char>>(
+    chars: &mut std::iter::Peekable<I>,
+) -> Number {
+    let mut number = Number {
+        zeros: 0,
+        digits: vec![],
+    };
+    while let Some(value) = chars.peek().and_then(|c| digit_value(*c)) {
+        chars.next();
+        if value == 0 && number.digits.is_empty() {
+            number.zeros += 1;
+        } else {
+            number.digits.push(value);
+        }
+    }
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sorts `names` in natural order, checking the order against every
+    // pair of them.
+    fn sorted(names: &[&str]) -> Vec<String> {
+        let mut sorted: Vec<&str> = names.to_vec();
+        sorted.sort_by(|a, b| natural_cmp(a, b));
+        for (i, a) in sorted.iter().enumerate() {
+            for (j, b) in sorted.iter().enumerate() {
+                assert_eq!(natural_cmp(a, b), i.cmp(&j), "{:?}, {:?}", a, b);
+            }
+        }
+        sorted.into_iter().map(String::from).collect()
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(
+            sorted(&["Meeting 10", "Meeting 2", "Meeting 1", "Meeting"]),
+            ["Meeting", "Meeting 1", "Meeting 2", "Meeting 10"]
+        );
+        assert_eq!(sorted(&["9", "10", "100", "11"]), ["9", "10", "11", "100"]);
+        // Longer than any integer type.
+        assert_eq!(
+            sorted(&[
+                "v100000000000000000000000000000",
+                "v99999999999999999999999999999",
+            ]),
+            [
+                "v99999999999999999999999999999",
+                "v100000000000000000000000000000",
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(
+            sorted(&["Take 010", "Take 7", "Take 007", "Take 07", "Take 8"]),
+            ["Take 7", "Take 07", "Take 007", "Take 8", "Take 010"]
+        );
+        assert_eq!(sorted(&["0", "00", "000"]), ["0", "00", "000"]);
+        // Only the first number to differ in zeros decides.
+        assert_eq!(
+            sorted(&["a01 b1", "a1 b01", "a1 b1"]),
+            ["a1 b1", "a1 b01", "a01 b1"]
+        );
+        // Anything after the numbers decides before their zeros do.
+        assert_eq!(sorted(&["x01b", "x1c"]), ["x01b", "x1c"]);
+    }
+
+    #[test]
+    fn mixed() {
+        assert_eq!(
+            sorted(&["a10b2", "a2b10", "a2b2", "a10", "a2", "a", "b1"]),
+            ["a", "a2", "a2b2", "a2b10", "a10", "a10b2", "b1"]
+        );
+        assert_eq!(
+            sorted(&["2020-12-01", "2020-2-10", "2020-02-09"]),
+            ["2020-02-09", "2020-2-10", "2020-12-01"]
+        );
+        assert_eq!(sorted(&["1.5", "1.10", "1.05"]), ["1.5", "1.05", "1.10"]);
+        // Digits before letters, as their code points are.
+        assert_eq!(sorted(&["a", "1", "A", ""]), ["", "1", "A", "a"]);
+        assert_eq!(sorted(&["x 2", "x2", "x 10"]), ["x 2", "x 10", "x2"]);
+    }
+
+    #[test]
+    fn unicode_digits() {
+        assert_eq!(digit_value('7'), Some(7));
+        assert_eq!(digit_value('\u{0663}'), Some(3));
+        assert_eq!(digit_value('\u{096F}'), Some(9));
+        assert_eq!(digit_value('\u{FF10}'), Some(0));
+        assert_eq!(digit_value('\u{1D7D8}'), Some(0));
+        assert_eq!(digit_value('a'), None);
+        assert_eq!(digit_value('\u{066A}'), None);
+        assert_eq!(digit_value('½'), None);
+        assert_eq!(digit_value('²'), None);
+        for zero in ZEROS {
+            let zero = std::char::from_u32(*zero).unwrap();
+            assert!(zero.is_numeric(), "{:?}", zero);
+        }
+        // Arabic-Indic ٣ and ١٠, and fullwidth ２.
+        assert_eq!(
+            sorted(&["p \u{0661}\u{0660}", "p \u{FF12}", "p \u{0663}", "p 11"]),
+            ["p \u{FF12}", "p \u{0663}", "p \u{0661}\u{0660}", "p 11"]
+        );
+        // Devanagari ११ and ३.
+        assert_eq!(
+            sorted(&["\u{0967}\u{0967}", "10", "\u{0969}"]),
+            ["\u{0969}", "10", "\u{0967}\u{0967}"]
+        );
+        // The same number in two scripts, apart by code point.
+        assert_eq!(sorted(&["\u{0663}", "3"]), ["3", "\u{0663}"]);
+        assert!(ZEROS.windows(2).all(|w| w[0] + 10 <= w[1]));
+    }
+}
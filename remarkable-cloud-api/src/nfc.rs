@@ -0,0 +1,69 @@
+//! Composing text into Unicode Normalization Form C, for names.
+//!
+//! macOS hands out file names decomposed, "é" as "e" and a combining acute
+//! accent, where Linux and Windows keep them as typed, which is almost
+//! always composed. Composing them gives the same name for both. That's
+//! done by `icu_normalizer`, which has all of Unicode's compositions and
+//! puts combining marks in their canonical order first, so marks typed in
+//! another order come out the same too.
+
+use icu_normalizer::ComposingNormalizer;
+
+/// `s` in Normalization Form C.
+pub(crate) fn compose(s: &str) -> String {
+    ComposingNormalizer::new_nfc().normalize(s).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn composes() {
+        // As macOS and Linux would name the same file.
+        assert_eq!(compose("Cafe\u{301} crème"), "Café crème");
+        assert_eq!(compose("Café crème"), "Café crème");
+        // Marks stacked one on another, in Vietnamese.
+        assert_eq!(
+            compose("Tie\u{302}\u{301}ng Vie\u{323}\u{302}t"),
+            "Tiếng Việt"
+        );
+        assert_eq!(compose("\u{438}\u{306}"), "й");
+        assert_eq!(compose("\u{304B}\u{3099}"), "が");
+        assert_eq!(compose("\u{1112}\u{1161}\u{11AB}"), "한");
+        // What has no composed form is left as it is.
+        assert_eq!(compose("q\u{301}"), "q\u{301}");
+        assert_eq!(compose("\u{301}e"), "\u{301}e");
+        assert_eq!(compose(""), "");
+    }
+
+    // Marks of different combining classes are put in canonical order, and
+    // a mark composes with the letter past one of another class, but not
+    // past one of the same class, which blocks it.
+    #[test]
+    fn reordered() {
+        // Grave accent below (220), then acute (230).
+        assert_eq!(compose("a\u{316}\u{301}"), "\u{e1}\u{316}");
+        assert_eq!(compose("a\u{301}\u{316}"), "\u{e1}\u{316}");
+        // Dot below (220) and circumflex (230), typed either way round.
+        assert_eq!(compose("e\u{323}\u{302}"), "\u{1ec7}");
+        assert_eq!(compose("e\u{302}\u{323}"), "\u{1ec7}");
+        // Two marks above: the second is blocked by the first.
+        assert_eq!(compose("a\u{308}\u{301}"), "\u{e4}\u{301}");
+        assert_eq!(compose("a\u{301}\u{308}"), "\u{e1}\u{308}");
+        // Decomposed from a composed character and composed again.
+        assert_eq!(compose("\u{1e0b}\u{323}"), "\u{1e0d}\u{307}");
+    }
+
+    // Scripts beyond Latin, Greek, Cyrillic and kana compose as well.
+    #[test]
+    fn other_scripts() {
+        // Devanagari nukta, and a Bengali two-part vowel sign.
+        assert_eq!(compose("\u{928}\u{93c}"), "\u{929}");
+        assert_eq!(compose("\u{9c7}\u{9be}"), "\u{9cb}");
+        // Composition exclusions stay decomposed.
+        assert_eq!(compose("\u{915}\u{93c}"), "\u{915}\u{93c}");
+        // Singletons, such as the Ångström sign, become what they stand for.
+        assert_eq!(compose("\u{212b}"), "\u{c5}");
+    }
+}
@@ -0,0 +1,840 @@
+//! The pages of a notebook's archive, and putting them in a new order.
+//!
+//! Each page is spread over several files: its entry in the `pages` list of
+//! `.content`, its line of `.pagedata`, and files of its own in folders
+//! named after the document, such as `{id}/{page}.rm`,
+//! `{id}/{page}-metadata.json` and `{id}.thumbnails/{page}.jpg`. Newer
+//! archives name those after the page's id, older ones, without a `pages`
+//! list, after its index. All of them have to be kept in step.
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+use remarkable_data_formats::content::Content;
+use remarkable_data_formats::highlights::{self, Highlight};
+use remarkable_data_formats::ink::{self, PageTransform};
+use remarkable_data_formats::lines::Page;
+use remarkable_data_formats::pagedata::PageData;
+use remarkable_data_formats::pdf;
+use remarkable_data_formats::stats::PageStats;
+use uuid::Uuid;
+
+use crate::details::read_entry;
+use crate::error::{Error, Result};
+
+type Archive<'a> = zip::ZipArchive<io::Cursor<&'a [u8]>>;
+
+/// One page of a document, as listed by `list_pages`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageInfo {
+    /// The page's id, or `None` in older archives which name pages by
+    /// index.
+    pub id: Option<String>,
+    /// The template behind the page, if `.pagedata` says.
+    pub template: Option<String>,
+    /// Strokes drawn on the page: 0 if nothing has been, and `None` if its
+    /// `.rm` file is in a format that can't be read.
+    pub stroke_count: Option<usize>,
+}
+
+// What an archive says about its pages.
+pub(crate) struct Pages {
+    pub content: Content,
+    /// The ids from `.content`, if it lists them.
+    pub ids: Option<Vec<String>>,
+    /// What each page's files are named after, in page order.
+    pub keys: Vec<String>,
+}
+
+impl Pages {
+    pub fn read(document_id: Uuid, archive: &mut Archive<'_>) -> Result<Self> {
+        let content =
+            match read_entry(archive, &format!("{}.content", document_id))? {
+                Some(data) => Content::parse(&data)?,
+                None => Content::default(),
+            };
+        let ids = page_ids(&content);
+        let keys = match (&ids, content.page_count) {
+            (Some(ids), _) => ids.clone(),
+            (None, Some(n)) => (0..n).map(|i| i.to_string()).collect(),
+            (None, None) => {
+                let prefix = format!("{}/", document_id);
+                let count = archive
+                    .file_names()
+                    .filter(|n| n.starts_with(&prefix) && n.ends_with(".rm"))
+                    .count();
+                (0..count).map(|i| i.to_string()).collect()
+            }
+        };
+        Ok(Pages { content, ids, keys })
+    }
+}
+
+// The ids of the pages `content` lists, if it does.
+fn page_ids(content: &Content) -> Option<Vec<String>> {
+    content
+        .other
+        .get("pages")
+        .and_then(|p| p.as_array())
+        .map(|pages| {
+            pages
+                .iter()
+                .filter_map(|p| p.as_str().map(String::from))
+                .collect()
+        })
+}
+
+/// A page bookmarked by tagging it on the tablet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BookmarkedPage {
+    /// The page's index, counting from 0.
+    pub index: usize,
+    /// The names of its tags, in the order `.content` has them.
+    pub tags: Vec<String>,
+    /// When it was last tagged, if `.content` says.
+    pub tagged: Option<DateTime<Utc>>,
+}
+
+// The pages `content` has tags on, in page order. Tags on pages it doesn't
+// list, as deleted pages' may be left, are ignored.
+pub(crate) fn bookmarks(content: &Content) -> Vec<BookmarkedPage> {
+    let ids = page_ids(content).unwrap_or_default();
+    let mut found: Vec<BookmarkedPage> = vec![];
+    for tag in content.page_tags() {
+        let index = match ids.iter().position(|id| *id == tag.page_id) {
+            Some(index) => index,
+            None => continue,
+        };
+        match found.iter_mut().find(|b| b.index == index) {
+            Some(page) => {
+                page.tags.push(tag.name);
+                page.tagged = page.tagged.max(tag.timestamp);
+            }
+            None => found.push(BookmarkedPage {
+                index,
+                tags: vec![tag.name],
+                tagged: tag.timestamp,
+            }),
+        }
+    }
+    found.sort_by_key(|b| b.index);
+    found
+}
+
+/// The pages bookmarked in `zip`, the archive of the document
+/// `document_id`, in page order.
+pub fn bookmarked_pages(
+    document_id: Uuid,
+    zip: &[u8],
+) -> Result<Vec<BookmarkedPage>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    Ok(bookmarks(&pages.content))
+}
+
+// Which page the file `name` belongs to, if it's one of the files kept for
+// each page, with the folder it's in and the rest of its name after the
+// page's key.
+fn per_page<'a>(
+    document_id: &str,
+    name: &'a str,
+    keys: &[String],
+) -> Option<(usize, &'a str, &'a str)> {
+    let (folder, file) = name.rsplit_once('/')?;
+    let owned = folder == document_id
+        || folder
+            .strip_prefix(document_id)
+            .is_some_and(|rest| rest.starts_with('.') && !rest.contains('/'));
+    if !owned {
+        return None;
+    }
+    keys.iter().enumerate().find_map(|(index, key)| {
+        let rest = file.strip_prefix(key.as_str())?;
+        if rest.starts_with('.') || rest.starts_with('-') {
+            Some((index, folder, rest))
+        } else {
+            None
+        }
+    })
+}
+
+/// Lists the pages in `zip`, the archive of the document `document_id`.
+pub fn list_pages(document_id: Uuid, zip: &[u8]) -> Result<Vec<PageInfo>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    let pagedata =
+        read_entry(&mut archive, &format!("{}.pagedata", document_id))?
+            .map(|data| PageData::parse(&data));
+    let mut infos = vec![];
+    for (index, key) in pages.keys.iter().enumerate() {
+        let rm = format!("{}/{}.rm", document_id, key);
+        let stroke_count = match read_entry(&mut archive, &rm)? {
+            Some(data) => Page::parse(&data).ok().map(|p| p.stroke_count()),
+            None => Some(0),
+        };
+        infos.push(PageInfo {
+            id: pages.ids.as_ref().map(|_| key.clone()),
+            template: pagedata
+                .as_ref()
+                .and_then(|p| p.templates.get(index).cloned()),
+            stroke_count,
+        });
+    }
+    Ok(infos)
+}
+
+/// What's drawn on each page of `zip`, the archive of the document
+/// `document_id`, in page order: `None` for a page whose `.rm` file is in a
+/// format that can't be read.
+pub fn ink_stats(
+    document_id: Uuid,
+    zip: &[u8],
+) -> Result<Vec<Option<PageStats>>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    let mut stats = vec![];
+    for key in &pages.keys {
+        let rm = format!("{}/{}.rm", document_id, key);
+        stats.push(match read_entry(&mut archive, &rm)? {
+            Some(data) => Page::parse(&data).ok().map(|p| PageStats::from(&p)),
+            None => Some(PageStats::default()),
+        });
+    }
+    Ok(stats)
+}
+
+/// The text highlighted on the pages of `zip`, the archive of the document
+/// `document_id`, as the index of each page with any, counting from 0, and
+/// its highlights in the order of its text.
+pub fn highlights(
+    document_id: Uuid,
+    zip: &[u8],
+) -> Result<Vec<(usize, Vec<Highlight>)>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    let mut found = vec![];
+    for (index, key) in pages.keys.iter().enumerate() {
+        let name = format!("{}.highlights/{}.json", document_id, key);
+        if let Some(data) = read_entry(&mut archive, &name)? {
+            let on_page = highlights::parse(&data)?;
+            if !on_page.is_empty() {
+                found.push((index, on_page));
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// The text typed onto each page of `zip`, the archive of the document
+/// `document_id`, as its paragraphs, in page order. A page whose `.rm` file
+/// is in a format that can't be read has none.
+pub fn typed_text(document_id: Uuid, zip: &[u8]) -> Result<Vec<Vec<String>>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    let mut text = vec![];
+    for key in &pages.keys {
+        let rm = format!("{}/{}.rm", document_id, key);
+        let page = read_entry(&mut archive, &rm)?
+            .and_then(|data| Page::parse(&data).ok());
+        text.push(
+            page.iter()
+                .flat_map(|p| &p.text)
+                .flat_map(|block| &block.paragraphs)
+                .map(|p| p.text.clone())
+                .collect(),
+        );
+    }
+    Ok(text)
+}
+
+/// The thumbnail of page `index`, counting from 0, in `zip`, the archive of
+/// the document `document_id`: a JPEG, or `None` if the archive has none for
+/// that page.
+pub fn page_thumbnail(
+    document_id: Uuid,
+    zip: &[u8],
+    index: usize,
+) -> Result<Option<Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    let key = pages.keys.get(index).ok_or(Error::NoSuchPage {
+        index,
+        page_count: pages.keys.len(),
+    })?;
+    let name = format!("{}.thumbnails/{}.jpg", document_id, key);
+    read_entry(&mut archive, &name)
+}
+
+// Each page of `zip` with what's drawn on it, its layers' names and the
+// transform onto it: of the PDF's page of the same index, if the document
+// is a PDF whose pages can be read, or of the tablet's screen.
+fn ink_pages(
+    document_id: Uuid,
+    zip: &[u8],
+) -> Result<Vec<(Page, Vec<String>, PageTransform)>> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let pages = Pages::read(document_id, &mut archive)?;
+    let boxes = match pages.content.file_type.as_str() {
+        "pdf" => read_entry(&mut archive, &format!("{}.pdf", document_id))?
+            .map(|pdf| pdf::page_boxes(&pdf))
+            .unwrap_or_default(),
+        _ => vec![],
+    };
+    let mut ink = vec![];
+    for (index, key) in pages.keys.iter().enumerate() {
+        let rm = format!("{}/{}.rm", document_id, key);
+        let page = match read_entry(&mut archive, &rm)? {
+            Some(data) => Page::parse(&data)?,
+            None => Page {
+                version: 5,
+                layers: vec![],
+                text: vec![],
+                warnings: vec![],
+            },
+        };
+        let meta = format!("{}/{}-metadata.json", document_id, key);
+        let names = read_entry(&mut archive, &meta)?
+            .and_then(|data| {
+                serde_json::from_slice::<serde_json::Value>(&data).ok()
+            })
+            .and_then(|meta| {
+                let layers = meta.get("layers")?.as_array()?.iter();
+                layers
+                    .map(|l| Some(l.get("name")?.as_str()?.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let transform = match boxes.get(index) {
+            Some(page) => PageTransform::new(*page),
+            None => PageTransform::tablet(),
+        };
+        ink.push((page, names, transform));
+    }
+    Ok(ink)
+}
+
+/// What's drawn on each page of `zip`, the archive of the document
+/// `document_id`, without the page behind it, as an SVG per page. A PDF's
+/// pages are drawn in the size of the PDF's pages, so as to lie over them;
+/// others in the size of the tablet's screen. See `ink::svg`.
+pub fn render_ink_only(document_id: Uuid, zip: &[u8]) -> Result<Vec<String>> {
+    Ok(ink_pages(document_id, zip)?
+        .iter()
+        .map(|(page, names, transform)| ink::svg(page, names, transform))
+        .collect())
+}
+
+/// What's drawn on the pages of `zip` as `render_ink_only` draws it, as one
+/// PDF with a page for each.
+pub fn render_ink_pdf(document_id: Uuid, zip: &[u8]) -> Result<Vec<u8>> {
+    let pages = ink_pages(document_id, zip)?;
+    let pages: Vec<_> = pages.iter().map(|(p, _, t)| (p, *t)).collect();
+    Ok(ink::pdf(&pages))
+}
+
+/// Rewrites `zip`, the archive of the notebook `document_id`, to hold the
+/// pages at the indexes in `order`, counting from 0, in that order. Pages
+/// left out of `order` are deleted, along with all their files.
+///
+/// Fails with `Error::InvalidArchive` rather than guess if the archive
+/// isn't a notebook or its files disagree about how many pages it has, and
+/// with `Error::NoSuchPage` for an index it doesn't have.
+pub fn rearrange_pages(
+    document_id: Uuid,
+    zip: &[u8],
+    order: &[usize],
+) -> Result<Vec<u8>> {
+    let invalid = |reason: String| Err(Error::InvalidArchive { reason });
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let Pages {
+        mut content,
+        ids,
+        keys,
+    } = Pages::read(document_id, &mut archive)?;
+    if matches!(content.file_type.as_str(), "pdf" | "epub") {
+        return invalid(format!(
+            "the pages of a {} follow the file, so can't be rearranged",
+            content.file_type
+        ));
+    }
+    if content.other.contains_key("cPages") {
+        return invalid("its .content is in a newer format".to_string());
+    }
+    if let Some(&index) = order.iter().find(|i| **i >= keys.len()) {
+        return Err(Error::NoSuchPage {
+            index,
+            page_count: keys.len(),
+        });
+    }
+    let mut seen = HashSet::new();
+    if let Some(index) = order.iter().find(|i| !seen.insert(**i)) {
+        return invalid(format!("page {} is given twice", index + 1));
+    }
+    if order.is_empty() {
+        return invalid("a notebook needs at least one page".to_string());
+    }
+    let pagedata_name = format!("{}.pagedata", document_id);
+    let pagedata = read_entry(&mut archive, &pagedata_name)?
+        .map(|data| PageData::parse(&data));
+    if let Some(pagedata) = &pagedata {
+        if pagedata.templates.len() != keys.len() {
+            return invalid(format!(
+                "the pagedata has {} pages but the content {}",
+                pagedata.templates.len(),
+                keys.len()
+            ));
+        }
+    }
+
+    // Pages named by id keep their names; those named by index are renamed
+    // to their new one.
+    let new_key: Vec<Option<String>> = {
+        let mut new_key = vec![None; keys.len()];
+        for (new, old) in order.iter().enumerate() {
+            new_key[*old] = Some(match ids {
+                Some(_) => keys[*old].clone(),
+                None => new.to_string(),
+            });
+        }
+        new_key
+    };
+    if let Some(ids) = &ids {
+        let ids: Vec<&String> = order.iter().map(|i| &ids[*i]).collect();
+        content
+            .other
+            .insert("pages".to_string(), serde_json::json!(ids));
+    }
+    if content.page_count.is_some() {
+        content.page_count = Some(order.len() as u64);
+    }
+    let pagedata = pagedata.map(|p| PageData {
+        templates: order.iter().map(|i| p.templates[*i].clone()).collect(),
+    });
+
+    let content_name = format!("{}.content", document_id);
+    let had_content = archive.by_name(&content_name).is_ok();
+    let id = document_id.to_string();
+    let mut dst = zip::ZipWriter::new(io::Cursor::new(vec![]));
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let options = zip::write::FileOptions::default()
+            .compression_method(file.compression());
+        let name = file.name().to_string();
+        if name == content_name || name == pagedata_name {
+            continue;
+        }
+        if file.is_dir() {
+            dst.add_directory(name, options)?;
+            continue;
+        }
+        let name = match per_page(&id, &name, &keys) {
+            Some((page, folder, rest)) => match &new_key[page] {
+                Some(key) => format!("{}/{}{}", folder, key, rest),
+                None => continue,
+            },
+            None => name,
+        };
+        dst.start_file(name, options)?;
+        io::copy(&mut file, &mut dst)?;
+    }
+    if had_content {
+        dst.start_file(content_name, zip::write::FileOptions::default())?;
+        dst.write_all(&content.to_vec())?;
+    }
+    if let Some(pagedata) = pagedata {
+        dst.start_file(pagedata_name, zip::write::FileOptions::default())?;
+        dst.write_all(&pagedata.to_vec())?;
+    }
+    Ok(dst.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn doc_id() -> Uuid {
+        Uuid::from_u128(1)
+    }
+
+    fn page_id(index: usize) -> String {
+        Uuid::from_u128(100 + index as u128).to_string()
+    }
+
+    fn rm(strokes: u32) -> Vec<u8> {
+        let mut buf =
+            format!("{:<43}", "reMarkable .lines file, version=5").into_bytes();
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&strokes.to_le_bytes());
+        for _ in 0..strokes {
+            buf.extend_from_slice(&[0; 20]);
+            buf.extend_from_slice(&0u32.to_le_bytes());
+        }
+        buf
+    }
+
+    // A notebook of three pages, page `i` having `i + 1` strokes and files
+    // saying which page they belong to.
+    fn fixture(by_id: bool) -> Vec<u8> {
+        let id = doc_id();
+        let keys: Vec<String> = (0..3)
+            .map(|i| if by_id { page_id(i) } else { i.to_string() })
+            .collect();
+        let content = if by_id {
+            serde_json::json!({
+                "fileType": "notebook",
+                "pageCount": 3,
+                "pages": keys,
+            })
+        } else {
+            serde_json::json!({ "fileType": "notebook", "pageCount": 3 })
+        };
+        let mut files = vec![
+            (format!("{}.content", id), content.to_string().into_bytes()),
+            (format!("{}.metadata", id), b"{}".to_vec()),
+            (format!("{}.pagedata", id), b"T0\nT1\nT2\n".to_vec()),
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            files.push((format!("{}/{}.rm", id, key), rm(i as u32 + 1)));
+            files.push((
+                format!("{}/{}-metadata.json", id, key),
+                format!("meta{}", i).into_bytes(),
+            ));
+            files.push((
+                format!("{}.thumbnails/{}.jpg", id, key),
+                format!("thumb{}", i).into_bytes(),
+            ));
+        }
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(name, Default::default()).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn thumbnails() {
+        for by_id in &[true, false] {
+            let zip = fixture(*by_id);
+            let thumb = page_thumbnail(doc_id(), &zip, 2).unwrap();
+            assert_eq!(thumb.as_deref(), Some(&b"thumb2"[..]));
+            assert!(matches!(
+                page_thumbnail(doc_id(), &zip, 3),
+                Err(Error::NoSuchPage { index: 3, .. })
+            ));
+        }
+        let zip = rearrange_pages(doc_id(), &fixture(true), &[2, 0]).unwrap();
+        let thumb = page_thumbnail(doc_id(), &zip, 1).unwrap();
+        assert_eq!(thumb.as_deref(), Some(&b"thumb0"[..]));
+    }
+
+    #[test]
+    fn highlights_and_text() {
+        let id = doc_id();
+        let content = serde_json::json!({
+            "fileType": "pdf",
+            "pages": [page_id(0), page_id(1)],
+        });
+        let files = [
+            (format!("{}.content", id), content.to_string()),
+            (
+                format!("{}.highlights/{}.json", id, page_id(1)),
+                r#"{"highlights": [[{"text": "spice", "start": 4}]]}"#
+                    .to_string(),
+            ),
+        ];
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in &files {
+            zip.start_file(name, Default::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+        let found = highlights(id, &zip).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, 1);
+        assert_eq!(found[0].1[0].text, "spice");
+
+        let text = typed_text(doc_id(), &fixture(true)).unwrap();
+        assert_eq!(text, vec![Vec::<String>::new(); 3]);
+    }
+
+    #[test]
+    fn ink_stats_per_page() {
+        let stats = ink_stats(doc_id(), &fixture(true)).unwrap();
+        let strokes: Vec<usize> =
+            stats.iter().map(|s| s.as_ref().unwrap().strokes).collect();
+        assert_eq!(strokes, [1, 2, 3]);
+
+        // A page which can't be read, and one with nothing drawn yet.
+        let id = doc_id();
+        let content = serde_json::json!({
+            "fileType": "notebook",
+            "pages": [page_id(0), page_id(1)],
+        });
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        zip.start_file(format!("{}.content", id), Default::default())
+            .unwrap();
+        zip.write_all(content.to_string().as_bytes()).unwrap();
+        zip.start_file(format!("{}/{}.rm", id, page_id(0)), Default::default())
+            .unwrap();
+        zip.write_all(b"%PDF-1.4").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        let stats = ink_stats(id, &zip).unwrap();
+        assert_eq!(stats, [None, Some(PageStats::default())]);
+    }
+
+    #[test]
+    fn ink() {
+        let id = doc_id();
+        let a4 = pdf::PageBox::from_corners([0.0, 0.0, 595.0, 842.0]);
+        let wide = pdf::PageBox::from_corners([0.0, 0.0, 842.0, 595.0]);
+        let blank = Page::parse(&rm(0)).unwrap();
+        let original = ink::pdf(&[
+            (&blank, PageTransform::new(a4)),
+            (&blank, PageTransform::new(wide)),
+        ]);
+        // One stroke in the middle of the screen on the first page.
+        let mut stroke = rm(0);
+        stroke.truncate(stroke.len() - 4);
+        for n in &[1u32, 2, 0, 0] {
+            stroke.extend_from_slice(&n.to_le_bytes());
+        }
+        stroke.extend_from_slice(&2f32.to_le_bytes());
+        stroke.extend_from_slice(&0u32.to_le_bytes());
+        stroke.extend_from_slice(&1u32.to_le_bytes());
+        for v in &[702f32, 936.0, 0.0, 0.0, 2.0, 1.0] {
+            stroke.extend_from_slice(&v.to_le_bytes());
+        }
+        let content = serde_json::json!({
+            "fileType": "pdf",
+            "pages": [page_id(0), page_id(1)],
+        });
+        let files = vec![
+            (format!("{}.content", id), content.to_string().into_bytes()),
+            (format!("{}.pdf", id), original),
+            (format!("{}/{}.rm", id, page_id(0)), stroke),
+            (
+                format!("{}/{}-metadata.json", id, page_id(0)),
+                br#"{"layers": [{"name": "Margin notes"}]}"#.to_vec(),
+            ),
+        ];
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(name, Default::default()).unwrap();
+            zip.write_all(&data).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+
+        let svgs = render_ink_only(id, &zip).unwrap();
+        assert_eq!(svgs.len(), 2);
+        assert!(svgs[0].contains("viewBox=\"0 0 595 842\""));
+        assert!(svgs[0].contains("<g id=\"Margin notes\">"));
+        assert!(svgs[0].contains("points=\"297.50,421.00\""));
+        assert!(svgs[1].contains("viewBox=\"0 0 842 595\""));
+        assert!(!svgs[1].contains("<g"));
+
+        let inked = render_ink_pdf(id, &zip).unwrap();
+        assert_eq!(pdf::page_boxes(&inked), [a4, wide]);
+    }
+
+    fn read(zip: &[u8], name: &str) -> String {
+        let mut za = zip::ZipArchive::new(io::Cursor::new(zip)).unwrap();
+        let mut data = String::new();
+        za.by_name(name).unwrap().read_to_string(&mut data).unwrap();
+        data
+    }
+
+    // Checks that `zip` holds the pages of the fixture at `expected`, in
+    // that order, with every file of each page kept together.
+    fn check(zip: &[u8], expected: &[usize], by_id: bool) {
+        let id = doc_id();
+        let pages = list_pages(id, zip).unwrap();
+        assert_eq!(pages.len(), expected.len(), "{:?}", expected);
+        for (new, (old, page)) in expected.iter().zip(&pages).enumerate() {
+            let key = if by_id {
+                page_id(*old)
+            } else {
+                new.to_string()
+            };
+            assert_eq!(page.id, Some(page_id(*old)).filter(|_| by_id));
+            assert_eq!(page.template, Some(format!("T{}", old)));
+            assert_eq!(page.stroke_count, Some(old + 1));
+            let meta = read(zip, &format!("{}/{}-metadata.json", id, key));
+            assert_eq!(meta, format!("meta{}", old));
+            let thumb = read(zip, &format!("{}.thumbnails/{}.jpg", id, key));
+            assert_eq!(thumb, format!("thumb{}", old));
+        }
+        let content: serde_json::Value =
+            serde_json::from_str(&read(zip, &format!("{}.content", id)))
+                .unwrap();
+        assert_eq!(content["pageCount"], expected.len());
+        assert_eq!(content["fileType"], "notebook");
+        // Nothing is left behind of deleted pages.
+        let za = zip::ZipArchive::new(io::Cursor::new(zip)).unwrap();
+        assert_eq!(za.len(), 3 + 3 * expected.len());
+    }
+
+    // Every order of every non-empty selection of `n` pages.
+    fn orders(n: usize) -> Vec<Vec<usize>> {
+        let mut orders = vec![];
+        let mut stack: Vec<Vec<usize>> = vec![vec![]];
+        while let Some(order) = stack.pop() {
+            if !order.is_empty() {
+                orders.push(order.clone());
+            }
+            for i in (0..n).filter(|i| !order.contains(i)) {
+                let mut longer = order.clone();
+                longer.push(i);
+                stack.push(longer);
+            }
+        }
+        orders
+    }
+
+    #[test]
+    fn every_order() {
+        let orders = orders(3);
+        assert_eq!(orders.len(), 15);
+        for by_id in [true, false] {
+            let zip = fixture(by_id);
+            check(&zip, &[0, 1, 2], by_id);
+            for order in &orders {
+                let rearranged = rearrange_pages(doc_id(), &zip, order)
+                    .unwrap_or_else(|e| panic!("{:?}: {}", order, e));
+                check(&rearranged, order, by_id);
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_rearranging() {
+        for by_id in [true, false] {
+            let zip = fixture(by_id);
+            let zip = rearrange_pages(doc_id(), &zip, &[2, 0, 1]).unwrap();
+            let zip = rearrange_pages(doc_id(), &zip, &[2, 0]).unwrap();
+            // Now pages 1 and 2 of the fixture, in that order.
+            check(&zip, &[1, 2], by_id);
+        }
+    }
+
+    #[test]
+    fn refusals() {
+        let zip = fixture(true);
+        let reason = |zip: &[u8], order: &[usize]| match rearrange_pages(
+            doc_id(),
+            zip,
+            order,
+        ) {
+            Err(Error::InvalidArchive { reason }) => reason,
+            other => panic!("{:?}", other.map(|_| ())),
+        };
+        assert!(matches!(
+            rearrange_pages(doc_id(), &zip, &[0, 3]),
+            Err(Error::NoSuchPage {
+                index: 3,
+                page_count: 3
+            })
+        ));
+        assert!(reason(&zip, &[1, 0, 1]).contains("page 2 is given twice"));
+        assert!(reason(&zip, &[]).contains("at least one page"));
+
+        let with_content = |content: serde_json::Value, pagedata: &[u8]| {
+            let id = doc_id();
+            let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+            zip.start_file(format!("{}.content", id), Default::default())
+                .unwrap();
+            zip.write_all(content.to_string().as_bytes()).unwrap();
+            zip.start_file(format!("{}.pagedata", id), Default::default())
+                .unwrap();
+            zip.write_all(pagedata).unwrap();
+            zip.finish().unwrap().into_inner()
+        };
+        let pdf = with_content(
+            serde_json::json!({"fileType": "pdf", "pageCount": 2}),
+            b"Blank\nBlank\n",
+        );
+        assert!(reason(&pdf, &[1, 0]).contains("pdf"));
+        let newer = with_content(
+            serde_json::json!({"fileType": "notebook", "cPages": {}}),
+            b"",
+        );
+        assert!(reason(&newer, &[0]).contains("newer format"));
+        let short = with_content(
+            serde_json::json!({"fileType": "notebook", "pageCount": 2}),
+            b"Blank\n",
+        );
+        assert!(reason(&short, &[1, 0]).contains("pagedata has 1 pages"));
+    }
+
+    #[test]
+    fn listing() {
+        let id = doc_id();
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        // No .content or .pagedata: pages are counted from their files.
+        zip.start_file(format!("{}/0.rm", id), Default::default())
+            .unwrap();
+        zip.write_all(b"reMarkable .lines file, version=7          ")
+            .unwrap();
+        zip.start_file(format!("{}/1.rm", id), Default::default())
+            .unwrap();
+        zip.write_all(&rm(2)).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        let pages = list_pages(id, &zip).unwrap();
+        let counts: Vec<Option<usize>> =
+            pages.iter().map(|p| p.stroke_count).collect();
+        assert_eq!(counts, vec![None, Some(2)]);
+        assert!(pages.iter().all(|p| p.id.is_none() && p.template.is_none()));
+    }
+
+    #[test]
+    fn bookmarks_from_firmware_3() {
+        let content = Content::parse(include_bytes!(
+            "../tests/fixtures/firmware3/notebook.content"
+        ))
+        .unwrap();
+        let found = bookmarks(&content);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            (found[0].index, &found[0].tags[..]),
+            (1, &["Todo".into()][..])
+        );
+        assert_eq!(
+            found[0].tagged.unwrap().to_rfc3339(),
+            "2024-04-05T19:34:38.901+00:00"
+        );
+
+        // Tags on one page are gathered, and those on pages no longer
+        // listed are left out.
+        let mut content = content;
+        content.other.insert(
+            "pageTags".to_string(),
+            serde_json::json!([
+                {"name": "Quote", "pageId": page_id(2), "timestamp": 2000},
+                {"name": "Todo", "pageId": page_id(0), "timestamp": 1000},
+                {"name": "Later", "pageId": page_id(2), "timestamp": 3000},
+                {"name": "Gone", "pageId": page_id(7), "timestamp": 4000},
+            ]),
+        );
+        let found = bookmarks(&content);
+        let pages: Vec<(usize, Vec<String>, i64)> = found
+            .iter()
+            .map(|b| {
+                (
+                    b.index,
+                    b.tags.clone(),
+                    b.tagged.unwrap().timestamp_millis(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            pages,
+            vec![
+                (0, vec!["Todo".into()], 1000),
+                (2, vec!["Quote".into(), "Later".into()], 3000),
+            ]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! A ready-made `Metrics` for services scraped by Prometheus, which keeps
+//! counts of requests and renders them in Prometheus's text format, to be
+//! served from wherever the service serves its own metrics.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::metrics::{Metrics, Operation, Outcome};
+
+/// The upper bounds, in seconds, of the buckets request durations are
+/// counted in. Blob transfers can take minutes.
+const BUCKETS: &[f64] =
+    &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Default)]
+struct Series {
+    requests: u64,
+    bytes: u64,
+    seconds: f64,
+    // Counts per bucket, each not including the ones before.
+    buckets: [u64; BUCKETS.len()],
+}
+
+/// Counts requests by operation and outcome, as:
+///
+/// - `remarkable_cloud_requests_total`, a counter,
+/// - `remarkable_cloud_transferred_bytes_total`, a counter,
+/// - `remarkable_cloud_request_duration_seconds`, a histogram,
+///
+/// each labelled with `operation` and `status`, the HTTP status or
+/// `failed`.
+#[derive(Default)]
+pub struct PrometheusMetrics {
+    series: Mutex<BTreeMap<(Operation, Outcome), Series>>,
+}
+
+impl PrometheusMetrics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Everything counted so far, in Prometheus's text exposition format.
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+        let labels = |(op, status): &(Operation, Outcome)| {
+            format!("operation=\"{}\",status=\"{}\"", op, status)
+        };
+        out.push_str(
+            "# HELP remarkable_cloud_requests_total Requests made to the cloud.\n\
+             # TYPE remarkable_cloud_requests_total counter\n",
+        );
+        for (key, s) in series.iter() {
+            writeln!(
+                out,
+                "remarkable_cloud_requests_total{{{}}} {}",
+                labels(key),
+                s.requests
+            )
+            .unwrap();
+        }
+        out.push_str(
+            "# HELP remarkable_cloud_transferred_bytes_total Bytes sent and received in requests to the cloud.\n\
+             # TYPE remarkable_cloud_transferred_bytes_total counter\n",
+        );
+        for (key, s) in series.iter() {
+            writeln!(
+                out,
+                "remarkable_cloud_transferred_bytes_total{{{}}} {}",
+                labels(key),
+                s.bytes
+            )
+            .unwrap();
+        }
+        out.push_str(
+            "# HELP remarkable_cloud_request_duration_seconds How long requests to the cloud took.\n\
+             # TYPE remarkable_cloud_request_duration_seconds histogram\n",
+        );
+        let name = "remarkable_cloud_request_duration_seconds";
+        for (key, s) in series.iter() {
+            let labels = labels(key);
+            let mut cumulative = 0;
+            for (le, count) in BUCKETS.iter().zip(&s.buckets) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "{}_bucket{{{},le=\"{}\"}} {}",
+                    name, labels, le, cumulative
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "{}_bucket{{{},le=\"+Inf\"}} {}",
+                name, labels, s.requests
+            )
+            .unwrap();
+            writeln!(out, "{}_sum{{{}}} {}", name, labels, s.seconds).unwrap();
+            writeln!(out, "{}_count{{{}}} {}", name, labels, s.requests)
+                .unwrap();
+        }
+        out
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn record_request(
+        &self,
+        op: Operation,
+        status: Outcome,
+        duration: Duration,
+        bytes: u64,
+    ) {
+        let mut series = self.series.lock().unwrap();
+        let s = series.entry((op, status)).or_default();
+        let seconds = duration.as_secs_f64();
+        s.requests += 1;
+        s.bytes += bytes;
+        s.seconds += seconds;
+        if let Some(i) = BUCKETS.iter().position(|le| seconds <= *le) {
+            s.buckets[i] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rendered() {
+        let metrics = PrometheusMetrics::new();
+        let ms = Duration::from_millis;
+        metrics.record_request(
+            Operation::Listing,
+            Outcome::Status(200),
+            ms(250),
+            1000,
+        );
+        metrics.record_request(
+            Operation::Listing,
+            Outcome::Status(200),
+            ms(500),
+            500,
+        );
+        metrics.record_request(
+            Operation::UploadBlob,
+            Outcome::Failed,
+            Duration::from_secs(90),
+            0,
+        );
+        let rendered = metrics.render();
+        for line in &[
+            "remarkable_cloud_requests_total{operation=\"listing\",status=\"200\"} 2",
+            "remarkable_cloud_requests_total{operation=\"upload_blob\",status=\"failed\"} 1",
+            "remarkable_cloud_transferred_bytes_total{operation=\"listing\",status=\"200\"} 1500",
+            "remarkable_cloud_request_duration_seconds_bucket{operation=\"listing\",status=\"200\",le=\"0.1\"} 0",
+            "remarkable_cloud_request_duration_seconds_bucket{operation=\"listing\",status=\"200\",le=\"0.25\"} 1",
+            "remarkable_cloud_request_duration_seconds_bucket{operation=\"listing\",status=\"200\",le=\"0.5\"} 2",
+            "remarkable_cloud_request_duration_seconds_bucket{operation=\"listing\",status=\"200\",le=\"+Inf\"} 2",
+            "remarkable_cloud_request_duration_seconds_sum{operation=\"listing\",status=\"200\"} 0.75",
+            "remarkable_cloud_request_duration_seconds_bucket{operation=\"upload_blob\",status=\"failed\",le=\"60\"} 0",
+            "remarkable_cloud_request_duration_seconds_bucket{operation=\"upload_blob\",status=\"failed\",le=\"+Inf\"} 1",
+            "remarkable_cloud_request_duration_seconds_count{operation=\"upload_blob\",status=\"failed\"} 1",
+        ] {
+            assert!(rendered.lines().any(|l| l == *line), "{}", line);
+        }
+        assert!(PrometheusMetrics::new()
+            .render()
+            .lines()
+            .all(|l| l.starts_with('#')));
+    }
+}
@@ -0,0 +1,163 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::result;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::ready;
+use futures_util::stream::Stream;
+
+struct Bucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+/// A token bucket limiting the combined throughput of every stream it is
+/// shared by.
+///
+/// Up to one second's worth of bytes may be transferred in a burst after the
+/// limiter has been idle. Cloning a `RateLimiter` produces a handle to the same
+/// bucket.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let rate = bytes_per_sec.max(1) as f64;
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate,
+                capacity: rate,
+                tokens: rate,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bucket.lock().unwrap().rate as u64
+    }
+
+    /// Accounts for `n` bytes having been transferred, returning how long the
+    /// caller should wait before transferring any more.
+    pub fn take(&self, n: usize) -> Duration {
+        self.take_at(n, Instant::now())
+    }
+
+    fn take_at(&self, n: usize, now: Instant) -> Duration {
+        let mut b = self.bucket.lock().unwrap();
+        let elapsed = now.saturating_duration_since(b.last).as_secs_f64();
+        b.last = now.max(b.last);
+        b.tokens = (b.tokens + elapsed * b.rate).min(b.capacity);
+        // Tokens are allowed to go negative so that chunks larger than the
+        // bucket can still pass; the debt is paid off by waiting.
+        b.tokens -= n as f64;
+        if b.tokens < 0.0 {
+            Duration::from_secs_f64(-b.tokens / b.rate)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+/// Wraps a stream of byte chunks so that it is throttled by a
+/// [`RateLimiter`].
+pub struct RateLimitedStream<S> {
+    inner: S,
+    limiter: RateLimiter,
+    delay: Option<Pin<Box<tokio::time::Delay>>>,
+}
+
+impl<S> RateLimitedStream<S> {
+    pub fn new(inner: S, limiter: RateLimiter) -> Self {
+        RateLimitedStream {
+            inner,
+            limiter,
+            delay: None,
+        }
+    }
+}
+
+impl<S, B, E> Stream for RateLimitedStream<S>
+where
+    S: Stream<Item = result::Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    type Item = S::Item;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if let Some(delay) = self.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        let item = ready!(Pin::new(&mut self.inner).poll_next(cx));
+        if let Some(Ok(chunk)) = &item {
+            let wait = self.limiter.take(chunk.as_ref().len());
+            if wait > Duration::from_secs(0) {
+                self.delay = Some(Box::pin(tokio::time::delay_for(wait)));
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream::{self, StreamExt};
+
+    use super::*;
+
+    #[test]
+    fn burst_then_throttle() {
+        let limiter = RateLimiter::new(1000);
+        let t0 = Instant::now();
+        // A full bucket lets one second's worth through immediately.
+        assert_eq!(limiter.take_at(1000, t0), Duration::from_secs(0));
+        // After which each byte costs a millisecond.
+        assert_eq!(limiter.take_at(500, t0), Duration::from_millis(500));
+        let t1 = t0 + Duration::from_millis(500);
+        assert_eq!(limiter.take_at(500, t1), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn idle_refill_is_capped() {
+        let limiter = RateLimiter::new(1000);
+        let t0 = Instant::now();
+        limiter.take_at(1000, t0);
+        // However long the limiter sits idle, the burst stays one second.
+        let t1 = t0 + Duration::from_secs(60);
+        assert_eq!(limiter.take_at(2000, t1), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn shared_between_clones() {
+        let limiter = RateLimiter::new(1000);
+        let other = limiter.clone();
+        let t0 = Instant::now();
+        limiter.take_at(1000, t0);
+        assert_eq!(other.take_at(1000, t0), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn stream_is_throttled() {
+        let limiter = RateLimiter::new(10_000);
+        let chunks = (0..10).map(|_| Ok::<_, ()>(vec![0u8; 2000]));
+        let start = Instant::now();
+        let total: usize =
+            RateLimitedStream::new(stream::iter(chunks), limiter)
+                .map(|c| c.unwrap().len())
+                .fold(0, |acc, n| async move { acc + n })
+                .await;
+        assert_eq!(total, 20_000);
+        // 10k of burst, then 10k at 10k/s.
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }
+}
@@ -0,0 +1,264 @@
+use std::result;
+
+use serde::ser::Serializer;
+use uuid::Uuid;
+
+use crate::client::WireDialect;
+use crate::clock;
+use crate::documents::{DocType, Document};
+
+/// The `Parent` the cloud stores for documents in the trash.
+pub(crate) const TRASH_PARENT: &str = "trash";
+
+/// Where a document lives.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Parent {
+    Root,
+    Folder(Uuid),
+    Trash,
+}
+
+impl From<Option<Uuid>> for Parent {
+    fn from(parent: Option<Uuid>) -> Self {
+        match parent {
+            Some(id) => Parent::Folder(id),
+            None => Parent::Root,
+        }
+    }
+}
+
+impl serde::Serialize for Parent {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Parent::Root => serializer.serialize_str(""),
+            Parent::Folder(id) => serializer.serialize_str(&id.to_string()),
+            Parent::Trash => serializer.serialize_str(TRASH_PARENT),
+        }
+    }
+}
+
+/// Asks the cloud for somewhere to put a new version of a document's blob.
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UploadRequest {
+    #[serde(rename = "ID")]
+    pub id: Uuid,
+    #[serde(rename = "Type")]
+    pub doc_type: DocType,
+    #[serde(rename = "Version")]
+    pub version: u64,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UploadResponse {
+    #[serde(rename = "ID")]
+    pub id: Uuid,
+    #[serde(rename = "Version")]
+    pub version: u64,
+    #[serde(rename = "Message")]
+    pub message: String,
+    #[serde(rename = "Success")]
+    pub success: bool,
+    #[serde(rename = "BlobURLPut", default)]
+    pub blob_url_put: String,
+}
+
+/// Sets a document's metadata, creating it if need be.
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UpdateStatusRequest {
+    #[serde(rename = "ID")]
+    pub id: Uuid,
+    #[serde(rename = "Parent")]
+    pub parent: Parent,
+    #[serde(rename = "VissibleName")]
+    pub visible_name: String,
+    #[serde(rename = "Type")]
+    pub doc_type: DocType,
+    #[serde(rename = "Version")]
+    pub version: u64,
+    #[serde(rename = "ModifiedClient")]
+    pub modified_client: chrono::DateTime<chrono::Utc>,
+    #[serde(rename = "Bookmarked")]
+    pub bookmarked: bool,
+    #[serde(rename = "CurrentPage")]
+    pub current_page: i32,
+}
+
+impl UpdateStatusRequest {
+    /// Metadata for a document whose blob has just been uploaded, at
+    /// `modified_client`.
+    pub fn after_upload(
+        id: Uuid,
+        version: u64,
+        parent: Option<Uuid>,
+        visible_name: &str,
+        doc_type: DocType,
+        modified_client: chrono::DateTime<chrono::Utc>,
+    ) -> Self {
+        UpdateStatusRequest {
+            id,
+            parent: parent.into(),
+            visible_name: visible_name.to_string(),
+            doc_type,
+            version,
+            modified_client,
+            bookmarked: false,
+            current_page: 0,
+        }
+    }
+
+    pub(crate) fn to_json(&self, dialect: WireDialect) -> serde_json::Value {
+        let mut v = serde_json::to_value(self).unwrap();
+        let field = dialect.visible_name_field();
+        if let Some(map) = v.as_object_mut() {
+            if let Some(name) = map.remove("VissibleName") {
+                map.insert(field.to_string(), name);
+            }
+        }
+        v
+    }
+}
+
+/// Changes to a document's metadata for `Client::modify_metadata`. Fields
+/// left as `None` keep the value the cloud has for them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataPatch {
+    pub parent: Option<Parent>,
+    pub visible_name: Option<String>,
+    pub bookmarked: Option<bool>,
+    pub current_page: Option<i32>,
+}
+
+impl MetadataPatch {
+    /// The values `doc`, which is in `parent`, has for the fields the patch
+    /// sets.
+    pub(crate) fn current(&self, doc: &Document, parent: Parent) -> Self {
+        MetadataPatch {
+            parent: self.parent.map(|_| parent),
+            visible_name: self
+                .visible_name
+                .as_ref()
+                .map(|_| doc.visible_name.to_string()),
+            bookmarked: self.bookmarked.map(|_| doc.bookmarked),
+            current_page: self.current_page.map(|_| doc.current_page),
+        }
+    }
+
+    /// The next version of `doc`, which is in `parent`, with the patch
+    /// applied at `now`, or when `doc` was last modified if that's later.
+    pub(crate) fn apply(
+        self,
+        doc: &Document,
+        parent: Parent,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> UpdateStatusRequest {
+        UpdateStatusRequest {
+            id: doc.id,
+            parent: self.parent.unwrap_or(parent),
+            visible_name: self
+                .visible_name
+                .unwrap_or_else(|| doc.visible_name.to_string()),
+            doc_type: doc.doc_type.clone(),
+            version: doc.version + 1,
+            modified_client: clock::stamp(now, doc.modified_client),
+            bookmarked: self.bookmarked.unwrap_or(doc.bookmarked),
+            current_page: self.current_page.unwrap_or(doc.current_page),
+        }
+    }
+}
+
+/// What `Client::modify_metadata` changed: the fields the patch set, as
+/// they were and as they are now.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetadataChange {
+    pub id: Uuid,
+    pub before: MetadataPatch,
+    pub after: MetadataPatch,
+    /// The version the document is at after the change.
+    pub version: u64,
+}
+
+/// What became of one change in `Client::update_metadata_bulk`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    Updated(MetadataChange),
+    /// The cloud refused the change, or couldn't be asked, with why.
+    Failed(String),
+}
+
+/// Trades a one-time code from my.remarkable.com for a device token.
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterRequest {
+    pub code: String,
+    /// The kind of device registering, from those the cloud knows.
+    pub device_desc: String,
+    /// Any ID for this device, by which it's listed among the account's
+    /// devices.
+    #[serde(rename = "deviceID")]
+    pub device_id: Uuid,
+}
+
+/// Asks the cloud to delete a document outright, rather than move it to the
+/// trash. The version must be the document's current one.
+#[derive(serde::Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DeleteRequest {
+    #[serde(rename = "ID")]
+    pub id: Uuid,
+    #[serde(rename = "Version")]
+    pub version: u64,
+}
+
+/// The per-document result of an update-status or delete request.
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StatusResponse {
+    #[serde(rename = "ID")]
+    pub id: Uuid,
+    #[serde(rename = "Version")]
+    pub version: u64,
+    #[serde(rename = "Message")]
+    pub message: String,
+    #[serde(rename = "Success")]
+    pub success: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_status_dialects() {
+        let parent = Uuid::new_v4();
+        let req = UpdateStatusRequest::after_upload(
+            Uuid::new_v4(),
+            2,
+            Some(parent),
+            "Dune",
+            DocType::Document,
+            chrono::Utc::now(),
+        );
+        let official = req.to_json(WireDialect::Official);
+        assert_eq!(official["VissibleName"], "Dune");
+        assert!(official.get("VisibleName").is_none());
+        assert_eq!(official["Parent"], parent.to_string());
+        let fake = req.to_json(WireDialect::Rmfakecloud);
+        assert_eq!(fake["VisibleName"], "Dune");
+        assert!(fake.get("VissibleName").is_none());
+
+        let root = UpdateStatusRequest::after_upload(
+            Uuid::new_v4(),
+            1,
+            None,
+            "Top",
+            DocType::Collection,
+            chrono::Utc::now(),
+        );
+        assert_eq!(root.to_json(WireDialect::Official)["Parent"], "");
+
+        let mut trashed = root.clone();
+        trashed.parent = Parent::Trash;
+        assert_eq!(trashed.to_json(WireDialect::Official)["Parent"], "trash");
+    }
+}
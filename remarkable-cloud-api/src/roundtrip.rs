@@ -0,0 +1,164 @@
+//! Checks that rewriting an archive keeps everything in it this crate
+//! doesn't model. Firmware adds fields as it goes, such as folder and page
+//! tags in `.content` or `source` and `new` in `.metadata`, and a rewrite
+//! that dropped them would quietly take them off the tablet at its next
+//! sync.
+
+use std::io::{self, Read, Write};
+
+use uuid::Uuid;
+
+use crate::details::with_pinned;
+use crate::documents::{Document, Documents};
+use crate::pages::rearrange_pages;
+
+const CONTENT: &[u8] =
+    include_bytes!("../tests/fixtures/firmware3/notebook.content");
+const METADATA: &[u8] =
+    include_bytes!("../tests/fixtures/firmware3/notebook.metadata");
+const PAGEDATA: &[u8] =
+    include_bytes!("../tests/fixtures/firmware3/notebook.pagedata");
+const PAGE_METADATA: &[u8] =
+    include_bytes!("../tests/fixtures/firmware3/page-metadata.json");
+
+fn page_ids() -> Vec<String> {
+    (0..3)
+        .map(|i| Uuid::from_u128(100 + i).to_string())
+        .collect()
+}
+
+// The listing entry of a notebook, and its archive as firmware 3.x writes
+// it.
+fn notebook() -> (Document, Vec<u8>) {
+    let docs: Documents = serde_json::from_str(include_str!(
+        "../tests/fixtures/listing_official.json"
+    ))
+    .unwrap();
+    let mut doc = docs.iter().next().cloned().unwrap();
+    doc.id = Uuid::from_u128(1);
+    let mut files = vec![
+        (format!("{}.content", doc.id), CONTENT.to_vec()),
+        (format!("{}.metadata", doc.id), METADATA.to_vec()),
+        (format!("{}.pagedata", doc.id), PAGEDATA.to_vec()),
+    ];
+    for (i, page) in page_ids().iter().enumerate() {
+        files.push((format!("{}/{}.rm", doc.id, page), vec![i as u8]));
+        files.push((
+            format!("{}/{}-metadata.json", doc.id, page),
+            PAGE_METADATA.to_vec(),
+        ));
+    }
+    let mut zip = zip::ZipWriter::new(io::Cursor::new(vec![]));
+    for (name, data) in files {
+        zip.start_file(name, Default::default()).unwrap();
+        zip.write_all(&data).unwrap();
+    }
+    (doc, zip.finish().unwrap().into_inner())
+}
+
+fn read(zip: &[u8], name: &str) -> Vec<u8> {
+    let mut za = zip::ZipArchive::new(io::Cursor::new(zip)).unwrap();
+    let mut data = vec![];
+    za.by_name(name).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+// The fields of the JSON object `data` other than `modeled`, in order, each
+// with its value written out compactly, so that a number written
+// differently or a key moved shows up as a difference.
+fn unmodeled(data: &[u8], modeled: &[&str]) -> Vec<(String, String)> {
+    let object: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(data).unwrap();
+    object
+        .iter()
+        .filter(|(key, _)| !modeled.contains(&key.as_str()))
+        .map(|(key, value)| {
+            (key.clone(), serde_json::to_string(value).unwrap())
+        })
+        .collect()
+}
+
+// Checks that `zip` has the fixture's pages in `order`, and that nothing
+// the rewrites don't touch has changed.
+fn check(doc: &Document, zip: &[u8], order: &[usize]) {
+    let content = read(zip, &format!("{}.content", doc.id));
+    let content_modeled = ["fileType", "pageCount", "pages"];
+    assert_eq!(
+        unmodeled(&content, &content_modeled),
+        unmodeled(CONTENT, &content_modeled)
+    );
+    let ids = page_ids();
+    let content: serde_json::Value = serde_json::from_slice(&content).unwrap();
+    let pages: Vec<&String> = order.iter().map(|i| &ids[*i]).collect();
+    assert_eq!(content["pages"], serde_json::json!(pages));
+    assert_eq!(content["pageCount"], order.len());
+    assert_eq!(content["fileType"], "notebook");
+
+    let metadata = read(zip, &format!("{}.metadata", doc.id));
+    let metadata_modeled = [
+        "visibleName",
+        "type",
+        "parent",
+        "lastModified",
+        "version",
+        "pinned",
+        "synced",
+        "modified",
+        "metadatamodified",
+    ];
+    assert_eq!(
+        unmodeled(&metadata, &metadata_modeled),
+        unmodeled(METADATA, &metadata_modeled)
+    );
+    // Of the fields that are modeled, only those the rewrites set change.
+    let mut metadata: serde_json::Value =
+        serde_json::from_slice(&metadata).unwrap();
+    let mut original: serde_json::Value =
+        serde_json::from_slice(METADATA).unwrap();
+    for set in &["pinned", "version"] {
+        metadata.as_object_mut().unwrap().remove(*set);
+        original.as_object_mut().unwrap().remove(*set);
+    }
+    assert_eq!(metadata, original);
+
+    let templates = ["Blank", "P Lines medium", "P Grid small"];
+    let pagedata: String = order
+        .iter()
+        .map(|i| format!("{}\n", templates[*i]))
+        .collect();
+    let written = read(zip, &format!("{}.pagedata", doc.id));
+    assert_eq!(String::from_utf8(written).unwrap(), pagedata);
+
+    for (i, page) in order.iter().map(|i| (*i, &ids[*i])) {
+        assert_eq!(read(zip, &format!("{}/{}.rm", doc.id, page)), [i as u8]);
+        let name = format!("{}/{}-metadata.json", doc.id, page);
+        assert_eq!(read(zip, &name), PAGE_METADATA);
+    }
+}
+
+#[test]
+fn unmodeled_fields_kept() {
+    let (doc, zip) = notebook();
+    check(&doc, &zip, &[0, 1, 2]);
+
+    let reordered = rearrange_pages(doc.id, &zip, &[2, 0, 1]).unwrap();
+    check(&doc, &reordered, &[2, 0, 1]);
+    // Untouched by a reorder, so kept byte for byte.
+    assert_eq!(read(&reordered, &format!("{}.metadata", doc.id)), METADATA);
+
+    let pinned = with_pinned(&doc, &reordered, true).unwrap();
+    check(&doc, &pinned, &[2, 0, 1]);
+    let metadata: serde_json::Value =
+        serde_json::from_slice(&read(&pinned, &format!("{}.metadata", doc.id)))
+            .unwrap();
+    assert_eq!(metadata["pinned"], true);
+    assert_eq!(metadata["version"], doc.version + 1);
+
+    let restored = rearrange_pages(doc.id, &pinned, &[1, 2, 0]).unwrap();
+    check(&doc, &restored, &[0, 1, 2]);
+    let content = read(&restored, &format!("{}.content", doc.id));
+    assert_eq!(
+        serde_json::from_slice::<serde_json::Value>(&content).unwrap(),
+        serde_json::from_slice::<serde_json::Value>(CONTENT).unwrap()
+    );
+}
@@ -0,0 +1,115 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use crate::client::ClientState;
+use crate::error::Result;
+
+/// The future returned by the methods of `StateStore`.
+pub type StateFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Somewhere a `ClientState` is kept between runs, such as a file or a
+/// secrets service. A `Client` holding a store saves its state there itself
+/// whenever it gets a new user token.
+///
+/// The methods return boxed futures so that a `Client` can hold any store;
+/// implement them with `Box::pin(async move { ... })`.
+pub trait StateStore: Send + Sync {
+    fn load(&self) -> StateFuture<'_, ClientState>;
+
+    fn save<'a>(&'a self, state: &'a ClientState) -> StateFuture<'a, ()>;
+}
+
+/// Keeps the state in a JSON file, as `ClientState::load_from_path` and
+/// `ClientState::save_to_path` do.
+pub struct FileStateStore {
+    path: PathBuf,
+}
+
+impl FileStateStore {
+    pub fn new(path: PathBuf) -> Self {
+        FileStateStore { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn load(&self) -> StateFuture<'_, ClientState> {
+        Box::pin(async move {
+            let mut state = ClientState::new();
+            state.load_from_path(&self.path)?;
+            Ok(state)
+        })
+    }
+
+    fn save<'a>(&'a self, state: &'a ClientState) -> StateFuture<'a, ()> {
+        Box::pin(async move { state.save_to_path(&self.path) })
+    }
+}
+
+/// Keeps the state in memory only, for tests and for clients whose
+/// credentials are handed to them afresh each time.
+#[derive(Default)]
+pub struct MemoryStateStore {
+    state: Mutex<ClientState>,
+}
+
+impl MemoryStateStore {
+    pub fn new(state: ClientState) -> Self {
+        MemoryStateStore {
+            state: Mutex::new(state),
+        }
+    }
+
+    /// The state last saved, or the one the store started with.
+    pub fn state(&self) -> ClientState {
+        self.state.lock().unwrap().clone()
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn load(&self) -> StateFuture<'_, ClientState> {
+        Box::pin(async move { Ok(self.state()) })
+    }
+
+    fn save<'a>(&'a self, state: &'a ClientState) -> StateFuture<'a, ()> {
+        Box::pin(async move {
+            *self.state.lock().unwrap() = state.clone();
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_store() {
+        let dir = std::env::temp_dir()
+            .join(format!("state-store-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = FileStateStore::new(dir.join("client_state.json"));
+        assert!(store.load().await.is_err());
+
+        let mut state = ClientState::new();
+        state.set_device_token("device".to_string());
+        store.save(&state).await.unwrap();
+        let loaded = store.load().await.unwrap();
+        let json = serde_json::to_value(&loaded).unwrap();
+        assert_eq!(json["device_token"], "device");
+        // Nothing is left over from writing it but its lock.
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["client_state.json", "client_state.json.lock"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
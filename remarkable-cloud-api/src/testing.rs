@@ -0,0 +1,2400 @@
+//! An in-process fake of the reMarkable cloud, for testing code built on this
+//! crate without credentials or network access.
+//!
+//! Only available with the `testing` feature.
+//!
+//! ```no_run
+//! # async fn example() -> remarkable_cloud_api::Result<()> {
+//! use remarkable_cloud_api::testing::FakeCloud;
+//!
+//! let cloud = FakeCloud::start().await;
+//! let books = cloud.add_folder("Books", None);
+//! cloud.add_document("Dune", Some(books), b"zip bytes".to_vec());
+//!
+//! let mut client = cloud.client();
+//! client.refresh_token().await?;
+//! let documents = client.get_documents().await?;
+//! assert_eq!(documents.len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::header::{HeaderMap, ETAG, IF_NONE_MATCH};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::client::{Client, ClientState, WireDialect};
+use crate::documents::{DocType, Document, Documents};
+use crate::requests::TRASH_PARENT;
+
+// A JSON web token for "auth0|fake-user", expiring at the start of 2100.
+const USER_TOKEN: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+                          eyJzdWIiOiJhdXRoMHxmYWtlLXVzZXIiLCJleHAiOjQxMDI0NDQ4MDB9.\
+                          ZmFrZS1zaWduYXR1cmU";
+
+/// The one-time code a [`FakeCloud`] trades for its device token.
+pub const ONE_TIME_CODE: &str = "fakecode";
+
+/// A document or folder held by a [`FakeCloud`].
+#[derive(Clone, Debug)]
+pub struct FakeDocument {
+    pub id: Uuid,
+    pub version: u64,
+    pub visible_name: String,
+    pub parent: Option<Uuid>,
+    /// Whether the document is in the trash, in which case `parent` is
+    /// ignored.
+    pub trashed: bool,
+    pub doc_type: DocType,
+    pub current_page: i32,
+    pub bookmarked: bool,
+    pub modified_client: chrono::DateTime<chrono::Utc>,
+    pub blob: Vec<u8>,
+}
+
+/// A request received by a [`FakeCloud`].
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+// An upload which has been requested but not yet had its status set.
+struct PendingUpload {
+    version: u64,
+    blob: Option<Vec<u8>>,
+}
+
+// A resumable upload session, holding the start of a pending upload's blob.
+struct Session {
+    id: Uuid,
+    version: u64,
+    received: Vec<u8>,
+}
+
+// A failure to be injected into the next request whose path starts with
+// `path`.
+struct Fault {
+    path: String,
+    handled: bool,
+}
+
+// Changes made to each document in listings before they're sent.
+type ListingRewrite = Box<dyn Fn(&mut serde_json::Value) + Send>;
+
+// Changes made to each blob uploaded before it's stored.
+type BlobRewrite = Box<dyn Fn(&mut Vec<u8>) + Send>;
+
+#[derive(Default)]
+struct State {
+    dialect: WireDialect,
+    documents: Vec<FakeDocument>,
+    pending: HashMap<Uuid, PendingUpload>,
+    requests: Vec<RecordedRequest>,
+    faults: Vec<Fault>,
+    delays: Vec<(String, Duration)>,
+    migrated: bool,
+    without_validators: bool,
+    broken: Vec<(String, StatusCode)>,
+    listing_rewrite: Option<ListingRewrite>,
+    blob_rewrite: Option<BlobRewrite>,
+    resumable: bool,
+    sessions: HashMap<String, Session>,
+}
+
+impl State {
+    fn current_version(&self, id: &Uuid) -> u64 {
+        self.documents
+            .iter()
+            .find(|d| d.id == *id)
+            .map_or(0, |d| d.version)
+    }
+
+    fn upload_request(
+        &mut self,
+        base: &str,
+        body: &[u8],
+    ) -> Option<Vec<serde_json::Value>> {
+        let requests: Vec<serde_json::Value> =
+            serde_json::from_slice(body).ok()?;
+        let mut responses = vec![];
+        for r in requests {
+            let id: Uuid = r["ID"].as_str()?.parse().ok()?;
+            let version = r["Version"].as_u64()?;
+            let ok = version == self.current_version(&id) + 1;
+            if ok {
+                self.pending.insert(
+                    id,
+                    PendingUpload {
+                        version,
+                        blob: None,
+                    },
+                );
+            }
+            responses.push(serde_json::json!({
+                "ID": id,
+                "Version": version,
+                "Message": if ok { "" } else { "wrong version" },
+                "Success": ok,
+                "BlobURLPut": if ok {
+                    format!("{}/upload/{}/{}", base, id, version)
+                } else {
+                    String::new()
+                },
+                "BlobURLPutExpires": chrono::Utc::now().to_rfc3339(),
+            }));
+        }
+        Some(responses)
+    }
+
+    fn update_status(&mut self, body: &[u8]) -> Option<Vec<serde_json::Value>> {
+        let requests: Vec<serde_json::Value> =
+            serde_json::from_slice(body).ok()?;
+        let mut responses = vec![];
+        for r in requests {
+            let id: Uuid = r["ID"].as_str()?.parse().ok()?;
+            let version = r["Version"].as_u64()?;
+            let name = r
+                .get("VissibleName")
+                .or_else(|| r.get("VisibleName"))?
+                .as_str()?;
+            // As the real cloud, anything but a folder id, "" for the top
+            // level or the trash is refused, "root" included.
+            let placed = match r["Parent"].as_str()? {
+                "" => Some((None, false)),
+                TRASH_PARENT => Some((None, true)),
+                p => p.parse().ok().map(|p| (Some(p), false)),
+            };
+            let modified_client = r["ModifiedClient"]
+                .as_str()?
+                .parse::<chrono::DateTime<chrono::Utc>>()
+                .ok()?;
+            let current = self.current_version(&id);
+            let blob = match self.pending.get(&id) {
+                Some(p) if p.version == version => p.blob.clone(),
+                _ => None,
+            };
+            // Metadata can be changed either by completing an upload or by
+            // bumping the version of an existing document.
+            let (parent, trashed) = match placed {
+                Some(placed) => placed,
+                None => {
+                    responses.push(serde_json::json!({
+                        "ID": id,
+                        "Version": current,
+                        "Message": "unknown parent",
+                        "Success": false,
+                    }));
+                    continue;
+                }
+            };
+            let ok = version == current + 1
+                && (blob.is_some() || (current > 0 && !self.pending_for(&id)));
+            if ok {
+                let blob = match blob {
+                    Some(b) => {
+                        self.pending.remove(&id);
+                        b
+                    }
+                    None => self
+                        .documents
+                        .iter()
+                        .find(|d| d.id == id)
+                        .map(|d| d.blob.clone())
+                        .unwrap_or_default(),
+                };
+                let doc = FakeDocument {
+                    id,
+                    version,
+                    visible_name: name.to_string(),
+                    parent,
+                    trashed,
+                    doc_type: r["Type"].as_str()?.to_string().into(),
+                    current_page: r["CurrentPage"].as_i64().unwrap_or(0) as i32,
+                    bookmarked: r["Bookmarked"].as_bool().unwrap_or(false),
+                    modified_client,
+                    blob,
+                };
+                self.documents.retain(|d| d.id != id);
+                self.documents.push(doc);
+            }
+            responses.push(serde_json::json!({
+                "ID": id,
+                "Version": if ok { version } else { current },
+                "Message": if ok { "" } else { "wrong version" },
+                "Success": ok,
+            }));
+        }
+        Some(responses)
+    }
+
+    fn delete(&mut self, body: &[u8]) -> Option<Vec<serde_json::Value>> {
+        let requests: Vec<serde_json::Value> =
+            serde_json::from_slice(body).ok()?;
+        let mut responses = vec![];
+        for r in requests {
+            let id: Uuid = r["ID"].as_str()?.parse().ok()?;
+            let version = r["Version"].as_u64()?;
+            let current = self.current_version(&id);
+            let ok = current > 0 && version == current;
+            if ok {
+                self.documents.retain(|d| d.id != id);
+            }
+            responses.push(serde_json::json!({
+                "ID": id,
+                "Version": current,
+                "Message": if ok { "" } else { "wrong version" },
+                "Success": ok,
+            }));
+        }
+        Some(responses)
+    }
+
+    // Starts a session for the upload of version `version` of `id`,
+    // returning its URL.
+    fn start_session(
+        &mut self,
+        base: &str,
+        id: &str,
+        version: &str,
+    ) -> Option<String> {
+        let id: Uuid = id.parse().ok()?;
+        let version: u64 = version.parse().ok()?;
+        self.pending.get(&id).filter(|p| p.version == version)?;
+        let token = Uuid::new_v4().to_string();
+        let url = format!("{}/session/{}", base, token);
+        self.sessions.insert(
+            token,
+            Session {
+                id,
+                version,
+                received: vec![],
+            },
+        );
+        Some(url)
+    }
+
+    // Takes a chunk, or a question of how much has arrived, for the
+    // session `token`, as Google Cloud Storage does.
+    fn put_chunk(
+        &mut self,
+        token: &str,
+        range: &str,
+        body: Vec<u8>,
+    ) -> Response<Body> {
+        let session = match self.sessions.get_mut(token) {
+            Some(session) => session,
+            None => return respond(StatusCode::NOT_FOUND, vec![]),
+        };
+        let range = range.strip_prefix("bytes ").unwrap_or_default();
+        let (span, total) = match range.split_once('/') {
+            Some((span, total)) => (span, total.parse::<usize>().ok()),
+            None => return respond(StatusCode::BAD_REQUEST, vec![]),
+        };
+        let total = match total {
+            Some(total) => total,
+            None => return respond(StatusCode::BAD_REQUEST, vec![]),
+        };
+        if span != "*" {
+            let start = span
+                .split_once('-')
+                .and_then(|(start, _)| start.parse::<usize>().ok());
+            match start {
+                // Bytes sent again are taken again.
+                Some(start) if start <= session.received.len() => {
+                    session.received.truncate(start);
+                    session.received.extend(body);
+                }
+                _ => return respond(StatusCode::BAD_REQUEST, vec![]),
+            }
+        }
+        // A finished session goes on saying so until it expires.
+        if session.received.len() >= total {
+            let mut blob = session.received.clone();
+            let (id, version) = (session.id, session.version);
+            if let Some(rewrite) = &self.blob_rewrite {
+                rewrite(&mut blob);
+            }
+            if let Some(p) =
+                self.pending.get_mut(&id).filter(|p| p.version == version)
+            {
+                p.blob = Some(blob);
+            }
+            return respond(StatusCode::OK, vec![]);
+        }
+        let mut response = respond(StatusCode::PERMANENT_REDIRECT, vec![]);
+        if !session.received.is_empty() {
+            let range = format!("bytes=0-{}", session.received.len() - 1);
+            response
+                .headers_mut()
+                .insert(hyper::header::RANGE, range.parse().unwrap());
+        }
+        response
+    }
+
+    fn pending_for(&self, id: &Uuid) -> bool {
+        self.pending.get(id).is_some_and(|p| p.blob.is_none())
+    }
+}
+
+pub struct FakeCloud {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl FakeCloud {
+    /// Starts serving on a free loopback port. Must be called from within a
+    /// tokio runtime, which the server runs on until the `FakeCloud` is
+    /// dropped.
+    pub async fn start() -> Self {
+        let state: Arc<Mutex<State>> = Default::default();
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    handle(state.clone(), req)
+                }))
+            }
+        });
+        let server =
+            hyper::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+                .serve(make_service);
+        let addr = server.local_addr();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(server.with_graceful_shutdown(async {
+            rx.await.ok();
+        }));
+        FakeCloud {
+            addr,
+            state,
+            shutdown: Some(tx),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Makes the server speak the given dialect in its responses.
+    pub fn set_dialect(&self, dialect: WireDialect) {
+        self.state.lock().unwrap().dialect = dialect;
+    }
+
+    /// Returns a client configured to talk to this server.
+    pub fn client(&self) -> Client {
+        let mut state = ClientState::new();
+        state.set_endpoint(self.url());
+        state.set_device_token("fake-device-token".to_string());
+        let mut client = Client::new(state, reqwest::Client::new());
+        client.set_user_token_url(format!(
+            "{}/token/json/2/user/new",
+            self.url()
+        ));
+        client.set_device_token_url(format!(
+            "{}/token/json/2/device/new",
+            self.url()
+        ));
+        client
+    }
+
+    pub fn insert(&self, doc: FakeDocument) {
+        let mut state = self.state.lock().unwrap();
+        state.documents.retain(|d| d.id != doc.id);
+        state.documents.push(doc);
+    }
+
+    fn add(
+        &self,
+        name: &str,
+        parent: Option<Uuid>,
+        doc_type: DocType,
+        blob: Vec<u8>,
+    ) -> Uuid {
+        let id = Uuid::new_v4();
+        self.insert(FakeDocument {
+            id,
+            version: 1,
+            visible_name: name.to_string(),
+            parent,
+            trashed: false,
+            doc_type,
+            current_page: 0,
+            bookmarked: false,
+            modified_client: chrono::Utc::now(),
+            blob,
+        });
+        id
+    }
+
+    pub fn add_folder(&self, name: &str, parent: Option<Uuid>) -> Uuid {
+        self.add(name, parent, DocType::Collection, vec![])
+    }
+
+    pub fn add_document(
+        &self,
+        name: &str,
+        parent: Option<Uuid>,
+        blob: Vec<u8>,
+    ) -> Uuid {
+        self.add(name, parent, DocType::Document, blob)
+    }
+
+    pub fn document(&self, id: &Uuid) -> Option<FakeDocument> {
+        let state = self.state.lock().unwrap();
+        state.documents.iter().find(|d| d.id == *id).cloned()
+    }
+
+    /// Applies `f` to the stored document, as if another client had changed
+    /// it.
+    pub fn modify<F>(&self, id: &Uuid, f: F)
+    where
+        F: FnOnce(&mut FakeDocument),
+    {
+        let mut state = self.state.lock().unwrap();
+        if let Some(d) = state.documents.iter_mut().find(|d| d.id == *id) {
+            f(d);
+        }
+    }
+
+    /// Makes the server answer as it does for accounts moved to the newer
+    /// sync service, refusing the legacy document API.
+    pub fn set_migrated(&self, migrated: bool) {
+        self.state.lock().unwrap().migrated = migrated;
+    }
+
+    /// Makes the server leave the `ETag` off full listings, and ignore
+    /// `If-None-Match`, as some servers do. It sends them by default.
+    pub fn set_validators(&self, validators: bool) {
+        self.state.lock().unwrap().without_validators = !validators;
+    }
+
+    /// Makes every request whose path starts with `path` fail with
+    /// `status`, as when an endpoint is withdrawn or a token revoked.
+    pub fn break_endpoint(&self, path: &str, status: u16) {
+        let status = StatusCode::from_u16(status).unwrap();
+        self.state
+            .lock()
+            .unwrap()
+            .broken
+            .push((path.to_string(), status));
+    }
+
+    /// Passes each document of a listing through `rewrite` before it's
+    /// sent, to imitate a cloud whose responses have changed shape.
+    pub fn set_listing_rewrite<F>(&self, rewrite: F)
+    where
+        F: Fn(&mut serde_json::Value) + Send + 'static,
+    {
+        self.state.lock().unwrap().listing_rewrite = Some(Box::new(rewrite));
+    }
+
+    /// Passes each blob uploaded through `rewrite` before it's stored, to
+    /// imitate a cloud which doesn't store what it was sent.
+    pub fn set_blob_rewrite<F>(&self, rewrite: F)
+    where
+        F: Fn(&mut Vec<u8>) + Send + 'static,
+    {
+        self.state.lock().unwrap().blob_rewrite = Some(Box::new(rewrite));
+    }
+
+    /// Makes the server start resumable upload sessions when asked, as
+    /// Google Cloud Storage does. It refuses by default, as the official
+    /// cloud's upload URLs are only signed for a single PUT.
+    pub fn set_resumable(&self, resumable: bool) {
+        self.state.lock().unwrap().resumable = resumable;
+    }
+
+    /// Forgets every resumable upload session, as happens when they expire.
+    pub fn expire_sessions(&self) {
+        self.state.lock().unwrap().sessions.clear();
+    }
+
+    /// Makes the next request whose path starts with `path` fail with a 503.
+    /// If `handled`, the request is carried out first and only the response
+    /// is lost, as when a connection drops at the wrong moment.
+    pub fn fail_next(&self, path: &str, handled: bool) {
+        self.state.lock().unwrap().faults.push(Fault {
+            path: path.to_string(),
+            handled,
+        });
+    }
+
+    /// Makes the next request whose path starts with `path` wait for
+    /// `delay` before being handled.
+    pub fn delay_next(&self, path: &str, delay: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .delays
+            .push((path.to_string(), delay));
+    }
+
+    /// Every request received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for FakeCloud {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            tx.send(()).ok();
+        }
+    }
+}
+
+fn document_json(
+    base: &str,
+    dialect: WireDialect,
+    d: &FakeDocument,
+    with_blob: bool,
+) -> serde_json::Value {
+    let mut v = serde_json::json!({
+        "ID": d.id,
+        "Version": d.version,
+        "Message": "",
+        "Success": true,
+        "BlobURLGet": "",
+        "BlobURLGetExpires": "0001-01-01T00:00:00Z",
+        "ModifiedClient": d.modified_client,
+        "Type": d.doc_type,
+        "CurrentPage": d.current_page,
+        "Bookmarked": d.bookmarked,
+        "Parent": if d.trashed {
+            TRASH_PARENT.to_string()
+        } else {
+            d.parent.map(|p| p.to_string()).unwrap_or_default()
+        },
+    });
+    v[dialect.visible_name_field()] = d.visible_name.clone().into();
+    if with_blob {
+        v["BlobURLGet"] = format!("{}/blob/{}", base, d.id).into();
+        v["BlobURLGetExpires"] = (chrono::Utc::now()
+            + chrono::Duration::hours(1))
+        .to_rfc3339()
+        .into();
+    }
+    v
+}
+
+fn parse_query(query: Option<&str>) -> HashMap<String, String> {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|kv| !kv.is_empty())
+        .map(|kv| match kv.find('=') {
+            Some(i) => (kv[..i].to_string(), kv[i + 1..].to_string()),
+            None => (kv.to_string(), String::new()),
+        })
+        .collect()
+}
+
+fn respond(status: StatusCode, body: Vec<u8>) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let base = format!(
+        "http://{}",
+        req.headers()
+            .get(hyper::header::HOST)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+    );
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let query = parse_query(req.uri().query());
+    let authorized = req
+        .headers()
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        == Some(&format!("Bearer {}", USER_TOKEN));
+    let headers = req.headers().clone();
+    let body = hyper::body::to_bytes(req.into_body())
+        .await
+        .map(|b| b.to_vec())
+        .unwrap_or_default();
+
+    let delay = {
+        let mut state = state.lock().unwrap();
+        let i = state.delays.iter().position(|(p, _)| path.starts_with(p));
+        i.map(|i| state.delays.remove(i).1)
+    };
+    if let Some(delay) = delay {
+        tokio::time::delay_for(delay).await;
+    }
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(RecordedRequest {
+        method: method.clone(),
+        path: path.clone(),
+        query: query.clone(),
+        headers: headers.clone(),
+        body,
+    });
+
+    let fault = state
+        .faults
+        .iter()
+        .position(|f| path.starts_with(&f.path))
+        .map(|i| state.faults.remove(i));
+    if fault.as_ref().is_some_and(|f| !f.handled) {
+        return Ok(respond(StatusCode::SERVICE_UNAVAILABLE, vec![]));
+    }
+    if let Some((_, status)) =
+        state.broken.iter().find(|(p, _)| path.starts_with(p))
+    {
+        return Ok(respond(*status, vec![]));
+    }
+
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let response = match (&method, segments.as_slice()) {
+        (&Method::POST, ["token", "json", "2", "user", "new"]) => {
+            respond(StatusCode::OK, USER_TOKEN.into())
+        }
+        (&Method::POST, ["token", "json", "2", "device", "new"]) => {
+            let body = &state.requests.last().unwrap().body;
+            let code = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|r| r["code"].as_str().map(str::to_string));
+            if code.as_deref() == Some(ONE_TIME_CODE) {
+                respond(StatusCode::OK, b"fake-device-token".to_vec())
+            } else {
+                respond(StatusCode::BAD_REQUEST, vec![])
+            }
+        }
+        (_, ["document-storage", ..]) if !authorized => {
+            respond(StatusCode::UNAUTHORIZED, vec![])
+        }
+        (_, ["document-storage", ..]) if state.migrated => respond(
+            StatusCode::BAD_REQUEST,
+            include_bytes!("../tests/fixtures/migrated_listing_400.json")
+                .to_vec(),
+        ),
+        (&Method::GET, ["document-storage", "json", "2", "docs"]) => {
+            let with_blob = matches!(
+                query.get("withBlob").map(String::as_str),
+                Some("1") | Some("true")
+            );
+            let wanted = query.get("doc");
+            let docs: Vec<serde_json::Value> = state
+                .documents
+                .iter()
+                .filter(|d| wanted.is_none_or(|w| *w == d.id.to_string()))
+                .map(|d| {
+                    let mut json =
+                        document_json(&base, state.dialect, d, with_blob);
+                    if let Some(rewrite) = &state.listing_rewrite {
+                        rewrite(&mut json);
+                    }
+                    json
+                })
+                .collect();
+            let body = serde_json::to_vec(&docs).unwrap();
+            if wanted.is_some() || state.without_validators {
+                respond(StatusCode::OK, body)
+            } else {
+                let etag = Sha256::digest(&body)[..8]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>();
+                let etag = format!("\"{}\"", etag);
+                let unchanged = headers
+                    .get(IF_NONE_MATCH)
+                    .is_some_and(|v| v.as_bytes() == etag.as_bytes());
+                let mut response = if unchanged {
+                    respond(StatusCode::NOT_MODIFIED, vec![])
+                } else {
+                    respond(StatusCode::OK, body)
+                };
+                response.headers_mut().insert(ETAG, etag.parse().unwrap());
+                response
+            }
+        }
+        (
+            &Method::PUT,
+            ["document-storage", "json", "2", "upload", "request"],
+        ) => {
+            let body = state.requests.last().unwrap().body.clone();
+            match state.upload_request(&base, &body) {
+                Some(r) => {
+                    respond(StatusCode::OK, serde_json::to_vec(&r).unwrap())
+                }
+                None => respond(StatusCode::BAD_REQUEST, vec![]),
+            }
+        }
+        (
+            &Method::PUT,
+            ["document-storage", "json", "2", "upload", "update-status"],
+        ) => {
+            let body = state.requests.last().unwrap().body.clone();
+            match state.update_status(&body) {
+                Some(r) => {
+                    respond(StatusCode::OK, serde_json::to_vec(&r).unwrap())
+                }
+                None => respond(StatusCode::BAD_REQUEST, vec![]),
+            }
+        }
+        (&Method::PUT, ["document-storage", "json", "2", "delete"]) => {
+            let body = state.requests.last().unwrap().body.clone();
+            match state.delete(&body) {
+                Some(r) => {
+                    respond(StatusCode::OK, serde_json::to_vec(&r).unwrap())
+                }
+                None => respond(StatusCode::BAD_REQUEST, vec![]),
+            }
+        }
+        (&Method::PUT, ["upload", id, version]) => {
+            let mut body = state.requests.last().unwrap().body.clone();
+            if let Some(rewrite) = &state.blob_rewrite {
+                rewrite(&mut body);
+            }
+            let pending = id.parse::<Uuid>().ok().and_then(|id| {
+                state
+                    .pending
+                    .get_mut(&id)
+                    .filter(|p| version.parse::<u64>().ok() == Some(p.version))
+            });
+            match pending {
+                Some(p) => {
+                    p.blob = Some(body);
+                    respond(StatusCode::OK, vec![])
+                }
+                None => respond(StatusCode::FORBIDDEN, vec![]),
+            }
+        }
+        (&Method::POST, ["upload", id, version])
+            if state.resumable
+                && headers
+                    .get("x-goog-resumable")
+                    .is_some_and(|v| v == "start") =>
+        {
+            match state.start_session(&base, id, version) {
+                Some(url) => {
+                    let mut response = respond(StatusCode::CREATED, vec![]);
+                    response
+                        .headers_mut()
+                        .insert(hyper::header::LOCATION, url.parse().unwrap());
+                    response
+                }
+                None => respond(StatusCode::FORBIDDEN, vec![]),
+            }
+        }
+        (&Method::POST, ["upload", _, _]) => {
+            respond(StatusCode::FORBIDDEN, vec![])
+        }
+        (&Method::PUT, ["session", token]) => {
+            let body = state.requests.last().unwrap().body.clone();
+            let range = headers
+                .get(hyper::header::CONTENT_RANGE)
+                .and_then(|r| r.to_str().ok())
+                .unwrap_or_default();
+            state.put_chunk(token, range, body)
+        }
+        (&Method::GET, ["blob", id]) => {
+            match state.documents.iter().find(|d| d.id.to_string() == *id) {
+                Some(d) => respond(StatusCode::OK, d.blob.clone()),
+                None => respond(StatusCode::NOT_FOUND, vec![]),
+            }
+        }
+        _ => respond(StatusCode::NOT_FOUND, vec![]),
+    };
+    if fault.is_some() {
+        return Ok(respond(StatusCode::SERVICE_UNAVAILABLE, vec![]));
+    }
+    Ok(response)
+}
+
+/// Asserts that two [`Documents`] hold the same documents, listing which ids
+/// and fields differ if not.
+#[macro_export]
+macro_rules! assert_documents_eq {
+    ($left:expr, $right:expr $(,)?) => {{
+        let differences =
+            $crate::testing::document_differences(&$left, &$right);
+        if !differences.is_empty() {
+            panic!(
+                "assertion failed: documents differ (left vs right):\n  {}",
+                differences.join("\n  ")
+            );
+        }
+    }};
+}
+
+fn field_differences(left: &Document, right: &Document) -> Vec<String> {
+    let mut differences = vec![];
+    macro_rules! compare {
+        ($($field:ident),*) => {
+            $(
+                if left.$field != right.$field {
+                    differences.push(format!(
+                        "{}: {:?} != {:?}",
+                        stringify!($field),
+                        left.$field,
+                        right.$field
+                    ));
+                }
+            )*
+        };
+    }
+    compare!(
+        version,
+        visible_name,
+        parent,
+        doc_type,
+        current_page,
+        bookmarked,
+        message,
+        modified_client,
+        blob_url_get,
+        blob_url_get_expires
+    );
+    differences
+}
+
+/// Describes how two listings differ, one line per differing document, in
+/// id order. Empty if they're equal.
+pub fn document_differences(
+    left: &Documents,
+    right: &Documents,
+) -> Vec<String> {
+    let mut ids: Vec<Uuid> =
+        left.iter().chain(right.iter()).map(|d| d.id).collect();
+    ids.sort();
+    ids.dedup();
+    ids.into_iter()
+        .filter_map(|id| match (left.get(&id), right.get(&id)) {
+            (Some(l), None) => {
+                Some(format!("{} ({:?}) only on the left", id, l.visible_name))
+            }
+            (None, Some(r)) => {
+                Some(format!("{} ({:?}) only on the right", id, r.visible_name))
+            }
+            (Some(l), Some(r)) => {
+                let fields = field_differences(l, r);
+                if fields.is_empty() {
+                    None
+                } else {
+                    Some(format!(
+                        "{} ({:?}) differs in {}",
+                        id,
+                        l.visible_name,
+                        fields.join(", ")
+                    ))
+                }
+            }
+            (None, None) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancellationToken;
+    use crate::capabilities::{Capability, Generation};
+    use crate::delete::DeleteOutcome;
+    use crate::details::PinnedSource;
+    use crate::error::Error;
+    use crate::requests::{
+        DeleteRequest, MetadataPatch, Parent, UpdateOutcome,
+        UpdateStatusRequest,
+    };
+    use crate::state_store::{MemoryStateStore, StateStore};
+    use crate::upload::{Upload, UploadStage};
+    use crate::ListingEntry;
+    use futures_util::StreamExt;
+    use remarkable_data_formats::lines::Page;
+
+    #[tokio::test]
+    async fn roundtrip() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let dune = cloud.add_document("Dune", Some(books), b"blob".to_vec());
+
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs.get(&dune).unwrap().parent, Some(books));
+        assert_eq!(&*docs.get(&books).unwrap().visible_name, "Books");
+
+        let blobdoc = client.get_document_by_id(&dune).await.unwrap();
+        assert_eq!(client.download_blob(&blobdoc).await.unwrap(), b"blob");
+    }
+
+    #[tokio::test]
+    async fn documents_diff() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let dune = cloud.add_document("Dune", Some(books), vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let before = client.get_documents().await.unwrap();
+        assert_documents_eq!(before, before.clone());
+
+        cloud.modify(&dune, |d| {
+            d.version = 2;
+            d.visible_name = "Dune Messiah".to_string();
+        });
+        let added = cloud.add_document("Emma", None, vec![]);
+        let after = client.get_documents().await.unwrap();
+        let differences = document_differences(&before, &after);
+        let mut expected = vec![
+            format!(
+                "{} (\"Dune\") differs in version: 1 != 2, visible_name: \
+                 \"Dune\" != \"Dune Messiah\"",
+                dune
+            ),
+            format!("{} (\"Emma\") only on the right", added),
+        ];
+        if added < dune {
+            expected.reverse();
+        }
+        assert_eq!(differences, expected);
+
+        let result = std::panic::catch_unwind(|| {
+            assert_documents_eq!(before, after);
+        });
+        let message = result.unwrap_err();
+        let message = message.downcast_ref::<String>().unwrap();
+        assert!(message.contains("documents differ"), "{}", message);
+        assert!(message.contains(&added.to_string()), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn refreshed_tokens_saved_to_store() {
+        let cloud = FakeCloud::start().await;
+        cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        let store = Arc::new(MemoryStateStore::default());
+        let shared: Arc<dyn StateStore> = store.clone();
+        client.set_state_store(Some(shared.clone()));
+        client.refresh_token().await.unwrap();
+        let saved = serde_json::to_value(store.state()).unwrap();
+        assert_eq!(saved["user_token"], USER_TOKEN);
+        assert_eq!(saved["device_token"], "fake-device-token");
+
+        // A client made from the store can carry on without refreshing.
+        let client = Client::from_state_store(shared, reqwest::Client::new())
+            .await
+            .unwrap();
+        assert_eq!(client.get_documents().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn registered_with_code() {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        let mut state = ClientState::new();
+        state.set_endpoint(cloud.url());
+        *client.state() = state;
+        let store = Arc::new(MemoryStateStore::default());
+        client.set_state_store(Some(store.clone()));
+        assert!(client.register("wrong").await.is_err());
+        assert!(client.diagnostics().device_token_fingerprint.is_none());
+
+        client.register(ONE_TIME_CODE).await.unwrap();
+        let saved = serde_json::to_value(store.state()).unwrap();
+        assert_eq!(saved["device_token"], "fake-device-token");
+        let request = cloud.requests().pop().unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["code"], ONE_TIME_CODE);
+        assert_eq!(body["deviceDesc"], "desktop-linux");
+        client.refresh_token().await.unwrap();
+        assert!(client.get_documents().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn schema_drift_collected() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        client.get_documents().await.unwrap();
+        assert!(client.take_schema_drift().is_none());
+
+        client.set_schema_diagnostics(true);
+        client.get_documents().await.unwrap();
+        let drift = client.take_schema_drift().unwrap();
+        assert_eq!(drift.entries, 1);
+        assert!(drift.is_empty(), "{:?}", drift);
+
+        cloud.set_listing_rewrite(|doc| {
+            let doc = doc.as_object_mut().unwrap();
+            doc.remove("Bookmarked");
+            doc.insert("Tags".to_string(), serde_json::json!([]));
+        });
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(docs.parse_warnings().len(), 1);
+        client.get_document_by_id(&dune).await.unwrap_err();
+        let drift = client.take_schema_drift().unwrap();
+        assert_eq!(drift.entries, 2);
+        assert_eq!(drift.unknown_fields["Tags"], 2);
+        assert_eq!(drift.missing_fields["Bookmarked"], 2);
+        assert!(client.take_schema_drift().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn broken_endpoints() {
+        let cloud = FakeCloud::start().await;
+        cloud.break_endpoint("/token/", 401);
+        let mut client = cloud.client();
+        match client.refresh_token().await.unwrap_err() {
+            Error::HttpError { source } => {
+                assert_eq!(source.status().unwrap().as_u16(), 401)
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn move_trash_and_delete() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+
+        let docs = client.get_documents().await.unwrap();
+        let doc = docs.get(&dune).unwrap();
+        client
+            .move_document(doc, Parent::Folder(books), "Dune (1965)")
+            .await
+            .unwrap();
+        let docs = client.get_documents().await.unwrap();
+        let doc = docs.resolve("Books/Dune (1965)").unwrap().unwrap();
+        assert_eq!(doc.version, 2);
+
+        client
+            .move_document(doc, Parent::Trash, "Dune")
+            .await
+            .unwrap();
+        let docs = client.get_documents().await.unwrap();
+        assert!(docs.get(&dune).is_none());
+        let doc = docs.trashed().next().unwrap();
+        assert_eq!(doc.id, dune);
+        assert!(cloud.document(&dune).unwrap().trashed);
+
+        // Deleting needs the current version.
+        let stale = DeleteRequest {
+            id: dune,
+            version: 1,
+        };
+        assert!(!client.delete(&[stale]).await.unwrap()[0].success);
+        client.delete_document(doc).await.unwrap();
+        assert!(cloud.document(&dune).is_none());
+    }
+
+    #[tokio::test]
+    async fn moves_to_top_level_and_back() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let scifi = cloud.add_folder("Sci-fi", Some(books));
+        let dune = cloud.add_document("Dune", Some(scifi), vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+
+        let change = client
+            .modify_metadata(dune, |p| p.parent = Some(Parent::Root))
+            .await
+            .unwrap();
+        assert_eq!(change.before.parent, Some(Parent::Folder(scifi)));
+        assert_eq!(change.version, 2);
+        let sent = cloud.requests().pop().unwrap();
+        let sent: serde_json::Value =
+            serde_json::from_slice(&sent.body).unwrap();
+        assert_eq!(sent[0]["Parent"], "");
+        let doc = cloud.document(&dune).unwrap();
+        assert_eq!((doc.parent, doc.version), (None, 2));
+
+        client
+            .modify_metadata(dune, |p| p.parent = Some(Parent::Folder(scifi)))
+            .await
+            .unwrap();
+        let doc = cloud.document(&dune).unwrap();
+        assert_eq!((doc.parent, doc.version), (Some(scifi), 3));
+
+        // The cloud refuses a parent it doesn't know, leaving the document
+        // where it was.
+        let refused = reqwest::Client::new()
+            .put(&format!(
+                "{}/document-storage/json/2/upload/update-status",
+                cloud.url()
+            ))
+            .bearer_auth(USER_TOKEN)
+            .json(&serde_json::json!([{
+                "ID": dune,
+                "Version": 4,
+                "Parent": "root",
+                "VissibleName": "Dune",
+                "Type": "DocumentType",
+                "ModifiedClient": chrono::Utc::now().to_rfc3339(),
+            }]))
+            .send()
+            .await
+            .unwrap();
+        let refused: serde_json::Value = refused.json().await.unwrap();
+        assert_eq!(refused[0]["Success"], false);
+        assert_eq!(refused[0]["Message"], "unknown parent");
+        let doc = cloud.document(&dune).unwrap();
+        assert_eq!((doc.parent, doc.version), (Some(scifi), 3));
+
+        // Listings from servers that write "root" are read as the top
+        // level.
+        cloud.set_listing_rewrite(|doc| {
+            if doc["Parent"] == "" {
+                doc["Parent"] = "Root".into();
+            }
+        });
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(docs.get(&books).unwrap().parent, None);
+        assert_eq!(docs.get(&dune).unwrap().parent, Some(scifi));
+    }
+
+    // Documents in the cloud whose parent isn't.
+    fn orphans(cloud: &FakeCloud) -> Vec<String> {
+        let state = cloud.state.lock().unwrap();
+        state
+            .documents
+            .iter()
+            .filter(|d| {
+                d.parent.is_some_and(|p| state.current_version(&p) == 0)
+            })
+            .map(|d| d.visible_name.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn update_metadata_bulk_batches() {
+        let cloud = FakeCloud::start().await;
+        let folder = cloud.add_folder("Scans", None);
+        let scans: Vec<Uuid> = (0..5)
+            .map(|i| cloud.add_document(&format!("Scan {}", i), None, vec![]))
+            .collect();
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        client.set_metadata_batch_size(2);
+        let into_folder = MetadataPatch {
+            parent: Some(Parent::Folder(folder)),
+            ..Default::default()
+        };
+        let mut changes: Vec<(Uuid, MetadataPatch)> =
+            scans.iter().map(|id| (*id, into_folder.clone())).collect();
+        let missing = Uuid::new_v4();
+        changes.insert(3, (missing, into_folder.clone()));
+
+        // The first batch is lost before reaching the cloud, and the last
+        // document is listed at a version the cloud has moved on from.
+        cloud.fail_next("/document-storage/json/2/upload/update-status", false);
+        let stale = scans[4].to_string();
+        cloud.set_listing_rewrite(move |doc| {
+            if doc["ID"] == stale.as_str() {
+                doc["Version"] = 0.into();
+            }
+        });
+        let outcomes = client.update_metadata_bulk(changes).await.unwrap();
+        let ids: Vec<Uuid> = outcomes.iter().map(|(id, _)| *id).collect();
+        assert_eq!(
+            ids,
+            [scans[0], scans[1], scans[2], missing, scans[3], scans[4]]
+        );
+        let failed = |i: usize| match &outcomes[i].1 {
+            UpdateOutcome::Failed(message) => message.clone(),
+            UpdateOutcome::Updated(_) => String::new(),
+        };
+        assert!(failed(0).contains("503"), "{}", failed(0));
+        assert_eq!(failed(0), failed(1));
+        assert_eq!(failed(3), "not in the cloud");
+        assert!(!failed(5).is_empty());
+        for i in &[2, 4] {
+            match &outcomes[*i].1 {
+                UpdateOutcome::Updated(change) => {
+                    assert_eq!(change.version, 2);
+                    assert_eq!(change.before.parent, Some(Parent::Root));
+                }
+                outcome => panic!("{:?}", outcome),
+            }
+        }
+        let parents: Vec<Option<Uuid>> = scans
+            .iter()
+            .map(|id| cloud.document(id).unwrap().parent)
+            .collect();
+        assert_eq!(parents, [None, None, Some(folder), Some(folder), None]);
+
+        // Batches of two, but nothing is sent for the missing document.
+        let sizes: Vec<usize> = cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.ends_with("/update-status"))
+            .map(|r| {
+                serde_json::from_slice::<Vec<serde_json::Value>>(&r.body)
+                    .unwrap()
+                    .len()
+            })
+            .collect();
+        assert_eq!(sizes, [2, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn delete_subtree_leaves_first() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let scifi = cloud.add_folder("Sci-fi", Some(books));
+        let dune = cloud.add_document("Dune", Some(scifi), vec![]);
+        let hyperion = cloud.add_document("Hyperion", Some(scifi), vec![]);
+        let notes = cloud.add_document("Notes", Some(books), vec![]);
+        cloud.add_document("Other", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+
+        // Opened on the tablet since listed, and the response to the first
+        // deletion lost.
+        cloud.modify(&hyperion, |d| d.version += 1);
+        cloud.fail_next("/document-storage/json/2/delete", true);
+        let mut seen = vec![];
+        let report = client
+            .delete_subtree(docs.get(&books).unwrap(), &docs, |d, o| {
+                seen.push((d.id, o.clone()))
+            })
+            .await
+            .unwrap();
+        assert_eq!(seen, report.outcomes);
+        let wrong_version = DeleteOutcome::Failed("wrong version".into());
+        assert_eq!(
+            report.outcomes,
+            [
+                (dune, DeleteOutcome::AlreadyGone),
+                (hyperion, wrong_version.clone()),
+                (notes, DeleteOutcome::Deleted),
+                (scifi, DeleteOutcome::Skipped),
+                (books, DeleteOutcome::Skipped),
+            ]
+        );
+        assert!(!report.is_complete());
+        assert!(orphans(&cloud).is_empty(), "{:?}", orphans(&cloud));
+
+        // Nothing was asked to go before what was in it.
+        let batches: Vec<Vec<Uuid>> = cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.ends_with("/delete"))
+            .map(|r| {
+                let requests: Vec<serde_json::Value> =
+                    serde_json::from_slice(&r.body).unwrap();
+                requests
+                    .iter()
+                    .map(|r| r["ID"].as_str().unwrap().parse().unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0], batches[1]);
+        assert_eq!(batches[2], [notes]);
+
+        // Run again, it finishes the job.
+        let docs = client.get_documents().await.unwrap();
+        let report = client
+            .delete_subtree(docs.get(&books).unwrap(), &docs, |_, _| ())
+            .await
+            .unwrap();
+        assert!(report.is_complete());
+        let docs = client.get_documents().await.unwrap();
+        let names: Vec<&str> = docs.iter().map(|d| &*d.visible_name).collect();
+        assert_eq!(names, ["Other"]);
+
+        // A request which keeps failing stops the run.
+        let archive = cloud.add_folder("Archive", None);
+        let old = cloud.add_folder("Old", Some(archive));
+        let draft = cloud.add_document("Draft", Some(old), vec![]);
+        let memo = cloud.add_document("Memo", Some(archive), vec![]);
+        let docs = client.get_documents().await.unwrap();
+        let report = client
+            .delete_subtree(docs.get(&archive).unwrap(), &docs, |d, _| {
+                if d.id == draft {
+                    // As many times as a batch is tried.
+                    for _ in 0..4 {
+                        cloud.fail_next(
+                            "/document-storage/json/2/delete",
+                            false,
+                        );
+                    }
+                }
+            })
+            .await
+            .unwrap();
+        let outcomes: Vec<(Uuid, bool)> = report
+            .outcomes
+            .iter()
+            .map(|(id, o)| (*id, matches!(o, DeleteOutcome::Failed(_))))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [(draft, false), (memo, true), (old, true), (archive, false)]
+        );
+        assert_eq!(report.remaining().count(), 3);
+        assert!(orphans(&cloud).is_empty(), "{:?}", orphans(&cloud));
+    }
+
+    #[tokio::test]
+    async fn skewed_clocks() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let at = |t: &str| t.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let last = at("2024-06-01T12:00:00Z");
+        cloud.modify(&dune, |d| d.modified_client = last);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+
+        // A clock a day behind doesn't date the change before the last.
+        client
+            .set_clock(Arc::new(crate::FixedClock(at("2024-05-31T12:00:00Z"))));
+        client.set_bookmarked(dune, true).await.unwrap();
+        assert_eq!(cloud.document(&dune).unwrap().modified_client, last);
+
+        // One ahead of the last dates it as it says.
+        let later = at("2024-06-02T08:00:00Z");
+        client.set_clock(Arc::new(crate::FixedClock(later)));
+        client.set_bookmarked(dune, false).await.unwrap();
+        assert_eq!(cloud.document(&dune).unwrap().modified_client, later);
+
+        // As do uploads, which have nothing to go by.
+        let id = Uuid::new_v4();
+        client
+            .upload_zip(id, 1, None, "New", DocType::Document, vec![])
+            .await
+            .unwrap();
+        assert_eq!(cloud.document(&id).unwrap().modified_client, later);
+    }
+
+    #[tokio::test]
+    async fn metadata_changes_keep_other_fields() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let dune = cloud.add_document("Dune", Some(books), vec![]);
+        cloud.modify(&dune, |d| d.bookmarked = true);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+        let stale = docs.get(&dune).unwrap().clone();
+
+        // Read on the tablet since the listing was fetched.
+        cloud.modify(&dune, |d| {
+            d.current_page = 7;
+            d.version = 2;
+        });
+        let before = client.get_documents().await.unwrap();
+        let before = serde_json::to_value(before.get(&dune).unwrap()).unwrap();
+        client
+            .move_document(&stale, Parent::Folder(books), "Dune (1965)")
+            .await
+            .unwrap();
+        let update = cloud
+            .requests()
+            .into_iter()
+            .rfind(|r| r.path.ends_with("upload/update-status"))
+            .unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&update.body).unwrap();
+        let sent = body[0].as_object().unwrap();
+        for (field, value) in sent {
+            match field.as_str() {
+                "VissibleName" => assert_eq!(value, "Dune (1965)"),
+                "Version" => assert_eq!(value, 3),
+                "ModifiedClient" => (),
+                _ => assert_eq!(
+                    value.to_string(),
+                    before[field].to_string(),
+                    "{}",
+                    field
+                ),
+            }
+        }
+        let doc = cloud.document(&dune).unwrap();
+        assert_eq!((doc.current_page, doc.bookmarked), (7, true));
+
+        client.set_bookmarked(dune, false).await.unwrap();
+        client.set_current_page(dune, 12).await.unwrap();
+        let doc = cloud.document(&dune).unwrap();
+        assert_eq!(doc.visible_name, "Dune (1965)");
+        assert_eq!((doc.current_page, doc.bookmarked), (12, false));
+        assert_eq!(doc.version, 5);
+
+        // Trashed documents stay in the trash.
+        cloud.modify(&dune, |d| d.trashed = true);
+        let change = client
+            .modify_metadata(dune, |patch| {
+                patch.visible_name = Some("Dune".to_string())
+            })
+            .await
+            .unwrap();
+        let doc = cloud.document(&dune).unwrap();
+        assert!(doc.trashed);
+        assert_eq!(doc.visible_name, "Dune");
+        assert_eq!(
+            change,
+            crate::requests::MetadataChange {
+                id: dune,
+                before: crate::MetadataPatch {
+                    visible_name: Some("Dune (1965)".to_string()),
+                    ..Default::default()
+                },
+                after: crate::MetadataPatch {
+                    visible_name: Some("Dune".to_string()),
+                    ..Default::default()
+                },
+                version: 6,
+            }
+        );
+
+        let change = client
+            .modify_metadata_at(dune, 6, |patch| {
+                patch.parent = Some(Parent::Folder(books))
+            })
+            .await
+            .unwrap();
+        assert_eq!(change.before.parent, Some(Parent::Trash));
+        assert!(!cloud.document(&dune).unwrap().trashed);
+        let stale = client
+            .modify_metadata_at(dune, 6, |patch| patch.parent = None)
+            .await;
+        assert!(
+            matches!(
+                stale,
+                Err(Error::VersionMismatch {
+                    expected: 6,
+                    found: 7,
+                    ..
+                })
+            ),
+            "{:?}",
+            stale
+        );
+
+        let missing = client.set_current_page(Uuid::from_u128(1), 1).await;
+        assert!(matches!(missing, Err(Error::EmptyResult)));
+    }
+
+    #[tokio::test]
+    async fn destinations() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let dune = cloud.add_document("Dune", Some(books), vec![]);
+        let old = cloud.add_folder("Old", None);
+        cloud.modify(&old, |d| d.trashed = true);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+
+        let valid = docs.validate_parent(Parent::Folder(books)).unwrap();
+        assert_eq!(valid.folder(), Some(books));
+        assert_eq!(docs.validate_parent(Parent::Root).unwrap().folder(), None);
+        let reason = |parent| match docs.validate_parent(parent) {
+            Err(Error::InvalidDestination { reason, .. }) => reason,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(reason(Parent::Folder(dune)), "it isn't a folder");
+        assert_eq!(reason(Parent::Folder(old)), "the folder is in the trash");
+        assert_eq!(
+            reason(Parent::Folder(Uuid::new_v4())),
+            "there's no such folder"
+        );
+        assert!(reason(Parent::Trash).contains("moved there"));
+
+        // Creating a document in the trash is refused before asking.
+        let id = Uuid::new_v4();
+        let request = UpdateStatusRequest {
+            parent: Parent::Trash,
+            ..UpdateStatusRequest::after_upload(
+                id,
+                1,
+                None,
+                "New",
+                DocType::Document,
+                chrono::Utc::now(),
+            )
+        };
+        let sent = cloud.requests().len();
+        let err = client
+            .update_status(std::slice::from_ref(&request))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidDestination { .. }), "{}", err);
+        assert_eq!(cloud.requests().len(), sent);
+
+        client.set_allow_trash(true);
+        client.update_status(&[request]).await.unwrap();
+        assert_eq!(cloud.requests().len(), sent + 1);
+    }
+
+    #[tokio::test]
+    async fn read_only() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, b"zip".to_vec());
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        client.set_read_only(true);
+        let docs = client.get_documents().await.unwrap();
+        let doc = docs.get(&dune).unwrap();
+        let id = Uuid::new_v4();
+
+        let refused = |result: crate::error::Result<()>| match result {
+            Err(Error::ReadOnly) => {}
+            other => panic!("{:?}", other),
+        };
+        refused(
+            client
+                .move_document(doc, Parent::Trash, "Dune")
+                .await
+                .map(drop),
+        );
+        refused(client.delete_document(doc).await);
+        refused(client.set_pinned(doc, true).await);
+        refused(
+            client
+                .upload_zip(id, 1, None, "New", DocType::Document, vec![])
+                .await,
+        );
+        let mut upload = Upload::new(id, 1, None, "New", DocType::Collection);
+        refused(client.advance_upload(&mut upload, &[]).await);
+        refused(client.put_blob(&cloud.url(), vec![]).await);
+
+        // Reading still works.
+        client.get_document_by_id(&dune).await.unwrap();
+        assert!(cloud
+            .requests()
+            .iter()
+            .all(|r| r.method == Method::GET || r.method == Method::POST));
+        assert_eq!(cloud.document(&dune).unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn dialects() {
+        let cloud = FakeCloud::start().await;
+        cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        for dialect in &[WireDialect::Official, WireDialect::Rmfakecloud] {
+            cloud.set_dialect(*dialect);
+            let docs = client.get_documents().await.unwrap();
+            assert_eq!(&*docs.get_children(&None)[0].visible_name, "Dune");
+        }
+    }
+
+    #[tokio::test]
+    async fn upload() {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let id = Uuid::new_v4();
+        client
+            .upload_zip(id, 1, None, "New", DocType::Document, b"v1".to_vec())
+            .await
+            .unwrap();
+        client
+            .upload_zip(
+                id,
+                2,
+                None,
+                "Renamed",
+                DocType::Document,
+                b"v2".to_vec(),
+            )
+            .await
+            .unwrap();
+        let stored = cloud.document(&id).unwrap();
+        assert_eq!(stored.version, 2);
+        assert_eq!(stored.visible_name, "Renamed");
+        assert_eq!(stored.blob, b"v2");
+        // Versions must go up one at a time.
+        assert!(client
+            .upload_zip(id, 2, None, "Again", DocType::Document, vec![])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn upload_retries_stages() {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let id = Uuid::new_v4();
+        let mut upload = Upload::new(id, 1, None, "Dune", DocType::Document);
+
+        // Each stage is retried on its own, keeping the reserved URL.
+        cloud.fail_next("/document-storage/json/2/upload/request", false);
+        client.advance_upload(&mut upload, b"zip").await.unwrap();
+        assert_eq!(upload.stage, UploadStage::Reserved);
+        let url = upload.blob_url_put.clone().unwrap();
+        cloud.fail_next("/upload/", false);
+        client.advance_upload(&mut upload, b"zip").await.unwrap();
+        assert_eq!(upload.blob_url_put.as_ref(), Some(&url));
+        // The status is set but the response lost, so the retry is refused.
+        cloud.fail_next("/document-storage/json/2/upload/update-status", true);
+        client.advance_upload(&mut upload, b"zip").await.unwrap();
+        assert!(upload.is_done());
+
+        // The one that failed, and its retry.
+        let reserved = cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.ends_with("/upload/request"))
+            .count();
+        assert_eq!(reserved, 2);
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(cloud.document(&id).unwrap().blob, b"zip");
+    }
+
+    // A client sending blobs of 10 bytes or more in chunks of 4.
+    async fn chunking_client(cloud: &FakeCloud) -> Client {
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        client.set_resumable_threshold(Some(10));
+        client.set_resumable_chunk_size(4);
+        client
+    }
+
+    fn chunks_sent(cloud: &FakeCloud) -> Vec<String> {
+        cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.starts_with("/session/"))
+            .map(|r| {
+                let range = &r.headers[hyper::header::CONTENT_RANGE];
+                range.to_str().unwrap().to_string()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn resumable_upload() {
+        let cloud = FakeCloud::start().await;
+        cloud.set_resumable(true);
+        let client = chunking_client(&cloud).await;
+        let id = Uuid::new_v4();
+        let mut upload = Upload::new(id, 1, None, "Dune", DocType::Document);
+        let zip = b"0123456789";
+
+        // Reserving, starting the session and each chunk are steps of their
+        // own, so progress can be saved after each.
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert_eq!(upload.progress(), Some((0, 10)));
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert_eq!(upload.progress(), Some((4, 10)));
+
+        // A chunk that fails is checked on and sent again, and one whose
+        // answer is lost is found to have arrived.
+        cloud.fail_next("/session/", false);
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert_eq!(upload.progress(), Some((8, 10)));
+        cloud.fail_next("/session/", true);
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert_eq!(upload.stage, UploadStage::BlobPut);
+        assert_eq!(upload.session, None);
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert!(upload.is_done());
+        assert_eq!(cloud.document(&id).unwrap().blob, zip);
+        assert_eq!(
+            chunks_sent(&cloud),
+            [
+                "bytes 0-3/10",
+                "bytes 4-7/10",
+                "bytes */10",
+                "bytes 4-7/10",
+                "bytes 8-9/10",
+                "bytes */10"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_from_file() {
+        let cloud = FakeCloud::start().await;
+        let path = std::env::temp_dir()
+            .join(format!("upload-from-file-{}.zip", Uuid::new_v4()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let blob = crate::BlobSource::File {
+            path: &path,
+            size: 10,
+        };
+
+        // Sent whole, and in chunks over a session.
+        let client = chunking_client(&cloud).await;
+        for resumable in &[false, true] {
+            cloud.set_resumable(*resumable);
+            let id = Uuid::new_v4();
+            let mut upload =
+                Upload::new(id, 1, None, "Dune", DocType::Document);
+            while !upload.is_done() {
+                client.advance_upload_from(&mut upload, blob).await.unwrap();
+            }
+            assert_eq!(cloud.document(&id).unwrap().blob, b"0123456789");
+        }
+        assert_eq!(
+            chunks_sent(&cloud),
+            ["bytes 0-3/10", "bytes 4-7/10", "bytes 8-9/10"]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumable_upload_picked_up() {
+        let cloud = FakeCloud::start().await;
+        cloud.set_resumable(true);
+        let client = chunking_client(&cloud).await;
+        let id = Uuid::new_v4();
+        let mut upload = Upload::new(id, 1, None, "Dune", DocType::Document);
+        let zip = b"0123456789";
+        for _ in 0..3 {
+            client.advance_upload(&mut upload, zip).await.unwrap();
+        }
+
+        // Saved and loaded by another process, which asks before sending.
+        let saved = serde_json::to_string(&upload).unwrap();
+        let mut upload: Upload = serde_json::from_str(&saved).unwrap();
+        assert!(!upload.session.as_ref().unwrap().confirmed);
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert_eq!(upload.progress(), Some((8, 10)));
+        assert_eq!(chunks_sent(&cloud)[1..], ["bytes */10", "bytes 4-7/10"]);
+
+        // Once the session has expired, another is started and the blob
+        // sent from the start.
+        cloud.expire_sessions();
+        client.advance_upload(&mut upload, zip).await.unwrap();
+        assert_eq!(upload.session, None);
+        assert_eq!(upload.stage, UploadStage::Reserved);
+        while !upload.is_done() {
+            client.advance_upload(&mut upload, zip).await.unwrap();
+        }
+        assert_eq!(cloud.document(&id).unwrap().blob, zip);
+        assert_eq!(
+            chunks_sent(&cloud)[3..],
+            [
+                "bytes 8-9/10",
+                "bytes 0-3/10",
+                "bytes 4-7/10",
+                "bytes 8-9/10"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resumable_upload_refused() {
+        // The cloud won't start a session, so the blob goes whole.
+        let cloud = FakeCloud::start().await;
+        let client = chunking_client(&cloud).await;
+        let id = Uuid::new_v4();
+        client
+            .upload_zip(
+                id,
+                1,
+                None,
+                "Dune",
+                DocType::Document,
+                b"0123456789".to_vec(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(cloud.document(&id).unwrap().blob, b"0123456789");
+        let methods: Vec<Method> = cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.starts_with("/upload/"))
+            .map(|r| r.method.clone())
+            .collect();
+        assert_eq!(methods, [Method::POST, Method::PUT]);
+        assert!(chunks_sent(&cloud).is_empty());
+
+        // Small blobs don't ask.
+        cloud.set_resumable(true);
+        client
+            .upload_zip(id, 2, None, "Dune", DocType::Document, b"v2".to_vec())
+            .await
+            .unwrap();
+        let posts = cloud
+            .requests()
+            .iter()
+            .filter(|r| {
+                r.method == Method::POST && r.path.starts_with("/upload/")
+            })
+            .count();
+        assert_eq!(posts, 1);
+    }
+
+    #[tokio::test]
+    async fn pin_and_unpin() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let blob = {
+            let mut za = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+            za.start_file(
+                format!("{}.content", dune),
+                zip::write::FileOptions::default(),
+            )
+            .unwrap();
+            std::io::Write::write_all(&mut za, br#"{"fileType": "pdf"}"#)
+                .unwrap();
+            za.finish().unwrap().into_inner()
+        };
+        cloud.modify(&dune, |d| {
+            d.blob = blob;
+            d.current_page = 7;
+        });
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+
+        let details = client.document_details(&dune).await.unwrap();
+        assert!(!details.pinned());
+        assert_eq!(details.pinned_source(), PinnedSource::Listing);
+        assert_eq!(details.content.unwrap().file_type, "pdf");
+
+        client.set_pinned(&details.document, true).await.unwrap();
+        let details = client.document_details(&dune).await.unwrap();
+        assert!(details.pinned());
+        assert_eq!(details.pinned_source(), PinnedSource::Metadata);
+        assert_eq!(details.document.version, 2);
+        assert!(details.document.bookmarked);
+        assert_eq!(details.document.current_page, 7);
+
+        client.set_pinned(&details.document, false).await.unwrap();
+        let details = client.document_details(&dune).await.unwrap();
+        assert!(!details.pinned());
+        assert!(!details.document.bookmarked);
+        assert_eq!(details.document.version, 3);
+    }
+
+    async fn bulk_cloud(n: usize) -> (FakeCloud, Client, Vec<Uuid>) {
+        let cloud = FakeCloud::start().await;
+        let empty = zip::ZipWriter::new(std::io::Cursor::new(vec![]))
+            .finish()
+            .unwrap()
+            .into_inner();
+        let ids = (0..n)
+            .map(|i| cloud.add_document(&i.to_string(), None, empty.clone()))
+            .collect();
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        (cloud, client, ids)
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn details_bulk_keeps_order() {
+        let (cloud, client, ids) = bulk_cloud(4).await;
+        // The first finishes last, but still comes out first.
+        cloud.delay_next(
+            &format!("/blob/{}", ids[0]),
+            Duration::from_millis(200),
+        );
+        let details: Vec<_> =
+            client.document_details_bulk(&ids, 4).collect().await;
+        let names: Vec<String> = details
+            .into_iter()
+            .map(|d| d.unwrap().document.visible_name.into())
+            .collect();
+        assert_eq!(names, vec!["0", "1", "2", "3"]);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn details_bulk_isolates_errors() {
+        let (cloud, client, mut ids) = bulk_cloud(3).await;
+        cloud.modify(&ids[1], |d| d.blob = b"not a zip".to_vec());
+        ids.push(Uuid::new_v4());
+        let details: Vec<_> =
+            client.document_details_bulk(&ids, 2).collect().await;
+        assert_eq!(details.len(), 4);
+        assert!(details[0].is_ok());
+        assert!(matches!(details[1], Err(Error::ZipError { .. })));
+        assert!(details[2].is_ok());
+        assert!(matches!(details[3], Err(Error::EmptyResult)));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn details_bulk_stops_when_dropped() {
+        let (cloud, client, ids) = bulk_cloud(8).await;
+        let mut details = client.document_details_bulk(&ids, 2);
+        assert!(details.next().await.unwrap().is_ok());
+        drop(details);
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        let blobs = cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.starts_with("/blob/"))
+            .count();
+        assert!(blobs <= 2, "{} blobs fetched", blobs);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn cancelled_download() {
+        let (cloud, client, ids) = bulk_cloud(1).await;
+        let token = CancellationToken::new();
+        let scoped = client.with_cancellation(token.clone());
+        cloud.delay_next(&format!("/blob/{}", ids[0]), Duration::from_secs(5));
+        let doc = scoped.get_document_by_id(&ids[0]).await.unwrap();
+        let download = scoped.download_blob(&doc);
+        let cancel = async {
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            token.cancel();
+        };
+        let start = std::time::Instant::now();
+        let (result, _) = futures_util::future::join(download, cancel).await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{:?}", result);
+        assert!(start.elapsed() < Duration::from_secs(2));
+        // From then on nothing is sent, while the client it came from is
+        // as it was.
+        let requests = cloud.requests().len();
+        assert!(matches!(
+            scoped.get_documents().await,
+            Err(Error::Cancelled)
+        ));
+        assert_eq!(cloud.requests().len(), requests);
+        assert!(client.download_blob(&doc).await.is_ok());
+
+        let past = std::time::Instant::now();
+        let result = client.with_deadline(past).get_documents().await;
+        assert!(matches!(result, Err(Error::DeadlineExceeded)));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn cancelled_upload() {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let token = CancellationToken::new();
+        let scoped = client.with_cancellation(token.clone());
+        cloud.delay_next("/upload/", Duration::from_secs(5));
+        let id = Uuid::new_v4();
+        let upload = scoped.upload_zip(
+            id,
+            1,
+            None,
+            "Dune",
+            DocType::Document,
+            b"zip".to_vec(),
+        );
+        let cancel = async {
+            tokio::time::delay_for(Duration::from_millis(100)).await;
+            token.cancel();
+        };
+        let (result, _) = futures_util::future::join(upload, cancel).await;
+        assert!(matches!(result, Err(Error::Cancelled)), "{:?}", result);
+        let requests = cloud.requests();
+        assert!(requests.iter().any(|r| r.path.ends_with("/upload/request")));
+        assert!(!requests
+            .iter()
+            .any(|r| r.path.ends_with("/upload/update-status")));
+        assert!(client.get_documents().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn migrated_account() {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        assert!(client.get_documents().await.unwrap().is_empty());
+
+        cloud.set_migrated(true);
+        assert!(matches!(
+            client.get_documents().await,
+            Err(Error::AccountMigrated)
+        ));
+        // Having seen that, the client doesn't try to change anything.
+        let requests = cloud.requests().len();
+        assert!(matches!(
+            client
+                .upload_zip(
+                    Uuid::new_v4(),
+                    1,
+                    None,
+                    "New",
+                    DocType::Document,
+                    vec![]
+                )
+                .await,
+            Err(Error::Unsupported {
+                capability: Capability::Uploads
+            })
+        ));
+        assert_eq!(cloud.requests().len(), requests);
+    }
+
+    #[tokio::test]
+    async fn capabilities() {
+        for migrated in &[false, true] {
+            for dialect in &[WireDialect::Official, WireDialect::Rmfakecloud] {
+                let cloud = FakeCloud::start().await;
+                cloud.add_document("Dune", None, vec![]);
+                cloud.set_dialect(*dialect);
+                cloud.set_migrated(*migrated);
+                let mut client = cloud.client();
+                client.refresh_token().await.unwrap();
+                let requests = cloud.requests().len();
+
+                let found = client.capabilities().await.unwrap();
+                let generation = if *migrated {
+                    Generation::Sync15
+                } else {
+                    Generation::Legacy
+                };
+                assert_eq!(found.generation, generation);
+                assert_eq!(found.uploads, !migrated);
+                if !migrated {
+                    assert_eq!(found.dialect, *dialect);
+                }
+                // Found out with one request, and not again.
+                assert_eq!(client.capabilities().await.unwrap(), found);
+                assert_eq!(cloud.requests().len(), requests + 1);
+            }
+        }
+
+        // What's seen of a listing is enough to tell, with no more
+        // requests.
+        let cloud = FakeCloud::start().await;
+        cloud.add_document("Dune", None, vec![]);
+        cloud.set_dialect(WireDialect::Rmfakecloud);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        client.get_documents().await.unwrap();
+        let requests = cloud.requests().len();
+        let found = client.capabilities().await.unwrap();
+        assert_eq!(found.dialect, WireDialect::Rmfakecloud);
+        assert_eq!(found.max_blob_size, None);
+        assert_eq!(cloud.requests().len(), requests);
+
+        // An empty listing doesn't show the dialect, so the one set is
+        // taken, after asking once.
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let found = client.capabilities().await.unwrap();
+        assert_eq!(found.dialect, WireDialect::Official);
+        assert_eq!(found.max_blob_size, Some(crate::OFFICIAL_MAX_BLOB_SIZE));
+        let requests = cloud.requests().len();
+        client.capabilities().await.unwrap();
+        assert_eq!(cloud.requests().len(), requests);
+    }
+
+    #[tokio::test]
+    async fn streamed_listing() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        cloud.add_document("Dune", Some(books), vec![]);
+        let old = cloud.add_document("Old", None, vec![]);
+        cloud.modify(&old, |d| d.trashed = true);
+        cloud.set_dialect(WireDialect::Rmfakecloud);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+
+        let entries: Vec<ListingEntry> = client
+            .stream_documents()
+            .await
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect()
+            .await;
+        assert_eq!(entries.len(), 3);
+        // What's streamed shows the dialect, as a listing does.
+        let requests = cloud.requests().len();
+        let found = client.capabilities().await.unwrap();
+        assert_eq!(found.dialect, WireDialect::Rmfakecloud);
+        assert_eq!(cloud.requests().len(), requests);
+        assert!(entries.iter().any(|e| match e {
+            ListingEntry::Trashed(d) => d.id == old,
+            _ => false,
+        }));
+        let streamed =
+            Documents::from_stream(client.stream_documents().await.unwrap())
+                .await
+                .unwrap();
+        assert_eq!(streamed, client.get_documents().await.unwrap());
+        assert_eq!(streamed.trashed().count(), 1);
+
+        // Failures are told of before anything is streamed.
+        cloud.set_migrated(true);
+        match client.stream_documents().await {
+            Err(Error::AccountMigrated) => (),
+            Err(e) => panic!("{:?}", e),
+            Ok(_) => panic!("streamed a migrated account's listing"),
+        }
+    }
+
+    // The requests for full listings the cloud has received.
+    fn listings(cloud: &FakeCloud) -> Vec<RecordedRequest> {
+        cloud
+            .requests()
+            .into_iter()
+            .filter(|r| r.path.ends_with("/docs") && r.query.is_empty())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn listing_cache_revalidates() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        client.set_listing_cache(Some(crate::ListingCache::new()));
+        client.refresh_token().await.unwrap();
+
+        let first = client.get_documents().await.unwrap();
+        let second = client.get_documents().await.unwrap();
+        assert_eq!(first, second);
+        let sent = listings(&cloud);
+        assert_eq!(sent.len(), 2);
+        assert!(sent[0].headers.get(IF_NONE_MATCH).is_none());
+        assert!(sent[1].headers.get(IF_NONE_MATCH).is_some());
+
+        // Changed on the tablet.
+        cloud.modify(&dune, |d| d.visible_name = "Dune (1965)".to_string());
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(&*docs.get(&dune).unwrap().visible_name, "Dune (1965)");
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(&*docs.get(&dune).unwrap().visible_name, "Dune (1965)");
+    }
+
+    #[tokio::test]
+    async fn listing_cache_without_validators() {
+        let cloud = FakeCloud::start().await;
+        cloud.set_validators(false);
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        let cache =
+            crate::ListingCache::new().with_ttl(Duration::from_secs(60));
+        client.set_listing_cache(Some(cache));
+        client.refresh_token().await.unwrap();
+
+        let first = client.get_documents().await.unwrap();
+        cloud.modify(&dune, |d| d.visible_name = "Dune (1965)".to_string());
+        let second = client.get_documents().await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(listings(&cloud).len(), 1);
+
+        let cache = crate::ListingCache::new().with_ttl(Duration::from_secs(0));
+        client.set_listing_cache(Some(cache));
+        client.get_documents().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(&*docs.get(&dune).unwrap().visible_name, "Dune (1965)");
+        assert_eq!(listings(&cloud).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn listing_cache_invalidated_by_changes() {
+        let cloud = FakeCloud::start().await;
+        cloud.set_validators(false);
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        let cache =
+            crate::ListingCache::new().with_ttl(Duration::from_secs(60));
+        client.set_listing_cache(Some(cache));
+        client.refresh_token().await.unwrap();
+
+        let docs = client.get_documents().await.unwrap();
+        assert!(!docs.get(&dune).unwrap().bookmarked);
+        client.set_bookmarked(dune, true).await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+        assert!(docs.get(&dune).unwrap().bookmarked);
+
+        let doc = docs.get(&dune).unwrap().clone();
+        client.delete_document(&doc).await.unwrap();
+        assert!(client.get_documents().await.unwrap().is_empty());
+        assert_eq!(listings(&cloud).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn listing_cache_file() {
+        let cloud = FakeCloud::start().await;
+        cloud.add_document("Dune", None, vec![]);
+        let path = std::env::temp_dir()
+            .join(format!("listing-cache-{}.json", Uuid::new_v4()));
+
+        let mut client = cloud.client();
+        client.set_listing_cache(Some(crate::ListingCache::at_path(
+            path.clone(),
+        )));
+        client.refresh_token().await.unwrap();
+        let first = client.get_documents().await.unwrap();
+        assert!(path.exists());
+
+        // As a later run would.
+        let mut client = cloud.client();
+        client.set_listing_cache(Some(crate::ListingCache::at_path(
+            path.clone(),
+        )));
+        client.refresh_token().await.unwrap();
+        assert_eq!(client.get_documents().await.unwrap(), first);
+        let sent = listings(&cloud);
+        assert!(sent[1].headers.get(IF_NONE_MATCH).is_some());
+
+        // A damaged file is as good as none.
+        std::fs::write(&path, b"{").unwrap();
+        let mut client = cloud.client();
+        client.set_listing_cache(Some(crate::ListingCache::at_path(
+            path.clone(),
+        )));
+        client.refresh_token().await.unwrap();
+        assert_eq!(client.get_documents().await.unwrap(), first);
+        assert!(listings(&cloud)[2].headers.get(IF_NONE_MATCH).is_none());
+
+        client.listing_cache().unwrap().invalidate();
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn diagnostics() {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        client.set_read_only(true);
+
+        let diagnostics = client.diagnostics();
+        assert!(diagnostics.endpoint.starts_with("http://"));
+        assert_eq!(diagnostics.user_id.as_deref(), Some("auth0|fake-user"));
+        let expires = diagnostics.user_token_expires.unwrap();
+        assert_eq!(expires.to_rfc3339(), "2100-01-01T00:00:00+00:00");
+        assert_eq!(diagnostics.device_token_fingerprint.unwrap().len(), 16);
+        assert!(diagnostics.read_only);
+        assert!(client.ping().await.is_ok());
+
+        // Nothing that could be used to sign in is ever shown.
+        let diagnostics = client.diagnostics();
+        let shown = [
+            format!("{:?}", diagnostics),
+            serde_json::to_string(&diagnostics).unwrap(),
+        ];
+        let secrets = USER_TOKEN
+            .split('.')
+            .chain(std::iter::once("fake-device-token"));
+        for secret in secrets {
+            for shown in &shown {
+                assert!(!shown.contains(secret), "{} in {}", secret, shown);
+            }
+        }
+    }
+
+    // A version 5 `.rm` page of `strokes` strokes, each a single segment.
+    fn rm_page(strokes: u32) -> Vec<u8> {
+        let mut buf =
+            format!("{:<43}", "reMarkable .lines file, version=5").into_bytes();
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&strokes.to_le_bytes());
+        for _ in 0..strokes {
+            buf.extend_from_slice(&[0; 20]);
+            buf.extend_from_slice(&1u32.to_le_bytes());
+            buf.extend_from_slice(&[0; 24]);
+        }
+        buf
+    }
+
+    fn zip_of(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+        use std::io::Write;
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        for (name, data) in files {
+            zip.start_file(name, Default::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn read_page(blob: &[u8], name: &str) -> Page {
+        use std::io::Read;
+        let mut za = zip::ZipArchive::new(std::io::Cursor::new(blob)).unwrap();
+        let mut data = vec![];
+        za.by_name(name).unwrap().read_to_end(&mut data).unwrap();
+        Page::parse(&data).unwrap()
+    }
+
+    #[tokio::test]
+    async fn replace_page_strokes() {
+        let cloud = FakeCloud::start().await;
+        let notes = cloud.add_document("Notes", None, vec![]);
+        let content = br#"{"fileType": "notebook", "pages": ["a", "b"]}"#;
+        cloud.modify(&notes, |d| {
+            d.blob = zip_of(&[
+                (format!("{}.content", notes), content.to_vec()),
+                (format!("{}/a.rm", notes), rm_page(1)),
+                (format!("{}/b.rm", notes), rm_page(1)),
+            ])
+        });
+        let old = cloud.add_document("Old", None, vec![]);
+        cloud.modify(&old, |d| {
+            d.blob = zip_of(&[(format!("{}/0.rm", old), rm_page(0))])
+        });
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+
+        let doc = docs.get(&notes).unwrap();
+        let strokes = client.replace_page_strokes(doc, 1, &rm_page(3)).await;
+        assert_eq!(strokes.unwrap(), 3);
+        let stored = cloud.document(&notes).unwrap();
+        assert_eq!(stored.version, 2);
+        let page = read_page(&stored.blob, &format!("{}/b.rm", notes));
+        assert_eq!(page, Page::parse(&rm_page(3)).unwrap());
+        let untouched = read_page(&stored.blob, &format!("{}/a.rm", notes));
+        assert_eq!(untouched.stroke_count(), 1);
+
+        // Pages named by index, in archives from older firmware.
+        let doc = docs.get(&old).unwrap();
+        client
+            .replace_page_strokes(doc, 0, &rm_page(2))
+            .await
+            .unwrap();
+        let stored = cloud.document(&old).unwrap();
+        let page = read_page(&stored.blob, &format!("{}/0.rm", old));
+        assert_eq!(page.stroke_count(), 2);
+
+        // Nothing is uploaded for a page that isn't there or bad strokes.
+        let uploads = || {
+            cloud
+                .requests()
+                .iter()
+                .filter(|r| r.path.contains("upload/request"))
+                .count()
+        };
+        let before = uploads();
+        let doc = docs.get(&notes).unwrap();
+        let missing = client.replace_page_strokes(doc, 2, &rm_page(1)).await;
+        assert!(matches!(
+            missing,
+            Err(Error::NoSuchPage {
+                index: 2,
+                page_count: 2
+            })
+        ));
+        let newer = b"reMarkable .lines file, version=7          ";
+        let malformed = client.replace_page_strokes(doc, 0, newer).await;
+        assert!(matches!(malformed, Err(Error::FormatError { .. })));
+        let truncated = &rm_page(1)[..50];
+        let malformed = client.replace_page_strokes(doc, 0, truncated).await;
+        assert!(matches!(malformed, Err(Error::FormatError { .. })));
+        assert_eq!(uploads(), before);
+        assert_eq!(cloud.document(&notes).unwrap().version, 2);
+    }
+
+    #[tokio::test]
+    async fn requires_token() {
+        let cloud = FakeCloud::start().await;
+        let client = cloud.client();
+        assert!(client.get_documents().await.is_err());
+    }
+}
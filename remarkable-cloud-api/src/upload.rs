@@ -0,0 +1,205 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::documents::{DocType, Document};
+
+/// How far an [`Upload`] has got. Each stage only starts once the one
+/// before it has succeeded.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadStage {
+    /// Nothing has been sent yet.
+    Started,
+    /// The cloud has handed out a URL for the blob.
+    Reserved,
+    /// The blob is stored, but the document isn't visible yet.
+    BlobPut,
+    /// The metadata is set and the document is visible.
+    Done,
+}
+
+/// An upload of one version of a document, which can be saved between stages
+/// and picked up again later with `Client::advance_upload`.
+///
+/// The id, version and blob URL are kept from the first attempt, so retrying
+/// a stage never reserves a second upload, which could leave an invisible
+/// copy of the document behind.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Upload {
+    pub id: Uuid,
+    pub version: u64,
+    pub parent: Option<Uuid>,
+    pub visible_name: String,
+    pub doc_type: DocType,
+    #[serde(default)]
+    pub bookmarked: bool,
+    #[serde(default)]
+    pub current_page: i32,
+    pub blob_url_put: Option<String>,
+    /// The session a large blob is being sent in chunks over, once one is
+    /// started.
+    #[serde(default)]
+    pub session: Option<ResumableSession>,
+    pub stage: UploadStage,
+}
+
+/// A resumable upload session, in which a blob is sent in chunks that are
+/// each confirmed by the cloud, so an upload cut off partway only has to
+/// send the rest.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ResumableSession {
+    /// Where the chunks are sent.
+    pub url: String,
+    /// The size of the whole blob.
+    pub size: u64,
+    /// How much of the blob the cloud has said it has.
+    pub received: u64,
+    /// Whether `received` is known to be up to date. A chunk sent without
+    /// an answer may have arrived all the same, so the cloud is asked
+    /// before the next is sent. Never saved, so a session picked up again
+    /// by another process is always asked.
+    #[serde(skip)]
+    pub confirmed: bool,
+}
+
+impl Upload {
+    /// Plans an upload of version `version` of the document `id`. Pass
+    /// version 1 to create a new document. It starts unbookmarked on the
+    /// first page; set those fields to keep an existing document's.
+    pub fn new(
+        id: Uuid,
+        version: u64,
+        parent: Option<Uuid>,
+        visible_name: &str,
+        doc_type: DocType,
+    ) -> Self {
+        Upload {
+            id,
+            version,
+            parent,
+            visible_name: visible_name.to_string(),
+            doc_type,
+            bookmarked: false,
+            current_page: 0,
+            blob_url_put: None,
+            session: None,
+            stage: UploadStage::Started,
+        }
+    }
+
+    /// Plans an upload of the next version of `document`, keeping its name,
+    /// folder, bookmark and page.
+    pub fn next_version(document: &Document) -> Self {
+        Upload {
+            bookmarked: document.bookmarked,
+            current_page: document.current_page,
+            ..Upload::new(
+                document.id,
+                document.version + 1,
+                document.parent,
+                &document.visible_name,
+                document.doc_type.clone(),
+            )
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.stage == UploadStage::Done
+    }
+
+    /// How much of the blob has been sent and how big it is, while it's
+    /// being sent in chunks.
+    pub fn progress(&self) -> Option<(u64, u64)> {
+        let session = self.session.as_ref()?;
+        Some((session.received, session.size))
+    }
+}
+
+/// The pieces a blob is sent in, for `reqwest::Body::wrap_stream`.
+pub(crate) type BlobChunks =
+    Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
+/// The archive an upload sends: in memory, or in a file, which is read a
+/// piece at a time as it's sent rather than all at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobSource<'a> {
+    Memory(&'a [u8]),
+    /// The file at `path`, which holds `size` bytes.
+    File {
+        path: &'a Path,
+        size: u64,
+    },
+}
+
+impl<'a> From<&'a [u8]> for BlobSource<'a> {
+    fn from(blob: &'a [u8]) -> Self {
+        BlobSource::Memory(blob)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for BlobSource<'a> {
+    fn from(blob: &'a Vec<u8>) -> Self {
+        BlobSource::Memory(blob)
+    }
+}
+
+impl BlobSource<'_> {
+    pub fn len(&self) -> u64 {
+        match self {
+            BlobSource::Memory(blob) => blob.len() as u64,
+            BlobSource::File { size, .. } => *size,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes `start` to `end` of the blob, in pieces of `piece` bytes. A
+    /// file is only read as the pieces are taken.
+    pub(crate) fn chunks(
+        &self,
+        start: u64,
+        end: u64,
+        piece: usize,
+    ) -> io::Result<BlobChunks> {
+        match self {
+            BlobSource::Memory(blob) => {
+                let chunks: Vec<io::Result<Bytes>> = blob
+                    [start as usize..end as usize]
+                    .chunks(piece)
+                    .map(|c| Ok(Bytes::copy_from_slice(c)))
+                    .collect();
+                Ok(Box::pin(futures_util::stream::iter(chunks)))
+            }
+            BlobSource::File { path, .. } => {
+                let mut file = fs::File::open(path)?;
+                file.seek(SeekFrom::Start(start))?;
+                let mut left = end - start;
+                let pieces = std::iter::from_fn(move || {
+                    if left == 0 {
+                        return None;
+                    }
+                    let mut buf = vec![0; left.min(piece as u64) as usize];
+                    Some(match file.read_exact(&mut buf) {
+                        Ok(()) => {
+                            left -= buf.len() as u64;
+                            Ok(Bytes::from(buf))
+                        }
+                        Err(e) => {
+                            left = 0;
+                            Err(e)
+                        }
+                    })
+                });
+                Ok(Box::pin(futures_util::stream::iter(pieces)))
+            }
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+// `cargo test` builds the examples alongside the tests, so the example binary
+// sits next to this one's directory.
+fn example_path(name: &str) -> PathBuf {
+    let mut p = std::env::current_exe().unwrap();
+    p.pop();
+    if p.ends_with("deps") {
+        p.pop();
+    }
+    p.join("examples").join(name)
+}
+
+#[test]
+fn mock_roundtrip() {
+    let output = Command::new(example_path("mock_roundtrip"))
+        .output()
+        .expect("mock_roundtrip example should have been built");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Downloaded 9 bytes of Dune"), "{}", stdout);
+}
+
+#[test]
+fn upload_pdf() {
+    let output = Command::new(example_path("upload_pdf"))
+        .output()
+        .expect("upload_pdf example should have been built");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Uploaded Hello as version 1"), "{}", stdout);
+}
+
+#[test]
+fn watch_notifications() {
+    let output = Command::new(example_path("watch_notifications"))
+        .output()
+        .expect("watch_notifications example should have been built");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Added Hyperion"), "{}", stdout);
+    assert!(stdout.contains("Updated Dune to version 2"), "{}", stdout);
+}
@@ -0,0 +1,127 @@
+//! How much memory a large listing is held in, as the allocator counts it.
+//! The only test here, so that nothing else allocates while it counts.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use remarkable_cloud_api::{Document, Documents};
+use uuid::Uuid;
+
+// The system allocator, keeping count of the bytes allocated and not yet
+// freed.
+struct Counting;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        LIVE.fetch_add(
+            new_size as isize - layout.size() as isize,
+            Ordering::Relaxed,
+        );
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// What `make` returns, and the bytes it's held in.
+fn held<T>(make: impl FnOnce() -> T) -> (T, isize) {
+    let before = LIVE.load(Ordering::Relaxed);
+    let made = make();
+    (made, LIVE.load(Ordering::Relaxed) - before)
+}
+
+// A listing of `count` entries, a hundredth of them folders, as a server
+// sending every document's blob URL gives it.
+fn corpus(count: usize) -> String {
+    let folders: Vec<Uuid> = (0..count / 100)
+        .map(|n| Uuid::from_u128(n as u128 + 1))
+        .collect();
+    let entry = |id: Uuid, name: String, parent: Option<Uuid>, kind| {
+        serde_json::json!({
+            "ID": id,
+            "Version": 3,
+            "Message": "",
+            "Success": true,
+            "BlobURLGet": format!(
+                "https://storage.googleapis.com/remarkable-production-\
+                 document-storage/{}?GoogleAccessId=remarkable-production\
+                 %40appspot.gserviceaccount.com&Expires=1700000000\
+                 &Signature={}",
+                id,
+                "Zm9vYmFy".repeat(43)
+            ),
+            "BlobURLGetExpires": "2030-01-01T00:00:00Z",
+            "ModifiedClient": "2024-05-01T09:30:00.000000Z",
+            "Type": kind,
+            "VissibleName": name,
+            "CurrentPage": 0,
+            "Bookmarked": false,
+            "Parent": parent.map(|p| p.to_string()).unwrap_or_default(),
+        })
+    };
+    let mut entries: Vec<serde_json::Value> = folders
+        .iter()
+        .enumerate()
+        .map(|(n, id)| {
+            entry(*id, format!("Folder {}", n), None, "CollectionType")
+        })
+        .collect();
+    for n in 0..count - folders.len() {
+        let id = Uuid::from_u128((1 << 64) + n as u128);
+        let parent = folders[n % folders.len()];
+        let name = format!("Document {}", n);
+        entries.push(entry(id, name, Some(parent), "DocumentType"));
+    }
+    serde_json::to_string(&entries).unwrap()
+}
+
+#[test]
+fn listing_footprint() {
+    let body = corpus(20_000);
+
+    // Every document whole, blob URL and all, as listings used to be held.
+    let (whole, whole_bytes) = held(|| {
+        let documents: Vec<Document> = serde_json::from_str(&body).unwrap();
+        documents
+            .into_iter()
+            .map(|d| (d.id, d))
+            .collect::<HashMap<Uuid, Document>>()
+    });
+    let (documents, bytes) =
+        held(|| serde_json::from_str::<Documents>(&body).unwrap());
+    assert_eq!(documents.len(), whole.len());
+    assert!(documents.iter().all(|d| d.blob_url_get.is_none()));
+    assert!(
+        bytes * 10 <= whole_bytes * 6,
+        "{} bytes, against {} whole",
+        bytes,
+        whole_bytes
+    );
+
+    let approx = documents.approx_memory() as isize;
+    assert!(
+        (approx - bytes).abs() * 10 <= bytes,
+        "about {} bytes, but {} held",
+        approx,
+        bytes
+    );
+}
@@ -0,0 +1,146 @@
+//! The library never writes to stdout or stderr itself, as stray output
+//! corrupts the screen of a program drawing its own interface. What it has
+//! to say goes through `log`, or into its errors.
+//!
+//! libtest captures only what the `print!` macros write, so this runs
+//! without it: it runs itself again with `SILENCE_CHILD` set to go through
+//! the client's methods, and checks that child wrote nothing at all.
+
+use std::io::Write;
+use std::process::Command;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::{
+    Client, DocType, ListingCache, NamePolicy, Parent, RateLimiter,
+};
+use uuid::Uuid;
+
+const CHILD: &str = "SILENCE_CHILD";
+
+fn main() {
+    if std::env::var_os(CHILD).is_some() {
+        tokio::runtime::Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(round_trip());
+        return;
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .env(CHILD, "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "the round trip failed");
+    assert!(
+        output.stdout.is_empty() && output.stderr.is_empty(),
+        "the library wrote to stdio:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    println!("silence: ok");
+}
+
+// An empty page, as version 5 of the lines format writes it.
+fn rm() -> Vec<u8> {
+    let mut buf =
+        format!("{:<43}", "reMarkable .lines file, version=5").into_bytes();
+    buf.extend_from_slice(&1u32.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf
+}
+
+fn notebook(id: Uuid) -> Vec<u8> {
+    let content = serde_json::json!({
+        "fileType": "notebook",
+        "pageCount": 2,
+        "pages": ["a", "b"],
+    });
+    let files = vec![
+        (format!("{}.content", id), content.to_string().into_bytes()),
+        (format!("{}.pagedata", id), b"Blank\nBlank\n".to_vec()),
+        (format!("{}/a.rm", id), rm()),
+        (format!("{}.thumbnails/a.jpg", id), b"jpeg".to_vec()),
+    ];
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+    for (name, data) in &files {
+        zip.start_file(name, Default::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+// Every method which talks to the cloud, along with the paths which only
+// warn: retries, names which are too long, and a cache which can't be
+// written.
+async fn round_trip() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let notes = cloud.add_document("Notes", Some(books), vec![]);
+    cloud.modify(&notes, |d| d.blob = notebook(notes));
+
+    let mut client: Client = cloud.client();
+    let unwritable = std::env::temp_dir()
+        .join(format!("silence-{}", Uuid::new_v4()))
+        .join("missing")
+        .join("listing.json");
+    client.set_listing_cache(Some(ListingCache::at_path(unwritable)));
+    client.set_name_policy(Some(NamePolicy {
+        max_len: 8,
+        truncate: false,
+    }));
+    client.set_rate_limiter(Some(RateLimiter::new(10_000_000)));
+    client.refresh_token().await.unwrap();
+    client.ping().await.unwrap();
+    client.diagnostics();
+
+    client.get_documents().await.unwrap();
+    let doc = client.get_document_by_id(&notes).await.unwrap();
+    client
+        .get_document_by_id(&Uuid::new_v4())
+        .await
+        .unwrap_err();
+    client.download_blob(&doc).await.unwrap();
+    client.document_details(&notes).await.unwrap();
+    client.content_hash(&notes).await.unwrap();
+    client.verify_archive(&notes).await.unwrap();
+    client.pages(&doc).await.unwrap();
+    client.thumbnail(&doc, None).await.unwrap();
+
+    client.set_page_order(&doc, &[1, 0]).await.unwrap();
+    let doc = client.get_document_by_id(&notes).await.unwrap();
+    client.replace_page_strokes(&doc, 0, &rm()).await.unwrap();
+    let doc = client.get_document_by_id(&notes).await.unwrap();
+    client.set_pinned(&doc, true).await.unwrap();
+    client.set_bookmarked(notes, false).await.unwrap();
+    client.set_current_page(notes, 1).await.unwrap();
+    let doc = client.get_document_by_id(&notes).await.unwrap();
+    client
+        .move_document(&doc, Parent::Root, "Notes from a long meeting")
+        .await
+        .unwrap();
+
+    cloud.fail_next("/document-storage/json/2/upload/request", false);
+    let id = Uuid::new_v4();
+    client
+        .upload_zip(
+            id,
+            1,
+            Some(books),
+            "Sketches",
+            DocType::Document,
+            notebook(id),
+        )
+        .await
+        .unwrap();
+
+    let docs = client.get_documents().await.unwrap();
+    let folder = docs.get(&books).unwrap().clone();
+    cloud.fail_next("/document-storage/json/2/delete", false);
+    client
+        .delete_subtree(&folder, &docs, |_, _| {})
+        .await
+        .unwrap();
+    let doc = client.get_document_by_id(&notes).await.unwrap();
+    client.delete_document(&doc).await.unwrap();
+}
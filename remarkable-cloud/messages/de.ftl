@@ -0,0 +1,507 @@
+# German messages. Every id in src/messages.rs is here, with the same
+# placeholders; `cargo test` checks that.
+
+error = Fehler: { $error }
+error-max-time = Fehler: { $error } (--max-time)
+interrupted = Abgebrochen
+offline-hint = ls, find und export zeigen stattdessen die zwischengespeicherte Liste, falls es eine gibt; geändert werden kann nichts, bis die Cloud erreichbar ist.
+account-migrated =
+    Dieses Konto wurde auf reMarkables neueren Synchronisierungsdienst umgestellt.
+    Die Cloud bietet die API, die dieses Programm nutzt, nicht mehr an, daher
+    kann nichts aufgelistet oder geändert werden, bis dieser Dienst unterstützt wird.
+    Den Fortschritt verfolgen unter { $url }
+content-cache-not-saved = Der Inhaltszwischenspeicher konnte nicht gespeichert werden: { $error }
+redaction-map-not-written = Die Schwärzungstabelle konnte nicht geschrieben werden: { $error }
+lang-unknown = Es gibt keine Meldungen auf { $lang }, nur auf { $languages }
+lang-not-built = Dieses Programm hat nur englische Meldungen; andere brauchen das Feature i18n
+listing-not-cached = Die Liste konnte nicht zwischengespeichert werden: { $error }
+listing-changed-elsewhere = { $path } wurde seit der letzten Liste anderswo geändert (v{ $old } → v{ $new })
+listing-entry-unparsed = Listeneintrag { $index } konnte nicht gelesen werden: { $warning }
+listing-entries-unparsed =
+    { $count } { $count ->
+        [one] Eintrag konnte
+       *[other] Einträge konnten
+    } nicht gelesen werden; mit -v gibt es Einzelheiten
+listing-only-partial = { $error }; nur ein Teil der Liste ist zwischengespeichert, der Inhalt von { $folders }, den ls --shallow auflisten kann
+listing-partial-cached = Nur die Liste des Inhalts von { $folders } zwischengespeichert; andere Befehle holen sie ganz
+pull-no-such-id = Kein Dokument mit der ID { $id } gefunden
+pull-not-found = Dokument '{ $path }' nicht gefunden
+pull-ambiguous = { $path } passt auf { $count } Dokumente; einen genaueren Pfad, --id oder --all-matches angeben:
+pull-folder = { $path } ist ein Ordner; seinen Inhalt mit -r holen
+pull-no-file = Keine Datei in der Antwort für { $path }
+pull-no-pages = { $path } hat keine Seiten
+pull-no-filename = Kein Dateiname im Pfad { $path }
+pull-times-not-set = Die Änderungszeit von { $path } konnte nicht gesetzt werden, daher tragen geholte Dateien die Zeit des Holens: { $error }
+pull-linked = { $from } mit { $to } verknüpft
+pull-copied = { $from } nach { $to } kopiert
+push-verified-count = { $uploaded } hochgeladen, davon { $verified } überprüft
+push-not-verified = { $path } wurde nicht zurückgelesen, da größer als --verify-max-size
+push-verify-mismatch = { $path } wurde hochgeladen, aber die Kopie in der Cloud hat SHA-256 { $found } statt { $sent }; mit --on-conflict update erneut hochladen
+push-verify-missing = { $path } wurde hochgeladen, aber in der Kopie in der Cloud fehlt { $file }; mit --on-conflict update erneut hochladen
+push-verify-unreadable = { $path } wurde hochgeladen, aber die Kopie in der Cloud konnte nicht zurückgelesen werden: { $error }; mit --on-conflict update erneut hochladen
+sync-pulled = { $path } abgerufen
+sync-removed = { $path } entfernt, da in der Cloud gelöscht
+sync-nothing-to-pull = { $path } übersprungen, da es kein PDF oder EPUB zum Abrufen hat
+sync-conflicts-left =
+    { $count } { $count ->
+        [one] Datei wurde
+       *[other] Dateien wurden
+    } auf beiden Seiten geändert; sync resolve gleicht sie ab
+sync-up-to-date = Nichts aus { $folder } abzurufen
+sync-no-folder = { $dir } wurde noch nie abgeglichen; den Cloud-Ordner angeben, mit dem abgeglichen werden soll
+sync-other-folder = { $dir } wird mit { $folder } abgeglichen, nicht mit { $asked }
+sync-layout-changed = { $dir } ist nach { $layout } angeordnet; mit --relayout abrufen, um den Inhalt nach { $asked } zu verschieben, oder mit --layout { $layout }, um es so zu lassen
+sync-left-in-place = { $path } bleibt, wo es ist, da es nicht mehr in der Cloud ist
+sync-relaid =
+    Die Dateien von { $count } { $count ->
+        [one] Dokument
+       *[other] Dokumenten
+    } wurden verschoben, um { $dir } nach { $layout } anzuordnen
+watch-started =
+    { $count } { $count ->
+        [one] Dokument wird
+       *[other] Dokumente werden
+    } beobachtet; Änderungen ab jetzt werden gemeldet
+watch-unlisted = Die Dokumente konnten nicht aufgelistet werden: { $error }; neuer Versuch in { $wait }
+await-not-a-document = { $path } ist kein Dokument, auf das gewartet werden kann
+await-unlisted = Die Dokumente konnten nicht aufgelistet werden: { $error }
+await-not-served = { $path } ist in Version { $version } aufgelistet, aber die Cloud hat sie noch nicht
+await-not-fetched = { $path } konnte nicht abgerufen werden: { $error }
+await-nothing-came = In { $waited } kam nichts unter { $path } an
+await-still-old = { $path } war nach { $waited } noch in Version { $version }
+digest-not-built = Dieses Programm kann keine Übersichten erstellen; dafür braucht es das Feature digest
+digest-wrote =
+    { $pages } markierte { $pages ->
+        [one] Seite
+       *[other] Seiten
+    } aus { $documents } { $documents ->
+        [one] Dokument
+       *[other] Dokumenten
+    } nach { $path } geschrieben
+digest-none-since = In den letzten { $since } wurden keine Seiten markiert, daher gibt es keine Übersicht
+digest-none = Es sind keine Seiten markiert, daher gibt es keine Übersicht
+backup-reproducible-resumed = Eine reproduzierbare Sicherung kann nicht fortgesetzt werden
+backup-failed = { $path } konnte nicht gesichert werden: { $error }
+backup-plan-gone = { $path } ist seit der Planung aus der Cloud verschwunden; wird ausgelassen
+backup-incomplete-reproducible = { $count } Dokumente konnten nicht gesichert werden, und eine reproduzierbare Sicherung braucht sie alle
+restore-folder-created = Ordner { $path } angelegt
+restore-restored = { $path } wiederhergestellt
+restore-unnamed = (ohne Namen)
+restore-archive-version = Version { $version }
+restore-archive-version-unknown = Version unbekannt
+restore-archive = { $file }: { $name }, { $version }
+restore-archive-modified = { $file }: { $name }, { $version }, geändert { $modified }
+restore-no-such-version = Kein Archiv hat die Version { $version }
+restore-none-by-date = Kein Archiv ist vom { $date } oder früher
+restore-which = Welches wiederherstellen? [1-{ $count }]
+restore-nothing = Nichts wiederhergestellt
+restore-done = { $restored } Dokumente wiederhergestellt, { $created } Ordner angelegt, { $skipped } bereits vorhandene übersprungen
+restore-no-archives = { $dir } enthält keine Archive von Dokumenten
+restore-picked-version = { $file } als Version { $version } von { $name } wiederhergestellt
+restore-picked-new = { $file } als neues Dokument { $name } wiederhergestellt
+cache-refreshed = für den nächsten Lauf aufgefrischt
+cache-refresh-locked = nicht aufgefrischt, da ein anderer Prozess sie benutzte
+cache-refresh-failed = konnte nicht aufgefrischt werden: { $error }
+cache-was-old = (Zwischenspeicher war { $age } alt; { $how })
+cache-warmed = { $downloaded } Dokumente zwischengespeichert ({ $cached } schon zwischengespeichert, { $unreadable } unlesbar); { $pruned } seither entfernte oder geänderte vergessen
+ago-just-now = gerade eben
+ago-minutes =
+    vor { $count } { $count ->
+        [one] Minute
+       *[other] Minuten
+    }
+ago-hours =
+    vor { $count } { $count ->
+        [one] Stunde
+       *[other] Stunden
+    }
+ago-days =
+    vor { $count } { $count ->
+        [one] Tag
+       *[other] Tagen
+    }
+offline-cached = offline — zeige zwischengespeicherte Daten von { $ago }
+field-unknown = Unbekanntes Feld { $field }; die Felder sind { $fields }
+content-type-unchecked = { $count } Dokumente wurden nicht auf --content-type geprüft, da ihr Inhalt nicht zwischengespeichert ist; `cache warm` speichert ihn
+download-failed = Konnte { $path } nicht herunterladen: { $error }
+duration-invalid = Ungültige Dauer { $duration }: { $error }
+time-too-far-back = Zu weit in der Vergangenheit: { $time }
+time-invalid = Ungültige Zeit { $time }, erwartet ein Datum wie 2024-01-01 oder eine Dauer wie "2 weeks ago"
+pattern-unclosed = Nicht geschlossenes '[' im Muster { $pattern }
+operation-log-failed = Konnte nicht ins Vorgangsprotokoll schreiben: { $error }
+layout-unknown = unbekanntes Layout { $layout }
+is-a-folder = { $path } ist ein Ordner
+limits-strict =
+    Abgebrochen, bevor etwas geändert wurde: { $count } { $count ->
+        [one] Stelle würde
+       *[other] Stellen würden
+    } die Ordnergrenzen überschreiten; ohne --strict geht es trotzdem weiter
+limits-past =
+    { $count } { $count ->
+        [one] Stelle
+       *[other] Stellen
+    } über den Ordnergrenzen
+limits-none-past = Keine Ordner über den Grenzen
+lock-waiting = Warte, bis { $holder } fertig ist
+lock-holder-pid = ein anderer remarkable-cloud-Prozess (pid { $pid })
+lock-holder = ein anderer remarkable-cloud-Prozess
+lock-held-pid = ein anderer remarkable-cloud-Prozess hält die Sperre (pid { $pid }); mit --wait-lock wird darauf gewartet
+lock-held = ein anderer remarkable-cloud-Prozess hält die Sperre; mit --wait-lock wird darauf gewartet
+phase-listing = Auflisten…
+phase-planning = Planen ({ $compared } Dokumente verglichen)…
+phase-transferring = Übertrage { $documents } Dokumente…
+phase-resuming = Setze den gespeicherten Plan fort ({ $checked } Dokumente geprüft)…
+plan-other-version = Der Plan in { $path } hat die Version { $found }, die diese Version nicht fortsetzen kann (sie speichert Version { $expected }); entferne ihn, um neu zu planen
+plan-none = In { $path } ist kein Plan gespeichert; plane neu
+plan-moved = { $path } hat sich seit der Planung geändert; plane es neu
+file-unreadable = Konnte { $path } nicht lesen: { $error }
+file-unparsable = Konnte { $path } nicht auswerten: { $error }
+sort-locale-not-built = Dieser Build kann nicht nach Gebietsschema sortieren; dafür braucht es das Feature locale-sort
+sort-unknown = Unbekannte Sortierung { $order }
+summary-transferred =
+    { $count } { $count ->
+        [one] Datei
+       *[other] Dateien
+    } übertragen, { $bytes } in { $secs } s
+summary-skipped-failed = ; { $skipped } übersprungen, { $failed } fehlgeschlagen
+push-conflict-skipped = { $name } übersprungen: { $existing } ist schon da; mit --on-conflict lässt sich wählen, was geschehen soll
+sync-conflict-left = { $path } belassen, da es { $kind } war; mit --strategy lässt sich wählen, was bleibt
+conflict-skipped = { $name } übersprungen: { $existing } ist schon da
+sync-conflict-kept = { $path } belassen, da es { $kind } war
+value-not-one-of = { $value } ist keins von { $values }
+pull-writing = DEBUG: { $path }
+push-directory = { $path } ist ein Verzeichnis; sein Inhalt lässt sich mit -r hochladen
+push-unoptimized = Lade { $name } unverändert hoch: { $error }
+push-resume-finished = { $name } fertiggestellt
+push-resume-changed = { $name } zurückgenommen: { $source } hat sich geändert oder ist fort
+push-resume-unrereadable = { $name } zurückgenommen: es kam von stdin oder wurde optimiert und lässt sich nicht noch einmal lesen
+push-interrupted = Hinweis: { $count } unterbrochene Uploads; `push --resume` stellt sie fertig
+pushed = { $path } hochgeladen
+pushed-as = { $path } als { $name } hochgeladen
+pushed-as-version = { $path } als Version { $version } von { $name } hochgeladen
+folder-created = Ordner { $path } angelegt
+push-unsupported = Nicht hochgeladen, da weder PDFs noch EPUBs:
+push-ask-existing = { $name } ist schon da (v{ $version }, geändert { $modified }).
+push-ask = { $name } trotzdem hochladen? [S]kip: überspringen, [u]pdate: aktualisieren, [r]ename: in { $free } umbenennen, [d]uplicate: duplizieren:
+push-not-pdf = { $name } sieht nicht wie ein PDF aus
+push-not-epub = { $name } sieht nicht wie ein EPUB aus
+push-neither = { $name } ist weder ein PDF noch ein EPUB
+no-usable-name = { $name } hat keinen brauchbaren Namen
+sync-not-synced = { $dir } wurde nicht abgeglichen: es hat keine { $manifest }
+sync-unchanged = Unverändert
+sync-changed-here = Hier geändert
+sync-changed-in-cloud = In der Cloud geändert
+sync-changed-both = Auf beiden Seiten geändert
+sync-only-here = Nur hier
+sync-only-in-cloud = Nur in der Cloud
+sync-deleted-here = Hier gelöscht
+sync-deleted-in-cloud = In der Cloud gelöscht
+sync-deleted-both = Auf beiden Seiten gelöscht
+sync-not-a-folder = { $dir } wird mit { $folder } abgeglichen, das kein Ordner ist
+sync-folder-gone = { $dir } wird mit { $folder } abgeglichen, das aus der Cloud verschwunden ist
+conflict-both-changed = auf beiden Seiten geändert
+conflict-both-added = auf beiden Seiten hinzugefügt
+conflict-changed-here-deleted-there = hier geändert, aber in der Cloud gelöscht
+conflict-deleted-here-changed-there = hier gelöscht, aber in der Cloud geändert
+strategy-unknown = unbekannte Strategie { $strategy }
+sync-ask = { $path } wurde { $kind }. Behalten: [c]loud: aus der Cloud, [l]ocal: von hier, [b]oth: beide, oder [S]kip: überspringen:
+sync-document-gone = { $path } ist aus der Cloud verschwunden
+sync-no-format = { $path } in der Cloud hat kein { $format } zum Herunterladen
+sync-kept-both = Beide Fassungen von { $path } behalten: die aus der Cloud dort, diese als { $copy }
+sync-kept-cloud = { $path } aus der Cloud behalten
+sync-kept-local = { $path } von hier behalten
+resolve-resumed = Setze die zuvor unterbrochene Auflösung fort
+resolve-strategy-ignored = --strategy ignoriert: über die Konflikte wurde schon entschieden
+resolve-nothing = Nichts in { $dir } steht in Konflikt
+resolve-left = { $count } Konflikte belassen, wie sie waren
+sync-paths-differ =
+    { $count } { $count ->
+        [one] Pfad weicht
+       *[other] Pfade weichen
+    } seit dem letzten Abgleich ab
+sync-in-step = { $dir } ist auf dem Stand von { $folder }
+problems = { $count } Probleme mit der Befehlszeile:
+flags-together = { $flags } lassen sich nicht zusammen verwenden: { $why }
+preflight-raw-zip-format = --raw-zip speichert das Archiv, wie die Cloud es hat, gleich welches Format; eins von beiden weglassen
+preflight-resume-to = --resume stellt Uploads dort fertig, wo sie begonnen wurden; --to weglassen
+preflight-resume-on-conflict = --resume stellt Uploads fertig, wie sie begonnen wurden, samt Konflikten; --on-conflict weglassen
+preflight-resume-recursive = --resume stellt nur schon begonnene Uploads fertig; --recursive weglassen
+preflight-resume-create-missing = --resume stellt Uploads in schon bestehende Ordner fertig; --create-missing weglassen
+preflight-queue-create-missing = --queue verbindet sich nicht mit der Cloud, um Ordner anzulegen; zuerst push --create-missing einmal ohne --queue ausführen
+preflight-resume-strict = --resume stellt nur schon begonnene Uploads in schon angelegte Ordner fertig; --strict weglassen
+preflight-queue-strict = --queue verbindet sich nicht mit der Cloud, um ihre Ordner zu sehen; stattdessen push --strict ohne --queue ausführen
+preflight-stdin-recursive = --stdin liest ein einzelnes Dokument; --recursive weglassen
+preflight-resume-optimize = --resume stellt Uploads fertig, wie sie begonnen wurden, optimiert oder nicht; --optimize weglassen
+preflight-queue-optimize = Uploads aus der Warteschlange werden unverändert hochgeladen; --optimize weglassen oder ohne --queue hochladen
+preflight-stdin-optimize = --optimize arbeitet auf Dateien; das Hereingeleitete in einer Datei speichern und diese hochladen
+preflight-resume-verify = --resume stellt Uploads fertig, ohne sie zurückzulesen; --verify weglassen und sie stattdessen mit `info --verify` prüfen
+preflight-queue-verify = Uploads aus der Warteschlange werden nicht zurückgelesen; --verify weglassen oder ohne --queue hochladen
+preflight-pick-keep-ids = --pick behält die ID des Dokuments, wenn es noch existiert, und kann es sonst nicht; --keep-ids weglassen
+preflight-version-date = beide wählen für sich ein Archiv; eins von beiden weglassen
+preflight-trash = Dokumente lassen sich von hier nicht in den Papierkorb legen; rm verschiebt sie dorthin
+preflight-no-such-file = keine solche Datei
+preflight-push-directory = ist ein Verzeichnis; sein Inhalt lässt sich mit -r hochladen
+preflight-not-pushable = nur PDFs und EPUBs lassen sich hochladen; der Name muss auf .pdf oder .epub enden
+preflight-output-directory = ist ein Verzeichnis; einen Dateinamen darin angeben
+preflight-no-such-parent = { $dir } ist kein bestehendes Verzeichnis
+preflight-not-a-file = ist keine Datei
+preflight-not-a-directory = ist kein Verzeichnis
+preflight-no-such-directory = kein solches Verzeichnis
+setup-not-registered = Konnte mit diesem Code nicht registrieren; er ist vielleicht vertippt oder schon benutzt: { $error }
+setup-registered = Dieser Computer ist registriert.
+setup-signed-in = Angemeldet: die Cloud enthält { $count } Dokumente und Ordner.
+setup-already-registered = Dieser Computer ist schon registriert; mit --code wird er erneut registriert.
+setup-get-code = Um diesen Computer mit dem Konto zu registrieren, einen Einmalcode holen unter { $url }
+setup-ask-code = Einmalcode (leer lassen zum Überspringen):
+setup-code-skipped = Registrierung übersprungen; setup erneut ausführen, sobald ein Code da ist.
+setup-credentials-file = Die Zugangsdaten liegen in { $path }; dieser Build kann sie nicht im Schlüsselbund ablegen.
+setup-settings-kept = Behalte die Einstellungen, die schon in { $path } stehen.
+setup-ask-settings = Starteinstellungen nach { $path } schreiben?
+setup-settings-skipped = Einstellungen übersprungen.
+setup-settings-written = { $path } geschrieben, jede Einstellung auf ihrem Standardwert.
+setup-no-completions = Shell-Vervollständigungen übersprungen, da es für { $shell } keine gibt.
+setup-completions-installed = Shell-Vervollständigungen sind schon in { $path } installiert.
+setup-ask-completions = Shell-Vervollständigungen in { $path } installieren?
+setup-completions-skipped = Shell-Vervollständigungen übersprungen.
+setup-completions-written = Shell-Vervollständigungen installiert, für ab jetzt gestartete Shells.
+history-cached = (zwischengespeichert: { $value })
+history-root = (Wurzel)
+written-by-no-metadata = unbekannt, da das Archiv keine .metadata hat
+written-by-tablet-pending = das Tablet, mit noch nicht abgeglichenen Änderungen
+written-by-tablet-synced = das Tablet, auf dem Stand der Cloud
+written-by-tablet-unsynced = das Tablet, noch nicht abgeglichen
+written-by-unknown = unbekannt, da .metadata nicht sagt, ob es abgeglichen ist (so wie bei Skripten und manchen anderen Clients)
+blob-url-none = keine angegeben
+blob-url-fresh = frisch, läuft ab in { $left }
+blob-url-expired = abgelaufen um { $at }
+history-version = Version
+history-modified = geändert
+history-name = Name
+history-parent = Elternordner
+history-bookmarked = Lesezeichen
+history-current-page = aktuelle Seite
+history-metadata-version = Metadaten-Version
+history-listed = (Auflistung: { $version })
+history-written-by = geschrieben von
+history-blob-url = Blob-URL
+history-not-cached = nicht in der zwischengespeicherten Auflistung
+history-same-as-cached = wie in der zwischengespeicherten Auflistung
+verdict-ok = ok
+verdict-auth = Anmeldeproblem
+verdict-gone = Endpunkt fort
+verdict-drift = Schema geändert
+verdict-skipped = übersprungen
+verdict-failed = fehlgeschlagen
+drift-unknown = unbekannt: { $field } in { $count } von { $entries }
+drift-missing = fehlt: { $field } in { $count } von { $entries }
+doctor-needs-token = braucht ein Token
+doctor-documents = { $count } Dokumente
+doctor-entries-unread = { $count } Einträge konnten nicht gelesen werden
+doctor-needs-listing = braucht die Auflistung
+doctor-no-documents = keine Dokumente zum Ansehen
+doctor-read-bytes = die ersten { $count } Bytes gelesen
+doctor-empty = leer
+doctor-no-document = kein Dokument zum Herunterladen
+doctor-read-only = schreibgeschützt
+doctor-check = PRÜFUNG
+doctor-result = ERGEBNIS
+doctor-detail = DETAIL
+doctor-auth-advice = Die Cloud hat das Token dieses Geräts abgelehnt. Erneut anmelden, um ein neues zu bekommen.
+doctor-gone-advice = Die Cloud antwortet nicht mehr dort, wo diese Version von remarkable-cloud es erwartet: das Protokoll hat sich weiterentwickelt. Nach einer neueren Version schauen und { $url } lesen
+doctor-drift-advice = Die Antworten der Cloud haben ihre Form geändert, und Dokumente, die diese Version nicht lesen kann, fehlen. Nach einer neueren Version schauen oder die obigen Felder melden.
+doctor-failed-advice = Einige Prüfungen schlugen aus anderen, oben genannten Gründen fehl; ist das Netz weg, es erneut versuchen, sobald es wieder da ist.
+doctor-problems = { $problems } von { $checks } Prüfungen fanden Probleme
+in-unknown = In: unbekannt, da { $error }
+in-root = In: der Wurzel
+in-folders = In: { $folders }
+pinned-from-listing = pinned ist das Lesezeichen-Flag der Auflistung, da die .metadata des Dokuments kein pinned-Feld hat (ältere Firmware)
+content-pages = { $count } Seiten
+content-no-pages = keine Seiten
+content-strokes = { $count } Striche
+content-strokes-unreadable = Striche unlesbar
+content-summary = { $path }: { $pages }, { $strokes }, { $bytes } Bytes
+verify-intact = intakt
+verify-damaged = beschädigt
+verify-check-failed = FEHLER  { $reason }
+verify-hash-unavailable = nicht verfügbar
+verify-hash = Hash
+not-a-document = { $path } ist kein Dokument
+document-unreadable = Konnte { $path } nicht lesen: { $error }
+verify-folder = { $path } ist ein Ordner; mit -r wird sein Inhalt geprüft
+verify-totals = { $count } Dokumente geprüft: { $intact } intakt, { $damaged } beschädigt, { $unread } nicht heruntergeladen
+verify-failed = { $failed } von { $count } Dokumenten bestanden die Prüfung nicht
+matched-nothing-stop = { $pattern } passte auf nichts; mit --allow-empty geht es trotzdem weiter
+matched-nothing = { $pattern } passte auf nichts
+confirm-continue = Fortfahren? [y/N]
+cached-target-gone = { $path }: Dokument seit dem Zwischenspeichern gelöscht oder im Papierkorb, ohne --cached erneut ausführen
+cached-target-changed = { $path }: Dokument seit dem Zwischenspeichern geändert (v{ $old } → v{ $new }), ohne --cached erneut ausführen
+cached-target-deleted = { $path }: Dokument seit dem Zwischenspeichern gelöscht, ohne --cached erneut ausführen
+nothing-done = Nichts getan.
+move-into-itself = Kann { $path } nicht in sich selbst verschieben
+confirm-move = Das verschiebt { $count } Dokumente:
+confirm-trash = Das legt { $count } Dokumente in den Papierkorb:
+confirm-delete = Das löscht { $count } Dokumente endgültig:
+confirm-pin = Das heftet { $count } Dokumente an:
+confirm-unpin = Das löst { $count } Dokumente:
+moved = { $path } verschoben
+move-failed = Konnte { $path } nicht verschieben: { $error }
+trashed = { $path } in den Papierkorb gelegt
+trash-failed = Konnte { $path } nicht in den Papierkorb legen: { $error }
+rm-folder-not-empty = { $path } ist ein Ordner, der nicht leer ist; stattdessen in den Papierkorb legen oder seinen Inhalt mit angeben
+deleted = { $path } gelöscht
+pin-folder = { $path } ist ein Ordner; nur Dokumente lassen sich anheften
+unpin-folder = { $path } ist ein Ordner; nur Dokumente lassen sich lösen
+pinned = { $path } angeheftet
+unpinned = { $path } gelöst
+delete-failed = Konnte { $path } nicht löschen: { $error }
+delete-skipped = { $path } übersprungen
+delete-remaining = { $count } Dokumente sind noch da; für einen neuen Versuch erneut ausführen
+change-failed = { $failed } von { $count } Dokumenten wurden nicht geändert; für einen neuen Versuch erneut ausführen
+nothing-to-delete = Nichts zu löschen
+would-delete = Würde { $path } löschen
+status-unknown = unbekannt
+status-expires-in = { $at }, in { $left }
+status-expired = { $at } (abgelaufen)
+status-none = keins
+status-yes = ja
+status-no = nein
+status-endpoint = Endpunkt: { $endpoint }
+status-account = Konto: { $account }
+status-token-expires = Token läuft ab: { $expires }
+status-device-token = Geräte-Token: { $token }
+status-read-only = Schreibgeschützt: { $read_only }
+status-uploads = Uploads: { $attempts } Versuche, neuer Versuch nach { $retry }
+status-official = offiziell
+status-protocol = Protokoll: { $generation }
+status-dialect = Dialekt: { $dialect }
+status-notifications = Benachrichtigungen: { $notifications }
+status-uploads-supported = Uploads unterstützt: { $uploads }
+status-largest-blob = Größter bekannt guter Blob: { $size }
+status-reached = Cloud erreicht in { $latency }
+mapping-rule = die Upload-Zuordnung für { $rule }
+mapping-default = den Standardordner für Uploads
+mapping-goes-to = { $file } geht nach { $folder }, durch { $by }
+mapping-folders-missing = Diese Ordner aus den Upload-Einstellungen gibt es nicht: { $folders }; mit --create-missing werden sie angelegt
+mapping-folder-unmade = { $folder } gibt es nicht, und nur Ordner, die mit ihrem Pfad ab der Wurzel benannt sind, lassen sich anlegen
+mapping-not-absolute = Die Upload-Zuordnung für { $path } braucht einen absoluten Pfad oder einen, der mit ~ beginnt
+mapping-no-home = Kann das Home-Verzeichnis für die Upload-Zuordnung { $path } nicht finden
+queue-already-queued = { $source } steht schon als Auftrag { $job } dorthin in der Warteschlange
+queued = { $file } als Auftrag { $job } eingereiht
+job-pending = wartend
+job-uploading = lädt hoch
+job-done = fertig
+job-skipped = übersprungen
+job-failed = gescheitert
+job-attempts = ({ $attempts } gescheiterte Versuche, zuletzt: { $error })
+queue-name-taken = { $name } übersprungen: der Name ist vergeben
+queue-will-retry = Versuche { $name } erneut: { $error }
+queue-failed = { $name } gescheitert: { $error }
+queue-conflict = { $name } ist schon da; mit --on-conflict erneut einreihen
+queue-source-changed = { $name } hat sich seit dem Einreihen geändert
+trying-again = Neuer Versuch in { $wait }
+queue-run = { $done } hochgeladen, { $skipped } übersprungen, { $failed } gescheitert, { $retry } für einen neuen Versuch
+queue-not-emptied = Nicht alles Eingereihte wurde hochgeladen; siehe `queue list`
+serve-listening = Lausche auf http://{ $address }
+serve-shutting-down = fahre herunter
+serve-unauthorized = das Token aus { $file } als Bearer-Token angeben
+serve-no-document = kein solches Dokument
+serve-no-job = kein solcher Auftrag
+serve-no-route = keine solche Route; GET / listet sie auf
+serve-no-payload = das Dokument hat kein PDF oder EPUB
+find-pages-unsupported = seine Seiten sind in einem nicht unterstützten Format
+find-trash = { $path } lässt sich mit find nicht durchsuchen
+find-duplicates-offline = --duplicates-of braucht die Cloud, die nicht erreichbar ist; --by-name nicht
+find-compared = { $downloaded } Dokumente zum Vergleichen heruntergeladen ({ $unchecked } wegen --limit nicht geprüft, { $unreadable } unlesbar)
+find-notes-offline = --with-notes braucht die Cloud, die nicht erreichbar ist
+find-deep-offline = --empty und --pinned brauchen die Cloud, die nicht erreichbar ist
+find-checked = { $downloaded } Dokumente zum Prüfen auf --empty oder --pinned heruntergeladen ({ $unchecked } wegen --limit nicht geprüft, { $unreadable } unlesbar)
+note-not-allowed = { $path } kann keine Notiz haben
+noted = Notiz zu { $path } gespeichert
+note-removed = Notiz zu { $path } entfernt
+no-note = { $path } hat keine Notiz
+notes-none-to-prune = Keine Notizen zum Aufräumen
+protocol-unknown = Unbekanntes Bildprotokoll { $protocol }
+inline-not-built = Dieser Build kann keine Bilder im Terminal zeigen
+inline-not-terminal = Die Ausgabe geht nicht an ein Terminal
+inline-undetected = Das Terminal scheint keine Bilder zu zeigen; --inline-protocol versuchen
+peek-saved = Nach { $path } gespeichert
+peek-opened = { $path } geöffnet
+peek-viewer-failed = Konnte { $viewer } nicht ausführen ({ $error }); das Vorschaubild liegt unter { $path }
+thumbnail-not-jpeg = Das Vorschaubild ist kein JPEG
+no-thumbnails = { $path } hat keine Vorschaubilder
+peek-opening-instead = { $why }; öffne stattdessen das Vorschaubild
+peek-page = Seite { $page }: { $done }
+mutation-not-recorded = Konnte die Änderung nicht in { $path } festhalten: { $error }
+undo-damaged = sein Protokolleintrag ist beschädigt
+undo-upload = Hochladen lässt sich nicht rückgängig machen
+undo-delete = Löschen lässt sich nicht rückgängig machen
+undo-no-version = sein Protokolleintrag hat keine Version
+undo-nothing = Nichts rückgängig zu machen
+undone = Rückgängig gemacht: { $change }
+undo-changed = { $change } übersprungen: seitdem geändert, jetzt in Version { $version }
+undo-gone = { $change } übersprungen: existiert nicht mehr
+undo-skipped = { $change } übersprungen: { $reason }
+optimize-not-smaller = { $name } unverändert gelassen: Optimieren hat es nicht verkleinert
+optimized = { $name } optimiert: { $before } auf { $after }
+optimized-downsampling =
+    { $name } optimiert: { $before } auf { $after }, { $count } { $count ->
+        [one] Bild
+       *[other] Bilder
+    } herunterskaliert
+optimize-not-built = Dieser Build kann keine PDFs optimieren; dafür braucht es das Feature optimize
+optimize-unreadable = Es ließ sich nicht als PDF lesen: { $error }
+optimize-encrypted = Es ist verschlüsselt
+optimize-unwritable = Es ließ sich nicht wieder schreiben: { $error }
+optimize-unrereadable = Das Ergebnis des Optimierens ließ sich nicht wieder lesen: { $error }
+optimize-lost-pages = Beim Optimieren gingen Seiten verloren
+optimize-changed-text = Das Optimieren hat seinen Text verändert
+watch-event = { $event } { $path } (Version { $version })
+webhook-sent = { $event } an { $url } gesendet
+webhook-duplicate = { $url } hatte { $event } schon erhalten
+webhook-spooled = Konnte { $event } nicht an { $url } senden: { $reason }; es wartet auf watch --replay-spool
+push-stdin-terminal = --stdin braucht das Dokument über eine Pipe, nicht von einem Terminal
+serve-remote = { $listen } ist von anderen Rechnern erreichbar; mit --allow-remote trotzdem dort lauschen
+serve-token-in = Anfragen brauchen das Token aus { $path }
+webhook-no-secret = Webhooks brauchen ein webhook_secret zum Signieren des Gesendeten; es lässt sich in { $path } setzen
+backed-up = { $documents } Dokumente und { $folders } Ordner gesichert ({ $resumed } aus einem früheren Lauf übernommen, { $failed } fehlgeschlagen)
+template-at = { $message } (an Position { $position })
+template-unknown = Unbekannter Platzhalter { $placeholder }; erwartet wird einer von { $expected }
+template-no-width = Platzhalter { $placeholder } nimmt keine Breite
+template-width-invalid = Ungültige Breite { $width } für { $placeholder }
+template-unclosed = Nicht geschlossene '{ $brace }'; '{ $escaped }' steht für eine geschweifte Klammer
+template-unmatched = Nicht geöffnete '{ $brace }'; '{ $escaped }' steht für eine geschweifte Klammer
+template-unavailable = Platzhalter { $placeholder } ist hier nicht verfügbar
+template-no-value = Kein Wert für Platzhalter { $placeholder }
+template-repeated = Die Namensvorlage ergab { $name } mehr als einmal; ein Platzhalter wie { $id } hält die Ausgaben auseinander
+pages-not-a-number = { $page } ist keine Seitenzahl
+pages-no-such = in einem Dokument mit { $count } Seiten gibt es keine Seite { $page }
+pages-twice = Seite { $page } ist doppelt angegeben
+pages-missing = Seite { $page } fehlt; alle Seiten angeben, oder sie stattdessen löschen
+pages-was = (war { $page })
+pages-deleted = { $count } Seiten von { $path } gelöscht
+pages-reordered = Seiten von { $path } neu geordnet
+stats-report = { $path }: { $pages } Seiten, { $strokes } Striche, { $ink } px Tinte, { $layers } Ebenen
+stats-page-unreadable = Seite { $page } ließ sich nicht lesen
+stats-unread = { $failed } von { $count } Dokumenten ließen sich nicht herunterladen
+webhook-answered = { $url } antwortete { $status }
+spool-replayed = Warteschlange abgespielt: { $delivered } zugestellt, { $duplicates } schon gesendet, { $failed } schlagen weiter fehl
+spool-undelivered = Nicht alles aus der Warteschlange wurde zugestellt
+digest-no-ink = Auf seinen Seiten ist nichts gezeichnet
+digest-left-out = { $path } aus dem Digest weggelassen: { $error }
+digest-no-page = { $title } hat keine Seite { $page }
+digest-unwritable = Der Digest ließ sich nicht schreiben: { $error }
+digest-page-unreadable = Eine Seite ließ sich nicht lesen: { $error }
+digest-encrypted = Sein PDF ist verschlüsselt
+export-folder = { $path } ist ein Ordner; seinen Inhalt mit -r exportieren
+export-neither = { $path } lässt sich weder als { $format } noch als { $fallback } exportieren
+export-unsupported = { $path } lässt sich nicht als { $format } exportieren; für solche Dokumente --fallback angeben
+export-fell-back = { $path } lässt sich nicht als { $format } exportieren, daher als { $fallback }
+export-nothing = { $path } hat nichts zu exportieren
+progress-checked = Geprüft
+progress-hashed = Gehasht
+progress-read = Gelesen
+progress-deleted = Gelöscht
+progress-cached = Zwischengespeichert
+progress-downloaded = Heruntergeladen
+progress-verified = Überprüft
+progress-uploaded = Hochgeladen (MiB)
@@ -0,0 +1,226 @@
+//! `await-document`: waiting until a document is in the cloud, for scripts
+//! which upload with something else and carry on once it's there.
+//!
+//! The listing is fetched every `--interval`, give or take a fifth so that
+//! several waiting at once don't all ask together, until a document is at
+//! the path, at `--min-version` or later if given. The cloud can list a
+//! document before it answers for it by id, or answer with the version
+//! before the one listed, so what the listing shows is checked by fetching
+//! the document itself, and only reported once that agrees. This client
+//! doesn't speak the cloud's notifications, so it only ever polls.
+
+use std::error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use remarkable_cloud_api::{
+    CancellationToken, Client, CloudPath, Error, Resolved,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::help::Example;
+use crate::resolved::ResolvedTree;
+use crate::{msg, CliResult};
+
+/// How long `await-document` waits between looks, unless told otherwise.
+pub const DEFAULT_INTERVAL: &str = "5s";
+
+/// How many seconds `await-document` waits in all, unless told otherwise.
+pub const DEFAULT_TIMEOUT: u64 = 300;
+
+/// What `await-document` exits with when it gives up, as `timeout(1)` does.
+pub const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+/// The examples `await-document --help` shows.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        command: "remarkable-cloud await-document Scans/receipt",
+        description: "Waits up to five minutes for Scans/receipt, and \
+                      prints its id and version as JSON",
+    },
+    Example {
+        command: "remarkable-cloud await-document Scans/receipt \
+                  --min-version 3 --timeout 60",
+        description: "Waits up to a minute for Scans/receipt to be updated \
+                      to version 3",
+    },
+];
+
+/// What `await-document` was asked to wait for.
+pub struct AwaitOptions {
+    pub path: CloudPath,
+    /// The earliest version which will do; any, if none.
+    pub min_version: Option<u64>,
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// The document waited for, as `await-document` prints it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Found {
+    pub id: Uuid,
+    pub version: u64,
+    pub path: String,
+}
+
+/// The error `await_document` gives up with when the timeout passes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TimedOut {
+    pub path: String,
+    pub timeout: Duration,
+    /// The version last seen at the path, if anything was there.
+    pub seen: Option<u64>,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let waited = humantime::format_duration(self.timeout).to_string();
+        let said = match self.seen {
+            None => {
+                msg!(AWAIT_NOTHING_CAME, path = &self.path, waited = waited)
+            }
+            Some(version) => msg!(
+                AWAIT_STILL_OLD,
+                path = &self.path,
+                version = version,
+                waited = waited,
+            ),
+        };
+        f.write_str(&said)
+    }
+}
+
+impl error::Error for TimedOut {}
+
+/// `interval` lengthened or shortened by up to a fifth, by `roll`, which is
+/// taken as a fraction of `u32::MAX`.
+pub fn jittered(interval: Duration, roll: u32) -> Duration {
+    let fraction = f64::from(roll) / f64::from(u32::MAX);
+    interval.mul_f64(0.8 + 0.4 * fraction)
+}
+
+// What's at the path in the listing, if it's a document or folder: its id,
+// version and path as the cloud has it.
+fn listed(
+    documents: &ResolvedTree,
+    path: &CloudPath,
+) -> CliResult<Option<(Uuid, u64, String)>> {
+    match documents.lookup(path) {
+        Ok(Resolved::Document(d)) => {
+            let at = documents.path_of(&d.id).unwrap_or(path.to_string());
+            Ok(Some((d.id, d.version, at)))
+        }
+        Ok(_) => {
+            Err(msg!(AWAIT_NOT_A_DOCUMENT, path = path.to_string()).into())
+        }
+        Err(Error::PathNotFound { .. }) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Waits until a document is at `options.path`, at `options.min_version` or
+/// later, failing with `TimedOut` once `options.timeout` has passed.
+/// Failures to reach the cloud are warned about and tried again until then.
+pub async fn await_document(
+    client: &Client,
+    options: &AwaitOptions,
+    cancellation: &CancellationToken,
+    out: &mut dyn Output,
+) -> CliResult<Found> {
+    let deadline = Instant::now() + options.timeout;
+    let min_version = options.min_version.unwrap_or(0);
+    let mut seen = None;
+    loop {
+        let found = match client.get_documents().await {
+            Ok(documents) => {
+                let documents = ResolvedTree::new(documents);
+                listed(&documents, &options.path)?
+            }
+            Err(e) if e.is_transient() => {
+                out.warn(&msg!(AWAIT_UNLISTED, error = e.to_string()));
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if let Some((id, version, path)) = found {
+            seen = Some(version);
+            if version >= min_version {
+                match client.get_document_by_id(&id).await {
+                    Ok(doc) if doc.version >= min_version => {
+                        return Ok(Found {
+                            id,
+                            version: doc.version,
+                            path,
+                        });
+                    }
+                    // Kept off stdout, which the document is printed to.
+                    Ok(_) | Err(Error::EmptyResult) => out.warn(&msg!(
+                        AWAIT_NOT_SERVED,
+                        path = options.path.to_string(),
+                        version = version,
+                    )),
+                    Err(e) if e.is_transient() => out.warn(&msg!(
+                        AWAIT_NOT_FETCHED,
+                        path = options.path.to_string(),
+                        error = e.to_string(),
+                    )),
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::default() {
+            return Err(Box::new(TimedOut {
+                path: options.path.to_string(),
+                timeout: options.timeout,
+                seen,
+            }));
+        }
+        let roll = Uuid::new_v4().as_u128() as u32;
+        let delay =
+            tokio::time::delay_for(jittered(options.interval, roll).min(left));
+        let cancelled = cancellation.cancelled();
+        futures_util::pin_mut!(cancelled);
+        if let futures_util::future::Either::Right(_) =
+            futures_util::future::select(delay, cancelled).await
+        {
+            return Err(Error::Cancelled.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter() {
+        let interval = Duration::from_secs(10);
+        assert_eq!(jittered(interval, 0), Duration::from_secs(8));
+        assert_eq!(jittered(interval, u32::MAX), Duration::from_secs(12));
+        let middle = jittered(interval, u32::MAX / 2);
+        assert!(middle > Duration::from_millis(9999));
+        assert!(middle < Duration::from_millis(10001));
+    }
+
+    #[test]
+    fn timed_out() {
+        let timeout = Duration::from_secs(300);
+        let nothing = TimedOut {
+            path: "Scans/receipt".to_string(),
+            timeout,
+            seen: None,
+        };
+        assert_eq!(nothing.to_string(), "Nothing came to Scans/receipt in 5m");
+        let old = TimedOut {
+            seen: Some(2),
+            ..nothing
+        };
+        assert_eq!(
+            old.to_string(),
+            "Scans/receipt was still at version 2 after 5m"
+        );
+    }
+}
@@ -0,0 +1,1492 @@
+//! Whole-account backups, as written by `backup` and read by `restore`.
+//!
+//! A backup is a zstd-compressed tar archive. Every folder and document gets a
+//! `documents/<id>.json` entry holding its [`ManifestEntry`], and every
+//! document is preceded by `documents/<id>.zip`, its raw archive exactly as
+//! stored in the cloud. Folders come before documents and parents before
+//! their children. A `manifest.json` listing every entry closes the archive.
+//!
+//! An entry's description is only written once its zip is complete, and the
+//! archive is flushed after every document, so an interrupted backup can be
+//! resumed by keeping each described entry and fetching the rest. What's to
+//! be fetched is saved beside the archive as a [`crate::plan`] while the
+//! backup runs, so that `--resume-plan` can fetch the rest as planned,
+//! checking only that each document is at the version it was planned at.
+//!
+//! `restore --pick` restores one document instead, from a directory of its
+//! archives as they were at different times, such as those `pull --raw-zip`
+//! saves.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use chrono::NaiveDate;
+use remarkable_cloud_api::{
+    summarize_archives, ArchiveSummary, Client, CloudPath, DocType, Document,
+    DocumentArchiveBuilder, Documents,
+};
+use remarkable_data_formats::content::Content;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::limits::LimitCheck;
+use crate::mutations::MutationLog;
+use crate::observer::{Event, Phase};
+use crate::resolved::ResolvedTree;
+use crate::{destination, msg, plan as saved_plan, CliResult};
+
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    pub id: Uuid,
+    pub version: u64,
+    pub doc_type: DocType,
+    pub visible_name: String,
+    pub parent: Option<Uuid>,
+    pub path: String,
+    pub modified_client: chrono::DateTime<chrono::Utc>,
+    pub bookmarked: bool,
+    pub current_page: i32,
+}
+
+impl ManifestEntry {
+    fn is_folder(&self) -> bool {
+        self.doc_type == DocType::Collection
+    }
+
+    fn json_name(&self) -> String {
+        format!("documents/{}.json", self.id)
+    }
+
+    fn zip_name(&self) -> String {
+        format!("documents/{}.zip", self.id)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BackupOptions {
+    /// Keep what an earlier run to the same file saved.
+    pub resume: bool,
+    /// As `resume`, and fetch the rest as the earlier run planned to,
+    /// rather than planning again.
+    pub resume_plan: bool,
+    /// Make the archive depend only on what's in the cloud, see `backup`.
+    pub reproducible: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct BackupReport {
+    pub folders: usize,
+    pub documents: usize,
+    /// Entries carried over from an interrupted backup.
+    pub resumed: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct RestoreReport {
+    pub folders_created: usize,
+    pub uploaded: usize,
+    pub skipped: usize,
+    /// Each folder and document uploaded, as its id, name and version.
+    pub uploads: Vec<(Uuid, String, u64)>,
+}
+
+pub struct RestoreOptions {
+    /// The folder to restore into, or `None` for the root.
+    pub into: Option<Uuid>,
+    /// Whether to restore documents under their original ids rather than
+    /// creating copies.
+    pub keep_ids: bool,
+}
+
+// Counts ancestors, stopping at anything unknown or a cycle.
+fn depth<F>(mut parent: Option<Uuid>, parent_of: F) -> usize
+where
+    F: Fn(&Uuid) -> Option<Option<Uuid>>,
+{
+    let mut seen = HashSet::new();
+    while let Some(p) = parent {
+        if !seen.insert(p) {
+            break;
+        }
+        parent = parent_of(&p).unwrap_or(None);
+    }
+    seen.len()
+}
+
+// The entry describing `doc` as it is in `documents`, none if it has no
+// path there.
+fn entry_for(
+    documents: &ResolvedTree,
+    doc: &Document,
+) -> Option<ManifestEntry> {
+    Some(ManifestEntry {
+        id: doc.id,
+        version: doc.version,
+        doc_type: doc.doc_type.clone(),
+        visible_name: doc.visible_name.to_string(),
+        parent: doc.parent,
+        path: documents.path_of(&doc.id)?,
+        modified_client: doc.modified_client,
+        bookmarked: doc.bookmarked,
+        current_page: doc.current_page,
+    })
+}
+
+// Puts `entries` in the order a backup of `documents` writes them: folders
+// first, then parents before their children.
+fn sort_entries(
+    documents: &ResolvedTree,
+    entries: Vec<ManifestEntry>,
+) -> Vec<ManifestEntry> {
+    let mut entries: Vec<(bool, usize, ManifestEntry)> = entries
+        .into_iter()
+        .map(|e| {
+            let depth = depth(e.parent, |p| documents.get(p).map(|d| d.parent));
+            (!e.is_folder(), depth, e)
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        (a.0, a.1, &a.2.path, a.2.id).cmp(&(b.0, b.1, &b.2.path, b.2.id))
+    });
+    entries.into_iter().map(|(_, _, e)| e).collect()
+}
+
+/// Lists what a backup of `documents` holds, in the order it is written.
+pub fn plan(documents: &ResolvedTree) -> Vec<ManifestEntry> {
+    let entries = documents
+        .iter()
+        .filter_map(|d| entry_for(documents, d))
+        .collect();
+    sort_entries(documents, entries)
+}
+
+/// Where a backup to `output` saves its plan while it runs.
+pub fn plan_path(output: &Path) -> PathBuf {
+    sibling_path(output, ".plan.json")
+}
+
+// What's left to fetch of the plan `saved`, now that `documents` is the
+// listing and `done` what's already in the archive. Each entry is checked
+// against the listing: those gone are left out, and those at another
+// version than planned are planned again. What's been added since is
+// planned too.
+fn resume_plan(
+    documents: &ResolvedTree,
+    saved: Vec<ManifestEntry>,
+    done: &HashSet<Uuid>,
+    out: &mut dyn Output,
+) -> Vec<ManifestEntry> {
+    let saved: Vec<ManifestEntry> = saved
+        .into_iter()
+        .filter(|e| !done.contains(&e.id))
+        .collect();
+    out.observe(&Event::Phase(Phase::Resuming {
+        checked: saved.len(),
+    }));
+    let planned: HashSet<Uuid> = saved.iter().map(|e| e.id).collect();
+    let mut todo = vec![];
+    for entry in saved {
+        let doc = match documents.get(&entry.id) {
+            Some(doc) => doc,
+            None => {
+                out.note(&msg!(BACKUP_PLAN_GONE, path = &entry.path));
+                continue;
+            }
+        };
+        if doc.version == entry.version {
+            todo.push(entry);
+            continue;
+        }
+        out.note(&msg!(PLAN_MOVED, path = &entry.path));
+        todo.extend(entry_for(documents, doc));
+    }
+    todo.extend(
+        documents
+            .iter()
+            .filter(|d| !planned.contains(&d.id) && !done.contains(&d.id))
+            .filter_map(|d| entry_for(documents, d)),
+    );
+    sort_entries(documents, todo)
+}
+
+type ArchiveWriter =
+    tar::Builder<zstd::stream::write::Encoder<'static, fs::File>>;
+
+fn append(
+    builder: &mut ArchiveWriter,
+    name: &str,
+    data: &[u8],
+    mtime: &chrono::DateTime<chrono::Utc>,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime.timestamp().max(0) as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+fn append_entry(
+    builder: &mut ArchiveWriter,
+    entry: &ManifestEntry,
+) -> io::Result<()> {
+    append(
+        builder,
+        &entry.json_name(),
+        &serde_json::to_vec_pretty(entry)?,
+        &entry.modified_client,
+    )
+}
+
+// Copies every complete entry of a possibly truncated backup into `builder`,
+// returning their descriptions. A document whose zip made it into the old
+// archive but whose description didn't is dropped, to be fetched again.
+fn copy_complete(
+    src: &Path,
+    builder: &mut ArchiveWriter,
+) -> io::Result<Vec<ManifestEntry>> {
+    let mut archive =
+        tar::Archive::new(zstd::Decoder::new(fs::File::open(src)?)?);
+    let mut copied = vec![];
+    let mut pending_zip: Option<(String, Vec<u8>)> = None;
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(_) => return Ok(copied),
+    };
+    for entry in entries {
+        // Any error means we've reached the point the old run was cut off.
+        let mut entry = match entry {
+            Ok(entry) => entry,
+            Err(_) => break,
+        };
+        let name = match entry.path() {
+            Ok(p) => p.to_string_lossy().into_owned(),
+            Err(_) => break,
+        };
+        let mut data = vec![];
+        if entry.read_to_end(&mut data).is_err() {
+            break;
+        }
+        if name.ends_with(".zip") {
+            pending_zip = Some((name, data));
+        } else if name.starts_with("documents/") && name.ends_with(".json") {
+            let e: ManifestEntry = match serde_json::from_slice(&data) {
+                Ok(e) => e,
+                Err(_) => break,
+            };
+            if !e.is_folder() {
+                match pending_zip.take() {
+                    Some((zip_name, zip)) if zip_name == e.zip_name() => {
+                        append(builder, &zip_name, &zip, &e.modified_client)?
+                    }
+                    _ => continue,
+                }
+            }
+            append_entry(builder, &e)?;
+            copied.push(e);
+        }
+    }
+    Ok(copied)
+}
+
+fn sibling_path(p: &Path, suffix: &str) -> PathBuf {
+    let mut name = p.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    p.with_file_name(name)
+}
+
+/// Writes a backup of every document in `documents` to `output`.
+///
+/// The archive is written to `<output>.partial` and renamed into place once
+/// complete. With `resume`, complete entries from an earlier partial (or
+/// finished) backup at the same location are kept rather than downloaded
+/// again. What's left to download is saved at [`plan_path`] until the
+/// backup is complete, and with `resume_plan`, what an earlier run saved
+/// there is downloaded instead of planning again.
+///
+/// With `reproducible`, backing up the same documents twice gives the same
+/// bytes: the manifest is dated at the Unix epoch rather than now, and the
+/// entries come in listing order. That can't be had with `resume`, whose
+/// kept entries come first, nor with documents missing, so either fails the
+/// backup rather than giving an archive which differs for no reason.
+pub async fn backup(
+    client: &Client,
+    documents: &ResolvedTree,
+    output: &Path,
+    options: BackupOptions,
+    out: &mut dyn Output,
+) -> CliResult<BackupReport> {
+    let resume = options.resume || options.resume_plan;
+    if options.reproducible && resume {
+        return Err(msg!(BACKUP_REPRODUCIBLE_RESUMED).into());
+    }
+    let partial = sibling_path(output, ".partial");
+    let previous = sibling_path(output, ".resume");
+    let plan_path = plan_path(output);
+    let mut report = BackupReport::default();
+
+    // An unfinished run leaves its archive at the partial path, which the new
+    // archive is about to be written to, so move it out of the way first.
+    let resume_from = if resume && partial.exists() {
+        fs::rename(&partial, &previous)?;
+        Some(previous)
+    } else if resume && output.exists() {
+        fs::copy(output, &previous)?;
+        Some(previous)
+    } else {
+        None
+    };
+
+    let mut builder =
+        tar::Builder::new(zstd::Encoder::new(fs::File::create(&partial)?, 0)?);
+    let mut done = match &resume_from {
+        Some(p) => copy_complete(p, &mut builder)?,
+        None => vec![],
+    };
+    report.resumed = done.len();
+    let done_ids: HashSet<Uuid> = done.iter().map(|e| e.id).collect();
+
+    let saved = if options.resume_plan {
+        saved_plan::resume(&plan_path, out)?
+    } else {
+        None
+    };
+    let todo: Vec<ManifestEntry> = match saved {
+        Some(saved) => resume_plan(documents, saved, &done_ids, out),
+        None => {
+            out.observe(&Event::Phase(Phase::Planning {
+                compared: documents.len(),
+            }));
+            plan(documents)
+                .into_iter()
+                .filter(|e| !done_ids.contains(&e.id))
+                .collect()
+        }
+    };
+    saved_plan::save(&plan_path, &todo)?;
+    out.observe(&Event::Phase(Phase::Transferring {
+        documents: todo.iter().filter(|e| !e.is_folder()).count(),
+    }));
+    for entry in todo {
+        if entry.is_folder() {
+            append_entry(&mut builder, &entry)?;
+            report.folders += 1;
+            done.push(entry);
+            continue;
+        }
+        let start = Instant::now();
+        out.observe(&Event::Started {
+            path: PathBuf::from(&entry.path),
+        });
+        let fetched = match client.get_document_by_id(&entry.id).await {
+            Ok(blobdoc) => client.download_blob(&blobdoc).await,
+            Err(e) => Err(e),
+        };
+        let zip = match fetched {
+            Ok(zip) => zip,
+            Err(e) => {
+                out.warn(&msg!(
+                    BACKUP_FAILED,
+                    path = &entry.path,
+                    error = e.to_string()
+                ));
+                out.observe(&Event::Failed {
+                    path: PathBuf::from(&entry.path),
+                    id: Some(entry.id),
+                    category: crate::error_category(&e),
+                    message: e.to_string(),
+                    elapsed: start.elapsed(),
+                });
+                report.failed += 1;
+                continue;
+            }
+        };
+        append(
+            &mut builder,
+            &entry.zip_name(),
+            &zip,
+            &entry.modified_client,
+        )?;
+        append_entry(&mut builder, &entry)?;
+        builder.get_mut().flush()?;
+        out.note(&entry.path);
+        out.observe(&Event::Pulled {
+            path: PathBuf::from(&entry.path),
+            id: entry.id,
+            output: output.join(entry.zip_name()),
+            modified: entry.modified_client,
+            bytes: zip.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(&zip)),
+            elapsed: start.elapsed(),
+        });
+        report.documents += 1;
+        done.push(entry);
+    }
+
+    if options.reproducible && report.failed > 0 {
+        drop(builder);
+        fs::remove_file(&partial)?;
+        saved_plan::remove(&plan_path)?;
+        return Err(msg!(
+            BACKUP_INCOMPLETE_REPRODUCIBLE,
+            count = report.failed
+        )
+        .into());
+    }
+    let now = if options.reproducible {
+        chrono::DateTime::<chrono::Utc>::from(std::time::UNIX_EPOCH)
+    } else {
+        chrono::Utc::now()
+    };
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        created: now,
+        entries: done,
+    };
+    append(
+        &mut builder,
+        MANIFEST_NAME,
+        &serde_json::to_vec_pretty(&manifest)?,
+        &now,
+    )?;
+    builder.into_inner()?.finish()?;
+    fs::rename(&partial, output)?;
+    if let Some(p) = resume_from {
+        fs::remove_file(p)?;
+    }
+    saved_plan::remove(&plan_path)?;
+    Ok(report)
+}
+
+fn open_archive(
+    p: &Path,
+) -> io::Result<tar::Archive<zstd::Decoder<'static, io::BufReader<fs::File>>>> {
+    Ok(tar::Archive::new(zstd::Decoder::new(fs::File::open(p)?)?))
+}
+
+/// Reads the description of every entry in a backup, without reading any
+/// document contents into memory.
+pub fn read_entries(p: &Path) -> io::Result<Vec<ManifestEntry>> {
+    let mut archive = open_archive(p)?;
+    let mut entries = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if name.starts_with("documents/") && name.ends_with(".json") {
+            let mut data = vec![];
+            entry.read_to_end(&mut data)?;
+            entries.push(serde_json::from_slice(&data)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Hands `f` each document in a backup, with its archive, one at a time in
+/// the order the backup has them. Folders are left out.
+pub fn read_documents<F>(p: &Path, mut f: F) -> CliResult<()>
+where
+    F: FnMut(&ManifestEntry, Vec<u8>) -> CliResult<()>,
+{
+    let entries = read_entries(p)?;
+    let by_id: HashMap<Uuid, &ManifestEntry> =
+        entries.iter().map(|e| (e.id, e)).collect();
+    let mut archive = open_archive(p)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let e = match name
+            .strip_prefix("documents/")
+            .and_then(|n| n.strip_suffix(".zip"))
+            .and_then(|id| id.parse::<Uuid>().ok())
+            .and_then(|id| by_id.get(&id))
+        {
+            Some(e) => e,
+            None => continue,
+        };
+        let mut zip = vec![];
+        entry.read_to_end(&mut zip)?;
+        f(e, zip)?;
+    }
+    Ok(())
+}
+
+/// Rewrites a document archive so that the entries named after `old` are
+/// named after `new` instead, as the tablet expects for a copy.
+pub fn rename_archive_ids(
+    zip: &[u8],
+    old: &Uuid,
+    new: &Uuid,
+) -> zip::result::ZipResult<Vec<u8>> {
+    let (old, new) = (old.to_string(), new.to_string());
+    let mut src = zip::ZipArchive::new(io::Cursor::new(zip))?;
+    let mut dst = zip::ZipWriter::new(io::Cursor::new(vec![]));
+    for i in 0..src.len() {
+        let mut f = src.by_index(i)?;
+        let options = zip::write::FileOptions::default()
+            .compression_method(f.compression())
+            .last_modified_time(f.last_modified());
+        let name = f.name().replace(&old, &new);
+        if f.is_dir() {
+            dst.add_directory(name, options)?;
+        } else {
+            dst.start_file(name, options)?;
+            io::copy(&mut f, &mut dst)?;
+        }
+    }
+    Ok(dst.finish()?.into_inner())
+}
+
+fn folder_archive(id: &Uuid) -> remarkable_cloud_api::Result<Vec<u8>> {
+    DocumentArchiveBuilder::new()
+        .content(Content::default())
+        .build(*id)
+}
+
+/// The folder named `name` in `parent`, if there is one.
+pub fn existing_folder(
+    documents: &Documents,
+    parent: Option<Uuid>,
+    name: &str,
+) -> Option<Uuid> {
+    documents
+        .get_children(&parent)
+        .into_iter()
+        .find(|d| d.is_folder() && &*d.visible_name == name)
+        .map(|d| d.id)
+}
+
+/// Creates the folder `id`, named `name`, in `parent`.
+pub async fn create_folder(
+    client: &Client,
+    id: Uuid,
+    parent: Option<Uuid>,
+    name: &str,
+) -> CliResult<()> {
+    client
+        .upload_zip(
+            id,
+            1,
+            parent,
+            name,
+            DocType::Collection,
+            folder_archive(&id)?,
+        )
+        .await?;
+    Ok(())
+}
+
+/// What restoring `entries` adds, as `TreeLimits::check_planned` takes
+/// it: the names of the folders each is in, from the top of the backup,
+/// then its own.
+pub fn planned(entries: &[ManifestEntry]) -> Vec<Vec<String>> {
+    let by_id: HashMap<Uuid, &ManifestEntry> =
+        entries.iter().map(|e| (e.id, e)).collect();
+    entries
+        .iter()
+        .map(|e| {
+            let mut names = vec![e.visible_name.clone()];
+            let mut seen = HashSet::new();
+            let mut parent = e.parent;
+            while let Some(p) = parent.filter(|p| seen.insert(*p)) {
+                match by_id.get(&p) {
+                    Some(folder) => {
+                        names.push(folder.visible_name.clone());
+                        parent = folder.parent;
+                    }
+                    None => break,
+                }
+            }
+            names.reverse();
+            names
+        })
+        .collect()
+}
+
+/// Re-creates the folders and documents in a backup.
+///
+/// Folders are matched by name against those already present at the
+/// destination, and documents which already exist at the same place with the
+/// same version are skipped, so restoring the same backup twice is harmless.
+/// What's created is reported to `out`.
+pub async fn restore(
+    client: &Client,
+    documents: &Documents,
+    archive: &Path,
+    options: &RestoreOptions,
+    out: &mut dyn Output,
+) -> CliResult<RestoreReport> {
+    let mut report = RestoreReport::default();
+    let entries = read_entries(archive)?;
+    let by_id: HashMap<Uuid, &ManifestEntry> =
+        entries.iter().map(|e| (e.id, e)).collect();
+    // Maps ids in the backup to the ids of the folders they now live in.
+    let mut folder_ids: HashMap<Uuid, Uuid> = HashMap::new();
+    let dest_parent = |folder_ids: &HashMap<Uuid, Uuid>, e: &ManifestEntry| {
+        e.parent
+            .and_then(|p| folder_ids.get(&p).copied())
+            .or(options.into)
+    };
+
+    let mut folders: Vec<&ManifestEntry> =
+        entries.iter().filter(|e| e.is_folder()).collect();
+    folders
+        .sort_by_key(|e| depth(e.parent, |p| by_id.get(p).map(|e| e.parent)));
+    for e in folders {
+        let parent = dest_parent(&folder_ids, e);
+        let existing = if options.keep_ids {
+            documents.get(&e.id).map(|d| d.id)
+        } else {
+            existing_folder(documents, parent, &e.visible_name)
+        };
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = if options.keep_ids {
+                    e.id
+                } else {
+                    Uuid::new_v4()
+                };
+                create_folder(client, id, parent, &e.visible_name).await?;
+                out.note(&msg!(RESTORE_FOLDER_CREATED, path = &e.path));
+                report.folders_created += 1;
+                report.uploads.push((id, e.visible_name.clone(), 1));
+                id
+            }
+        };
+        folder_ids.insert(e.id, id);
+    }
+
+    let mut archive = open_archive(archive)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let e = match name
+            .strip_prefix("documents/")
+            .and_then(|n| n.strip_suffix(".zip"))
+            .and_then(|id| id.parse::<Uuid>().ok())
+            .and_then(|id| by_id.get(&id))
+        {
+            Some(e) => e,
+            None => continue,
+        };
+        let parent = dest_parent(&folder_ids, e);
+        let (id, version) = if options.keep_ids {
+            match documents.get(&e.id) {
+                Some(d) if d.version == e.version => {
+                    report.skipped += 1;
+                    continue;
+                }
+                Some(d) => (e.id, d.version + 1),
+                None => (e.id, 1),
+            }
+        } else {
+            let exists = documents.get_children(&parent).iter().any(|d| {
+                *d.visible_name == e.visible_name && d.version == e.version
+            });
+            if exists {
+                report.skipped += 1;
+                continue;
+            }
+            (Uuid::new_v4(), 1)
+        };
+        let mut zip = vec![];
+        entry.read_to_end(&mut zip)?;
+        if id != e.id {
+            zip = rename_archive_ids(&zip, &e.id, &id)?;
+        }
+        client
+            .upload_zip(
+                id,
+                version,
+                parent,
+                &e.visible_name,
+                e.doc_type.clone(),
+                zip,
+            )
+            .await?;
+        out.note(&msg!(RESTORE_RESTORED, path = &e.path));
+        report.uploaded += 1;
+        report.uploads.push((id, e.visible_name.clone(), version));
+    }
+    Ok(report)
+}
+
+/// Which of the archives in a directory `restore --pick` restores.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pick {
+    /// Whichever is chosen from the list of them.
+    Ask,
+    /// The one saved at this version.
+    Version(u64),
+    /// The newest one modified on or before this day.
+    Date(NaiveDate),
+}
+
+/// A line describing one of the archives `restore --pick` lists.
+pub fn describe(path: &Path, summary: &ArchiveSummary) -> String {
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = match summary.visible_name() {
+        Some(name) => name.to_string(),
+        None => msg!(RESTORE_UNNAMED),
+    };
+    let version = match summary.version() {
+        Some(version) => msg!(RESTORE_ARCHIVE_VERSION, version = version),
+        None => msg!(RESTORE_ARCHIVE_VERSION_UNKNOWN),
+    };
+    match summary.modified() {
+        Some(modified) => msg!(
+            RESTORE_ARCHIVE_MODIFIED,
+            file = file,
+            name = name,
+            version = version,
+            modified = modified.format("%Y-%m-%d %H:%M").to_string(),
+        ),
+        None => {
+            msg!(RESTORE_ARCHIVE, file = file, name = name, version = version)
+        }
+    }
+}
+
+/// The archive of `candidates` that `pick` picks, listing them on `output`
+/// and reading the number of one from `input` for `Pick::Ask`.
+pub fn choose<'a>(
+    candidates: &'a [(PathBuf, ArchiveSummary)],
+    pick: Pick,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> CliResult<&'a (PathBuf, ArchiveSummary)> {
+    let found = match pick {
+        Pick::Version(version) => candidates
+            .iter()
+            .find(|(_, s)| s.version() == Some(version))
+            .ok_or_else(|| msg!(RESTORE_NO_SUCH_VERSION, version = version))?,
+        Pick::Date(date) => candidates
+            .iter()
+            .filter(|(_, s)| {
+                s.modified().is_some_and(|m| m.naive_utc().date() <= date)
+            })
+            .max_by_key(|(_, s)| s.modified())
+            .ok_or_else(|| {
+                msg!(RESTORE_NONE_BY_DATE, date = date.to_string())
+            })?,
+        Pick::Ask => {
+            for (n, (path, summary)) in candidates.iter().enumerate() {
+                writeln!(output, "{:>3}) {}", n + 1, describe(path, summary))?;
+            }
+            write!(
+                output,
+                "{} ",
+                msg!(RESTORE_WHICH, count = candidates.len())
+            )?;
+            output.flush()?;
+            let mut answer = String::new();
+            input.read_line(&mut answer)?;
+            answer
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| candidates.get(n.checked_sub(1)?))
+                .ok_or_else(|| msg!(RESTORE_NOTHING))?
+        }
+    };
+    Ok(found)
+}
+
+/// How `restore_picked` restored an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Picked {
+    pub id: Uuid,
+    pub name: String,
+    pub version: u64,
+    /// Whether it replaced the document it was saved from, rather than
+    /// being uploaded as a new one.
+    pub replaced: bool,
+}
+
+/// Uploads the archive at `path` as a new version of the document it was
+/// saved from, if that's still in `documents`, or else as a new document
+/// in `into`, named as when it was saved.
+pub async fn restore_picked(
+    client: &Client,
+    documents: &Documents,
+    path: &Path,
+    summary: &ArchiveSummary,
+    into: Option<Uuid>,
+) -> CliResult<Picked> {
+    let zip = fs::read(path)?;
+    let picked = match documents.get(&summary.id) {
+        Some(current) => {
+            let picked = Picked {
+                id: current.id,
+                name: current.visible_name.to_string(),
+                version: current.version + 1,
+                replaced: true,
+            };
+            client
+                .upload_zip(
+                    picked.id,
+                    picked.version,
+                    current.parent,
+                    &picked.name,
+                    current.doc_type.clone(),
+                    zip,
+                )
+                .await?;
+            picked
+        }
+        None => {
+            let picked = Picked {
+                id: Uuid::new_v4(),
+                name: summary.visible_name().map_or_else(
+                    || {
+                        let stem = path.file_stem().unwrap_or_default();
+                        stem.to_string_lossy().into_owned()
+                    },
+                    str::to_string,
+                ),
+                version: 1,
+                replaced: false,
+            };
+            client
+                .upload_zip(
+                    picked.id,
+                    picked.version,
+                    into,
+                    &picked.name,
+                    DocType::Document,
+                    rename_archive_ids(&zip, &summary.id, &picked.id)?,
+                )
+                .await?;
+            picked
+        }
+    };
+    Ok(picked)
+}
+
+/// Restores every document in the backup at `archive` into the folder at
+/// `into`, or the root, as `restore` does, once `limits` are checked
+/// against what it adds.
+#[allow(clippy::too_many_arguments)]
+pub async fn restore_all(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    archive: &Path,
+    into: Option<&CloudPath>,
+    keep_ids: bool,
+    limits: &LimitCheck,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let into = match into {
+        None => None,
+        Some(path) => destination(documents, path)?.folder(),
+    };
+    let planned = planned(&read_entries(archive)?);
+    let violations = limits.limits.check_planned(documents, into, &planned);
+    limits.enforce(&violations, out)?;
+    let report = restore(
+        client,
+        documents,
+        archive,
+        &RestoreOptions { into, keep_ids },
+        out,
+    )
+    .await?;
+    for (id, name, version) in &report.uploads {
+        mutations.record_upload(*id, name, *version, out);
+    }
+    out.line(&msg!(
+        RESTORE_DONE,
+        restored = report.uploaded,
+        created = report.folders_created,
+        skipped = report.skipped,
+    ));
+    Ok(())
+}
+
+/// The archive in `dir` that `pick` picks, as `restore --pick` lists them,
+/// asking on `output` and reading the answer from `input` for `Pick::Ask`.
+pub fn pick_archive(
+    dir: &Path,
+    pick: Pick,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> CliResult<(PathBuf, ArchiveSummary)> {
+    let candidates = summarize_archives(dir)?;
+    if candidates.is_empty() {
+        return Err(
+            msg!(RESTORE_NO_ARCHIVES, dir = dir.display().to_string()).into()
+        );
+    }
+    let picked = choose(&candidates, pick, input, output)?;
+    Ok(picked.clone())
+}
+
+/// Restores the archive at `path`, as `restore_picked` does, into the
+/// folder at `into` if it's restored as a new document, telling `out` how.
+pub async fn restore_pick(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    (path, summary): &(PathBuf, ArchiveSummary),
+    into: Option<&CloudPath>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let into = match into {
+        None => None,
+        Some(path) => destination(documents, path)?.folder(),
+    };
+    let picked = restore_picked(client, documents, path, summary, into).await?;
+    mutations.record_upload(picked.id, &picked.name, picked.version, out);
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    if picked.replaced {
+        out.note(&msg!(
+            RESTORE_PICKED_VERSION,
+            file = file,
+            version = picked.version,
+            name = &picked.name,
+        ));
+    } else {
+        out.note(&msg!(RESTORE_PICKED_NEW, file = file, name = &picked.name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use remarkable_cloud_api::testing::FakeCloud;
+
+    use super::*;
+    use crate::commands::Capture;
+    use crate::observer::{Observer, Observers};
+
+    fn document_archive(id: &Uuid, payload: &[u8]) -> Vec<u8> {
+        let mut dst = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        let options = zip::write::FileOptions::default();
+        dst.start_file(format!("{}.content", id), options).unwrap();
+        dst.write_all(b"{\"fileType\": \"pdf\"}").unwrap();
+        dst.start_file(format!("{}.pdf", id), options).unwrap();
+        dst.write_all(payload).unwrap();
+        dst.finish().unwrap().into_inner()
+    }
+
+    // Populates a fake cloud with a few folders and documents, returning
+    // their paths.
+    fn populate(cloud: &FakeCloud) -> Vec<&'static str> {
+        let work = cloud.add_folder("Work", None);
+        let scans = cloud.add_folder("Scans", Some(work));
+        for (name, parent) in &[
+            ("Report", Some(work)),
+            ("Scan 1", Some(scans)),
+            ("Scan 2", Some(scans)),
+            ("Notes", None),
+        ] {
+            let id = Uuid::new_v4();
+            cloud.insert(remarkable_cloud_api::testing::FakeDocument {
+                id,
+                version: 3,
+                visible_name: name.to_string(),
+                parent: *parent,
+                trashed: false,
+                doc_type: DocType::Document,
+                current_page: 0,
+                bookmarked: false,
+                modified_client: chrono::Utc::now(),
+                blob: document_archive(&id, name.as_bytes()),
+            });
+        }
+        vec![
+            "Notes",
+            "Work",
+            "Work/Report",
+            "Work/Scans",
+            "Work/Scans/Scan 1",
+            "Work/Scans/Scan 2",
+        ]
+    }
+
+    async fn listing(cloud: &FakeCloud) -> (Client, ResolvedTree) {
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+        (client, ResolvedTree::new(docs))
+    }
+
+    fn paths(documents: &Documents) -> Vec<String> {
+        let mut paths: Vec<String> = documents
+            .iter()
+            .filter_map(|d| documents.path_of(&d.id))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn blob_downloads(cloud: &FakeCloud) -> usize {
+        cloud
+            .requests()
+            .iter()
+            .filter(|r| r.path.starts_with("/blob/"))
+            .count()
+    }
+
+    #[test]
+    fn plan_order() {
+        let docs: Documents = serde_json::from_str(
+            r#"[
+            {"ID": "00000000-0000-0000-0000-000000000003", "Version": 1,
+             "VissibleName": "Doc", "Type": "DocumentType",
+             "Parent": "00000000-0000-0000-0000-000000000002",
+             "CurrentPage": 0, "Bookmarked": false, "Message": "",
+             "ModifiedClient": "2020-01-01T00:00:00Z", "BlobURLGet": "",
+             "BlobURLGetExpires": "0001-01-01T00:00:00Z"},
+            {"ID": "00000000-0000-0000-0000-000000000002", "Version": 1,
+             "VissibleName": "Inner", "Type": "CollectionType",
+             "Parent": "00000000-0000-0000-0000-000000000001",
+             "CurrentPage": 0, "Bookmarked": false, "Message": "",
+             "ModifiedClient": "2020-01-01T00:00:00Z", "BlobURLGet": "",
+             "BlobURLGetExpires": "0001-01-01T00:00:00Z"},
+            {"ID": "00000000-0000-0000-0000-000000000001", "Version": 1,
+             "VissibleName": "Outer", "Type": "CollectionType", "Parent": "",
+             "CurrentPage": 0, "Bookmarked": false, "Message": "",
+             "ModifiedClient": "2020-01-01T00:00:00Z", "BlobURLGet": "",
+             "BlobURLGetExpires": "0001-01-01T00:00:00Z"}
+        ]"#,
+        )
+        .unwrap();
+        let entries = plan(&ResolvedTree::new(docs));
+        let order: Vec<&str> =
+            entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(order, vec!["Outer", "Outer/Inner", "Outer/Inner/Doc"]);
+        assert_eq!(
+            planned(&entries),
+            vec![
+                vec!["Outer"],
+                vec!["Outer", "Inner"],
+                vec!["Outer", "Inner", "Doc"],
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_ids() {
+        let (old, new) = (Uuid::new_v4(), Uuid::new_v4());
+        let renamed =
+            rename_archive_ids(&document_archive(&old, b"pdf"), &old, &new)
+                .unwrap();
+        let mut za = zip::ZipArchive::new(io::Cursor::new(renamed)).unwrap();
+        let mut names: Vec<&str> = za.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![format!("{}.content", new), format!("{}.pdf", new)]
+        );
+        let mut payload = vec![];
+        za.by_name(&format!("{}.pdf", new))
+            .unwrap()
+            .read_to_end(&mut payload)
+            .unwrap();
+        assert_eq!(payload, b"pdf");
+    }
+
+    #[tokio::test]
+    async fn backup_and_restore() {
+        let mut out = Capture::new(Observers::new());
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+
+        let source = FakeCloud::start().await;
+        let expected = populate(&source);
+        let (client, docs) = listing(&source).await;
+        let report = backup(
+            &client,
+            &docs,
+            &archive,
+            BackupOptions::default(),
+            &mut Capture::new(Observers::new()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            report,
+            BackupReport {
+                folders: 2,
+                documents: 4,
+                resumed: 0,
+                failed: 0,
+            }
+        );
+        assert!(!sibling_path(&archive, ".partial").exists());
+
+        let target = FakeCloud::start().await;
+        let (client, docs) = listing(&target).await;
+        let options = RestoreOptions {
+            into: None,
+            keep_ids: false,
+        };
+        let report = restore(&client, &docs, &archive, &options, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(report.folders_created, 2);
+        assert_eq!(report.uploaded, 4);
+        let (client, docs) = listing(&target).await;
+        assert_eq!(paths(&docs), expected);
+
+        // Restored copies have new ids, and archives renamed to match.
+        for d in docs.iter() {
+            assert!(source.document(&d.id).is_none());
+            if d.is_document() {
+                let blob = target.document(&d.id).unwrap().blob;
+                let za = zip::ZipArchive::new(io::Cursor::new(blob)).unwrap();
+                assert!(za
+                    .file_names()
+                    .all(|n| n.starts_with(&d.id.to_string())));
+            }
+        }
+
+        // Folders are reused by name, so a second run creates nothing new.
+        let report = restore(&client, &docs, &archive, &options, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(report.folders_created, 0);
+        assert_eq!(report.uploaded, 4);
+    }
+
+    #[tokio::test]
+    async fn restore_keeping_ids() {
+        let mut out = Capture::new(Observers::new());
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        backup(
+            &client,
+            &docs,
+            &archive,
+            BackupOptions::default(),
+            &mut Capture::new(Observers::new()),
+        )
+        .await
+        .unwrap();
+
+        let options = RestoreOptions {
+            into: None,
+            keep_ids: true,
+        };
+        // Nothing has changed, so nothing needs restoring.
+        let report = restore(&client, &docs, &archive, &options, &mut out)
+            .await
+            .unwrap();
+        assert_eq!(
+            report,
+            RestoreReport {
+                folders_created: 0,
+                uploaded: 0,
+                skipped: 4,
+                uploads: vec![],
+            }
+        );
+
+        // Into an empty account, everything comes back under its old id.
+        let target = FakeCloud::start().await;
+        let (client, empty) = listing(&target).await;
+        restore(&client, &empty, &archive, &options, &mut out)
+            .await
+            .unwrap();
+        for d in docs.iter() {
+            let restored = target.document(&d.id).unwrap();
+            assert_eq!(restored.visible_name, *d.visible_name);
+        }
+    }
+
+    #[derive(Default)]
+    struct Phases(Vec<Phase>);
+
+    impl Observer for Phases {
+        fn observe(&mut self, event: &Event) {
+            if let Event::Phase(phase) = event {
+                self.0.push(*phase);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_after_interruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        let expected = populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        backup(
+            &client,
+            &docs,
+            &archive,
+            BackupOptions::default(),
+            &mut Capture::new(Observers::new()),
+        )
+        .await
+        .unwrap();
+
+        // Simulate a run killed part way through by truncating the archive
+        // and leaving it where an unfinished run would have.
+        let len = fs::metadata(&archive).unwrap().len();
+        let partial = sibling_path(&archive, ".partial");
+        let data = fs::read(&archive).unwrap();
+        fs::write(&partial, &data[..(len as usize) * 2 / 3]).unwrap();
+        fs::remove_file(&archive).unwrap();
+
+        let downloads_before = blob_downloads(&cloud);
+        let phases = Rc::new(RefCell::new(Phases::default()));
+        let report = backup(
+            &client,
+            &docs,
+            &archive,
+            BackupOptions {
+                resume: true,
+                ..Default::default()
+            },
+            &mut Capture::new(phases.clone()),
+        )
+        .await
+        .unwrap();
+        assert!(report.resumed > 0);
+        // Only what wasn't kept is planned for transfer.
+        assert_eq!(
+            phases.borrow().0,
+            vec![
+                Phase::Planning { compared: 6 },
+                Phase::Transferring {
+                    documents: report.documents
+                }
+            ]
+        );
+        assert_eq!(report.resumed + report.folders + report.documents, 6);
+        // Only what was lost was downloaded again.
+        assert_eq!(blob_downloads(&cloud) - downloads_before, report.documents);
+        assert!(!partial.exists());
+        assert!(!plan_path(&archive).exists());
+
+        let mut entries: Vec<String> = read_entries(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        entries.sort();
+        assert_eq!(entries, expected);
+    }
+
+    fn resume_plan_options() -> BackupOptions {
+        BackupOptions {
+            resume_plan: true,
+            ..Default::default()
+        }
+    }
+
+    fn id_of(documents: &ResolvedTree, path: &str) -> Uuid {
+        let found = documents
+            .iter()
+            .find(|d| documents.path_of(&d.id).as_deref() == Some(path));
+        found.unwrap().id
+    }
+
+    #[tokio::test]
+    async fn resume_plan_before_planning() {
+        // Interrupted while listing: there's nothing saved, so it plans.
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        let phases = Rc::new(RefCell::new(Phases::default()));
+        let mut out = Capture::new(phases.clone());
+        let report =
+            backup(&client, &docs, &archive, resume_plan_options(), &mut out)
+                .await
+                .unwrap();
+        assert_eq!((report.folders, report.documents), (2, 4));
+        assert_eq!(
+            phases.borrow().0,
+            vec![
+                Phase::Planning { compared: 6 },
+                Phase::Transferring { documents: 4 }
+            ]
+        );
+        assert!(out.notes[0].starts_with("There's no saved plan"));
+        assert!(!plan_path(&archive).exists());
+    }
+
+    #[tokio::test]
+    async fn resume_plan_before_transferring() {
+        // Interrupted once planned, before anything was fetched.
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        let expected = populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        saved_plan::save(&plan_path(&archive), &plan(&docs)).unwrap();
+
+        let phases = Rc::new(RefCell::new(Phases::default()));
+        let report = backup(
+            &client,
+            &docs,
+            &archive,
+            resume_plan_options(),
+            &mut Capture::new(phases.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!((report.folders, report.documents), (2, 4));
+        assert_eq!(
+            phases.borrow().0,
+            vec![
+                Phase::Resuming { checked: 6 },
+                Phase::Transferring { documents: 4 }
+            ]
+        );
+        let mut entries: Vec<String> = read_entries(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        entries.sort();
+        assert_eq!(entries, expected);
+        assert!(!plan_path(&archive).exists());
+    }
+
+    #[tokio::test]
+    async fn resume_plan_while_transferring() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        let scans = [
+            id_of(&docs, "Work/Scans/Scan 1"),
+            id_of(&docs, "Work/Scans/Scan 2"),
+        ];
+
+        // Simulate a run killed before the scans were fetched: the archive
+        // has everything else, and the plan everything.
+        for id in &scans {
+            cloud.modify(id, |d| d.trashed = true);
+        }
+        let (_, before) = listing(&cloud).await;
+        backup(
+            &client,
+            &before,
+            &archive,
+            BackupOptions::default(),
+            &mut Capture::new(Observers::new()),
+        )
+        .await
+        .unwrap();
+        fs::rename(&archive, sibling_path(&archive, ".partial")).unwrap();
+        saved_plan::save(&plan_path(&archive), &plan(&docs)).unwrap();
+
+        // Since then, one scan has changed and another document is new.
+        for id in &scans {
+            cloud.modify(id, |d| d.trashed = false);
+        }
+        cloud.modify(&scans[1], |d| d.version += 1);
+        cloud.add_folder("New", None);
+        let (_, now) = listing(&cloud).await;
+
+        let downloads_before = blob_downloads(&cloud);
+        let phases = Rc::new(RefCell::new(Phases::default()));
+        let mut out = Capture::new(phases.clone());
+        let report =
+            backup(&client, &now, &archive, resume_plan_options(), &mut out)
+                .await
+                .unwrap();
+        assert_eq!(
+            report,
+            BackupReport {
+                folders: 1,
+                documents: 2,
+                resumed: 4,
+                failed: 0,
+            }
+        );
+        assert_eq!(
+            phases.borrow().0,
+            vec![
+                Phase::Resuming { checked: 2 },
+                Phase::Transferring { documents: 2 }
+            ]
+        );
+        assert_eq!(blob_downloads(&cloud) - downloads_before, 2);
+        assert_eq!(
+            out.notes[0],
+            "Work/Scans/Scan 2 changed since it was planned; planning it again"
+        );
+        let entries = read_entries(&archive).unwrap();
+        let scan = entries.iter().find(|e| e.id == scans[1]).unwrap();
+        assert_eq!(scan.version, 4);
+        assert!(entries.iter().any(|e| e.path == "New"));
+        assert!(!plan_path(&archive).exists());
+    }
+
+    #[tokio::test]
+    async fn resume_plan_gone() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        saved_plan::save(&plan_path(&archive), &plan(&docs)).unwrap();
+        cloud.modify(&id_of(&docs, "Notes"), |d| d.trashed = true);
+        let (_, now) = listing(&cloud).await;
+
+        let mut out = Capture::new(Observers::new());
+        let report =
+            backup(&client, &now, &archive, resume_plan_options(), &mut out)
+                .await
+                .unwrap();
+        assert_eq!((report.folders, report.documents), (2, 3));
+        assert_eq!(
+            out.notes[0],
+            "Notes is gone from the cloud since it was planned; leaving it out"
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_plan_of_another_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("account.tar.zst");
+        let cloud = FakeCloud::start().await;
+        populate(&cloud);
+        let (client, docs) = listing(&cloud).await;
+        fs::write(plan_path(&archive), r#"{"plan_version": 99, "items": []}"#)
+            .unwrap();
+        let e = backup(
+            &client,
+            &docs,
+            &archive,
+            resume_plan_options(),
+            &mut Capture::new(Observers::new()),
+        )
+        .await
+        .unwrap_err();
+        assert!(e.to_string().contains("of version 99"), "{}", e);
+        assert!(!archive.exists());
+    }
+}
@@ -0,0 +1,338 @@
+//! The listing saved from the last time it was fetched, used instead of
+//! fetching it again with `--cached`.
+//!
+//! A cached listing may be out of date: the tablet or the phone app may have
+//! changed documents since. Commands which change documents therefore check
+//! each one they act on against the cloud first, see
+//! `targets::check_unchanged`.
+//!
+//! When a read command is served a listing older than [`STALE_AFTER`], it's
+//! fetched again alongside the command, so the next run finds it fresh.
+//! That's started and waited for, once the command has printed what it
+//! does, by a [`Refresher`], and skipped if another process has the cache's
+//! lock.
+//!
+//! `cache warm --folder` saves only part of the listing instead, see
+//! `partial`. That's only read by what knows to ask for it, with
+//! [`ListingCache::load_partial`], so that nothing else mistakes it for
+//! everything there is.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use remarkable_cloud_api::{
+    lock_path, read_locked, replace_locked, write_atomically, Client,
+    CloudPath, ContentCache, Documents, FileLock,
+};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::commands::{self, ListingOptions, Output};
+use crate::partial::PartialListing;
+use crate::resolved::ResolvedTree;
+use crate::{content, destination, msg, CliResult};
+
+/// How old a listing served from the cache can be before it's refreshed
+/// for the next run.
+pub const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+pub struct ListingCache {
+    path: PathBuf,
+}
+
+impl ListingCache {
+    pub fn new(path: PathBuf) -> Self {
+        ListingCache { path }
+    }
+
+    /// The cached listing, or `None` if there isn't one, it can't be read,
+    /// or only part of it was cached.
+    pub fn load(&self) -> Option<Documents> {
+        let data = read_locked(&self.path).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// The part of the listing cached by `cache warm --folder`, or `None` if
+    /// there isn't one, it can't be read, or the whole listing was cached.
+    pub fn load_partial(&self) -> Option<PartialListing> {
+        let data = read_locked(&self.path).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// When the cached listing was saved, if there is one.
+    pub fn saved_at(&self) -> Option<SystemTime> {
+        fs::metadata(&self.path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Replaces the cached listing. It is written aside and renamed into
+    /// place, so readers never see half of it, under its lock, so that
+    /// processes saving it take turns.
+    pub fn save(&self, documents: &Documents) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(&self.path, &serde_json::to_vec(documents)?)
+    }
+
+    /// Replaces the cached listing with part of it, as `save` does.
+    pub fn save_partial(&self, partial: &PartialListing) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(&self.path, &serde_json::to_vec(partial)?)
+    }
+
+    /// Replaces the cached listing as `save` does, unless another process
+    /// has its lock, returning whether it was saved.
+    pub fn try_save(&self, documents: &Documents) -> io::Result<bool> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match FileLock::try_exclusive(&lock_path(&self.path))? {
+            Some(_lock) => {
+                replace_locked(&self.path, &serde_json::to_vec(documents)?)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// How long ago the cached listing was saved, if there is one.
+    pub fn age(&self) -> Option<Duration> {
+        Some(self.saved_at()?.elapsed().unwrap_or_default())
+    }
+}
+
+/// How a refresh of the cached listing went.
+#[derive(Debug)]
+pub enum Refreshed {
+    Saved,
+    /// Another process had the cache's lock, so it was left alone.
+    Locked,
+    Failed(String),
+}
+
+/// Adds `age`, how old the cached listing `value` was made from is, to it,
+/// as `cache_age_seconds`, if it was, so that scripts can tell.
+pub fn annotate(
+    mut value: serde_json::Value,
+    age: Option<Duration>,
+) -> serde_json::Value {
+    if let (Some(age), Some(object)) = (age, value.as_object_mut()) {
+        object.insert("cache_age_seconds".into(), age.as_secs().into());
+    }
+    value
+}
+
+/// Refreshes the cached listing alongside whatever else is going on, to be
+/// waited for with [`Refresher::finish`]. Clones share the refresh under
+/// way.
+#[derive(Clone, Default)]
+pub struct Refresher {
+    pending: Arc<Mutex<Option<Pending>>>,
+}
+
+// The refresh under way, and how old the listing it replaces is.
+type Pending = (Duration, JoinHandle<Refreshed>);
+
+impl Refresher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetches the listing with `client` and saves it to `cache`. `age` is
+    /// how old the listing it replaces is.
+    pub fn start(&self, client: &Client, cache: &ListingCache, age: Duration) {
+        let (client, cache) = (client.clone(), cache.clone());
+        let handle = tokio::spawn(async move {
+            match client.get_documents().await {
+                Ok(documents) => match cache.try_save(&documents) {
+                    Ok(true) => Refreshed::Saved,
+                    Ok(false) => Refreshed::Locked,
+                    Err(e) => Refreshed::Failed(e.to_string()),
+                },
+                Err(e) => Refreshed::Failed(e.to_string()),
+            }
+        });
+        *self.pending.lock().unwrap() = Some((age, handle));
+    }
+
+    /// Waits for the refresh started with [`start`](Refresher::start), if
+    /// there is one, and says how it went.
+    pub async fn finish(&self) -> Option<String> {
+        let (age, handle) = self.pending.lock().unwrap().take()?;
+        let how = match handle.await {
+            Ok(Refreshed::Saved) => msg!(CACHE_REFRESHED),
+            Ok(Refreshed::Locked) => msg!(CACHE_REFRESH_LOCKED),
+            Ok(Refreshed::Failed(e)) => msg!(CACHE_REFRESH_FAILED, error = e),
+            Err(e) => msg!(CACHE_REFRESH_FAILED, error = e.to_string()),
+        };
+        Some(msg!(CACHE_WAS_OLD, age = short_age(age), how = how))
+    }
+}
+
+/// Downloads what isn't in `content_cache` yet of the documents in
+/// `folder`, or all of them, for `cache warm`. Warming a folder caches only
+/// its part of the listing, adding it to the part already cached.
+pub async fn warm(
+    client: &Client,
+    listing: &ListingOptions,
+    content_cache: &ContentCache,
+    folder: Option<&CloudPath>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    // The whole of the listing isn't cached first when it won't be kept.
+    let documents = if folder.is_some() {
+        let documents =
+            commands::fetch_documents(client, listing.verbose, None, out)
+                .await?;
+        ResolvedTree::new(documents)
+    } else {
+        commands::list_documents(client, listing, out).await?
+    };
+    let id = match folder {
+        Some(path) => destination(&documents, path)?.folder(),
+        None => None,
+    };
+    let report =
+        content::warm(client, content_cache, &documents, id, out).await?;
+    out.note(&msg!(
+        CACHE_WARMED,
+        downloaded = report.downloaded,
+        cached = report.already_cached,
+        unreadable = report.unreadable,
+        pruned = report.pruned,
+    ));
+    let saved = match id {
+        Some(id) => {
+            let mut folders: Vec<Uuid> = match listing.cache.load_partial() {
+                Some(partial) => {
+                    partial.covered().iter().map(|c| c.id).collect()
+                }
+                None => vec![],
+            };
+            folders.push(id);
+            let partial = PartialListing::of(&documents, &folders);
+            listing.cache.save_partial(&partial).map(|()| Some(partial))
+        }
+        // The root, or the trash, is all of it.
+        None if folder.is_some() => {
+            listing.cache.save(&documents).map(|()| None)
+        }
+        None => Ok(None),
+    };
+    match saved {
+        Ok(Some(partial)) => out.note(&msg!(
+            LISTING_PARTIAL_CACHED,
+            folders = partial.covered_paths()
+        )),
+        Ok(None) => {}
+        Err(e) => out.warn(&msg!(LISTING_NOT_CACHED, error = e.to_string())),
+    }
+    Ok(())
+}
+
+/// How long ago something was, roughly, as "3 hours ago".
+pub fn ago(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => msg!(AGO_JUST_NOW),
+        60..=3599 => msg!(AGO_MINUTES, count = secs / 60),
+        3600..=86399 => msg!(AGO_HOURS, count = secs / 3600),
+        _ => msg!(AGO_DAYS, count = secs / 86400),
+    }
+}
+
+/// How old something is, roughly and briefly, as "3h".
+pub fn short_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ages() {
+        let ago = |secs| ago(Duration::from_secs(secs));
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(3 * 3600 + 1800), "3 hours ago");
+        assert_eq!(ago(86400 * 2), "2 days ago");
+        let short = |secs| short_age(Duration::from_secs(secs));
+        assert_eq!(short(5), "5s");
+        assert_eq!(short(3 * 3600 + 1800), "3h");
+        assert_eq!(short(86400 * 2), "2d");
+    }
+
+    #[test]
+    fn try_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ListingCache::new(dir.path().join("listing.json"));
+        let documents = Documents::default();
+        assert!(cache.try_save(&documents).unwrap());
+        assert!(cache.age().unwrap() < STALE_AFTER);
+
+        // Someone reading it holds the lock, so it's left alone.
+        let _reading =
+            FileLock::shared(&lock_path(&dir.path().join("listing.json")));
+        fs::remove_file(dir.path().join("listing.json")).unwrap();
+        assert!(!cache.try_save(&documents).unwrap());
+        assert!(cache.load().is_none());
+    }
+
+    #[test]
+    fn partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ListingCache::new(dir.path().join("listing.json"));
+        let documents = crate::testutil::listing(&[
+            (1, "Work", None, "CollectionType"),
+            (2, "Home", None, "CollectionType"),
+        ]);
+        let folder = uuid::Uuid::from_u128(1);
+        let partial = PartialListing::of(&documents, &[folder]);
+        cache.save_partial(&partial).unwrap();
+        // Only what asks for part of it gets it.
+        assert!(cache.load().is_none());
+        assert_eq!(cache.load_partial(), Some(partial));
+
+        cache.save(&documents).unwrap();
+        assert_eq!(cache.load(), Some(documents));
+        assert!(cache.load_partial().is_none());
+    }
+
+    #[tokio::test]
+    async fn refresher() {
+        let cloud = remarkable_cloud_api::testing::FakeCloud::start().await;
+        cloud.add_document("Dune", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ListingCache::new(dir.path().join("listing.json"));
+
+        // Each refresher only waits for what it started.
+        let refresher = Refresher::new();
+        let other = Refresher::new();
+        assert!(refresher.finish().await.is_none());
+        refresher
+            .clone()
+            .start(&client, &cache, Duration::from_secs(7200));
+        assert!(other.finish().await.is_none());
+        assert_eq!(
+            refresher.finish().await.unwrap(),
+            "(cache was 2h old; refreshed for next run)"
+        );
+        assert_eq!(cache.load().unwrap().iter().count(), 1);
+        assert!(refresher.finish().await.is_none());
+    }
+}
@@ -0,0 +1,1324 @@
+//! The command line the client takes, and reading what was given on it:
+//! the arguments of each command, as clap parses them, and the values
+//! several commands share, such as rates, sizes and cloud paths.
+//!
+//! [`app`] is the whole of it, which the binary parses its arguments with
+//! and [`dispatch`](crate::dispatch) runs the command from.
+
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use remarkable_cloud_api::CloudPath;
+
+use crate::columns::Column;
+use crate::filter::DocumentFilter;
+use crate::glob::Pattern;
+use crate::lock::LockMode;
+use crate::template::Template;
+use crate::{
+    await_document, commands, content, exporters, help, layout, peek, push,
+    serve, sort, sync, watch, webhook, CliResult,
+};
+
+pub fn paths_from_arg<'a>(
+    matches: &'a clap::ArgMatches,
+    arg_name: &str,
+) -> Box<dyn Iterator<Item = &'a Path> + 'a> {
+    match matches.values_of(arg_name) {
+        Some(i) => Box::new(i.map(Path::new)),
+        None => Box::new(std::iter::empty()),
+    }
+}
+
+/// The values given for `arg_name`, if any.
+pub fn values(matches: &clap::ArgMatches, arg_name: &str) -> Vec<String> {
+    matches
+        .values_of(arg_name)
+        .map_or_else(Vec::new, |v| v.map(String::from).collect())
+}
+
+/// The cloud paths given for `arg_name`, or `default` if there are none.
+pub fn cloud_paths_from_arg(
+    matches: &clap::ArgMatches,
+    arg_name: &str,
+    default: Option<CloudPath>,
+) -> remarkable_cloud_api::Result<Vec<CloudPath>> {
+    match matches.values_of(arg_name) {
+        Some(values) => values.map(str::parse).collect(),
+        None => Ok(default.into_iter().collect()),
+    }
+}
+
+/// Parses a transfer rate such as "500k" or "2m" into bytes per second. As
+/// with curl's --limit-rate, the suffixes are powers of 1024.
+pub fn parse_rate(s: &str) -> std::result::Result<u64, String> {
+    parse_bytes(s, "rate")
+}
+
+/// Parses a size such as "32m" into bytes, as `parse_rate` does a rate.
+pub fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    parse_bytes(s, "size")
+}
+
+fn parse_bytes(s: &str, what: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len() - 1], 1024),
+        Some('m') | Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('g') | Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    match digits.parse::<u64>() {
+        Ok(0) => {
+            Err(format!("The {} must be greater than zero: {:?}", what, s))
+        }
+        Ok(n) => Ok(n * multiplier),
+        Err(_) => Err(format!("Invalid {}: {:?}", what, s)),
+    }
+}
+
+// The arguments shared by commands which act on the documents matching
+// patterns.
+fn selection_args() -> Vec<clap::Arg<'static, 'static>> {
+    vec![
+        clap::Arg::with_name("yes")
+            .short("y")
+            .long("yes")
+            .help("Goes ahead without asking when several documents match"),
+        clap::Arg::with_name("allow-empty")
+            .long("allow-empty")
+            .help("Carries on when a pattern matches nothing"),
+    ]
+}
+
+// The arguments of the commands which empty the trash.
+fn trash_args() -> Vec<clap::Arg<'static, 'static>> {
+    vec![
+        clap::Arg::with_name("yes")
+            .short("y")
+            .long("yes")
+            .help("Goes ahead without asking"),
+        clap::Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("Prints what would be deleted, changing nothing"),
+    ]
+}
+
+// The arguments of commands which can print chosen details of each document
+// listed.
+fn fields_args() -> Vec<clap::Arg<'static, 'static>> {
+    vec![
+        clap::Arg::with_name("fields")
+            .long("fields")
+            .value_name("name,...")
+            .takes_value(true)
+            .validator(|s| Column::parse_list(&s).map(|_| ()))
+            .help("Prints these details of each document, separated by tabs: any of name, id, version, modified, path, type and bookmarked, or content-type and pages from the content cache, which are ? for documents not in it"),
+        clap::Arg::with_name("header")
+            .long("header")
+            .requires("fields")
+            .help("Starts the --fields output with a line of the fields' names"),
+    ]
+}
+
+/// The columns given to --fields, if it was.
+pub fn fields_from_arg(matches: &clap::ArgMatches) -> Option<Vec<Column>> {
+    matches
+        .value_of("fields")
+        .map(|s| Column::parse_list(s).unwrap())
+}
+
+fn sort_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("sort")
+        .long("sort")
+        .value_name("order")
+        .takes_value(true)
+        .possible_values(sort::SORT_VALUES)
+        .help("The order names are listed in: natural, the default, puts numbers in order of their value, so \"Meeting 2\" comes before \"Meeting 10\"; locale sorts as LANG's language does, in builds with the locale-sort feature; and codepoint compares byte by byte, the same everywhere, for scripts. JSON output is always in codepoint order.")
+}
+
+/// The order given to --sort, or natural order.
+pub fn sort_from_arg(matches: &clap::ArgMatches) -> CliResult<sort::SortOrder> {
+    match matches.value_of("sort") {
+        Some(s) => Ok(s.parse()?),
+        None => Ok(sort::SortOrder::default()),
+    }
+}
+
+fn content_type_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("content-type")
+        .long("content-type")
+        .value_name("type")
+        .takes_value(true)
+        .possible_values(content::CONTENT_TYPE_VALUES)
+        .help("Only documents of this type, by the content cache, without downloading anything; documents not in the cache are left out, see `cache warm`")
+}
+
+pub fn content_type_from_arg(
+    matches: &clap::ArgMatches,
+) -> Option<&'static str> {
+    let value = matches.value_of("content-type")?;
+    content::CONTENT_TYPE_VALUES
+        .iter()
+        .copied()
+        .find(|t| *t == value)
+}
+
+/// The command line the client takes.
+pub fn app() -> clap::App<'static, 'static> {
+    clap::App::new("reMarkable cloud cli")
+        .setting(clap::AppSettings::DisableHelpSubcommand)
+        .after_help(leaked(help::render_topic_list()))
+        .arg(clap::Arg::with_name("verbose")
+             .short("v")
+             .long("verbose")
+             .help("Prints more detail about problems encountered"))
+        .arg(clap::Arg::with_name("quiet")
+             .short("q")
+             .long("quiet")
+             .multiple(true)
+             .global(true)
+             .help("Prints only errors and the final summary; given twice, leaves out the summary too"))
+        .arg(clap::Arg::with_name("cached")
+             .long("cached")
+             .global(true)
+             .help("Uses the listing saved last time instead of fetching it; documents are still checked before being changed"))
+        .arg(clap::Arg::with_name("no-refresh")
+             .long("no-refresh")
+             .global(true)
+             .help("Leaves a stale cached listing be, rather than fetching it again for the next run"))
+        .arg(clap::Arg::with_name("log-json")
+             .long("log-json")
+             .value_name("path")
+             .takes_value(true)
+             .global(true)
+             .help("Appends a JSON record of each operation performed to the given file"))
+        .arg(clap::Arg::with_name("redact")
+             .long("redact")
+             .global(true)
+             .help("Replaces the names and ids of documents with hashes of them in everything printed or logged, for sharing output in bug reports"))
+        .arg(clap::Arg::with_name("redact-map")
+             .long("redact-map")
+             .value_name("path")
+             .takes_value(true)
+             .global(true)
+             .requires("redact")
+             .help("Writes what each hash printed under --redact stands for to the given file"))
+        .arg(clap::Arg::with_name("limit-rate")
+             .long("limit-rate")
+             .value_name("bytes/sec")
+             .takes_value(true)
+             .global(true)
+             .validator(|s| parse_rate(&s).map(|_| ()))
+             .help("Caps the combined transfer rate, e.g. 500k or 2m"))
+        .arg(clap::Arg::with_name("chunked-above")
+             .long("chunked-above")
+             .value_name("size")
+             .takes_value(true)
+             .global(true)
+             .validator(|s| parse_size(&s).map(|_| ()))
+             .help("Uploads files at least this big in chunks which are each retried, and kept across push --resume, where the cloud allows; 32m by default"))
+        .arg(clap::Arg::with_name("read-only")
+             .long("read-only")
+             .global(true)
+             .help("Refuses to change anything in the cloud; set read_only in settings.json to make this permanent"))
+        .arg(clap::Arg::with_name("no-validate-names")
+             .long("no-validate-names")
+             .global(true)
+             .help("Sends names as given, rather than trimmed and refused if empty"))
+        .arg(clap::Arg::with_name("normalize-paths")
+             .long("normalize-paths")
+             .global(true)
+             .help("Matches names in cloud paths ignoring case and how accented letters are encoded, so \"résumé\" finds \"Résumé\" however it was typed"))
+        .arg(clap::Arg::with_name("lang")
+             .long("lang")
+             .value_name("language")
+             .takes_value(true)
+             .global(true)
+             .help("Prints messages in this language, e.g. de, rather than the one LC_MESSAGES or LANG asks for; languages other than English need the i18n feature"))
+        .arg(clap::Arg::with_name("max-time")
+             .long("max-time")
+             .value_name("duration")
+             .takes_value(true)
+             .global(true)
+             .validator(|s| humantime::parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+             .help("Gives up on whatever is still talking to the cloud after this long, e.g. 90s or 10m"))
+        .arg(clap::Arg::with_name("wait-lock")
+             .long("wait-lock")
+             .value_name("secs")
+             .takes_value(true)
+             .global(true)
+             .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+             .help("Waits up to this many seconds for another run to let go of the profile, rather than failing straight away"))
+        .subcommand(
+            clap::SubCommand::with_name("ls")
+                .about("Lists files.")
+                .arg(clap::Arg::with_name("recurse")
+                     .short("r")
+                     .long("recursive")
+                     .help("Lists files recursively"))
+                .arg(clap::Arg::with_name("depth")
+                     .long("depth")
+                     .value_name("levels")
+                     .takes_value(true)
+                     .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Lists files at most this many levels down, implying --recursive"))
+                .arg(clap::Arg::with_name("shallow")
+                     .long("shallow")
+                     .conflicts_with_all(&["recurse", "depth"])
+                     .help("Answers from the cached listing without fetching it, if one saved in the last hour holds all of each folder, as cache warm --folder caches of its folder and those above it"))
+                .arg(clap::Arg::with_name("paths-only")
+                     .long("paths")
+                     .conflicts_with("fields")
+                     .help("Prints the full path of each file on a line of its own, in order, instead of the tree"))
+                .arg(clap::Arg::with_name("print0")
+                     .long("print0")
+                     .requires("paths-only")
+                     .help("Ends each path with a NUL rather than a newline, for names containing newlines"))
+                .arg(content_type_arg())
+                .arg(sort_arg())
+                .args(&fields_args())
+                // TODO: accept multiple paths
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("info")
+                .about("Describes a file in detail.")
+                // TODO: accept multiple files
+                .arg(clap::Arg::with_name("json")
+                     .long("json")
+                     .help("Prints a JSON object per document, including details read from inside its archive, which is downloaded"))
+                .arg(clap::Arg::with_name("content")
+                     .long("content")
+                     .conflicts_with("json")
+                     .help("Prints the page count, strokes drawn and archive size of each document, which is downloaded"))
+                .arg(clap::Arg::with_name("history")
+                     .long("history")
+                     .conflicts_with_all(&["json", "content"])
+                     .help("Prints what the cloud holds for each document now, marking where the cached listing differs; the listing entry is fetched afresh and the archive downloaded"))
+                .arg(clap::Arg::with_name("verify")
+                     .long("verify")
+                     .conflicts_with_all(&["json", "content", "history"])
+                     .help("Downloads each document's archive and checks it's whole: that the zip and every entry in it read, and the .content parses and its pages are there"))
+                .arg(clap::Arg::with_name("recursive")
+                     .short("r")
+                     .long("recursive")
+                     .requires("verify")
+                     .help("Verifies everything in folders"))
+                .arg(clap::Arg::with_name("filenames")
+                     .index(1)
+                     .multiple(true)
+                     .required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("stats")
+                .about("Reports how much is drawn in documents.")
+                .arg(clap::Arg::with_name("ink")
+                     .long("ink")
+                     .required(true)
+                     .help("Counts the strokes, length of ink, layers and pens on each page, downloading each document; folders count everything in them"))
+                .arg(clap::Arg::with_name("json")
+                     .long("json")
+                     .help("Prints a JSON object per document, with the numbers of every page"))
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)
+                     .required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pull")
+                .after_help(examples_help("pull"))
+                .about("Downloads files.")
+                .arg(clap::Arg::with_name("raw-zip")
+                     .long("raw-zip")
+                     .hidden(true)
+                     .help("Gets the raw .zip from the API rather than extracting the document. Mostly useful for development."))
+                .arg(clap::Arg::with_name("name-template")
+                     .long("name-template")
+                     .value_name("template")
+                     .takes_value(true)
+                     .validator(|s| s.parse::<Template>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Names output files from a template such as \"{name}-v{version}.{ext}\". Available placeholders: {name}, {id}, {version}, {date}, {ext}."))
+                .arg(clap::Arg::with_name("format")
+                     .long("format")
+                     .value_name("format")
+                     .takes_value(true)
+                     .possible_values(commands::PULL_FORMAT_VALUES)
+                     .help("What to write: the original document, or only what's drawn on it, with nothing behind, as an SVG per page (ink-svg) or a PDF (ink-pdf) the size of its pages"))
+                .arg(clap::Arg::with_name("id")
+                     .long("id")
+                     .value_name("uuid")
+                     .takes_value(true)
+                     .multiple(true)
+                     .number_of_values(1)
+                     .validator(|s| s.parse::<Uuid>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Pulls the document with the given id"))
+                .arg(clap::Arg::with_name("all-matches")
+                     .long("all-matches")
+                     .help("Pulls every document a bare name matches, rather than asking for a fuller path"))
+                .arg(clap::Arg::with_name("recursive")
+                     .short("r")
+                     .long("recursive")
+                     .help("Pulls everything in folders, into directories of the same names"))
+                .arg(clap::Arg::with_name("preserve-times")
+                     .long("preserve-times")
+                     .overrides_with("no-preserve-times")
+                     .help("Gives each file the time its document was last changed on the device (the default)"))
+                .arg(clap::Arg::with_name("no-preserve-times")
+                     .long("no-preserve-times")
+                     .overrides_with("preserve-times")
+                     .help("Leaves each file with the time it was pulled"))
+                .arg(clap::Arg::with_name("tag")
+                     .long("tag")
+                     .value_name("name")
+                     .takes_value(true)
+                     .multiple(true)
+                     .number_of_values(1)
+                     .help("Pulls every document with the given tag; downloads every document to check. Given more than once, or with --bookmarked, each set goes in a directory named after it, documents in several being hard-linked into each"))
+                .arg(clap::Arg::with_name("bookmarked")
+                     .long("bookmarked")
+                     .help("Pulls every document starred on the home screen; downloads every document to check"))
+                .arg(clap::Arg::with_name("output")
+                     .short("o")
+                     .long("output")
+                     .value_name("dir")
+                     .takes_value(true)
+                     .help("Writes the files here rather than to the current directory"))
+                .setting(clap::AppSettings::TrailingVarArg)
+                .arg(clap::Arg::with_name("filenames")
+                     .index(1)
+                     .multiple(true)
+                     .required_unless_one(&["id", "tag", "bookmarked"])),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("find")
+                .about("Lists documents and folders matching the given conditions.")
+                .args(&DocumentFilter::args())
+                .args(&fields_args())
+                .arg(clap::Arg::with_name("path")
+                     .long("path")
+                     .value_name("pattern")
+                     .takes_value(true)
+                     .validator(|s| s.parse::<Pattern>().map(|_| ()))
+                     .help("Only documents whose full path matches a pattern such as \"Work/scans/2023-*\""))
+                .arg(clap::Arg::with_name("empty")
+                     .long("empty")
+                     .help("Only notebooks with nothing drawn in them. Downloads every candidate notebook to check."))
+                .arg(clap::Arg::with_name("pinned")
+                     .long("pinned")
+                     .help("Only documents starred on the home screen. Downloads every candidate document to check."))
+                .arg(clap::Arg::with_name("limit")
+                     .long("limit")
+                     .value_name("count")
+                     .takes_value(true)
+                     .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Downloads at most this many documents for --empty, --pinned or --duplicates-of"))
+                .arg(clap::Arg::with_name("duplicates-of")
+                     .long("duplicates-of")
+                     .value_name("path")
+                     .takes_value(true)
+                     .conflicts_with_all(&["empty", "pinned"])
+                     .help("Only other copies of this document, with the same contents. Downloads every candidate document to check, oldest first."))
+                .arg(clap::Arg::with_name("by-name")
+                     .long("by-name")
+                     .requires("duplicates-of")
+                     .help("Takes documents with the same name as copies for --duplicates-of, without downloading anything"))
+                .arg(clap::Arg::with_name("limit-folders")
+                     .long("limit-folders")
+                     .value_name("path")
+                     .takes_value(true)
+                     .requires("duplicates-of")
+                     .conflicts_with("paths")
+                     .help("Only looks for copies in this folder"))
+                .arg(clap::Arg::with_name("with-notes")
+                     .long("with-notes")
+                     .value_name("term")
+                     .takes_value(true)
+                     .conflicts_with("duplicates-of")
+                     .help("Only documents whose note mentions this, ignoring case"))
+                .arg(content_type_arg().conflicts_with("duplicates-of"))
+                .arg(sort_arg().conflicts_with("duplicates-of"))
+                .arg(clap::Arg::with_name("json")
+                     .long("json")
+                     .requires("duplicates-of")
+                     .conflicts_with("fields")
+                     .help("Prints a JSON object per copy found"))
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("cache")
+                .about("Manages what's cached of documents' contents.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("warm")
+                        .about("Downloads the documents whose type, page count and tags aren't cached yet, so find and ls can tell them without downloading, and forgets those of documents since removed or changed.")
+                        .arg(clap::Arg::with_name("folder")
+                             .long("folder")
+                             .value_name("path")
+                             .takes_value(true)
+                             .help("Only caches what's in this folder, and only its part of the listing, which ls --shallow answers from; folders warmed so before stay cached")),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("note")
+                .after_help(examples_help("note"))
+                .about("Keeps notes on documents, in a folder of their own in the cloud.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("set")
+                        .about("Sets the note on a document, or removes it if empty.")
+                        .arg(clap::Arg::with_name("path")
+                             .index(1)
+                             .required(true))
+                        .arg(clap::Arg::with_name("text")
+                             .index(2)
+                             .required(true)))
+                .subcommand(
+                    clap::SubCommand::with_name("show")
+                        .about("Prints the note on a document.")
+                        .arg(clap::Arg::with_name("path")
+                             .index(1)
+                             .required(true)))
+                .subcommand(
+                    clap::SubCommand::with_name("search")
+                        .about("Prints the notes mentioning a term, ignoring case, each after its document.")
+                        .arg(clap::Arg::with_name("term")
+                             .index(1)
+                             .required(true)))
+                .subcommand(
+                    clap::SubCommand::with_name("prune")
+                        .about("Removes the notes on documents which have been deleted.")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("push")
+                .after_help(examples_help("push"))
+                .about("Uploads PDFs and EPUBs as new documents.")
+                .arg(clap::Arg::with_name("to")
+                     .long("to")
+                     .value_name("folder")
+                     .takes_value(true)
+                     .help("Folder to upload into, instead of the root or the folder the push settings give"))
+                .arg(clap::Arg::with_name("create-missing")
+                     .long("create-missing")
+                     .conflicts_with("to")
+                     .help("Makes the folders the push settings name which don't exist yet, rather than stopping"))
+                .arg(clap::Arg::with_name("resume")
+                     .long("resume")
+                     .conflicts_with_all(&["files", "stdin"])
+                     .help("Finishes, or rolls back if the file has changed since, any upload that was interrupted"))
+                .arg(clap::Arg::with_name("stdin")
+                     .long("stdin")
+                     .conflicts_with("files")
+                     .requires("name")
+                     .help("Reads the document to upload from stdin"))
+                .arg(clap::Arg::with_name("name")
+                     .long("name")
+                     .value_name("filename")
+                     .takes_value(true)
+                     .requires("stdin")
+                     .help("File name for the document read with --stdin, such as \"Paper.pdf\"; the extension gives its type"))
+                .arg(clap::Arg::with_name("on-conflict")
+                     .long("on-conflict")
+                     .value_name("action")
+                     .takes_value(true)
+                     .possible_values(push::ON_CONFLICT_VALUES)
+                     .help("What to do when the folder already has a document of the same name, instead of asking; without a terminal to ask on, skip"))
+                .arg(clap::Arg::with_name("recursive")
+                     .short("r")
+                     .long("recursive")
+                     .help("Pushes what's in directories, making folders to match and skipping what .remarkableignore files list"))
+                .arg(clap::Arg::with_name("strict")
+                     .long("strict")
+                     .help("Stops before pushing anything if it would put documents inside more folders, or more in a folder, than the tablet handles well, rather than only warning"))
+                .arg(clap::Arg::with_name("queue")
+                     .long("queue")
+                     .conflicts_with_all(&["resume", "stdin", "recursive"])
+                     .help("Checks the files and queues them to be pushed by `queue run`, without connecting to the cloud"))
+                .arg(clap::Arg::with_name("max-memory")
+                     .long("max-memory")
+                     .value_name("size")
+                     .takes_value(true)
+                     .validator(|s| parse_size(&s).map(|_| ()))
+                     .help("Roughly the most memory to build and send archives in, e.g. 64m, sending fewer at once and building those of large files in temporary files to stay within it"))
+                .arg(clap::Arg::with_name("verify")
+                     .long("verify")
+                     .help("Downloads each document again once it's uploaded, hashing it as it arrives, and fails it if the cloud's copy isn't what was sent"))
+                .arg(clap::Arg::with_name("verify-max-size")
+                     .long("verify-max-size")
+                     .value_name("size")
+                     .takes_value(true)
+                     .requires("verify")
+                     .validator(|s| parse_size(&s).map(|_| ()))
+                     .help("With --verify, pushes files larger than this, e.g. 100m, without reading them back"))
+                .arg(clap::Arg::with_name("optimize")
+                     .long("optimize")
+                     .help("Shrinks PDFs before uploading them, dropping what they don't use and compressing what isn't; any it can't be sure of doing safely are uploaded as they are"))
+                .arg(clap::Arg::with_name("downsample-dpi")
+                     .long("downsample-dpi")
+                     .value_name("dpi")
+                     .takes_value(true)
+                     .requires("optimize")
+                     .validator(|s| match s.parse::<u32>() {
+                         Ok(dpi) if dpi > 0 => Ok(()),
+                         _ => Err(format!("{:?} isn't a number of pixels per inch", s)),
+                     })
+                     .help("With --optimize, scales images drawn at more pixels per inch than this down to it, as scans often are, e.g. 200"))
+                .arg(clap::Arg::with_name("files")
+                     .index(1)
+                     .multiple(true)
+                     .required_unless_one(&["resume", "stdin"])),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("queue")
+                .about("Works through uploads queued with push --queue.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("list")
+                        .about("Lists the uploads waiting and those which failed."),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("run")
+                        .about("Pushes what's queued, retrying uploads which fail on errors that may go away.")
+                        .arg(clap::Arg::with_name("forever")
+                             .long("forever")
+                             .help("Keeps going until interrupted, waiting longer between tries while the cloud can't be reached, and taking on uploads queued meanwhile")),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("serve")
+                .about("Serves an HTTP API for other programs to list, pull and push documents with, until interrupted.")
+                .arg(clap::Arg::with_name("listen")
+                     .long("listen")
+                     .value_name("address")
+                     .takes_value(true)
+                     .default_value(serve::DEFAULT_LISTEN)
+                     .validator(|s| s.parse::<std::net::SocketAddr>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Address and port to listen on"))
+                .arg(clap::Arg::with_name("allow-remote")
+                     .long("allow-remote")
+                     .help("Allows listening on an address other machines can reach, rather than only loopback ones")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("watch")
+                .about("Reports documents as they're added, updated and removed, by printing each change as a line of JSON or sending it to webhooks, until interrupted.")
+                .after_help(examples_help("watch"))
+                .arg(clap::Arg::with_name("interval")
+                     .long("interval")
+                     .value_name("duration")
+                     .takes_value(true)
+                     .default_value(watch::DEFAULT_INTERVAL)
+                     .validator(|s| humantime::parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+                     .help("How long to wait between looking for changes"))
+                .arg(clap::Arg::with_name("debounce")
+                     .long("debounce")
+                     .value_name("duration")
+                     .takes_value(true)
+                     .default_value(watch::DEFAULT_DEBOUNCE)
+                     .validator(|s| humantime::parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+                     .help("How long a document has to stay as it is before a change to it is reported, so one written in several steps is reported once"))
+                .arg(clap::Arg::with_name("once")
+                     .long("once")
+                     .help("Looks once, reporting everything changed since watch last looked, and stops"))
+                .arg(clap::Arg::with_name("webhook")
+                     .long("webhook")
+                     .value_name("url")
+                     .takes_value(true)
+                     .multiple(true)
+                     .number_of_values(1)
+                     .help("Sends each change to this URL instead of printing it, signed with webhook_secret from settings.json; see help webhooks"))
+                .arg(clap::Arg::with_name("retries")
+                     .long("retries")
+                     .value_name("n")
+                     .takes_value(true)
+                     .default_value(leaked(webhook::DEFAULT_RETRIES.to_string()))
+                     .validator(|s| s.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("How many more times to try a delivery which fails before spooling it"))
+                .arg(clap::Arg::with_name("replay-spool")
+                     .long("replay-spool")
+                     .conflicts_with_all(&["once", "webhook"])
+                     .help("Sends the changes which couldn't be delivered before, and stops")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("await-document")
+                .about("Waits until a document is at the given path, then prints its id and version as JSON. Exits with 124 if it's still not there when the timeout passes.")
+                .after_help(examples_help("await-document"))
+                .arg(clap::Arg::with_name("path")
+                     .index(1)
+                     .required(true))
+                .arg(clap::Arg::with_name("min-version")
+                     .long("min-version")
+                     .value_name("n")
+                     .takes_value(true)
+                     .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Waits for the document to be at this version or later, as when waiting for an update"))
+                .arg(clap::Arg::with_name("timeout")
+                     .long("timeout")
+                     .value_name("secs")
+                     .takes_value(true)
+                     .default_value(leaked(await_document::DEFAULT_TIMEOUT.to_string()))
+                     .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("How many seconds to wait before giving up"))
+                .arg(clap::Arg::with_name("interval")
+                     .long("interval")
+                     .value_name("duration")
+                     .takes_value(true)
+                     .default_value(await_document::DEFAULT_INTERVAL)
+                     .validator(|s| humantime::parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+                     .help("About how long to wait between looks")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("peek")
+                .about("Shows the thumbnail of the page a document is open at, or of its first page.")
+                .arg(clap::Arg::with_name("open")
+                     .long("open")
+                     .help("Opens the thumbnail in the image viewer"))
+                .arg(clap::Arg::with_name("inline")
+                     .long("inline")
+                     .help("Shows the thumbnail in the terminal, if it can, or else opens it"))
+                .arg(clap::Arg::with_name("inline-protocol")
+                     .long("inline-protocol")
+                     .value_name("protocol")
+                     .takes_value(true)
+                     .possible_values(peek::PROTOCOL_VALUES)
+                     .requires("inline")
+                     .help("How to send the terminal the image, instead of going by what it seems to be"))
+                .arg(clap::Arg::with_name("output")
+                     .short("o")
+                     .long("output")
+                     .value_name("file.jpg")
+                     .takes_value(true)
+                     .help("Saves the thumbnail"))
+                .group(clap::ArgGroup::with_name("how")
+                       .args(&["open", "inline", "output"])
+                       .required(true))
+                .arg(clap::Arg::with_name("path")
+                     .index(1)
+                     .required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pages")
+                .about("Lists, reorders and deletes the pages of a notebook.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("list")
+                        .about("Prints each page's number, id, template and strokes drawn.")
+                        .arg(clap::Arg::with_name("path")
+                             .index(1)
+                             .required(true)))
+                .subcommand(
+                    clap::SubCommand::with_name("delete")
+                        .about("Deletes pages, uploading the notebook as a new version.")
+                        .arg(clap::Arg::with_name("pages")
+                             .long("pages")
+                             .value_name("list")
+                             .takes_value(true)
+                             .required(true)
+                             .help("Page numbers and ranges, counting from 1, such as 3,7 or 2-4"))
+                        .arg(clap::Arg::with_name("dry-run")
+                             .long("dry-run")
+                             .help("Prints the pages that would be left, changing nothing"))
+                        .arg(clap::Arg::with_name("path")
+                             .index(1)
+                             .required(true)))
+                .subcommand(
+                    clap::SubCommand::with_name("reorder")
+                        .about("Puts pages in a new order, uploading the notebook as a new version.")
+                        .arg(clap::Arg::with_name("order")
+                             .long("order")
+                             .value_name("list")
+                             .takes_value(true)
+                             .required(true)
+                             .help("Every page number, counting from 1, in the new order, such as 2,1,3-10"))
+                        .arg(clap::Arg::with_name("dry-run")
+                             .long("dry-run")
+                             .help("Prints the resulting page order, changing nothing"))
+                        .arg(clap::Arg::with_name("path")
+                             .index(1)
+                             .required(true))),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("pin")
+                .about("Stars documents on the home screen.")
+                .args(&selection_args())
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)
+                     .required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("unpin")
+                .about("Unstars documents on the home screen.")
+                .args(&selection_args())
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)
+                     .required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("mv")
+                .about("Moves or renames documents and folders.")
+                .args(&selection_args())
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)
+                     .min_values(2)
+                     .required(true)
+                     .help("Paths or patterns to move, followed by the destination folder (/ for the top level) or new path")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("trash")
+                .after_help(examples_help("trash"))
+                .about("Moves documents and folders to the trash.")
+                .setting(clap::AppSettings::SubcommandsNegateReqs)
+                .args(&selection_args())
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)
+                     .required(true)
+                     .help("Paths or patterns to trash; write /prune or /empty for documents of those names"))
+                .subcommand(
+                    clap::SubCommand::with_name("prune")
+                        .about("Deletes for good what's been in the trash a while.")
+                        .args(&trash_args())
+                        .arg(clap::Arg::with_name("older-than")
+                             .long("older-than")
+                             .value_name("duration")
+                             .takes_value(true)
+                             .required(true)
+                             .validator(|s| humantime::parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+                             .help("Deletes what was trashed longer ago than this, such as 30d"))
+                        .arg(clap::Arg::with_name("keep-latest")
+                             .long("keep-latest")
+                             .value_name("n")
+                             .takes_value(true)
+                             .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                             .help("Keeps the n most recently trashed, however old")))
+                .subcommand(
+                    clap::SubCommand::with_name("empty")
+                        .about("Deletes everything in the trash for good.")
+                        .args(&trash_args())),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("rm")
+                .about("Deletes documents and empty folders for good.")
+                .args(&selection_args())
+                .arg(clap::Arg::with_name("recursive")
+                     .short("r")
+                     .long("recursive")
+                     .help("Also deletes everything in the folders given"))
+                .arg(clap::Arg::with_name("paths")
+                     .index(1)
+                     .multiple(true)
+                     .required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("export")
+                .after_help(examples_help("export"))
+                .about("Writes out documents in other formats, or the document tree for other tools.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommands(exporters::EXPORTERS.iter().map(|exporter| {
+                    clap::SubCommand::with_name(exporter.name())
+                        .about(exporter.about())
+                        .arg(clap::Arg::with_name("output")
+                             .short("o")
+                             .long("output")
+                             .value_name("dir")
+                             .takes_value(true)
+                             .help("Writes the files here rather than to the current directory"))
+                        .arg(clap::Arg::with_name("fallback")
+                             .long("fallback")
+                             .value_name("format")
+                             .takes_value(true)
+                             .possible_values(&exporters::names())
+                             .help("Exports documents the format doesn't suit in this one, rather than skipping them"))
+                        .arg(clap::Arg::with_name("recursive")
+                             .short("r")
+                             .long("recursive")
+                             .help("Exports everything in the folders given, into directories of the same names"))
+                        .arg(clap::Arg::with_name("from-zip")
+                             .long("from-zip")
+                             .value_name("file")
+                             .takes_value(true)
+                             .multiple(true)
+                             .number_of_values(1)
+                             .conflicts_with("paths")
+                             .help("Exports an archive saved locally, such as by pull --raw-zip, rather than a document in the cloud"))
+                        .arg(clap::Arg::with_name("from-dir")
+                             .long("from-dir")
+                             .value_name("dir")
+                             .takes_value(true)
+                             .conflicts_with("paths")
+                             .help("Exports every archive in a directory, or in a backup, keeping the folders they're in"))
+                        .arg(clap::Arg::with_name("paths")
+                             .index(1)
+                             .multiple(true)
+                             .required_unless_one(&["from-zip", "from-dir"]))
+                }))
+                .subcommands(["csv", "opml"].iter().map(|format| {
+                    clap::SubCommand::with_name(format)
+                        .about(if *format == "csv" {
+                            "Prints a CSV row per document and folder."
+                        } else {
+                            "Prints an OPML outline of the folder tree."
+                        })
+                        .arg(clap::Arg::with_name("include-trash")
+                             .long("include-trash")
+                             .help("Also includes what's in the trash"))
+                        .arg(clap::Arg::with_name("path")
+                             .index(1)
+                             .help("Exports only what's below this folder"))
+                }))
+                .subcommand(
+                    clap::SubCommand::with_name("feed")
+                        .about("Writes an Atom feed of the documents added or updated since it was last run.")
+                        .arg(clap::Arg::with_name("since")
+                             .long("since")
+                             .value_name("state-file")
+                             .takes_value(true)
+                             .help("Where the listing is kept between runs, by default in the config directory; nothing is new the first time"))
+                        .arg(clap::Arg::with_name("output")
+                             .short("o")
+                             .long("output")
+                             .value_name("feed.xml")
+                             .takes_value(true)
+                             .help("Writes the feed here rather than to standard output")))
+                .subcommand(
+                    clap::SubCommand::with_name("digest")
+                        .about("Puts the pages bookmarked on the tablet, by tagging them, together into one PDF.")
+                        .arg(clap::Arg::with_name("output")
+                             .short("o")
+                             .long("output")
+                             .value_name("digest.pdf")
+                             .takes_value(true)
+                             .required(true))
+                        .arg(clap::Arg::with_name("since")
+                             .long("since")
+                             .value_name("duration")
+                             .takes_value(true)
+                             .validator(|s| humantime::parse_duration(&s).map(|_| ()).map_err(|e| e.to_string()))
+                             .help("Only takes pages tagged this recently, such as 7d"))),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("backup")
+                .about("Saves every document to a single archive.")
+                .arg(clap::Arg::with_name("output")
+                     .short("o")
+                     .long("output")
+                     .value_name("file.tar.zst")
+                     .takes_value(true)
+                     .required(true))
+                .arg(clap::Arg::with_name("resume")
+                     .long("resume")
+                     .help("Keeps what an interrupted backup to the same file already saved"))
+                .arg(clap::Arg::with_name("resume-plan")
+                     .long("resume-plan")
+                     .help("Carries on an interrupted backup from the plan it saved beside the output, only planning again what's changed since; implies --resume"))
+                .arg(clap::Arg::with_name("reproducible")
+                     .long("reproducible")
+                     .conflicts_with_all(&["resume", "resume-plan"])
+                     .help("Makes backups of unchanged documents byte for byte the same, failing rather than leaving any document out")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("restore")
+                .about("Uploads the contents of a backup archive.")
+                .arg(clap::Arg::with_name("into")
+                     .long("into")
+                     .value_name("folder")
+                     .takes_value(true)
+                     .help("Restores under the given folder rather than the root"))
+                .arg(clap::Arg::with_name("keep-ids")
+                     .long("keep-ids")
+                     .help("Restores documents under their original ids, replacing them if they still exist"))
+                .arg(clap::Arg::with_name("strict")
+                     .long("strict")
+                     .help("Stops before restoring anything if it would put documents inside more folders, or more in a folder, than the tablet handles well, rather than only warning"))
+                .arg(clap::Arg::with_name("pick")
+                     .long("pick")
+                     .value_name("dir")
+                     .takes_value(true)
+                     .conflicts_with("archive")
+                     .help("Restores one document from a directory of its archives, saved at different times, as a new version of it, or as a new document if it's gone"))
+                .arg(clap::Arg::with_name("version")
+                     .long("version")
+                     .value_name("N")
+                     .takes_value(true)
+                     .requires("pick")
+                     .validator(|s| s.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Picks the archive saved at this version rather than asking"))
+                .arg(clap::Arg::with_name("date")
+                     .long("date")
+                     .value_name("YYYY-MM-DD")
+                     .takes_value(true)
+                     .requires("pick")
+                     .validator(|s| s.parse::<chrono::NaiveDate>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("Picks the newest archive modified on or before this day rather than asking"))
+                .arg(clap::Arg::with_name("archive")
+                     .index(1)
+                     .required_unless("pick")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("undo")
+                .about("Reverses the last moves and renames, unless the documents have changed since.")
+                .arg(clap::Arg::with_name("last")
+                     .long("last")
+                     .value_name("count")
+                     .takes_value(true)
+                     .default_value("1")
+                     .validator(|s| s.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                     .help("How many changes to reverse, newest first; uploads and deletions are counted but can't be undone")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("auth")
+                .about("Shows how the client signs in.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("status")
+                        .about("Prints the endpoint, account and token expiry, without the tokens.")
+                        .arg(clap::Arg::with_name("check")
+                             .long("check")
+                             .help("Also makes a request to check the cloud answers, and how quickly"))),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("setup")
+                .about("Sets up this computer: registers it with a one-time code, checks the documents can be listed, and writes starter settings and shell completions. Steps already done are skipped, so it's safe to run again.")
+                .arg(clap::Arg::with_name("code")
+                     .long("code")
+                     .takes_value(true)
+                     .value_name("CODE")
+                     .help("Registers with this one-time code rather than asking for one, even if already registered"))
+                .arg(clap::Arg::with_name("endpoint")
+                     .long("endpoint")
+                     .takes_value(true)
+                     .value_name("URL")
+                     .help("The storage host to register with, for clouds other than the official one"))
+                .arg(clap::Arg::with_name("defaults")
+                     .long("defaults")
+                     .help("Asks nothing, taking the default answer to each question")),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("doctor")
+                .about("Checks that this version still speaks the cloud's protocol, with a harmless request to each endpoint."),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("fsck")
+                .about("Reports folders nested deeper, or holding more, than the tablet handles well, as the settings max_folder_depth and max_folder_items give."),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("sync")
+                .about("Keeps a local directory in step with a cloud folder.")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("status")
+                        .about("Lists what changed in a synced directory, and in its cloud folder, since they were last synced, without transferring anything. Exits with 1 if anything did.")
+                        .arg(clap::Arg::with_name("local-dir")
+                             .index(1)
+                             .required(true)))
+                .subcommand(
+                    clap::SubCommand::with_name("pull")
+                        .about("Brings what changed in the cloud folder since the directory was last synced into it, and removes what was deleted there, leaving what changed on both sides to resolve. The first pull of a directory makes it a synced one.")
+                        .arg(clap::Arg::with_name("local-dir")
+                             .index(1)
+                             .required(true))
+                        .arg(clap::Arg::with_name("folder")
+                             .index(2)
+                             .help("The cloud folder to sync with; needed only the first time"))
+                        .arg(clap::Arg::with_name("layout")
+                             .long("layout")
+                             .takes_value(true)
+                             .possible_values(layout::LAYOUT_VALUES)
+                             .help("Where documents go in the directory: mirror puts them in folders as the cloud has them, flat all in the directory itself, by-date in YYYY/MM folders by when they were last modified, and by-tag in a folder for each tag, hardlinked into each if they have several. A directory keeps the layout it was first pulled with [default: sync_layout in settings.json, or mirror]"))
+                        .arg(clap::Arg::with_name("relayout")
+                             .long("relayout")
+                             .help("Moves what's in the directory to where --layout puts it, if it was laid out otherwise"))
+                        .arg(clap::Arg::with_name("resume-plan")
+                             .long("resume-plan")
+                             .conflicts_with("relayout")
+                             .help("Carries on an interrupted pull from the plan it saved in the directory, only planning again what's changed since")))
+                .subcommand(
+                    clap::SubCommand::with_name("resolve")
+                        .about("Settles what changed on both sides of a synced directory, keeping the cloud's version, the local one, or both, asking which for each unless --strategy says. An interrupted resolve is carried on by running it again.")
+                        .arg(clap::Arg::with_name("local-dir")
+                             .index(1)
+                             .required(true))
+                        .arg(clap::Arg::with_name("strategy")
+                             .long("strategy")
+                             .takes_value(true)
+                             .possible_values(sync::STRATEGY_VALUES)
+                             .help("Settles every conflict the same way: keep-cloud overwrites the local file, keep-local uploads it as a new version, and keep-both uploads it as a copy named with \" (local)\" and pulls the cloud's"))),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("help")
+                .about("Prints this message, the help of a command, or one of the topics listed below.")
+                .arg(clap::Arg::with_name("topic")
+                     .index(1)
+                     .multiple(true)
+                     .help("A command such as push or trash prune, or a topic such as path-addressing")),
+        )
+}
+
+// `s` as a string for clap, which only takes ones that live for good.
+// Help is built once per run, so nothing much is lost.
+fn leaked(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+/// The help `help` is asked for: that of the command `words` name, or of
+/// the topic they name, or without any, the client's own.
+pub fn help_text(words: &[&str]) -> CliResult<String> {
+    if let Some(topic) = help::topic(&words.join(" ")) {
+        return Ok(help::render_topic(topic));
+    }
+    let args = std::iter::once("remarkable-cloud")
+        .chain(words.iter().copied())
+        .chain(std::iter::once("--help"));
+    match app().get_matches_from_safe(args) {
+        Err(e) if e.kind == clap::ErrorKind::HelpDisplayed => {
+            Ok(format!("{}\n", e.message))
+        }
+        _ => Err(format!(
+            "There's no command or topic {:?}; the topics are {}",
+            words.join(" "),
+            help::TOPICS
+                .iter()
+                .map(|t| t.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
+
+// The examples section of the help of the command `name`.
+fn examples_help(name: &str) -> &'static str {
+    leaked(help::render_examples(
+        help::examples(name).unwrap_or_default(),
+    ))
+}
+
+/// The archives `export <format>` is to export instead of documents in the
+/// cloud, if it's given any.
+pub fn local_archives(
+    sub_m: &clap::ArgMatches,
+) -> Option<exporters::LocalArchives> {
+    if !sub_m.is_present("from-zip") && !sub_m.is_present("from-dir") {
+        return None;
+    }
+    Some(exporters::LocalArchives {
+        zips: sub_m
+            .values_of("from-zip")
+            .map_or_else(Vec::new, |v| v.map(PathBuf::from).collect()),
+        dir: sub_m.value_of("from-dir").map(PathBuf::from),
+    })
+}
+
+/// How the command `matches` is for locks the profile, if at all. `queue run
+/// --forever` takes the lock for each run of the queue instead.
+pub fn lock_mode(matches: &clap::ArgMatches) -> Option<LockMode> {
+    let (name, sub_m) = matches.subcommand();
+    let sub_m = sub_m?;
+    let (action, action_m) = sub_m.subcommand();
+    let exclusive = match (name, action) {
+        ("help", _) | ("queue", "list") => return None,
+        ("push", _) if sub_m.is_present("queue") => return None,
+        // Archives saved locally are exported without the profile.
+        ("export", _) if action_m.and_then(local_archives).is_some() => {
+            return None
+        }
+        // Each job takes the lock while it runs.
+        ("serve", _) => return None,
+        // Nothing is changed, and it runs until interrupted.
+        ("watch", _) | ("await-document", _) => return None,
+        ("queue", "run")
+            if action_m.is_some_and(|m| m.is_present("forever")) =>
+        {
+            return None
+        }
+        ("push", _)
+        | ("queue", "run")
+        | ("pages", "delete")
+        | ("pages", "reorder")
+        | ("pin", _)
+        | ("unpin", _)
+        | ("mv", _)
+        | ("trash", _)
+        | ("rm", _)
+        | ("export", "feed")
+        | ("note", "set")
+        | ("note", "prune")
+        | ("restore", _)
+        | ("setup", _)
+        | ("sync", "resolve")
+        // These write the cached listing, or all of a local directory, so
+        // nothing else may read or write either halfway through.
+        | ("sync", "pull")
+        | ("cache", "warm")
+        | ("undo", _) => true,
+        _ => false,
+    };
+    Some(if exclusive {
+        LockMode::Exclusive
+    } else {
+        LockMode::Shared
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preflight;
+
+    #[test]
+    fn rates() {
+        assert_eq!(parse_rate("100"), Ok(100));
+        assert_eq!(parse_rate("500k"), Ok(500 * 1024));
+        assert_eq!(parse_rate("2M"), Ok(2 * 1024 * 1024));
+        assert_eq!(parse_rate("1g"), Ok(1024 * 1024 * 1024));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("k").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("-5k").is_err());
+    }
+
+    #[test]
+    fn lock_modes() {
+        let mode = |args: &[&str]| {
+            let args = [&["remarkable-cloud"], args].concat();
+            lock_mode(&app().get_matches_from(args))
+        };
+        assert_eq!(mode(&["ls"]), Some(LockMode::Shared));
+        assert_eq!(mode(&["mv", "a", "b"]), Some(LockMode::Exclusive));
+        assert_eq!(
+            mode(&["cache", "warm", "--folder", "/Work"]),
+            Some(LockMode::Exclusive)
+        );
+        assert_eq!(mode(&["sync", "pull", "dir"]), Some(LockMode::Exclusive));
+        assert_eq!(mode(&["sync", "status", "dir"]), Some(LockMode::Shared));
+        assert_eq!(mode(&["watch"]), None);
+    }
+
+    // Every documented command line parses as written, so the examples can
+    // never name flags or values the commands don't take.
+    #[test]
+    fn examples_parse() {
+        let mut examples: Vec<(Option<&str>, &help::Example)> = vec![];
+        for (command, table) in help::EXAMPLES {
+            examples.extend(table.iter().map(|e| (Some(*command), e)));
+        }
+        for topic in help::TOPICS {
+            examples.extend(topic.examples.iter().map(|e| (None, e)));
+        }
+        for (command, example) in examples {
+            let args = help::example_arguments(example.command);
+            assert_eq!(args[0], "remarkable-cloud", "{}", example.command);
+            let matches = match app().get_matches_from_safe(&args) {
+                Ok(matches) => matches,
+                Err(e) => panic!("{}: {}", example.command, e.message),
+            };
+            if let Some(command) = command {
+                assert_eq!(
+                    matches.subcommand_name(),
+                    Some(command),
+                    "{}",
+                    example.command
+                );
+            }
+        }
+    }
+
+    // Each rule's example breaks it, and what's said names its flags.
+    #[test]
+    fn preflight_rules() {
+        for rule in preflight::RULES {
+            let mut args = vec!["remarkable-cloud"];
+            args.extend(rule.command.split(' '));
+            args.extend(rule.example);
+            let matches = match app().get_matches_from_safe(&args) {
+                Ok(matches) => matches,
+                Err(e) => panic!("{:?}: {}", args, e.message),
+            };
+            let problems = match preflight::check(&matches) {
+                Err(problems) => problems.0,
+                Ok(()) => panic!("{:?} passed", args),
+            };
+            let mentions = |p: &String| {
+                rule.flags.iter().all(|flag| match flag.starts_with("--") {
+                    true => p.contains(flag),
+                    false => p.contains(args.last().unwrap()),
+                })
+            };
+            assert!(
+                problems.iter().any(mentions),
+                "{:?}: {:?}",
+                args,
+                problems
+            );
+        }
+    }
+
+    // The flags listed in a command's help, leaving out the examples.
+    fn flags_in_help(words: &[&str]) -> Vec<String> {
+        let mut args = vec!["remarkable-cloud"];
+        args.extend(words);
+        args.push("--help");
+        let help = app().get_matches_from_safe(&args).unwrap_err().message;
+        let help = help.split("EXAMPLES:").next().unwrap();
+        help.lines()
+            .filter_map(|line| {
+                let line = line.trim_start();
+                let line = match line.strip_prefix('-')?.chars().next()? {
+                    '-' => line,
+                    _ => line.get(4..)?,
+                };
+                let flag = line.split([' ', '<']).next()?;
+                Some(flag.strip_prefix("--")?).map(|f| format!("--{}", f))
+            })
+            .collect()
+    }
+
+    // A command with rules has every flag it takes declared, so that a new
+    // one has to be thought about.
+    #[test]
+    fn preflight_declares_every_flag() {
+        let global = flags_in_help(&[]);
+        for rule in preflight::RULES {
+            assert!(
+                preflight::INDEPENDENT
+                    .iter()
+                    .any(|(c, _)| *c == rule.command),
+                "{} isn't in INDEPENDENT",
+                rule.command
+            );
+        }
+        for (command, independent) in preflight::INDEPENDENT {
+            let words: Vec<&str> = command.split(' ').collect();
+            for flag in flags_in_help(&words) {
+                let declared = independent.contains(&flag.as_str())
+                    || preflight::RULES.iter().any(|r| {
+                        r.command == *command
+                            && r.flags.contains(&flag.as_str())
+                    });
+                assert!(
+                    declared || global.contains(&flag),
+                    "{} {} has no rules and isn't in INDEPENDENT",
+                    command,
+                    flag
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn help_topics() {
+        assert!(help_text(&["path-addressing"]).is_ok());
+        assert!(help_text(&["trash", "prune"]).is_ok());
+        assert!(help_text(&["sync", "status"]).is_ok());
+        assert!(help_text(&["sync", "pull"]).is_ok());
+        assert!(help_text(&["sync", "resolve"]).is_ok());
+        assert!(help_text(&["mirror"]).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! What can be printed about each document, shared by `ls --fields`, `find
+//! --fields`, the CSV export and the JSON output, so a value reads the same
+//! wherever it's printed.
+//!
+//! A document attribute added to [`Column`] is available to all of them.
+//! Names, ids and paths are redacted here under `--redact`, see
+//! [`crate::redact`].
+
+use remarkable_cloud_api::Document;
+
+use crate::{content, msg, redact};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Name,
+    Id,
+    Version,
+    Modified,
+    Path,
+    Type,
+    Bookmarked,
+    ContentType,
+    Pages,
+}
+
+impl Column {
+    pub const ALL: [Column; 7] = [
+        Column::Name,
+        Column::Id,
+        Column::Version,
+        Column::Modified,
+        Column::Path,
+        Column::Type,
+        Column::Bookmarked,
+    ];
+
+    /// The columns read from the content cache rather than the listing,
+    /// which `--fields` takes besides `ALL`. They're `?` for documents whose
+    /// contents aren't cached.
+    pub const FROM_CONTENT: [Column; 2] = [Column::ContentType, Column::Pages];
+
+    /// The name given to `--fields`, and heading the column.
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Name => "name",
+            Column::Id => "id",
+            Column::Version => "version",
+            Column::Modified => "modified",
+            Column::Path => "path",
+            Column::Type => "type",
+            Column::Bookmarked => "bookmarked",
+            Column::ContentType => "content-type",
+            Column::Pages => "pages",
+        }
+    }
+
+    /// The key of the value in JSON output, which follows the listing's
+    /// own names.
+    pub fn key(self) -> &'static str {
+        match self {
+            Column::Name => "visible_name",
+            Column::Modified => "modified_client",
+            Column::ContentType => "file_type",
+            Column::Pages => "page_count",
+            other => other.name(),
+        }
+    }
+
+    /// The value for `doc`, found at `path`, as text.
+    pub fn text(self, path: &str, doc: &Document) -> String {
+        match self {
+            Column::Name => redact::name(&doc.visible_name).into_owned(),
+            Column::Id => redact::id(&doc.id).to_string(),
+            Column::Version => doc.version.to_string(),
+            Column::Modified => doc.modified_client.to_rfc3339(),
+            Column::Path => redact::text(path).into_owned(),
+            Column::Type => doc.doc_type.as_str().to_string(),
+            Column::Bookmarked => doc.bookmarked.to_string(),
+            Column::ContentType | Column::Pages if !doc.is_document() => {
+                String::new()
+            }
+            Column::ContentType => content::get(doc)
+                .map_or_else(|| "?".to_string(), |c| c.file_type),
+            Column::Pages => content::get(doc)
+                .and_then(|c| c.page_count)
+                .map_or_else(|| "?".to_string(), |n| n.to_string()),
+        }
+    }
+
+    /// The value for `doc`, found at `path`, as JSON.
+    pub fn json(self, path: &str, doc: &Document) -> serde_json::Value {
+        match self {
+            Column::Name => serde_json::json!(redact::name(&doc.visible_name)),
+            Column::Id => serde_json::json!(redact::id(&doc.id)),
+            Column::Version => serde_json::json!(doc.version),
+            Column::Modified => serde_json::json!(doc.modified_client),
+            Column::Path => serde_json::json!(redact::text(path)),
+            Column::Type => serde_json::json!(doc.doc_type),
+            Column::Bookmarked => serde_json::json!(doc.bookmarked),
+            Column::ContentType => {
+                serde_json::json!(content::get(doc).map(|c| c.file_type))
+            }
+            Column::Pages => {
+                serde_json::json!(content::get(doc).and_then(|c| c.page_count))
+            }
+        }
+    }
+
+    /// The columns named in a comma-separated list, in its order.
+    pub fn parse_list(s: &str) -> Result<Vec<Column>, String> {
+        s.split(',')
+            .map(|name| {
+                let all = Column::ALL.iter().chain(&Column::FROM_CONTENT);
+                all.clone().copied().find(|c| c.name() == name).ok_or_else(
+                    || {
+                        let names: Vec<&str> = all.map(|c| c.name()).collect();
+                        msg!(
+                            FIELD_UNKNOWN,
+                            field = format!("{:?}", name),
+                            fields = names.join(", "),
+                        )
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+// Escapes what would otherwise end a field or a row, as in the
+// "linear TSV" convention.
+fn tsv_field(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The names of `columns`, separated by tabs.
+pub fn tsv_header(columns: &[Column]) -> String {
+    let names: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+    names.join("\t")
+}
+
+/// The values of `columns` for `doc`, separated by tabs.
+pub fn tsv_row(columns: &[Column], path: &str, doc: &Document) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|c| tsv_field(&c.text(path, doc)))
+        .collect();
+    fields.join("\t")
+}
+
+/// The values of `columns` for `doc`, as a JSON object.
+pub fn json(
+    columns: &[Column],
+    path: &str,
+    doc: &Document,
+) -> serde_json::Map<String, serde_json::Value> {
+    columns
+        .iter()
+        .map(|c| (c.key().to_string(), c.json(path, doc)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    #[test]
+    fn rows() {
+        let docs =
+            listing(&[(1, "Tabs\tand\nnewlines \\o/", None, "DocumentType")]);
+        let doc = docs.get(&Uuid::from_u128(1)).unwrap();
+        let columns = Column::parse_list("type,name,id").unwrap();
+        assert_eq!(tsv_header(&columns), "type\tname\tid");
+        assert_eq!(
+            tsv_row(&columns, "Tabs", doc),
+            format!(
+                "DocumentType\tTabs\\tand\\nnewlines \\\\o/\t{}",
+                Uuid::from_u128(1)
+            )
+        );
+        let object = json(&[Column::Name, Column::Path], "Tabs", doc);
+        assert_eq!(
+            serde_json::Value::Object(object),
+            serde_json::json!({
+                "visible_name": "Tabs\tand\nnewlines \\o/",
+                "path": "Tabs",
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_fields() {
+        assert_eq!(
+            Column::parse_list("name,size"),
+            Err("Unknown field \"size\"; the fields are name, id, version, \
+                 modified, path, type, bookmarked, content-type, pages"
+                .to_string())
+        );
+        assert!(Column::parse_list("").is_err());
+    }
+}
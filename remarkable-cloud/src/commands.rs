@@ -0,0 +1,1664 @@
+//! The commands which list and transfer documents, as functions taking
+//! their options and an [`Output`] to report to, so that they can be run
+//! without the command line.
+//!
+//! [`crate::dispatch`] turns arguments into these options, and the binary
+//! prints what's reported; another program can drive the same commands and
+//! keep what happens.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use filetime::FileTime;
+use futures_util::{stream, StreamExt};
+use remarkable_cloud_api::{
+    Client, CloudPath, Conflict, Document, Documents, Error, Parent, Resolved,
+    Result,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::cache::{self, ListingCache, Refresher};
+use crate::columns::{self, Column};
+use crate::content;
+use crate::exporters::{self, ExportFile, Source};
+use crate::help::Example;
+use crate::limits::LimitCheck;
+use crate::mappings::{self, Mappings};
+use crate::mutations::MutationLog;
+use crate::observer::{Event, Observer};
+use crate::optimize::{optimize_pdf, OptimizeOptions};
+use crate::push::{self, OnConflict};
+use crate::redact;
+use crate::render::{self, ListOptions};
+use crate::resolved::ResolvedTree;
+use crate::sync::{ConflictKind, Strategy};
+use crate::template::{NameRegistry, Template, Values};
+use crate::{error_category, msg, naming, scan, CliResult};
+
+/// Where a command sends what it has to say, besides the [`Event`]s for the
+/// documents it transfers.
+pub trait Output: Observer {
+    /// A line for stdout: what the command was asked to print, or what it
+    /// couldn't find.
+    fn line(&mut self, line: &str);
+
+    /// A note on what the command is doing, which --quiet leaves out.
+    fn note(&mut self, note: &str);
+
+    /// A problem which didn't stop the command.
+    fn warn(&mut self, warning: &str);
+
+    /// What to do about pushing `name` where `conflict` already is, when the
+    /// options don't say.
+    fn on_conflict(
+        &mut self,
+        name: &str,
+        conflict: &Conflict,
+    ) -> io::Result<OnConflict>;
+
+    /// Which side to keep of the file at `path` and its document, which
+    /// conflict as `kind` says, when the options don't say. `None` leaves
+    /// them as they are.
+    fn on_sync_conflict(
+        &mut self,
+        path: &str,
+        kind: ConflictKind,
+    ) -> io::Result<Option<Strategy>>;
+
+    /// Whether to go ahead with what's just been listed, asking `question`,
+    /// when the options don't say.
+    fn confirm(&mut self, question: &str) -> io::Result<bool>;
+}
+
+/// An [`Output`] which keeps what it's told, passing events on to
+/// `observer`. Conflicts are skipped, with a warning.
+#[derive(Default)]
+pub struct Capture<O> {
+    pub observer: O,
+    pub lines: Vec<String>,
+    pub notes: Vec<String>,
+    pub warnings: Vec<String>,
+    /// What's answered when asked to confirm.
+    pub yes: bool,
+}
+
+impl<O: Observer> Capture<O> {
+    pub fn new(observer: O) -> Self {
+        Capture {
+            observer,
+            lines: vec![],
+            notes: vec![],
+            warnings: vec![],
+            yes: false,
+        }
+    }
+}
+
+impl<O: Observer> Observer for Capture<O> {
+    fn observe(&mut self, event: &Event) {
+        self.observer.observe(event);
+    }
+}
+
+impl<O: Observer> Output for Capture<O> {
+    fn line(&mut self, line: &str) {
+        self.lines.push(line.to_string());
+    }
+
+    fn note(&mut self, note: &str) {
+        self.notes.push(note.to_string());
+    }
+
+    fn warn(&mut self, warning: &str) {
+        self.warnings.push(warning.to_string());
+    }
+
+    fn on_conflict(
+        &mut self,
+        name: &str,
+        conflict: &Conflict,
+    ) -> io::Result<OnConflict> {
+        self.warn(&msg!(
+            CONFLICT_SKIPPED,
+            name = name,
+            existing = format!("{:?}", conflict.existing.visible_name),
+        ));
+        Ok(OnConflict::Skip)
+    }
+
+    fn on_sync_conflict(
+        &mut self,
+        path: &str,
+        kind: ConflictKind,
+    ) -> io::Result<Option<Strategy>> {
+        self.warn(&msg!(
+            SYNC_CONFLICT_KEPT,
+            path = path,
+            kind = kind.describe()
+        ));
+        Ok(None)
+    }
+
+    fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        self.lines.push(question.to_string());
+        Ok(self.yes)
+    }
+}
+
+pub struct ListingOptions {
+    pub verbose: bool,
+    /// Whether to use the cached listing, if there is one, rather than
+    /// fetching it.
+    pub use_cache: bool,
+    pub cache: ListingCache,
+    /// What refreshes the cached listing for the next run, when
+    /// `use_cache` finds it older than `cache::STALE_AFTER`, if it's to be.
+    pub refresh: Option<Refresher>,
+}
+
+/// Fetches the listing, or reads the saved one with `use_cache`, noting any
+/// entries which had to be left out of it.
+pub async fn list_documents(
+    client: &Client,
+    options: &ListingOptions,
+    out: &mut dyn Output,
+) -> Result<ResolvedTree> {
+    let cached = options.cache.load();
+    if options.use_cache {
+        if let Some(documents) = cached {
+            redact::learn(&documents);
+            let age = match options.cache.age() {
+                Some(age) => age,
+                None => return Ok(ResolvedTree::new(documents)),
+            };
+            if let Some(refresher) = &options.refresh {
+                if age > cache::STALE_AFTER {
+                    refresher.start(client, &options.cache, age);
+                }
+            }
+            return Ok(ResolvedTree::from_cache(documents, age));
+        }
+    }
+    let documents =
+        fetch_documents(client, options.verbose, cached.as_ref(), out).await?;
+    if let Err(e) = options.cache.save(&documents) {
+        out.warn(&msg!(LISTING_NOT_CACHED, error = e.to_string()));
+    }
+    Ok(ResolvedTree::new(documents))
+}
+
+/// Fetches the listing as `list_documents` does, without caching it,
+/// noting with `verbose` what's changed since `cached`.
+pub async fn fetch_documents(
+    client: &Client,
+    verbose: bool,
+    cached: Option<&Documents>,
+    out: &mut dyn Output,
+) -> Result<Documents> {
+    let documents = client.get_documents().await?;
+    redact::learn(&documents);
+    if let Some(cached) = cached.filter(|_| verbose) {
+        for d in documents.newer_than(cached) {
+            out.warn(&msg!(
+                LISTING_CHANGED_ELSEWHERE,
+                path = documents
+                    .path_of(&d.id)
+                    .unwrap_or_else(|| d.id.to_string()),
+                old = cached.get(&d.id).map_or(0, |c| c.version),
+                new = d.version,
+            ));
+        }
+    }
+    let warnings = documents.parse_warnings();
+    if verbose {
+        for (index, warning) in warnings {
+            out.warn(&msg!(
+                LISTING_ENTRY_UNPARSED,
+                index = *index,
+                warning = warning.to_string(),
+            ));
+        }
+    } else if !warnings.is_empty() {
+        out.warn(&msg!(LISTING_ENTRIES_UNPARSED, count = warnings.len()));
+    }
+    Ok(documents)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LsOptions {
+    /// The folders to list, or the root if there are none.
+    pub paths: Vec<CloudPath>,
+    pub list: ListOptions,
+    /// What to print about each document, as tab-separated rows, rather
+    /// than the tree.
+    pub fields: Option<Vec<Column>>,
+    /// Whether to start `fields` rows with a row of their names.
+    pub header: bool,
+}
+
+/// Lists what's in each folder, a line at a time.
+pub fn ls(documents: &ResolvedTree, options: &LsOptions, out: &mut dyn Output) {
+    let root = [CloudPath::root()];
+    let paths = if options.paths.is_empty() {
+        &root[..]
+    } else {
+        &options.paths[..]
+    };
+    let lines = |path| match &options.fields {
+        Some(columns) => {
+            render::ls_fields(documents, path, options.list, columns)
+        }
+        None => render::ls(documents, path, options.list),
+    };
+    if let (Some(columns), true) = (&options.fields, options.header) {
+        out.line(&columns::tsv_header(columns));
+    }
+    let mut unknown = 0;
+    for path in paths {
+        for line in lines(path) {
+            out.line(&line);
+        }
+        unknown += render::unknown_content(documents, path, options.list);
+    }
+    if unknown > 0 {
+        out.warn(&content::unknown_note(unknown));
+    }
+}
+
+pub struct PullOptions {
+    /// The documents to pull, by path or bare name, or with `recursive`
+    /// folders to pull everything in.
+    pub paths: Vec<PathBuf>,
+    pub ids: Vec<Uuid>,
+    /// Whether to pull every document a bare name matches, rather than none
+    /// when it matches several.
+    pub all_matches: bool,
+    /// Whether to pull what's in the folders among `paths`, into
+    /// directories of the same names.
+    pub recursive: bool,
+    pub raw_zip: bool,
+    /// What's written of each document, unless `raw_zip`.
+    pub format: PullFormat,
+    pub name_template: Option<Template>,
+    /// Where the files are written; the current directory if empty.
+    pub dir: PathBuf,
+    /// Whether to give each file the time its document was last changed on
+    /// the device, rather than the time it was pulled.
+    pub preserve_times: bool,
+    /// Sets of documents to pull all of, besides `paths` and `ids`. When
+    /// there are several, each goes in a directory named after it, and
+    /// documents in more than one are linked into each.
+    pub groups: Vec<PullGroup>,
+}
+
+/// A set of documents `pull` takes every one of, which each has to be
+/// downloaded to tell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PullGroup {
+    /// Those tagged with this name on the tablet.
+    Tag(String),
+    /// Those starred on the home screen.
+    Bookmarked,
+}
+
+impl PullGroup {
+    /// The directory the group's documents go in when several groups are
+    /// pulled.
+    pub fn dir_name(&self) -> &str {
+        match self {
+            PullGroup::Tag(name) => name,
+            PullGroup::Bookmarked => "bookmarked",
+        }
+    }
+
+    fn contains(&self, source: &Source) -> bool {
+        match self {
+            PullGroup::Tag(name) => source
+                .details
+                .content
+                .as_ref()
+                .is_some_and(|c| c.tags().contains(&name.as_str())),
+            PullGroup::Bookmarked => source.details.pinned(),
+        }
+    }
+}
+
+/// What `pull` writes of each document.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PullFormat {
+    /// The PDF or EPUB as it was pushed.
+    Original,
+    /// What's drawn on each page, by itself, as an SVG per page the size of
+    /// the page; see `render_ink_only`.
+    InkSvg,
+    /// What's drawn on the pages, by itself, as a PDF.
+    InkPdf,
+}
+
+/// The choices `PullFormat` parses from.
+pub const PULL_FORMAT_VALUES: &[&str] = &["original", "ink-svg", "ink-pdf"];
+
+impl FromStr for PullFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "original" => Ok(PullFormat::Original),
+            "ink-svg" => Ok(PullFormat::InkSvg),
+            "ink-pdf" => Ok(PullFormat::InkPdf),
+            _ => Err(msg!(
+                VALUE_NOT_ONE_OF,
+                value = format!("{:?}", s),
+                values = PULL_FORMAT_VALUES.join(", "),
+            )),
+        }
+    }
+}
+
+/// The examples `pull --help` shows.
+pub const PULL_EXAMPLES: &[Example] = &[
+    Example {
+        command: "remarkable-cloud pull Books/Dune",
+        description: "Pulls Dune as Dune.pdf into the current directory",
+    },
+    Example {
+        command: "remarkable-cloud pull -r Books",
+        description: "Pulls everything in Books into a directory Books",
+    },
+    Example {
+        command: "remarkable-cloud pull --name-template '{name}-v{version}.{ext}' Notes",
+        description: "Pulls Notes, putting its version in the file's name",
+    },
+    Example {
+        command: "remarkable-cloud pull --format ink-svg Books/Dune",
+        description: "Pulls what's drawn on each page of Dune, without the \
+                      book, as Dune-01.svg, Dune-02.svg and so on",
+    },
+    Example {
+        command: "remarkable-cloud pull --tag projectX --tag projectY -o \
+                  exports",
+        description: "Pulls everything tagged projectX into exports/projectX \
+                      and everything tagged projectY into exports/projectY",
+    },
+    Example {
+        command: "remarkable-cloud pull --no-preserve-times --id \
+                  8f5c4a1e-6a2b-4c6f-9d3e-2b1a7c9e0f11",
+        description: "Pulls a document by its id, timed as it's pulled",
+    },
+];
+
+impl Default for PullOptions {
+    fn default() -> Self {
+        PullOptions {
+            paths: vec![],
+            ids: vec![],
+            all_matches: false,
+            recursive: false,
+            raw_zip: false,
+            format: PullFormat::Original,
+            name_template: None,
+            dir: PathBuf::new(),
+            preserve_times: true,
+            groups: vec![],
+        }
+    }
+}
+
+// What a pull keeps track of from one document to the next.
+#[derive(Default)]
+struct PullState {
+    names: NameRegistry,
+    /// Whether setting a file's modification time has failed, which is
+    /// only warned about once.
+    times_failed: bool,
+}
+
+// A document to pull, with the path it was asked for by.
+struct Pull<'a> {
+    path: PathBuf,
+    doc: &'a Document,
+    /// What the local file is named after.
+    local: PathBuf,
+    /// The directory below `PullOptions::dir` to write it to, which matches
+    /// its folder in a recursive pull.
+    subdir: PathBuf,
+    /// Whether it's only pulled if it's in one of `PullOptions::groups`,
+    /// into the directory of each it's in.
+    grouped: bool,
+}
+
+/// Downloads the documents `options` picks out, reporting each to `out` as
+/// it goes. Documents which can't be found, or can't be written, are
+/// skipped; the first which fails to download stops the pull.
+pub async fn pull(
+    client: &Client,
+    documents: &ResolvedTree,
+    options: &PullOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut state = PullState::default();
+    let mut pulls = vec![];
+    for id in &options.ids {
+        match documents.get(id) {
+            Some(d) => pulls.push(Pull {
+                path: PathBuf::from(
+                    documents.path_of(id).unwrap_or(id.to_string()),
+                ),
+                doc: d,
+                local: PathBuf::from(&*d.visible_name),
+                subdir: PathBuf::new(),
+                grouped: false,
+            }),
+            None => out.line(&msg!(PULL_NO_SUCH_ID, id = id.to_string())),
+        }
+    }
+    for filepath in &options.paths {
+        match pull_targets(documents, filepath, options.all_matches) {
+            Ok(found) => {
+                pulls.extend(found.into_iter().map(|(d, local)| Pull {
+                    path: filepath.to_path_buf(),
+                    doc: d,
+                    local,
+                    subdir: PathBuf::new(),
+                    grouped: false,
+                }))
+            }
+            Err(TargetError::Folder(id)) if options.recursive => {
+                pulls.extend(folder_pulls(documents, id))
+            }
+            Err(e) => {
+                match &e {
+                    TargetError::NotFound(None) => out.line(&msg!(
+                        PULL_NOT_FOUND,
+                        path = format!("{:?}", filepath)
+                    )),
+                    TargetError::NotFound(Some(e))
+                    | TargetError::Invalid(e) => out.line(&e.to_string()),
+                    TargetError::Ambiguous(candidates) => {
+                        out.line(&msg!(
+                            PULL_AMBIGUOUS,
+                            path = format!("{:?}", filepath),
+                            count = candidates.len(),
+                        ));
+                        for (path, id) in candidates {
+                            out.line(&format!("  {}  {}", path, id));
+                        }
+                    }
+                    TargetError::Folder(_) => out.line(&msg!(
+                        PULL_FOLDER,
+                        path = format!("{:?}", filepath)
+                    )),
+                }
+                out.observe(&Event::Skipped {
+                    path: filepath.to_path_buf(),
+                    id: None,
+                    reason: e.reason().to_string(),
+                });
+            }
+        }
+    }
+    keep_apart(documents, &mut pulls);
+    if !options.groups.is_empty() {
+        pulls.extend(group_pulls(documents));
+    }
+    for pull in pulls {
+        pull_document(client, &pull, options, &mut state, out).await?;
+    }
+    Ok(())
+}
+
+// Renames pulls of different documents which would be written to the same
+// file, as names differing only in normalization, or in case where the local
+// filesystem ignores it, would be, qualifying them as `naming::disambiguate`
+// does.
+fn keep_apart(documents: &ResolvedTree, pulls: &mut [Pull]) {
+    let mut by_key: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (n, pull) in pulls.iter().enumerate() {
+        let name = pull.local.file_name().unwrap_or_default();
+        let key = naming::local_key(&name.to_string_lossy());
+        by_key
+            .entry((pull.subdir.clone(), key))
+            .or_default()
+            .push(n);
+    }
+    // The same document asked for twice is left for `NameRegistry` to skip.
+    let groups = by_key.into_values().filter(|group| {
+        group
+            .iter()
+            .any(|&n| pulls[n].doc.id != pulls[group[0]].doc.id)
+    });
+    for group in groups.collect::<Vec<_>>() {
+        let names: Vec<String> = group
+            .iter()
+            .map(|&n| {
+                let name = pulls[n].local.file_name().unwrap_or_default();
+                name.to_string_lossy().into_owned()
+            })
+            .collect();
+        let candidates: Vec<naming::Candidate> = group
+            .iter()
+            .zip(&names)
+            .map(|(&n, name)| naming::Candidate {
+                name,
+                parent: pulls[n]
+                    .doc
+                    .parent
+                    .and_then(|p| documents.get(&p))
+                    .map(|p| &*p.visible_name),
+                id: pulls[n].doc.id,
+            })
+            .collect();
+        let local =
+            naming::disambiguate(&candidates, naming::case_insensitive());
+        for (&n, local) in group.iter().zip(local) {
+            let pull = &mut pulls[n];
+            pull.local = pull.local.with_file_name(local);
+        }
+    }
+}
+
+// Every document, to be pulled if it's in one of the groups asked for, named
+// after itself whatever folder it's in.
+fn group_pulls(documents: &ResolvedTree) -> Vec<Pull<'_>> {
+    documents
+        .descendants(Parent::Root)
+        .filter(|(_, d)| !d.is_folder())
+        .map(|(_, d)| Pull {
+            path: PathBuf::from(
+                documents
+                    .path_of(&d.id)
+                    .unwrap_or_else(|| d.visible_name.to_string()),
+            ),
+            doc: d,
+            local: PathBuf::from(&*d.visible_name),
+            subdir: PathBuf::new(),
+            grouped: true,
+        })
+        .collect()
+}
+
+// The directories below `PullOptions::dir` `pull` goes in, or none if it's
+// in none of the groups it was for.
+fn pull_dirs(
+    pull: &Pull,
+    options: &PullOptions,
+    source: &Source,
+) -> Vec<PathBuf> {
+    if !pull.grouped {
+        return vec![pull.subdir.clone()];
+    }
+    let groups = options.groups.iter().filter(|g| g.contains(source));
+    if options.groups.len() == 1 {
+        groups.map(|_| pull.subdir.clone()).collect()
+    } else {
+        groups
+            .map(|g| Path::new(g.dir_name()).join(&pull.subdir))
+            .collect()
+    }
+}
+
+/// Makes `to` a hard link to `from`, or where the filesystem can't, a copy
+/// of it, returning whether it was linked. Anything at `to` is replaced.
+pub fn link_or_copy(from: &Path, to: &Path) -> io::Result<bool> {
+    link_or_copy_with(from, to, |from, to| fs::hard_link(from, to))
+}
+
+// `link_or_copy`, linking with `link`.
+fn link_or_copy_with(
+    from: &Path,
+    to: &Path,
+    link: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> io::Result<bool> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match fs::remove_file(to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    if link(from, to).is_ok() {
+        return Ok(true);
+    }
+    fs::copy(from, to)?;
+    Ok(false)
+}
+
+// Everything below the folder `id`, to go in a directory named after it
+// and subdirectories named after the folders below it.
+fn folder_pulls(documents: &ResolvedTree, id: Uuid) -> Vec<Pull<'_>> {
+    let top = documents.get(&id).map(|d| &*d.visible_name);
+    let mut folders: Vec<&str> = top.into_iter().collect();
+    let mut pulls = vec![];
+    for (depth, d) in documents.descendants(Parent::Folder(id)) {
+        folders.truncate(depth + 1);
+        if d.is_folder() {
+            folders.push(&d.visible_name);
+            continue;
+        }
+        pulls.push(Pull {
+            path: PathBuf::from(
+                documents
+                    .path_of(&d.id)
+                    .unwrap_or_else(|| d.visible_name.to_string()),
+            ),
+            doc: d,
+            local: PathBuf::from(&*d.visible_name),
+            subdir: folders.iter().collect(),
+            grouped: false,
+        });
+    }
+    pulls
+}
+
+// Fetches a document, with its archive.
+pub(crate) async fn fetch_document(
+    client: &Client,
+    doc: &Document,
+) -> Result<Source> {
+    let blobdoc = client.get_document_by_id(&doc.id).await?;
+    // TODO: add progress indicator
+    let docbytes = client.download_blob(&blobdoc).await?;
+    Ok(Source::new(blobdoc, docbytes))
+}
+
+// The names of the files written of a document, with their contents.
+type PulledFiles = Vec<(PathBuf, Vec<u8>)>;
+
+// Picks out what should be written locally of a fetched document, returning
+// the local file names and their contents, or the reason nothing was
+// written.
+fn pulled_files(
+    source: &Source,
+    filepath: &Path,
+    options: &PullOptions,
+) -> CliResult<std::result::Result<PulledFiles, String>> {
+    let doc = &source.details.document;
+    let exporter = match options.format {
+        _ if options.raw_zip => exporters::find("zip"),
+        PullFormat::Original => ["epub", "pdf"]
+            .iter()
+            .filter_map(|name| exporters::find(name))
+            .find(|e| e.supports(source)),
+        PullFormat::InkPdf => exporters::find("ink-pdf"),
+        PullFormat::InkSvg => exporters::find("svg"),
+    };
+    let exporter = match exporter {
+        Some(exporter) => exporter,
+        None => {
+            return Ok(Err(msg!(
+                PULL_NO_FILE,
+                path = format!("{:?}", filepath)
+            )))
+        }
+    };
+    let mut files: Vec<ExportFile> = vec![];
+    exporter.export(source, &mut files)?;
+    if files.is_empty() {
+        return Ok(Err(msg!(PULL_NO_PAGES, path = format!("{:?}", filepath))));
+    }
+    let mut named = vec![];
+    for ExportFile { ext, page, data } in files {
+        let fp = match &options.name_template {
+            Some(t) => PathBuf::from(t.render(&Values {
+                name: Some(&doc.visible_name),
+                id: Some(doc.id),
+                version: Some(doc.version),
+                date: Some(doc.modified_client.naive_utc().date()),
+                ext: Some(&ext),
+                ..Default::default()
+            })?),
+            None => add_ext_to_path(filepath, &ext),
+        };
+        // Pages each have a file, numbered from 1.
+        let fp = match page {
+            Some((n, count)) => numbered(&fp, n, count),
+            None => fp,
+        };
+        match fp.file_name() {
+            Some(fpn) => named.push((PathBuf::from(fpn), data)),
+            None => {
+                return Ok(Err(msg!(
+                    PULL_NO_FILENAME,
+                    path = format!("{:?}", fp)
+                )))
+            }
+        }
+    }
+    Ok(Ok(named))
+}
+
+// `path` with `n` added to its name, before its extension, padded to as many
+// digits as `count` has so that the files sort in order.
+fn numbered(path: &Path, n: usize, count: usize) -> PathBuf {
+    let width = count.to_string().len();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}-{:0width$}", stem, n, width = width);
+    if let Some(ext) = path.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+fn add_ext_to_path(path: &Path, ext: &str) -> PathBuf {
+    let mut buf = path.to_path_buf();
+    let mut newext = path.extension().unwrap_or_default().to_os_string();
+    if !newext.is_empty() {
+        newext.push(".");
+    }
+    newext.push(ext);
+    buf.set_extension(newext);
+    buf
+}
+
+enum TargetError {
+    /// Nothing is at the path, with why if it was looked up from the root.
+    NotFound(Option<Error>),
+    /// The path can't be read, or is ambiguous.
+    Invalid(Error),
+    /// A bare name matched several documents, listed by full path.
+    Ambiguous(Vec<(String, Uuid)>),
+    /// The path is of a folder, which only a recursive pull takes.
+    Folder(Uuid),
+}
+
+impl TargetError {
+    fn reason(&self) -> &'static str {
+        match self {
+            TargetError::NotFound(_) => "not found",
+            TargetError::Invalid(_) => "invalid path",
+            TargetError::Ambiguous(_) => "ambiguous",
+            TargetError::Folder(_) => "folder",
+        }
+    }
+}
+
+// Works out which documents a path given to pull refers to, along with the
+// local name each should be saved under. A bare name matches documents in
+// any folder; when it matches several, they're only pulled if `all_matches`
+// is set, under names qualified by their parent folder.
+fn pull_targets<'a>(
+    documents: &'a ResolvedTree,
+    filepath: &Path,
+    all_matches: bool,
+) -> std::result::Result<Vec<(&'a Document, PathBuf)>, TargetError> {
+    let path: CloudPath = filepath
+        .to_string_lossy()
+        .parse()
+        .map_err(TargetError::Invalid)?;
+    let found = documents.lookup(&path);
+    if let Ok(Resolved::Document(d)) = found {
+        if d.is_folder() {
+            return Err(TargetError::Folder(d.id));
+        }
+    }
+    let mut components = filepath.components();
+    let name = match (&path, components.next(), components.next()) {
+        (
+            CloudPath::Tree(_),
+            Some(std::path::Component::Normal(name)),
+            None,
+        ) => name.to_string_lossy(),
+        _ => {
+            return match found {
+                // Named after the document when the path doesn't end in its
+                // name.
+                Ok(Resolved::Document(d)) => match path {
+                    CloudPath::Tree(_) => Ok(vec![(d, filepath.to_path_buf())]),
+                    _ => Ok(vec![(d, PathBuf::from(&*d.visible_name))]),
+                },
+                Ok(_) => Err(TargetError::NotFound(None)),
+                Err(e @ Error::PathNotFound { .. }) => {
+                    Err(TargetError::NotFound(Some(e)))
+                }
+                Err(e) => Err(TargetError::Invalid(e)),
+            };
+        }
+    };
+    let mut matches: Vec<(String, &Document)> = documents
+        .iter()
+        .filter(|d| documents.same_name(&name, &d.visible_name))
+        .filter(|d| !d.is_folder())
+        .map(|d| {
+            let path = documents
+                .path_of(&d.id)
+                .unwrap_or_else(|| d.visible_name.to_string());
+            (path, d)
+        })
+        .collect();
+    matches.sort_by(|a, b| a.0.cmp(&b.0));
+    match matches.len() {
+        0 => Err(TargetError::NotFound(None)),
+        1 => Ok(vec![(matches[0].1, filepath.to_path_buf())]),
+        _ if all_matches => {
+            let candidates: Vec<naming::Candidate> = matches
+                .iter()
+                .map(|(_, d)| naming::Candidate {
+                    name: &d.visible_name,
+                    parent: d
+                        .parent
+                        .and_then(|p| documents.get(&p))
+                        .map(|p| &*p.visible_name),
+                    id: d.id,
+                })
+                .collect();
+            Ok(matches
+                .iter()
+                .zip(naming::disambiguate(
+                    &candidates,
+                    naming::case_insensitive(),
+                ))
+                .map(|((_, d), local)| (*d, PathBuf::from(local)))
+                .collect())
+        }
+        _ => Err(TargetError::Ambiguous(
+            matches.into_iter().map(|(path, d)| (path, d.id)).collect(),
+        )),
+    }
+}
+
+async fn pull_document(
+    client: &Client,
+    pull: &Pull<'_>,
+    options: &PullOptions,
+    state: &mut PullState,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let start = Instant::now();
+    let (path, doc) = (&pull.path, pull.doc);
+    // Documents pulled for the groups they're in are only started on once
+    // it's known they're in one.
+    if !pull.grouped {
+        out.observe(&Event::Started { path: path.clone() });
+    }
+    let failed = |out: &mut dyn Output,
+                  e: &(dyn std::error::Error + 'static)| {
+        out.observe(&Event::Failed {
+            path: path.clone(),
+            id: Some(doc.id),
+            category: error_category(e),
+            message: e.to_string(),
+            elapsed: start.elapsed(),
+        })
+    };
+    let skipped = |out: &mut dyn Output, reason: String| {
+        out.note(&reason);
+        out.observe(&Event::Skipped {
+            path: path.clone(),
+            id: Some(doc.id),
+            reason,
+        });
+    };
+    let source = match fetch_document(client, doc).await {
+        Ok(source) => source,
+        Err(e) => {
+            failed(out, &e);
+            return Err(e.into());
+        }
+    };
+    let dirs = pull_dirs(pull, options, &source);
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    if pull.grouped {
+        out.observe(&Event::Started { path: path.clone() });
+    }
+    let files = match pulled_files(&source, &pull.local, options) {
+        Ok(Ok(files)) => files,
+        Ok(Err(reason)) => {
+            skipped(out, reason);
+            return Ok(());
+        }
+        Err(e) => {
+            failed(out, &*e);
+            return Err(e);
+        }
+    };
+    for (name, contents) in files {
+        // The first copy is written, and the rest linked to it.
+        let mut written: Option<PathBuf> = None;
+        for dir in &dirs {
+            let output = dir.join(&name);
+            out.note(&msg!(PULL_WRITING, path = format!("{:?}", output)));
+            // TODO: Handle overwriting
+            if let Err(reason) = state.names.claim(&output.to_string_lossy()) {
+                skipped(out, reason);
+                continue;
+            }
+            let output = options.dir.join(output);
+            let result = match &written {
+                Some(first) => link_or_copy(first, &output).map(Some),
+                None => write_pulled(&output, &contents).map(|_| None),
+            };
+            let linked = match result {
+                Ok(linked) => linked,
+                Err(e) => {
+                    let e: Box<dyn std::error::Error> = e.into();
+                    failed(out, &*e);
+                    return Err(e);
+                }
+            };
+            if options.preserve_times {
+                let modified = &doc.modified_client;
+                let mtime = FileTime::from_unix_time(
+                    modified.timestamp(),
+                    modified.timestamp_subsec_nanos(),
+                );
+                if let Err(e) = filetime::set_file_mtime(&output, mtime) {
+                    if !state.times_failed {
+                        out.warn(&msg!(
+                            PULL_TIMES_NOT_SET,
+                            path = output.display().to_string(),
+                            error = e.to_string(),
+                        ));
+                    }
+                    state.times_failed = true;
+                }
+            }
+            if let (Some(first), Some(linked)) = (&written, linked) {
+                let (from, to) =
+                    (first.display().to_string(), output.display().to_string());
+                out.note(&match linked {
+                    true => msg!(PULL_LINKED, from = from, to = to),
+                    false => msg!(PULL_COPIED, from = from, to = to),
+                });
+                continue;
+            }
+            out.observe(&Event::Pulled {
+                path: path.clone(),
+                id: doc.id,
+                output: output.clone(),
+                modified: doc.modified_client,
+                bytes: contents.len() as u64,
+                sha256: format!("{:x}", Sha256::digest(&contents)),
+                elapsed: start.elapsed(),
+            });
+            written = Some(output);
+        }
+    }
+    Ok(())
+}
+
+fn write_pulled(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, contents)
+}
+
+pub struct PushOptions {
+    /// PDFs and EPUBs to upload, or with `recursive` directories of them.
+    pub files: Vec<PathBuf>,
+    /// The folder to push into, or `None` for the root.
+    pub parent: Option<Uuid>,
+    pub recursive: bool,
+    /// What to do when the folder already has a document of the same name,
+    /// or `None` to ask `Output::on_conflict`.
+    pub on_conflict: Option<OnConflict>,
+    /// How many documents are sent at once, and which are built in
+    /// temporary files.
+    pub memory: push::MemoryBudget,
+    /// How to optimize PDFs before they're uploaded, if they are.
+    pub optimize: Option<OptimizeOptions>,
+    /// What to read back once it's uploaded, to check the cloud has what
+    /// was sent, if anything.
+    pub verify: Option<push::Verify>,
+}
+
+/// The examples `push --help` shows; see also `help conflict-resolution`.
+pub const PUSH_EXAMPLES: &[Example] = &[
+    Example {
+        command:
+            "remarkable-cloud push Dune.pdf 'The Left Hand of Darkness.epub'",
+        description: "Uploads two books to the root",
+    },
+    Example {
+        command: "remarkable-cloud push --to Books/Sci-fi Dune.pdf",
+        description: "Uploads into a folder, which must exist",
+    },
+    Example {
+        command: "remarkable-cloud push --on-conflict rename Dune.pdf",
+        description: "Uploads as \"Dune (2)\" if there's a Dune already",
+    },
+    Example {
+        command: "remarkable-cloud push -r --to Papers ~/papers",
+        description: "Uploads a directory, making folders to match",
+    },
+    Example {
+        command: "remarkable-cloud push --create-missing ~/scans/*.pdf",
+        description: "Uploads to the folders the push mappings in \
+                      settings.json give, making any that don't exist",
+    },
+    Example {
+        command: "curl -sL https://example.com/paper.pdf | \
+                  remarkable-cloud push --stdin --name Paper.pdf",
+        description: "Uploads what's piped in",
+    },
+    Example {
+        command: "remarkable-cloud push --optimize --downsample-dpi 200 \
+                  scan.pdf",
+        description: "Shrinks a scan before uploading it, in builds with \
+                      the optimize feature",
+    },
+    Example {
+        command: "remarkable-cloud push --verify contract.pdf",
+        description: "Uploads, then downloads the document again to check \
+                      the cloud stored what was sent",
+    },
+    Example {
+        command: "remarkable-cloud push --queue Dune.pdf",
+        description: "Queues an upload for `queue run` to make when online",
+    },
+];
+
+/// Uploads the files `options` gives, recording each upload in `journal`
+/// until it's done and in `mutations` once it is. Where each goes is worked
+/// out first, then `options.memory` of them are sent at once; once one
+/// fails no more are started, and the first failure is returned once those
+/// under way are done. With `options.verify`, each is read back as part of
+/// sending it, and one which doesn't match what was sent fails.
+pub async fn push(
+    client: &Client,
+    journal: &push::Journal,
+    mutations: &MutationLog,
+    documents: &Documents,
+    options: &PushOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    if let Some(dir) = options
+        .files
+        .iter()
+        .find(|f| !options.recursive && f.is_dir())
+    {
+        return Err(msg!(PUSH_DIRECTORY, path = format!("{:?}", dir)).into());
+    }
+    let mut planned = vec![];
+    for file in &options.files {
+        if file.is_dir() {
+            plan_dir(
+                client,
+                mutations,
+                documents,
+                options.parent,
+                file,
+                options,
+                &mut planned,
+                out,
+            )
+            .await?;
+            continue;
+        }
+        let name = file.to_string_lossy();
+        if let Some(target) = push_target(
+            documents,
+            options.parent,
+            &name,
+            options.on_conflict,
+            out,
+        )? {
+            planned.push((file.clone(), target));
+        }
+    }
+    push_planned(client, journal, mutations, planned, options, out).await
+}
+
+// Sends each file in `planned` to its target, as `push` describes.
+async fn push_planned(
+    client: &Client,
+    journal: &push::Journal,
+    mutations: &MutationLog,
+    planned: Vec<(PathBuf, push::Target)>,
+    options: &PushOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let memory = options.memory;
+    let optimize = options.optimize;
+    let verify = options.verify;
+    let failed = AtomicBool::new(false);
+    let failed = &failed;
+    let mut pushes = stream::iter(planned)
+        .map(|(path, target)| async move {
+            if failed.load(Ordering::SeqCst) {
+                return None;
+            }
+            let (optimized, pushed) =
+                push_file(client, journal, &path, &target, memory, optimize)
+                    .await;
+            // Read back while this still holds its place among those sent at
+            // once, so reading back doesn't add to them.
+            let verified = match (&pushed, verify) {
+                (Ok(entry), Some(verify)) => {
+                    Some(verify_file(client, &path, entry, verify).await)
+                }
+                _ => None,
+            };
+            if pushed.is_err() || matches!(verified, Some(Err(_))) {
+                failed.store(true, Ordering::SeqCst);
+            }
+            Some((path, target, optimized, pushed, verified))
+        })
+        .buffered(memory.concurrency);
+    let mut first_error = None;
+    let (mut uploaded, mut verified_count) = (0usize, 0usize);
+    while let Some(pushed) = pushes.next().await {
+        match &pushed {
+            Some((_, _, Some(Ok(note)), _, _)) => out.note(note),
+            Some((_, _, Some(Err(warning)), _, _)) => out.warn(warning),
+            _ => (),
+        }
+        match pushed {
+            Some((path, target, _, Ok(entry), verified)) => {
+                let upload = &entry.upload;
+                mutations.record_upload(
+                    upload.id,
+                    &upload.visible_name,
+                    upload.version,
+                    out,
+                );
+                let shown = path.display().to_string();
+                out.note(&describe_push(&shown, &target));
+                uploaded += 1;
+                match verified {
+                    Some(Ok(true)) => verified_count += 1,
+                    Some(Ok(false)) => out.note(&msg!(
+                        PUSH_NOT_VERIFIED,
+                        path = path.display().to_string()
+                    )),
+                    Some(Err(e)) => {
+                        first_error.get_or_insert(e);
+                    }
+                    None => (),
+                }
+            }
+            Some((_, _, _, Err(e), _)) => {
+                first_error.get_or_insert(e);
+            }
+            None => (),
+        }
+    }
+    if verify.is_some() && uploaded > 0 {
+        out.note(&msg!(
+            PUSH_VERIFIED_COUNT,
+            uploaded = uploaded,
+            verified = verified_count,
+        ));
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// Reads back what pushing `path` uploaded, as `push::verify` does, unless
+// it's too large for `verify`, returning whether it was read back.
+async fn verify_file(
+    client: &Client,
+    path: &Path,
+    entry: &push::JournalEntry,
+    verify: push::Verify,
+) -> CliResult<bool> {
+    if !verify.covers(fs::metadata(path)?.len()) {
+        return Ok(false);
+    }
+    push::verify(client, entry, &path.display().to_string()).await?;
+    Ok(true)
+}
+
+// Pushes `path`, optimizing it first if it's a PDF and `optimize` says how.
+// Along with the upload comes a note on what optimizing did, or a warning
+// saying why the file was pushed as it was.
+async fn push_file(
+    client: &Client,
+    journal: &push::Journal,
+    path: &Path,
+    target: &push::Target,
+    memory: push::MemoryBudget,
+    optimize: Option<OptimizeOptions>,
+) -> (
+    Option<std::result::Result<String, String>>,
+    CliResult<push::JournalEntry>,
+) {
+    let options = match optimize {
+        Some(options) if push::file_type(path) == Some("pdf") => options,
+        _ => {
+            let pushed =
+                push::push(client, journal, path, target, memory.spill_above)
+                    .await;
+            return (None, pushed);
+        }
+    };
+    let pdf = match fs::read(path) {
+        Ok(pdf) => pdf,
+        Err(e) => return (None, Err(e.into())),
+    };
+    // Rewriting a large scan takes a while, which uploads under way
+    // shouldn't wait on.
+    let optimized = tokio::task::spawn_blocking(move || {
+        optimize_pdf(&pdf, &options).map_err(|e| e.to_string())
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    let name = path.display();
+    match optimized {
+        Ok(report) if report.after < report.before => {
+            let pushed = push::push_optimized(
+                client,
+                journal,
+                path,
+                &report.pdf,
+                target,
+            )
+            .await;
+            (Some(Ok(report.describe(&name.to_string()))), pushed)
+        }
+        optimized => {
+            let pushed =
+                push::push(client, journal, path, target, memory.spill_above)
+                    .await;
+            let outcome = match optimized {
+                Ok(report) => Ok(report.describe(&name.to_string())),
+                Err(e) => Err(msg!(
+                    PUSH_UNOPTIMIZED,
+                    name = name.to_string(),
+                    error = e.to_string(),
+                )),
+            };
+            (Some(outcome), pushed)
+        }
+    }
+}
+
+/// Finishes or rolls back the uploads `journal` has, as `push --resume`
+/// does, telling `out` which.
+pub async fn push_resume(
+    client: &Client,
+    journal: &push::Journal,
+    mutations: &MutationLog,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    for (entry, outcome) in push::resume(client, journal).await? {
+        let name = &entry.upload.visible_name;
+        match outcome {
+            push::Outcome::Finished => {
+                let upload = &entry.upload;
+                mutations.record_upload(
+                    upload.id,
+                    &upload.visible_name,
+                    upload.version,
+                    out,
+                );
+                out.note(&msg!(PUSH_RESUME_FINISHED, name = name))
+            }
+            push::Outcome::RolledBack => match entry.source {
+                Some(source) => out.note(&msg!(
+                    PUSH_RESUME_CHANGED,
+                    name = name,
+                    source = format!("{:?}", source),
+                )),
+                None => out.note(&msg!(PUSH_RESUME_UNREREADABLE, name = name)),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Points out to `out` uploads `journal` has which were interrupted, if
+/// any.
+pub fn note_interrupted(
+    journal: &push::Journal,
+    out: &mut dyn Output,
+) -> io::Result<()> {
+    let interrupted = journal.entries()?.len();
+    if interrupted > 0 {
+        out.warn(&msg!(PUSH_INTERRUPTED, count = interrupted));
+    }
+    Ok(())
+}
+
+/// Where `push` sends what it's given.
+pub enum PushDestination {
+    /// The folder given with `--to`.
+    Folder(CloudPath),
+    /// Wherever the push settings say each file goes.
+    Mapped(Mappings),
+}
+
+/// What `push` is given, before it's worked out where each file goes.
+pub struct PushPlan {
+    pub files: Vec<PathBuf>,
+    pub destination: PushDestination,
+    /// Whether the document is read from stdin, so goes to the default
+    /// folder of mapped destinations.
+    pub stdin: bool,
+    /// Whether folders mappings name which don't exist are made.
+    pub create_missing: bool,
+    pub recursive: bool,
+    pub limits: LimitCheck,
+}
+
+/// Groups the files `plan` gives by the folder each goes to, once the
+/// limits have been checked against all that's to be added.
+pub async fn plan_push(
+    client: &Client,
+    documents: &ResolvedTree,
+    plan: PushPlan,
+    out: &mut dyn Output,
+) -> CliResult<Vec<(Option<Uuid>, Vec<PathBuf>)>> {
+    let groups = match plan.destination {
+        PushDestination::Folder(path) => {
+            vec![(crate::destination(documents, &path)?.folder(), plan.files)]
+        }
+        PushDestination::Mapped(mappings) => {
+            let planned = if plan.stdin {
+                let folder = mappings.default_folder();
+                vec![(folder.map(str::to_string), plan.files)]
+            } else {
+                let cwd = std::env::current_dir()?;
+                mappings.plan(plan.files, &cwd, out)
+            };
+            mappings::resolve(
+                client,
+                documents,
+                planned,
+                plan.create_missing,
+                &plan.limits,
+                out,
+            )
+            .await?
+        }
+    };
+    let mut violations = vec![];
+    for (parent, files) in &groups {
+        let planned = push::planned(files, plan.recursive)?;
+        violations.extend(
+            plan.limits
+                .limits
+                .check_planned(documents, *parent, &planned),
+        );
+    }
+    plan.limits.enforce(&violations, out)?;
+    Ok(groups)
+}
+
+/// Uploads what's read from `input` as `name` into `parent`, reading it
+/// back afterwards if `verify`.
+#[allow(clippy::too_many_arguments)]
+pub async fn push_stdin(
+    client: &Client,
+    journal: &push::Journal,
+    mutations: &MutationLog,
+    documents: &Documents,
+    parent: Option<Uuid>,
+    name: &str,
+    on_conflict: Option<OnConflict>,
+    verify: bool,
+    input: &mut dyn io::Read,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let target = match push_target(documents, parent, name, on_conflict, out)? {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+    let entry = push::push_input(client, journal, name, input, &target).await?;
+    let upload = &entry.upload;
+    mutations.record_upload(
+        upload.id,
+        &upload.visible_name,
+        upload.version,
+        out,
+    );
+    out.note(&describe_push(name, &target));
+    if verify {
+        push::verify(client, &entry, name).await?;
+        out.note(&msg!(PUSH_VERIFIED_COUNT, uploaded = 1u64, verified = 1u64));
+    }
+    Ok(())
+}
+
+/// Works out where `push` puts `name`. When the name is taken,
+/// `on_conflict` says what to do, or else `out` is asked.
+pub fn push_target(
+    documents: &Documents,
+    parent: Option<Uuid>,
+    name: &str,
+    on_conflict: Option<OnConflict>,
+    out: &mut dyn Output,
+) -> CliResult<Option<push::Target>> {
+    push::target(documents, parent, name, on_conflict, &mut |conflict| {
+        out.on_conflict(name, conflict)
+    })
+}
+
+/// How `push` reports where the file at `path` went.
+pub fn describe_push(path: &str, target: &push::Target) -> String {
+    match target {
+        push::Target::New {
+            name: Some(name), ..
+        } => msg!(PUSHED_AS, path = path, name = format!("{:?}", name)),
+        push::Target::New { name: None, .. } => msg!(PUSHED, path = path),
+        push::Target::Update(doc) => msg!(
+            PUSHED_AS_VERSION,
+            path = path,
+            version = doc.version + 1,
+            name = format!("{:?}", doc.visible_name),
+        ),
+    }
+}
+
+// Works out where what's in `dir` goes under `parent`, adding it to
+// `planned`, and creates folders to match.
+#[allow(clippy::too_many_arguments)]
+async fn plan_dir(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &Documents,
+    parent: Option<Uuid>,
+    dir: &Path,
+    options: &PushOptions,
+    planned: &mut Vec<(PathBuf, push::Target)>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let scan = scan::scan(dir)?;
+    let (folders, created) =
+        push::make_folders(client, documents, parent, &scan.folders).await?;
+    for folder in created {
+        let name = folder.file_name().unwrap_or_default().to_string_lossy();
+        mutations.record_upload(folders[&folder], &name, 1, out);
+        let path = dir.join(&folder).display().to_string();
+        out.note(&msg!(FOLDER_CREATED, path = path));
+    }
+    for file in &scan.files {
+        let parent = file
+            .parent()
+            .and_then(|p| folders.get(p).copied())
+            .or(parent);
+        let path = dir.join(file);
+        let name = path.to_string_lossy();
+        if let Some(target) =
+            push_target(documents, parent, &name, options.on_conflict, out)?
+        {
+            planned.push((path, target));
+        }
+    }
+    if !scan.unsupported.is_empty() {
+        out.note(&msg!(PUSH_UNSUPPORTED));
+        for file in &scan.unsupported {
+            out.note(&format!("  {}", dir.join(file).display()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary::TransferReport;
+    use crate::testutil::listing;
+
+    #[test]
+    fn ambiguous_pull() {
+        let docs = ResolvedTree::new(listing(&[
+            (1, "Work", None, "CollectionType"),
+            (2, "Archive", None, "CollectionType"),
+            (3, "Quick sheets", Some(1), "DocumentType"),
+            (4, "Quick sheets", Some(2), "DocumentType"),
+            (5, "Quick sheets", None, "DocumentType"),
+            (6, "Report", Some(1), "DocumentType"),
+        ]));
+        let ids = |targets: Vec<(&Document, PathBuf)>| -> Vec<(u128, String)> {
+            targets
+                .into_iter()
+                .map(|(d, p)| (d.id.as_u128(), p.to_string_lossy().into()))
+                .collect()
+        };
+
+        // A bare name unique across folders is found wherever it is.
+        let found = pull_targets(&docs, Path::new("Report"), false);
+        assert_eq!(ids(found.ok().unwrap()), vec![(6, "Report".into())]);
+
+        match pull_targets(&docs, Path::new("Quick sheets"), false) {
+            Err(TargetError::Ambiguous(candidates)) => {
+                let paths: Vec<&str> =
+                    candidates.iter().map(|(p, _)| p.as_str()).collect();
+                assert_eq!(
+                    paths,
+                    vec![
+                        "Archive/Quick sheets",
+                        "Quick sheets",
+                        "Work/Quick sheets"
+                    ]
+                );
+            }
+            _ => panic!("expected an ambiguity"),
+        }
+
+        let found = pull_targets(&docs, Path::new("Quick sheets"), true);
+        assert_eq!(
+            ids(found.ok().unwrap()),
+            vec![
+                (4, "Quick sheets (Archive)".into()),
+                (5, "Quick sheets (root)".into()),
+                (3, "Quick sheets (Work)".into()),
+            ]
+        );
+
+        // A fuller path picks out one document.
+        let found = pull_targets(&docs, Path::new("Work/Quick sheets"), false);
+        assert_eq!(
+            ids(found.ok().unwrap()),
+            vec![(3, "Work/Quick sheets".into())]
+        );
+        assert!(matches!(
+            pull_targets(&docs, Path::new("Missing"), true),
+            Err(TargetError::NotFound(None))
+        ));
+        match pull_targets(&docs, Path::new("Work/Missing"), false) {
+            Err(TargetError::NotFound(Some(e))) => assert_eq!(
+                e.to_string(),
+                "Couldn't find \"/Work/Missing\": nothing named \"Missing\" \
+                 is in Work"
+            ),
+            _ => panic!("expected a reason"),
+        }
+        let by_id = format!("uuid:{}", Uuid::from_u128(3));
+        assert_eq!(
+            ids(pull_targets(&docs, Path::new(&by_id), false).ok().unwrap()),
+            vec![(3, "Quick sheets".into())]
+        );
+        assert!(matches!(
+            pull_targets(&docs, Path::new("Work"), false),
+            Err(TargetError::Folder(id)) if id.as_u128() == 1
+        ));
+    }
+
+    #[test]
+    fn folder_layout() {
+        let docs = ResolvedTree::new(listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+            (3, "Sci-fi", Some(1), "CollectionType"),
+            (4, "Hyperion", Some(3), "DocumentType"),
+            (5, "Empty", Some(3), "CollectionType"),
+            (6, "Notes", None, "DocumentType"),
+        ]));
+        let pulls: Vec<(String, PathBuf)> =
+            folder_pulls(&docs, Uuid::from_u128(1))
+                .into_iter()
+                .map(|p| (p.path.to_string_lossy().into_owned(), p.subdir))
+                .collect();
+        assert_eq!(
+            pulls,
+            vec![
+                ("Books/Dune".to_string(), PathBuf::from("Books")),
+                (
+                    "Books/Sci-fi/Hyperion".to_string(),
+                    PathBuf::from("Books/Sci-fi")
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn interrupted_pull_counts_as_failed() {
+        use remarkable_cloud_api::testing::FakeCloud;
+        use std::time::Duration;
+
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        cloud.delay_next(&format!("/blob/{}", dune), Duration::from_secs(5));
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = client.get_documents().await.unwrap();
+
+        let mut out = Capture::new(TransferReport::new());
+        let options = PullOptions::default();
+        let mut state = PullState::default();
+        let target = Pull {
+            path: PathBuf::from("Dune"),
+            doc: docs.get(&dune).unwrap(),
+            local: PathBuf::from("Dune"),
+            subdir: PathBuf::new(),
+            grouped: false,
+        };
+        let pull =
+            pull_document(&client, &target, &options, &mut state, &mut out);
+        let timeout = Duration::from_millis(100);
+        assert!(tokio::time::timeout(timeout, pull).await.is_err());
+
+        let summary = out.observer.summary();
+        assert_eq!(summary.transferred, 0);
+        assert_eq!(
+            summary.failed,
+            vec![(PathBuf::from("Dune"), "interrupted".to_string())]
+        );
+    }
+
+    #[test]
+    fn linked_or_copied() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("a/Dune.pdf");
+        write_pulled(&from, b"dune").unwrap();
+
+        let linked = dir.path().join("b/Dune.pdf");
+        assert!(link_or_copy(&from, &linked).unwrap());
+        assert_eq!(fs::read(&linked).unwrap(), b"dune");
+        // Linked again over itself, as when pulled again.
+        assert!(link_or_copy(&from, &linked).unwrap());
+
+        // A filesystem without hard links gets a copy.
+        let copied = dir.path().join("c/Dune.pdf");
+        let unsupported = |_: &Path, _: &Path| {
+            Err(io::Error::other("hard links aren't supported"))
+        };
+        assert!(!link_or_copy_with(&from, &copied, unsupported).unwrap());
+        fs::write(&from, b"changed").unwrap();
+        assert_eq!(fs::read(&copied).unwrap(), b"dune");
+        assert_eq!(fs::read(&linked).unwrap(), b"changed");
+    }
+}
@@ -0,0 +1,137 @@
+//! What's known of documents' contents without downloading them, from the
+//! content cache the client adds to whenever it downloads a blob: used by
+//! `--content-type` and the `content-type` and `pages` fields, and filled
+//! by `cache warm`.
+
+use std::io;
+use std::sync::Mutex;
+
+use futures_util::StreamExt;
+use remarkable_cloud_api::{CachedContent, Client, ContentCache, Document};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::msg;
+use crate::progress::Progress;
+use crate::resolved::ResolvedTree;
+use crate::DETAILS_CONCURRENCY;
+
+/// The file in the cache directory the content cache is kept in.
+pub const CONTENT_CACHE_FILE: &str = "content.json";
+
+/// What `--content-type` takes.
+pub const CONTENT_TYPE_VALUES: &[&str] = &["pdf", "epub", "notebook"];
+
+// The cache looked in, once `enable` has been called.
+static CACHE: Mutex<Option<ContentCache>> = Mutex::new(None);
+
+/// Looks up documents' contents in `cache` from now on.
+pub fn enable(cache: ContentCache) {
+    *CACHE.lock().unwrap() = Some(cache);
+}
+
+/// What's cached of the contents of `doc` at its version, if anything.
+pub fn get(doc: &Document) -> Option<CachedContent> {
+    CACHE.lock().unwrap().as_ref()?.get(doc)
+}
+
+/// Writes out what's been added to the cache this run.
+pub fn flush() -> io::Result<()> {
+    match &*CACHE.lock().unwrap() {
+        Some(cache) => cache.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Whether `doc` is a document of `content_type`, or `None` if its contents
+/// aren't cached. Folders are of no type.
+pub fn is_type(doc: &Document, content_type: &str) -> Option<bool> {
+    if !doc.is_document() {
+        return Some(false);
+    }
+    Some(get(doc)?.file_type == content_type)
+}
+
+/// The documents among `found` of `content_type`, and how many couldn't be
+/// told as their contents aren't cached.
+pub fn of_type<'a>(
+    found: Vec<(String, &'a Document)>,
+    content_type: &str,
+) -> (Vec<(String, &'a Document)>, usize) {
+    let mut unknown = 0;
+    let found = found
+        .into_iter()
+        .filter(|(_, d)| match is_type(d, content_type) {
+            Some(is) => is,
+            None => {
+                unknown += 1;
+                false
+            }
+        })
+        .collect();
+    (found, unknown)
+}
+
+/// What `--content-type` says about the documents it couldn't check.
+pub fn unknown_note(unknown: usize) -> String {
+    msg!(CONTENT_TYPE_UNCHECKED, count = unknown)
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WarmReport {
+    pub downloaded: usize,
+    pub already_cached: usize,
+    pub unreadable: usize,
+    /// Entries dropped as their documents are gone or have changed.
+    pub pruned: usize,
+}
+
+/// Downloads every document below `folder`, or in the account, whose
+/// contents `cache` doesn't have at its version, so `client` caches them,
+/// and drops what's cached of documents no longer in `documents`. Those
+/// which can't be downloaded are pointed out on `out`.
+pub async fn warm(
+    client: &Client,
+    cache: &ContentCache,
+    documents: &ResolvedTree,
+    folder: Option<Uuid>,
+    out: &mut dyn Output,
+) -> io::Result<WarmReport> {
+    let mut report = WarmReport {
+        pruned: cache.prune(documents),
+        ..Default::default()
+    };
+    let start = match folder {
+        Some(id) => remarkable_cloud_api::Parent::Folder(id),
+        None => remarkable_cloud_api::Parent::Root,
+    };
+    let mut wanted = vec![];
+    for (_, doc) in documents.descendants(start) {
+        if !doc.is_document() {
+            continue;
+        }
+        match cache.get(doc) {
+            Some(_) => report.already_cached += 1,
+            None => wanted.push(doc),
+        }
+    }
+    let ids: Vec<Uuid> = wanted.iter().map(|d| d.id).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_CACHED), ids.len());
+    let mut details = client.document_details_bulk(&ids, DETAILS_CONCURRENCY);
+    for doc in wanted {
+        let result = details.next().await.expect("a result for each id");
+        progress.tick();
+        match result {
+            Ok(_) => report.downloaded += 1,
+            Err(e) => {
+                let path = documents.path_of(&doc.id).unwrap_or_default();
+                let warning =
+                    msg!(DOWNLOAD_FAILED, path = path, error = e.to_string());
+                progress.warn(out, &warning);
+                report.unreadable += 1;
+            }
+        }
+    }
+    cache.flush()?;
+    Ok(report)
+}
@@ -0,0 +1,754 @@
+//! Digests for `export digest`: every page bookmarked on the tablet, by
+//! putting a tag on it, gathered from all documents into one PDF.
+//!
+//! Each page is drawn as `ink-pdf` exports it, over the page of the PDF the
+//! document was made from if it was one, as the tablet shows it. Notebooks'
+//! and EPUBs' pages are drawn without their templates or text, as
+//! exporters can't yet draw those. Pages of contents come first, naming
+//! each page's document and number, and the outline has an entry for
+//! each document with one for each of its pages below it.
+//!
+//! Putting the pages together needs the `digest` feature.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{bookmarked_pages, write_atomically, Client};
+
+use crate::commands::Output;
+use crate::exporters::{self, ExportFile, Source};
+use crate::resolved::ResolvedTree;
+use crate::CliResult;
+
+/// Whether this build can make digests.
+pub const DIGEST_BUILT: bool = cfg!(feature = "digest");
+
+/// One document's bookmarked pages, as they go in a digest.
+#[derive(Clone, Debug)]
+pub struct Part {
+    /// The document's path.
+    pub title: String,
+    /// The pages, counting from 0, in order, each with its tags' names.
+    pub pages: Vec<(usize, Vec<String>)>,
+    /// What's drawn on each of the document's pages, as `ink-pdf` exports
+    /// it.
+    pub ink: Vec<u8>,
+    /// The PDF the document was made from, to draw over, if it was one.
+    pub original: Option<Vec<u8>>,
+}
+
+impl Part {
+    /// The part of a digest for `source`, the archive of the document at
+    /// `title`, with the pages in it bookmarked since `since`, or `None` if
+    /// none were. Pages without a time they were tagged at are counted in.
+    pub fn from_source(
+        source: &Source,
+        title: String,
+        since: Option<DateTime<Utc>>,
+    ) -> CliResult<Option<Part>> {
+        let pages: Vec<(usize, Vec<String>)> =
+            bookmarked_pages(source.id(), &source.archive)?
+                .into_iter()
+                .filter(|b| match (since, b.tagged) {
+                    (Some(since), Some(tagged)) => tagged >= since,
+                    _ => true,
+                })
+                .map(|b| (b.index, b.tags))
+                .collect();
+        if pages.is_empty() {
+            return Ok(None);
+        }
+        let ink = export_one(source, "ink-pdf")?
+            .ok_or_else(|| crate::msg!(DIGEST_NO_INK))?;
+        let is_pdf = source
+            .details
+            .content
+            .as_ref()
+            .is_some_and(|c| c.file_type == "pdf");
+        let original = if is_pdf {
+            export_one(source, "pdf")?
+        } else {
+            None
+        };
+        Ok(Some(Part {
+            title,
+            pages,
+            ink,
+            original,
+        }))
+    }
+}
+
+// What the exporter `name` makes of `source`, if it makes one file of it.
+fn export_one(source: &Source, name: &str) -> CliResult<Option<Vec<u8>>> {
+    let exporter = exporters::find(name).unwrap();
+    if !exporter.supports(source) {
+        return Ok(None);
+    }
+    let mut files: Vec<ExportFile> = vec![];
+    exporter.export(source, &mut files)?;
+    Ok(match files.len() {
+        1 => files.pop().map(|f| f.data),
+        _ => None,
+    })
+}
+
+/// The parts of a digest of the pages in `documents` bookmarked since
+/// `since`, in the order of the documents' paths. Documents the content
+/// cache knows have no bookmarked pages aren't downloaded; any which can't
+/// be are warned about and left out.
+pub async fn collect(
+    client: &Client,
+    documents: &ResolvedTree,
+    since: Option<DateTime<Utc>>,
+    out: &mut dyn Output,
+) -> CliResult<Vec<Part>> {
+    let mut candidates: Vec<(String, uuid::Uuid)> = documents
+        .bookmark_candidates(since)
+        .into_iter()
+        .filter(|d| {
+            crate::content::get(d).and_then(|c| c.bookmarked_pages) != Some(0)
+        })
+        .filter_map(|d| Some((documents.path_of(&d.id)?, d.id)))
+        .collect();
+    candidates.sort();
+    let mut parts = vec![];
+    for (path, id) in candidates {
+        let part = async {
+            let current = client.get_document_by_id(&id).await?;
+            let archive = client.download_blob(&current).await?;
+            Part::from_source(
+                &Source::new(current, archive),
+                path.clone(),
+                since,
+            )
+        };
+        match part.await {
+            Ok(Some(part)) => parts.push(part),
+            Ok(None) => {}
+            Err(e) => out.warn(&crate::msg!(
+                DIGEST_LEFT_OUT,
+                path = &path,
+                error = e.to_string(),
+            )),
+        }
+    }
+    Ok(parts)
+}
+
+/// Writes a digest of the pages in `documents` bookmarked in the last
+/// `since`, a duration such as `7days`, or ever, to `output`, as `export
+/// digest` does. Nothing is written if there are none.
+pub async fn digest(
+    client: &Client,
+    documents: &ResolvedTree,
+    since: Option<&str>,
+    output: &Path,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let after = since.map(|s| {
+        let age = humantime::parse_duration(s).unwrap();
+        chrono::Duration::from_std(age)
+            .ok()
+            .and_then(|age| Utc::now().checked_sub_signed(age))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    });
+    let parts = collect(client, documents, after, out).await?;
+    if parts.is_empty() {
+        match since {
+            Some(s) => out.note(&crate::msg!(DIGEST_NONE_SINCE, since = s)),
+            None => out.note(&crate::msg!(DIGEST_NONE)),
+        }
+        return Ok(());
+    }
+    let pages: usize = parts.iter().map(|p| p.pages.len()).sum();
+    write_atomically(output, &assemble(&parts)?)?;
+    out.note(&crate::msg!(
+        DIGEST_WROTE,
+        pages = pages,
+        documents = parts.len(),
+        path = output.display().to_string(),
+    ));
+    Ok(())
+}
+
+// How a page is named in the outline, below its document.
+#[cfg(feature = "digest")]
+fn page_title(index: usize, tags: &[String]) -> String {
+    format!("Page {} ({})", index + 1, tags.join(", "))
+}
+
+#[cfg(not(feature = "digest"))]
+pub fn assemble(_: &[Part]) -> CliResult<Vec<u8>> {
+    Err(crate::msg!(DIGEST_NOT_BUILT).into())
+}
+
+/// Puts the bookmarked pages of `parts` together into one PDF, with pages
+/// of contents first, as the module describes.
+#[cfg(feature = "digest")]
+pub fn assemble(parts: &[Part]) -> CliResult<Vec<u8>> {
+    use lopdf::{dictionary, Document, Object};
+
+    let mut out = Document::with_version("1.5");
+    let tree = out.new_object_id();
+    let mut outline = vec![];
+    for part in parts {
+        let ink = import(&mut out, &part.ink)?;
+        let original = match &part.original {
+            Some(pdf) => Some(import(&mut out, pdf)?),
+            None => None,
+        };
+        let mut entries = vec![];
+        for (index, tags) in &part.pages {
+            let ink_page = *ink.get(*index).ok_or_else(|| {
+                crate::msg!(
+                    DIGEST_NO_PAGE,
+                    title = &part.title,
+                    page = index + 1
+                )
+            })?;
+            let page = match original.as_ref().and_then(|o| o.get(*index)) {
+                Some(&page) => {
+                    draw_over(&mut out, page, ink_page)?;
+                    page
+                }
+                None => ink_page,
+            };
+            out.get_dictionary_mut(page)?.set("Parent", tree);
+            entries.push((*index, tags, page));
+        }
+        outline.push((part.title.clone(), entries));
+    }
+
+    let lines: Vec<(String, lopdf::ObjectId)> = outline
+        .iter()
+        .flat_map(|(title, entries)| {
+            entries.iter().map(move |(index, tags, id)| {
+                let tags = tags.join(", ");
+                (format!("{}, page {} ({})", title, index + 1, tags), *id)
+            })
+        })
+        .collect();
+    let mut kids = contents_pages(&mut out, tree, &lines);
+    kids.extend(lines.iter().map(|(_, id)| *id));
+    out.objects.insert(
+        tree,
+        dictionary! {
+            "Type" => "Pages",
+            "Count" => kids.len() as i64,
+            "Kids" => kids.into_iter().map(Object::from).collect::<Vec<_>>(),
+        }
+        .into(),
+    );
+    let outlines = add_outline(&mut out, &outline);
+    let catalog = out.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => tree,
+        "Outlines" => outlines,
+        "PageMode" => "UseOutlines",
+    });
+    out.trailer.set("Root", catalog);
+    out.prune_objects();
+    out.renumber_objects();
+    out.compress();
+    let mut pdf = vec![];
+    out.save_to(&mut pdf)
+        .map_err(|e| crate::msg!(DIGEST_UNWRITABLE, error = e.to_string()))?;
+    Ok(pdf)
+}
+
+// Moves the objects of `pdf` into `out`, returning its pages in order. What
+// each page inherits from the page tree it was in is set on it, as it's
+// moved out of it.
+#[cfg(feature = "digest")]
+fn import(
+    out: &mut lopdf::Document,
+    pdf: &[u8],
+) -> CliResult<Vec<lopdf::ObjectId>> {
+    use lopdf::Document;
+
+    let mut doc = Document::load_mem(pdf).map_err(|e| {
+        crate::msg!(DIGEST_PAGE_UNREADABLE, error = e.to_string())
+    })?;
+    if doc.is_encrypted() {
+        return Err(crate::msg!(DIGEST_ENCRYPTED).into());
+    }
+    doc.renumber_objects_with(out.max_id + 1);
+    let pages: Vec<lopdf::ObjectId> = doc.page_iter().collect();
+    for page in &pages {
+        for key in [&b"Resources"[..], b"MediaBox", b"CropBox", b"Rotate"] {
+            if let Some(value) = inherited(&doc, *page, key) {
+                doc.get_dictionary_mut(*page)?.set(key, value);
+            }
+        }
+    }
+    out.max_id = out.max_id.max(doc.max_id);
+    out.objects.extend(doc.objects);
+    Ok(pages)
+}
+
+// What `page` inherits of `key` from the page tree above it, if it doesn't
+// have it itself.
+#[cfg(feature = "digest")]
+fn inherited(
+    doc: &lopdf::Document,
+    page: lopdf::ObjectId,
+    key: &[u8],
+) -> Option<lopdf::Object> {
+    let mut node = doc.get_dictionary(page).ok()?;
+    if node.has(key) {
+        return None;
+    }
+    // Deep enough for any real page tree, and no further if it loops.
+    for _ in 0..32 {
+        let parent = node.get(b"Parent").ok()?.as_reference().ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+        if let Ok(value) = node.get(key) {
+            return Some(value.clone());
+        }
+    }
+    None
+}
+
+// Draws what's on `ink_page` over `page`, as a form of its own, so that
+// nothing the page's drawing leaves set changes it.
+#[cfg(feature = "digest")]
+fn draw_over(
+    out: &mut lopdf::Document,
+    page: lopdf::ObjectId,
+    ink_page: lopdf::ObjectId,
+) -> CliResult<()> {
+    use lopdf::{dictionary, Dictionary, Object, Stream};
+
+    let ink = out.get_dictionary(ink_page)?.clone();
+    let drawn = out.get_page_content(ink_page)?;
+    let form = out.add_object(Stream::new(
+        dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Form",
+            "BBox" => ink.get(b"MediaBox")?.clone(),
+            "Resources" => ink
+                .get(b"Resources")
+                .cloned()
+                .unwrap_or_else(|_| Dictionary::new().into()),
+        },
+        drawn,
+    ));
+
+    let direct = |out: &lopdf::Document, object: Option<&Object>| match object
+        .map(|o| out.dereference(o))
+    {
+        Some(Ok((_, Object::Dictionary(d)))) => d.clone(),
+        _ => Dictionary::new(),
+    };
+    let dict = out.get_dictionary(page)?;
+    let mut resources = direct(out, dict.get(b"Resources").ok());
+    let mut xobjects = direct(out, resources.get(b"XObject").ok());
+    let name = (0..)
+        .map(|n| format!("Ink{}", n))
+        .find(|n| !xobjects.has(n.as_bytes()))
+        .unwrap();
+    xobjects.set(name.as_bytes(), form);
+    resources.set("XObject", xobjects);
+    let mut contents = match dict.get(b"Contents") {
+        Ok(Object::Array(streams)) => streams.clone(),
+        Ok(stream) => vec![stream.clone()],
+        Err(_) => vec![],
+    };
+    let save = out.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+    let draw = format!("\nQ q /{} Do Q\n", name).into_bytes();
+    let draw = out.add_object(Stream::new(Dictionary::new(), draw));
+    contents.insert(0, save.into());
+    contents.push(draw.into());
+
+    let dict = out.get_dictionary_mut(page)?;
+    dict.set("Resources", resources);
+    dict.set("Contents", contents);
+    Ok(())
+}
+
+// The size of pages of contents, A4, and where their lines go.
+#[cfg(feature = "digest")]
+const CONTENTS_SIZE: (f32, f32) = (595.0, 842.0);
+#[cfg(feature = "digest")]
+const MARGIN: f32 = 56.0;
+#[cfg(feature = "digest")]
+const LINE_HEIGHT: f32 = 16.0;
+#[cfg(feature = "digest")]
+const LINES_PER_PAGE: usize = 42;
+// Past this many characters a line would run off the page.
+#[cfg(feature = "digest")]
+const LINE_CHARS: usize = 78;
+
+// Adds pages of contents listing `lines`, each the name of a page after
+// them, in order, and linking to it, returning the pages added.
+#[cfg(feature = "digest")]
+fn contents_pages(
+    out: &mut lopdf::Document,
+    tree: lopdf::ObjectId,
+    lines: &[(String, lopdf::ObjectId)],
+) -> Vec<lopdf::ObjectId> {
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Object, Stream, StringFormat};
+
+    let text = |x: f32, y: f32, size: i64, text: &str| {
+        vec![
+            Operation::new("BT", vec![]),
+            Operation::new("Tf", vec!["F1".into(), size.into()]),
+            Operation::new("Td", vec![x.into(), y.into()]),
+            Operation::new(
+                "Tj",
+                vec![Object::String(win_ansi(text), StringFormat::Literal)],
+            ),
+            Operation::new("ET", vec![]),
+        ]
+    };
+    let font = out.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let (width, height) = CONTENTS_SIZE;
+    let count = lines.len().div_ceil(LINES_PER_PAGE);
+    let mut pages = vec![];
+    for (n, chunk) in lines.chunks(LINES_PER_PAGE).enumerate() {
+        let mut operations =
+            text(MARGIN, height - MARGIN - 18.0, 18, "Contents");
+        let mut links = vec![];
+        for (i, (line, page)) in chunk.iter().enumerate() {
+            let y = height - MARGIN - 18.0 - LINE_HEIGHT * (i as f32 + 2.0);
+            let number = count + n * LINES_PER_PAGE + i + 1;
+            operations.extend(text(MARGIN, y, 11, &number.to_string()));
+            operations.extend(text(MARGIN + 36.0, y, 11, &truncate(line)));
+            let link = out.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "Rect" => vec![
+                    MARGIN.into(),
+                    (y - 4.0).into(),
+                    (width - MARGIN).into(),
+                    (y + 12.0).into(),
+                ],
+                "Border" => vec![0.into(), 0.into(), 0.into()],
+                "Dest" => vec![(*page).into(), "Fit".into()],
+            });
+            links.push(Object::from(link));
+        }
+        let content = Content { operations }.encode().unwrap();
+        let content = out.add_object(Stream::new(dictionary! {}, content));
+        pages.push(out.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => tree,
+            "MediaBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+            "Resources" => dictionary! {
+                "Font" => dictionary! { "F1" => font },
+            },
+            "Contents" => content,
+            "Annots" => links,
+        }));
+    }
+    pages
+}
+
+// `line`, cut short if it wouldn't fit on a page of contents.
+#[cfg(feature = "digest")]
+fn truncate(line: &str) -> String {
+    if line.chars().count() <= LINE_CHARS {
+        return line.to_string();
+    }
+    let cut: String = line.chars().take(LINE_CHARS - 3).collect();
+    cut + "..."
+}
+
+// `text` in WinAnsiEncoding, as the contents' font is, which agrees with
+// Latin-1 on the characters it shares with it. Others are drawn as `?`.
+#[cfg(feature = "digest")]
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+// Adds the outline of `entries`, an item for each document with its pages
+// below it, returning its root.
+#[cfg(feature = "digest")]
+#[allow(clippy::type_complexity)]
+fn add_outline(
+    out: &mut lopdf::Document,
+    entries: &[(String, Vec<(usize, &Vec<String>, lopdf::ObjectId)>)],
+) -> lopdf::ObjectId {
+    use lopdf::{dictionary, text_string, Dictionary, Object, ObjectId};
+
+    // Links `item`, the `i`th of `ids`, to those either side.
+    fn siblings(item: &mut Dictionary, ids: &[ObjectId], i: usize) {
+        if i > 0 {
+            item.set("Prev", ids[i - 1]);
+        }
+        if let Some(next) = ids.get(i + 1) {
+            item.set("Next", *next);
+        }
+    }
+    let fit =
+        |page: ObjectId| -> Object { vec![page.into(), "Fit".into()].into() };
+
+    let root = out.new_object_id();
+    let items: Vec<ObjectId> =
+        entries.iter().map(|_| out.new_object_id()).collect();
+    let mut visible = items.len();
+    for (i, (title, pages)) in entries.iter().enumerate() {
+        let children: Vec<ObjectId> =
+            pages.iter().map(|_| out.new_object_id()).collect();
+        for (j, (index, tags, page)) in pages.iter().enumerate() {
+            let mut child = dictionary! {
+                "Title" => text_string(&page_title(*index, tags)),
+                "Parent" => items[i],
+                "Dest" => fit(*page),
+            };
+            siblings(&mut child, &children, j);
+            out.objects.insert(children[j], child.into());
+        }
+        visible += children.len();
+        let mut item = dictionary! {
+            "Title" => text_string(title),
+            "Parent" => root,
+            "Count" => children.len() as i64,
+        };
+        if let (Some(first), Some(last)) = (children.first(), children.last()) {
+            item.set("First", *first);
+            item.set("Last", *last);
+            item.set("Dest", fit(pages[0].2));
+        }
+        siblings(&mut item, &items, i);
+        out.objects.insert(items[i], item.into());
+    }
+    let mut outline = dictionary! {
+        "Type" => "Outlines",
+        "Count" => visible as i64,
+    };
+    if let (Some(first), Some(last)) = (items.first(), items.last()) {
+        outline.set("First", *first);
+        outline.set("Last", *last);
+    }
+    out.objects.insert(root, outline.into());
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "digest"))]
+    #[test]
+    fn not_built() {
+        assert!(assemble(&[]).is_err());
+    }
+
+    #[cfg(feature = "digest")]
+    mod assembling {
+        use lopdf::{decode_text_string, Document, Object, ObjectId};
+        use remarkable_data_formats::ink::{self, PageTransform};
+        use remarkable_data_formats::lines::Page;
+        use remarkable_data_formats::pdf::PageBox;
+
+        use super::*;
+
+        // A one-page A4 paper with some text on it, its size and fonts
+        // given by the page tree rather than the page.
+        fn paper() -> Vec<u8> {
+            use lopdf::content::{Content, Operation};
+            use lopdf::{dictionary, Stream};
+
+            let mut doc = Document::with_version("1.5");
+            let tree = doc.new_object_id();
+            let font = doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Courier",
+            });
+            let resources = doc.add_object(dictionary! {
+                "Font" => dictionary! { "F1" => font },
+            });
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                    Operation::new("Td", vec![72.into(), 700.into()]),
+                    Operation::new(
+                        "Tj",
+                        vec![Object::string_literal("Abstract")],
+                    ),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content = doc.add_object(Stream::new(
+                dictionary! {},
+                content.encode().unwrap(),
+            ));
+            let page = doc.add_object(dictionary! {
+                "Type" => "Page",
+                "Parent" => tree,
+                "Contents" => content,
+            });
+            doc.objects.insert(
+                tree,
+                dictionary! {
+                    "Type" => "Pages",
+                    "Kids" => vec![page.into()],
+                    "Count" => 1,
+                    "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+                    "Resources" => resources,
+                }
+                .into(),
+            );
+            let catalog = doc.add_object(dictionary! {
+                "Type" => "Catalog",
+                "Pages" => tree,
+            });
+            doc.trailer.set("Root", catalog);
+            let mut pdf = vec![];
+            doc.save_to(&mut pdf).unwrap();
+            pdf
+        }
+
+        // What's drawn on `count` pages with nothing drawn on them, each
+        // the size of `page`.
+        fn ink(count: usize, transform: PageTransform) -> Vec<u8> {
+            let blank = Page {
+                version: 5,
+                layers: vec![],
+                text: vec![],
+                warnings: vec![],
+            };
+            let pages: Vec<_> =
+                (0..count).map(|_| (&blank, transform)).collect();
+            ink::pdf(&pages)
+        }
+
+        fn parts() -> Vec<Part> {
+            let a4 = PageBox::from_corners([0.0, 0.0, 595.0, 842.0]);
+            vec![
+                Part {
+                    title: "Books/Paper".into(),
+                    pages: vec![(0, vec!["Quote".into()])],
+                    ink: ink(1, PageTransform::new(a4)),
+                    original: Some(paper()),
+                },
+                Part {
+                    title: "Journal".into(),
+                    pages: vec![
+                        (1, vec!["Todo".into(), "Later".into()]),
+                        (2, vec!["Idée".into()]),
+                    ],
+                    ink: ink(3, PageTransform::tablet()),
+                    original: None,
+                },
+            ]
+        }
+
+        fn title(doc: &Document, item: ObjectId) -> String {
+            let item = doc.get_dictionary(item).unwrap();
+            decode_text_string(item.get(b"Title").unwrap()).unwrap()
+        }
+
+        fn linked(doc: &Document, item: ObjectId, key: &[u8]) -> ObjectId {
+            let item = doc.get_dictionary(item).unwrap();
+            item.get(key).unwrap().as_reference().unwrap()
+        }
+
+        #[test]
+        fn concatenates() {
+            let pdf = assemble(&parts()).unwrap();
+            let doc = Document::load_mem(&pdf).unwrap();
+            let pages: Vec<ObjectId> = doc.page_iter().collect();
+            assert_eq!(pages.len(), 4);
+
+            let contents = doc.extract_text(&[1]).unwrap();
+            assert!(contents.contains("Contents"), "{}", contents);
+            assert!(contents.contains("Books/Paper, page 1 (Quote)"));
+            assert!(contents.contains("Journal, page 2 (Todo, Later)"));
+            let links = doc.get_page_annotations(pages[0]).unwrap();
+            let targets: Vec<ObjectId> = links
+                .iter()
+                .map(|l| {
+                    let dest = l.get(b"Dest").unwrap().as_array().unwrap();
+                    dest[0].as_reference().unwrap()
+                })
+                .collect();
+            assert_eq!(targets, pages[1..]);
+
+            // The paper's page, with the ink drawn over it.
+            let paper = doc.get_dictionary(pages[1]).unwrap();
+            let media_box = paper.get(b"MediaBox").unwrap().as_array().unwrap();
+            assert_eq!(media_box[2].as_float().unwrap(), 595.0);
+            let content = doc.get_page_content(pages[1]).unwrap();
+            assert!(String::from_utf8_lossy(&content).contains("/Ink0 Do"));
+            let (resources, _) = doc.get_page_resources(pages[1]).unwrap();
+            let xobjects = doc
+                .get_dict_in_dict(resources.unwrap(), b"XObject")
+                .unwrap();
+            assert!(xobjects.has(b"Ink0"));
+            let text = doc.extract_text(&[2]).unwrap();
+            assert!(text.contains("Abstract"), "{}", text);
+
+            // The notebook's, the size of the tablet's screen.
+            let journal = doc.get_dictionary(pages[2]).unwrap();
+            let media_box =
+                journal.get(b"MediaBox").unwrap().as_array().unwrap();
+            assert!(
+                media_box[3].as_float().unwrap()
+                    > media_box[2].as_float().unwrap()
+            );
+
+            let catalog = doc.catalog().unwrap();
+            let outline =
+                catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+            let first = linked(&doc, outline, b"First");
+            assert_eq!(title(&doc, first), "Books/Paper");
+            assert_eq!(
+                title(&doc, linked(&doc, first, b"First")),
+                "Page 1 (Quote)"
+            );
+            let journal = linked(&doc, first, b"Next");
+            assert_eq!(linked(&doc, outline, b"Last"), journal);
+            let second = linked(&doc, linked(&doc, journal, b"First"), b"Next");
+            assert_eq!(title(&doc, second), "Page 3 (Idée)");
+            let dest =
+                doc.get_dictionary(second).unwrap().get(b"Dest").unwrap();
+            let dest = dest.as_array().unwrap();
+            assert_eq!(dest[0], Object::Reference(pages[3]));
+        }
+
+        #[test]
+        fn contents_run_over() {
+            let mut parts = parts();
+            parts[1].pages =
+                (0..50).map(|i| (i, vec!["Todo".into()])).collect();
+            parts[1].ink = ink(50, PageTransform::tablet());
+            let doc = Document::load_mem(&assemble(&parts).unwrap()).unwrap();
+            assert_eq!(doc.get_pages().len(), 2 + 51);
+            let second = doc.extract_text(&[2]).unwrap();
+            assert!(second.contains("Journal, page 50 (Todo)"), "{}", second);
+            assert!(second.contains("53"));
+        }
+
+        #[test]
+        fn missing_page() {
+            let mut parts = parts();
+            parts[1].pages.push((3, vec!["Todo".into()]));
+            let e = assemble(&parts).unwrap_err();
+            assert_eq!(e.to_string(), "Journal has no page 4");
+        }
+
+        #[test]
+        fn contents_text() {
+            assert_eq!(win_ansi("Idée ✓"), b"Id\xe9e ?");
+            let long = "x".repeat(100);
+            assert_eq!(truncate(&long).chars().count(), LINE_CHARS);
+            assert!(truncate(&long).ends_with("..."));
+        }
+    }
+}
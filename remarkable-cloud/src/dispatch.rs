@@ -0,0 +1,1467 @@
+//! Running the command a parsed command line names, in the profile the
+//! global flags pick, reporting to an [`Output`].
+//!
+//! [`Profile::open`] reads what every command needs from the profile's
+//! directories and takes its lock; [`run`] then hands the command's
+//! arguments, turned into its options, to the module it's in. What a
+//! command reads or writes as it is, rather than as lines of report, goes
+//! through a [`Console`].
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+
+use remarkable_cloud_api::*;
+
+use crate::cache::{self, ListingCache, Refresher};
+use crate::cli::{
+    cloud_paths_from_arg, content_type_from_arg, fields_from_arg,
+    local_archives, lock_mode, parse_rate, parse_size, paths_from_arg,
+    sort_from_arg, values,
+};
+use crate::commands::{self, ListingOptions, Output};
+use crate::filter::DocumentFilter;
+use crate::limits::{self, LimitCheck};
+use crate::lock::{self, HeldLock, LockMode, ProfileLock};
+use crate::mappings::Mappings;
+use crate::mutations::{self, MutationLog};
+use crate::notes;
+use crate::observer::{Event, Phase};
+use crate::optimize::{self, OptimizeOptions};
+use crate::partial;
+use crate::queue::{self, Queue};
+use crate::resolved::{self, ResolvedTree};
+use crate::serve;
+use crate::settings::Settings;
+use crate::template::{self, Template};
+use crate::{
+    await_document, backup, cli, content, digest, doctor, export, exporters,
+    find, info, manage, msg, naming, pages, peek, push, redact, render, setup,
+    stats, status, sync, trash, watch, webhook, CliResult,
+};
+
+/// Where a command reads what it's given and writes what isn't a line of
+/// report: the document `push --stdin` reads, answers to `setup` and
+/// `restore --pick`, and images and exports written out as they are.
+pub struct Console<'a> {
+    pub input: &'a mut dyn BufRead,
+    pub output: &'a mut dyn Write,
+    /// Whether `input` is someone typing rather than something piped in.
+    pub typing: bool,
+}
+
+/// What commands run with: the profile's files, and the options the
+/// global flags give the client and the listing.
+pub struct Profile {
+    pub config_dir: PathBuf,
+    pub client_state_path: PathBuf,
+    pub mutations: MutationLog,
+    pub settings: Settings,
+    pub listing: ListingOptions,
+    pub content_cache: ContentCache,
+    pub lock: ProfileLock,
+    pub client: ClientOptions,
+    /// The lock, if the command takes it, held for as long as the profile.
+    _held: Option<HeldLock>,
+}
+
+impl Profile {
+    /// Opens the profile for the command `matches`, creating its settings
+    /// directory if need be, and takes its lock as the command needs it,
+    /// telling `out` about any wait.
+    pub async fn open(
+        matches: &clap::ArgMatches<'_>,
+        refresher: &Refresher,
+        cancellation: CancellationToken,
+        out: &mut dyn Output,
+    ) -> CliResult<Profile> {
+        let project_dirs =
+            match ProjectDirs::from("zone", "ounce", "remarkable-cloud") {
+                Some(x) => x,
+                None => panic!("Could not determine settings directory."),
+            };
+        let config_dir = project_dirs.config_dir();
+        if !config_dir.exists() {
+            std::fs::create_dir_all(config_dir)?;
+        }
+
+        let lock = ProfileLock::new(config_dir.join(lock::LOCK_FILE));
+        let wait_lock = matches
+            .value_of("wait-lock")
+            .map_or(0, |s| s.parse().unwrap());
+        let held = match lock_mode(matches) {
+            Some(mode) => Some(
+                lock.acquire(
+                    mode,
+                    Some(std::time::Duration::from_secs(wait_lock)),
+                    out,
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
+        let listing = ListingOptions {
+            verbose: matches.is_present("verbose"),
+            use_cache: matches.is_present("cached"),
+            cache: ListingCache::new(
+                project_dirs.cache_dir().join("listing.json"),
+            ),
+            refresh: Some(refresher.clone()).filter(|_| {
+                !matches.is_present("no-refresh")
+                    && lock_mode(matches) == Some(LockMode::Shared)
+            }),
+        };
+
+        let content_cache = ContentCache::at_path(
+            project_dirs.cache_dir().join(content::CONTENT_CACHE_FILE),
+        );
+        content::enable(content_cache.clone());
+        resolved::normalize_paths(matches.is_present("normalize-paths"));
+        match std::env::var(naming::CASE_INSENSITIVE_VAR).as_deref() {
+            Ok("1") => naming::set_case_insensitive(true),
+            Ok("0") => naming::set_case_insensitive(false),
+            _ => {}
+        }
+
+        let settings = Settings::load(&config_dir.join("settings.json"))?;
+        let client = ClientOptions {
+            rate_limiter: matches
+                .value_of("limit-rate")
+                .map(|s| RateLimiter::new(parse_rate(s).unwrap())),
+            resumable_threshold: matches
+                .value_of("chunked-above")
+                .map_or(DEFAULT_RESUMABLE_THRESHOLD, |s| {
+                    parse_size(s).unwrap()
+                }),
+            read_only: settings.read_only || matches.is_present("read-only"),
+            name_policy: if matches.is_present("no-validate-names") {
+                None
+            } else {
+                Some(NamePolicy {
+                    max_len: settings
+                        .max_name_length
+                        .unwrap_or(DEFAULT_MAX_NAME_LEN),
+                    truncate: settings.truncate_names,
+                })
+            },
+            listing_validators: project_dirs
+                .cache_dir()
+                .join("listing-validators.json"),
+            content_cache: content_cache.clone(),
+            cancellation,
+            deadline: matches.value_of("max-time").map(|s| {
+                std::time::Instant::now()
+                    + humantime::parse_duration(s).unwrap()
+            }),
+        };
+
+        Ok(Profile {
+            config_dir: config_dir.to_path_buf(),
+            client_state_path: config_dir.join("client_state.json"),
+            mutations: MutationLog::new(
+                config_dir.join(mutations::MUTATIONS_LOG),
+            ),
+            settings,
+            listing,
+            content_cache,
+            lock,
+            client,
+            _held: held,
+        })
+    }
+}
+
+/// The end of each line the command `matches` prints: a NUL for
+/// `ls --print0`, and otherwise a newline.
+pub fn line_end(matches: &clap::ArgMatches) -> &'static str {
+    match matches.subcommand() {
+        ("ls", Some(sub_m)) if sub_m.is_present("print0") => "\0",
+        _ => "\n",
+    }
+}
+
+/// Whether the command `matches` asks what to do about conflicts when
+/// there's someone typing to ask. With `push --stdin`, the input is the
+/// document instead.
+pub fn asks(matches: &clap::ArgMatches) -> bool {
+    match matches.subcommand() {
+        ("push", Some(sub_m)) => !sub_m.is_present("stdin"),
+        ("sync", Some(sub_m)) => sub_m.subcommand_name() == Some("resolve"),
+        _ => false,
+    }
+}
+
+/// Runs the command `matches` names, in `profile`.
+pub async fn run(
+    matches: &clap::ArgMatches<'_>,
+    profile: Profile,
+    console: &mut Console<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    match matches.subcommand() {
+        ("ls", Some(sub_m)) => ls(&profile, sub_m, out).await,
+        ("info", Some(sub_m)) if sub_m.is_present("verify") => {
+            verify(&profile, sub_m, out).await
+        }
+        ("info", Some(sub_m)) => info(&profile, sub_m, out).await,
+        ("stats", Some(sub_m)) => stats(&profile, sub_m, out).await,
+        ("pull", Some(sub_m)) => pull(&profile, sub_m, out).await,
+        ("find", Some(sub_m)) => find(&profile, sub_m, out).await,
+        ("cache", Some(sub_m)) => warm(&profile, sub_m, out).await,
+        ("note", Some(sub_m)) => note(&profile, sub_m, out).await,
+        ("push", Some(sub_m)) if sub_m.is_present("queue") => {
+            enqueue(&profile, sub_m, out).await
+        }
+        ("push", Some(sub_m)) => push(&profile, sub_m, console, out).await,
+        ("queue", Some(sub_m)) => queue(&profile, sub_m, out).await,
+        ("serve", Some(sub_m)) => serve(profile, sub_m, out).await,
+        ("watch", Some(sub_m)) => watch(&profile, sub_m, out).await,
+        ("await-document", Some(sub_m)) => {
+            await_document(&profile, sub_m, out).await
+        }
+        ("peek", Some(sub_m)) => peek(&profile, sub_m, console, out).await,
+        ("pages", Some(sub_m)) => pages(&profile, sub_m, out).await,
+        ("pin", Some(sub_m)) => pin(&profile, sub_m, true, out).await,
+        ("unpin", Some(sub_m)) => pin(&profile, sub_m, false, out).await,
+        ("mv", Some(sub_m)) => mv(&profile, sub_m, out).await,
+        ("trash", Some(sub_m)) if sub_m.subcommand_name().is_some() => {
+            empty_trash(&profile, sub_m, out).await
+        }
+        ("trash", Some(sub_m)) => trash(&profile, sub_m, out).await,
+        ("rm", Some(sub_m)) => rm(&profile, sub_m, out).await,
+        ("export", Some(sub_m)) => match sub_m.subcommand_name() {
+            Some("feed") => feed(&profile, sub_m, console, out).await,
+            Some("digest") => digest(&profile, sub_m, out).await,
+            Some(format) if exporters::find(format).is_some() => {
+                export(&profile, sub_m, out).await
+            }
+            _ => tree(&profile, sub_m, console, out).await,
+        },
+        ("backup", Some(sub_m)) => backup(&profile, sub_m, out).await,
+        ("restore", Some(sub_m)) if sub_m.is_present("pick") => {
+            restore_pick(&profile, sub_m, console, out).await
+        }
+        ("restore", Some(sub_m)) => restore(&profile, sub_m, out).await,
+        ("undo", Some(sub_m)) => undo(&profile, sub_m, out).await,
+        ("auth", Some(sub_m)) => auth(&profile, sub_m, out).await,
+        ("setup", Some(sub_m)) => setup(&profile, sub_m, console).await,
+        ("doctor", Some(_)) => doctor(&profile, out).await,
+        ("fsck", Some(_)) => fsck(&profile, out).await,
+        ("sync", Some(sub_m)) => sync(&profile, sub_m, out).await,
+        _ => panic!("Subcommand not found."),
+    }
+}
+
+// The rules in the push settings, with `~` standing for the home directory.
+fn push_mappings(settings: &Settings) -> CliResult<Mappings> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    Ok(Mappings::new(&settings.push, home.as_deref())?)
+}
+
+const AUTH_URL_VAR: &str = "REMARKABLE_AUTH_URL";
+const REGISTER_URL_VAR: &str = "REMARKABLE_REGISTER_URL";
+
+// How long to wait for a connection to the cloud. The client keeps
+// connections open, so in practice this is only waited on by the first
+// request, and when there's no network, commands fail after it rather than
+// the much longer system timeout.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How the client is set up, as the global flags and the settings say.
+pub struct ClientOptions {
+    rate_limiter: Option<RateLimiter>,
+    /// Blobs at least this big are sent in chunks where the cloud allows.
+    resumable_threshold: u64,
+    read_only: bool,
+    /// How names are checked on upload and rename, if at all.
+    name_policy: Option<NamePolicy>,
+    /// Where the last listing is kept with its ETag, to be revalidated
+    /// rather than fetched again whole.
+    listing_validators: PathBuf,
+    content_cache: ContentCache,
+    /// Cancelled on Ctrl-C.
+    cancellation: CancellationToken,
+    /// When --max-time runs out.
+    deadline: Option<std::time::Instant>,
+}
+
+// A client set up as the options say, which has yet to get a user token.
+async fn new_client(
+    state_path: &Path,
+    options: &ClientOptions,
+) -> Result<Client> {
+    let store = FileStateStore::new(state_path.to_path_buf());
+    let mut client = Client::from_state_store(
+        std::sync::Arc::new(store),
+        reqwest::Client::builder()
+            .user_agent("remarkable-cloud")
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()?,
+    )
+    .await?
+    .with_cancellation(options.cancellation.clone());
+    if let Some(deadline) = options.deadline {
+        client = client.with_deadline(deadline);
+    }
+    client.set_rate_limiter(options.rate_limiter.clone());
+    client.set_resumable_threshold(Some(options.resumable_threshold));
+    client.set_read_only(options.read_only);
+    client.set_name_policy(options.name_policy);
+    client.set_listing_cache(Some(
+        remarkable_cloud_api::ListingCache::at_path(
+            options.listing_validators.clone(),
+        ),
+    ));
+    client.set_content_cache(Some(options.content_cache.clone()));
+    // Self-hosted clouds, and the tests, hand out tokens from elsewhere.
+    if let Ok(url) = std::env::var(AUTH_URL_VAR) {
+        client.set_user_token_url(url);
+    }
+    if let Ok(url) = std::env::var(REGISTER_URL_VAR) {
+        client.set_device_token_url(url);
+    }
+    Ok(client)
+}
+
+async fn get_client(
+    state_path: &Path,
+    options: &ClientOptions,
+) -> Result<Client> {
+    let mut client = new_client(state_path, options).await?;
+    client.refresh_token().await?;
+    Ok(client)
+}
+
+// Fetches the listing for a command which only reads it. If the cloud can't
+// be reached, the cached listing is used instead, with no client.
+async fn read_listing(
+    state_path: &Path,
+    client_options: &ClientOptions,
+    options: &ListingOptions,
+    out: &mut dyn Output,
+) -> CliResult<(Option<Client>, ResolvedTree)> {
+    let error = match get_client(state_path, client_options).await {
+        Ok(client) => {
+            match commands::list_documents(&client, options, out).await {
+                Ok(documents) => return Ok((Some(client), documents)),
+                Err(e) => e,
+            }
+        }
+        Err(e) => e,
+    };
+    let cached = (options.cache.load(), options.cache.saved_at());
+    let (documents, saved_at) = match (&error, cached) {
+        (Error::Offline { .. }, (Some(documents), Some(saved_at))) => {
+            (documents, saved_at)
+        }
+        // Part of it won't do for all of it.
+        (Error::Offline { .. }, _) => match options.cache.load_partial() {
+            Some(partial) => {
+                return Err(msg!(
+                    LISTING_ONLY_PARTIAL,
+                    error = error.to_string(),
+                    folders = partial.covered_paths(),
+                )
+                .into())
+            }
+            None => return Err(error.into()),
+        },
+        _ => return Err(error.into()),
+    };
+    redact::learn(&documents);
+    let age = saved_at.elapsed().unwrap_or_default();
+    out.warn(&msg!(OFFLINE_CACHED, ago = cache::ago(age)));
+    Ok((None, ResolvedTree::from_cache(documents, age)))
+}
+
+// What `ls --shallow` needs of the cached listing to list `paths`, if it was
+// saved within `cache::STALE_AFTER` and holds all of that.
+fn shallow_listing(
+    cache: &ListingCache,
+    paths: &[CloudPath],
+) -> Option<ResolvedTree> {
+    let age = cache.age().filter(|age| *age <= cache::STALE_AFTER)?;
+    let root = [CloudPath::root()];
+    let paths = if paths.is_empty() { &root[..] } else { paths };
+    let normalized = resolved::normalizing();
+    let documents = match cache.load() {
+        Some(documents) => {
+            partial::shallow(&documents, &|_| true, paths, normalized)
+        }
+        None => cache.load_partial()?.shallow(paths, normalized),
+    }?;
+    redact::learn(&documents);
+    Some(ResolvedTree::from_cache(documents, age))
+}
+
+async fn ls(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let paths = cloud_paths_from_arg(sub_m, "paths", None)?;
+    let cached = if sub_m.is_present("shallow") {
+        shallow_listing(&profile.listing.cache, &paths)
+    } else {
+        None
+    };
+    let documents = match cached {
+        Some(documents) => documents,
+        None => {
+            read_listing(
+                &profile.client_state_path,
+                &profile.client,
+                &profile.listing,
+                out,
+            )
+            .await?
+            .1
+        }
+    };
+    let options = commands::LsOptions {
+        paths,
+        list: render::ListOptions {
+            max_depth: match sub_m.value_of("depth") {
+                Some(d) => Some(d.parse().unwrap()),
+                None if sub_m.is_present("recurse") => None,
+                None => Some(1),
+            },
+            paths: sub_m.is_present("paths-only"),
+            content_type: content_type_from_arg(sub_m),
+            sort: sort_from_arg(sub_m)?,
+        },
+        fields: fields_from_arg(sub_m),
+        header: sub_m.is_present("header"),
+    };
+    commands::ls(&documents, &options, out);
+    Ok(())
+}
+
+// `info --verify`: downloads each document to check it is whole.
+async fn verify(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let paths = cloud_paths_from_arg(sub_m, "filenames", None)?;
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    let recursive = sub_m.is_present("recursive");
+    info::verify(&client, &documents, &paths, recursive, out).await?;
+    Ok(())
+}
+
+async fn info(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let paths = cloud_paths_from_arg(sub_m, "filenames", None)?;
+    let options = info::InfoOptions {
+        history: sub_m.is_present("history"),
+        json: sub_m.is_present("json"),
+        content: sub_m.is_present("content"),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    // Loaded before listing, which replaces the cache.
+    let cached = if options.history {
+        profile.listing.cache.load()
+    } else {
+        None
+    };
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    info::info(&client, &documents, cached.as_ref(), &paths, &options, out)
+        .await?;
+    Ok(())
+}
+
+async fn stats(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let paths = cloud_paths_from_arg(sub_m, "paths", None)?;
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    let json = sub_m.is_present("json");
+    stats::stats(&client, &documents, &paths, json, out).await?;
+    Ok(())
+}
+
+async fn pull(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let options = commands::PullOptions {
+        paths: paths_from_arg(sub_m, "filenames")
+            .map(Path::to_path_buf)
+            .collect(),
+        ids: sub_m
+            .values_of("id")
+            .into_iter()
+            .flatten()
+            .map(|id| id.parse())
+            .collect::<std::result::Result<_, _>>()?,
+        all_matches: sub_m.is_present("all-matches"),
+        recursive: sub_m.is_present("recursive"),
+        raw_zip: sub_m.is_present("raw-zip"),
+        format: sub_m
+            .value_of("format")
+            .map_or(Ok(commands::PullFormat::Original), str::parse)?,
+        name_template: match sub_m.value_of("name-template") {
+            Some(t) => {
+                let t: Template = t.parse()?;
+                t.check_available(&[
+                    template::Field::Name,
+                    template::Field::Id,
+                    template::Field::Version,
+                    template::Field::Date,
+                    template::Field::Ext,
+                ])?;
+                Some(t)
+            }
+            None => None,
+        },
+        dir: sub_m
+            .value_of("output")
+            .map_or_else(PathBuf::new, PathBuf::from),
+        preserve_times: !sub_m.is_present("no-preserve-times"),
+        groups: sub_m
+            .values_of("tag")
+            .into_iter()
+            .flatten()
+            .map(|tag| commands::PullGroup::Tag(tag.to_string()))
+            .chain(
+                sub_m
+                    .is_present("bookmarked")
+                    .then_some(commands::PullGroup::Bookmarked),
+            )
+            .collect(),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    commands::pull(&client, &documents, &options, out).await?;
+    Ok(())
+}
+
+async fn find(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let options = find::FindOptions {
+        filter: DocumentFilter::from_matches(sub_m, chrono::Utc::now())?,
+        pattern: match sub_m.value_of("path") {
+            Some(p) => Some(p.parse()?),
+            None => None,
+        },
+        paths: match sub_m.value_of("limit-folders") {
+            Some(folder) => vec![folder.parse()?],
+            None => {
+                cloud_paths_from_arg(sub_m, "paths", Some(CloudPath::root()))?
+            }
+        },
+        deep: find::DeepFilter {
+            empty: sub_m.is_present("empty"),
+            pinned: sub_m.is_present("pinned"),
+        },
+        content_type: content_type_from_arg(sub_m),
+        with_notes: sub_m.value_of("with-notes").map(String::from),
+        duplicates_of: match sub_m.value_of("duplicates-of") {
+            Some(path) => Some(path.parse()?),
+            None => None,
+        },
+        by_name: sub_m.is_present("by-name"),
+        limit: sub_m.value_of("limit").map(|s| s.parse().unwrap()),
+        sort: sort_from_arg(sub_m)?,
+        fields: fields_from_arg(sub_m),
+        header: sub_m.is_present("header"),
+        json: sub_m.is_present("json"),
+    };
+    let (client, documents) = read_listing(
+        &profile.client_state_path,
+        &profile.client,
+        &profile.listing,
+        out,
+    )
+    .await?;
+    find::find(client.as_ref(), &documents, &options, out).await?;
+    Ok(())
+}
+
+async fn warm(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let warm_m = sub_m.subcommand_matches("warm").unwrap();
+    let folder = match warm_m.value_of("folder") {
+        Some(path) => Some(path.parse::<CloudPath>()?),
+        None => None,
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    cache::warm(
+        &client,
+        &profile.listing,
+        &profile.content_cache,
+        folder.as_ref(),
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn note(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let command = match sub_m.subcommand() {
+        ("set", Some(set_m)) => notes::NoteCommand::Set {
+            path: set_m.value_of("path").unwrap().parse()?,
+            text: set_m.value_of("text").unwrap().to_string(),
+        },
+        ("show", Some(show_m)) => {
+            notes::NoteCommand::Show(show_m.value_of("path").unwrap().parse()?)
+        }
+        ("search", Some(search_m)) => notes::NoteCommand::Search(
+            search_m.value_of("term").unwrap().to_string(),
+        ),
+        ("prune", Some(_)) => notes::NoteCommand::Prune,
+        _ => unreachable!(),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    notes::note(&client, &documents, command, out).await?;
+    Ok(())
+}
+
+// `push --queue`: adds the files to the queue rather than sending them.
+async fn enqueue(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let queue = Queue::new(profile.config_dir.join(queue::QUEUE_FILE));
+    queue::enqueue_all(
+        &queue,
+        paths_from_arg(sub_m, "files"),
+        sub_m.value_of("to"),
+        &push_mappings(&profile.settings)?,
+        sub_m.value_of("on-conflict").map(|s| s.parse().unwrap()),
+        out,
+    )?;
+    Ok(())
+}
+
+async fn push(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    console: &mut Console<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let journal = push::Journal::new(profile.config_dir.join("uploads"));
+    if sub_m.is_present("resume") {
+        commands::push_resume(&client, &journal, &profile.mutations, out)
+            .await?;
+        return Ok(());
+    }
+    if sub_m.is_present("optimize") && !optimize::OPTIMIZE_BUILT {
+        return Err(msg!(OPTIMIZE_NOT_BUILT).into());
+    }
+    // Don't wait for input nobody is going to type.
+    if sub_m.is_present("stdin") && console.typing {
+        return Err(msg!(PUSH_STDIN_TERMINAL).into());
+    }
+    commands::note_interrupted(&journal, out)?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    // Without --to, the push settings say where each file goes, and
+    // what's read from stdin goes to their default folder.
+    let plan = commands::PushPlan {
+        files: paths_from_arg(sub_m, "files")
+            .map(Path::to_path_buf)
+            .collect(),
+        destination: match sub_m.value_of("to") {
+            Some(p) => commands::PushDestination::Folder(p.parse()?),
+            None => commands::PushDestination::Mapped(push_mappings(
+                &profile.settings,
+            )?),
+        },
+        stdin: sub_m.is_present("stdin"),
+        create_missing: sub_m.is_present("create-missing"),
+        recursive: sub_m.is_present("recursive"),
+        limits: LimitCheck {
+            limits: profile.settings.tree_limits(),
+            strict: sub_m.is_present("strict"),
+        },
+    };
+    let groups = commands::plan_push(&client, &documents, plan, out).await?;
+    let on_conflict = sub_m.value_of("on-conflict").map(|s| s.parse().unwrap());
+    let memory = push::MemoryBudget::new(
+        sub_m.value_of("max-memory").map(|s| parse_size(s).unwrap()),
+    );
+    let optimize = if sub_m.is_present("optimize") {
+        Some(OptimizeOptions {
+            downsample_dpi: sub_m
+                .value_of("downsample-dpi")
+                .map(|s| s.parse().unwrap()),
+        })
+    } else {
+        None
+    };
+    let verify = sub_m.is_present("verify").then(|| push::Verify {
+        max_size: sub_m
+            .value_of("verify-max-size")
+            .map(|s| parse_size(s).unwrap()),
+    });
+    if let Some(name) = sub_m.value_of("name") {
+        // --verify-max-size is about files; what's read from stdin
+        // is always read back.
+        commands::push_stdin(
+            &client,
+            &journal,
+            &profile.mutations,
+            &documents,
+            groups.first().and_then(|(parent, _)| *parent),
+            name,
+            on_conflict,
+            verify.is_some(),
+            &mut console.input,
+            out,
+        )
+        .await?;
+    }
+    for (parent, files) in groups {
+        let options = commands::PushOptions {
+            files,
+            parent,
+            recursive: sub_m.is_present("recursive"),
+            on_conflict,
+            memory,
+            optimize,
+            verify,
+        };
+        commands::push(
+            &client,
+            &journal,
+            &profile.mutations,
+            &documents,
+            &options,
+            out,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn queue(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let queue = Queue::new(profile.config_dir.join(queue::QUEUE_FILE));
+    match sub_m.subcommand() {
+        ("list", _) => queue::list(&queue, out)?,
+        ("run", Some(run_m)) => {
+            let client =
+                get_client(&profile.client_state_path, &profile.client).await?;
+            if run_m.is_present("forever") {
+                return queue::run_forever(
+                    &client,
+                    &queue,
+                    &profile.mutations,
+                    &profile.lock,
+                    &profile.client.cancellation,
+                    out,
+                )
+                .await;
+            }
+            queue::run_and_report(&client, &queue, &profile.mutations, out)
+                .await?;
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+    Ok(())
+}
+
+async fn serve(
+    profile: Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let listen: std::net::SocketAddr =
+        sub_m.value_of("listen").unwrap().parse()?;
+    if !listen.ip().is_loopback() && !sub_m.is_present("allow-remote") {
+        return Err(msg!(SERVE_REMOTE, listen = listen.to_string()).into());
+    }
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let token_file = profile.config_dir.join(serve::TOKEN_FILE);
+    let options = serve::ServeOptions {
+        listen,
+        token: serve::token(&token_file)?,
+        listing: profile.listing,
+        journal: push::Journal::new(profile.config_dir.join("uploads")),
+        mutations: profile.mutations,
+        lock: profile.lock,
+    };
+    out.warn(&msg!(
+        SERVE_TOKEN_IN,
+        path = token_file.display().to_string()
+    ));
+    let cancellation = &profile.client.cancellation;
+    serve::serve(client, options, cancellation, out).await?;
+    Ok(())
+}
+
+async fn watch(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let retries = sub_m.value_of("retries").unwrap().parse()?;
+    let mut deliveries = if sub_m.is_present("webhook")
+        || sub_m.is_present("replay-spool")
+    {
+        let secret =
+            profile.settings.webhook_secret.as_deref().ok_or_else(|| {
+                let settings = profile.config_dir.join("settings.json");
+                msg!(WEBHOOK_NO_SECRET, path = settings.display().to_string())
+            })?;
+        Some(webhook::Deliveries::new(
+            &profile.config_dir,
+            secret,
+            retries,
+        )?)
+    } else {
+        None
+    };
+    if sub_m.is_present("replay-spool") {
+        return webhook::replay_spool(deliveries.as_mut().unwrap(), out).await;
+    }
+    let duration = |name| {
+        humantime::parse_duration(sub_m.value_of(name).unwrap()).unwrap()
+    };
+    let options = watch::WatchOptions {
+        interval: duration("interval"),
+        debounce: duration("debounce"),
+        webhooks: sub_m
+            .values_of("webhook")
+            .map_or_else(Vec::new, |v| v.map(String::from).collect()),
+        once: sub_m.is_present("once"),
+        state: ListingCache::new(profile.config_dir.join(watch::STATE_FILE)),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    watch::watch(
+        &client,
+        &options,
+        deliveries.as_mut(),
+        &profile.client.cancellation,
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn await_document(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let options = await_document::AwaitOptions {
+        path: sub_m.value_of("path").unwrap().parse()?,
+        min_version: sub_m.value_of("min-version").map(|s| s.parse().unwrap()),
+        interval: humantime::parse_duration(
+            sub_m.value_of("interval").unwrap(),
+        )
+        .unwrap(),
+        timeout: std::time::Duration::from_secs(
+            sub_m.value_of("timeout").unwrap().parse().unwrap(),
+        ),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let found = await_document::await_document(
+        &client,
+        &options,
+        &profile.client.cancellation,
+        out,
+    )
+    .await?;
+    out.line(&serde_json::to_string(&found)?);
+    Ok(())
+}
+
+async fn peek(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    console: &mut Console<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let request = if let Some(output) = sub_m.value_of("output") {
+        peek::Request::Save(PathBuf::from(output))
+    } else if sub_m.is_present("inline") {
+        let protocol = sub_m
+            .value_of("inline-protocol")
+            .map(|p| p.parse().unwrap());
+        peek::Request::Inline(protocol)
+    } else {
+        peek::Request::Open
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    let path = sub_m.value_of("path").unwrap();
+    peek::peek(&client, &documents, path, request, console.output, out).await?;
+    Ok(())
+}
+
+async fn pages(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (action, sub_m) = sub_m.subcommand();
+    let sub_m = sub_m.unwrap();
+    let action = match action {
+        "list" => pages::PagesAction::List,
+        "delete" => pages::PagesAction::Delete(
+            sub_m.value_of("pages").unwrap().to_string(),
+        ),
+        _ => pages::PagesAction::Reorder(
+            sub_m.value_of("order").unwrap().to_string(),
+        ),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    pages::pages(
+        &client,
+        &profile.mutations,
+        &documents,
+        sub_m.value_of("path").unwrap(),
+        &action,
+        sub_m.is_present("dry-run"),
+        profile.listing.use_cache,
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn pin(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    pinned: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let selection = manage::Selection {
+        patterns: values(sub_m, "paths"),
+        allow_empty: sub_m.is_present("allow-empty"),
+        cached: profile.listing.use_cache,
+        yes: sub_m.is_present("yes"),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    manage::pin(
+        &client,
+        &profile.mutations,
+        &documents,
+        &selection,
+        pinned,
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn mv(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut selection = manage::Selection {
+        patterns: values(sub_m, "paths"),
+        allow_empty: sub_m.is_present("allow-empty"),
+        cached: profile.listing.use_cache,
+        yes: sub_m.is_present("yes"),
+    };
+    let dest: CloudPath = selection.patterns.pop().unwrap().parse()?;
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    manage::mv(
+        &client,
+        &profile.mutations,
+        &documents,
+        &selection,
+        &dest,
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+// `trash empty` and `trash prune`, rather than trashing documents.
+async fn empty_trash(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (_, empty_m) = sub_m.subcommand();
+    let empty_m = empty_m.unwrap();
+    let options = trash::EmptyOptions {
+        cutoff: empty_m.value_of("older-than").map(|s| {
+            // Too long ago to say is before anything was trashed.
+            let age = humantime::parse_duration(s).unwrap();
+            chrono::Duration::from_std(age)
+                .ok()
+                .and_then(|age| chrono::Utc::now().checked_sub_signed(age))
+                .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC)
+        }),
+        keep_latest: empty_m
+            .value_of("keep-latest")
+            .map_or(0, |n| n.parse().unwrap()),
+        dry_run: empty_m.is_present("dry-run"),
+        cached: profile.listing.use_cache,
+        yes: empty_m.is_present("yes"),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    trash::empty(&client, &profile.mutations, &documents, &options, out)
+        .await?;
+    Ok(())
+}
+
+async fn trash(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let selection = manage::Selection {
+        patterns: values(sub_m, "paths"),
+        allow_empty: sub_m.is_present("allow-empty"),
+        cached: profile.listing.use_cache,
+        yes: sub_m.is_present("yes"),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    manage::trash(&client, &profile.mutations, &documents, &selection, out)
+        .await?;
+    Ok(())
+}
+
+async fn rm(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let selection = manage::Selection {
+        patterns: values(sub_m, "paths"),
+        allow_empty: sub_m.is_present("allow-empty"),
+        cached: profile.listing.use_cache,
+        yes: sub_m.is_present("yes"),
+    };
+    let recursive = sub_m.is_present("recursive");
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    manage::rm(
+        &client,
+        &profile.mutations,
+        &documents,
+        &selection,
+        recursive,
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+// `export feed`: an Atom feed of what changed since it last ran.
+async fn feed(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    console: &mut Console<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let feed_m = sub_m.subcommand_matches("feed").unwrap();
+    let snapshot =
+        ListingCache::new(feed_m.value_of("since").map_or_else(
+            || profile.config_dir.join("feed.json"),
+            PathBuf::from,
+        ));
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    let output = feed_m.value_of("output").map(Path::new);
+    export::feed(&documents, &snapshot, output, console.output)?;
+    Ok(())
+}
+
+// `export digest`: the bookmarked pages, together in one PDF.
+async fn digest(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let digest_m = sub_m.subcommand_matches("digest").unwrap();
+    if !digest::DIGEST_BUILT {
+        return Err(msg!(DIGEST_NOT_BUILT).into());
+    }
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    digest::digest(
+        &client,
+        &documents,
+        digest_m.value_of("since"),
+        Path::new(digest_m.value_of("output").unwrap()),
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+// `export <format>`, of documents in the cloud or archives saved
+// locally.
+async fn export(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (format, sub_m) = sub_m.subcommand();
+    let sub_m = sub_m.unwrap();
+    let job = exporters::ExportJob {
+        exporter: exporters::find(format).unwrap(),
+        fallback: sub_m.value_of("fallback").and_then(exporters::find),
+        paths: cloud_paths_from_arg(sub_m, "paths", None)?,
+        recursive: sub_m.is_present("recursive"),
+        dir: sub_m
+            .value_of("output")
+            .map_or_else(PathBuf::new, PathBuf::from),
+    };
+    if let Some(local) = local_archives(sub_m) {
+        return exporters::export_local(&job, &local, out);
+    }
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    exporters::export(&client, &documents, &job, out).await?;
+    Ok(())
+}
+
+// `export csv` and `export opml`: the tree of names, without content.
+async fn tree(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    console: &mut Console<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (format, sub_m) = sub_m.subcommand();
+    let sub_m = sub_m.unwrap();
+    let format = match format {
+        "csv" => export::TreeFormat::Csv,
+        _ => export::TreeFormat::Opml,
+    };
+    let path: CloudPath = sub_m.value_of("path").unwrap_or("/").parse()?;
+    let options = export::ExportOptions {
+        include_trash: sub_m.is_present("include-trash"),
+    };
+    let (_, documents) = read_listing(
+        &profile.client_state_path,
+        &profile.client,
+        &profile.listing,
+        out,
+    )
+    .await?;
+    export::tree(&documents, &path, format, options, console.output)?;
+    Ok(())
+}
+
+async fn backup(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    out.observe(&Event::Phase(Phase::Listing));
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    let report = backup::backup(
+        &client,
+        &documents,
+        Path::new(sub_m.value_of("output").unwrap()),
+        backup::BackupOptions {
+            resume: sub_m.is_present("resume"),
+            resume_plan: sub_m.is_present("resume-plan"),
+            reproducible: sub_m.is_present("reproducible"),
+        },
+        out,
+    )
+    .await?;
+    out.note(&msg!(
+        BACKED_UP,
+        documents = report.documents,
+        folders = report.folders,
+        resumed = report.resumed,
+        failed = report.failed,
+    ));
+    Ok(())
+}
+
+// `restore --pick`: one document, from a directory of its archives saved
+// at different times.
+async fn restore_pick(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    console: &mut Console<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let dir = Path::new(sub_m.value_of("pick").unwrap());
+    let pick = match (sub_m.value_of("version"), sub_m.value_of("date")) {
+        (Some(version), _) => backup::Pick::Version(version.parse()?),
+        (_, Some(date)) => backup::Pick::Date(date.parse()?),
+        _ => backup::Pick::Ask,
+    };
+    let into = match sub_m.value_of("into") {
+        Some(p) => Some(p.parse()?),
+        None => None,
+    };
+    let picked =
+        backup::pick_archive(dir, pick, console.input, console.output)?;
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    backup::restore_pick(
+        &client,
+        &profile.mutations,
+        &documents,
+        &picked,
+        into.as_ref(),
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn restore(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let into = match sub_m.value_of("into") {
+        Some(p) => Some(p.parse()?),
+        None => None,
+    };
+    let limits = LimitCheck {
+        limits: profile.settings.tree_limits(),
+        strict: sub_m.is_present("strict"),
+    };
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    let documents =
+        commands::list_documents(&client, &profile.listing, out).await?;
+    backup::restore_all(
+        &client,
+        &profile.mutations,
+        &documents,
+        Path::new(sub_m.value_of("archive").unwrap()),
+        into.as_ref(),
+        sub_m.is_present("keep-ids"),
+        &limits,
+        out,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn undo(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let count = sub_m.value_of("last").unwrap().parse().unwrap();
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    mutations::undo_and_report(&client, &profile.mutations, count, out).await?;
+    Ok(())
+}
+
+async fn auth(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (_, sub_m) = sub_m.subcommand();
+    let check = sub_m.unwrap().is_present("check");
+    let client =
+        get_client(&profile.client_state_path, &profile.client).await?;
+    status::auth(&client, check, out).await?;
+    Ok(())
+}
+
+async fn setup(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    console: &mut Console<'_>,
+) -> CliResult<()> {
+    let mut prompt = setup::Prompt::new(
+        console.input,
+        console.output,
+        sub_m.is_present("defaults"),
+    );
+    let mut registered = profile.client_state_path.exists()
+        && new_client(&profile.client_state_path, &profile.client)
+            .await?
+            .diagnostics()
+            .device_token_fingerprint
+            .is_some();
+    let code = setup::code(&mut prompt, registered, sub_m.value_of("code"))?;
+    if let Some(code) = code {
+        if !profile.client_state_path.exists() {
+            let mut state = ClientState::new();
+            state.set_endpoint(DEFAULT_ENDPOINT.to_string());
+            state.save_to_path(&profile.client_state_path)?;
+        }
+        let mut client =
+            new_client(&profile.client_state_path, &profile.client).await?;
+        let endpoint = sub_m.value_of("endpoint");
+        setup::register(&mut prompt, &mut client, &code, endpoint).await?;
+        registered = true;
+    }
+    if registered {
+        let client =
+            get_client(&profile.client_state_path, &profile.client).await?;
+        setup::signed_in(&mut prompt, &client).await?;
+    }
+    setup::credentials(&mut prompt, &profile.client_state_path)?;
+    setup::write_settings(
+        &mut prompt,
+        &profile.config_dir.join("settings.json"),
+    )?;
+    setup::completions(&mut prompt, |shell| {
+        let mut completions = vec![];
+        cli::app().gen_completions_to(
+            "remarkable-cloud",
+            shell,
+            &mut completions,
+        );
+        completions
+    })?;
+    Ok(())
+}
+
+async fn doctor(profile: &Profile, out: &mut dyn Output) -> CliResult<()> {
+    let mut client =
+        new_client(&profile.client_state_path, &profile.client).await?;
+    doctor::doctor(&mut client, out).await?;
+    Ok(())
+}
+
+async fn fsck(profile: &Profile, out: &mut dyn Output) -> CliResult<()> {
+    let (_, documents) = read_listing(
+        &profile.client_state_path,
+        &profile.client,
+        &profile.listing,
+        out,
+    )
+    .await?;
+    limits::fsck(&profile.settings.tree_limits(), &documents, out)?;
+    Ok(())
+}
+
+async fn sync(
+    profile: &Profile,
+    sub_m: &clap::ArgMatches<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (action, action_m) = sub_m.subcommand();
+    let action_m = action_m.unwrap();
+    let dir = Path::new(action_m.value_of("local-dir").unwrap());
+    // Only a pull can be into a directory never synced.
+    let manifest = match action {
+        "pull" => sync::Manifest::load(dir)?,
+        _ => Some(sync::Manifest::load_synced(dir)?),
+    };
+    out.observe(&Event::Phase(Phase::Listing));
+    let (_, documents) = read_listing(
+        &profile.client_state_path,
+        &profile.client,
+        &profile.listing,
+        out,
+    )
+    .await?;
+    match action {
+        "pull" => {
+            let options = sync::PullOptions {
+                folder: match action_m.value_of("folder") {
+                    Some(folder) => Some(folder.parse()?),
+                    None => None,
+                },
+                layout: action_m.value_of("layout").map(|l| l.parse().unwrap()),
+                relayout: action_m.is_present("relayout"),
+                default_layout: profile.settings.sync_layout,
+                resume_plan: action_m.is_present("resume-plan"),
+            };
+            let client =
+                get_client(&profile.client_state_path, &profile.client).await?;
+            sync::pull_dir(&client, dir, manifest, &documents, &options, out)
+                .await?;
+        }
+        "resolve" => {
+            let client =
+                get_client(&profile.client_state_path, &profile.client).await?;
+            let journal =
+                push::Journal::new(profile.config_dir.join("uploads"));
+            let strategy =
+                action_m.value_of("strategy").map(|s| s.parse().unwrap());
+            sync::resolve_dir(
+                &client,
+                &journal,
+                &profile.mutations,
+                dir,
+                manifest.unwrap(),
+                &documents,
+                strategy,
+                out,
+            )
+            .await?;
+        }
+        _ => sync::check_dir(dir, &manifest.unwrap(), &documents, out)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,351 @@
+//! The protocol self-test run by `doctor`: a harmless request to each
+//! endpoint, and what the answer says about whether this version of the
+//! client still speaks the cloud's protocol.
+
+use futures_util::StreamExt;
+use remarkable_cloud_api::{
+    Client, Error, Result, SchemaDrift, MIGRATION_ISSUES_URL,
+};
+
+use crate::commands::Output;
+use crate::messages;
+use crate::{msg, status, CliResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Ok,
+    /// The cloud didn't accept the client's token.
+    Auth,
+    /// The endpoint isn't there any more.
+    Gone,
+    /// The answer wasn't shaped as this version expects.
+    Drift,
+    /// The check wasn't made.
+    Skipped,
+    /// Anything else, such as the network being down.
+    Failed,
+}
+
+impl Verdict {
+    fn label(self) -> String {
+        match self {
+            Verdict::Ok => msg!(VERDICT_OK),
+            Verdict::Auth => msg!(VERDICT_AUTH),
+            Verdict::Gone => msg!(VERDICT_GONE),
+            Verdict::Drift => msg!(VERDICT_DRIFT),
+            Verdict::Skipped => msg!(VERDICT_SKIPPED),
+            Verdict::Failed => msg!(VERDICT_FAILED),
+        }
+    }
+
+    pub fn is_problem(self) -> bool {
+        !matches!(self, Verdict::Ok | Verdict::Skipped)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+/// What an error from an endpoint says about it.
+pub fn classify(e: &Error) -> Verdict {
+    match e {
+        Error::HttpError { source } => {
+            match source.status().map(|s| s.as_u16()) {
+                Some(401) | Some(403) => Verdict::Auth,
+                Some(404) | Some(405) | Some(410) => Verdict::Gone,
+                _ => Verdict::Failed,
+            }
+        }
+        Error::AccountMigrated => Verdict::Gone,
+        Error::JsonError { .. } => Verdict::Drift,
+        _ => Verdict::Failed,
+    }
+}
+
+fn describe_drift(drift: &SchemaDrift) -> String {
+    let mut parts = vec![];
+    for (what, counts) in &[
+        (&messages::DRIFT_UNKNOWN, &drift.unknown_fields),
+        (&messages::DRIFT_MISSING, &drift.missing_fields),
+    ] {
+        for (field, n) in counts.iter() {
+            parts.push(messages::render(
+                what,
+                &[
+                    ("field", format!("{:?}", field).into()),
+                    ("count", (*n).into()),
+                    ("entries", drift.entries.into()),
+                ],
+            ));
+        }
+    }
+    parts.join(", ")
+}
+
+// Makes a check of `result`, with the schema drift seen while getting it.
+fn check(name: &'static str, client: &Client, result: Result<String>) -> Check {
+    let drift = client.take_schema_drift().unwrap_or_default();
+    let (verdict, detail) = match result {
+        Ok(_) if !drift.is_empty() => (Verdict::Drift, describe_drift(&drift)),
+        Ok(detail) => (Verdict::Ok, detail),
+        Err(e) => (classify(&e), e.to_string()),
+    };
+    Check {
+        name,
+        verdict,
+        detail,
+    }
+}
+
+fn skipped(name: &'static str, why: &str) -> Check {
+    Check {
+        name,
+        verdict: Verdict::Skipped,
+        detail: why.to_string(),
+    }
+}
+
+/// Runs every check in turn. Nothing is changed in the cloud: the requests
+/// which could change anything are sent with nothing in them, and not at
+/// all by a read-only client.
+pub async fn run(client: &mut Client) -> Vec<Check> {
+    client.set_listing_cache(None);
+    client.set_schema_diagnostics(true);
+    let mut checks = vec![];
+
+    let token = client.refresh_token().await.map(|_| String::new());
+    checks.push(check("token", client, token));
+    if checks[0].verdict != Verdict::Ok {
+        for name in &["listing", "document", "blob", "writes"] {
+            checks.push(skipped(name, &msg!(DOCTOR_NEEDS_TOKEN)));
+        }
+        return checks;
+    }
+
+    let (listing, result) = match client.get_documents().await {
+        Ok(docs) => {
+            let detail = msg!(DOCTOR_DOCUMENTS, count = docs.len());
+            (Some(docs), Ok(detail))
+        }
+        Err(e) => (None, Err(e)),
+    };
+    let mut listed = check("listing", client, result);
+    let warnings = listing.as_ref().map_or(0, |d| d.parse_warnings().len());
+    if listed.verdict == Verdict::Ok && warnings > 0 {
+        listed.verdict = Verdict::Drift;
+        listed.detail = msg!(DOCTOR_ENTRIES_UNREAD, count = warnings);
+    }
+    checks.push(listed);
+
+    // The same document each time, for comparable runs.
+    let document = listing.as_ref().and_then(|docs| {
+        docs.iter()
+            .filter(|d| d.is_document())
+            .min_by_key(|d| d.id)
+            .map(|d| d.id)
+    });
+    let doc = match document {
+        Some(id) => {
+            let (doc, result) = match client.get_document_by_id(&id).await {
+                Ok(doc) => (Some(doc), Ok(id.to_string())),
+                Err(e) => (None, Err(e)),
+            };
+            checks.push(check("document", client, result));
+            doc
+        }
+        None if listing.is_none() => {
+            checks.push(skipped("document", &msg!(DOCTOR_NEEDS_LISTING)));
+            None
+        }
+        None => {
+            checks.push(skipped("document", &msg!(DOCTOR_NO_DOCUMENTS)));
+            None
+        }
+    };
+
+    match doc {
+        Some(doc) => {
+            let first = match client.blob_stream(&doc).await {
+                Ok(mut stream) => match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        Ok(msg!(DOCTOR_READ_BYTES, count = chunk.len()))
+                    }
+                    Some(Err(e)) => Err(e),
+                    None => Ok(msg!(DOCTOR_EMPTY)),
+                },
+                Err(e) => Err(e),
+            };
+            checks.push(check("blob", client, first));
+        }
+        None => checks.push(skipped("blob", &msg!(DOCTOR_NO_DOCUMENT))),
+    }
+
+    if client.is_read_only() {
+        checks.push(skipped("writes", &msg!(DOCTOR_READ_ONLY)));
+    } else {
+        let writes = async {
+            client.upload_request(&[]).await?;
+            client.update_status(&[]).await?;
+            client.delete(&[]).await?;
+            Ok(String::new())
+        };
+        let writes = writes.await;
+        checks.push(check("writes", client, writes));
+    }
+    checks
+}
+
+/// The checks as a table, a row each.
+pub fn table(checks: &[Check]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<10}{:<15}{}",
+        msg!(DOCTOR_CHECK),
+        msg!(DOCTOR_RESULT),
+        msg!(DOCTOR_DETAIL)
+    )];
+    for c in checks {
+        let line =
+            format!("{:<10}{:<15}{}", c.name, c.verdict.label(), c.detail);
+        lines.push(line.trim_end().to_string());
+    }
+    lines
+}
+
+/// What to do about the problems found, a paragraph for each kind.
+pub fn guidance(checks: &[Check]) -> Vec<String> {
+    let found = |verdict| checks.iter().any(|c| c.verdict == verdict);
+    let mut advice = vec![];
+    if found(Verdict::Auth) {
+        advice.push(msg!(DOCTOR_AUTH_ADVICE));
+    }
+    if found(Verdict::Gone) {
+        advice.push(msg!(DOCTOR_GONE_ADVICE, url = MIGRATION_ISSUES_URL));
+    }
+    if found(Verdict::Drift) {
+        advice.push(msg!(DOCTOR_DRIFT_ADVICE));
+    }
+    if found(Verdict::Failed) {
+        advice.push(msg!(DOCTOR_FAILED_ADVICE));
+    }
+    advice
+}
+
+/// Runs the checks and reports the table of them to `out`, with what the
+/// cloud offers and advice on what failed, then fails if any found a
+/// problem.
+pub async fn doctor(
+    client: &mut Client,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let checks = run(client).await;
+    for line in table(&checks) {
+        out.line(&line);
+    }
+    // Known from the checks already, unless they all failed.
+    if let Ok(capabilities) = client.capabilities().await {
+        out.line("");
+        for line in status::capability_lines(&capabilities) {
+            out.line(&line);
+        }
+    }
+    for advice in guidance(&checks) {
+        out.line("");
+        out.line(&advice);
+    }
+    let problems = checks.iter().filter(|c| c.verdict.is_problem()).count();
+    if problems > 0 {
+        return Err(msg!(
+            DOCTOR_PROBLEMS,
+            problems = problems,
+            checks = checks.len(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use remarkable_cloud_api::testing::FakeCloud;
+
+    use super::*;
+
+    fn verdicts(checks: &[Check]) -> Vec<(&str, Verdict)> {
+        checks.iter().map(|c| (c.name, c.verdict)).collect()
+    }
+
+    #[tokio::test]
+    async fn failure_classes() {
+        let cloud = FakeCloud::start().await;
+        cloud.add_document("Dune", None, b"zip".to_vec());
+        let checks = run(&mut cloud.client()).await;
+        assert!(
+            checks.iter().all(|c| c.verdict == Verdict::Ok),
+            "{:?}",
+            checks
+        );
+        assert_eq!(checks[3].detail, "read the first 3 bytes");
+        assert!(guidance(&checks).is_empty());
+
+        let mut client = cloud.client();
+        client.set_read_only(true);
+        let checks = run(&mut client).await;
+        assert_eq!(checks[4].verdict, Verdict::Skipped);
+
+        cloud.set_listing_rewrite(|doc| {
+            doc["Tags"] = serde_json::json!([]);
+        });
+        let checks = run(&mut cloud.client()).await;
+        assert_eq!(
+            verdicts(&checks),
+            vec![
+                ("token", Verdict::Ok),
+                ("listing", Verdict::Drift),
+                ("document", Verdict::Drift),
+                ("blob", Verdict::Ok),
+                ("writes", Verdict::Ok),
+            ]
+        );
+        assert_eq!(checks[1].detail, "unknown \"Tags\" in 1 of 1");
+
+        cloud.break_endpoint("/document-storage/json/2/delete", 404);
+        let checks = run(&mut cloud.client()).await;
+        assert_eq!(checks[4].verdict, Verdict::Gone);
+
+        cloud.break_endpoint("/token/", 401);
+        let checks = run(&mut cloud.client()).await;
+        assert_eq!(checks[0].verdict, Verdict::Auth);
+        assert!(checks[1..].iter().all(|c| c.verdict == Verdict::Skipped));
+        let advice = guidance(&checks);
+        assert_eq!(advice.len(), 1);
+        assert!(advice[0].contains("Sign in again"));
+    }
+
+    #[test]
+    fn layout() {
+        let checks = vec![
+            Check {
+                name: "token",
+                verdict: Verdict::Ok,
+                detail: String::new(),
+            },
+            Check {
+                name: "listing",
+                verdict: Verdict::Drift,
+                detail: "unknown \"Tags\" in 2 of 2".to_string(),
+            },
+        ];
+        assert_eq!(
+            table(&checks),
+            vec![
+                "CHECK     RESULT         DETAIL",
+                "token     ok",
+                "listing   schema drift   unknown \"Tags\" in 2 of 2",
+            ]
+        );
+    }
+}
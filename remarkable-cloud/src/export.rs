@@ -0,0 +1,521 @@
+//! Writing out the document tree for other tools, as done by `export`: CSV
+//! for spreadsheets, OPML for outliners and Atom for feed readers. The
+//! documents themselves are exported by the `exporters`.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{
+    join_path, write_atomically, CloudPath, Document, Documents, DocumentsDiff,
+    Parent,
+};
+use remarkable_data_formats::timefmt;
+
+use crate::cache::ListingCache;
+use crate::columns::Column;
+use crate::help::Example;
+use crate::resolved::ResolvedTree;
+use crate::{locate, CliResult, Location};
+
+/// The examples `export --help` shows.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        command:
+            "remarkable-cloud export pdf -r Books -o ~/books --fallback zip",
+        description: "Writes the PDF of everything under Books, and the \
+                      archive of anything without one",
+    },
+    Example {
+        command: "remarkable-cloud export md Journal",
+        description: "Writes what's typed in a notebook as Markdown",
+    },
+    Example {
+        command: "remarkable-cloud export md --from-dir ~/backup.tar.zst",
+        description: "Writes the notebooks in a backup as Markdown, without \
+                      signing in",
+    },
+    Example {
+        command: "remarkable-cloud export csv > tree.csv",
+        description: "Writes a row per document and folder, for a spreadsheet",
+    },
+    Example {
+        command:
+            "remarkable-cloud export opml --include-trash Work > work.opml",
+        description:
+            "Writes the folders under Work, and the trash, as an outline",
+    },
+    Example {
+        command: "remarkable-cloud export feed -o ~/public/remarkable.xml",
+        description: "Writes a feed of what's new since the last run, for a \
+                      feed reader, say from cron",
+    },
+    Example {
+        command: "remarkable-cloud export feed --since work.json -o work.xml",
+        description: "Keeps a second feed with a state file of its own",
+    },
+    Example {
+        command: "remarkable-cloud export digest --since 7d -o weekly.pdf",
+        description: "Puts the pages tagged on the tablet this week together \
+                      into one PDF, in builds with the digest feature",
+    },
+];
+
+/// The formats `export` writes the tree out in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeFormat {
+    Csv,
+    Opml,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExportOptions {
+    /// Also write out what's in the trash.
+    pub include_trash: bool,
+}
+
+// Everything below `start`, each with its depth and its path from the root.
+// With `include_trash`, the trash follows, its paths starting at the trash.
+fn entries(
+    docs: &Documents,
+    start: Parent,
+    options: ExportOptions,
+) -> Vec<(bool, usize, String, &Document)> {
+    let prefix = match start {
+        Parent::Folder(id) => docs.path_of(&id),
+        Parent::Root | Parent::Trash => None,
+    };
+    let mut walks = vec![(false, start)];
+    if options.include_trash && start != Parent::Trash {
+        walks.push((true, Parent::Trash));
+    }
+    let mut entries = vec![];
+    for (trashed, parent) in walks {
+        let mut names: Vec<&str> = vec![];
+        for (depth, d) in docs.descendants(parent) {
+            names.truncate(depth);
+            names.push(&d.visible_name);
+            let path = match (&prefix, trashed) {
+                (Some(prefix), false) => {
+                    format!("{}/{}", prefix, join_path(&names))
+                }
+                _ => join_path(&names),
+            };
+            entries.push((trashed, depth, path, d));
+        }
+    }
+    entries
+}
+
+// Quotes a CSV field if it holds anything that would otherwise end it.
+pub(crate) fn csv_field(s: &str) -> String {
+    if s.contains(&[',', '"', '\n', '\r'][..])
+        || s.starts_with(' ')
+        || s.ends_with(' ')
+    {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// The columns of the CSV export, before `trashed`.
+const CSV_COLUMNS: [Column; 6] = [
+    Column::Path,
+    Column::Id,
+    Column::Type,
+    Column::Version,
+    Column::Modified,
+    Column::Bookmarked,
+];
+
+/// One row per document and folder below `start`, after a header row. A
+/// `trashed` column is added with `include_trash`.
+pub fn csv(
+    docs: &Documents,
+    start: Parent,
+    options: ExportOptions,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let names: Vec<&str> = CSV_COLUMNS.iter().map(|c| c.name()).collect();
+    let mut header = names.join(",");
+    if options.include_trash {
+        header.push_str(",trashed");
+    }
+    // RFC 4180 ends records with CRLF.
+    writeln!(out, "{}\r", header)?;
+    for (trashed, _, path, d) in entries(docs, start, options) {
+        let mut row: Vec<String> = CSV_COLUMNS
+            .iter()
+            .map(|c| csv_field(&c.text(&path, d)))
+            .collect();
+        if options.include_trash {
+            row.push(trashed.to_string());
+        }
+        writeln!(out, "{}\r", row.join(","))?;
+    }
+    Ok(())
+}
+
+fn xml_attr(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn outline(d: &Document) -> String {
+    format!(
+        "<outline text=\"{}\" type=\"{}\" id=\"{}\" version=\"{}\" modified=\"{}\"",
+        xml_attr(&d.visible_name),
+        if d.is_folder() {
+            "folder"
+        } else {
+            "document"
+        },
+        d.id,
+        d.version,
+        d.modified_client.to_rfc3339()
+    )
+}
+
+/// An OPML 2.0 outline of everything below `start`, folders holding their
+/// contents. With `include_trash`, the trash is a last outline of its own.
+pub fn opml(
+    docs: &Documents,
+    start: Parent,
+    options: ExportOptions,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <opml version=\"2.0\">\n  \
+         <head>\n    \
+         <title>reMarkable documents</title>\n  \
+         </head>\n  \
+         <body>"
+    )?;
+    let entries = entries(docs, start, options);
+    let mut in_trash = false;
+    let mut open = 0;
+    let mut iter = entries.iter().peekable();
+    while let Some((trashed, depth, _, d)) = iter.next() {
+        let depth = depth + *trashed as usize;
+        if *trashed && !in_trash {
+            for level in (0..open).rev() {
+                writeln!(out, "{}</outline>", "  ".repeat(level + 2))?;
+            }
+            writeln!(out, "    <outline text=\"Trash\" type=\"trash\">")?;
+            in_trash = true;
+            open = 1;
+        }
+        while open > depth {
+            open -= 1;
+            writeln!(out, "{}</outline>", "  ".repeat(open + 2))?;
+        }
+        let indent = "  ".repeat(depth + 2);
+        let next_depth = iter
+            .peek()
+            .filter(|(t, ..)| t == trashed)
+            .map(|(t, depth, ..)| depth + *t as usize);
+        if next_depth.is_some_and(|n| n > depth) {
+            writeln!(out, "{}{}>", indent, outline(d))?;
+            open += 1;
+        } else {
+            writeln!(out, "{}{}/>", indent, outline(d))?;
+        }
+    }
+    for level in (0..open).rev() {
+        writeln!(out, "{}</outline>", "  ".repeat(level + 2))?;
+    }
+    writeln!(out, "  </body>\n</opml>")
+}
+
+// The namespace of the feed's ids, which are tag URIs (RFC 4151).
+const TAG: &str = "tag:remarkable-cloud,2024:";
+
+/// An Atom feed with an entry for each document other than a folder added
+/// or updated in `diff`, `docs` being the listing it's from, newest first.
+/// An entry's id is made from the document's id and version, so it's the
+/// same whenever that version is written out. The feed is updated as of its
+/// newest entry, or `now` if it has none.
+pub fn atom(
+    docs: &Documents,
+    diff: &DocumentsDiff,
+    now: DateTime<Utc>,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let mut entries: Vec<(&str, &Document)> = diff
+        .added
+        .iter()
+        .map(|d| ("Added", *d))
+        .chain(diff.updated.iter().map(|d| ("Updated", *d)))
+        .filter(|(_, d)| !d.is_folder())
+        .collect();
+    entries.sort_by(|(_, a), (_, b)| {
+        (b.modified_client, a.id).cmp(&(a.modified_client, b.id))
+    });
+    let updated = entries.first().map_or(now, |(_, d)| d.modified_client);
+    writeln!(
+        out,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n  \
+         <id>{}feed</id>\n  \
+         <title>New on reMarkable</title>\n  \
+         <updated>{}</updated>\n  \
+         <author>\n    \
+         <name>reMarkable</name>\n  \
+         </author>",
+        TAG,
+        timefmt::to_rfc3339(&updated)
+    )?;
+    for (change, d) in entries {
+        let path = docs
+            .path_of(&d.id)
+            .unwrap_or_else(|| d.visible_name.to_string());
+        writeln!(
+            out,
+            "  <entry>\n    \
+             <id>{}{}/{}</id>\n    \
+             <title>{}</title>\n    \
+             <updated>{}</updated>\n    \
+             <content type=\"text\">{}, version {}</content>\n  \
+             </entry>",
+            TAG,
+            d.id,
+            d.version,
+            xml_attr(&path),
+            timefmt::to_rfc3339(&d.modified_client),
+            change,
+            d.version
+        )?;
+    }
+    writeln!(out, "</feed>")
+}
+
+/// Writes out everything below `path` in `format` to `out`, as `export csv`
+/// and `export opml` do.
+pub fn tree(
+    documents: &ResolvedTree,
+    path: &CloudPath,
+    format: TreeFormat,
+    options: ExportOptions,
+    out: &mut dyn Write,
+) -> CliResult<()> {
+    let start = match locate(documents, path)? {
+        Location::Root => Parent::Root,
+        Location::Trash => Parent::Trash,
+        Location::Document(d) => Parent::Folder(d.id),
+        Location::Missing(e) => return Err(e.into()),
+    };
+    match format {
+        TreeFormat::Csv => csv(documents, start, options, out)?,
+        TreeFormat::Opml => opml(documents, start, options, out)?,
+    }
+    Ok(())
+}
+
+/// Writes a feed of what's been added or updated in `documents` since the
+/// listing `snapshot` holds, to `output` or else `stdout`, as `export feed`
+/// does. Only then is `documents` saved in `snapshot`, so that a run which
+/// fails is repeated.
+pub fn feed(
+    documents: &Documents,
+    snapshot: &ListingCache,
+    output: Option<&Path>,
+    stdout: &mut dyn Write,
+) -> CliResult<()> {
+    let earlier = snapshot.load().unwrap_or_else(|| documents.clone());
+    let diff = documents.diff(&earlier);
+    let mut feed = vec![];
+    atom(documents, &diff, Utc::now(), &mut feed)?;
+    match output {
+        Some(output) => write_atomically(output, &feed)?,
+        None => stdout.write_all(&feed)?,
+    }
+    snapshot.save(documents)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    fn docs() -> Documents {
+        let docs = listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+            (3, "Sci-fi", Some(1), "CollectionType"),
+            (4, "Hyperion", Some(3), "DocumentType"),
+            (5, "Notes", None, "DocumentType"),
+        ]);
+        let old = listing(&[(6, "Old <draft>", None, "DocumentType")]);
+        let mut trashed = serde_json::to_value(&old).unwrap()[0].clone();
+        trashed["Parent"] = "trash".into();
+        let mut entries = serde_json::to_value(&docs).unwrap();
+        entries.as_array_mut().unwrap().push(trashed);
+        serde_json::from_value(entries).unwrap()
+    }
+
+    fn to_string(
+        f: fn(
+            &Documents,
+            Parent,
+            ExportOptions,
+            &mut dyn Write,
+        ) -> io::Result<()>,
+        docs: &Documents,
+        start: Parent,
+        include_trash: bool,
+    ) -> String {
+        let mut out = vec![];
+        f(docs, start, ExportOptions { include_trash }, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("Dune"), "Dune");
+        assert_eq!(csv_field("Dune, Part 1"), "\"Dune, Part 1\"");
+        assert_eq!(csv_field("The \"Dune\""), "\"The \"\"Dune\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_field(" padded"), "\" padded\"");
+        assert_eq!(csv_field(""), "");
+
+        let docs =
+            listing(&[(1, "Dune, \"Part 1\"\nnotes", None, "DocumentType")]);
+        let csv = to_string(csv, &docs, Parent::Root, false);
+        let id = Uuid::from_u128(1);
+        assert_eq!(
+            csv,
+            format!(
+                "path,id,type,version,modified,bookmarked\r\n\
+                 \"Dune, \"\"Part 1\"\"\nnotes\",{},DocumentType,1,\
+                 2024-01-01T00:00:00+00:00,false\r\n",
+                id
+            )
+        );
+    }
+
+    #[test]
+    fn csv_rows() {
+        let docs = docs();
+        let rows = to_string(csv, &docs, Parent::Root, false);
+        let paths: Vec<&str> = rows
+            .split("\r\n")
+            .skip(1)
+            .filter(|l| !l.is_empty())
+            .map(|l| l.split(',').next().unwrap())
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "Books",
+                "Books/Dune",
+                "Books/Sci-fi",
+                "Books/Sci-fi/Hyperion",
+                "Notes"
+            ]
+        );
+
+        let books =
+            to_string(csv, &docs, Parent::Folder(Uuid::from_u128(1)), true);
+        let lines: Vec<&str> = books.split("\r\n").collect();
+        assert!(lines[0].ends_with(",bookmarked,trashed"));
+        assert!(lines[1].starts_with("Books/Dune,"));
+        assert!(lines[1].ends_with(",false"));
+        assert!(lines[4].starts_with("Old <draft>,"));
+        assert!(lines[4].ends_with(",true"));
+    }
+
+    #[test]
+    fn opml_snapshot() {
+        let docs = docs();
+        let outline = to_string(opml, &docs, Parent::Root, true);
+        let attrs = |id: u128, name: &str, kind: &str| {
+            format!(
+                "text=\"{}\" type=\"{}\" id=\"{}\" version=\"1\" \
+                 modified=\"2024-01-01T00:00:00+00:00\"",
+                name,
+                kind,
+                Uuid::from_u128(id)
+            )
+        };
+        let expected = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>reMarkable documents</title>
+  </head>
+  <body>
+    <outline {}>
+      <outline {}/>
+      <outline {}>
+        <outline {}/>
+      </outline>
+    </outline>
+    <outline {}/>
+    <outline text="Trash" type="trash">
+      <outline {}/>
+    </outline>
+  </body>
+</opml>
+"#,
+            attrs(1, "Books", "folder"),
+            attrs(2, "Dune", "document"),
+            attrs(3, "Sci-fi", "folder"),
+            attrs(4, "Hyperion", "document"),
+            attrs(5, "Notes", "document"),
+            attrs(6, "Old &lt;draft&gt;", "document"),
+        );
+        assert_eq!(outline, expected);
+
+        let empty = to_string(opml, &Documents::default(), Parent::Root, false);
+        assert!(empty.ends_with("  <body>\n  </body>\n</opml>\n"));
+    }
+
+    #[test]
+    fn atom_golden() {
+        let mut current = serde_json::to_value(docs()).unwrap();
+        for entry in current.as_array_mut().unwrap() {
+            if entry["ID"] == Uuid::from_u128(2).to_string() {
+                entry["Version"] = 2.into();
+                entry["VissibleName"] = "Dune & <Messiah>".into();
+                entry["ModifiedClient"] = "2024-02-01T10:00:00.5Z".into();
+            }
+        }
+        let current: Documents = serde_json::from_value(current).unwrap();
+        let mut earlier = docs();
+        for id in &[3, 4, 5] {
+            earlier.remove(&Uuid::from_u128(*id));
+        }
+        let now = timefmt::parse("2024-03-01T00:00:00Z").unwrap();
+        let mut out = vec![];
+        atom(&current, &current.diff(&earlier), now, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            include_str!("../tests/fixtures/feed.atom")
+        );
+
+        let mut out = vec![];
+        atom(&current, &current.diff(&current), now, &mut out).unwrap();
+        let empty = String::from_utf8(out).unwrap();
+        assert!(empty.contains("<updated>2024-03-01T00:00:00Z</updated>"));
+        assert!(!empty.contains("<entry>"));
+    }
+}
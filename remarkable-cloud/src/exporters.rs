@@ -0,0 +1,852 @@
+//! Writing documents out in other formats, for `export <format>` and
+//! `pull --format`, from the cloud or from archives saved locally.
+//!
+//! Each format is an [`Exporter`], and [`EXPORTERS`] lists them all. An
+//! exporter is handed a document with its archive, as a [`Source`], and
+//! writes what it makes to a [`Sink`]. Not every format suits every
+//! document, so an export can name a second format to fall back on for
+//! those the first doesn't suit, as `--fallback zip` keeps the archive of
+//! a notebook asked for as a PDF.
+//!
+//! Exporters can't yet draw what's written on a PDF over its pages, or
+//! render pages as images, so there's no annotated PDF or PNG among them.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use remarkable_cloud_api::{
+    highlights, render_ink_only, render_ink_pdf, split_path, typed_text,
+    Client, CloudPath, Document, DocumentDetails, Parent,
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::backup;
+use crate::commands::Output;
+use crate::export::csv_field;
+use crate::observer::Event;
+use crate::resolved::ResolvedTree;
+use crate::template::NameRegistry;
+use crate::{error_category, locate, msg, CliResult, Location};
+
+/// A document to export, with the archive it's in.
+pub struct Source {
+    /// What's known of the document. Anything its archive describes which
+    /// couldn't be read is left out.
+    pub details: DocumentDetails,
+    pub archive: Vec<u8>,
+}
+
+impl Source {
+    pub fn new(document: Document, archive: Vec<u8>) -> Self {
+        let details = DocumentDetails::from_archive(document.clone(), &archive)
+            .unwrap_or_else(|_| DocumentDetails {
+                document,
+                metadata: None,
+                content: None,
+                page_count: None,
+                stroke_count: None,
+                blob_size: archive.len() as u64,
+            });
+        Source { details, archive }
+    }
+
+    /// The archive of a document saved locally, described as
+    /// `DocumentDetails::from_local_archive` has it.
+    pub fn from_local(archive: Vec<u8>, name: &str) -> CliResult<Self> {
+        let details = DocumentDetails::from_local_archive(&archive, name)?;
+        Ok(Source { details, archive })
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.details.document.id
+    }
+
+    // The names of the files in the archive, none if it can't be read.
+    fn entries(&self) -> Vec<String> {
+        zip::ZipArchive::new(io::Cursor::new(&self.archive))
+            .map(|za| za.file_names().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    // The first file in the archive whose name ends with `suffix`.
+    fn entry_ending(&self, suffix: &str) -> Option<String> {
+        self.entries().into_iter().find(|n| n.ends_with(suffix))
+    }
+
+    fn read(&self, name: &str) -> CliResult<Vec<u8>> {
+        let mut za = zip::ZipArchive::new(io::Cursor::new(&self.archive))?;
+        let mut data = vec![];
+        za.by_name(name)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    // Whether the archive has highlights on any page.
+    fn has_highlights(&self) -> bool {
+        let folder = format!("{}.highlights/", self.id());
+        self.entries().iter().any(|n| n.starts_with(&folder))
+    }
+}
+
+/// One file made by an exporter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExportFile {
+    /// The extension the file is named with.
+    pub ext: String,
+    /// For a format with a file per page, the page's number, counting from
+    /// 1, and how many pages there are.
+    pub page: Option<(usize, usize)>,
+    pub data: Vec<u8>,
+}
+
+/// Where an exporter writes the files it makes.
+pub trait Sink {
+    fn write(&mut self, file: ExportFile) -> CliResult<()>;
+}
+
+/// Keeps the files in memory.
+impl Sink for Vec<ExportFile> {
+    fn write(&mut self, file: ExportFile) -> CliResult<()> {
+        self.push(file);
+        Ok(())
+    }
+}
+
+/// What an exporter wrote of one document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExportReport {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl ExportReport {
+    /// Writes `file` to `sink`, counting it.
+    pub fn write(
+        &mut self,
+        sink: &mut dyn Sink,
+        file: ExportFile,
+    ) -> CliResult<()> {
+        self.files += 1;
+        self.bytes += file.data.len() as u64;
+        sink.write(file)
+    }
+}
+
+/// A format documents can be exported in.
+pub trait Exporter {
+    /// The name it's asked for by, as in `export pdf`.
+    fn name(&self) -> &'static str;
+
+    /// What it writes, for `export --help`.
+    fn about(&self) -> &'static str;
+
+    /// Whether it can make anything of `source`.
+    fn supports(&self, source: &Source) -> bool;
+
+    /// Writes `source` to `sink`.
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport>;
+}
+
+/// Every format, in the order `export --help` lists them.
+pub const EXPORTERS: &[&dyn Exporter] = &[
+    &Original {
+        name: "pdf",
+        about: "Writes the PDF a document was made from",
+    },
+    &Original {
+        name: "epub",
+        about: "Writes the EPUB a document was made from",
+    },
+    &Zip,
+    &InkSvg,
+    &InkPdf,
+    &Markdown,
+    &CsvHighlights,
+];
+
+/// The exporter named `name`.
+pub fn find(name: &str) -> Option<&'static dyn Exporter> {
+    EXPORTERS.iter().copied().find(|e| e.name() == name)
+}
+
+/// The names of the exporters.
+pub fn names() -> Vec<&'static str> {
+    EXPORTERS.iter().map(|e| e.name()).collect()
+}
+
+/// The exporter to write `source` with: `preferred` if it supports it, or
+/// else `fallback`, if there is one and it does.
+pub fn negotiate<'a>(
+    preferred: &'a dyn Exporter,
+    fallback: Option<&'a dyn Exporter>,
+    source: &Source,
+) -> Option<&'a dyn Exporter> {
+    if preferred.supports(source) {
+        return Some(preferred);
+    }
+    fallback.filter(|f| f.supports(source))
+}
+
+/// The PDF or EPUB a document was made from, as it was pushed.
+pub struct Original {
+    /// Also the extension of the file in the archive.
+    name: &'static str,
+    about: &'static str,
+}
+
+impl Exporter for Original {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn about(&self) -> &'static str {
+        self.about
+    }
+
+    fn supports(&self, source: &Source) -> bool {
+        source.entry_ending(&format!(".{}", self.name)).is_some()
+    }
+
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport> {
+        let mut report = ExportReport::default();
+        if let Some(name) = source.entry_ending(&format!(".{}", self.name)) {
+            let file = ExportFile {
+                ext: self.name.to_string(),
+                page: None,
+                data: source.read(&name)?,
+            };
+            report.write(sink, file)?;
+        }
+        Ok(report)
+    }
+}
+
+/// The archive as the cloud has it.
+pub struct Zip;
+
+impl Exporter for Zip {
+    fn name(&self) -> &'static str {
+        "zip"
+    }
+
+    fn about(&self) -> &'static str {
+        "Writes the archive of a document as the cloud has it"
+    }
+
+    fn supports(&self, _: &Source) -> bool {
+        true
+    }
+
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport> {
+        let mut report = ExportReport::default();
+        let file = ExportFile {
+            ext: "zip".to_string(),
+            page: None,
+            data: source.archive.clone(),
+        };
+        report.write(sink, file)?;
+        Ok(report)
+    }
+}
+
+fn has_pages(source: &Source) -> bool {
+    source.details.page_count.unwrap_or(0) > 0
+}
+
+/// What's drawn on each page, by itself, as an SVG per page; see
+/// `render_ink_only`.
+pub struct InkSvg;
+
+impl Exporter for InkSvg {
+    fn name(&self) -> &'static str {
+        "svg"
+    }
+
+    fn about(&self) -> &'static str {
+        "Writes what's drawn on each page, without the page behind it, as an \
+         SVG per page"
+    }
+
+    fn supports(&self, source: &Source) -> bool {
+        has_pages(source)
+    }
+
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport> {
+        let mut report = ExportReport::default();
+        let pages = render_ink_only(source.id(), &source.archive)?;
+        let count = pages.len();
+        for (n, svg) in pages.into_iter().enumerate() {
+            let file = ExportFile {
+                ext: "svg".to_string(),
+                page: Some((n + 1, count)),
+                data: svg.into_bytes(),
+            };
+            report.write(sink, file)?;
+        }
+        Ok(report)
+    }
+}
+
+/// What's drawn on the pages, by itself, as a PDF; see `render_ink_pdf`.
+pub struct InkPdf;
+
+impl Exporter for InkPdf {
+    fn name(&self) -> &'static str {
+        "ink-pdf"
+    }
+
+    fn about(&self) -> &'static str {
+        "Writes what's drawn on the pages, without the pages behind it, as a \
+         PDF"
+    }
+
+    fn supports(&self, source: &Source) -> bool {
+        has_pages(source)
+    }
+
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport> {
+        let mut report = ExportReport::default();
+        let file = ExportFile {
+            ext: "pdf".to_string(),
+            page: None,
+            data: render_ink_pdf(source.id(), &source.archive)?,
+        };
+        report.write(sink, file)?;
+        Ok(report)
+    }
+}
+
+/// The text typed on a notebook's pages and highlighted in a book's, as
+/// Markdown with a section per page.
+pub struct Markdown;
+
+impl Exporter for Markdown {
+    fn name(&self) -> &'static str {
+        "md"
+    }
+
+    fn about(&self) -> &'static str {
+        "Writes the text typed in a notebook, or highlighted in a PDF or \
+         EPUB, as Markdown with a section per page"
+    }
+
+    fn supports(&self, source: &Source) -> bool {
+        source.details.is_notebook() || source.has_highlights()
+    }
+
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport> {
+        let typed = typed_text(source.id(), &source.archive)?;
+        let highlighted = highlights(source.id(), &source.archive)?;
+        let mut md = format!("# {}\n", source.details.document.visible_name);
+        for (index, paragraphs) in typed.iter().enumerate() {
+            let marked = highlighted
+                .iter()
+                .find(|(i, _)| *i == index)
+                .map_or(&[][..], |(_, h)| h.as_slice());
+            if paragraphs.is_empty() && marked.is_empty() {
+                continue;
+            }
+            md.push_str(&format!("\n## Page {}\n", index + 1));
+            for paragraph in paragraphs.iter().filter(|p| !p.is_empty()) {
+                md.push_str(&format!("\n{}\n", paragraph));
+            }
+            for highlight in marked {
+                md.push_str(&format!("\n> {}\n", highlight.text));
+            }
+        }
+        let mut report = ExportReport::default();
+        let file = ExportFile {
+            ext: "md".to_string(),
+            page: None,
+            data: md.into_bytes(),
+        };
+        report.write(sink, file)?;
+        Ok(report)
+    }
+}
+
+/// The text highlighted in a PDF or EPUB, as CSV with a row per highlight.
+pub struct CsvHighlights;
+
+impl Exporter for CsvHighlights {
+    fn name(&self) -> &'static str {
+        "csv-highlights"
+    }
+
+    fn about(&self) -> &'static str {
+        "Writes the text highlighted in a PDF or EPUB as CSV, a row per \
+         highlight with its page and color"
+    }
+
+    fn supports(&self, source: &Source) -> bool {
+        source.has_highlights()
+    }
+
+    fn export(
+        &self,
+        source: &Source,
+        sink: &mut dyn Sink,
+    ) -> CliResult<ExportReport> {
+        let mut csv = "page,color,text\n".to_string();
+        for (index, on_page) in highlights(source.id(), &source.archive)? {
+            for highlight in on_page {
+                csv.push_str(&format!(
+                    "{},{},{}\n",
+                    index + 1,
+                    highlight.color,
+                    csv_field(&highlight.text)
+                ));
+            }
+        }
+        let mut report = ExportReport::default();
+        let file = ExportFile {
+            ext: "csv".to_string(),
+            page: None,
+            data: csv.into_bytes(),
+        };
+        report.write(sink, file)?;
+        Ok(report)
+    }
+}
+
+/// What `export` exports, and how.
+pub struct ExportJob<'a> {
+    pub exporter: &'a dyn Exporter,
+    /// The format for documents `exporter` doesn't support, which are
+    /// skipped without one.
+    pub fallback: Option<&'a dyn Exporter>,
+    /// The documents to export, or with `recursive` folders to export
+    /// everything in.
+    pub paths: Vec<CloudPath>,
+    /// Whether to export what's in the folders among `paths`, into
+    /// directories of the same names.
+    pub recursive: bool,
+    /// Where the files are written; the current directory if empty.
+    pub dir: PathBuf,
+}
+
+/// Downloads the documents `job` picks out and writes each in its format,
+/// reporting each to `out` as `pull` does. Documents which can't be
+/// found, or which neither format suits, are skipped; the first which
+/// fails to download or export stops the job.
+pub async fn export(
+    client: &Client,
+    documents: &ResolvedTree,
+    job: &ExportJob<'_>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut found: Vec<(&Document, PathBuf)> = vec![];
+    for path in &job.paths {
+        match locate(documents, path)? {
+            Location::Document(d) if d.is_folder() => {
+                if !job.recursive {
+                    out.line(&msg!(EXPORT_FOLDER, path = path.to_string()));
+                    continue;
+                }
+                found.extend(folder_exports(documents, d));
+            }
+            Location::Document(d) => found.push((d, PathBuf::new())),
+            Location::Root | Location::Trash => {
+                out.line(&msg!(NOT_A_DOCUMENT, path = path.to_string()))
+            }
+            Location::Missing(e) => out.line(&e.to_string()),
+        }
+    }
+    let mut names = NameRegistry::new();
+    for (doc, subdir) in found {
+        export_document(client, documents, job, doc, &subdir, &mut names, out)
+            .await?;
+    }
+    Ok(())
+}
+
+// Everything below the folder `folder`, each with the directory it goes
+// in: one named after the folder, and below it after the folders inside.
+fn folder_exports<'a>(
+    documents: &'a ResolvedTree,
+    folder: &'a Document,
+) -> Vec<(&'a Document, PathBuf)> {
+    let mut folders: Vec<&str> = vec![&folder.visible_name];
+    let mut found = vec![];
+    for (depth, d) in documents.descendants(Parent::Folder(folder.id)) {
+        folders.truncate(depth + 1);
+        if d.is_folder() {
+            folders.push(&d.visible_name);
+        } else {
+            found.push((d, folders.iter().collect()));
+        }
+    }
+    found
+}
+
+// The name a file of `doc` is written under.
+fn file_name(doc: &Document, file: &ExportFile) -> String {
+    let stem = doc.visible_name.replace('/', "_");
+    match file.page {
+        Some((n, count)) => {
+            let width = count.to_string().len();
+            format!("{}-{:0width$}.{}", stem, n, file.ext, width = width)
+        }
+        None => format!("{}.{}", stem, file.ext),
+    }
+}
+
+async fn export_document(
+    client: &Client,
+    documents: &ResolvedTree,
+    job: &ExportJob<'_>,
+    doc: &Document,
+    subdir: &Path,
+    names: &mut NameRegistry,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let start = Instant::now();
+    let path = PathBuf::from(
+        documents
+            .path_of(&doc.id)
+            .unwrap_or_else(|| doc.visible_name.to_string()),
+    );
+    out.observe(&Event::Started { path: path.clone() });
+    let fetched = async {
+        let current = client.get_document_by_id(&doc.id).await?;
+        let archive = client.download_blob(&current).await?;
+        CliResult::Ok(Source::new(current, archive))
+    };
+    match fetched.await {
+        Ok(source) => {
+            export_source(job, doc, &source, &path, subdir, names, out, start)
+        }
+        Err(e) => Err(failed(out, &path, Some(doc.id), e, start)),
+    }
+}
+
+// Reports `e` as what stopped the document at `path`, giving it back.
+fn failed(
+    out: &mut dyn Output,
+    path: &Path,
+    id: Option<Uuid>,
+    e: Box<dyn std::error::Error>,
+    start: Instant,
+) -> Box<dyn std::error::Error> {
+    out.observe(&Event::Failed {
+        path: path.to_path_buf(),
+        id,
+        category: error_category(&*e),
+        message: e.to_string(),
+        elapsed: start.elapsed(),
+    });
+    e
+}
+
+// Writes `source`, the archive of `doc`, as `job` asks, into `subdir` of
+// its directory. `path` is what it's reported as.
+#[allow(clippy::too_many_arguments)]
+fn export_source(
+    job: &ExportJob<'_>,
+    doc: &Document,
+    source: &Source,
+    path: &Path,
+    subdir: &Path,
+    names: &mut NameRegistry,
+    out: &mut dyn Output,
+    start: Instant,
+) -> CliResult<()> {
+    let skipped = |out: &mut dyn Output, reason: String| {
+        out.note(&reason);
+        out.observe(&Event::Skipped {
+            path: path.to_path_buf(),
+            id: Some(doc.id),
+            reason,
+        });
+    };
+    let exporter = negotiate(job.exporter, job.fallback, source);
+    let mut files = vec![];
+    if let Some(exporter) = exporter {
+        if let Err(e) = exporter.export(source, &mut files) {
+            return Err(failed(out, path, Some(doc.id), e, start));
+        }
+    }
+    let exporter = match exporter {
+        Some(exporter) => exporter,
+        None => {
+            let reason = match job.fallback {
+                Some(f) => msg!(
+                    EXPORT_NEITHER,
+                    path = path.display().to_string(),
+                    format = job.exporter.name(),
+                    fallback = f.name(),
+                ),
+                None => msg!(
+                    EXPORT_UNSUPPORTED,
+                    path = path.display().to_string(),
+                    format = job.exporter.name(),
+                ),
+            };
+            skipped(out, reason);
+            return Ok(());
+        }
+    };
+    if exporter.name() != job.exporter.name() {
+        out.note(&msg!(
+            EXPORT_FELL_BACK,
+            path = path.display().to_string(),
+            format = job.exporter.name(),
+            fallback = exporter.name(),
+        ));
+    }
+    if files.is_empty() {
+        let path = path.display().to_string();
+        skipped(out, msg!(EXPORT_NOTHING, path = path));
+        return Ok(());
+    }
+    for file in files {
+        let output = subdir.join(file_name(doc, &file));
+        if let Err(reason) = names.claim(&output.to_string_lossy()) {
+            skipped(out, reason);
+            continue;
+        }
+        let output = job.dir.join(output);
+        let written = output
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&output, &file.data));
+        if let Err(e) = written {
+            return Err(failed(out, path, Some(doc.id), e.into(), start));
+        }
+        out.observe(&Event::Pulled {
+            path: path.to_path_buf(),
+            id: doc.id,
+            output,
+            modified: doc.modified_client,
+            bytes: file.data.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(&file.data)),
+            elapsed: start.elapsed(),
+        });
+    }
+    Ok(())
+}
+
+/// Where `export --from-zip` and `--from-dir` find archives.
+#[derive(Clone, Debug, Default)]
+pub struct LocalArchives {
+    /// Archives of single documents.
+    pub zips: Vec<PathBuf>,
+    /// A directory of archives, as `pull --raw-zip` saves them, or a
+    /// backup written by `backup`.
+    pub dir: Option<PathBuf>,
+}
+
+/// Writes each archive `from` names in the format `job` asks for, as
+/// `export` does documents in the cloud, without needing the cloud: what
+/// the listing would say is read from the archive, or for a backup from
+/// its own description of the document. Archives in a directory, or a
+/// backup, are written into the directories of the same names as the ones
+/// they're in.
+pub fn export_local(
+    job: &ExportJob<'_>,
+    from: &LocalArchives,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut names = NameRegistry::new();
+    let mut archives: Vec<(PathBuf, PathBuf)> = from
+        .zips
+        .iter()
+        .map(|z| (z.clone(), PathBuf::new()))
+        .collect();
+    match &from.dir {
+        Some(dir) if dir.is_dir() => archives.extend(archives_below(dir)?),
+        Some(backup) => {
+            return backup::read_documents(backup, |entry, archive| {
+                let path = PathBuf::from(&entry.path);
+                let mut subdir: PathBuf =
+                    split_path(&entry.path)?.into_iter().collect();
+                subdir.pop();
+                export_archive(
+                    job,
+                    archive,
+                    &entry.visible_name,
+                    &path,
+                    &subdir,
+                    &mut names,
+                    out,
+                )
+            });
+        }
+        None => (),
+    }
+    for (zip, subdir) in archives {
+        let name = zip
+            .file_stem()
+            .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        let archive = fs::read(&zip)?;
+        export_archive(job, archive, &name, &zip, &subdir, &mut names, out)?;
+    }
+    Ok(())
+}
+
+// The `.zip` files anywhere below `dir`, each with the directory it's in
+// relative to `dir`, in order of their paths.
+fn archives_below(dir: &Path) -> CliResult<Vec<(PathBuf, PathBuf)>> {
+    let mut found = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(subdir) = dirs.pop() {
+        for entry in fs::read_dir(dir.join(&subdir))? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                dirs.push(subdir.join(entry.file_name()));
+            } else if path.extension().is_some_and(|e| e == "zip") {
+                found.push((path, subdir.clone()));
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+// Exports one local archive, named `name` if it doesn't say, reported as
+// `path`.
+fn export_archive(
+    job: &ExportJob<'_>,
+    archive: Vec<u8>,
+    name: &str,
+    path: &Path,
+    subdir: &Path,
+    names: &mut NameRegistry,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let start = Instant::now();
+    out.observe(&Event::Started {
+        path: path.to_path_buf(),
+    });
+    let source = match Source::from_local(archive, name) {
+        Ok(source) => source,
+        Err(e) => return Err(failed(out, path, None, e, start)),
+    };
+    let doc = &source.details.document;
+    export_source(job, doc, &source, path, subdir, names, out, start)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    fn source(files: &[(&str, &[u8])]) -> Source {
+        let id = Uuid::from_u128(1);
+        let mut za = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in files {
+            let name = name.replace("{id}", &id.to_string());
+            za.start_file(name, Default::default()).unwrap();
+            za.write_all(data).unwrap();
+        }
+        let archive = za.finish().unwrap().into_inner();
+        let doc = listing(&[(1, "Dune", None, "DocumentType")])
+            .get(&id)
+            .unwrap()
+            .clone();
+        Source::new(doc, archive)
+    }
+
+    fn exported(name: &str, source: &Source) -> Vec<ExportFile> {
+        let mut files = vec![];
+        let report = find(name).unwrap().export(source, &mut files).unwrap();
+        assert_eq!(report.files, files.len());
+        files
+    }
+
+    #[test]
+    fn originals_and_fallback() {
+        let book = source(&[
+            ("{id}.content", br#"{"fileType": "epub"}"#),
+            ("{id}.epub", b"epub"),
+        ]);
+        let pdf = find("pdf").unwrap();
+        let zip = find("zip").unwrap();
+        assert!(!pdf.supports(&book));
+        assert!(find("epub").unwrap().supports(&book));
+        assert!(negotiate(pdf, None, &book).is_none());
+        assert_eq!(negotiate(pdf, Some(zip), &book).unwrap().name(), "zip");
+        assert_eq!(exported("epub", &book)[0].data, b"epub");
+        assert_eq!(exported("zip", &book)[0].data, book.archive);
+
+        // Older archives may have no .content at all.
+        let bare = source(&[("p/p.pdf", b"pdf")]);
+        assert_eq!(negotiate(pdf, Some(zip), &bare).unwrap().name(), "pdf");
+        assert!(!find("svg").unwrap().supports(&bare));
+    }
+
+    #[test]
+    fn highlights() {
+        let book = source(&[
+            (
+                "{id}.content",
+                br#"{"fileType": "epub", "pages": ["a", "b"]}"#,
+            ),
+            (
+                "{id}.highlights/b.json",
+                br#"{"highlights": [[{"text": "fear, is", "color": 3}]]}"#,
+            ),
+        ]);
+        assert!(find("csv-highlights").unwrap().supports(&book));
+        let csv = exported("csv-highlights", &book);
+        assert_eq!(csv[0].ext, "csv");
+        assert_eq!(
+            String::from_utf8_lossy(&csv[0].data),
+            "page,color,text\n2,3,\"fear, is\"\n"
+        );
+        let md = exported("md", &book);
+        assert_eq!(
+            String::from_utf8_lossy(&md[0].data),
+            "# Dune\n\n## Page 2\n\n> fear, is\n"
+        );
+
+        let plain = source(&[("{id}.content", br#"{"fileType": "pdf"}"#)]);
+        assert!(!find("csv-highlights").unwrap().supports(&plain));
+        assert!(!find("md").unwrap().supports(&plain));
+    }
+
+    #[test]
+    fn names() {
+        let doc = listing(&[(1, "A/B", None, "DocumentType")])
+            .get(&Uuid::from_u128(1))
+            .unwrap()
+            .clone();
+        let file = |page| ExportFile {
+            ext: "svg".to_string(),
+            page,
+            data: vec![],
+        };
+        assert_eq!(file_name(&doc, &file(None)), "A_B.svg");
+        assert_eq!(file_name(&doc, &file(Some((3, 12)))), "A_B-03.svg");
+        assert_eq!(super::names().len(), EXPORTERS.len());
+    }
+}
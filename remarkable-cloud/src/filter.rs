@@ -0,0 +1,146 @@
+//! Filters on document metadata shared by the commands that select documents.
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use remarkable_cloud_api::Document;
+
+use crate::msg;
+
+/// Parses a point in time given on the command line: an RFC 3339 timestamp,
+/// a date (taken as midnight UTC), or a duration such as "2 weeks ago".
+pub fn parse_time(
+    s: &str,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Utc));
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(Utc.from_utc_datetime(&d.and_hms_opt(0, 0, 0).unwrap()));
+    }
+    match s.strip_suffix("ago") {
+        Some(ago) => {
+            let duration =
+                humantime::parse_duration(ago.trim()).map_err(|e| {
+                    msg!(
+                        DURATION_INVALID,
+                        duration = format!("{:?}", ago),
+                        error = e.to_string(),
+                    )
+                })?;
+            chrono::Duration::from_std(duration)
+                .ok()
+                .and_then(|d| now.checked_sub_signed(d))
+                .ok_or_else(|| {
+                    msg!(TIME_TOO_FAR_BACK, time = format!("{:?}", s))
+                })
+        }
+        None => Err(msg!(TIME_INVALID, time = format!("{:?}", s))),
+    }
+}
+
+/// A set of conditions on document metadata, all of which must hold.
+#[derive(Debug, Default)]
+pub struct DocumentFilter {
+    pub modified_after: Option<DateTime<Utc>>,
+    pub modified_before: Option<DateTime<Utc>>,
+}
+
+impl DocumentFilter {
+    /// The arguments `from_matches` reads, for adding to a subcommand.
+    pub fn args() -> Vec<clap::Arg<'static, 'static>> {
+        vec![
+            clap::Arg::with_name("modified-after")
+                .long("modified-after")
+                .value_name("time")
+                .takes_value(true)
+                .validator(|s| parse_time(&s, Utc::now()).map(|_| ()))
+                .help("Only documents modified after the given date or duration ago, e.g. 2024-01-01 or \"2 weeks ago\""),
+            clap::Arg::with_name("modified-before")
+                .long("modified-before")
+                .value_name("time")
+                .takes_value(true)
+                .validator(|s| parse_time(&s, Utc::now()).map(|_| ()))
+                .help("Only documents modified before the given date or duration ago"),
+        ]
+    }
+
+    pub fn from_matches(
+        matches: &clap::ArgMatches,
+        now: DateTime<Utc>,
+    ) -> Result<Self, String> {
+        let time = |name| matches.value_of(name).map(|s| parse_time(s, now));
+
+        Ok(DocumentFilter {
+            modified_after: time("modified-after").transpose()?,
+            modified_before: time("modified-before").transpose()?,
+        })
+    }
+
+    pub fn matches(&self, doc: &Document) -> bool {
+        self.modified_after.is_none_or(|t| doc.modified_client > t)
+            && self.modified_before.is_none_or(|t| doc.modified_client < t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn times() {
+        let now = at("2024-03-15T12:00:00Z");
+        assert_eq!(
+            parse_time("2024-01-01", now),
+            Ok(at("2024-01-01T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_time("2024-01-01T08:30:00+02:00", now),
+            Ok(at("2024-01-01T06:30:00Z"))
+        );
+        assert_eq!(
+            parse_time("2 weeks ago", now),
+            Ok(at("2024-03-01T12:00:00Z"))
+        );
+        assert_eq!(parse_time("36h ago", now), Ok(at("2024-03-14T00:00:00Z")));
+        assert!(parse_time("yesterday", now).is_err());
+        assert!(parse_time("2 fortnights ago", now).is_err());
+        assert!(parse_time("2024-13-01", now).is_err());
+    }
+
+    #[test]
+    fn composition() {
+        let docs: remarkable_cloud_api::Documents = serde_json::from_str(
+            r#"[{"ID": "00000000-0000-0000-0000-000000000001", "Version": 1,
+                 "VissibleName": "Doc", "Type": "DocumentType", "Parent": "",
+                 "CurrentPage": 0, "Bookmarked": false, "Message": "",
+                 "ModifiedClient": "2024-02-01T00:00:00Z", "BlobURLGet": "",
+                 "BlobURLGetExpires": "0001-01-01T00:00:00Z"}]"#,
+        )
+        .unwrap();
+        let doc = docs.iter().next().unwrap();
+        let filter =
+            |after: Option<&str>, before: Option<&str>| DocumentFilter {
+                modified_after: after.map(at),
+                modified_before: before.map(at),
+            };
+        assert!(DocumentFilter::default().matches(doc));
+        assert!(filter(Some("2024-01-01T00:00:00Z"), None).matches(doc));
+        assert!(!filter(Some("2024-03-01T00:00:00Z"), None).matches(doc));
+        assert!(filter(None, Some("2024-03-01T00:00:00Z")).matches(doc));
+        assert!(filter(
+            Some("2024-01-01T00:00:00Z"),
+            Some("2024-03-01T00:00:00Z")
+        )
+        .matches(doc));
+        assert!(!filter(
+            Some("2024-01-01T00:00:00Z"),
+            Some("2024-01-15T00:00:00Z")
+        )
+        .matches(doc));
+    }
+}
@@ -0,0 +1,564 @@
+//! Searching the document tree, as done by `find`.
+
+use futures_util::StreamExt;
+use remarkable_cloud_api::{
+    Client, CloudPath, Document, DocumentDetails, MetaStore,
+};
+use uuid::Uuid;
+
+use crate::columns::{self, Column};
+use crate::commands::Output;
+use crate::filter::DocumentFilter;
+use crate::glob::Pattern;
+use crate::progress::Progress;
+use crate::resolved::ResolvedTree;
+use crate::sort::{Collation, SortOrder};
+use crate::{cache, content, msg, notes};
+use crate::{document_at, locate, CliResult, Location, DETAILS_CONCURRENCY};
+
+/// Every document and folder below each of `roots` matching `filter`, and
+/// `pattern` if given, in path order.
+pub fn matching<'a>(
+    documents: &'a ResolvedTree,
+    roots: &[Option<Uuid>],
+    filter: &DocumentFilter,
+    pattern: Option<&Pattern>,
+) -> Vec<(String, &'a Document)> {
+    let mut found = vec![];
+    let mut pending: Vec<Option<Uuid>> = roots.to_vec();
+    while let Some(parent) = pending.pop() {
+        for child in documents.get_children(&parent) {
+            pending.push(Some(child.id));
+            if !filter.matches(child)
+                || !pattern.is_none_or(|p| p.matches_document(documents, child))
+            {
+                continue;
+            }
+            if let Some(path) = documents.path_of(&child.id) {
+                found.push((path, child));
+            }
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    found.dedup_by_key(|(_, d)| d.id);
+    found
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DeepReport {
+    pub downloaded: usize,
+    /// Candidates skipped because of the download limit.
+    pub unchecked: usize,
+    /// Documents whose contents couldn't be read.
+    pub unreadable: usize,
+}
+
+/// The conditions which can only be checked by looking inside documents'
+/// archives.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeepFilter {
+    /// Only notebooks with nothing drawn in them.
+    pub empty: bool,
+    /// Only documents starred on the home screen.
+    pub pinned: bool,
+}
+
+impl DeepFilter {
+    pub fn is_active(&self) -> bool {
+        self.empty || self.pinned
+    }
+
+    fn matches(&self, details: &DocumentDetails) -> CliResult<bool> {
+        if self.empty {
+            if !details.is_notebook() {
+                return Ok(false);
+            }
+            match details.stroke_count {
+                Some(0) => (),
+                Some(_) => return Ok(false),
+                None => return Err(msg!(FIND_PAGES_UNSUPPORTED).into()),
+            }
+        }
+        Ok(!self.pinned || details.pinned())
+    }
+}
+
+/// Narrows `candidates` to those matching `filter`, downloading at most
+/// `limit` blobs to find out. Folders have nothing worth downloading, so
+/// they're never empty notebooks, and count as pinned if bookmarked.
+/// Documents which can't be read are pointed out on `out`.
+pub async fn deep_matching<'a>(
+    client: &Client,
+    candidates: Vec<(String, &'a Document)>,
+    filter: DeepFilter,
+    limit: Option<usize>,
+    out: &mut dyn Output,
+) -> (Vec<(String, &'a Document)>, DeepReport) {
+    let mut found = vec![];
+    let mut documents = vec![];
+    for (path, doc) in candidates {
+        if doc.is_document() {
+            documents.push((path, doc));
+        } else if !filter.empty && (!filter.pinned || doc.bookmarked) {
+            found.push((path, doc));
+        }
+    }
+    let limit = limit.unwrap_or(documents.len());
+    let mut report = DeepReport {
+        unchecked: documents.len().saturating_sub(limit),
+        ..Default::default()
+    };
+    let documents: Vec<_> = documents.into_iter().take(limit).collect();
+    let ids: Vec<Uuid> = documents.iter().map(|(_, d)| d.id).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_CHECKED), ids.len());
+    let mut details = client.document_details_bulk(&ids, DETAILS_CONCURRENCY);
+    for (path, doc) in documents {
+        let result = details.next().await.expect("a result for each id");
+        progress.tick();
+        let details = match result {
+            Ok(details) => details,
+            Err(e) => {
+                let warning =
+                    msg!(DOWNLOAD_FAILED, path = &path, error = e.to_string());
+                progress.warn(out, &warning);
+                report.unreadable += 1;
+                continue;
+            }
+        };
+        report.downloaded += 1;
+        match filter.matches(&details) {
+            Ok(true) => found.push((path, doc)),
+            Ok(false) => (),
+            Err(e) => {
+                let warning = msg!(
+                    DOCUMENT_UNREADABLE,
+                    path = &path,
+                    error = e.to_string()
+                );
+                progress.warn(out, &warning);
+                report.unreadable += 1;
+            }
+        }
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    (found, report)
+}
+
+/// The documents among `candidates` with the same visible name as
+/// `target`, oldest first. Folders are never copies.
+pub fn same_name<'a>(
+    target: &Document,
+    candidates: Vec<(String, &'a Document)>,
+) -> Vec<(String, &'a Document)> {
+    let mut found: Vec<_> = candidates
+        .into_iter()
+        .filter(|(_, d)| {
+            d.is_document()
+                && d.id != target.id
+                && d.visible_name == target.visible_name
+        })
+        .collect();
+    by_modified(&mut found);
+    found
+}
+
+/// The documents among `candidates` holding the same files as `target`,
+/// by their content hash, oldest first, downloading at most `limit` of
+/// them to find out. Fails if `target` itself can't be hashed; others which
+/// can't be are pointed out on `out`.
+pub async fn same_content<'a>(
+    client: &Client,
+    target: &Document,
+    candidates: Vec<(String, &'a Document)>,
+    limit: Option<usize>,
+    out: &mut dyn Output,
+) -> CliResult<(Vec<(String, &'a Document)>, DeepReport)> {
+    let wanted = client.content_hash(&target.id).await?;
+    let documents: Vec<_> = candidates
+        .into_iter()
+        .filter(|(_, d)| d.is_document() && d.id != target.id)
+        .collect();
+    let limit = limit.unwrap_or(documents.len());
+    let mut report = DeepReport {
+        downloaded: 1,
+        unchecked: documents.len().saturating_sub(limit),
+        ..Default::default()
+    };
+    let documents: Vec<_> = documents.into_iter().take(limit).collect();
+    let ids: Vec<Uuid> = documents.iter().map(|(_, d)| d.id).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_HASHED), ids.len());
+    let mut hashes = client.content_hash_bulk(&ids, DETAILS_CONCURRENCY);
+    let mut found = vec![];
+    for (path, doc) in documents {
+        let result = hashes.next().await.expect("a result for each id");
+        progress.tick();
+        match result {
+            Ok(hash) => {
+                report.downloaded += 1;
+                if hash == wanted {
+                    found.push((path, doc));
+                }
+            }
+            Err(e) => {
+                let warning =
+                    msg!(DOWNLOAD_FAILED, path = &path, error = e.to_string());
+                progress.warn(out, &warning);
+                report.unreadable += 1;
+            }
+        }
+    }
+    by_modified(&mut found);
+    Ok((found, report))
+}
+
+fn by_modified(found: &mut [(String, &Document)]) {
+    found.sort_by(|a, b| {
+        (a.1.modified_client, &a.0).cmp(&(b.1.modified_client, &b.0))
+    });
+}
+
+// What `find --duplicates-of` prints about each copy by default.
+const DUPLICATE_COLUMNS: [Column; 4] =
+    [Column::Path, Column::Id, Column::Version, Column::Modified];
+
+/// A line of `find --duplicates-of` output: path, id, version and time
+/// last modified, separated by tabs.
+pub fn duplicate_line(path: &str, doc: &Document) -> String {
+    columns::tsv_row(&DUPLICATE_COLUMNS, path, doc)
+}
+
+/// The same as `duplicate_line`, as a JSON object.
+pub fn duplicate_json(path: &str, doc: &Document) -> serde_json::Value {
+    columns::json(&DUPLICATE_COLUMNS, path, doc).into()
+}
+
+/// What `find` looks for, and how it prints what it finds.
+pub struct FindOptions {
+    pub filter: DocumentFilter,
+    /// What paths must match, if anything.
+    pub pattern: Option<Pattern>,
+    /// The folders to look in.
+    pub paths: Vec<CloudPath>,
+    pub deep: DeepFilter,
+    pub content_type: Option<&'static str>,
+    /// What notes must have in them, if they're looked at.
+    pub with_notes: Option<String>,
+    /// The document to find copies of, instead of what matches.
+    pub duplicates_of: Option<CloudPath>,
+    /// Whether copies are found by name rather than content.
+    pub by_name: bool,
+    /// How many documents to download at most to look inside them.
+    pub limit: Option<usize>,
+    pub sort: SortOrder,
+    /// The columns to print, rather than only paths.
+    pub fields: Option<Vec<Column>>,
+    pub header: bool,
+    pub json: bool,
+}
+
+/// Reports the documents and folders `options` asks for to `out`. What
+/// needs to look inside documents needs `client`, which is none when the
+/// cloud can't be reached.
+pub async fn find(
+    client: Option<&Client>,
+    documents: &ResolvedTree,
+    options: &FindOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut roots = vec![];
+    for path in &options.paths {
+        match locate(documents, path)? {
+            Location::Root => roots.push(None),
+            Location::Document(d) => roots.push(Some(d.id)),
+            Location::Trash => {
+                out.line(&msg!(FIND_TRASH, path = path.to_string()))
+            }
+            Location::Missing(e) => out.line(&e.to_string()),
+        }
+    }
+    let mut found =
+        matching(documents, &roots, &options.filter, options.pattern.as_ref());
+    if let Some(path) = &options.duplicates_of {
+        let target = document_at(documents, path)?;
+        let copies = if options.by_name {
+            same_name(target, found)
+        } else {
+            let client = client.ok_or_else(|| msg!(FIND_DUPLICATES_OFFLINE))?;
+            let (copies, report) =
+                same_content(client, target, found, options.limit, out).await?;
+            out.warn(&msg!(
+                FIND_COMPARED,
+                downloaded = report.downloaded,
+                unchecked = report.unchecked,
+                unreadable = report.unreadable,
+            ));
+            copies
+        };
+        print_header(options, out);
+        for (path, doc) in copies {
+            if options.json {
+                out.line(
+                    &cache::annotate(
+                        duplicate_json(&path, doc),
+                        documents.cache_age(),
+                    )
+                    .to_string(),
+                );
+            } else if let Some(columns) = &options.fields {
+                out.line(&columns::tsv_row(columns, &path, doc));
+            } else {
+                out.line(&duplicate_line(&path, doc));
+            }
+        }
+        return Ok(());
+    }
+    if let Some(term) = &options.with_notes {
+        let client = client.ok_or_else(|| msg!(FIND_NOTES_OFFLINE))?;
+        let store = MetaStore::new(client);
+        found = notes::with_notes(&store, documents, found, term).await?;
+    }
+    if options.deep.is_active() {
+        let client = client.ok_or_else(|| msg!(FIND_DEEP_OFFLINE))?;
+        let (matched, report) =
+            deep_matching(client, found, options.deep, options.limit, out)
+                .await;
+        out.warn(&msg!(
+            FIND_CHECKED,
+            downloaded = report.downloaded,
+            unchecked = report.unchecked,
+            unreadable = report.unreadable,
+        ));
+        found = matched;
+    }
+    if let Some(content_type) = options.content_type {
+        let (matched, unknown) = content::of_type(found, content_type);
+        if unknown > 0 {
+            out.warn(&content::unknown_note(unknown));
+        }
+        found = matched;
+    }
+    Collation::new(options.sort).sort_paths(&mut found);
+    print_header(options, out);
+    for (path, doc) in found {
+        match &options.fields {
+            Some(columns) => out.line(&columns::tsv_row(columns, &path, doc)),
+            None => out.line(&Column::Path.text(&path, doc)),
+        }
+    }
+    Ok(())
+}
+
+// Reports the names of the columns asked for, if `--header` was given.
+fn print_header(options: &FindOptions, out: &mut dyn Output) {
+    if let (Some(columns), true) = (&options.fields, options.header) {
+        out.line(&columns::tsv_header(columns));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use remarkable_cloud_api::testing::FakeCloud;
+
+    use super::*;
+    use crate::commands::Capture;
+    use crate::observer::Observers;
+
+    fn page(strokes: u32) -> Vec<u8> {
+        let mut buf =
+            format!("{:<43}", "reMarkable .lines file, version=5").into_bytes();
+        buf.extend_from_slice(&1u32.to_le_bytes());
+        buf.extend_from_slice(&strokes.to_le_bytes());
+        for _ in 0..strokes {
+            buf.extend_from_slice(&[0; 20]);
+            buf.extend_from_slice(&0u32.to_le_bytes());
+        }
+        buf
+    }
+
+    fn archive(files: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut za = zip::ZipWriter::new(io::Cursor::new(vec![]));
+        for (name, data) in files {
+            za.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            za.write_all(data).unwrap();
+        }
+        za.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn deep_pass() {
+        let cloud = FakeCloud::start().await;
+        let folder = cloud.add_folder("Notes", None);
+        cloud.add_document(
+            "Blank",
+            Some(folder),
+            archive(&[("b/0.rm", page(0))]),
+        );
+        cloud.add_document(
+            "Drawn",
+            Some(folder),
+            archive(&[("d/0.rm", page(1))]),
+        );
+        cloud.add_document("Untouched", None, archive(&[]));
+        cloud.add_document(
+            "Newer firmware",
+            None,
+            archive(&[(
+                "n/0.rm",
+                b"reMarkable .lines file, version=7          ".to_vec(),
+            )]),
+        );
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = ResolvedTree::new(client.get_documents().await.unwrap());
+        let mut out = Capture::new(Observers::new());
+
+        let all = matching(&docs, &[None], &DocumentFilter::default(), None);
+        let paths: Vec<&str> = all.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "Newer firmware",
+                "Notes",
+                "Notes/Blank",
+                "Notes/Drawn",
+                "Untouched"
+            ]
+        );
+
+        let empty_only = DeepFilter {
+            empty: true,
+            pinned: false,
+        };
+        let (empty, report) =
+            deep_matching(&client, all.clone(), empty_only, None, &mut out)
+                .await;
+        let paths: Vec<&str> = empty.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, vec!["Notes/Blank", "Untouched"]);
+        assert_eq!(
+            report,
+            DeepReport {
+                downloaded: 4,
+                unchecked: 0,
+                unreadable: 1,
+            }
+        );
+
+        let (_, report) =
+            deep_matching(&client, all, empty_only, Some(1), &mut out).await;
+        assert_eq!(report.downloaded, 1);
+        assert_eq!(report.unchecked, 3);
+
+        let within =
+            matching(&docs, &[Some(folder)], &DocumentFilter::default(), None);
+        assert_eq!(within.len(), 2);
+        let pattern: Pattern = "*/?ra*".parse().unwrap();
+        let drawn = matching(
+            &docs,
+            &[None],
+            &DocumentFilter::default(),
+            Some(&pattern),
+        );
+        assert_eq!(drawn.len(), 1);
+        assert_eq!(drawn[0].0, "Notes/Drawn");
+    }
+
+    #[tokio::test]
+    async fn pinned() {
+        let cloud = FakeCloud::start().await;
+        let folder = cloud.add_folder("Starred folder", None);
+        cloud.modify(&folder, |d| d.bookmarked = true);
+        cloud.add_folder("Folder", None);
+        let new = cloud.add_document("New firmware", None, vec![]);
+        cloud.modify(&new, |d| {
+            d.blob = archive(&[(
+                &format!("{}.metadata", new),
+                br#"{"pinned": true}"#.to_vec(),
+            )])
+        });
+        // Metadata wins over the listing's bookmark when it says anything.
+        let unpinned = cloud.add_document("Unpinned", None, vec![]);
+        cloud.modify(&unpinned, |d| {
+            d.bookmarked = true;
+            d.blob = archive(&[(
+                &format!("{}.metadata", unpinned),
+                br#"{"pinned": false}"#.to_vec(),
+            )])
+        });
+        let old = cloud.add_document("Old firmware", None, archive(&[]));
+        cloud.modify(&old, |d| d.bookmarked = true);
+        cloud.add_document("Plain", None, archive(&[]));
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = ResolvedTree::new(client.get_documents().await.unwrap());
+        let mut out = Capture::new(Observers::new());
+
+        let all = matching(&docs, &[None], &DocumentFilter::default(), None);
+        let filter = DeepFilter {
+            empty: false,
+            pinned: true,
+        };
+        let (pinned, report) =
+            deep_matching(&client, all, filter, None, &mut out).await;
+        let paths: Vec<&str> = pinned.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["New firmware", "Old firmware", "Starred folder"]
+        );
+        assert_eq!(report.downloaded, 4);
+    }
+
+    #[tokio::test]
+    async fn duplicates() {
+        let cloud = FakeCloud::start().await;
+        let folder = cloud.add_folder("Old", None);
+        let paper = || archive(&[("p/p.pdf", b"%PDF".to_vec())]);
+        let original = cloud.add_document("Paper", None, paper());
+        let copy = cloud.add_document("Paper (1)", Some(folder), paper());
+        let renamed = cloud.add_document("Paper", Some(folder), archive(&[]));
+        cloud.add_document("Paper", None, b"not a zip".to_vec());
+        cloud.modify(&copy, |d| {
+            d.modified_client = "2020-01-01T00:00:00Z".parse().unwrap()
+        });
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let docs = ResolvedTree::new(client.get_documents().await.unwrap());
+        let mut out = Capture::new(Observers::new());
+        let target = docs.get(&original).unwrap();
+        let all = matching(&docs, &[None], &DocumentFilter::default(), None);
+
+        let named = same_name(target, all.clone());
+        assert_eq!(named.len(), 2);
+        assert!(named.iter().all(|(_, d)| d.id != original));
+
+        let (same, report) =
+            same_content(&client, target, all.clone(), None, &mut out)
+                .await
+                .unwrap();
+        let ids: Vec<Uuid> = same.iter().map(|(_, d)| d.id).collect();
+        assert_eq!(ids, vec![copy]);
+        assert_eq!(
+            report,
+            DeepReport {
+                downloaded: 3,
+                unchecked: 0,
+                unreadable: 1,
+            }
+        );
+        assert!(!ids.contains(&renamed));
+
+        let within =
+            matching(&docs, &[Some(folder)], &DocumentFilter::default(), None);
+        let (same, _) =
+            same_content(&client, target, within, Some(0), &mut out)
+                .await
+                .unwrap();
+        assert!(same.is_empty());
+        assert_eq!(
+            duplicate_line("Old/Paper (1)", docs.get(&copy).unwrap()),
+            format!("Old/Paper (1)\t{}\t1\t2020-01-01T00:00:00+00:00", copy)
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Shell-style patterns matched against whole document paths.
+//!
+//! Patterns are split into components the same way paths are (see
+//! `split_path`), and each component is matched against one level of the
+//! tree, so a `*` never reaches across folders: `Work/scans/2023-*` only
+//! matches documents directly inside `Work/scans`. Within a component, `*`
+//! matches any run of characters, `?` any single one, and `[...]` one of a
+//! set such as `[abc]` or `[0-9]`, negated as `[!...]`. A component that is
+//! exactly `**` matches any number of folders.
+//!
+//! Against a tree which normalizes paths, patterns and names are both
+//! compared by `name_key`, ignoring Unicode normalization and case.
+
+use std::str::FromStr;
+
+use remarkable_cloud_api::{name_key, split_path, Document};
+
+use crate::msg;
+use crate::resolved::ResolvedTree;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyRun,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Component {
+    /// `**`, any number of path components.
+    AnyDepth,
+    Tokens(Vec<Token>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    components: Vec<Component>,
+    /// The pattern as it's matched against normalized names.
+    normalized: Vec<Component>,
+}
+
+fn parse_class(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Option<Token> {
+    let negated = chars.peek() == Some(&'!');
+    if negated {
+        chars.next();
+    }
+    let mut ranges = vec![];
+    // A `]` straight after the opening bracket is part of the set.
+    let mut first = true;
+    loop {
+        let c = chars.next()?;
+        if c == ']' && !first {
+            return Some(Token::Class { negated, ranges });
+        }
+        first = false;
+        let mut lookahead = chars.clone();
+        match (lookahead.next(), lookahead.next()) {
+            (Some('-'), Some(end)) if end != ']' => {
+                chars.next();
+                chars.next();
+                ranges.push((c, end));
+            }
+            _ => ranges.push((c, c)),
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Pattern {
+            components: parse_components(s)?,
+            normalized: parse_components(&name_key(s, true))?,
+        })
+    }
+}
+
+fn parse_components(s: &str) -> Result<Vec<Component>, String> {
+    let mut components = vec![];
+    for component in split_path(s).map_err(|e| e.to_string())? {
+        if component == "**" {
+            components.push(Component::AnyDepth);
+            continue;
+        }
+        let mut tokens = vec![];
+        let mut chars = component.chars().peekable();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '*' => Token::AnyRun,
+                '?' => Token::AnyChar,
+                '[' => parse_class(&mut chars).ok_or_else(|| {
+                    msg!(PATTERN_UNCLOSED, pattern = format!("{:?}", s))
+                })?,
+                c => Token::Char(c),
+            });
+        }
+        components.push(Component::Tokens(tokens));
+    }
+    Ok(components)
+}
+
+fn matches_tokens(tokens: &[Token], name: &[char]) -> bool {
+    match tokens.split_first() {
+        None => name.is_empty(),
+        Some((Token::AnyRun, rest)) => {
+            (0..=name.len()).any(|i| matches_tokens(rest, &name[i..]))
+        }
+        Some((token, rest)) => match name.split_first() {
+            None => false,
+            Some((c, name_rest)) => {
+                let ok = match token {
+                    Token::Char(t) => t == c,
+                    Token::AnyChar => true,
+                    Token::Class { negated, ranges } => {
+                        ranges.iter().any(|(lo, hi)| lo <= c && c <= hi)
+                            != *negated
+                    }
+                    Token::AnyRun => unreachable!(),
+                };
+                ok && matches_tokens(rest, name_rest)
+            }
+        },
+    }
+}
+
+fn matches_components(pattern: &[Component], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((Component::AnyDepth, rest)) => {
+            (0..=path.len()).any(|i| matches_components(rest, &path[i..]))
+        }
+        Some((Component::Tokens(tokens), rest)) => match path.split_first() {
+            None => false,
+            Some((name, path_rest)) => {
+                let name: Vec<char> = name.chars().collect();
+                matches_tokens(tokens, &name)
+                    && matches_components(rest, path_rest)
+            }
+        },
+    }
+}
+
+impl Pattern {
+    /// Whether the pattern has no wildcards, and so names a single path.
+    pub fn is_literal(&self) -> bool {
+        self.components.iter().all(|c| match c {
+            Component::AnyDepth => false,
+            Component::Tokens(tokens) => {
+                tokens.iter().all(|t| matches!(t, Token::Char(_)))
+            }
+        })
+    }
+
+    /// Whether the pattern matches a path, given as its components.
+    pub fn matches<S: AsRef<str>>(&self, path: &[S]) -> bool {
+        let path: Vec<&str> = path.iter().map(|c| c.as_ref()).collect();
+        matches_components(&self.components, &path)
+    }
+
+    /// Whether the pattern matches the path to a document.
+    pub fn matches_document(
+        &self,
+        documents: &ResolvedTree,
+        doc: &Document,
+    ) -> bool {
+        documents
+            .path_of(&doc.id)
+            .is_some_and(|path| match split_path(&path) {
+                Ok(components) if documents.normalizes_paths() => {
+                    let keys: Vec<String> =
+                        components.iter().map(|c| name_key(c, true)).collect();
+                    let keys: Vec<&str> = keys.iter().map(|k| &**k).collect();
+                    matches_components(&self.normalized, &keys)
+                }
+                Ok(components) => self.matches(&components),
+                Err(_) => false,
+            })
+    }
+
+    /// Every document and folder the pattern matches, in path order.
+    pub fn expand<'a>(
+        &self,
+        documents: &'a ResolvedTree,
+    ) -> Vec<(String, &'a Document)> {
+        let mut found: Vec<(String, &Document)> = documents
+            .iter()
+            .filter(|d| self.matches_document(documents, d))
+            .filter_map(|d| Some((documents.path_of(&d.id)?, d)))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        let pattern: Pattern = pattern.parse().unwrap();
+        pattern.matches(&split_path(path).unwrap())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("scan-2024-*", "scan-2024-01"));
+        assert!(matches("scan-2024-*", "scan-2024-"));
+        assert!(!matches("scan-2024-*", "scan-2023-01"));
+        assert!(matches("scan-????-01", "scan-2024-01"));
+        assert!(!matches("scan-???-01", "scan-2024-01"));
+        assert!(matches("scan-202[34]-*", "scan-2023-05"));
+        assert!(!matches("scan-202[34]-*", "scan-2022-05"));
+        assert!(matches("scan-[0-9]*", "scan-7"));
+        assert!(matches("scan-[!0-9]*", "scan-x"));
+        assert!(!matches("scan-[!0-9]*", "scan-7"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("a[-]b", "a-b"));
+        assert!(matches("*", "Quick sheets"));
+    }
+
+    #[test]
+    fn whole_paths() {
+        assert!(matches("Work/scans/2023-*", "Work/scans/2023-01"));
+        assert!(!matches("Work/scans/2023-*", "Home/scans/2023-01"));
+        assert!(!matches("Work/scans/2023-*", "Work/scans/old/2023-01"));
+        assert!(!matches("Work/*", "Work"));
+        assert!(!matches("*", "Work/Notes"));
+        assert!(matches("*/Notes", "Work/Notes"));
+        assert!(matches("/Work//scans\\2023-*/", "Work/scans/2023-01"));
+
+        assert!(matches("Work/**/2023-*", "Work/2023-01"));
+        assert!(matches("Work/**/2023-*", "Work/scans/old/2023-01"));
+        assert!(!matches("Work/**/2023-*", "Home/scans/2023-01"));
+        assert!(matches("**", "Anything/at/all"));
+    }
+
+    #[test]
+    fn parsing() {
+        assert!("scan-[0-9".parse::<Pattern>().is_err());
+        assert!("../scans".parse::<Pattern>().is_err());
+        assert!("Work/scans".parse::<Pattern>().unwrap().is_literal());
+        assert!(!"Work/scan?".parse::<Pattern>().unwrap().is_literal());
+        assert!(!"Work/**".parse::<Pattern>().unwrap().is_literal());
+    }
+}
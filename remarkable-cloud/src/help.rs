@@ -0,0 +1,303 @@
+//! Worked examples for `--help`, and the longer topics `help <topic>`
+//! prints, all compiled in.
+//!
+//! Each command's examples are kept next to its options, as `EXAMPLES` or
+//! `PUSH_EXAMPLES` and so on, and gathered into [`EXAMPLES`] here. Every
+//! example, in a command's table or a topic, is parsed by [`crate::cli`]'s
+//! argument parser in its tests, so none can name a flag that no longer
+//! exists.
+
+use crate::{await_document, commands, export, notes, trash, watch};
+
+/// A command line, as it would be typed, and what it does.
+#[derive(Clone, Copy, Debug)]
+pub struct Example {
+    pub command: &'static str,
+    pub description: &'static str,
+}
+
+/// Something to read about at more length than one command's help.
+#[derive(Clone, Copy, Debug)]
+pub struct Topic {
+    /// What's given to `help` to print it, in lowercase with hyphens.
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub body: &'static str,
+    pub examples: &'static [Example],
+}
+
+/// The examples of each command which has some, by the name of the
+/// command.
+pub const EXAMPLES: &[(&str, &[Example])] = &[
+    ("push", commands::PUSH_EXAMPLES),
+    ("pull", commands::PULL_EXAMPLES),
+    ("export", export::EXAMPLES),
+    ("trash", trash::EXAMPLES),
+    ("note", notes::EXAMPLES),
+    ("watch", watch::EXAMPLES),
+    ("await-document", await_document::EXAMPLES),
+];
+
+pub const TOPICS: &[Topic] = &[
+    Topic {
+        name: "path-addressing",
+        summary: "How documents and folders are named on the command line",
+        body: "\
+Documents are named by their path from the root, as the tablet shows it,
+with folders separated by /: \"Books/Dune\". A leading /, doubled slashes and
+a trailing / make no difference, and \\ works as a separator too. \"..\" isn't
+supported.
+
+Names can contain /, so \"A/B\" could be the document \"B\" in the folder
+\"A\" or a document named \"A/B\"; if both exist the path is refused as
+ambiguous. Write \"A\\/B\" to mean only the latter.
+
+pull also takes a bare name, found wherever it is in the tree; it asks for
+a fuller path when the name matches several documents, unless given
+--all-matches. --id names a document by its id instead.
+
+mv, trash and rm take shell-style patterns: * matches any run of characters
+and ? any one within a single folder, [abc] one of a set, and a component
+of just ** any number of folders. Quote patterns so the shell leaves them
+alone. A pattern matching more than one document asks before going ahead,
+unless given --yes, and one matching nothing is an error unless given
+--allow-empty.",
+        examples: &[
+            Example {
+                command: "remarkable-cloud ls Books/Sci-fi",
+                description: "Lists the folder Sci-fi in the folder Books",
+            },
+            Example {
+                command: "remarkable-cloud pull --all-matches Notes",
+                description: "Pulls every document named Notes, wherever it is",
+            },
+            Example {
+                command: "remarkable-cloud mv 'Inbox/*.pdf' Books",
+                description: "Moves the PDFs in Inbox into Books",
+            },
+            Example {
+                command: "remarkable-cloud trash --yes 'Work/**/draft*'",
+                description: "Trashes every draft anywhere under Work",
+            },
+        ],
+    },
+    Topic {
+        name: "conflict-resolution",
+        summary: "What happens when a name is already taken, or a document \
+                  has changed",
+        body: "\
+push puts each file in its folder under the file's name without its
+extension. When the folder already has a document of that name, push asks
+what to do, or does what --on-conflict says:
+
+  skip       leaves the existing document alone and uploads nothing
+  update     uploads the file as a new version of the existing document
+  rename     uploads it as a new document with a numbered name, \"Dune (2)\"
+  duplicate  uploads it as a new document of the same name anyway
+
+Without a terminal to ask on, and without --on-conflict, push skips. The
+same applies to every file of push -r, and to files queued with
+push --queue, which are checked again when `queue run` pushes them.
+
+Commands run with --cached act on the listing saved last time, so before
+mv, trash or rm changes anything each document is checked against the
+cloud, and the command stops if one has changed since.
+
+restore adds documents under new ids, alongside what's there; with
+--keep-ids it replaces documents that still exist instead.",
+        examples: &[
+            Example {
+                command: "remarkable-cloud push --on-conflict update Dune.pdf",
+                description: "Replaces the Dune already there with a new \
+                              version",
+            },
+            Example {
+                command: "remarkable-cloud push -r --on-conflict skip --to \
+                          Papers ~/papers",
+                description: "Pushes a directory, leaving alone what's \
+                              already there",
+            },
+            Example {
+                command: "remarkable-cloud restore --keep-ids backup.tar.zst",
+                description: "Puts documents back as they were in a backup",
+            },
+        ],
+    },
+    Topic {
+        name: "webhooks",
+        summary: "What watch --webhook sends, and how to check it",
+        body: "\
+watch --webhook POSTs each change to a document to the URL given, as a JSON
+object:
+
+  event      \"added\", \"updated\" or \"removed\", for deleted or trashed
+  id         the document's id
+  name       its name
+  path       its path, as ls -r --paths shows it
+  version    its version once changed, or when removed the last one seen
+  timestamp  when the change was first seen, in RFC 3339
+
+For example:
+
+  {\"event\":\"added\",\"id\":\"0d9e5a4c-3f59-4d4b-9b8c-2f0b6f6a1f7e\",
+   \"name\":\"Dune\",\"path\":\"Books/Dune\",\"version\":1,
+   \"timestamp\":\"2024-05-01T09:30:00Z\"}
+
+Folders aren't reported on. A change is sent once the document has stayed
+as it is for --debounce, so one written in several steps is sent once.
+
+Each request has the header X-Remarkable-Signature: sha256= and the
+HMAC-SHA256 of the body in hex, keyed with webhook_secret from settings.json,
+which has to be set. Receivers should work it out from the body as received
+and turn away requests where it differs.
+
+Any answer but 2xx is a failure, and the delivery is tried again after 1s,
+2s, 4s and so on, --retries times. One which still fails is kept in
+webhook-spool in the config directory, and sent by watch --replay-spool.
+No version of a document is sent to a URL twice as the same event, even
+across runs; should a receiver take an event but its answer be lost, it can
+tell the event sent again by its event, id and version.",
+        examples: &[
+            Example {
+                command: "remarkable-cloud watch --webhook \
+                          https://example.com/hooks/remarkable --debounce 1m",
+                description: "Sends each change a minute after the document \
+                              last changed",
+            },
+            Example {
+                command: "remarkable-cloud watch --replay-spool",
+                description: "Sends the changes which couldn't be delivered \
+                              before",
+            },
+        ],
+    },
+];
+
+/// The topic `name` names, ignoring case and taking spaces for hyphens.
+pub fn topic(name: &str) -> Option<&'static Topic> {
+    let name = name.trim().to_lowercase().replace(' ', "-");
+    TOPICS.iter().find(|t| t.name == name)
+}
+
+/// The examples of the command `name`, if it has any.
+pub fn examples(name: &str) -> Option<&'static [Example]> {
+    EXAMPLES
+        .iter()
+        .find(|(command, _)| *command == name)
+        .map(|(_, examples)| *examples)
+}
+
+/// `examples` as the section shown at the end of a command's `--help`.
+pub fn render_examples(examples: &[Example]) -> String {
+    let mut out = "EXAMPLES:".to_string();
+    for example in examples {
+        out.push_str(&format!(
+            "\n    {}\n            {}",
+            example.command, example.description
+        ));
+    }
+    out
+}
+
+/// `topic` as `help <topic>` prints it.
+pub fn render_topic(topic: &Topic) -> String {
+    let mut out = format!("{}\n\n{}\n", topic.summary, topic.body);
+    if !topic.examples.is_empty() {
+        out.push('\n');
+        out.push_str(&render_examples(topic.examples));
+        out.push('\n');
+    }
+    out
+}
+
+/// The list of topics shown at the end of the top-level `--help`.
+pub fn render_topic_list() -> String {
+    let mut out =
+        "TOPICS:\n    Run `remarkable-cloud help <topic>` to read about:"
+            .to_string();
+    for topic in TOPICS {
+        out.push_str(&format!("\n    {:<22}{}", topic.name, topic.summary));
+    }
+    out
+}
+
+/// Splits an example's command line into arguments the way a shell would,
+/// for one without variables or escapes: at spaces, except within single
+/// or double quotes, which are dropped.
+pub fn split_command_line(command: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// The arguments `remarkable-cloud` is run with by the example `command`,
+/// starting with its own name: the words of the last command of a
+/// pipeline, without redirections.
+pub fn example_arguments(command: &str) -> Vec<String> {
+    let mut words = split_command_line(command);
+    if let Some(pipe) = words.iter().rposition(|w| w == "|") {
+        words.drain(..=pipe);
+    }
+    let mut arguments = vec![];
+    let mut words = words.into_iter();
+    while let Some(word) = words.next() {
+        if word == "<" || word == ">" {
+            words.next();
+        } else {
+            arguments.push(word);
+        }
+    }
+    arguments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            split_command_line("remarkable-cloud mv  'Inbox/*.pdf' \"A B\" ''"),
+            ["remarkable-cloud", "mv", "Inbox/*.pdf", "A B", ""]
+        );
+    }
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            example_arguments(
+                "curl -s x | remarkable-cloud push --stdin --name P.pdf"
+            ),
+            ["remarkable-cloud", "push", "--stdin", "--name", "P.pdf"]
+        );
+        assert_eq!(
+            example_arguments("remarkable-cloud export csv > tree.csv"),
+            ["remarkable-cloud", "export", "csv"]
+        );
+    }
+
+    #[test]
+    fn topics() {
+        assert_eq!(topic("Path addressing").unwrap().name, "path-addressing");
+        assert!(topic("conflict-resolution").is_some());
+        assert!(topic("sync").is_none());
+        let rendered = render_topic(topic("conflict-resolution").unwrap());
+        assert!(rendered.contains("\nEXAMPLES:\n    remarkable-cloud push"));
+        let list = render_topic_list();
+        assert!(list.contains("\n    path-addressing       How documents"));
+    }
+}
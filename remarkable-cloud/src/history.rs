@@ -0,0 +1,197 @@
+//! What the cloud currently holds for a document, set against the cached
+//! listing, as shown by `info --history`. Useful for working out who last
+//! changed a document when the tablet and another client disagree.
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::Document;
+use remarkable_data_formats::metadata::Metadata;
+
+use crate::msg;
+
+// A labelled value, marked with the cached one if that differs.
+fn field(label: &str, current: String, cached: Option<String>) -> String {
+    let mut line = format!("  {:<18}{}", label, current);
+    if let Some(cached) = cached.filter(|c| *c != current) {
+        line.push_str(&format!("  {} *", msg!(HISTORY_CACHED, value = cached)));
+    }
+    line
+}
+
+fn parent(doc: &Document) -> String {
+    doc.parent
+        .map_or_else(|| msg!(HISTORY_ROOT), |p| p.to_string())
+}
+
+// Who last wrote the document, as far as the flags the tablet keeps in
+// `.metadata` tell.
+fn written_by(metadata: Option<&Metadata>) -> String {
+    let metadata = match metadata {
+        Some(m) => m,
+        None => return msg!(WRITTEN_BY_NO_METADATA),
+    };
+    let pending = metadata.modified == Some(true)
+        || metadata.metadata_modified == Some(true);
+    match (metadata.synced, pending) {
+        (_, true) => msg!(WRITTEN_BY_TABLET_PENDING),
+        (Some(true), false) => msg!(WRITTEN_BY_TABLET_SYNCED),
+        (Some(false), false) => msg!(WRITTEN_BY_TABLET_UNSYNCED),
+        (None, false) => msg!(WRITTEN_BY_UNKNOWN),
+    }
+}
+
+fn blob_url(doc: &Document, now: DateTime<Utc>) -> String {
+    let expires = match (&doc.blob_url_get, doc.blob_url_get_expires) {
+        (Some(_), Some(expires)) => expires,
+        _ => return msg!(BLOB_URL_NONE),
+    };
+    match (expires - now).to_std() {
+        Ok(left) if !left.is_zero() => {
+            let left = std::time::Duration::from_secs(left.as_secs());
+            let left = humantime::format_duration(left).to_string();
+            msg!(BLOB_URL_FRESH, left = left)
+        }
+        _ => msg!(BLOB_URL_EXPIRED, at = expires.to_rfc3339()),
+    }
+}
+
+/// The lines describing `current`, as just fetched from the cloud, with any
+/// field that differs from `cached` marked with `*` and the cached value.
+pub fn render(
+    current: &Document,
+    cached: Option<&Document>,
+    metadata: Option<&Metadata>,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let mut lines = vec![format!("{} {}", current.visible_name, current.id)];
+    let compare =
+        |f: &dyn Fn(&Document) -> String| -> (String, Option<String>) {
+            (f(current), cached.map(f))
+        };
+    let fields: Vec<(String, (String, Option<String>))> = vec![
+        (msg!(HISTORY_VERSION), compare(&|d| d.version.to_string())),
+        (
+            msg!(HISTORY_MODIFIED),
+            compare(&|d| d.modified_client.to_rfc3339()),
+        ),
+        (msg!(HISTORY_NAME), compare(&|d| d.visible_name.to_string())),
+        (msg!(HISTORY_PARENT), compare(&parent)),
+        (
+            msg!(HISTORY_BOOKMARKED),
+            compare(&|d| d.bookmarked.to_string()),
+        ),
+        (
+            msg!(HISTORY_CURRENT_PAGE),
+            compare(&|d| d.current_page.to_string()),
+        ),
+    ];
+    for (label, (current, cached)) in fields {
+        lines.push(field(&label, current, cached));
+    }
+    if let Some(m) = metadata {
+        let label = msg!(HISTORY_METADATA_VERSION);
+        let mut line = field(&label, m.version.to_string(), None);
+        if m.version != current.version {
+            let listed = msg!(HISTORY_LISTED, version = current.version);
+            line.push_str(&format!("  {} *", listed));
+        }
+        lines.push(line);
+    }
+    lines.push(field(&msg!(HISTORY_WRITTEN_BY), written_by(metadata), None));
+    lines.push(field(&msg!(HISTORY_BLOB_URL), blob_url(current, now), None));
+    match cached {
+        None => lines.push(format!("  {}", msg!(HISTORY_NOT_CACHED))),
+        Some(c) if c == current => {
+            lines.push(format!("  {}", msg!(HISTORY_SAME_AS_CACHED)))
+        }
+        Some(_) => (),
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    fn dune() -> Document {
+        let docs = listing(&[(1, "Dune", None, "DocumentType")]);
+        docs.get(&Uuid::from_u128(1)).unwrap().clone()
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2024-01-02T00:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn differences() {
+        let cached = dune();
+        let mut current = cached.clone();
+        current.version = 3;
+        current.current_page = 7;
+        let lines = render(&current, Some(&cached), None, now());
+        assert_eq!(lines[0], format!("Dune {}", Uuid::from_u128(1)));
+        let marked: Vec<&String> =
+            lines.iter().filter(|l| l.ends_with('*')).collect();
+        assert_eq!(
+            marked,
+            vec![
+                "  version           3  (cached: 1) *",
+                "  current page      7  (cached: 0) *",
+            ]
+        );
+        assert!(lines.contains(
+            &"  written by        unknown, as the archive has no .metadata"
+                .to_string()
+        ));
+
+        let same = render(&cached, Some(&cached), None, now());
+        assert!(same.iter().all(|l| !l.ends_with('*')));
+        assert_eq!(same.last().unwrap(), "  same as the cached listing");
+        let uncached = render(&cached, None, None, now());
+        assert_eq!(uncached.last().unwrap(), "  not in the cached listing");
+    }
+
+    #[test]
+    fn metadata_and_blob_url() {
+        let mut current = dune();
+        current.version = 5;
+        current.blob_url_get = Some("https://example.com/blob".to_string());
+        current.blob_url_get_expires =
+            Some(now() + chrono::Duration::minutes(90));
+        let metadata = Metadata::parse(
+            br#"{"version": 4, "synced": true, "metadatamodified": true}"#,
+        )
+        .unwrap();
+        let lines = render(&current, None, Some(&metadata), now());
+        let find = |label: &str| {
+            lines
+                .iter()
+                .find(|l| l.trim_start().starts_with(label))
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(
+            find("metadata version"),
+            "  metadata version  4  (listing: 5) *"
+        );
+        assert!(find("written by").ends_with("changes it hadn't synced yet"));
+        assert!(find("blob URL").ends_with("fresh, expires in 1h 30m"));
+
+        current.blob_url_get_expires =
+            Some(now() - chrono::Duration::minutes(1));
+        let lines = render(&current, None, None, now());
+        assert!(lines.iter().any(|l| l.contains("expired at")));
+
+        let synced = Metadata::parse(br#"{"synced": true}"#).unwrap();
+        assert_eq!(
+            written_by(Some(&synced)),
+            "the tablet, in step with the cloud"
+        );
+        let script = Metadata::parse(br#"{"version": 2}"#).unwrap();
+        assert!(written_by(Some(&script)).contains("doesn't say whether"));
+        let pending = Metadata::parse(br#"{"synced": false}"#).unwrap();
+        assert_eq!(written_by(Some(&pending)), "the tablet, not yet synced");
+    }
+}
@@ -0,0 +1,368 @@
+//! Describing documents in detail, as done by `info`.
+
+use futures_util::StreamExt;
+use remarkable_cloud_api::{
+    ArchiveVerification, CheckResult, Client, CloudPath, Document,
+    DocumentDetails, Documents, Parent, PinnedSource,
+};
+use uuid::Uuid;
+
+use crate::columns::{self, Column};
+use crate::commands::Output;
+use crate::progress::Progress;
+use crate::resolved::ResolvedTree;
+use crate::trash::TRASH;
+use crate::{cache, history, msg, redact};
+use crate::{locate, CliResult, Location, DETAILS_CONCURRENCY};
+
+/// The line `info` prints of the folders a document is in, from the root
+/// down, as "In: Books > Sci-fi".
+pub fn breadcrumb(documents: &Documents, doc: &Document) -> String {
+    let ancestors = match documents.ancestors(&doc.id) {
+        Ok(ancestors) => ancestors,
+        Err(e) => return msg!(IN_UNKNOWN, error = e.to_string()),
+    };
+    let mut names: Vec<std::borrow::Cow<str>> = ancestors
+        .iter()
+        .rev()
+        .map(|d| redact::name(&d.visible_name))
+        .collect();
+    // Only what's in the trash ends at a folder which isn't listed.
+    let trashed = match ancestors.last() {
+        Some(top) => documents.get(&top.id).is_none(),
+        None => documents.get(&doc.id).is_none(),
+    };
+    if trashed {
+        names.insert(0, TRASH.into());
+    }
+    if names.is_empty() {
+        return msg!(IN_ROOT);
+    }
+    msg!(IN_FOLDERS, folders = names.join(" > "))
+}
+
+/// Describes a document for `info --json`.
+pub fn details_json(
+    path: &str,
+    details: &DocumentDetails,
+) -> serde_json::Value {
+    let doc = &details.document;
+    let mut notes = vec![];
+    if details.pinned_source() == PinnedSource::Listing {
+        notes.push(msg!(PINNED_FROM_LISTING));
+    }
+    // The columns every output shares, then the rest.
+    let mut json = columns::json(&Column::ALL, path, doc);
+    let rest = serde_json::json!({
+        "parent": doc.parent,
+        "current_page": doc.current_page,
+        "pinned": details.pinned(),
+        "pinned_source": details.pinned_source(),
+        "file_type": details.content.as_ref().map(|c| &c.file_type),
+        "page_count": details.page_count,
+        "stroke_count": details.stroke_count,
+        "blob_size": details.blob_size,
+        "notes": notes,
+    });
+    if let serde_json::Value::Object(rest) = rest {
+        json.extend(rest);
+    }
+    json.into()
+}
+
+/// The line `info --content` prints for a document.
+pub fn content_summary(path: &str, details: &DocumentDetails) -> String {
+    let pages = match details.page_count {
+        Some(n) => msg!(CONTENT_PAGES, count = n),
+        None => msg!(CONTENT_NO_PAGES),
+    };
+    let strokes = match details.stroke_count {
+        Some(n) => msg!(CONTENT_STROKES, count = n),
+        None => msg!(CONTENT_STROKES_UNREADABLE),
+    };
+    msg!(
+        CONTENT_SUMMARY,
+        path = redact::text(path),
+        pages = pages,
+        strokes = strokes,
+        bytes = details.blob_size,
+    )
+}
+
+/// The table `info --verify` prints for a document: whether each check
+/// passed, then the content hash.
+pub fn verification_table(
+    path: &str,
+    verification: &ArchiveVerification,
+) -> String {
+    let verdict = if verification.is_ok() {
+        msg!(VERIFY_INTACT)
+    } else {
+        msg!(VERIFY_DAMAGED)
+    };
+    let mut lines = vec![format!("{}: {}", redact::text(path), verdict)];
+    for check in &verification.checks {
+        let result = match &check.result {
+            CheckResult::Passed => msg!(VERDICT_OK),
+            CheckResult::Failed(reason) => {
+                msg!(VERIFY_CHECK_FAILED, reason = reason.to_string())
+            }
+            CheckResult::Skipped => msg!(VERDICT_SKIPPED),
+        };
+        lines.push(format!("  {:<8} {}", check.name, result));
+    }
+    let hash = match &verification.content_hash {
+        Some(hash) => hash.iter().map(|b| format!("{:02x}", b)).collect(),
+        None => msg!(VERIFY_HASH_UNAVAILABLE),
+    };
+    lines.push(format!("  {:<8} {}", msg!(VERIFY_HASH), hash));
+    lines.join("\n")
+}
+
+/// What `info` shows of each document.
+pub struct InfoOptions {
+    /// Its versions, against the cached listing's.
+    pub history: bool,
+    /// What's in it, as JSON.
+    pub json: bool,
+    /// A summary of what's in it.
+    pub content: bool,
+}
+
+/// Reports what `options` asks for of the documents at `paths` to `out`.
+/// `cached` is the listing cached before `documents` was fetched, for the
+/// history.
+pub async fn info(
+    client: &Client,
+    documents: &ResolvedTree,
+    cached: Option<&Documents>,
+    paths: &[CloudPath],
+    options: &InfoOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let inspect = options.json || options.content;
+    let mut found = vec![];
+    for path in paths {
+        match locate(documents, path)? {
+            Location::Document(d) if options.history => {
+                let details = client.document_details(&d.id).await?;
+                for line in history::render(
+                    &details.document,
+                    cached.and_then(|c| c.get(&d.id)),
+                    details.metadata.as_ref(),
+                    chrono::Utc::now(),
+                ) {
+                    out.line(&line);
+                }
+            }
+            Location::Document(d) if inspect => found.push(d),
+            Location::Document(d) => {
+                out.line(&breadcrumb(documents, d));
+                out.line(&format!("{:?}", d));
+            }
+            Location::Root | Location::Trash => {
+                out.line(&msg!(NOT_A_DOCUMENT, path = path.to_string()))
+            }
+            Location::Missing(e) => out.line(&e.to_string()),
+        }
+    }
+    let ids: Vec<Uuid> = found.iter().map(|d| d.id).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_DOWNLOADED), ids.len());
+    let mut details = client.document_details_bulk(&ids, DETAILS_CONCURRENCY);
+    for d in found {
+        let path = documents.path_of(&d.id).unwrap_or_default();
+        let result = details.next().await.expect("a result for each id");
+        progress.tick();
+        match result {
+            Ok(details) if options.json => {
+                progress.clear();
+                out.line(
+                    &cache::annotate(
+                        details_json(&path, &details),
+                        documents.cache_age(),
+                    )
+                    .to_string(),
+                )
+            }
+            Ok(details) => {
+                progress.clear();
+                out.line(&content_summary(&path, &details))
+            }
+            Err(e) => {
+                let warning = msg!(
+                    DOCUMENT_UNREADABLE,
+                    path = &path,
+                    error = e.to_string()
+                );
+                progress.warn(out, &warning)
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Downloads the documents at `paths`, and with `recursive`, those in the
+/// folders there, for `info --verify`: reports how each archive checks out
+/// to `out`, then fails if any didn't.
+pub async fn verify(
+    client: &Client,
+    documents: &ResolvedTree,
+    paths: &[CloudPath],
+    recursive: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut found = vec![];
+    for path in paths {
+        match locate(documents, path)? {
+            Location::Document(d) if d.is_folder() => {
+                if !recursive {
+                    return Err(
+                        msg!(VERIFY_FOLDER, path = path.to_string()).into()
+                    );
+                }
+                found.extend(
+                    documents
+                        .descendants(Parent::Folder(d.id))
+                        .map(|(_, d)| d)
+                        .filter(|d| !d.is_folder()),
+                );
+            }
+            Location::Document(d) => found.push(d),
+            Location::Root | Location::Trash => {
+                out.line(&msg!(NOT_A_DOCUMENT, path = path.to_string()))
+            }
+            Location::Missing(e) => out.line(&e.to_string()),
+        }
+    }
+    let ids: Vec<Uuid> = found.iter().map(|d| d.id).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_VERIFIED), ids.len());
+    let mut verifications =
+        client.verify_archive_bulk(&ids, DETAILS_CONCURRENCY);
+    let (mut intact, mut damaged, mut unread) = (0usize, 0usize, 0usize);
+    for d in found {
+        let path = documents.path_of(&d.id).unwrap_or_default();
+        let result = verifications.next().await.expect("a result for each id");
+        progress.tick();
+        match result {
+            Ok(verification) => {
+                if verification.is_ok() {
+                    intact += 1;
+                } else {
+                    damaged += 1;
+                }
+                progress.clear();
+                out.line(&verification_table(&path, &verification));
+            }
+            Err(e) => {
+                unread += 1;
+                let warning =
+                    msg!(DOWNLOAD_FAILED, path = &path, error = e.to_string());
+                progress.warn(out, &warning)
+            }
+        }
+    }
+    progress.clear();
+    out.line(&msg!(
+        VERIFY_TOTALS,
+        count = ids.len(),
+        intact = intact,
+        damaged = damaged,
+        unread = unread,
+    ));
+    if damaged + unread > 0 {
+        return Err(msg!(
+            VERIFY_FAILED,
+            failed = damaged + unread,
+            count = ids.len(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    #[test]
+    fn breadcrumbs() {
+        let docs = listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Sci-fi", Some(1), "CollectionType"),
+            (3, "Dune", Some(2), "DocumentType"),
+            (4, "Emma", None, "DocumentType"),
+            (5, "Old", Some(0xdead), "CollectionType"),
+        ]);
+        let crumb =
+            |n| breadcrumb(&docs, docs.get(&Uuid::from_u128(n)).unwrap());
+        assert_eq!(crumb(3), "In: Books > Sci-fi");
+        assert_eq!(crumb(2), "In: Books");
+        assert_eq!(crumb(4), "In: the root");
+        assert_eq!(
+            crumb(5),
+            format!(
+                "In: unknown, as {} isn't in the listing",
+                Uuid::from_u128(0xdead)
+            )
+        );
+    }
+
+    #[test]
+    fn pinned_json() {
+        let docs = listing(&[(1, "Dune", None, "DocumentType")]);
+        let mut details = DocumentDetails {
+            document: docs.get(&Uuid::from_u128(1)).unwrap().clone(),
+            metadata: None,
+            content: None,
+            page_count: None,
+            stroke_count: Some(0),
+            blob_size: 0,
+        };
+        let json = details_json("Dune", &details);
+        assert_eq!(json["pinned"], false);
+        assert_eq!(json["pinned_source"], "listing");
+        assert!(json["notes"][0]
+            .as_str()
+            .unwrap()
+            .contains("older firmware"));
+
+        details.metadata = Some(
+            remarkable_data_formats::metadata::Metadata::parse(
+                br#"{"pinned": true}"#,
+            )
+            .unwrap(),
+        );
+        let json = details_json("Dune", &details);
+        assert_eq!(json["pinned"], true);
+        assert_eq!(json["pinned_source"], "metadata");
+        assert_eq!(json["notes"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn verification() {
+        let check =
+            |name, result| remarkable_cloud_api::ArchiveCheck { name, result };
+        let verification = ArchiveVerification {
+            checks: vec![
+                check("zip", CheckResult::Passed),
+                check("content", CheckResult::Failed("bad".to_string())),
+                check("pages", CheckResult::Skipped),
+            ],
+            content_hash: Some([0xab; 32]),
+        };
+        assert_eq!(
+            verification_table("Books/Dune", &verification),
+            format!(
+                "Books/Dune: damaged\n  \
+                 zip      ok\n  \
+                 content  FAILED  bad\n  \
+                 pages    skipped\n  \
+                 hash     {}",
+                "ab".repeat(32)
+            )
+        );
+    }
+}
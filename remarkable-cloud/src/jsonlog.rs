@@ -0,0 +1,253 @@
+//! Machine-readable operation log, as written by `--log-json <path>`.
+//!
+//! The log is appended to, one JSON object per line, and each line is flushed
+//! as soon as the operation it describes finishes so that a run which is
+//! killed part way through still leaves a usable partial log.
+//!
+//! Every record carries these fields:
+//!
+//! * `schema_version`: `1`. Incremented whenever a field is removed or changes
+//!   meaning; new fields may be added without a bump.
+//! * `timestamp`: RFC 3339 time at which the record was written.
+//! * `event`: the kind of operation, one of the values below.
+//! * `path`: the cloud path the operation concerned.
+//!
+//! Further fields depend on `event`:
+//!
+//! * `pulled`: `id`, `output` (local file written), `modified_client` (RFC
+//!   3339 time the document was last changed on the device), `bytes`,
+//!   `sha256` (hex digest of the written file) and `elapsed_ms`.
+//! * `skipped`: `id` (or `null` if the document was never resolved) and
+//!   `reason`.
+//! * `failed`: `id` (or `null`), `category` (a short machine-readable error
+//!   class such as `http`, `io`, `json` or `zip`), `message` and `elapsed_ms`.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use uuid::Uuid;
+
+use crate::msg;
+use crate::observer::{Event, Observer};
+use crate::redact;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    schema_version: u32,
+    timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    event: RecordEvent<'a>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum RecordEvent<'a> {
+    Pulled {
+        path: String,
+        id: &'a Uuid,
+        output: String,
+        modified_client: &'a chrono::DateTime<chrono::Utc>,
+        bytes: u64,
+        sha256: &'a str,
+        elapsed_ms: u64,
+    },
+    Skipped {
+        path: String,
+        id: &'a Option<Uuid>,
+        reason: &'a str,
+    },
+    Failed {
+        path: String,
+        id: &'a Option<Uuid>,
+        category: &'a str,
+        message: &'a str,
+        elapsed_ms: u64,
+    },
+}
+
+impl<'a> RecordEvent<'a> {
+    // Only finished operations are logged, so `Started` has no record.
+    fn from_event(event: &'a Event) -> Option<Self> {
+        Some(match event {
+            Event::Phase(_) | Event::Started { .. } => return None,
+            Event::Pulled {
+                path,
+                id,
+                output,
+                modified,
+                bytes,
+                sha256,
+                elapsed,
+            } => RecordEvent::Pulled {
+                path: path.to_string_lossy().into_owned(),
+                id,
+                output: output.to_string_lossy().into_owned(),
+                modified_client: modified,
+                bytes: *bytes,
+                sha256,
+                elapsed_ms: elapsed.as_millis() as u64,
+            },
+            Event::Skipped { path, id, reason } => RecordEvent::Skipped {
+                path: path.to_string_lossy().into_owned(),
+                id,
+                reason,
+            },
+            Event::Failed {
+                path,
+                id,
+                category,
+                message,
+                elapsed,
+            } => RecordEvent::Failed {
+                path: path.to_string_lossy().into_owned(),
+                id,
+                category,
+                message,
+                elapsed_ms: elapsed.as_millis() as u64,
+            },
+        })
+    }
+}
+
+pub struct JsonLog<W: io::Write> {
+    writer: W,
+    /// Where it's said that a record couldn't be written.
+    errors: Box<dyn io::Write>,
+}
+
+impl JsonLog<fs::File> {
+    pub fn append_to_path(
+        p: &Path,
+        errors: Box<dyn io::Write>,
+    ) -> io::Result<Self> {
+        let f = fs::OpenOptions::new().create(true).append(true).open(p)?;
+        Ok(JsonLog::new(f, errors))
+    }
+}
+
+impl<W: io::Write> JsonLog<W> {
+    pub fn new(writer: W, errors: Box<dyn io::Write>) -> Self {
+        JsonLog { writer, errors }
+    }
+
+    fn write_event(&mut self, event: &Event) -> io::Result<()> {
+        let event = match RecordEvent::from_event(event) {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+        let record = Record {
+            schema_version: SCHEMA_VERSION,
+            timestamp: chrono::Utc::now(),
+            event,
+        };
+        // Serialize up front so a record is written with a single call and
+        // never left half-written by a serialization error.
+        let line = serde_json::to_string(&record)?;
+        let line = format!("{}\n", redact::text(&line));
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl<W: io::Write> Observer for JsonLog<W> {
+    fn observe(&mut self, event: &Event) {
+        if let Err(e) = self.write_event(event) {
+            // With nowhere to say so either, there's nothing more to do.
+            let warning = msg!(OPERATION_LOG_FAILED, error = e.to_string());
+            let _ = writeln!(self.errors, "{}", warning);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn log_parses_back() {
+        let id = Uuid::new_v4();
+        let mut log = JsonLog::new(vec![], Box::new(io::sink()));
+        log.observe(&Event::Started {
+            path: PathBuf::from("Books/Dune"),
+        });
+        log.observe(&Event::Pulled {
+            path: PathBuf::from("Books/Dune"),
+            id,
+            output: PathBuf::from("Dune.epub"),
+            modified: "2024-03-01T12:00:00Z".parse().unwrap(),
+            bytes: 1234,
+            sha256: "abcd".to_string(),
+            elapsed: Duration::from_millis(1500),
+        });
+        log.observe(&Event::Skipped {
+            path: PathBuf::from("Missing"),
+            id: None,
+            reason: "not found".to_string(),
+        });
+        log.observe(&Event::Failed {
+            path: PathBuf::from("Books/Broken"),
+            id: Some(id),
+            category: "zip",
+            message: "invalid archive".to_string(),
+            elapsed: Duration::from_millis(20),
+        });
+
+        let output = String::from_utf8(log.writer).unwrap();
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        for r in &records {
+            assert_eq!(r["schema_version"], SCHEMA_VERSION);
+            assert!(r["timestamp"]
+                .as_str()
+                .unwrap()
+                .parse::<chrono::DateTime<chrono::Utc>>()
+                .is_ok());
+        }
+
+        assert_eq!(records[0]["event"], "pulled");
+        assert_eq!(records[0]["path"], "Books/Dune");
+        assert_eq!(records[0]["id"], id.to_string());
+        assert_eq!(records[0]["output"], "Dune.epub");
+        assert_eq!(records[0]["modified_client"], "2024-03-01T12:00:00Z");
+        assert_eq!(records[0]["bytes"], 1234);
+        assert_eq!(records[0]["sha256"], "abcd");
+        assert_eq!(records[0]["elapsed_ms"], 1500);
+
+        assert_eq!(records[1]["event"], "skipped");
+        assert!(records[1]["id"].is_null());
+        assert_eq!(records[1]["reason"], "not found");
+
+        assert_eq!(records[2]["event"], "failed");
+        assert_eq!(records[2]["category"], "zip");
+        assert_eq!(records[2]["message"], "invalid archive");
+    }
+
+    #[test]
+    fn log_appends() {
+        let dir = std::env::temp_dir()
+            .join(format!("remarkable-cloud-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let p = dir.join("log.json");
+        for _ in 0..2 {
+            let mut log =
+                JsonLog::append_to_path(&p, Box::new(io::sink())).unwrap();
+            log.observe(&Event::Skipped {
+                path: PathBuf::from("x"),
+                id: None,
+                reason: "test".to_string(),
+            });
+        }
+        let contents = fs::read_to_string(&p).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+    }
+}
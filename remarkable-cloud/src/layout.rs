@@ -0,0 +1,408 @@
+//! Where `sync pull` puts documents in the directory it syncs.
+//!
+//! A [`Layout`] names one of the ways: in folders as the cloud has them,
+//! all in the directory itself, by when they were last modified, or by
+//! their tags. Each has a [`LayoutStrategy`] saying where a document goes.
+//! The layout a directory was pulled with is kept in its manifest, since
+//! pulling with another would put a second copy of everything beside the
+//! first; `sync pull --relayout` moves what's there instead.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use remarkable_cloud_api::{Document, DocumentDetails, Documents, Parent};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::msg;
+use crate::naming::{self, Candidate};
+
+/// What `--layout` takes.
+pub const LAYOUT_VALUES: &[&str] = &["mirror", "flat", "by-date", "by-tag"];
+
+/// How documents are laid out in a synced directory.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// In folders as the cloud has them.
+    #[default]
+    Mirror,
+    /// All in the directory itself, those of the same name told apart by
+    /// their folders' names.
+    Flat,
+    /// In a folder for the year they were last modified, and in it one for
+    /// the month, as in 2024/05.
+    ByDate,
+    /// In a folder for each of their tags, hardlinked into every one if
+    /// they have several. Those without tags are in the directory itself.
+    ByTag,
+}
+
+impl Layout {
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::Mirror => "mirror",
+            Layout::Flat => "flat",
+            Layout::ByDate => "by-date",
+            Layout::ByTag => "by-tag",
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mirror" => Ok(Layout::Mirror),
+            "flat" => Ok(Layout::Flat),
+            "by-date" => Ok(Layout::ByDate),
+            "by-tag" => Ok(Layout::ByTag),
+            _ => Err(msg!(LAYOUT_UNKNOWN, layout = format!("{:?}", s))),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A path in a synced directory, without the extension of the file there.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RelativePath(Vec<String>);
+
+impl RelativePath {
+    /// The path through `parts` in turn. Those with a `/` in, or which
+    /// would name the folder they're in or the one above, are made safe to
+    /// use as names.
+    pub fn new<S: AsRef<str>>(parts: &[S]) -> Self {
+        RelativePath(parts.iter().map(|p| safe(p.as_ref())).collect())
+    }
+
+    pub fn parts(&self) -> &[String] {
+        &self.0
+    }
+
+    /// The path with the start of `id` after its name, to tell it apart
+    /// from another document's.
+    pub fn qualified(&self, id: &Uuid) -> Self {
+        let mut parts = self.0.clone();
+        if let Some(name) = parts.last_mut() {
+            name.push_str(&format!(" ({})", &id.to_string()[..8]));
+        }
+        RelativePath(parts)
+    }
+
+    /// The path of the file, with `ext`, as the manifest has it.
+    pub fn with_ext(&self, ext: &str) -> String {
+        format!("{}.{}", self, ext)
+    }
+}
+
+impl fmt::Display for RelativePath {
+    /// The parts with `/` between them, whatever the platform.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0.join("/"))
+    }
+}
+
+// `name` as the name of a file or folder.
+fn safe(name: &str) -> String {
+    match name {
+        "" | "." | ".." => "_".to_string(),
+        _ => name.replace('/', "_"),
+    }
+}
+
+/// Where a layout puts documents.
+pub trait LayoutStrategy {
+    /// Where `details`'s document goes.
+    fn local_path(&self, details: &DocumentDetails) -> RelativePath;
+
+    /// Where else it goes, as hardlinks to the file at `local_path`.
+    fn links(&self, _details: &DocumentDetails) -> Vec<RelativePath> {
+        vec![]
+    }
+
+    /// Whether it needs what's in a document's archive, rather than only
+    /// its listing.
+    fn needs_content(&self) -> bool {
+        false
+    }
+}
+
+/// The strategy for `layout`, for the documents below `folder`. Paths
+/// differing only in case are told apart as the same with `fold_case`.
+pub fn strategy(
+    layout: Layout,
+    documents: &Documents,
+    folder: Parent,
+    fold_case: bool,
+) -> Box<dyn LayoutStrategy> {
+    match layout {
+        Layout::Mirror => Box::new(Mirror::new(documents, folder)),
+        Layout::Flat => Box::new(Flat::new(documents, folder, fold_case)),
+        Layout::ByDate => Box::new(ByDate),
+        Layout::ByTag => Box::new(ByTag),
+    }
+}
+
+/// The details of `document` as its listing gives them, for strategies
+/// which don't need its archive.
+pub fn listed(document: &Document) -> DocumentDetails {
+    DocumentDetails {
+        document: document.clone(),
+        metadata: None,
+        content: None,
+        page_count: None,
+        stroke_count: None,
+        blob_size: 0,
+    }
+}
+
+// The documents below `folder`, each with the names of the folders it's in
+// there.
+fn folders_of(
+    documents: &Documents,
+    folder: Parent,
+) -> Vec<(Vec<&str>, &Document)> {
+    let mut folders: Vec<&str> = vec![];
+    let mut found = vec![];
+    for (depth, d) in documents.descendants(folder) {
+        folders.truncate(depth);
+        if d.is_folder() {
+            folders.push(&d.visible_name);
+        } else {
+            found.push((folders.clone(), d));
+        }
+    }
+    found
+}
+
+/// In folders as the cloud has them.
+pub struct Mirror {
+    /// The folders each document is in, by its id.
+    folders: HashMap<Uuid, Vec<String>>,
+}
+
+impl Mirror {
+    pub fn new(documents: &Documents, folder: Parent) -> Self {
+        let folders = folders_of(documents, folder)
+            .into_iter()
+            .map(|(folders, d)| {
+                (d.id, folders.into_iter().map(String::from).collect())
+            })
+            .collect();
+        Mirror { folders }
+    }
+}
+
+impl LayoutStrategy for Mirror {
+    fn local_path(&self, details: &DocumentDetails) -> RelativePath {
+        let doc = &details.document;
+        let mut parts = self.folders.get(&doc.id).cloned().unwrap_or_default();
+        parts.push(doc.visible_name.to_string());
+        RelativePath::new(&parts)
+    }
+}
+
+/// All in the directory itself.
+pub struct Flat {
+    /// The name each document is given, by its id.
+    names: HashMap<Uuid, String>,
+}
+
+impl Flat {
+    pub fn new(documents: &Documents, folder: Parent, fold_case: bool) -> Self {
+        let found = folders_of(documents, folder);
+        let candidates: Vec<Candidate> = found
+            .iter()
+            .map(|(folders, d)| Candidate {
+                name: &d.visible_name,
+                parent: folders.last().copied(),
+                id: d.id,
+            })
+            .collect();
+        let names = naming::disambiguate(&candidates, fold_case);
+        Flat {
+            names: candidates.iter().map(|c| c.id).zip(names).collect(),
+        }
+    }
+}
+
+impl LayoutStrategy for Flat {
+    fn local_path(&self, details: &DocumentDetails) -> RelativePath {
+        let doc = &details.document;
+        match self.names.get(&doc.id) {
+            Some(name) => RelativePath::new(&[name]),
+            None => RelativePath::new(&[&*doc.visible_name]),
+        }
+    }
+}
+
+/// By the year and month documents were last modified.
+pub struct ByDate;
+
+impl LayoutStrategy for ByDate {
+    fn local_path(&self, details: &DocumentDetails) -> RelativePath {
+        let doc = &details.document;
+        let modified = doc.modified_client;
+        RelativePath::new(&[
+            modified.format("%Y").to_string(),
+            modified.format("%m").to_string(),
+            doc.visible_name.to_string(),
+        ])
+    }
+}
+
+/// By the tags on documents.
+pub struct ByTag;
+
+impl ByTag {
+    // The tags on the document, each once, in the order it has them.
+    fn tags(details: &DocumentDetails) -> Vec<String> {
+        let mut tags: Vec<String> = vec![];
+        let all = details.content.as_ref().map(|c| c.tags());
+        for tag in all.unwrap_or_default() {
+            let tag = safe(tag);
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+}
+
+impl LayoutStrategy for ByTag {
+    fn local_path(&self, details: &DocumentDetails) -> RelativePath {
+        let name = details.document.visible_name.to_string();
+        match ByTag::tags(details).into_iter().next() {
+            Some(tag) => RelativePath::new(&[tag, name]),
+            None => RelativePath::new(&[name]),
+        }
+    }
+
+    fn links(&self, details: &DocumentDetails) -> Vec<RelativePath> {
+        let name = details.document.visible_name.to_string();
+        ByTag::tags(details)
+            .into_iter()
+            .skip(1)
+            .map(|tag| RelativePath::new(&[tag, name.clone()]))
+            .collect()
+    }
+
+    fn needs_content(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::listing;
+    use remarkable_data_formats::content::Content;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    fn tree() -> Documents {
+        listing(&[
+            (1, "Work", None, "CollectionType"),
+            (2, "Notes", Some(1), "DocumentType"),
+            (3, "Archive", Some(1), "CollectionType"),
+            (4, "Notes", Some(3), "DocumentType"),
+            (5, "Notes", None, "DocumentType"),
+            (6, "a/b", None, "DocumentType"),
+        ])
+    }
+
+    fn path_of(
+        strategy: &dyn LayoutStrategy,
+        docs: &Documents,
+        n: u128,
+    ) -> String {
+        let details = listed(docs.get(&id(n)).unwrap());
+        strategy.local_path(&details).to_string()
+    }
+
+    #[test]
+    fn mirror() {
+        let docs = tree();
+        let mirror = Mirror::new(&docs, Parent::Root);
+        assert_eq!(path_of(&mirror, &docs, 2), "Work/Notes");
+        assert_eq!(path_of(&mirror, &docs, 4), "Work/Archive/Notes");
+        assert_eq!(path_of(&mirror, &docs, 5), "Notes");
+        assert_eq!(path_of(&mirror, &docs, 6), "a_b");
+        // Below a folder, paths start from it.
+        let mirror = Mirror::new(&docs, Parent::Folder(id(1)));
+        assert_eq!(path_of(&mirror, &docs, 4), "Archive/Notes");
+    }
+
+    #[test]
+    fn flat() {
+        let docs = tree();
+        let flat = Flat::new(&docs, Parent::Root, false);
+        assert_eq!(path_of(&flat, &docs, 2), "Notes (Work)");
+        assert_eq!(path_of(&flat, &docs, 4), "Notes (Archive)");
+        assert_eq!(path_of(&flat, &docs, 5), "Notes (root)");
+        assert_eq!(path_of(&flat, &docs, 6), "a_b");
+        // Only those below the folder can collide.
+        let flat = Flat::new(&docs, Parent::Folder(id(3)), false);
+        assert_eq!(path_of(&flat, &docs, 4), "Notes");
+    }
+
+    #[test]
+    fn by_date() {
+        let docs = tree();
+        let mut details = listed(docs.get(&id(5)).unwrap());
+        assert_eq!(ByDate.local_path(&details).to_string(), "2024/01/Notes");
+        details.document.modified_client =
+            "2023-11-30T23:59:59Z".parse().unwrap();
+        assert_eq!(ByDate.local_path(&details).to_string(), "2023/11/Notes");
+        assert!(ByDate.links(&details).is_empty());
+    }
+
+    #[test]
+    fn by_tag() {
+        let docs = tree();
+        let mut details = listed(docs.get(&id(5)).unwrap());
+        assert_eq!(ByTag.local_path(&details).to_string(), "Notes");
+        assert!(ByTag.links(&details).is_empty());
+        details.content = Some(
+            Content::parse(
+                br#"{"tags": [{"name": "Work"}, {"name": "to/do"},
+                              {"name": "Work"}, {"name": ".."}]}"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(ByTag.local_path(&details).to_string(), "Work/Notes");
+        let links: Vec<String> = ByTag
+            .links(&details)
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        assert_eq!(links, ["to_do/Notes", "_/Notes"]);
+        assert!(ByTag.needs_content());
+    }
+
+    #[test]
+    fn paths() {
+        let path = RelativePath::new(&["2024", "05", "Notes"]);
+        assert_eq!(path.with_ext("pdf"), "2024/05/Notes.pdf");
+        assert_eq!(
+            path.qualified(&id(0xabcdef12 << 96)).to_string(),
+            "2024/05/Notes (abcdef12)"
+        );
+        assert_eq!(RelativePath::new(&["", "."]).to_string(), "_/_");
+        for name in LAYOUT_VALUES {
+            assert_eq!(name.parse::<Layout>().unwrap().name(), *name);
+        }
+        assert!("tree".parse::<Layout>().is_err());
+    }
+}
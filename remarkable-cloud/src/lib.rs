@@ -0,0 +1,199 @@
+//! The workings of the `remarkable-cloud` command line client, for driving
+//! its commands from another program.
+//!
+//! [`commands`] has the commands themselves, each taking its options and an
+//! [`commands::Output`] to report to instead of printing. [`cli`] is the
+//! command line they're run from and [`dispatch`] turns its arguments into
+//! their options, so the binary is only the terminal they report to. The
+//! other modules are what the commands are built from.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use remarkable_cloud_api::{
+    CloudPath, Document, Error, Parent, PathError, Resolved, Result,
+    ValidatedParent,
+};
+
+use crate::resolved::ResolvedTree;
+
+// How many times --quiet was given.
+static QUIET: AtomicU64 = AtomicU64::new(0);
+
+pub fn quiet_level() -> u64 {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn set_quiet_level(level: u64) {
+    QUIET.store(level, Ordering::Relaxed);
+}
+
+/// Prints a line about what a command is doing, which --quiet leaves out.
+/// Errors, and what a command was asked to print, don't go through this.
+#[macro_export]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::quiet_level() == 0 {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod await_document;
+pub mod backup;
+pub mod cache;
+pub mod cli;
+pub mod columns;
+pub mod commands;
+pub mod content;
+pub mod digest;
+pub mod dispatch;
+pub mod doctor;
+pub mod export;
+pub mod exporters;
+pub mod filter;
+pub mod find;
+pub mod glob;
+pub mod help;
+pub mod history;
+pub mod info;
+pub mod jsonlog;
+pub mod layout;
+pub mod limits;
+pub mod lock;
+pub mod manage;
+pub mod mappings;
+pub mod messages;
+pub mod mutations;
+pub mod naming;
+pub mod notes;
+pub mod observer;
+pub mod optimize;
+pub mod pages;
+pub mod partial;
+pub mod peek;
+pub mod plan;
+pub mod preflight;
+pub mod progress;
+pub mod push;
+pub mod queue;
+pub mod redact;
+pub mod render;
+pub mod resolved;
+pub mod scan;
+pub mod serve;
+pub mod settings;
+pub mod setup;
+pub mod sort;
+pub mod stats;
+pub mod status;
+pub mod summary;
+pub mod sync;
+pub mod targets;
+pub mod template;
+pub mod trash;
+pub mod watch;
+pub mod webhook;
+
+#[cfg(test)]
+mod testutil;
+
+pub type CliResult<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// How many documents are downloaded at once to look inside them.
+pub const DETAILS_CONCURRENCY: usize = 4;
+
+/// How many documents `push` builds the archives of and sends at once, at
+/// most; see `push::MemoryBudget`.
+pub const PUSH_CONCURRENCY: usize = 4;
+
+pub enum Location<'a> {
+    Root,
+    Trash,
+    Document(&'a Document),
+    /// Nothing is there, as the `Error::PathNotFound` says.
+    Missing(Error),
+}
+
+/// Looks up a path given on the command line, which may name the root or
+/// the trash.
+pub fn locate<'a>(
+    docs: &'a ResolvedTree,
+    path: &CloudPath,
+) -> Result<Location<'a>> {
+    match docs.lookup(path) {
+        Ok(Resolved::Root) => Ok(Location::Root),
+        Ok(Resolved::Trash) => Ok(Location::Trash),
+        Ok(Resolved::Document(d)) => Ok(Location::Document(d)),
+        Err(e @ Error::PathNotFound { .. }) => Ok(Location::Missing(e)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Looks up a document given on the command line, refusing folders.
+pub fn document_at<'a>(
+    docs: &'a ResolvedTree,
+    path: &CloudPath,
+) -> CliResult<&'a Document> {
+    match locate(docs, path)? {
+        Location::Document(d) if d.is_document() => Ok(d),
+        Location::Missing(e) => Err(e.into()),
+        _ => Err(msg!(IS_A_FOLDER, path = path.to_string()).into()),
+    }
+}
+
+/// Looks up a folder given on the command line to put documents in.
+pub fn destination(
+    docs: &ResolvedTree,
+    path: &CloudPath,
+) -> CliResult<ValidatedParent> {
+    let parent = match locate(docs, path)? {
+        Location::Root => Parent::Root,
+        Location::Trash => Parent::Trash,
+        Location::Document(d) => Parent::Folder(d.id),
+        Location::Missing(e) => return Err(e.into()),
+    };
+    Ok(docs.validate_parent(parent)?)
+}
+
+/// The kind of error `e` is, as recorded in the JSON operation log.
+pub fn error_category(e: &(dyn std::error::Error + 'static)) -> &'static str {
+    if e.is::<preflight::Problems>() {
+        return "invalid_arguments";
+    }
+    if let Some(e) = e.downcast_ref::<Error>() {
+        match e {
+            Error::EmptyResult => "empty_result",
+            Error::Rejected { .. } => "rejected",
+            Error::InvalidPath { .. } => "invalid_path",
+            Error::AmbiguousPath { .. } => "ambiguous_path",
+            Error::PathNotFound { .. } => "path_not_found",
+            Error::InvalidDestination { .. } => "invalid_destination",
+            Error::InvalidName { .. } => "invalid_name",
+            Error::ReadOnly => "read_only",
+            Error::InvalidArchive { .. } => "invalid_archive",
+            Error::NoSuchPage { .. } => "no_such_page",
+            Error::VersionMismatch { .. } => "version_mismatch",
+            Error::IoError { .. } => "io",
+            Error::Offline { .. } => "offline",
+            Error::HttpError { .. } => "http",
+            Error::AccountMigrated => "account_migrated",
+            Error::Unsupported { .. } => "unsupported",
+            Error::SessionExpired => "session_expired",
+            Error::Cancelled => "cancelled",
+            Error::DeadlineExceeded => "deadline_exceeded",
+            Error::JsonError { .. } => "json",
+            Error::ZipError { .. } => "zip",
+            Error::FormatError { .. } => "format",
+        }
+    } else if e.is::<PathError>() {
+        "invalid_path"
+    } else if e.is::<zip::result::ZipError>() {
+        "zip"
+    } else if e.is::<std::io::Error>() {
+        "io"
+    } else if e.is::<reqwest::Error>() {
+        "http"
+    } else {
+        "other"
+    }
+}
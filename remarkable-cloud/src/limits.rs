@@ -0,0 +1,85 @@
+//! What `push`, `restore` and `fsck` do about folders nested deeper, or
+//! holding more, than the tablet handles well.
+
+use remarkable_cloud_api::{Documents, TreeLimits, Violation};
+
+use crate::commands::Output;
+use crate::msg;
+use crate::CliResult;
+
+/// The limits to check what's added against, and whether going past them
+/// stops the command or is only warned about.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LimitCheck {
+    pub limits: TreeLimits,
+    pub strict: bool,
+}
+
+impl LimitCheck {
+    /// Warns about each of `violations`, then, if strict, fails so that
+    /// nothing is changed.
+    pub fn enforce(
+        &self,
+        violations: &[Violation],
+        out: &mut dyn Output,
+    ) -> CliResult<()> {
+        for violation in violations {
+            out.warn(&violation.to_string());
+        }
+        if self.strict && !violations.is_empty() {
+            return Err(msg!(LIMITS_STRICT, count = violations.len()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Reports each place `documents` goes past `limits` to `out`, as `fsck`
+/// does, then fails if there are any.
+pub fn fsck(
+    limits: &TreeLimits,
+    documents: &Documents,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let violations = limits.check(documents);
+    for violation in &violations {
+        out.line(&violation.to_string());
+    }
+    if !violations.is_empty() {
+        return Err(msg!(LIMITS_PAST, count = violations.len()).into());
+    }
+    out.note(&msg!(LIMITS_NONE_PAST));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Capture;
+    use crate::summary::TransferReport;
+
+    #[test]
+    fn strict() {
+        let violations = [Violation::TooWide {
+            path: "Scans".to_string(),
+            children: 2001,
+            limit: 2000,
+        }];
+        let mut out = Capture::new(TransferReport::new());
+        let check = LimitCheck::default();
+        assert!(check.enforce(&violations, &mut out).is_ok());
+        assert!(check.enforce(&[], &mut out).is_ok());
+        assert_eq!(
+            out.warnings,
+            ["Scans holds 2001 items, more than the 2000 the tablet handles \
+              well"]
+        );
+
+        let check = LimitCheck {
+            strict: true,
+            ..check
+        };
+        assert!(check.enforce(&[], &mut out).is_ok());
+        let e = check.enforce(&violations, &mut out).unwrap_err();
+        assert!(e.to_string().contains("1 place would"), "{}", e);
+    }
+}
@@ -0,0 +1,168 @@
+//! The lock on a profile, so that runs which overlap, as from cron, don't
+//! change documents or state underneath each other.
+//!
+//! Commands which change anything hold the lock exclusively for as long as
+//! they run, and the others share it. Whoever holds it exclusively writes
+//! their pid into the lock file, for the error anyone else gets. Unless
+//! given `--wait-lock`, a command finding the lock taken fails straight
+//! away rather than queueing up behind it.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use remarkable_cloud_api::FileLock;
+
+use crate::commands::Output;
+use crate::msg;
+use crate::CliResult;
+
+/// The file in the config directory which is locked.
+pub const LOCK_FILE: &str = "lock";
+
+/// How often a lock which is taken is tried again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// For commands which only read, which can run alongside each other.
+    Shared,
+    /// For commands which change anything, which run alone.
+    Exclusive,
+}
+
+pub struct ProfileLock {
+    path: PathBuf,
+}
+
+/// A profile's lock, held until dropped.
+pub struct HeldLock {
+    lock: FileLock,
+    mode: LockMode,
+}
+
+impl ProfileLock {
+    pub fn new(path: PathBuf) -> Self {
+        ProfileLock { path }
+    }
+
+    /// Takes the lock, waiting up to `wait` for whoever has it to let it
+    /// go, or for as long as that takes if `None`, and telling `out` so.
+    pub async fn acquire(
+        &self,
+        mode: LockMode,
+        wait: Option<Duration>,
+        out: &mut dyn Output,
+    ) -> CliResult<HeldLock> {
+        let deadline = wait.map(|wait| Instant::now() + wait);
+        let mut waiting = false;
+        loop {
+            let lock = match mode {
+                LockMode::Shared => FileLock::try_shared(&self.path)?,
+                LockMode::Exclusive => FileLock::try_exclusive(&self.path)?,
+            };
+            if let Some(lock) = lock {
+                if mode == LockMode::Exclusive {
+                    let mut file = lock.file();
+                    file.set_len(0)?;
+                    writeln!(file, "{}", std::process::id())?;
+                }
+                return Ok(HeldLock { lock, mode });
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(self.describe_holder().into());
+            }
+            if !waiting {
+                waiting = true;
+                out.warn(&msg!(LOCK_WAITING, holder = self.holder()));
+            }
+            tokio::time::delay_for(POLL_INTERVAL).await;
+        }
+    }
+
+    // The pid of whoever holds the lock exclusively, if they've written it.
+    fn holder_pid(&self) -> Option<u32> {
+        std::fs::read_to_string(&self.path)
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    fn holder(&self) -> String {
+        match self.holder_pid() {
+            Some(pid) => msg!(LOCK_HOLDER_PID, pid = pid),
+            None => msg!(LOCK_HOLDER),
+        }
+    }
+
+    fn describe_holder(&self) -> String {
+        match self.holder_pid() {
+            Some(pid) => msg!(LOCK_HELD_PID, pid = pid),
+            None => msg!(LOCK_HELD),
+        }
+    }
+}
+
+impl Drop for HeldLock {
+    fn drop(&mut self) {
+        // The pid goes with the lock, so as not to be blamed for a later
+        // holder who doesn't write one.
+        if self.mode == LockMode::Exclusive {
+            let _ = self.lock.file().set_len(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Capture;
+    use crate::observer::Observers;
+
+    #[tokio::test]
+    async fn held() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock = ProfileLock::new(dir.path().join(LOCK_FILE));
+        let mut out = Capture::new(Observers::new());
+        let held = lock
+            .acquire(LockMode::Exclusive, None, &mut out)
+            .await
+            .unwrap();
+        let pid = std::process::id().to_string();
+        let zero = Some(Duration::from_secs(0));
+        let e = lock
+            .acquire(LockMode::Shared, zero, &mut out)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.to_string(),
+            format!(
+                "another remarkable-cloud process holds the lock (pid {}); \
+                 give --wait-lock to wait for it",
+                pid
+            )
+        );
+        drop(held);
+        assert_eq!(std::fs::read(dir.path().join(LOCK_FILE)).unwrap(), b"");
+
+        let shared = lock
+            .acquire(LockMode::Shared, zero, &mut out)
+            .await
+            .unwrap();
+        let _also = lock
+            .acquire(LockMode::Shared, zero, &mut out)
+            .await
+            .unwrap();
+        let e = lock
+            .acquire(LockMode::Exclusive, zero, &mut out)
+            .await
+            .err()
+            .unwrap();
+        assert!(e.to_string().starts_with(
+            "another remarkable-cloud process holds the lock; give"
+        ));
+        drop(shared);
+    }
+}
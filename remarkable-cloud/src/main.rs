@@ -1,241 +1,238 @@
-use std::fs;
+use std::cell::RefCell;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
-
-use directories::ProjectDirs;
-use zip::ZipArchive;
+use std::rc::Rc;
 
 use remarkable_cloud_api::*;
+use remarkable_cloud_cli::cache::Refresher;
+use remarkable_cloud_cli::commands::Output;
+use remarkable_cloud_cli::dispatch::{self, Console, Profile};
+use remarkable_cloud_cli::jsonlog::JsonLog;
+use remarkable_cloud_cli::observer::{Event, Observer, Observers};
+use remarkable_cloud_cli::progress::PhaseDisplay;
+use remarkable_cloud_cli::summary::{self, TransferReport};
+use remarkable_cloud_cli::{
+    await_document, cli, content, messages, msg, preflight, push, redact, say,
+    sync, targets,
+};
+use remarkable_cloud_cli::{quiet_level, set_quiet_level, CliResult};
 
-fn print_documents(
-    docs: &Documents,
-    path: &Option<&Path>,
-    recurse: bool,
-    prefix: &str,
-) {
-    let doc_id = match path {
-        None => None,
-        Some(p) => match p.to_string_lossy().into_owned().as_str() {
-            "/" => None,
-            _ => match docs.get_by_path(p) {
-                None => {
-                    println!("Couldn't find {:?}", p);
-                    return;
-                }
-                Some(d) => Some(d.id),
-            },
-        },
-    };
-    for doc in docs.get_children(&doc_id) {
-        println!("{}{} {}", prefix, doc.visible_name, doc.id);
-        if recurse {
-            let p = path.map_or_else(
-                || PathBuf::from(&doc.visible_name),
-                |p| p.join(&doc.visible_name),
+// Prints what commands report, passing their events on to the observers.
+struct Terminal {
+    observers: Observers,
+    /// What ends each line printed: a newline, or a NUL with --print0.
+    end: &'static str,
+    /// Whether to ask on stdin what to do about conflicts.
+    interactive: bool,
+}
+
+impl Observer for Terminal {
+    fn observe(&mut self, event: &Event) {
+        self.observers.observe(event);
+    }
+}
+
+impl Output for Terminal {
+    fn line(&mut self, line: &str) {
+        print!("{}{}", redact::text(line), self.end);
+    }
+
+    fn note(&mut self, note: &str) {
+        say!("{}", redact::text(note));
+    }
+
+    fn warn(&mut self, warning: &str) {
+        eprintln!("{}", redact::text(warning));
+    }
+
+    // Without a terminal to ask on, the file is skipped with a warning.
+    fn on_conflict(
+        &mut self,
+        name: &str,
+        conflict: &Conflict,
+    ) -> std::io::Result<push::OnConflict> {
+        if self.interactive {
+            return push::ask(
+                name,
+                conflict,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stdout(),
             );
-            print_documents(
-                &docs,
-                &Some(p.as_path()),
-                recurse,
-                &format!("{}  ", prefix),
+        }
+        let existing = format!("{:?}", conflict.existing.visible_name);
+        eprintln!(
+            "{}",
+            msg!(PUSH_CONFLICT_SKIPPED, name = name, existing = existing)
+        );
+        Ok(push::OnConflict::Skip)
+    }
+
+    fn on_sync_conflict(
+        &mut self,
+        path: &str,
+        kind: sync::ConflictKind,
+    ) -> std::io::Result<Option<sync::Strategy>> {
+        if self.interactive {
+            return sync::ask(
+                path,
+                kind,
+                &mut std::io::stdin().lock(),
+                &mut std::io::stdout(),
             );
         }
+        eprintln!(
+            "{}",
+            msg!(SYNC_CONFLICT_LEFT, path = path, kind = kind.describe())
+        );
+        Ok(None)
     }
-}
 
-fn add_ext_to_path(path: &Path, ext: &str) -> PathBuf {
-    let mut buf = path.to_path_buf();
-    let mut newext = path.extension().unwrap_or_default().to_os_string();
-    if newext.len() > 0 {
-        newext.push(".");
-    }
-    newext.push(ext);
-    buf.set_extension(newext);
-    buf
+    fn confirm(&mut self, question: &str) -> std::io::Result<bool> {
+        targets::ask(
+            question,
+            &mut std::io::stdin().lock(),
+            &mut std::io::stdout(),
+        )
+    }
 }
 
-fn paths_from_arg<'a>(
-    matches: &'a clap::ArgMatches,
-    arg_name: &str,
-) -> Box<dyn Iterator<Item = &'a Path> + 'a> {
-    paths_from_arg_or(matches, arg_name, None)
-}
+async fn run(
+    report: Rc<RefCell<TransferReport>>,
+    cancellation: CancellationToken,
+    refresher: &Refresher,
+) -> CliResult<()> {
+    let matches = cli::app().get_matches();
+    // Asked for by name, a language that can't be had is an error; picked up
+    // from the environment, it's English instead.
+    match matches.value_of("lang") {
+        Some(lang) => messages::set_language(lang)?,
+        None => {
+            if let Some(lang) = messages::environment_language() {
+                let _ = messages::set_language(&lang);
+            }
+        }
+    }
+    if let ("help", Some(sub_m)) = matches.subcommand() {
+        let words: Vec<&str> = sub_m
+            .values_of("topic")
+            .map(|v| v.collect())
+            .unwrap_or_default();
+        print!("{}", cli::help_text(&words)?);
+        return Ok(());
+    }
+    preflight::check(&matches)?;
 
-fn paths_from_arg_or<'a>(
-    matches: &'a clap::ArgMatches,
-    arg_name: &str,
-    default: Option<&'a Path>,
-) -> Box<dyn Iterator<Item = &'a Path> + 'a> {
-    match matches.values_of(arg_name) {
-        Some(i) => Box::new(i.map(Path::new)),
-        None => match default {
-            Some(d) => Box::new(std::iter::once(d)),
-            None => Box::new(std::iter::empty()),
-        },
+    set_quiet_level(matches.occurrences_of("quiet"));
+    if matches.is_present("redact") {
+        redact::enable(matches.value_of("redact-map").map(PathBuf::from));
     }
-}
+    let mut observers = Observers::new();
+    observers.add(Box::new(report));
+    observers.add(Box::new(PhaseDisplay));
+    if let Some(p) = matches.value_of("log-json") {
+        let errors = Box::new(std::io::stderr());
+        observers.add(Box::new(JsonLog::append_to_path(Path::new(p), errors)?));
+    }
+    let typing = std::io::stdin().is_terminal();
+    let mut terminal = Terminal {
+        observers,
+        end: dispatch::line_end(&matches),
+        interactive: dispatch::asks(&matches) && typing,
+    };
 
-async fn get_client(state_path: &Path) -> Result<Client> {
-    let mut client = Client::new(
-        ClientState::new(),
-        reqwest::Client::builder()
-            .user_agent("remarkable-cloud")
-            .build()?,
-    );
-    client.state().load_from_path(state_path)?;
-    client.refresh_token().await?;
-    Ok(client)
+    let profile =
+        Profile::open(&matches, refresher, cancellation, &mut terminal).await?;
+    // Not a lock on stdin, which would be held for the whole command and
+    // keep `terminal` from asking on it.
+    let mut console = Console {
+        input: &mut std::io::BufReader::new(std::io::stdin()),
+        output: &mut std::io::stdout(),
+        typing,
+    };
+    dispatch::run(&matches, profile, &mut console, &mut terminal).await?;
+    std::io::stdout().flush()?;
+    Ok(())
 }
 
 #[tokio::main]
-async fn main() -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let matches = clap::App::new("reMarkable cloud cli")
-        .subcommand(
-            clap::SubCommand::with_name("ls")
-                .about("Lists files.")
-                .arg(clap::Arg::with_name("recurse")
-                     .short("r")
-                     .long("recursive")
-                     .help("Lists files recursively"))
-                // TODO: accept multiple paths
-                .arg(clap::Arg::with_name("paths")
-                     .index(1)
-                     .multiple(true)),
-        )
-        .subcommand(
-            clap::SubCommand::with_name("info")
-                .about("Describes a file in detail.")
-                // TODO: accept multiple files
-                .arg(clap::Arg::with_name("filenames")
-                     .index(1)
-                     .multiple(true)
-                     .required(true)),
-        )
-        .subcommand(
-            clap::SubCommand::with_name("pull")
-                .about("Downloads files.")
-                .arg(clap::Arg::with_name("raw-zip")
-                     .long("raw-zip")
-                     .hidden(true)
-                     .help("Gets the raw .zip from the API rather than extracting the document. Mostly useful for development."))
-                .setting(clap::AppSettings::TrailingVarArg)
-                .arg(clap::Arg::with_name("filenames")
-                     .index(1)
-                     .multiple(true)
-                     .required(true)),
-        )
-        .get_matches();
-
-    let project_dirs =
-        match ProjectDirs::from("zone", "ounce", "remarkable-cloud") {
-            Some(x) => x,
-            None => panic!("Could not determine settings directory."),
-        };
-    let config_dir = project_dirs.config_dir();
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir)?;
-    }
-    let client_state_path = config_dir.join("client_state.json");
-
-    match matches.subcommand() {
-        ("ls", Some(sub_m)) => {
-            let client = get_client(&client_state_path).await?;
-            let documents = client.get_documents().await?;
-            for path in paths_from_arg_or(sub_m, "paths", Some(Path::new("/")))
-            {
-                print_documents(
-                    &documents,
-                    &Some(&path),
-                    sub_m.is_present("recurse"),
-                    "",
-                );
+async fn main() {
+    let report = Rc::new(RefCell::new(TransferReport::new()));
+    let cancellation = CancellationToken::new();
+    let token = cancellation.clone();
+    // The first Ctrl-C stops what's talking to the cloud, leaving the
+    // command to finish up as it would after any error; a second one, as
+    // when that's stuck elsewhere, exits there and then.
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            token.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
             }
         }
-        ("info", Some(sub_m)) => {
-            let client = get_client(&client_state_path).await?;
-            let documents = client.get_documents().await?;
-            for filepath in paths_from_arg(sub_m, "filenames") {
-                match documents.get_by_path(&filepath) {
-                    Some(d) => println!("{:?}", d),
-                    None => println!("Couldn't find document '{:?}'", filepath),
-                }
-            }
+    });
+    let refresher = Refresher::new();
+    let result = run(report.clone(), cancellation, &refresher).await;
+    if let Err(e) = content::flush() {
+        eprintln!("{}", msg!(CONTENT_CACHE_NOT_SAVED, error = e.to_string()));
+    }
+    if let Some(note) = refresher.finish().await {
+        if quiet_level() == 0 {
+            eprintln!("{}", note);
+        }
+    }
+    if let Err(e) = redact::finish() {
+        eprintln!("{}", msg!(REDACTION_MAP_NOT_WRITTEN, error = e.to_string()));
+    }
+    let report = report.borrow();
+    if !report.is_empty() && quiet_level() < 2 {
+        for line in summary::render(&report.summary()) {
+            println!("{}", line);
+        }
+    }
+    if let Err(e) = result {
+        if let Some(problems) = e.downcast_ref::<preflight::Problems>() {
+            eprintln!("{}", msg!(ERROR, error = problems.to_string()));
+            std::process::exit(2);
+        }
+        if let Some(e) = e.downcast_ref::<await_document::TimedOut>() {
+            eprintln!("{}", msg!(ERROR, error = redact::text(&e.to_string())));
+            std::process::exit(await_document::TIMED_OUT_EXIT_CODE);
         }
-        ("pull", Some(sub_m)) => {
-            let client = get_client(&client_state_path).await?;
-            let documents = client.get_documents().await?;
-            for filepath in paths_from_arg(sub_m, "filenames") {
-                let docbytes = match documents.get_by_path(&filepath) {
-                    None => {
-                        println!("Couldn't find document '{:?}'", filepath);
-                        continue;
-                    }
-                    Some(doc) => {
-                        let blobdoc =
-                            client.get_document_by_id(&doc.id).await?;
-                        //println!("{:?}", blobdoc);
-                        // TODO: add progress indicator
-                        client
-                            .http()
-                            .get(&blobdoc.blob_url_get)
-                            .send()
-                            .await?
-                            .bytes()
-                            .await?
-                    }
-                };
-                match sub_m.is_present("raw-zip") {
-                    true => {
-                        let fp = add_ext_to_path(filepath, "zip");
-                        match fp.file_name() {
-                            Some(fpn) => fs::write(fpn, docbytes)?,
-                            None => {
-                                println!("No filename found in path {:?}", fp)
-                            }
-                        }
-                    }
-                    false => {
-                        let mut za =
-                            ZipArchive::new(std::io::Cursor::new(docbytes))?;
-                        let opt_f = za
-                            .file_names()
-                            .find(|i| i.ends_with(".epub"))
-                            .or_else(|| {
-                                za.file_names().find(|i| i.ends_with(".pdf"))
-                            });
-                        let f = match opt_f {
-                            Some(f) => f,
-                            None => {
-                                println!(
-                                    "No file found in response for {:?}",
-                                    filepath
-                                );
-                                continue;
-                            }
-                        }
-                        .to_string();
-                        let ext = Path::new(&f)
-                            .extension()
-                            .unwrap_or_default()
-                            .to_string_lossy();
-                        let fp = add_ext_to_path(filepath, &ext);
-                        println!("DEBUG: {:?}", fp);
-                        // TODO: Handle overwriting
-                        match fp.file_name() {
-                            Some(fpn) => {
-                                std::io::copy(
-                                    &mut za.by_name(&f)?,
-                                    &mut fs::File::create(fpn)?,
-                                )?;
-                            }
-                            None => {
-                                println!("No filename found in path {:?}", fp)
-                            }
-                        }
-                    }
+        match e.downcast_ref::<Error>() {
+            Some(Error::AccountMigrated) => {
+                eprintln!();
+                let migrated =
+                    msg!(ACCOUNT_MIGRATED, url = MIGRATION_ISSUES_URL);
+                for line in migrated.lines() {
+                    eprintln!("  {}", line);
                 }
+                eprintln!();
+            }
+            Some(Error::Cancelled) => eprintln!("{}", msg!(INTERRUPTED)),
+            Some(e @ Error::Unsupported { .. }) => {
+                eprintln!("{}", msg!(ERROR, error = e.to_string()))
             }
+            Some(e @ Error::DeadlineExceeded) => {
+                eprintln!("{}", msg!(ERROR_MAX_TIME, error = e.to_string()))
+            }
+            Some(e @ Error::Offline { .. }) => {
+                eprintln!("{}", msg!(ERROR, error = e.to_string()));
+                eprintln!("{}", msg!(OFFLINE_HINT));
+            }
+            // Paths are what the user typed, so these say what to fix.
+            Some(
+                e @ (Error::InvalidPath { .. }
+                | Error::PathNotFound { .. }
+                | Error::AmbiguousPath { .. }),
+            ) => eprintln!(
+                "{}",
+                msg!(ERROR, error = redact::text(&e.to_string()))
+            ),
+            _ => eprintln!(
+                "{}",
+                msg!(ERROR, error = redact::text(&format!("{:?}", e)))
+            ),
         }
-        _ => panic!("Subcommand not found."),
+        std::process::exit(1);
     }
-    Ok(())
 }
@@ -0,0 +1,351 @@
+//! The commands which change the documents `targets` picks: `mv`, `trash`,
+//! `rm`, `pin` and `unpin`.
+//!
+//! Each one expands its patterns, checks the targets against the cloud when
+//! the listing is the cached one, and asks before going ahead unless told
+//! not to.
+
+use std::collections::{HashMap, HashSet};
+
+use remarkable_cloud_api::{
+    Client, CloudPath, DeleteOutcome, Document, Documents, MetadataPatch,
+    Parent, UpdateOutcome,
+};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::messages::{self, Message};
+use crate::mutations::MutationLog;
+use crate::progress::Progress;
+use crate::resolved::ResolvedTree;
+use crate::{destination, locate, CliResult, Location};
+use crate::{msg, targets};
+
+/// The documents a command acts on, and how sure to be of them first.
+pub struct Selection {
+    /// Paths or glob patterns, as `targets::expand` takes them.
+    pub patterns: Vec<String>,
+    /// Whether patterns which match nothing are let through.
+    pub allow_empty: bool,
+    /// Whether the listing is the cached one, so that the targets are
+    /// checked against the cloud before they're changed.
+    pub cached: bool,
+    /// Whether to go ahead without asking.
+    pub yes: bool,
+}
+
+impl Selection {
+    // The documents the patterns pick out of `documents`.
+    fn expand<'a>(
+        &self,
+        documents: &'a ResolvedTree,
+        out: &mut dyn Output,
+    ) -> CliResult<Vec<(String, &'a Document)>> {
+        let patterns: Vec<&str> =
+            self.patterns.iter().map(String::as_str).collect();
+        Ok(targets::expand(
+            documents,
+            &patterns,
+            self.allow_empty,
+            out,
+        )?)
+    }
+
+    // Fails if the listing is the cached one and any of `targets` has
+    // changed in the cloud since.
+    async fn check(
+        &self,
+        client: &Client,
+        targets: &[(String, &Document)],
+    ) -> CliResult<()> {
+        if self.cached {
+            targets::check_unchanged(client, targets).await?;
+        }
+        Ok(())
+    }
+
+    // Asks `out` whether to `action` the `targets`, unless told to go
+    // ahead.
+    fn confirm(
+        &self,
+        action: &Message,
+        targets: &[(String, &Document)],
+        out: &mut dyn Output,
+    ) -> std::io::Result<bool> {
+        let confirmed = targets::confirm(action, targets, self.yes, out)?;
+        if !confirmed {
+            out.line(&msg!(NOTHING_DONE));
+        }
+        Ok(confirmed)
+    }
+}
+
+/// Moves what `selection` picks into the folder at `dest`, or, if there's
+/// nothing there and it picks a single document, to exactly that path.
+pub async fn mv(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    selection: &Selection,
+    dest: &CloudPath,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let targets = selection.expand(documents, out)?;
+    selection.check(client, &targets).await?;
+    // Moving into a folder keeps names; otherwise a single source is moved
+    // to exactly the path given.
+    let (parent, rename) = match locate(documents, dest)? {
+        Location::Missing(e)
+            if selection.patterns.len() == 1 && targets.len() == 1 =>
+        {
+            let (folder, name) = dest.split_last().ok_or(e)?;
+            let parent = destination(documents, &folder)?;
+            (parent.parent(), Some(name.to_string()))
+        }
+        _ => (destination(documents, dest)?.parent(), None),
+    };
+    if let Parent::Folder(folder) = parent {
+        for (path, doc) in &targets {
+            if doc.id == folder || documents.is_ancestor_of(&doc.id, &folder)? {
+                let path = format!("{:?}", path);
+                return Err(msg!(MOVE_INTO_ITSELF, path = path).into());
+            }
+        }
+    }
+    if !selection.confirm(&messages::CONFIRM_MOVE, &targets, out)? {
+        return Ok(());
+    }
+    change_selection(
+        client,
+        mutations,
+        (&messages::MOVE_FAILED, &messages::MOVED),
+        &targets,
+        |doc| MetadataPatch {
+            parent: Some(parent),
+            visible_name: Some(
+                rename
+                    .clone()
+                    .unwrap_or_else(|| doc.visible_name.to_string()),
+            ),
+            ..Default::default()
+        },
+        out,
+    )
+    .await
+}
+
+/// Moves what `selection` picks to the trash.
+pub async fn trash(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    selection: &Selection,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let targets = selection.expand(documents, out)?;
+    selection.check(client, &targets).await?;
+    if !selection.confirm(&messages::CONFIRM_TRASH, &targets, out)? {
+        return Ok(());
+    }
+    change_selection(
+        client,
+        mutations,
+        (&messages::TRASH_FAILED, &messages::TRASHED),
+        &targets,
+        |_| MetadataPatch {
+            parent: Some(Parent::Trash),
+            ..Default::default()
+        },
+        out,
+    )
+    .await
+}
+
+/// Deletes what `selection` picks for good. Folders must be empty, or have
+/// all they hold picked too, unless `recursive`.
+pub async fn rm(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    selection: &Selection,
+    recursive: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let targets = selection.expand(documents, out)?;
+    selection.check(client, &targets).await?;
+    if recursive {
+        let selected = targets::with_contents(documents, &targets);
+        if !selection.confirm(&messages::CONFIRM_DELETE, &selected, out)? {
+            return Ok(());
+        }
+        let roots = targets::topmost(documents, &targets);
+        return delete_subtrees(
+            client, mutations, documents, &roots, &selected, out,
+        )
+        .await;
+    }
+    let ids: HashSet<Uuid> = targets.iter().map(|(_, d)| d.id).collect();
+    for (path, doc) in &targets {
+        let children = documents.get_children(&Some(doc.id));
+        if children.iter().any(|c| !ids.contains(&c.id)) {
+            let path = format!("{:?}", path);
+            return Err(msg!(RM_FOLDER_NOT_EMPTY, path = path).into());
+        }
+    }
+    if !selection.confirm(&messages::CONFIRM_DELETE, &targets, out)? {
+        return Ok(());
+    }
+    // Children go before their folders.
+    let mut targets = targets;
+    targets.sort_by(|a, b| b.0.cmp(&a.0));
+    for (path, doc) in &targets {
+        client.delete_document(doc).await?;
+        mutations.record_delete(doc.id, &doc.visible_name, out);
+        out.note(&msg!(DELETED, path = path));
+    }
+    Ok(())
+}
+
+/// Pins what `selection` picks, or with `pinned` false, unpins it. Only
+/// documents can be.
+pub async fn pin(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    selection: &Selection,
+    pinned: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let targets = selection.expand(documents, out)?;
+    if let Some((path, _)) = targets.iter().find(|(_, d)| !d.is_document()) {
+        let path = format!("{:?}", path);
+        return Err(if pinned {
+            msg!(PIN_FOLDER, path = path)
+        } else {
+            msg!(UNPIN_FOLDER, path = path)
+        }
+        .into());
+    }
+    selection.check(client, &targets).await?;
+    let action = if pinned {
+        &messages::CONFIRM_PIN
+    } else {
+        &messages::CONFIRM_UNPIN
+    };
+    if !selection.confirm(action, &targets, out)? {
+        return Ok(());
+    }
+    for (path, doc) in &targets {
+        client.set_pinned(doc, pinned).await?;
+        mutations.record_upload(
+            doc.id,
+            &doc.visible_name,
+            doc.version + 1,
+            out,
+        );
+        out.note(&if pinned {
+            msg!(PINNED, path = path)
+        } else {
+            msg!(UNPINNED, path = path)
+        });
+    }
+    Ok(())
+}
+
+/// Deletes each of `roots` along with everything below it, reporting each
+/// document by its path in `selected` to `out`, then fails if any are still
+/// there.
+pub async fn delete_subtrees(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &Documents,
+    roots: &[&Document],
+    selected: &[(String, &Document)],
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let paths: HashMap<Uuid, &str> =
+        selected.iter().map(|(p, d)| (d.id, p.as_str())).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_DELETED), selected.len());
+    let mut remaining = 0;
+    for root in roots {
+        let report = client
+            .delete_subtree(root, documents, |doc, outcome| {
+                progress.tick();
+                let path = paths.get(&doc.id).copied().unwrap_or_default();
+                match outcome {
+                    DeleteOutcome::Deleted | DeleteOutcome::AlreadyGone => {
+                        if *outcome == DeleteOutcome::Deleted {
+                            mutations.record_delete(
+                                doc.id,
+                                &doc.visible_name,
+                                out,
+                            );
+                        }
+                        progress.clear();
+                        out.note(&msg!(DELETED, path = path));
+                    }
+                    DeleteOutcome::Failed(message) => {
+                        progress.clear();
+                        out.warn(&msg!(
+                            DELETE_FAILED,
+                            path = path,
+                            error = message,
+                        ));
+                    }
+                    DeleteOutcome::Skipped => {
+                        progress.clear();
+                        out.warn(&msg!(DELETE_SKIPPED, path = path));
+                    }
+                }
+            })
+            .await?;
+        remaining += report.remaining().count();
+    }
+    if remaining > 0 {
+        return Err(msg!(DELETE_REMAINING, count = remaining).into());
+    }
+    Ok(())
+}
+
+// Makes the metadata change `patch` gives for each of `targets` in bulk,
+// recording and reporting each one made to `out`, then fails if any
+// weren't.
+async fn change_selection<F>(
+    client: &Client,
+    mutations: &MutationLog,
+    (failure, done): (&Message, &Message),
+    targets: &[(String, &Document)],
+    patch: F,
+    out: &mut dyn Output,
+) -> CliResult<()>
+where
+    F: Fn(&Document) -> MetadataPatch,
+{
+    let changes = targets.iter().map(|(_, d)| (d.id, patch(d))).collect();
+    let outcomes = client.update_metadata_bulk(changes).await?;
+    let mut failed: usize = 0;
+    for ((path, _), (_, outcome)) in targets.iter().zip(outcomes) {
+        match outcome {
+            UpdateOutcome::Updated(change) => {
+                mutations.record_change(&change, None, out);
+                out.note(&messages::render(done, &[("path", path.into())]));
+            }
+            UpdateOutcome::Failed(message) => {
+                out.warn(&messages::render(
+                    failure,
+                    &[("path", path.into()), ("error", message.into())],
+                ));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(msg!(
+            CHANGE_FAILED,
+            failed = failed,
+            count = targets.len(),
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,377 @@
+//! Where `push` puts files it isn't given `--to` for: the folder of the
+//! mapping whose local directory most closely holds the file, or else a
+//! default folder, as set in `settings.json`:
+//!
+//! ```json
+//! {"push": {"mappings": {"~/papers": "/Papers", "~/scans": "/Inbox"},
+//!           "default_folder": "/Unsorted"}}
+//! ```
+//!
+//! A directory holds the files below it however they're named on the
+//! command line: relative to the working directory, through `..`, or by
+//! way of a symlink to or from the directory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+use remarkable_cloud_api::CloudPath;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::limits::LimitCheck;
+use crate::resolved::ResolvedTree;
+use crate::{destination, locate, msg, push, CliResult, Location};
+
+/// The `push` section of the settings.
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct PushSettings {
+    /// Cloud folders by the local directory whose files go to them, which
+    /// may start with `~`.
+    pub mappings: BTreeMap<String, String>,
+    /// Where files no mapping covers go, rather than the root.
+    pub default_folder: Option<String>,
+}
+
+struct Rule {
+    written: String,
+    local: PathBuf,
+    // Where `local` leads once its symlinks are followed, if it exists.
+    canonical: Option<PathBuf>,
+    folder: String,
+}
+
+pub struct Mappings {
+    rules: Vec<Rule>,
+    default_folder: Option<String>,
+}
+
+/// The folder chosen for a file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Choice<'a> {
+    pub folder: &'a str,
+    /// The local directory of the mapping which applied, as written in the
+    /// settings, or `None` for the default folder.
+    pub rule: Option<&'a str>,
+}
+
+impl Choice<'_> {
+    /// Why the folder was chosen, to follow "by".
+    pub fn describe(&self) -> String {
+        match self.rule {
+            Some(rule) => msg!(MAPPING_RULE, rule = rule),
+            None => msg!(MAPPING_DEFAULT),
+        }
+    }
+
+    /// What's said of `file` going to the folder.
+    pub fn note(&self, file: &Path) -> String {
+        msg!(
+            MAPPING_GOES_TO,
+            file = file.display().to_string(),
+            folder = self.folder,
+            by = self.describe(),
+        )
+    }
+}
+
+impl Mappings {
+    /// The rules in `settings`, with `~` standing for `home`.
+    pub fn new(
+        settings: &PushSettings,
+        home: Option<&Path>,
+    ) -> Result<Self, String> {
+        let mut rules = vec![];
+        for (written, folder) in &settings.mappings {
+            let local = expand(written, home)?;
+            rules.push(Rule {
+                written: written.clone(),
+                canonical: local.canonicalize().ok(),
+                local,
+                folder: folder.clone(),
+            });
+        }
+        Ok(Mappings {
+            rules,
+            default_folder: settings.default_folder.clone(),
+        })
+    }
+
+    pub fn default_folder(&self) -> Option<&str> {
+        self.default_folder.as_deref()
+    }
+
+    /// The folder `file` goes to, with relative paths taken from `cwd`, or
+    /// `None` for the root.
+    pub fn choose(&self, file: &Path, cwd: &Path) -> Option<Choice<'_>> {
+        let absolute = cwd.join(file);
+        let mut paths = vec![normalize(&absolute)];
+        paths.extend(absolute.canonicalize().ok());
+        let mut best: Option<(usize, &Rule)> = None;
+        for rule in &self.rules {
+            let depth = std::iter::once(&rule.local)
+                .chain(&rule.canonical)
+                .filter(|dir| paths.iter().any(|p| p.starts_with(dir)))
+                .map(|dir| dir.components().count())
+                .max();
+            if let Some(depth) = depth {
+                if best.is_none_or(|(most, _)| depth > most) {
+                    best = Some((depth, rule));
+                }
+            }
+        }
+        match best {
+            Some((_, rule)) => Some(Choice {
+                folder: &rule.folder,
+                rule: Some(&rule.written),
+            }),
+            None => self
+                .default_folder
+                .as_deref()
+                .map(|folder| Choice { folder, rule: None }),
+        }
+    }
+
+    /// Sorts `files` by the folder each goes to, noting which rule applied,
+    /// in the order the folders first come up.
+    pub fn plan(
+        &self,
+        files: Vec<PathBuf>,
+        cwd: &Path,
+        out: &mut dyn Output,
+    ) -> Vec<(Option<String>, Vec<PathBuf>)> {
+        let mut groups: Vec<(Option<String>, Vec<PathBuf>)> = vec![];
+        for file in files {
+            let folder = self.choose(&file, cwd).map(|choice| {
+                out.note(&choice.note(&file));
+                choice.folder.to_string()
+            });
+            match groups.iter_mut().find(|(f, _)| *f == folder) {
+                Some((_, group)) => group.push(file),
+                None => groups.push((folder, vec![file])),
+            }
+        }
+        groups
+    }
+}
+
+/// Looks up the folders `groups` go to, before anything is pushed. Folders
+/// which don't exist are an error listing them all, unless
+/// `create_missing`, when they're made, once `limits` allows.
+pub async fn resolve(
+    client: &remarkable_cloud_api::Client,
+    documents: &ResolvedTree,
+    groups: Vec<(Option<String>, Vec<PathBuf>)>,
+    create_missing: bool,
+    limits: &LimitCheck,
+    out: &mut dyn Output,
+) -> CliResult<Vec<(Option<Uuid>, Vec<PathBuf>)>> {
+    let mut ids: HashMap<&str, Option<Uuid>> = HashMap::new();
+    let mut missing = vec![];
+    for folder in groups.iter().filter_map(|(f, _)| f.as_deref()) {
+        let path: CloudPath = folder.parse()?;
+        match locate(documents, &path)? {
+            Location::Missing(_) => missing.push((folder, path)),
+            _ => {
+                ids.insert(folder, destination(documents, &path)?.folder());
+            }
+        }
+    }
+    if !missing.is_empty() && !create_missing {
+        let folders: Vec<&str> = missing.iter().map(|(f, _)| *f).collect();
+        let folders = folders.join(", ");
+        return Err(msg!(MAPPING_FOLDERS_MISSING, folders = folders).into());
+    }
+    // Each folder and those above it, from the top, as `make_folders` takes
+    // them, made all at once so that folders they share are made once.
+    let mut chain: Vec<PathBuf> = vec![];
+    let mut paths = vec![];
+    for (folder, missing) in &missing {
+        let names = match missing {
+            CloudPath::Tree(names) => names,
+            _ => {
+                return Err(msg!(MAPPING_FOLDER_UNMADE, folder = *folder).into())
+            }
+        };
+        let mut path = PathBuf::new();
+        for name in names {
+            path.push(name);
+            if !chain.contains(&path) {
+                chain.push(path.clone());
+            }
+        }
+        paths.push(path);
+    }
+    let planned: Vec<Vec<String>> = chain
+        .iter()
+        .map(|p| p.iter().map(|n| n.to_string_lossy().into_owned()).collect())
+        .collect();
+    let violations = limits.limits.check_planned(documents, None, &planned);
+    limits.enforce(&violations, out)?;
+    let (made, created) =
+        push::make_folders(client, documents, None, &chain).await?;
+    for created in created {
+        let path = created.display().to_string();
+        out.note(&msg!(FOLDER_CREATED, path = path));
+    }
+    for ((folder, _), path) in missing.iter().zip(&paths) {
+        ids.insert(folder, Some(made[path]));
+    }
+    Ok(groups
+        .iter()
+        .map(|(folder, files)| {
+            let parent = folder.as_deref().and_then(|f| ids[f]);
+            (parent, files.clone())
+        })
+        .collect())
+}
+
+// `path` with a leading `~` standing for `home`. Anything else has to be
+// absolute, as there's no telling what a relative path would be relative
+// to.
+fn expand(path: &str, home: Option<&Path>) -> Result<PathBuf, String> {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ if Path::new(path).is_absolute() => return Ok(normalize(path)),
+        _ => {
+            return Err(msg!(
+                MAPPING_NOT_ABSOLUTE,
+                path = format!("{:?}", path)
+            ))
+        }
+    };
+    let home = home
+        .ok_or_else(|| msg!(MAPPING_NO_HOME, path = format!("{:?}", path)))?;
+    Ok(normalize(home.join(rest.trim_start_matches('/'))))
+}
+
+// `path` with `.` and `..` worked out, without looking at the filesystem.
+fn normalize(path: impl AsRef<Path>) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mappings(rules: &[(&str, &str)], default: Option<&str>) -> Mappings {
+        let settings = PushSettings {
+            mappings: rules
+                .iter()
+                .map(|(l, f)| (l.to_string(), f.to_string()))
+                .collect(),
+            default_folder: default.map(str::to_string),
+        };
+        Mappings::new(&settings, Some(Path::new("/home/me"))).unwrap()
+    }
+
+    fn folder<'a>(m: &'a Mappings, file: &str, cwd: &str) -> Option<&'a str> {
+        m.choose(Path::new(file), Path::new(cwd)).map(|c| c.folder)
+    }
+
+    #[test]
+    fn prefixes() {
+        let m = mappings(
+            &[
+                ("~/papers", "/Papers"),
+                ("~/papers/drafts", "/Drafts"),
+                ("/srv/scans/", "/Inbox"),
+            ],
+            None,
+        );
+        assert_eq!(folder(&m, "/home/me/papers/a.pdf", "/"), Some("/Papers"));
+        assert_eq!(
+            folder(&m, "/home/me/papers/drafts/b/a.pdf", "/"),
+            Some("/Drafts")
+        );
+        // Whole components, not strings.
+        assert_eq!(folder(&m, "/home/me/papers-old/a.pdf", "/"), None);
+        assert_eq!(folder(&m, "a.pdf", "/srv/scans"), Some("/Inbox"));
+        assert_eq!(folder(&m, "../scans/./a.pdf", "/srv/x"), Some("/Inbox"));
+        assert_eq!(folder(&m, "../a.pdf", "/srv/scans"), None);
+        assert_eq!(
+            m.choose(Path::new("/srv/scans/a.pdf"), Path::new("/")),
+            Some(Choice {
+                folder: "/Inbox",
+                rule: Some("/srv/scans/"),
+            })
+        );
+
+        let m = mappings(&[("~", "/Home")], Some("/Unsorted"));
+        assert_eq!(folder(&m, "/home/me/a.pdf", "/"), Some("/Home"));
+        let choice = m.choose(Path::new("/tmp/a.pdf"), Path::new("/"));
+        assert_eq!(
+            choice,
+            Some(Choice {
+                folder: "/Unsorted",
+                rule: None,
+            })
+        );
+        assert_eq!(choice.unwrap().describe(), "the default push folder");
+    }
+
+    #[test]
+    fn tildes() {
+        let home = Some(Path::new("/home/me"));
+        assert_eq!(expand("~", home).unwrap(), Path::new("/home/me"));
+        assert_eq!(expand("~/a/../b", home).unwrap(), Path::new("/home/me/b"));
+        assert_eq!(expand("/abs", None).unwrap(), Path::new("/abs"));
+        assert!(expand("~/a", None).is_err());
+        // Someone else's home isn't looked up.
+        assert!(expand("~other/a", home).is_err());
+        assert!(expand("papers", home).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let real = dir.join("real");
+        std::fs::create_dir(&real).unwrap();
+        std::fs::write(real.join("a.pdf"), b"").unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        // A rule through the link covers files named without it,
+        let settings = PushSettings {
+            mappings: vec![(link.display().to_string(), "/Linked".into())]
+                .into_iter()
+                .collect(),
+            default_folder: None,
+        };
+        let m = Mappings::new(&settings, None).unwrap();
+        assert_eq!(
+            folder(&m, "real/a.pdf", dir.to_str().unwrap()),
+            Some("/Linked")
+        );
+        assert_eq!(
+            folder(&m, "link/a.pdf", dir.to_str().unwrap()),
+            Some("/Linked")
+        );
+
+        // and one on the real directory covers files named through it.
+        let settings = PushSettings {
+            mappings: vec![(real.display().to_string(), "/Real".into())]
+                .into_iter()
+                .collect(),
+            default_folder: None,
+        };
+        let m = Mappings::new(&settings, None).unwrap();
+        assert_eq!(
+            folder(&m, "link/a.pdf", dir.to_str().unwrap()),
+            Some("/Real")
+        );
+    }
+}
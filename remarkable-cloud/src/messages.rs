@@ -0,0 +1,1521 @@
+//! The messages the command line prints, kept in one catalog so they can be
+//! reworded in one place and translated.
+//!
+//! Each message is a constant here, with an id and an English template
+//! built in, and is printed with [`msg!`](crate::msg), which fills in its
+//! placeholders by name. Templates are written in Fluent's syntax: `{ $path }`
+//! is a placeholder, and
+//!
+//! ```text
+//! { $count ->
+//!     [one] page
+//!    *[other] pages
+//! }
+//! ```
+//!
+//! picks a word by number. English is filled in here; other languages are
+//! Fluent resources under `messages/`, embedded in every build but only
+//! loaded, with the `fluent` crate, in builds with the `i18n` feature. A
+//! message a translation leaves out is printed in English.
+//!
+//! The language is picked with `--lang`, or else from `LC_ALL`,
+//! `LC_MESSAGES` or `LANG`.
+//!
+//! Everything the command line says goes through the catalog, and a test
+//! fails on words written anywhere else. The exceptions are the help, in
+//! `cli` and `help` and the examples and descriptions beside each command,
+//! what's written into files such as PDFs and feeds, and the messages of
+//! panics, which are for whoever fixes the bug.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Whether this build can print messages in languages other than English.
+pub const I18N_BUILT: bool = cfg!(feature = "i18n");
+
+/// The translations, by language code, as Fluent resources.
+pub const TRANSLATIONS: &[(&str, &str)] =
+    &[("de", include_str!("../messages/de.ftl"))];
+
+/// A message in the catalog.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// The constant it's defined as.
+    pub name: &'static str,
+    /// What translations call it.
+    pub id: &'static str,
+    pub english: &'static str,
+}
+
+macro_rules! catalog {
+    ($($name:ident = $id:literal $english:literal;)*) => {
+        $(
+            pub const $name: Message = Message {
+                name: stringify!($name),
+                id: $id,
+                english: $english,
+            };
+        )*
+
+        /// Every message, in the order they're defined.
+        pub const CATALOG: &[Message] = &[$($name),*];
+    };
+}
+
+catalog! {
+    ERROR = "error" "Error: { $error }";
+    ERROR_MAX_TIME = "error-max-time" "Error: { $error } (--max-time)";
+    INTERRUPTED = "interrupted" "Interrupted";
+    OFFLINE_HINT = "offline-hint"
+        "ls, find and export show the cached listing instead, if there is \
+         one; nothing can be changed until the cloud can be reached.";
+    ACCOUNT_MIGRATED = "account-migrated"
+        "This account has been moved to reMarkable's newer sync service.
+The cloud no longer offers the API this tool uses to reach it, so
+nothing can be listed or changed until that service is supported.
+Follow progress at { $url }";
+    CONTENT_CACHE_NOT_SAVED = "content-cache-not-saved"
+        "Couldn't save the content cache: { $error }";
+    REDACTION_MAP_NOT_WRITTEN = "redaction-map-not-written"
+        "Couldn't write the redaction map: { $error }";
+    LANG_UNKNOWN = "lang-unknown"
+        "There are no messages in { $lang }, only in { $languages }";
+    LANG_NOT_BUILT = "lang-not-built"
+        "This build only has messages in English; others need the i18n \
+         feature";
+
+    LISTING_NOT_CACHED = "listing-not-cached"
+        "Couldn't cache the listing: { $error }";
+    LISTING_CHANGED_ELSEWHERE = "listing-changed-elsewhere"
+        "{ $path } changed elsewhere since the last listing \
+         (v{ $old } \u{2192} v{ $new })";
+    LISTING_ENTRY_UNPARSED = "listing-entry-unparsed"
+        "Couldn't parse listing entry { $index }: { $warning }";
+    LISTING_ENTRIES_UNPARSED = "listing-entries-unparsed"
+        "{ $count } { $count ->
+    [one] entry
+   *[other] entries
+} could not be parsed; run with -v for details";
+    LISTING_ONLY_PARTIAL = "listing-only-partial"
+        "{ $error }; only part of the listing is cached, what's in \
+         { $folders }, which ls --shallow can list";
+    LISTING_PARTIAL_CACHED = "listing-partial-cached"
+        "Cached the listing of what's in { $folders } only; other commands \
+         fetch the whole of it";
+
+    PULL_NO_SUCH_ID = "pull-no-such-id"
+        "Couldn't find document with id { $id }";
+    PULL_NOT_FOUND = "pull-not-found" "Couldn't find document '{ $path }'";
+    PULL_AMBIGUOUS = "pull-ambiguous"
+        "{ $path } matches { $count } documents; give a fuller path, --id, \
+         or --all-matches:";
+    PULL_FOLDER = "pull-folder"
+        "{ $path } is a folder; pull what's in it with -r";
+    PULL_NO_FILE = "pull-no-file" "No file found in response for { $path }";
+    PULL_NO_PAGES = "pull-no-pages" "{ $path } has no pages";
+    PULL_NO_FILENAME = "pull-no-filename"
+        "No filename found in path { $path }";
+    PULL_TIMES_NOT_SET = "pull-times-not-set"
+        "Couldn't set the modification time of { $path }, so pulled files \
+         have the time they were pulled: { $error }";
+    PULL_LINKED = "pull-linked" "Linked { $from } to { $to }";
+    PULL_COPIED = "pull-copied" "Copied { $from } to { $to }";
+
+    PUSH_VERIFIED_COUNT = "push-verified-count"
+        "{ $uploaded } uploaded, { $verified } of them verified";
+    PUSH_NOT_VERIFIED = "push-not-verified"
+        "Didn't read back { $path }, which is larger than --verify-max-size";
+    PUSH_VERIFY_MISMATCH = "push-verify-mismatch"
+        "{ $path } was uploaded, but the cloud's copy has SHA-256 { $found } \
+         rather than { $sent }; push it again with --on-conflict update";
+    PUSH_VERIFY_MISSING = "push-verify-missing"
+        "{ $path } was uploaded, but the cloud's copy has no { $file } in it; \
+         push it again with --on-conflict update";
+    PUSH_VERIFY_UNREADABLE = "push-verify-unreadable"
+        "{ $path } was uploaded, but the cloud's copy couldn't be read back: \
+         { $error }; push it again with --on-conflict update";
+
+    SYNC_PULLED = "sync-pulled" "Pulled { $path }";
+    SYNC_REMOVED = "sync-removed"
+        "Removed { $path }, which was deleted in the cloud";
+    SYNC_NOTHING_TO_PULL = "sync-nothing-to-pull"
+        "Skipped { $path }, which has no PDF or EPUB to pull";
+    SYNC_CONFLICTS_LEFT = "sync-conflicts-left"
+        "{ $count } { $count ->
+    [one] file was
+   *[other] files were
+} changed on both sides; sync resolve settles them";
+    SYNC_UP_TO_DATE = "sync-up-to-date" "Nothing to pull from { $folder }";
+    SYNC_NO_FOLDER = "sync-no-folder"
+        "{ $dir } hasn't been synced yet; give the cloud folder to sync it \
+         with";
+    SYNC_OTHER_FOLDER = "sync-other-folder"
+        "{ $dir } is synced with { $folder }, not { $asked }";
+    SYNC_LAYOUT_CHANGED = "sync-layout-changed"
+        "{ $dir } is laid out { $layout }; pull with --relayout to move \
+         what's there to { $asked }, or with --layout { $layout } to keep it";
+    SYNC_LEFT_IN_PLACE = "sync-left-in-place"
+        "Left { $path } where it is, as it's gone from the cloud";
+    SYNC_RELAID = "sync-relaid"
+        "Moved the files of { $count } { $count ->
+    [one] document
+   *[other] documents
+} to lay { $dir } out { $layout }";
+
+    WATCH_STARTED = "watch-started"
+        "Watching { $count } { $count ->
+    [one] document
+   *[other] documents
+}; changes from now on will be reported";
+    WATCH_UNLISTED = "watch-unlisted"
+        "Couldn't list the documents: { $error }; trying again in { $wait }";
+
+    AWAIT_NOT_A_DOCUMENT = "await-not-a-document"
+        "{ $path } isn't a document to wait for";
+    AWAIT_UNLISTED = "await-unlisted" "Couldn't list the documents: { $error }";
+    AWAIT_NOT_SERVED = "await-not-served"
+        "{ $path } is listed at version { $version }, but the cloud doesn't \
+         have it yet";
+    AWAIT_NOT_FETCHED = "await-not-fetched"
+        "Couldn't fetch { $path }: { $error }";
+    AWAIT_NOTHING_CAME = "await-nothing-came"
+        "Nothing came to { $path } in { $waited }";
+    AWAIT_STILL_OLD = "await-still-old"
+        "{ $path } was still at version { $version } after { $waited }";
+
+    DIGEST_NOT_BUILT = "digest-not-built"
+        "This build can't make digests; it needs the digest feature";
+    DIGEST_WROTE = "digest-wrote"
+        "Wrote { $pages } bookmarked { $pages ->
+    [one] page
+   *[other] pages
+} from { $documents } { $documents ->
+    [one] document
+   *[other] documents
+} to { $path }";
+    DIGEST_NONE_SINCE = "digest-none-since"
+        "No pages were bookmarked in the last { $since }, so there's no \
+         digest";
+    DIGEST_NONE = "digest-none" "No pages are bookmarked, so there's no digest";
+
+    BACKUP_REPRODUCIBLE_RESUMED = "backup-reproducible-resumed"
+        "a reproducible backup can't be resumed";
+    BACKUP_FAILED = "backup-failed" "Failed to back up { $path }: { $error }";
+    BACKUP_PLAN_GONE = "backup-plan-gone"
+        "{ $path } is gone from the cloud since it was planned; leaving it out";
+    BACKUP_INCOMPLETE_REPRODUCIBLE = "backup-incomplete-reproducible"
+        "{ $count } documents couldn't be backed up, and a reproducible backup \
+         needs them all";
+    RESTORE_FOLDER_CREATED = "restore-folder-created"
+        "Created folder { $path }";
+    RESTORE_RESTORED = "restore-restored" "Restored { $path }";
+    RESTORE_UNNAMED = "restore-unnamed" "(unnamed)";
+    RESTORE_ARCHIVE_VERSION = "restore-archive-version" "version { $version }";
+    RESTORE_ARCHIVE_VERSION_UNKNOWN = "restore-archive-version-unknown"
+        "version unknown";
+    RESTORE_ARCHIVE = "restore-archive" "{ $file }: { $name }, { $version }";
+    RESTORE_ARCHIVE_MODIFIED = "restore-archive-modified"
+        "{ $file }: { $name }, { $version }, modified { $modified }";
+    RESTORE_NO_SUCH_VERSION = "restore-no-such-version"
+        "no archive is of version { $version }";
+    RESTORE_NONE_BY_DATE = "restore-none-by-date"
+        "no archive is from { $date } or before";
+    RESTORE_WHICH = "restore-which" "Restore which? [1-{ $count }]";
+    RESTORE_NOTHING = "restore-nothing" "Nothing restored";
+    RESTORE_DONE = "restore-done"
+        "Restored { $restored } documents, created { $created } folders, \
+         skipped { $skipped } already present";
+    RESTORE_NO_ARCHIVES = "restore-no-archives"
+        "{ $dir } holds no archives of documents";
+    RESTORE_PICKED_VERSION = "restore-picked-version"
+        "Restored { $file } as version { $version } of { $name }";
+    RESTORE_PICKED_NEW = "restore-picked-new"
+        "Restored { $file } as a new document, { $name }";
+
+    CACHE_REFRESHED = "cache-refreshed" "refreshed for next run";
+    CACHE_REFRESH_LOCKED = "cache-refresh-locked"
+        "not refreshed, as another process was using it";
+    CACHE_REFRESH_FAILED = "cache-refresh-failed"
+        "couldn't refresh it: { $error }";
+    CACHE_WAS_OLD = "cache-was-old" "(cache was { $age } old; { $how })";
+    CACHE_WARMED = "cache-warmed"
+        "Cached { $downloaded } documents ({ $cached } already cached, { \
+         $unreadable } unreadable); forgot { $pruned } removed or changed \
+         since";
+    AGO_JUST_NOW = "ago-just-now" "just now";
+    AGO_MINUTES = "ago-minutes"
+        "{ $count } { $count ->
+    [one] minute
+   *[other] minutes
+} ago";
+    AGO_HOURS = "ago-hours"
+        "{ $count } { $count ->
+    [one] hour
+   *[other] hours
+} ago";
+    AGO_DAYS = "ago-days"
+        "{ $count } { $count ->
+    [one] day
+   *[other] days
+} ago";
+
+    OFFLINE_CACHED = "offline-cached"
+        "offline — showing cached data from { $ago }";
+
+    FIELD_UNKNOWN = "field-unknown"
+        "Unknown field { $field }; the fields are { $fields }";
+
+    CONTENT_TYPE_UNCHECKED = "content-type-unchecked"
+        "{ $count } documents weren't checked for --content-type, as their \
+         contents aren't cached; `cache warm` caches them";
+    DOWNLOAD_FAILED = "download-failed"
+        "Couldn't download { $path }: { $error }";
+
+    DURATION_INVALID = "duration-invalid"
+        "Invalid duration { $duration }: { $error }";
+    TIME_TOO_FAR_BACK = "time-too-far-back" "Too far in the past: { $time }";
+    TIME_INVALID = "time-invalid"
+        "Invalid time { $time }, expected a date like 2024-01-01 or a duration \
+         like \"2 weeks ago\"";
+
+    PATTERN_UNCLOSED = "pattern-unclosed"
+        "Unclosed '[' in pattern { $pattern }";
+
+    OPERATION_LOG_FAILED = "operation-log-failed"
+        "Could not write to operation log: { $error }";
+
+    LAYOUT_UNKNOWN = "layout-unknown" "unknown layout { $layout }";
+
+    IS_A_FOLDER = "is-a-folder" "{ $path } is a folder";
+
+    LIMITS_STRICT = "limits-strict"
+        "Stopped before changing anything: { $count } { $count ->
+    [one] place
+   *[other] places
+} would go past the folder limits; leave out --strict to go ahead anyway";
+    LIMITS_PAST = "limits-past"
+        "{ $count } { $count ->
+    [one] place
+   *[other] places
+} past the folder limits";
+    LIMITS_NONE_PAST = "limits-none-past" "No folders past the limits";
+
+    LOCK_WAITING = "lock-waiting" "Waiting for { $holder } to finish";
+    LOCK_HOLDER_PID = "lock-holder-pid"
+        "another remarkable-cloud process (pid { $pid })";
+    LOCK_HOLDER = "lock-holder" "another remarkable-cloud process";
+    LOCK_HELD_PID = "lock-held-pid"
+        "another remarkable-cloud process holds the lock (pid { $pid }); give \
+         --wait-lock to wait for it";
+    LOCK_HELD = "lock-held"
+        "another remarkable-cloud process holds the lock; give --wait-lock to \
+         wait for it";
+
+    PHASE_LISTING = "phase-listing" "Listing…";
+    PHASE_PLANNING = "phase-planning"
+        "Planning ({ $compared } documents compared)…";
+    PHASE_TRANSFERRING = "phase-transferring"
+        "Transferring { $documents } documents…";
+    PHASE_RESUMING = "phase-resuming"
+        "Resuming the saved plan ({ $checked } documents checked)…";
+    PLAN_OTHER_VERSION = "plan-other-version"
+        "The plan in { $path } is of version { $found }, which this version \
+         can't resume (it saves version { $expected }); remove it to plan \
+         again";
+    PLAN_NONE = "plan-none"
+        "There's no saved plan in { $path }; planning afresh";
+    PLAN_MOVED = "plan-moved"
+        "{ $path } changed since it was planned; planning it again";
+
+    FILE_UNREADABLE = "file-unreadable" "Couldn't read { $path }: { $error }";
+    FILE_UNPARSABLE = "file-unparsable" "Couldn't parse { $path }: { $error }";
+
+    SORT_LOCALE_NOT_BUILT = "sort-locale-not-built"
+        "This build can't sort by locale; it needs the locale-sort feature";
+    SORT_UNKNOWN = "sort-unknown" "Unknown sort order { $order }";
+
+    SUMMARY_TRANSFERRED = "summary-transferred"
+        "Transferred { $count } { $count ->
+    [one] file
+   *[other] files
+}, { $bytes } in { $secs }s";
+    SUMMARY_SKIPPED_FAILED = "summary-skipped-failed"
+        "; { $skipped } skipped, { $failed } failed";
+
+    PUSH_CONFLICT_SKIPPED = "push-conflict-skipped"
+        "Skipped { $name }: { $existing } is already there; choose what to do \
+         with --on-conflict";
+    SYNC_CONFLICT_LEFT = "sync-conflict-left"
+        "Left { $path }, which was { $kind }; choose what to keep with \
+         --strategy";
+
+    CONFLICT_SKIPPED = "conflict-skipped"
+        "Skipped { $name }: { $existing } is already there";
+    SYNC_CONFLICT_KEPT = "sync-conflict-kept"
+        "Left { $path }, which was { $kind }";
+    VALUE_NOT_ONE_OF = "value-not-one-of" "{ $value } isn't one of { $values }";
+    PULL_WRITING = "pull-writing" "DEBUG: { $path }";
+    PUSH_DIRECTORY = "push-directory"
+        "{ $path } is a directory; push what's in it with -r";
+    PUSH_UNOPTIMIZED = "push-unoptimized"
+        "Pushing { $name } as it is: { $error }";
+    PUSH_RESUME_FINISHED = "push-resume-finished" "Finished { $name }";
+    PUSH_RESUME_CHANGED = "push-resume-changed"
+        "Rolled back { $name }: { $source } has changed or gone";
+    PUSH_RESUME_UNREREADABLE = "push-resume-unrereadable"
+        "Rolled back { $name }: it was read from stdin or optimized, so can't \
+         be read again";
+    PUSH_INTERRUPTED = "push-interrupted"
+        "Note: { $count } interrupted uploads; finish them with `push \
+         --resume`";
+    PUSHED = "pushed" "Pushed { $path }";
+    PUSHED_AS = "pushed-as" "Pushed { $path } as { $name }";
+    PUSHED_AS_VERSION = "pushed-as-version"
+        "Pushed { $path } as version { $version } of { $name }";
+    FOLDER_CREATED = "folder-created" "Created folder { $path }";
+    PUSH_UNSUPPORTED = "push-unsupported"
+        "Not pushed, as they're neither PDFs nor EPUBs:";
+
+    PUSH_ASK_EXISTING = "push-ask-existing"
+        "{ $name } is already there (v{ $version }, modified { $modified }).";
+    PUSH_ASK = "push-ask"
+        "Push { $name } anyway? [S]kip, [u]pdate it, [r]ename to { $free }, \
+         [d]uplicate:";
+    PUSH_NOT_PDF = "push-not-pdf" "{ $name } doesn't look like a PDF";
+    PUSH_NOT_EPUB = "push-not-epub" "{ $name } doesn't look like an EPUB";
+    PUSH_NEITHER = "push-neither" "{ $name } is neither a PDF nor an EPUB";
+    NO_USABLE_NAME = "no-usable-name" "{ $name } has no usable name";
+
+    SYNC_NOT_SYNCED = "sync-not-synced"
+        "{ $dir } hasn't been synced: it has no { $manifest }";
+    SYNC_UNCHANGED = "sync-unchanged" "Unchanged";
+    SYNC_CHANGED_HERE = "sync-changed-here" "Changed here";
+    SYNC_CHANGED_IN_CLOUD = "sync-changed-in-cloud" "Changed in the cloud";
+    SYNC_CHANGED_BOTH = "sync-changed-both" "Changed on both sides";
+    SYNC_ONLY_HERE = "sync-only-here" "Only here";
+    SYNC_ONLY_IN_CLOUD = "sync-only-in-cloud" "Only in the cloud";
+    SYNC_DELETED_HERE = "sync-deleted-here" "Deleted here";
+    SYNC_DELETED_IN_CLOUD = "sync-deleted-in-cloud" "Deleted in the cloud";
+    SYNC_DELETED_BOTH = "sync-deleted-both" "Deleted on both sides";
+    SYNC_NOT_A_FOLDER = "sync-not-a-folder"
+        "{ $dir } is synced with { $folder }, which isn't a folder";
+    SYNC_FOLDER_GONE = "sync-folder-gone"
+        "{ $dir } is synced with { $folder }, which is gone from the cloud";
+    CONFLICT_BOTH_CHANGED = "conflict-both-changed" "changed on both sides";
+    CONFLICT_BOTH_ADDED = "conflict-both-added" "added on both sides";
+    CONFLICT_CHANGED_HERE_DELETED_THERE = "conflict-changed-here-deleted-there"
+        "changed here but deleted in the cloud";
+    CONFLICT_DELETED_HERE_CHANGED_THERE = "conflict-deleted-here-changed-there"
+        "deleted here but changed in the cloud";
+    STRATEGY_UNKNOWN = "strategy-unknown" "unknown strategy { $strategy }";
+    SYNC_ASK = "sync-ask"
+        "{ $path } was { $kind }. Keep [c]loud, [l]ocal, [b]oth, or [S]kip:";
+    SYNC_DOCUMENT_GONE = "sync-document-gone"
+        "{ $path } is gone from the cloud";
+    SYNC_NO_FORMAT = "sync-no-format"
+        "The cloud's { $path } has no { $format } to pull";
+    SYNC_KEPT_BOTH = "sync-kept-both"
+        "Kept both of { $path }: the cloud's there, and this one as { $copy }";
+    SYNC_KEPT_CLOUD = "sync-kept-cloud" "Kept the cloud's { $path }";
+    SYNC_KEPT_LOCAL = "sync-kept-local" "Kept { $path } from here";
+    RESOLVE_RESUMED = "resolve-resumed"
+        "Carrying on the resolve interrupted before";
+    RESOLVE_STRATEGY_IGNORED = "resolve-strategy-ignored"
+        "Ignored --strategy: the conflicts were already decided on";
+    RESOLVE_NOTHING = "resolve-nothing" "Nothing in { $dir } conflicts";
+    RESOLVE_LEFT = "resolve-left" "{ $count } conflicts left as they were";
+    SYNC_PATHS_DIFFER = "sync-paths-differ"
+        "{ $count } { $count ->
+    [one] path differs
+   *[other] paths differ
+} since the last sync";
+    SYNC_IN_STEP = "sync-in-step" "{ $dir } is in step with { $folder }";
+
+    PROBLEMS = "problems" "{ $count } problems with the command line:";
+    FLAGS_TOGETHER = "flags-together"
+        "{ $flags } can't be used together: { $why }";
+    PREFLIGHT_RAW_ZIP_FORMAT = "preflight-raw-zip-format"
+        "--raw-zip saves the archive as the cloud has it, whatever the format; \
+         drop one of them";
+    PREFLIGHT_RESUME_TO = "preflight-resume-to"
+        "--resume finishes uploads where they were started; drop --to";
+    PREFLIGHT_RESUME_ON_CONFLICT = "preflight-resume-on-conflict"
+        "--resume finishes uploads as they were started, conflicts and all; \
+         drop --on-conflict";
+    PREFLIGHT_RESUME_RECURSIVE = "preflight-resume-recursive"
+        "--resume only finishes uploads already started; drop --recursive";
+    PREFLIGHT_RESUME_CREATE_MISSING = "preflight-resume-create-missing"
+        "--resume finishes uploads into folders which already exist; drop \
+         --create-missing";
+    PREFLIGHT_QUEUE_CREATE_MISSING = "preflight-queue-create-missing"
+        "--queue doesn't connect to the cloud to make folders; run push \
+         --create-missing once without --queue first";
+    PREFLIGHT_RESUME_STRICT = "preflight-resume-strict"
+        "--resume only finishes uploads already started, into folders already \
+         made; drop --strict";
+    PREFLIGHT_QUEUE_STRICT = "preflight-queue-strict"
+        "--queue doesn't connect to the cloud to see its folders; run push \
+         --strict without --queue instead";
+    PREFLIGHT_STDIN_RECURSIVE = "preflight-stdin-recursive"
+        "--stdin reads a single document; drop --recursive";
+    PREFLIGHT_RESUME_OPTIMIZE = "preflight-resume-optimize"
+        "--resume finishes uploads as they were started, optimized or not; \
+         drop --optimize";
+    PREFLIGHT_QUEUE_OPTIMIZE = "preflight-queue-optimize"
+        "queued uploads are pushed as they are; drop --optimize, or push \
+         without --queue";
+    PREFLIGHT_STDIN_OPTIMIZE = "preflight-stdin-optimize"
+        "--optimize works on files; save what's piped in to a file and push \
+         that";
+    PREFLIGHT_RESUME_VERIFY = "preflight-resume-verify"
+        "--resume finishes uploads without reading them back; drop --verify, \
+         and check them with `info --verify` instead";
+    PREFLIGHT_QUEUE_VERIFY = "preflight-queue-verify"
+        "queued uploads are pushed without being read back; drop --verify, or \
+         push without --queue";
+    PREFLIGHT_PICK_KEEP_IDS = "preflight-pick-keep-ids"
+        "--pick keeps the document's id if it still exists, and can't \
+         otherwise; drop --keep-ids";
+    PREFLIGHT_VERSION_DATE = "preflight-version-date"
+        "each picks an archive by itself; drop one of them";
+    PREFLIGHT_TRASH = "preflight-trash"
+        "documents can't be put in the trash from here; rm moves them there";
+    PREFLIGHT_NO_SUCH_FILE = "preflight-no-such-file" "no such file";
+    PREFLIGHT_PUSH_DIRECTORY = "preflight-push-directory"
+        "is a directory; push what's in it with -r";
+    PREFLIGHT_NOT_PUSHABLE = "preflight-not-pushable"
+        "only PDFs and EPUBs can be pushed; the name needs to end in .pdf or \
+         .epub";
+    PREFLIGHT_OUTPUT_DIRECTORY = "preflight-output-directory"
+        "is a directory; give a file name in it";
+    PREFLIGHT_NO_SUCH_PARENT = "preflight-no-such-parent"
+        "{ $dir } isn't a directory that exists";
+    PREFLIGHT_NOT_A_FILE = "preflight-not-a-file" "isn't a file";
+    PREFLIGHT_NOT_A_DIRECTORY = "preflight-not-a-directory" "isn't a directory";
+    PREFLIGHT_NO_SUCH_DIRECTORY = "preflight-no-such-directory"
+        "no such directory";
+
+    SETUP_NOT_REGISTERED = "setup-not-registered"
+        "Couldn't register with that code, which may have been mistyped or \
+         used already: { $error }";
+    SETUP_REGISTERED = "setup-registered" "Registered this computer.";
+    SETUP_SIGNED_IN = "setup-signed-in"
+        "Signed in: the cloud holds { $count } documents and folders.";
+    SETUP_ALREADY_REGISTERED = "setup-already-registered"
+        "This computer is already registered; give --code to register it \
+         again.";
+    SETUP_GET_CODE = "setup-get-code"
+        "To register this computer with your account, get a one-time code from \
+         { $url }";
+    SETUP_ASK_CODE = "setup-ask-code" "One-time code (leave blank to skip):";
+    SETUP_CODE_SKIPPED = "setup-code-skipped"
+        "Skipped registering; run setup again once you have a code.";
+    SETUP_CREDENTIALS_FILE = "setup-credentials-file"
+        "The credentials are kept in { $path }; this build can't keep them in \
+         a keyring.";
+    SETUP_SETTINGS_KEPT = "setup-settings-kept"
+        "Keeping the settings already in { $path }.";
+    SETUP_ASK_SETTINGS = "setup-ask-settings"
+        "Write starter settings to { $path }?";
+    SETUP_SETTINGS_SKIPPED = "setup-settings-skipped" "Skipped the settings.";
+    SETUP_SETTINGS_WRITTEN = "setup-settings-written"
+        "Wrote { $path }, with each setting at its default.";
+    SETUP_NO_COMPLETIONS = "setup-no-completions"
+        "Skipped shell completions, there being none for { $shell }.";
+    SETUP_COMPLETIONS_INSTALLED = "setup-completions-installed"
+        "Shell completions are already installed in { $path }.";
+    SETUP_ASK_COMPLETIONS = "setup-ask-completions"
+        "Install shell completions in { $path }?";
+    SETUP_COMPLETIONS_SKIPPED = "setup-completions-skipped"
+        "Skipped shell completions.";
+    SETUP_COMPLETIONS_WRITTEN = "setup-completions-written"
+        "Installed shell completions, for shells started from now.";
+
+    HISTORY_CACHED = "history-cached" "(cached: { $value })";
+    HISTORY_ROOT = "history-root" "(root)";
+    WRITTEN_BY_NO_METADATA = "written-by-no-metadata"
+        "unknown, as the archive has no .metadata";
+    WRITTEN_BY_TABLET_PENDING = "written-by-tablet-pending"
+        "the tablet, with changes it hadn't synced yet";
+    WRITTEN_BY_TABLET_SYNCED = "written-by-tablet-synced"
+        "the tablet, in step with the cloud";
+    WRITTEN_BY_TABLET_UNSYNCED = "written-by-tablet-unsynced"
+        "the tablet, not yet synced";
+    WRITTEN_BY_UNKNOWN = "written-by-unknown"
+        "unknown, as .metadata doesn't say whether it's synced (as when \
+         written by scripts and some other clients)";
+    BLOB_URL_NONE = "blob-url-none" "none given";
+    BLOB_URL_FRESH = "blob-url-fresh" "fresh, expires in { $left }";
+    BLOB_URL_EXPIRED = "blob-url-expired" "expired at { $at }";
+    HISTORY_VERSION = "history-version" "version";
+    HISTORY_MODIFIED = "history-modified" "modified";
+    HISTORY_NAME = "history-name" "name";
+    HISTORY_PARENT = "history-parent" "parent";
+    HISTORY_BOOKMARKED = "history-bookmarked" "bookmarked";
+    HISTORY_CURRENT_PAGE = "history-current-page" "current page";
+    HISTORY_METADATA_VERSION = "history-metadata-version" "metadata version";
+    HISTORY_LISTED = "history-listed" "(listing: { $version })";
+    HISTORY_WRITTEN_BY = "history-written-by" "written by";
+    HISTORY_BLOB_URL = "history-blob-url" "blob URL";
+    HISTORY_NOT_CACHED = "history-not-cached" "not in the cached listing";
+    HISTORY_SAME_AS_CACHED = "history-same-as-cached"
+        "same as the cached listing";
+
+    VERDICT_OK = "verdict-ok" "ok";
+    VERDICT_AUTH = "verdict-auth" "auth problem";
+    VERDICT_GONE = "verdict-gone" "endpoint gone";
+    VERDICT_DRIFT = "verdict-drift" "schema drift";
+    VERDICT_SKIPPED = "verdict-skipped" "skipped";
+    VERDICT_FAILED = "verdict-failed" "failed";
+    DRIFT_UNKNOWN = "drift-unknown"
+        "unknown { $field } in { $count } of { $entries }";
+    DRIFT_MISSING = "drift-missing"
+        "missing { $field } in { $count } of { $entries }";
+    DOCTOR_NEEDS_TOKEN = "doctor-needs-token" "needs a token";
+    DOCTOR_DOCUMENTS = "doctor-documents" "{ $count } documents";
+    DOCTOR_ENTRIES_UNREAD = "doctor-entries-unread"
+        "{ $count } entries couldn't be read";
+    DOCTOR_NEEDS_LISTING = "doctor-needs-listing" "needs the listing";
+    DOCTOR_NO_DOCUMENTS = "doctor-no-documents" "no documents to look at";
+    DOCTOR_READ_BYTES = "doctor-read-bytes" "read the first { $count } bytes";
+    DOCTOR_EMPTY = "doctor-empty" "empty";
+    DOCTOR_NO_DOCUMENT = "doctor-no-document" "no document to download";
+    DOCTOR_READ_ONLY = "doctor-read-only" "read-only";
+    DOCTOR_CHECK = "doctor-check" "CHECK";
+    DOCTOR_RESULT = "doctor-result" "RESULT";
+    DOCTOR_DETAIL = "doctor-detail" "DETAIL";
+    DOCTOR_AUTH_ADVICE = "doctor-auth-advice"
+        "The cloud refused this device's token. Sign in again to get a new \
+         one.";
+    DOCTOR_GONE_ADVICE = "doctor-gone-advice"
+        "The cloud no longer answers where this version of remarkable-cloud \
+         expects it to: the protocol has moved on. Look for a newer release, \
+         and see { $url }";
+    DOCTOR_DRIFT_ADVICE = "doctor-drift-advice"
+        "The cloud's answers have changed shape, and documents this version \
+         can't read are left out. Look for a newer release, or report the \
+         fields above.";
+    DOCTOR_FAILED_ADVICE = "doctor-failed-advice"
+        "Some checks failed for other reasons, as given above; if the network \
+         is down, try again once it's back.";
+    DOCTOR_PROBLEMS = "doctor-problems"
+        "{ $problems } of { $checks } checks found problems";
+
+    IN_UNKNOWN = "in-unknown" "In: unknown, as { $error }";
+    IN_ROOT = "in-root" "In: the root";
+    IN_FOLDERS = "in-folders" "In: { $folders }";
+    PINNED_FROM_LISTING = "pinned-from-listing"
+        "pinned is the listing's bookmark flag, as the document's .metadata \
+         has no pinned field (older firmware)";
+    CONTENT_PAGES = "content-pages" "{ $count } pages";
+    CONTENT_NO_PAGES = "content-no-pages" "no pages";
+    CONTENT_STROKES = "content-strokes" "{ $count } strokes";
+    CONTENT_STROKES_UNREADABLE = "content-strokes-unreadable"
+        "strokes unreadable";
+    CONTENT_SUMMARY = "content-summary"
+        "{ $path }: { $pages }, { $strokes }, { $bytes } bytes";
+    VERIFY_INTACT = "verify-intact" "intact";
+    VERIFY_DAMAGED = "verify-damaged" "damaged";
+    VERIFY_CHECK_FAILED = "verify-check-failed" "FAILED  { $reason }";
+    VERIFY_HASH_UNAVAILABLE = "verify-hash-unavailable" "unavailable";
+    VERIFY_HASH = "verify-hash" "hash";
+    NOT_A_DOCUMENT = "not-a-document" "{ $path } isn't a document";
+    DOCUMENT_UNREADABLE = "document-unreadable"
+        "Couldn't read { $path }: { $error }";
+    VERIFY_FOLDER = "verify-folder"
+        "{ $path } is a folder; use -r to verify what's in it";
+    VERIFY_TOTALS = "verify-totals"
+        "Verified { $count } documents: { $intact } intact, { $damaged } \
+         damaged, { $unread } not downloaded";
+    VERIFY_FAILED = "verify-failed"
+        "{ $failed } of { $count } documents failed verification";
+
+    MATCHED_NOTHING_STOP = "matched-nothing-stop"
+        "{ $pattern } matched nothing; pass --allow-empty to carry on \
+         regardless";
+    MATCHED_NOTHING = "matched-nothing" "{ $pattern } matched nothing";
+    CONFIRM_CONTINUE = "confirm-continue" "Continue? [y/N]";
+    CACHED_TARGET_GONE = "cached-target-gone"
+        "{ $path }: document deleted or trashed since cache, re-run without \
+         --cached";
+    CACHED_TARGET_CHANGED = "cached-target-changed"
+        "{ $path }: document changed since cache (v{ $old } → v{ $new }), \
+         re-run without --cached";
+    CACHED_TARGET_DELETED = "cached-target-deleted"
+        "{ $path }: document deleted since cache, re-run without --cached";
+
+    NOTHING_DONE = "nothing-done" "Nothing done.";
+    MOVE_INTO_ITSELF = "move-into-itself" "Can't move { $path } into itself";
+    CONFIRM_MOVE = "confirm-move" "This will move { $count } documents:";
+    CONFIRM_TRASH = "confirm-trash" "This will trash { $count } documents:";
+    CONFIRM_DELETE = "confirm-delete"
+        "This will permanently delete { $count } documents:";
+    CONFIRM_PIN = "confirm-pin" "This will pin { $count } documents:";
+    CONFIRM_UNPIN = "confirm-unpin" "This will unpin { $count } documents:";
+    MOVED = "moved" "Moved { $path }";
+    MOVE_FAILED = "move-failed" "Couldn't move { $path }: { $error }";
+    TRASHED = "trashed" "Trashed { $path }";
+    TRASH_FAILED = "trash-failed" "Couldn't trash { $path }: { $error }";
+    RM_FOLDER_NOT_EMPTY = "rm-folder-not-empty"
+        "{ $path } is a folder which isn't empty; trash it instead, or include \
+         its contents";
+    DELETED = "deleted" "Deleted { $path }";
+    PIN_FOLDER = "pin-folder"
+        "{ $path } is a folder; only documents can be pinned";
+    UNPIN_FOLDER = "unpin-folder"
+        "{ $path } is a folder; only documents can be unpinned";
+    PINNED = "pinned" "Pinned { $path }";
+    UNPINNED = "unpinned" "Unpinned { $path }";
+    DELETE_FAILED = "delete-failed" "Couldn't delete { $path }: { $error }";
+    DELETE_SKIPPED = "delete-skipped" "Skipped { $path }";
+    DELETE_REMAINING = "delete-remaining"
+        "{ $count } documents are still there; run again to retry";
+    CHANGE_FAILED = "change-failed"
+        "{ $failed } of { $count } documents weren't changed; run again to \
+         retry";
+
+    NOTHING_TO_DELETE = "nothing-to-delete" "Nothing to delete";
+    WOULD_DELETE = "would-delete" "Would delete { $path }";
+
+    STATUS_UNKNOWN = "status-unknown" "unknown";
+    STATUS_EXPIRES_IN = "status-expires-in" "{ $at }, in { $left }";
+    STATUS_EXPIRED = "status-expired" "{ $at } (expired)";
+    STATUS_NONE = "status-none" "none";
+    STATUS_YES = "status-yes" "yes";
+    STATUS_NO = "status-no" "no";
+    STATUS_ENDPOINT = "status-endpoint" "Endpoint: { $endpoint }";
+    STATUS_ACCOUNT = "status-account" "Account: { $account }";
+    STATUS_TOKEN_EXPIRES = "status-token-expires" "Token expires: { $expires }";
+    STATUS_DEVICE_TOKEN = "status-device-token" "Device token: { $token }";
+    STATUS_READ_ONLY = "status-read-only" "Read-only: { $read_only }";
+    STATUS_UPLOADS = "status-uploads"
+        "Uploads: { $attempts } attempts, retrying after { $retry }";
+    STATUS_OFFICIAL = "status-official" "official";
+    STATUS_PROTOCOL = "status-protocol" "Protocol: { $generation }";
+    STATUS_DIALECT = "status-dialect" "Dialect: { $dialect }";
+    STATUS_NOTIFICATIONS = "status-notifications"
+        "Notifications: { $notifications }";
+    STATUS_UPLOADS_SUPPORTED = "status-uploads-supported"
+        "Uploads supported: { $uploads }";
+    STATUS_LARGEST_BLOB = "status-largest-blob"
+        "Largest known-good blob: { $size }";
+    STATUS_REACHED = "status-reached" "Reached the cloud in { $latency }";
+
+    MAPPING_RULE = "mapping-rule" "the push mapping for { $rule }";
+    MAPPING_DEFAULT = "mapping-default" "the default push folder";
+    MAPPING_GOES_TO = "mapping-goes-to"
+        "{ $file } goes to { $folder }, by { $by }";
+    MAPPING_FOLDERS_MISSING = "mapping-folders-missing"
+        "These folders from the push settings don't exist: { $folders }; make \
+         them with --create-missing";
+    MAPPING_FOLDER_UNMADE = "mapping-folder-unmade"
+        "{ $folder } doesn't exist, and only folders named by their path from \
+         the root can be made";
+    MAPPING_NOT_ABSOLUTE = "mapping-not-absolute"
+        "The push mapping for { $path } needs an absolute path, or one \
+         starting with ~";
+    MAPPING_NO_HOME = "mapping-no-home"
+        "Can't find the home directory for the push mapping { $path }";
+
+    QUEUE_ALREADY_QUEUED = "queue-already-queued"
+        "{ $source } is already queued to go there, as job { $job }";
+    QUEUED = "queued" "Queued { $file } as job { $job }";
+    JOB_PENDING = "job-pending" "pending";
+    JOB_UPLOADING = "job-uploading" "uploading";
+    JOB_DONE = "job-done" "done";
+    JOB_SKIPPED = "job-skipped" "skipped";
+    JOB_FAILED = "job-failed" "failed";
+    JOB_ATTEMPTS = "job-attempts"
+        "({ $attempts } failed attempts, last: { $error })";
+    QUEUE_NAME_TAKEN = "queue-name-taken"
+        "Skipped { $name }: the name is taken";
+    QUEUE_WILL_RETRY = "queue-will-retry" "Will retry { $name }: { $error }";
+    QUEUE_FAILED = "queue-failed" "Failed { $name }: { $error }";
+    QUEUE_CONFLICT = "queue-conflict"
+        "{ $name } is already there; queue it again with --on-conflict";
+    QUEUE_SOURCE_CHANGED = "queue-source-changed"
+        "{ $name } has changed since it was queued";
+    TRYING_AGAIN = "trying-again" "Trying again in { $wait }";
+    QUEUE_RUN = "queue-run"
+        "{ $done } pushed, { $skipped } skipped, { $failed } failed, { $retry \
+         } to retry";
+    QUEUE_NOT_EMPTIED = "queue-not-emptied"
+        "Not everything queued was pushed; see `queue list`";
+
+    SERVE_LISTENING = "serve-listening" "Listening on http://{ $address }";
+    SERVE_SHUTTING_DOWN = "serve-shutting-down" "shutting down";
+    SERVE_UNAUTHORIZED = "serve-unauthorized"
+        "give the token in { $file } as a bearer token";
+    SERVE_NO_DOCUMENT = "serve-no-document" "no such document";
+    SERVE_NO_JOB = "serve-no-job" "no such job";
+    SERVE_NO_ROUTE = "serve-no-route" "no such route; GET / lists them";
+    SERVE_NO_PAYLOAD = "serve-no-payload" "the document has no PDF or EPUB";
+
+    FIND_PAGES_UNSUPPORTED = "find-pages-unsupported"
+        "its pages are in an unsupported format";
+    FIND_TRASH = "find-trash" "{ $path } can't be searched by find";
+    FIND_DUPLICATES_OFFLINE = "find-duplicates-offline"
+        "--duplicates-of needs the cloud, which can't be reached; --by-name \
+         doesn't";
+    FIND_COMPARED = "find-compared"
+        "Downloaded { $downloaded } documents to compare ({ $unchecked } not \
+         checked due to --limit, { $unreadable } unreadable)";
+    FIND_NOTES_OFFLINE = "find-notes-offline"
+        "--with-notes needs the cloud, which can't be reached";
+    FIND_DEEP_OFFLINE = "find-deep-offline"
+        "--empty and --pinned need the cloud, which can't be reached";
+    FIND_CHECKED = "find-checked"
+        "Downloaded { $downloaded } documents to check for --empty or --pinned \
+         ({ $unchecked } not checked due to --limit, { $unreadable } \
+         unreadable)";
+
+    NOTE_NOT_ALLOWED = "note-not-allowed" "{ $path } can't have a note";
+    NOTED = "noted" "Noted { $path }";
+    NOTE_REMOVED = "note-removed" "Removed the note on { $path }";
+    NO_NOTE = "no-note" "{ $path } has no note";
+    NOTES_NONE_TO_PRUNE = "notes-none-to-prune" "No notes to prune";
+
+    PROTOCOL_UNKNOWN = "protocol-unknown"
+        "Unknown image protocol { $protocol }";
+    INLINE_NOT_BUILT = "inline-not-built"
+        "This build can't show images in the terminal";
+    INLINE_NOT_TERMINAL = "inline-not-terminal" "Not writing to a terminal";
+    INLINE_UNDETECTED = "inline-undetected"
+        "The terminal doesn't seem to show images; try --inline-protocol";
+    PEEK_SAVED = "peek-saved" "Saved to { $path }";
+    PEEK_OPENED = "peek-opened" "Opened { $path }";
+    PEEK_VIEWER_FAILED = "peek-viewer-failed"
+        "Couldn't run { $viewer } ({ $error }); the thumbnail is at { $path }";
+    THUMBNAIL_NOT_JPEG = "thumbnail-not-jpeg" "The thumbnail isn't a JPEG";
+    NO_THUMBNAILS = "no-thumbnails" "{ $path } has no thumbnails";
+    PEEK_OPENING_INSTEAD = "peek-opening-instead"
+        "{ $why }; opening the thumbnail instead";
+    PEEK_PAGE = "peek-page" "Page { $page }: { $done }";
+
+    MUTATION_NOT_RECORDED = "mutation-not-recorded"
+        "Couldn't record the change in { $path }: { $error }";
+    UNDO_DAMAGED = "undo-damaged" "its log entry is damaged";
+    UNDO_UPLOAD = "undo-upload" "uploads can't be undone";
+    UNDO_DELETE = "undo-delete" "deletions can't be undone";
+    UNDO_NO_VERSION = "undo-no-version" "its log entry has no version";
+    UNDO_NOTHING = "undo-nothing" "Nothing to undo";
+    UNDONE = "undone" "Undid { $change }";
+    UNDO_CHANGED = "undo-changed"
+        "Skipped { $change }: changed since, and now at version { $version }";
+    UNDO_GONE = "undo-gone" "Skipped { $change }: no longer exists";
+    UNDO_SKIPPED = "undo-skipped" "Skipped { $change }: { $reason }";
+
+    OPTIMIZE_NOT_SMALLER = "optimize-not-smaller"
+        "Left { $name } as it was: optimizing didn't make it smaller";
+    OPTIMIZED = "optimized" "Optimized { $name }: { $before } to { $after }";
+    OPTIMIZED_DOWNSAMPLING = "optimized-downsampling"
+        "Optimized { $name }: { $before } to { $after }, downsampling { $count } { $count ->
+    [one] image
+   *[other] images
+}";
+    OPTIMIZE_NOT_BUILT = "optimize-not-built"
+        "This build can't optimize PDFs; it needs the optimize feature";
+    OPTIMIZE_UNREADABLE = "optimize-unreadable"
+        "It couldn't be read as a PDF: { $error }";
+    OPTIMIZE_ENCRYPTED = "optimize-encrypted" "It's encrypted";
+    OPTIMIZE_UNWRITABLE = "optimize-unwritable"
+        "It couldn't be written again: { $error }";
+    OPTIMIZE_UNREREADABLE = "optimize-unrereadable"
+        "What optimizing made didn't read back: { $error }";
+    OPTIMIZE_LOST_PAGES = "optimize-lost-pages" "Optimizing it lost pages";
+    OPTIMIZE_CHANGED_TEXT = "optimize-changed-text"
+        "Optimizing it changed its text";
+
+    WATCH_EVENT = "watch-event" "{ $event } { $path } (version { $version })";
+    WEBHOOK_SENT = "webhook-sent" "Sent { $event } to { $url }";
+    WEBHOOK_DUPLICATE = "webhook-duplicate"
+        "{ $url } had been sent { $event } already";
+    WEBHOOK_SPOOLED = "webhook-spooled"
+        "Couldn't send { $event } to { $url }: { $reason }; it's spooled for \
+         watch --replay-spool";
+
+    PUSH_STDIN_TERMINAL = "push-stdin-terminal"
+        "--stdin needs the document piped in, not a terminal";
+    SERVE_REMOTE = "serve-remote"
+        "{ $listen } can be reached from other machines; give --allow-remote \
+         to listen there anyway";
+    SERVE_TOKEN_IN = "serve-token-in" "Requests need the token in { $path }";
+    WEBHOOK_NO_SECRET = "webhook-no-secret"
+        "Webhooks need a webhook_secret to sign what's sent with; set one in { \
+         $path }";
+    BACKED_UP = "backed-up"
+        "Backed up { $documents } documents and { $folders } folders ({ \
+         $resumed } kept from a previous run, { $failed } failed)";
+
+    TEMPLATE_AT = "template-at" "{ $message } (at position { $position })";
+    TEMPLATE_UNKNOWN = "template-unknown"
+        "Unknown placeholder { $placeholder }; expected one of { $expected }";
+    TEMPLATE_NO_WIDTH = "template-no-width"
+        "Placeholder { $placeholder } does not take a width";
+    TEMPLATE_WIDTH_INVALID = "template-width-invalid"
+        "Invalid width { $width } for { $placeholder }";
+    TEMPLATE_UNCLOSED = "template-unclosed"
+        "Unclosed '{ $brace }'; write '{ $escaped }' for a literal brace";
+    TEMPLATE_UNMATCHED = "template-unmatched"
+        "Unmatched '{ $brace }'; write '{ $escaped }' for a literal brace";
+    TEMPLATE_UNAVAILABLE = "template-unavailable"
+        "Placeholder { $placeholder } is not available here";
+    TEMPLATE_NO_VALUE = "template-no-value"
+        "No value for placeholder { $placeholder }";
+    TEMPLATE_REPEATED = "template-repeated"
+        "Name template produced { $name } more than once; add a placeholder \
+         such as { $id } to tell the outputs apart";
+
+    PAGES_NOT_A_NUMBER = "pages-not-a-number" "{ $page } isn't a page number";
+    PAGES_NO_SUCH = "pages-no-such"
+        "there's no page { $page } in a document of { $count } pages";
+    PAGES_TWICE = "pages-twice" "page { $page } is given twice";
+    PAGES_MISSING = "pages-missing"
+        "page { $page } is missing; give every page, or delete it instead";
+    PAGES_WAS = "pages-was" "(was { $page })";
+    PAGES_DELETED = "pages-deleted" "Deleted { $count } pages of { $path }";
+    PAGES_REORDERED = "pages-reordered" "Reordered the pages of { $path }";
+
+    STATS_REPORT = "stats-report"
+        "{ $path }: { $pages } pages, { $strokes } strokes, { $ink } px of \
+         ink, { $layers } layers";
+    STATS_PAGE_UNREADABLE = "stats-page-unreadable"
+        "page { $page } couldn't be read";
+    STATS_UNREAD = "stats-unread"
+        "{ $failed } of { $count } documents couldn't be downloaded";
+
+    WEBHOOK_ANSWERED = "webhook-answered" "{ $url } answered { $status }";
+    SPOOL_REPLAYED = "spool-replayed"
+        "Replayed the spool: { $delivered } delivered, { $duplicates } sent \
+         before, { $failed } still failing";
+    SPOOL_UNDELIVERED = "spool-undelivered"
+        "Not everything spooled was delivered";
+
+    DIGEST_NO_INK = "digest-no-ink" "Nothing is drawn on its pages";
+    DIGEST_LEFT_OUT = "digest-left-out"
+        "Left { $path } out of the digest: { $error }";
+    DIGEST_NO_PAGE = "digest-no-page" "{ $title } has no page { $page }";
+    DIGEST_UNWRITABLE = "digest-unwritable"
+        "The digest couldn't be written: { $error }";
+    DIGEST_PAGE_UNREADABLE = "digest-page-unreadable"
+        "A page couldn't be read: { $error }";
+    DIGEST_ENCRYPTED = "digest-encrypted" "Its PDF is encrypted";
+
+    EXPORT_FOLDER = "export-folder"
+        "{ $path } is a folder; export what's in it with -r";
+    EXPORT_NEITHER = "export-neither"
+        "{ $path } can't be exported as { $format } or { $fallback }";
+    EXPORT_UNSUPPORTED = "export-unsupported"
+        "{ $path } can't be exported as { $format }; give --fallback for \
+         documents like it";
+    EXPORT_FELL_BACK = "export-fell-back"
+        "{ $path } can't be exported as { $format }, so it's exported as { \
+         $fallback }";
+    EXPORT_NOTHING = "export-nothing" "{ $path } has nothing to export";
+
+    PROGRESS_CHECKED = "progress-checked" "Checked";
+    PROGRESS_HASHED = "progress-hashed" "Hashed";
+    PROGRESS_READ = "progress-read" "Read";
+    PROGRESS_DELETED = "progress-deleted" "Deleted";
+    PROGRESS_CACHED = "progress-cached" "Cached";
+    PROGRESS_DOWNLOADED = "progress-downloaded" "Downloaded";
+    PROGRESS_VERIFIED = "progress-verified" "Verified";
+    PROGRESS_UPLOADED = "progress-uploaded" "Uploaded (MiB)";
+}
+
+/// What's put in a placeholder.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+    Text(String),
+    /// A number, which a template can pick words by.
+    Number(i64),
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Arg::Text(s) => f.write_str(s),
+            Arg::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl From<&str> for Arg {
+    fn from(s: &str) -> Self {
+        Arg::Text(s.to_string())
+    }
+}
+
+impl From<String> for Arg {
+    fn from(s: String) -> Self {
+        Arg::Text(s)
+    }
+}
+
+impl From<Cow<'_, str>> for Arg {
+    fn from(s: Cow<str>) -> Self {
+        Arg::Text(s.into_owned())
+    }
+}
+
+impl From<&String> for Arg {
+    fn from(s: &String) -> Self {
+        Arg::Text(s.clone())
+    }
+}
+
+macro_rules! number_args {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Arg {
+                fn from(n: $t) -> Self {
+                    Arg::Number(n as i64)
+                }
+            }
+        )*
+    };
+}
+
+number_args!(usize, u64, u32, i64);
+
+/// A message, with what goes in its placeholders, in the language picked.
+/// `msg!(PULL_FOLDER, path = name)` is `render(&PULL_FOLDER, &[("path",
+/// name.into())])`.
+#[macro_export]
+macro_rules! msg {
+    ($message:ident $(, $arg:ident = $value:expr)* $(,)?) => {
+        $crate::messages::render(
+            &$crate::messages::$message,
+            &[$((stringify!($arg), $crate::messages::Arg::from($value))),*],
+        )
+    };
+}
+
+static LANGUAGE: Mutex<Option<translation::Translation>> = Mutex::new(None);
+
+/// `message` with its placeholders filled in from `args`, in the language
+/// picked, or in English if it's not been translated.
+pub fn render(message: &Message, args: &[(&str, Arg)]) -> String {
+    let language = LANGUAGE.lock().unwrap();
+    language
+        .as_ref()
+        .and_then(|t| t.render(message.id, args))
+        .unwrap_or_else(|| fill(message.english, args))
+}
+
+/// The language asked for by the environment: the language part of
+/// `LC_ALL`, `LC_MESSAGES` or `LANG`, whichever is set first, as "de" of
+/// "de_DE.UTF-8".
+pub fn environment_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| language_of(&value))
+}
+
+fn language_of(locale: &str) -> String {
+    locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Prints messages in `lang` from here on, as a code such as "de" or a
+/// locale such as "de_DE.UTF-8". English, "C" and "POSIX" need nothing
+/// loaded; other languages fail if there's no translation into them, or
+/// this build can't load translations.
+pub fn set_language(lang: &str) -> Result<(), String> {
+    let code = language_of(lang);
+    if ["en", "c", "posix", ""].contains(&code.as_str()) {
+        *LANGUAGE.lock().unwrap() = None;
+        return Ok(());
+    }
+    let source = match TRANSLATIONS.iter().find(|(c, _)| *c == code) {
+        Some((_, source)) => source,
+        None => {
+            let languages: Vec<&str> = std::iter::once("en")
+                .chain(TRANSLATIONS.iter().map(|(c, _)| *c))
+                .collect();
+            return Err(msg!(
+                LANG_UNKNOWN,
+                lang = lang,
+                languages = languages.join(", "),
+            ));
+        }
+    };
+    let loaded = translation::Translation::load(&code, source)?;
+    *LANGUAGE.lock().unwrap() = Some(loaded);
+    Ok(())
+}
+
+#[cfg(feature = "i18n")]
+mod translation {
+    use fluent::concurrent::FluentBundle;
+    use fluent::{FluentArgs, FluentResource, FluentValue};
+
+    use super::Arg;
+
+    pub struct Translation {
+        bundle: FluentBundle<FluentResource>,
+    }
+
+    impl Translation {
+        pub fn load(code: &str, source: &str) -> Result<Self, String> {
+            let resource = FluentResource::try_new(source.to_string())
+                .map_err(|(_, errors)| {
+                    format!("The {} messages can't be read: {:?}", code, errors)
+                })?;
+            let lang = code.parse().map_err(|e| format!("{}: {}", code, e))?;
+            let mut bundle = FluentBundle::new_concurrent(vec![lang]);
+            // Isolation marks would end up in what's written to files and
+            // compared by scripts.
+            bundle.set_use_isolating(false);
+            bundle.add_resource(resource).map_err(|errors| {
+                format!("The {} messages can't be used: {:?}", code, errors)
+            })?;
+            Ok(Translation { bundle })
+        }
+
+        pub fn render(&self, id: &str, args: &[(&str, Arg)]) -> Option<String> {
+            let pattern = self.bundle.get_message(id)?.value()?;
+            let mut fluent_args = FluentArgs::new();
+            for (name, value) in args {
+                let value = match value {
+                    Arg::Text(s) => FluentValue::from(s.clone()),
+                    Arg::Number(n) => FluentValue::from(*n),
+                };
+                fluent_args.set(*name, value);
+            }
+            let mut errors = vec![];
+            let text = self.bundle.format_pattern(
+                pattern,
+                Some(&fluent_args),
+                &mut errors,
+            );
+            match errors.is_empty() {
+                true => Some(text.into_owned()),
+                false => None,
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "i18n"))]
+mod translation {
+    use super::Arg;
+
+    pub enum Translation {}
+
+    impl Translation {
+        pub fn load(_: &str, _: &str) -> Result<Self, String> {
+            Err(msg!(LANG_NOT_BUILT))
+        }
+
+        pub fn render(&self, _: &str, _: &[(&str, Arg)]) -> Option<String> {
+            match *self {}
+        }
+    }
+}
+
+/// `template` with its placeholders filled in from `args`, and the
+/// variants of its selectors picked, by English's plural rules. A
+/// placeholder with nothing given for it is left as `{$name}`, as Fluent
+/// leaves it.
+pub fn fill(template: &str, args: &[(&str, Arg)]) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let close = open + closing(&rest[open..]);
+        out.push_str(&placeable(&rest[open + 1..close], args));
+        rest = &rest[(close + 1).min(rest.len())..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// Where the brace `s` starts with is closed, or the end of `s` if it isn't.
+fn closing(s: &str) -> usize {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+    s.len()
+}
+
+// What's between a placeable's braces, filled in.
+fn placeable(inner: &str, args: &[(&str, Arg)]) -> String {
+    let (selector, variants) = match inner.find("->") {
+        Some(arrow) => (&inner[..arrow], Some(&inner[arrow + 2..])),
+        None => (inner, None),
+    };
+    let name = selector.trim().trim_start_matches('$');
+    let value = args.iter().find(|(n, _)| *n == name).map(|(_, v)| v);
+    match (variants, value) {
+        (Some(variants), value) => fill(pick(variants, value), args),
+        (None, Some(value)) => value.to_string(),
+        (None, None) => format!("{{${}}}", name),
+    }
+}
+
+// The variant of a selector `value` picks: the one keyed by its number, or
+// else "one" for 1 and "other" for the rest, or else the default.
+fn pick<'a>(variants: &'a str, value: Option<&Arg>) -> &'a str {
+    let mut found: Vec<(&str, bool, &str)> = vec![];
+    let mut depth = 0;
+    let mut start = None;
+    for (i, c) in variants.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '[' if depth == 0 => {
+                if let Some((key, default, from)) = start {
+                    found.push((key, default, &variants[from..i]));
+                }
+                let default = variants[..i].trim_end().ends_with('*');
+                let end = i + variants[i..].find(']').unwrap_or(0);
+                start = Some((&variants[i + 1..end], default, end + 1));
+            }
+            _ => {}
+        }
+    }
+    if let Some((key, default, from)) = start {
+        found.push((key, default, &variants[from..]));
+    }
+    // A variant's text runs up to the `*` marking the next as the default.
+    let text = |t: &'a str| t.trim().trim_end_matches('*').trim();
+    let by_key = |key: &str| found.iter().find(|(k, _, _)| k.trim() == key);
+    let chosen = match value {
+        Some(Arg::Number(n)) => by_key(&n.to_string())
+            .or_else(|| by_key(if *n == 1 { "one" } else { "other" })),
+        _ => None,
+    };
+    chosen
+        .or_else(|| found.iter().find(|(_, default, _)| *default))
+        .map_or("", |(_, _, t)| text(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn filling() {
+        let count = |n: usize| {
+            fill(
+                DIGEST_WROTE.english,
+                &[
+                    ("pages", n.into()),
+                    ("documents", 1usize.into()),
+                    ("path", "digest.pdf".into()),
+                ],
+            )
+        };
+        assert_eq!(
+            count(1),
+            "Wrote 1 bookmarked page from 1 document to digest.pdf"
+        );
+        assert_eq!(
+            count(3),
+            "Wrote 3 bookmarked pages from 1 document to digest.pdf"
+        );
+        assert_eq!(
+            fill(
+                "{ $n ->\n [0] none\n [one] one\n *[other] { $n } }",
+                &[("n", 0usize.into())]
+            ),
+            "none"
+        );
+        assert_eq!(
+            fill("{ $what } and { $else }", &[("what", "this".into())]),
+            "this and {$else}"
+        );
+        assert_eq!(
+            fill("Unclosed { $what", &[("what", "x".into())]),
+            "Unclosed x"
+        );
+    }
+
+    // The ids, and the placeholders of each, of a Fluent resource.
+    fn ids_and_placeholders(source: &str) -> Vec<(String, BTreeSet<String>)> {
+        let mut messages: Vec<(String, BTreeSet<String>)> = vec![];
+        for line in source.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            if !line.starts_with(' ') {
+                if let Some(eq) = line.find('=') {
+                    messages
+                        .push((line[..eq].trim().to_string(), BTreeSet::new()));
+                }
+            }
+            if let Some((_, placeholders)) = messages.last_mut() {
+                placeholders.extend(placeholders_in(line));
+            }
+        }
+        messages
+    }
+
+    fn placeholders_in(text: &str) -> BTreeSet<String> {
+        text.split('$')
+            .skip(1)
+            .map(|s| {
+                s.chars()
+                    .take_while(|c| {
+                        c.is_alphanumeric() || *c == '_' || *c == '-'
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Every message in the catalog is printed somewhere, and everything
+    // printed with `msg!` is in the catalog.
+    #[test]
+    fn catalog_used() {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut code = String::new();
+        for entry in fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            let text = fs::read_to_string(&path).unwrap();
+            if path.file_name().unwrap() == "messages.rs" {
+                // Between the catalog, which names every message, and these
+                // tests.
+                let catalog = text.find("\ncatalog! {").unwrap();
+                let end = catalog + text[catalog..].find("\n}\n").unwrap();
+                let tests = text.find("#[cfg(test)]\nmod tests").unwrap();
+                code.push_str(&text[end..tests]);
+            } else if path.extension().is_some_and(|e| e == "rs") {
+                code.push_str(&text);
+            }
+        }
+        let words: BTreeSet<&str> = code
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .collect();
+        for message in CATALOG {
+            assert!(words.contains(message.name), "{} is unused", message.name);
+        }
+        let names: BTreeSet<&str> = CATALOG.iter().map(|m| m.name).collect();
+        for call in code.split("msg!(").skip(1) {
+            let name: String = call
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            assert!(
+                names.contains(name.as_str()),
+                "{} isn't in the catalog",
+                name
+            );
+        }
+        let ids: BTreeSet<&str> = CATALOG.iter().map(|m| m.id).collect();
+        assert_eq!(ids.len(), CATALOG.len(), "ids are used twice");
+    }
+
+    // What a literal written right after these is, is help text or a
+    // programming error, neither of which goes through the catalog.
+    const NOT_PRINTED: &[&str] = &[
+        "command:",
+        "description:",
+        "about:",
+        "fn about(&self) -> &'static str {",
+        ".help(",
+        "expect(",
+        "panic!(",
+        "unreachable!(",
+    ];
+
+    // Literals which read as words but aren't messages: what's written into
+    // PDFs, Markdown, OPML and Atom files, the reasons for skipping given to
+    // the JSON log, which are codes, the names of local copies, and a
+    // command's name.
+    const NOT_MESSAGES: &[&str] = &[
+        "Page {} ({})",
+        "{}, page {} ({})",
+        "\\nQ q /{} Do Q\\n",
+        "\\n## Page {}\\n",
+        "<outline text=",
+        "  <outline text=",
+        "<?xml version=",
+        "  <entry>",
+        "not found",
+        "invalid path",
+        "{} (local {})",
+        "export feed",
+    ];
+
+    // Nothing outside the catalog reads as a message: the command line's
+    // own files, the help and tests aside, have no string literals of
+    // words, which would be printed in English whatever the language.
+    #[test]
+    fn strings_in_catalog() {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let mut found = vec![];
+        for entry in fs::read_dir(&src).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            if ["messages.rs", "cli.rs", "help.rs"].contains(&name.as_str())
+                || path.extension().is_none_or(|e| e != "rs")
+            {
+                continue;
+            }
+            let text = fs::read_to_string(&path).unwrap();
+            let code = match text.find("#[cfg(test)]\nmod tests") {
+                Some(tests) => &text[..tests],
+                None => &text[..],
+            };
+            for (before, literal) in literals(code) {
+                let before = before.trim_end();
+                if prose(literal)
+                    && !NOT_PRINTED.iter().any(|p| before.ends_with(p))
+                    && !NOT_MESSAGES.iter().any(|m| literal.starts_with(m))
+                {
+                    found.push(format!("{}: {}", name, literal));
+                }
+            }
+        }
+        assert!(
+            found.is_empty(),
+            "not in the catalog:\n{}",
+            found.join("\n")
+        );
+    }
+
+    // The string literals in `code`, as written, each with the code before
+    // it.
+    fn literals(code: &str) -> Vec<(&str, &str)> {
+        let bytes = code.as_bytes();
+        let mut found = vec![];
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                    i = code[i..].find('\n').map_or(code.len(), |n| i + n);
+                }
+                // A char literal, or else a lifetime.
+                b'\'' => {
+                    let next = code[i + 1..]
+                        .chars()
+                        .next()
+                        .map_or(1, |c| c.len_utf8());
+                    i += if bytes.get(i + 1) == Some(&b'\\') {
+                        code[i + 2..].find('\'').map_or(1, |n| n + 3)
+                    } else if bytes.get(i + 1 + next) == Some(&b'\'') {
+                        next + 2
+                    } else {
+                        1
+                    };
+                }
+                b'"' => {
+                    let hashes =
+                        code[..i].len() - code[..i].trim_end_matches('#').len();
+                    let raw = code[..i - hashes].ends_with('r');
+                    let mut end = i + 1;
+                    if raw {
+                        let close = format!("\"{}", "#".repeat(hashes));
+                        end += code[end..].find(&close).unwrap();
+                    } else {
+                        while bytes[end] != b'"' {
+                            end += if bytes[end] == b'\\' { 2 } else { 1 };
+                        }
+                    }
+                    found.push((&code[..i], &code[i + 1..end]));
+                    i = end + 1 + if raw { hashes } else { 0 };
+                }
+                _ => i += 1,
+            }
+        }
+        found
+    }
+
+    // Whether `literal` reads as words, as a message does, rather than as
+    // a name, a path or a format.
+    fn prose(literal: &str) -> bool {
+        let words: Vec<&str> = literal.split(' ').collect();
+        words.windows(2).any(|pair| {
+            let word = pair[0].trim_end_matches(&[',', ';', ':', '.'][..]);
+            word.len() > 1
+                && word.chars().all(|c| c.is_ascii_alphabetic() || c == '\'')
+                && pair[1].starts_with(|c: char| {
+                    c.is_ascii_alphabetic() || "'{(".contains(c)
+                })
+        })
+    }
+
+    // Each translation has every message in the catalog, and nothing else,
+    // with the same placeholders.
+    #[test]
+    fn translations_complete() {
+        for (code, source) in TRANSLATIONS {
+            let translated = ids_and_placeholders(source);
+            let ids: Vec<&str> =
+                translated.iter().map(|(id, _)| id.as_str()).collect();
+            let catalog: Vec<&str> = CATALOG.iter().map(|m| m.id).collect();
+            assert_eq!(ids, catalog, "{}", code);
+            for ((id, placeholders), message) in translated.iter().zip(CATALOG)
+            {
+                assert_eq!(
+                    *placeholders,
+                    placeholders_in(message.english),
+                    "{} in {}",
+                    id,
+                    code
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(language_of("de_DE.UTF-8"), "de");
+        assert_eq!(language_of("pt-BR"), "pt");
+        assert_eq!(language_of("C"), "c");
+        assert!(set_language("en_GB.UTF-8").is_ok());
+        let e = set_language("xx").unwrap_err();
+        assert_eq!(e, "There are no messages in xx, only in en, de");
+        if !I18N_BUILT {
+            assert!(set_language("de").unwrap_err().contains("i18n feature"));
+        }
+    }
+
+    #[cfg(feature = "i18n")]
+    #[test]
+    fn german() {
+        for (code, source) in TRANSLATIONS {
+            translation::Translation::load(code, source).unwrap();
+        }
+        let german =
+            translation::Translation::load("de", TRANSLATIONS[0].1).unwrap();
+        let wrote = |pages: usize| {
+            german
+                .render(
+                    DIGEST_WROTE.id,
+                    &[
+                        ("pages", pages.into()),
+                        ("documents", 2usize.into()),
+                        ("path", "digest.pdf".into()),
+                    ],
+                )
+                .unwrap()
+        };
+        assert!(wrote(1).contains("1 markierte Seite "), "{}", wrote(1));
+        assert!(wrote(4).contains("4 markierte Seiten "), "{}", wrote(4));
+        assert!(german.render("no-such-message", &[]).is_none());
+    }
+}
@@ -0,0 +1,534 @@
+//! The record of what has been changed in the cloud, and `undo`.
+//!
+//! Every change the CLI makes is appended to `mutations.log` in the config
+//! directory, one JSON object per line for each field changed:
+//!
+//! * `timestamp`: RFC 3339 time at which the change was made.
+//! * `id`: the document changed.
+//! * `field`: `parent`, `visible_name`, `bookmarked` or `current_page` for
+//!   metadata, or `upload` or `delete` for a whole document.
+//! * `old` and `new`: the field's value before and after, with parents
+//!   given as the cloud gives them: a folder's id, `""` for the root or
+//!   `"trash"`. An upload's `new` and a deletion's `old` are the document's
+//!   name.
+//! * `version`: the version the document is at after the change, or `null`
+//!   for a deletion.
+//! * `undoes`: only for changes made by `undo`, the `version` of the change
+//!   they reversed.
+//!
+//! Entries for the same document at the same version, one after another,
+//! are one change, as a move and rename made together are.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{
+    lock_path, Client, Error, FileLock, MetadataChange, MetadataPatch, Parent,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::msg;
+
+pub const MUTATIONS_LOG: &str = "mutations.log";
+
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Parent,
+    VisibleName,
+    Bookmarked,
+    CurrentPage,
+    Upload,
+    Delete,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub id: Uuid,
+    pub field: Field,
+    pub old: Value,
+    pub new: Value,
+    pub version: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<u64>,
+}
+
+fn parent_value(parent: Parent) -> Value {
+    serde_json::to_value(parent).unwrap()
+}
+
+fn parse_parent(value: &Value) -> Option<Parent> {
+    match value.as_str()? {
+        "" => Some(Parent::Root),
+        "trash" => Some(Parent::Trash),
+        id => id.parse().ok().map(Parent::Folder),
+    }
+}
+
+// One entry for each field `change` set to something else.
+fn change_entries(change: &MetadataChange, undoes: Option<u64>) -> Vec<Entry> {
+    let (before, after) = (&change.before, &change.after);
+    let fields = [
+        (
+            Field::Parent,
+            before.parent.map(parent_value),
+            after.parent.map(parent_value),
+        ),
+        (
+            Field::VisibleName,
+            before.visible_name.clone().map(Value::from),
+            after.visible_name.clone().map(Value::from),
+        ),
+        (
+            Field::Bookmarked,
+            before.bookmarked.map(Value::from),
+            after.bookmarked.map(Value::from),
+        ),
+        (
+            Field::CurrentPage,
+            before.current_page.map(Value::from),
+            after.current_page.map(Value::from),
+        ),
+    ];
+    let timestamp = Utc::now();
+    fields
+        .iter()
+        .filter(|(_, old, new)| old != new)
+        .filter_map(|(field, old, new)| {
+            Some(Entry {
+                timestamp,
+                id: change.id,
+                field: *field,
+                old: old.clone()?,
+                new: new.clone()?,
+                version: Some(change.version),
+                undoes,
+            })
+        })
+        .collect()
+}
+
+/// The append-only log of changes.
+pub struct MutationLog {
+    path: PathBuf,
+}
+
+impl MutationLog {
+    pub fn new(path: PathBuf) -> Self {
+        MutationLog { path }
+    }
+
+    // The change has been made whether or not it can be logged, so failing
+    // to log it is only worth a warning on `out`.
+    fn append(&self, entries: &[Entry], out: &mut dyn Output) {
+        let write = || -> io::Result<()> {
+            let _lock = FileLock::exclusive(&lock_path(&self.path))?;
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            let mut lines = vec![];
+            for entry in entries {
+                serde_json::to_writer(&mut lines, entry)?;
+                lines.push(b'\n');
+            }
+            file.write_all(&lines)
+        };
+        if let Err(e) = write() {
+            out.warn(&msg!(
+                MUTATION_NOT_RECORDED,
+                path = self.path.display().to_string(),
+                error = e.to_string(),
+            ));
+        }
+    }
+
+    /// Records a change of metadata, made by `undo` if `undoes` gives the
+    /// version of the change reversed.
+    pub fn record_change(
+        &self,
+        change: &MetadataChange,
+        undoes: Option<u64>,
+        out: &mut dyn Output,
+    ) {
+        self.append(&change_entries(change, undoes), out);
+    }
+
+    /// Records that a document named `name` was uploaded at `version`.
+    pub fn record_upload(
+        &self,
+        id: Uuid,
+        name: &str,
+        version: u64,
+        out: &mut dyn Output,
+    ) {
+        let entry = Entry {
+            timestamp: Utc::now(),
+            id,
+            field: Field::Upload,
+            old: Value::Null,
+            new: name.into(),
+            version: Some(version),
+            undoes: None,
+        };
+        self.append(&[entry], out);
+    }
+
+    /// Records that the document named `name` was deleted.
+    pub fn record_delete(&self, id: Uuid, name: &str, out: &mut dyn Output) {
+        let entry = Entry {
+            timestamp: Utc::now(),
+            id,
+            field: Field::Delete,
+            old: name.into(),
+            new: Value::Null,
+            version: None,
+            undoes: None,
+        };
+        self.append(&[entry], out);
+    }
+
+    /// Every entry in the log, oldest first, leaving out any line which
+    /// can't be read, such as one cut short.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut entries = vec![];
+        for line in io::BufReader::new(file).lines() {
+            if let Ok(entry) = serde_json::from_str(&line?) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// One change: the entries logged for a document at one version.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mutation {
+    pub id: Uuid,
+    pub version: Option<u64>,
+    pub entries: Vec<Entry>,
+}
+
+impl Mutation {
+    /// Describes the change, as `<id> v3: visible_name "a" → "b"`.
+    pub fn describe(&self) -> String {
+        let fields: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                let field = serde_json::to_value(e.field).unwrap();
+                format!(
+                    "{} {} \u{2192} {}",
+                    field.as_str().unwrap(),
+                    e.old,
+                    e.new
+                )
+            })
+            .collect();
+        match self.version {
+            Some(v) => format!("{} v{}: {}", self.id, v, fields.join(", ")),
+            None => format!("{}: {}", self.id, fields.join(", ")),
+        }
+    }
+}
+
+fn group(entries: &[Entry]) -> Vec<Mutation> {
+    let mut mutations: Vec<Mutation> = vec![];
+    for entry in entries {
+        match mutations.last_mut() {
+            Some(m)
+                if m.id == entry.id
+                    && m.version == entry.version
+                    && m.entries[0].undoes == entry.undoes =>
+            {
+                m.entries.push(entry.clone())
+            }
+            _ => mutations.push(Mutation {
+                id: entry.id,
+                version: entry.version,
+                entries: vec![entry.clone()],
+            }),
+        }
+    }
+    mutations
+}
+
+/// The last `count` changes in `entries` which weren't made by `undo` and
+/// haven't been undone, newest first.
+pub fn undoable(entries: &[Entry], count: usize) -> Vec<Mutation> {
+    let undone: HashSet<(Uuid, u64)> = entries
+        .iter()
+        .filter_map(|e| e.undoes.map(|v| (e.id, v)))
+        .collect();
+    group(entries)
+        .into_iter()
+        .rev()
+        .filter(|m| m.entries[0].undoes.is_none())
+        .filter(|m| m.version.is_none_or(|v| !undone.contains(&(m.id, v))))
+        .take(count)
+        .collect()
+}
+
+/// The patch which reverses `mutation`, or why there can't be one.
+pub fn inverse(mutation: &Mutation) -> Result<MetadataPatch, String> {
+    let damaged = || msg!(UNDO_DAMAGED);
+    let mut patch = MetadataPatch::default();
+    for e in &mutation.entries {
+        match e.field {
+            Field::Upload => return Err(msg!(UNDO_UPLOAD)),
+            Field::Delete => return Err(msg!(UNDO_DELETE)),
+            Field::Parent => {
+                patch.parent = Some(parse_parent(&e.old).ok_or_else(damaged)?)
+            }
+            Field::VisibleName => {
+                let name = e.old.as_str().ok_or_else(damaged)?;
+                patch.visible_name = Some(name.to_string());
+            }
+            Field::Bookmarked => {
+                patch.bookmarked = Some(e.old.as_bool().ok_or_else(damaged)?)
+            }
+            Field::CurrentPage => {
+                let page = e.old.as_i64().ok_or_else(damaged)?;
+                patch.current_page = Some(page as i32);
+            }
+        }
+    }
+    Ok(patch)
+}
+
+/// What came of trying to undo a change.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Undone,
+    /// The document has changed since, and is now at this version.
+    Changed(u64),
+    /// The document no longer exists.
+    Gone,
+    /// The change can't be undone, for this reason.
+    Impossible(String),
+}
+
+/// Reverses the last `count` changes in `log`, newest first, leaving alone
+/// any document which has changed since. Each reversal is logged too,
+/// warning `out` if it can't be.
+pub async fn undo(
+    client: &Client,
+    log: &MutationLog,
+    count: usize,
+    out: &mut dyn Output,
+) -> crate::CliResult<Vec<(Mutation, Outcome)>> {
+    let mut outcomes = vec![];
+    for mutation in undoable(&log.entries()?, count) {
+        let outcome = match (inverse(&mutation), mutation.version) {
+            (Err(reason), _) => Outcome::Impossible(reason),
+            (Ok(_), None) => Outcome::Impossible(msg!(UNDO_NO_VERSION)),
+            (Ok(patch), Some(version)) => match client
+                .modify_metadata_at(mutation.id, version, |p| *p = patch)
+                .await
+            {
+                Ok(change) => {
+                    log.record_change(&change, Some(version), out);
+                    Outcome::Undone
+                }
+                Err(Error::VersionMismatch { found, .. }) => {
+                    Outcome::Changed(found)
+                }
+                Err(Error::EmptyResult) => Outcome::Gone,
+                Err(e) => return Err(e.into()),
+            },
+        };
+        outcomes.push((mutation, outcome));
+    }
+    Ok(outcomes)
+}
+
+/// Undoes the last `count` changes, as `undo` does, telling `out` what came
+/// of each.
+pub async fn undo_and_report(
+    client: &Client,
+    log: &MutationLog,
+    count: usize,
+    out: &mut dyn Output,
+) -> crate::CliResult<()> {
+    let outcomes = undo(client, log, count, out).await?;
+    if outcomes.is_empty() {
+        out.line(&msg!(UNDO_NOTHING));
+    }
+    for (mutation, outcome) in outcomes {
+        let change = mutation.describe();
+        match outcome {
+            Outcome::Undone => out.note(&msg!(UNDONE, change = &change)),
+            Outcome::Changed(version) => out.line(&msg!(
+                UNDO_CHANGED,
+                change = &change,
+                version = version,
+            )),
+            Outcome::Gone => out.line(&msg!(UNDO_GONE, change = &change)),
+            Outcome::Impossible(reason) => {
+                out.line(
+                    &msg!(UNDO_SKIPPED, change = &change, reason = reason,),
+                )
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use remarkable_cloud_api::testing::FakeCloud;
+
+    use super::*;
+    use crate::commands::Capture;
+    use crate::observer::Observers;
+
+    fn entry(id: Uuid, field: Field, old: Value, new: Value) -> Entry {
+        Entry {
+            timestamp: "2024-05-01T12:00:00Z".parse().unwrap(),
+            id,
+            field,
+            old,
+            new,
+            version: Some(2),
+            undoes: None,
+        }
+    }
+
+    #[test]
+    fn inverses() {
+        let (a, b) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let folder = Uuid::from_u128(3);
+        let mut entries = vec![
+            entry(a, Field::Parent, "".into(), folder.to_string().into()),
+            entry(a, Field::VisibleName, "A".into(), "A2".into()),
+            entry(b, Field::Parent, folder.to_string().into(), "trash".into()),
+            entry(b, Field::Upload, Value::Null, "B".into()),
+            entry(a, Field::Bookmarked, false.into(), true.into()),
+            entry(a, Field::CurrentPage, 4.into(), 9.into()),
+        ];
+        entries[3].version = Some(3);
+        entries[4].version = Some(3);
+        entries[5].version = Some(3);
+
+        let all = undoable(&entries, 10);
+        assert_eq!(
+            all.iter().map(|m| (m.id, m.version)).collect::<Vec<_>>(),
+            vec![(a, Some(3)), (b, Some(3)), (b, Some(2)), (a, Some(2))]
+        );
+        assert_eq!(undoable(&entries, 1), all[..1]);
+        assert_eq!(
+            inverse(&all[0]),
+            Ok(MetadataPatch {
+                bookmarked: Some(false),
+                current_page: Some(4),
+                ..Default::default()
+            })
+        );
+        assert_eq!(inverse(&all[1]).unwrap_err(), "uploads can't be undone");
+        assert_eq!(
+            inverse(&all[2]).unwrap().parent,
+            Some(Parent::Folder(folder))
+        );
+        assert_eq!(
+            inverse(&all[3]),
+            Ok(MetadataPatch {
+                parent: Some(Parent::Root),
+                visible_name: Some("A".to_string()),
+                ..Default::default()
+            })
+        );
+        assert!(all[3].describe().ends_with(
+            ": parent \"\" \u{2192} \"00000000-0000-0000-0000-000000000003\", \
+             visible_name \"A\" \u{2192} \"A2\""
+        ));
+
+        // Undoing a change hides it, and the undo itself, from later undos.
+        let mut undo = entry(a, Field::CurrentPage, 9.into(), 4.into());
+        undo.version = Some(4);
+        undo.undoes = Some(3);
+        entries.push(undo);
+        assert_eq!(undoable(&entries, 1)[0].version, Some(3));
+        assert_eq!(undoable(&entries, 1)[0].id, b);
+
+        let mut damaged = all[3].clone();
+        damaged.entries[0].old = 7.into();
+        assert!(inverse(&damaged).is_err());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn undo_against_cloud() {
+        let cloud = FakeCloud::start().await;
+        let books = cloud.add_folder("Books", None);
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let notes = cloud.add_document("Notes", None, vec![]);
+        let gone = cloud.add_document("Gone", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let log = MutationLog::new(dir.path().join(MUTATIONS_LOG));
+        let mut out = Capture::new(Observers::new());
+
+        let docs = client.get_documents().await.unwrap();
+        for id in [dune, notes, gone] {
+            let doc = docs.get(&id).unwrap();
+            let name = format!("{} (old)", doc.visible_name);
+            let change = client
+                .move_document(doc, Parent::Folder(books), &name)
+                .await
+                .unwrap();
+            log.record_change(&change, None, &mut out);
+        }
+        log.record_upload(books, "Books", 2, &mut out);
+        // Read on the tablet since.
+        cloud.modify(&notes, |d| d.version += 1);
+        let docs = client.get_documents().await.unwrap();
+        client
+            .delete_document(docs.get(&gone).unwrap())
+            .await
+            .unwrap();
+
+        let outcomes = undo(&client, &log, 3, &mut out).await.unwrap();
+        let outcomes: Vec<(Uuid, Outcome)> =
+            outcomes.into_iter().map(|(m, o)| (m.id, o)).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                (
+                    books,
+                    Outcome::Impossible("uploads can't be undone".to_string())
+                ),
+                (gone, Outcome::Gone),
+                (notes, Outcome::Changed(3)),
+            ]
+        );
+
+        // Changes which couldn't be undone are still the latest.
+        let outcomes = undo(&client, &log, 4, &mut out).await.unwrap();
+        assert_eq!(outcomes.len(), 4);
+        assert_eq!(outcomes[3].0.id, dune);
+        assert_eq!(outcomes[3].1, Outcome::Undone);
+        let doc = cloud.document(&dune).unwrap();
+        assert_eq!((doc.parent, doc.visible_name.as_str()), (None, "Dune"));
+        assert_eq!(doc.version, 3);
+
+        // The undo is logged, and not itself undone.
+        let entries = log.entries().unwrap();
+        assert_eq!(entries.last().unwrap().undoes, Some(2));
+        let next = undoable(&entries, 10);
+        assert!(next.iter().all(|m| m.id != dune), "{:?}", next);
+        assert!(out.warnings.is_empty(), "{:?}", out.warnings);
+    }
+}
@@ -0,0 +1,176 @@
+//! Local names for documents which share a visible name.
+//!
+//! Names count as shared if they differ only in Unicode normalization, and
+//! where the local filesystem ignores case, only in case, since either way
+//! they'd be written to the same file.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use remarkable_cloud_api::name_key;
+use uuid::Uuid;
+
+/// The environment variable which, set to 1 or 0, says whether local
+/// filesystems ignore case, rather than going by the platform. Lets
+/// collisions macOS and Windows would have be tried out on Linux.
+pub const CASE_INSENSITIVE_VAR: &str = "REMARKABLE_CASE_INSENSITIVE";
+
+static CASE_INSENSITIVE: AtomicBool =
+    AtomicBool::new(cfg!(any(target_os = "macos", target_os = "windows")));
+
+/// Whether local file names are taken to ignore case, as they do by default
+/// on macOS and Windows.
+pub fn case_insensitive() -> bool {
+    CASE_INSENSITIVE.load(Ordering::Relaxed)
+}
+
+/// Takes local file names to ignore case, or not, whatever the platform.
+pub fn set_case_insensitive(on: bool) {
+    CASE_INSENSITIVE.store(on, Ordering::Relaxed)
+}
+
+/// What a local file name is told apart from others by; see `name_key`.
+pub fn local_key(name: &str) -> String {
+    name_key(name, case_insensitive())
+}
+
+/// A document that needs a local name of its own.
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    /// The name of the folder holding the document, or `None` at the root.
+    pub parent: Option<&'a str>,
+    pub id: Uuid,
+}
+
+const ROOT_NAME: &str = "root";
+
+fn counts<'a, I>(names: I, fold_case: bool) -> HashMap<String, usize>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut counts = HashMap::new();
+    for n in names {
+        *counts.entry(name_key(n, fold_case)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Gives each candidate a distinct name, without an extension. Names which
+/// are already unique are kept; the others are qualified with their parent
+/// folder's name, as in "Quick sheets (Work)", and if that still collides,
+/// with the start of their id too. Names the same but for normalization,
+/// or with `fold_case` for case, count as the same.
+pub fn disambiguate(candidates: &[Candidate], fold_case: bool) -> Vec<String> {
+    let plain: Vec<String> =
+        candidates.iter().map(|c| c.name.to_string()).collect();
+    let plain_counts = counts(&plain, fold_case);
+    let qualified: Vec<String> = candidates
+        .iter()
+        .zip(&plain)
+        .map(|(c, name)| {
+            if plain_counts[&name_key(name, fold_case)] == 1 {
+                name.clone()
+            } else {
+                format!("{} ({})", c.name, c.parent.unwrap_or(ROOT_NAME))
+            }
+        })
+        .collect();
+    let qualified_counts = counts(&qualified, fold_case);
+    candidates
+        .iter()
+        .zip(qualified)
+        .map(|(c, name)| {
+            if qualified_counts[&name_key(&name, fold_case)] == 1 {
+                name
+            } else {
+                let id = c.id.to_string();
+                format!(
+                    "{} ({}, {})",
+                    c.name,
+                    c.parent.unwrap_or(ROOT_NAME),
+                    &id[..8]
+                )
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disambiguation() {
+        let ids: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+        let candidates = [
+            Candidate {
+                name: "Quick sheets",
+                parent: Some("Work"),
+                id: ids[0],
+            },
+            Candidate {
+                name: "Quick sheets",
+                parent: None,
+                id: ids[1],
+            },
+            Candidate {
+                name: "Quick sheets",
+                parent: Some("Archive"),
+                id: ids[2],
+            },
+            Candidate {
+                name: "Quick sheets",
+                parent: Some("Archive"),
+                id: ids[3],
+            },
+            Candidate {
+                name: "Notes",
+                parent: Some("Work"),
+                id: ids[4],
+            },
+        ];
+        assert_eq!(
+            disambiguate(&candidates, false),
+            vec![
+                "Quick sheets (Work)".to_string(),
+                "Quick sheets (root)".to_string(),
+                format!("Quick sheets (Archive, {})", &ids[2].to_string()[..8]),
+                format!("Quick sheets (Archive, {})", &ids[3].to_string()[..8]),
+                "Notes".to_string(),
+            ]
+        );
+        assert!(disambiguate(&[], false).is_empty());
+    }
+
+    #[test]
+    fn normalization_and_case() {
+        let ids: Vec<Uuid> =
+            (1..=4).map(|n| Uuid::from_u128(n << 96)).collect();
+        let candidate = |name, parent, id| Candidate { name, parent, id };
+        let candidates = [
+            // The same name from macOS and from Linux.
+            candidate("Re\u{301}sume\u{301}", Some("Jobs"), ids[0]),
+            candidate("Résumé", None, ids[1]),
+            candidate("notes", None, ids[2]),
+            candidate("Notes", None, ids[3]),
+        ];
+        assert_eq!(
+            disambiguate(&candidates, false),
+            [
+                "Re\u{301}sume\u{301} (Jobs)",
+                "Résumé (root)",
+                "notes",
+                "Notes",
+            ]
+        );
+        assert_eq!(
+            disambiguate(&candidates, true),
+            [
+                "Re\u{301}sume\u{301} (Jobs)",
+                "Résumé (root)",
+                "notes (root, 00000003)",
+                "Notes (root, 00000004)",
+            ]
+        );
+    }
+}
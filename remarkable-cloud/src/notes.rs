@@ -0,0 +1,174 @@
+//! Notes on documents, kept in the cloud by `MetaStore`, for `note` and
+//! `find --with-notes`.
+
+use std::collections::HashSet;
+
+use remarkable_cloud_api::{
+    join_path, Client, CloudPath, Document, MetaStore, Note,
+};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::help::Example;
+use crate::resolved::ResolvedTree;
+use crate::trash::TRASH;
+use crate::{locate, msg, CliResult, Location};
+
+/// The examples `note --help` shows.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        command: "remarkable-cloud note set Contracts/Acme 'signed copy, \
+                  sent to client 2024-03-01'",
+        description: "Notes something about a document",
+    },
+    Example {
+        command: "remarkable-cloud note search acme",
+        description: "Shows every note mentioning acme, with its document",
+    },
+    Example {
+        command: "remarkable-cloud note set Contracts/Acme ''",
+        description: "Removes the note on a document",
+    },
+];
+
+/// Whether `note` mentions `term`, ignoring case.
+pub fn mentions(note: &Note, term: &str) -> bool {
+    note.text.to_lowercase().contains(&term.to_lowercase())
+}
+
+/// The path the document `id` is shown with beside its note, which is in
+/// [`TRASH`] if it's in the trash, or `None` if it's gone.
+pub fn path_of(documents: &ResolvedTree, id: &Uuid) -> Option<String> {
+    if let Some(path) = documents.path_of(id) {
+        return Some(path);
+    }
+    let trashed = documents.trashed().find(|d| d.id == *id)?;
+    Some(join_path(&[TRASH, &trashed.visible_name]))
+}
+
+/// The document or folder at `path`, to read or write the note on.
+pub fn target<'a>(
+    documents: &'a ResolvedTree,
+    path: &CloudPath,
+) -> CliResult<&'a Document> {
+    match locate(documents, path)? {
+        Location::Document(d) => Ok(d),
+        Location::Missing(e) => Err(e.into()),
+        Location::Root | Location::Trash => {
+            Err(msg!(NOTE_NOT_ALLOWED, path = path.to_string()).into())
+        }
+    }
+}
+
+/// The notes in `documents` mentioning `term`, each with the path of the
+/// document it's on, in path order. Notes on documents which are gone are
+/// left out.
+pub async fn search(
+    store: &MetaStore<'_>,
+    documents: &ResolvedTree,
+    term: &str,
+) -> CliResult<Vec<(String, Note)>> {
+    let mut found: Vec<(String, Note)> = store
+        .all(documents)
+        .await?
+        .into_iter()
+        .filter(|note| mentions(note, term))
+        .filter_map(|note| Some((path_of(documents, &note.target)?, note)))
+        .collect();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(found)
+}
+
+/// Those of `found` whose note mentions `term`.
+pub async fn with_notes<'a>(
+    store: &MetaStore<'_>,
+    documents: &ResolvedTree,
+    found: Vec<(String, &'a Document)>,
+    term: &str,
+) -> CliResult<Vec<(String, &'a Document)>> {
+    let noted: HashSet<Uuid> = store
+        .all(documents)
+        .await?
+        .into_iter()
+        .filter(|note| mentions(note, term))
+        .map(|note| note.target)
+        .collect();
+    Ok(found
+        .into_iter()
+        .filter(|(_, d)| noted.contains(&d.id))
+        .collect())
+}
+
+/// What `note` was asked to do.
+pub enum NoteCommand {
+    /// Notes `text` on the document at `path`, or removes its note if empty.
+    Set {
+        path: CloudPath,
+        text: String,
+    },
+    Show(CloudPath),
+    Search(String),
+    /// Removes the notes on documents which are gone.
+    Prune,
+}
+
+/// Runs `command` against the notes kept in the cloud, reporting to `out`.
+pub async fn note(
+    client: &Client,
+    documents: &ResolvedTree,
+    command: NoteCommand,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let store = MetaStore::new(client);
+    match command {
+        NoteCommand::Set { path, text } => {
+            let target = target(documents, &path)?;
+            match store.set(documents, target.id, &text).await? {
+                Some(_) => out.note(&msg!(NOTED, path = path.to_string())),
+                None => out.note(&msg!(NOTE_REMOVED, path = path.to_string())),
+            }
+        }
+        NoteCommand::Show(path) => {
+            let target = target(documents, &path)?;
+            match store.get(documents, &target.id).await? {
+                Some(note) => out.line(&note.text),
+                None => out.line(&msg!(NO_NOTE, path = path.to_string())),
+            }
+        }
+        NoteCommand::Search(term) => {
+            for (path, note) in search(&store, documents, &term).await? {
+                out.line(&format!("{}: {}", path, note.text));
+            }
+        }
+        NoteCommand::Prune => {
+            let pruned = store.prune(documents).await?;
+            for doc in &pruned {
+                out.note(&msg!(
+                    NOTE_REMOVED,
+                    path = doc.visible_name.to_string()
+                ));
+            }
+            if pruned.is_empty() {
+                out.note(&msg!(NOTES_NONE_TO_PRUNE));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentioning() {
+        let note = Note {
+            target: Uuid::new_v4(),
+            text: "Signed copy, sent to client".to_string(),
+            updated: chrono::Utc::now(),
+        };
+        assert!(mentions(&note, "signed"));
+        assert!(mentions(&note, "COPY, SENT"));
+        assert!(!mentions(&note, "unsigned"));
+    }
+}
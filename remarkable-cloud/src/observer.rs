@@ -0,0 +1,98 @@
+//! Reporting of the operations a command performs.
+//!
+//! Commands describe what they do as [`Event`]s sent to an [`Observer`]
+//! instead of logging directly, so that everything which wants to know about
+//! progress (the JSON operation log, summaries, progress output) hangs off the
+//! same hook.
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use uuid::Uuid;
+
+/// The stages a command which transfers many documents goes through, in
+/// the order they come.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Fetching the listing.
+    Listing,
+    /// Working out what to transfer, by comparing this many documents with
+    /// what's already done.
+    Planning { compared: usize },
+    /// Checking a plan saved by a run which was interrupted, instead of
+    /// planning, with this many documents left in it.
+    Resuming { checked: usize },
+    /// Transferring this many documents.
+    Transferring { documents: usize },
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// The command moved on to another stage.
+    Phase(Phase),
+    /// A document started downloading. One of the events below follows
+    /// unless the command is interrupted first.
+    Started { path: PathBuf },
+    /// A document was downloaded and written locally.
+    Pulled {
+        path: PathBuf,
+        id: Uuid,
+        output: PathBuf,
+        /// When the document was last changed on the device.
+        modified: chrono::DateTime<chrono::Utc>,
+        bytes: u64,
+        sha256: String,
+        elapsed: Duration,
+    },
+    /// An operation was deliberately not performed.
+    Skipped {
+        path: PathBuf,
+        id: Option<Uuid>,
+        reason: String,
+    },
+    /// An operation was attempted and failed.
+    Failed {
+        path: PathBuf,
+        id: Option<Uuid>,
+        category: &'static str,
+        message: String,
+        elapsed: Duration,
+    },
+}
+
+pub trait Observer {
+    fn observe(&mut self, event: &Event);
+}
+
+/// Fans each event out to every registered observer.
+#[derive(Default)]
+pub struct Observers {
+    observers: Vec<Box<dyn Observer>>,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add(&mut self, observer: Box<dyn Observer>) {
+        self.observers.push(observer);
+    }
+}
+
+impl Observer for Observers {
+    fn observe(&mut self, event: &Event) {
+        for o in self.observers.iter_mut() {
+            o.observe(event);
+        }
+    }
+}
+
+/// Lets an observer be read once the command is done with it.
+impl<O: Observer> Observer for Rc<RefCell<O>> {
+    fn observe(&mut self, event: &Event) {
+        self.borrow_mut().observe(event);
+    }
+}
@@ -0,0 +1,654 @@
+//! Shrinking PDFs before `push --optimize` uploads them, in builds with the
+//! `optimize` feature.
+//!
+//! Objects nothing refers to are dropped, what's left is renumbered, and
+//! streams stored uncompressed are compressed. With a density to downsample
+//! to, images drawn at more than it, as scans usually are, are scaled down
+//! and encoded again as they were. The file isn't linearized: the tablet
+//! reads documents whole once they're downloaded, so it would gain nothing.
+//!
+//! Whatever is made is read back and compared with the original, page count
+//! and text, before it's used. Anything which can't be done with confidence
+//! is an error, and the original should be uploaded as it is instead.
+
+use crate::msg;
+use crate::CliResult;
+
+/// Whether this build can optimize PDFs.
+pub const OPTIMIZE_BUILT: bool = cfg!(feature = "optimize");
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    /// The density, in pixels per inch, to scale images drawn at more than
+    /// it down to, or `None` to leave images as they are.
+    pub downsample_dpi: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct OptimizeReport {
+    /// The optimized PDF, or the original if optimizing didn't make it
+    /// smaller.
+    pub pdf: Vec<u8>,
+    /// The sizes in bytes of the original and of `pdf`.
+    pub before: u64,
+    pub after: u64,
+    pub pages: usize,
+    /// How many objects nothing referred to.
+    pub objects_removed: usize,
+    pub images_downsampled: usize,
+}
+
+impl OptimizeReport {
+    /// A line about what optimizing `name` did.
+    pub fn describe(&self, name: &str) -> String {
+        use crate::summary::format_bytes;
+
+        if self.after >= self.before {
+            return msg!(OPTIMIZE_NOT_SMALLER, name = name);
+        }
+        let (before, after) =
+            (format_bytes(self.before), format_bytes(self.after));
+        if self.images_downsampled > 0 {
+            return msg!(
+                OPTIMIZED_DOWNSAMPLING,
+                name = name,
+                before = before,
+                after = after,
+                count = self.images_downsampled,
+            );
+        }
+        msg!(OPTIMIZED, name = name, before = before, after = after)
+    }
+}
+
+#[cfg(not(feature = "optimize"))]
+pub fn optimize_pdf(
+    _: &[u8],
+    _: &OptimizeOptions,
+) -> CliResult<OptimizeReport> {
+    Err(msg!(OPTIMIZE_NOT_BUILT).into())
+}
+
+/// Optimizes `pdf` as the module describes.
+#[cfg(feature = "optimize")]
+pub fn optimize_pdf(
+    pdf: &[u8],
+    options: &OptimizeOptions,
+) -> CliResult<OptimizeReport> {
+    use lopdf::Document;
+
+    let mut doc = Document::load_mem(pdf)
+        .map_err(|e| msg!(OPTIMIZE_UNREADABLE, error = e.to_string()))?;
+    if doc.is_encrypted() {
+        return Err(msg!(OPTIMIZE_ENCRYPTED).into());
+    }
+    let texts = page_texts(&doc);
+    let images_downsampled = match options.downsample_dpi {
+        Some(dpi) => downsample(&mut doc, dpi),
+        None => 0,
+    };
+    let objects_removed = doc.prune_objects().len();
+    doc.renumber_objects();
+    doc.compress();
+    let mut optimized = vec![];
+    doc.save_to(&mut optimized)
+        .map_err(|e| msg!(OPTIMIZE_UNWRITABLE, error = e.to_string()))?;
+
+    let check = Document::load_mem(&optimized)
+        .map_err(|e| msg!(OPTIMIZE_UNREREADABLE, error = e.to_string()))?;
+    if check.get_pages().len() != texts.len() {
+        return Err(msg!(OPTIMIZE_LOST_PAGES).into());
+    }
+    if page_texts(&check) != texts {
+        return Err(msg!(OPTIMIZE_CHANGED_TEXT).into());
+    }
+    if optimized.len() >= pdf.len() {
+        optimized = pdf.to_vec();
+    }
+    Ok(OptimizeReport {
+        before: pdf.len() as u64,
+        after: optimized.len() as u64,
+        pdf: optimized,
+        pages: texts.len(),
+        objects_removed,
+        images_downsampled,
+    })
+}
+
+// The text of each page, or `None` for those it can't be read from.
+#[cfg(feature = "optimize")]
+fn page_texts(doc: &lopdf::Document) -> Vec<Option<String>> {
+    doc.get_pages()
+        .keys()
+        .map(|n| doc.extract_text(&[*n]).ok())
+        .collect()
+}
+
+// Downsamples the images drawn at more than `dpi`, returning how many were.
+//
+// An image's density is worked out as if it filled the largest page it's
+// drawn on. Those drawn smaller are denser than that, so no image ends up
+// less dense than `dpi`, though some may be left denser.
+#[cfg(feature = "optimize")]
+fn downsample(doc: &mut lopdf::Document, dpi: u32) -> usize {
+    use std::collections::BTreeMap;
+
+    use lopdf::Object;
+
+    let mut placed: BTreeMap<lopdf::ObjectId, (f32, f32)> = BTreeMap::new();
+    for page in doc.get_pages().values() {
+        let size = match page_size(doc, *page) {
+            Some(size) => size,
+            None => continue,
+        };
+        for image in doc.get_page_images(*page).unwrap_or_default() {
+            let largest = placed.entry(image.id).or_insert(size);
+            if size.0 * size.1 > largest.0 * largest.1 {
+                *largest = size;
+            }
+        }
+    }
+    let mut downsampled = 0;
+    for (id, size) in placed {
+        let stream = match doc.get_object(id).and_then(Object::as_stream) {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if let Some(smaller) = resample(stream, size, dpi) {
+            doc.objects.insert(id, Object::Stream(smaller));
+            downsampled += 1;
+        }
+    }
+    downsampled
+}
+
+// The page's width and height in inches, from its media box or the one it
+// inherits.
+#[cfg(feature = "optimize")]
+fn page_size(
+    doc: &lopdf::Document,
+    page: lopdf::ObjectId,
+) -> Option<(f32, f32)> {
+    use lopdf::Object;
+
+    let mut node = doc.get_dictionary(page).ok()?;
+    // Deep enough for any real page tree, and no further if it loops.
+    for _ in 0..32 {
+        if let Ok(media_box) = node.get(b"MediaBox") {
+            let (_, media_box) = doc.dereference(media_box).ok()?;
+            let corners = media_box
+                .as_array()
+                .ok()?
+                .iter()
+                .map(Object::as_float)
+                .collect::<Result<Vec<f32>, _>>()
+                .ok()?;
+            return match corners[..] {
+                [x0, y0, x1, y1] => {
+                    let width = (x1 - x0).abs() / 72.0;
+                    let height = (y1 - y0).abs() / 72.0;
+                    Some((width, height))
+                        .filter(|_| width > 0.0 && height > 0.0)
+                }
+                _ => None,
+            };
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+// The image `stream` scaled down to `dpi` on a page of `size`, encoded as it
+// was, or `None` if it's no denser than that, or isn't an image this knows
+// how to scale: 8-bit grey or RGB, compressed with Flate or as a JPEG, and
+// without masks or anything else depending on its pixels.
+#[cfg(feature = "optimize")]
+fn resample(
+    stream: &lopdf::Stream,
+    size: (f32, f32),
+    dpi: u32,
+) -> Option<lopdf::Stream> {
+    use lopdf::Object;
+
+    let dict = &stream.dict;
+    let risky: &[&[u8]] = &[
+        b"SMask",
+        b"Mask",
+        b"Decode",
+        b"DecodeParms",
+        b"ImageMask",
+        b"Alternates",
+        b"SMaskInData",
+    ];
+    if risky.iter().any(|key| dict.has(key)) {
+        return None;
+    }
+    if dict
+        .get(b"BitsPerComponent")
+        .and_then(Object::as_i64)
+        .ok()?
+        != 8
+    {
+        return None;
+    }
+    let channels =
+        match dict.get(b"ColorSpace").and_then(Object::as_name).ok()? {
+            b"DeviceGray" => 1,
+            b"DeviceRGB" => 3,
+            _ => return None,
+        };
+    let width = dict.get(b"Width").and_then(Object::as_i64).ok()?;
+    let height = dict.get(b"Height").and_then(Object::as_i64).ok()?;
+    if width <= 0 || height <= 0 {
+        return None;
+    }
+    let (width, height) = (width as usize, height as usize);
+    // Compared long side to long side and short to short, in case the
+    // image is turned on the page.
+    let (long, short) = (width.max(height) as f32, width.min(height) as f32);
+    let (long_in, short_in) = (size.0.max(size.1), size.0.min(size.1));
+    let density = (long / long_in).max(short / short_in);
+    if density <= dpi as f32 {
+        return None;
+    }
+    let scale = dpi as f32 / density;
+    let new_width = ((width as f32 * scale).ceil() as usize).max(1);
+    let new_height = ((height as f32 * scale).ceil() as usize).max(1);
+    if new_width >= width && new_height >= height {
+        return None;
+    }
+
+    let filter = stream.filters().ok()?;
+    let content = match filter.iter().map(String::as_str).collect::<Vec<_>>()[..]
+    {
+        ["FlateDecode"] => {
+            let pixels = inflate(&stream.content)?;
+            if pixels.len() != width * height * channels {
+                return None;
+            }
+            let pixels = shrink(
+                &pixels,
+                (width, height),
+                channels,
+                (new_width, new_height),
+            );
+            deflate(&pixels)?
+        }
+        ["DCTDecode"] => {
+            let pixels = decode_jpeg(&stream.content, width, height, channels)?;
+            let pixels = shrink(
+                &pixels,
+                (width, height),
+                channels,
+                (new_width, new_height),
+            );
+            encode_jpeg(&pixels, (new_width, new_height), channels)?
+        }
+        _ => return None,
+    };
+    if content.len() >= stream.content.len() {
+        return None;
+    }
+    let mut smaller = stream.clone();
+    smaller.dict.set("Width", new_width as i64);
+    smaller.dict.set("Height", new_height as i64);
+    smaller.set_content(content);
+    Some(smaller)
+}
+
+#[cfg(feature = "optimize")]
+fn inflate(data: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Read;
+
+    let mut pixels = vec![];
+    flate2::read::ZlibDecoder::new(data)
+        .read_to_end(&mut pixels)
+        .ok()?;
+    Some(pixels)
+}
+
+#[cfg(feature = "optimize")]
+fn deflate(pixels: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder =
+        flate2::write::ZlibEncoder::new(vec![], flate2::Compression::best());
+    encoder.write_all(pixels).ok()?;
+    encoder.finish().ok()
+}
+
+// The pixels of a JPEG, if it's of the size and number of channels the
+// image's dictionary says.
+#[cfg(feature = "optimize")]
+fn decode_jpeg(
+    jpeg: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+) -> Option<Vec<u8>> {
+    use jpeg_decoder::PixelFormat;
+
+    let mut decoder = jpeg_decoder::Decoder::new(jpeg);
+    let pixels = decoder.decode().ok()?;
+    let info = decoder.info()?;
+    let decoded_channels = match info.pixel_format {
+        PixelFormat::L8 => 1,
+        PixelFormat::RGB24 => 3,
+        _ => return None,
+    };
+    let same = (info.width as usize, info.height as usize, decoded_channels)
+        == (width, height, channels);
+    Some(pixels).filter(|_| same)
+}
+
+#[cfg(feature = "optimize")]
+fn encode_jpeg(
+    pixels: &[u8],
+    (width, height): (usize, usize),
+    channels: usize,
+) -> Option<Vec<u8>> {
+    use jpeg_encoder::{ColorType, Encoder};
+
+    let color = if channels == 1 {
+        ColorType::Luma
+    } else {
+        ColorType::Rgb
+    };
+    let mut jpeg = vec![];
+    Encoder::new(&mut jpeg, 85)
+        .encode(pixels, width as u16, height as u16, color)
+        .ok()?;
+    Some(jpeg)
+}
+
+// Scales `pixels` down from `from` to `to`, each pixel the average of those
+// it covers.
+#[cfg_attr(not(feature = "optimize"), allow(dead_code))]
+fn shrink(
+    pixels: &[u8],
+    from: (usize, usize),
+    channels: usize,
+    to: (usize, usize),
+) -> Vec<u8> {
+    // The source pixels the `i`th of `count` covers, of `of`.
+    let span = |i: usize, count: usize, of: usize| {
+        let start = i * of / count;
+        let end = ((i + 1) * of / count).max(start + 1);
+        start..end
+    };
+    let mut shrunk = Vec::with_capacity(to.0 * to.1 * channels);
+    for y in 0..to.1 {
+        let rows = span(y, to.1, from.1);
+        for x in 0..to.0 {
+            let columns = span(x, to.0, from.0);
+            let count = rows.len() * columns.len();
+            for c in 0..channels {
+                let mut sum = 0;
+                for row in rows.clone() {
+                    for column in columns.clone() {
+                        sum += pixels[(row * from.0 + column) * channels + c]
+                            as usize;
+                    }
+                }
+                shrunk.push(((sum + count / 2) / count) as u8);
+            }
+        }
+    }
+    shrunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shrinks() {
+        // Each output pixel averages the 2x2 it covers.
+        let pixels = [0, 10, 20, 30, 20, 30, 40, 50];
+        assert_eq!(shrink(&pixels, (4, 2), 1, (2, 1)), [15, 35]);
+        let rgb = [0, 0, 9, 255, 255, 9];
+        assert_eq!(shrink(&rgb, (2, 1), 3, (1, 1)), [128, 128, 9]);
+        // Never down to nothing, whatever the ratio.
+        let pixels = vec![7; 100 * 3];
+        assert_eq!(shrink(&pixels, (100, 3), 1, (3, 1)), [7, 7, 7]);
+    }
+
+    #[cfg(not(feature = "optimize"))]
+    #[test]
+    fn not_built() {
+        assert!(optimize_pdf(b"%PDF-1.4", &Default::default()).is_err());
+    }
+
+    #[cfg(feature = "optimize")]
+    mod fixtures {
+        use std::io::Write;
+
+        use lopdf::content::{Content, Operation};
+        use lopdf::{dictionary, Document, Object, Stream};
+
+        // The pixels of a grey scan, noisy enough not to compress to
+        // nothing.
+        pub fn scan(width: usize, height: usize, channels: usize) -> Vec<u8> {
+            let mut pixels = Vec::with_capacity(width * height * channels);
+            let mut seed = 7u32;
+            for y in 0..height {
+                for x in 0..width {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    let ink = if (x / 40 + y / 60) % 2 == 0 { 40 } else { 220 };
+                    let noise = (seed >> 16) as u8 % 16;
+                    for _ in 0..channels {
+                        pixels.push(ink + noise);
+                    }
+                }
+            }
+            pixels
+        }
+
+        pub fn flate(data: &[u8]) -> Vec<u8> {
+            let mut encoder = flate2::write::ZlibEncoder::new(
+                vec![],
+                flate2::Compression::default(),
+            );
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        }
+
+        pub fn jpeg(width: usize, height: usize) -> Vec<u8> {
+            let mut jpeg = vec![];
+            jpeg_encoder::Encoder::new(&mut jpeg, 95)
+                .encode(
+                    &scan(width, height, 3),
+                    width as u16,
+                    height as u16,
+                    jpeg_encoder::ColorType::Rgb,
+                )
+                .unwrap();
+            jpeg
+        }
+
+        fn image(width: usize, height: usize, jpeg: bool) -> Stream {
+            let (color_space, filter, data) = if jpeg {
+                ("DeviceRGB", "DCTDecode", self::jpeg(width, height))
+            } else {
+                ("DeviceGray", "FlateDecode", flate(&scan(width, height, 1)))
+            };
+            Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => width as i64,
+                    "Height" => height as i64,
+                    "ColorSpace" => color_space,
+                    "BitsPerComponent" => 8,
+                    "Filter" => filter,
+                },
+                data,
+            )
+        }
+
+        // A letter-sized PDF of two pages of text, each over a scan at
+        // `dpi`, the first grey and the second in colour as a JPEG, with
+        // its content stored uncompressed and an object nothing uses.
+        pub fn pdf(dpi: usize) -> Vec<u8> {
+            let mut doc = Document::with_version("1.5");
+            let pages_id = doc.new_object_id();
+            let font_id = doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => "Helvetica",
+                "Encoding" => "WinAnsiEncoding",
+            });
+            let (width, height) = (dpi * 17 / 2, dpi * 11);
+            let mut kids = vec![];
+            for (n, text) in
+                ["The first page", "The second page"].iter().enumerate()
+            {
+                let image_id = doc.add_object(image(width, height, n == 1));
+                let content = Content {
+                    operations: vec![
+                        Operation::new("q", vec![]),
+                        Operation::new(
+                            "cm",
+                            vec![
+                                612.into(),
+                                0.into(),
+                                0.into(),
+                                792.into(),
+                                0.into(),
+                                0.into(),
+                            ],
+                        ),
+                        Operation::new("Do", vec!["Scan".into()]),
+                        Operation::new("Q", vec![]),
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                        Operation::new("Td", vec![72.into(), 700.into()]),
+                        Operation::new(
+                            "Tj",
+                            vec![Object::string_literal(*text)],
+                        ),
+                        Operation::new("ET", vec![]),
+                    ],
+                };
+                let content_id = doc.add_object(
+                    Stream::new(dictionary! {}, content.encode().unwrap())
+                        .with_compression(false),
+                );
+                kids.push(Object::from(doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                    "Resources" => dictionary! {
+                        "Font" => dictionary! { "F1" => font_id },
+                        "XObject" => dictionary! { "Scan" => image_id },
+                    },
+                })));
+            }
+            doc.objects.insert(
+                pages_id,
+                Object::Dictionary(dictionary! {
+                    "Type" => "Pages",
+                    "Kids" => kids,
+                    "Count" => 2,
+                    "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                }),
+            );
+            // Left over from an earlier edit, say.
+            doc.add_object(
+                Stream::new(dictionary! {}, vec![b'x'; 20_000])
+                    .with_compression(false),
+            );
+            let catalog_id = doc.add_object(dictionary! {
+                "Type" => "Catalog",
+                "Pages" => pages_id,
+            });
+            doc.trailer.set("Root", catalog_id);
+            let mut pdf = vec![];
+            doc.save_to(&mut pdf).unwrap();
+            pdf
+        }
+    }
+
+    #[cfg(feature = "optimize")]
+    fn images(pdf: &[u8]) -> Vec<(i64, i64)> {
+        let doc = lopdf::Document::load_mem(pdf).unwrap();
+        doc.get_pages()
+            .values()
+            .flat_map(|page| doc.get_page_images(*page).unwrap())
+            .map(|image| (image.width, image.height))
+            .collect()
+    }
+
+    #[cfg(feature = "optimize")]
+    fn texts(pdf: &[u8]) -> Vec<String> {
+        let doc = lopdf::Document::load_mem(pdf).unwrap();
+        (1..=2).map(|n| doc.extract_text(&[n]).unwrap()).collect()
+    }
+
+    #[cfg(feature = "optimize")]
+    #[test]
+    fn prunes() {
+        let pdf = fixtures::pdf(30);
+        let report = optimize_pdf(&pdf, &Default::default()).unwrap();
+        assert!(report.after < report.before, "{:?}", report.after);
+        assert_eq!(report.before, pdf.len() as u64);
+        assert_eq!(report.after, report.pdf.len() as u64);
+        assert_eq!(report.pages, 2);
+        // The unused stream, and the cross-reference stream, which is
+        // written afresh.
+        assert_eq!(report.objects_removed, 2);
+        assert_eq!(report.images_downsampled, 0);
+        assert_eq!(texts(&report.pdf), texts(&pdf));
+        assert!(texts(&pdf)[1].contains("The second page"));
+        assert_eq!(images(&report.pdf), images(&pdf));
+
+        // Nothing more to gain the second time, so it's left as it is.
+        let again = optimize_pdf(&report.pdf, &Default::default()).unwrap();
+        assert_eq!(again.pdf, report.pdf);
+        assert!(again.describe("a.pdf").starts_with("Left a.pdf as it was"));
+    }
+
+    #[cfg(feature = "optimize")]
+    #[test]
+    fn downsamples() {
+        let pdf = fixtures::pdf(300);
+        let options = OptimizeOptions {
+            downsample_dpi: Some(100),
+        };
+        let report = optimize_pdf(&pdf, &options).unwrap();
+        assert_eq!(report.images_downsampled, 2);
+        assert!(
+            report.after * 4 < report.before,
+            "{} to {}",
+            report.before,
+            report.after
+        );
+        assert_eq!(images(&report.pdf), [(850, 1100), (850, 1100)]);
+        assert_eq!(report.pages, 2);
+        assert_eq!(texts(&report.pdf), texts(&pdf));
+        assert!(report
+            .describe("scan.pdf")
+            .ends_with("downsampling 2 images"));
+
+        // Already less dense than asked for.
+        let options = OptimizeOptions {
+            downsample_dpi: Some(400),
+        };
+        let report = optimize_pdf(&pdf, &options).unwrap();
+        assert_eq!(report.images_downsampled, 0);
+        assert_eq!(images(&report.pdf), [(2550, 3300), (2550, 3300)]);
+    }
+
+    #[cfg(feature = "optimize")]
+    #[test]
+    fn refuses() {
+        let options = OptimizeOptions {
+            downsample_dpi: Some(100),
+        };
+        assert!(optimize_pdf(b"%PDF-1.4 not really", &options).is_err());
+        let mut truncated = fixtures::pdf(30);
+        truncated.truncate(truncated.len() / 2);
+        assert!(optimize_pdf(&truncated, &options).is_err());
+    }
+}
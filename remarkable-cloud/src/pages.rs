@@ -0,0 +1,203 @@
+//! Page numbers as given to `pages`, and what it prints.
+//!
+//! Pages are numbered from 1 on the command line, and from 0 everywhere
+//! else.
+
+use std::collections::HashSet;
+
+use remarkable_cloud_api::{Client, PageInfo};
+
+use crate::commands::Output;
+use crate::mutations::MutationLog;
+use crate::resolved::ResolvedTree;
+use crate::{document_at, msg, targets, CliResult};
+
+/// What `pages` was asked to do with a document's pages.
+pub enum PagesAction {
+    List,
+    /// Deletes the pages a list such as `2,4-6` gives.
+    Delete(String),
+    /// Puts the pages in the order a list gives, which must name them all.
+    Reorder(String),
+}
+
+/// Parses a list of page numbers and ranges, such as `2,1,3-10`, into
+/// indexes of pages in a document of `page_count`. A range may run
+/// backwards, as `5-1` does.
+pub fn parse_list(spec: &str, page_count: usize) -> Result<Vec<usize>, String> {
+    let number = |s: &str| -> Result<usize, String> {
+        let n: usize = s.trim().parse().map_err(|_| {
+            msg!(PAGES_NOT_A_NUMBER, page = format!("{:?}", s.trim()))
+        })?;
+        if n == 0 || n > page_count {
+            return Err(msg!(PAGES_NO_SUCH, page = n, count = page_count));
+        }
+        Ok(n - 1)
+    };
+    let mut indexes = vec![];
+    for part in spec.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (number(first)?, number(last)?);
+                if first <= last {
+                    indexes.extend(first..=last);
+                } else {
+                    indexes.extend((last..=first).rev());
+                }
+            }
+            None => indexes.push(number(part)?),
+        }
+    }
+    Ok(indexes)
+}
+
+/// Checks that `order` gives each of `page_count` pages exactly once.
+pub fn check_complete(
+    order: &[usize],
+    page_count: usize,
+) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    if let Some(index) = order.iter().find(|i| !seen.insert(**i)) {
+        return Err(msg!(PAGES_TWICE, page = index + 1));
+    }
+    match (0..page_count).find(|i| !seen.contains(i)) {
+        Some(index) => Err(msg!(PAGES_MISSING, page = index + 1)),
+        None => Ok(()),
+    }
+}
+
+/// The order of the pages left when those at `deleted` are deleted.
+pub fn without(page_count: usize, deleted: &[usize]) -> Vec<usize> {
+    (0..page_count).filter(|i| !deleted.contains(i)).collect()
+}
+
+/// A line for each page of `pages`, in `order`, numbered by where it ends
+/// up, noting where it was if that's elsewhere.
+pub fn lines(pages: &[PageInfo], order: &[usize]) -> Vec<String> {
+    order
+        .iter()
+        .enumerate()
+        .map(|(new, old)| {
+            let page = &pages[*old];
+            let moved = if new == *old {
+                String::new()
+            } else {
+                format!(" {}", msg!(PAGES_WAS, page = old + 1))
+            };
+            let strokes = match page.stroke_count {
+                Some(n) => msg!(CONTENT_STROKES, count = n),
+                None => msg!(CONTENT_STROKES_UNREADABLE),
+            };
+            format!(
+                "{}{}  {}  {}  {}",
+                new + 1,
+                moved,
+                page.id.as_deref().unwrap_or("-"),
+                page.template.as_deref().unwrap_or("-"),
+                strokes
+            )
+        })
+        .collect()
+}
+
+/// Lists, deletes or reorders the pages of the document at `path`,
+/// reporting to `out`. With `dry_run`, reports the pages as they'd be
+/// instead; with `cached`, checks the document hasn't changed in the cloud
+/// first.
+#[allow(clippy::too_many_arguments)]
+pub async fn pages(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &ResolvedTree,
+    path: &str,
+    action: &PagesAction,
+    dry_run: bool,
+    cached: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let doc = document_at(documents, &path.parse()?)?;
+    let page_list = client.pages(doc).await?;
+    let count = page_list.len();
+    let order = match action {
+        PagesAction::List => {
+            let order: Vec<usize> = (0..count).collect();
+            for line in lines(&page_list, &order) {
+                out.line(&line);
+            }
+            return Ok(());
+        }
+        PagesAction::Delete(spec) => {
+            let deleted = parse_list(spec, count)?;
+            without(count, &deleted)
+        }
+        PagesAction::Reorder(spec) => {
+            let order = parse_list(spec, count)?;
+            check_complete(&order, count)?;
+            order
+        }
+    };
+    if dry_run {
+        for line in lines(&page_list, &order) {
+            out.line(&line);
+        }
+        return Ok(());
+    }
+    if cached {
+        let targets = vec![(path.to_string(), doc)];
+        targets::check_unchanged(client, &targets).await?;
+    }
+    client.set_page_order(doc, &order).await?;
+    mutations.record_upload(doc.id, &doc.visible_name, doc.version + 1, out);
+    if let PagesAction::Delete(_) = action {
+        out.note(&msg!(
+            PAGES_DELETED,
+            count = count - order.len(),
+            path = path.to_string(),
+        ));
+    } else {
+        out.note(&msg!(PAGES_REORDERED, path = path.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_lists() {
+        assert_eq!(parse_list("2,1,3-5", 5).unwrap(), vec![1, 0, 2, 3, 4]);
+        assert_eq!(parse_list("3-1", 3).unwrap(), vec![2, 1, 0]);
+        assert_eq!(parse_list(" 2 ", 3).unwrap(), vec![1]);
+        assert!(parse_list("0", 3).unwrap_err().contains("no page 0"));
+        assert!(parse_list("2-4", 3).unwrap_err().contains("no page 4"));
+        assert!(parse_list("1,,2", 3).unwrap_err().contains("\"\""));
+        assert!(parse_list("a", 3).is_err());
+
+        assert!(check_complete(&[1, 0, 2], 3).is_ok());
+        let twice = check_complete(&[1, 0, 1], 3).unwrap_err();
+        assert!(twice.contains("page 2 is given twice"), "{}", twice);
+        let missing = check_complete(&[2, 0], 3).unwrap_err();
+        assert!(missing.contains("page 2 is missing"), "{}", missing);
+
+        assert_eq!(without(5, &[2, 6, 0]), vec![1, 3, 4]);
+    }
+
+    #[test]
+    fn page_lines() {
+        let page = |id: &str, strokes| PageInfo {
+            id: Some(id.to_string()),
+            template: Some("Blank".to_string()),
+            stroke_count: strokes,
+        };
+        let pages = [page("a", Some(3)), page("b", None)];
+        assert_eq!(
+            lines(&pages, &[0, 1]),
+            vec!["1  a  Blank  3 strokes", "2  b  Blank  strokes unreadable"]
+        );
+        assert_eq!(
+            lines(&pages, &[1]),
+            vec!["1 (was 2)  b  Blank  strokes unreadable"]
+        );
+    }
+}
@@ -0,0 +1,621 @@
+//! Partial listings: the part of the listing below some folders, which
+//! `cache warm --folder` caches for accounts so large that reading the
+//! whole listing back each run is slow.
+//!
+//! The cloud only lists everything, so warming fetches the whole listing
+//! and keeps what's below each folder asked for, with the folders leading
+//! down to it and everything beside each of those. Warming another folder
+//! later cuts the listing again for all of them, so every part is as of the
+//! same moment rather than pieced together from different ones, and a
+//! folder since deleted, or inside another, stops being covered.
+//!
+//! Only a lookup which looked in nothing but folders held whole is answered
+//! from one, see [`shallow`]; anything else is fetched. Commands which need
+//! the whole listing never see a partial one, as `ListingCache::load`
+//! leaves it out.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+
+use remarkable_cloud_api::{
+    lookup_with, name_key, CloudPath, Document, Documents, Parent, Resolved,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A folder a partial listing holds everything below.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Covered {
+    pub id: Uuid,
+    /// Its path when the listing was cut, for saying what's covered.
+    pub path: String,
+}
+
+/// What `cache warm --folder` caches of the listing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialListing {
+    covered: Vec<Covered>,
+    documents: Documents,
+    // The folders all of whose documents are in `documents`, `None` for the
+    // root.
+    whole: HashSet<Option<Uuid>>,
+}
+
+impl PartialListing {
+    /// The part of `full` below each of `folders`. Those which aren't
+    /// folders in it, which have no path, or which are inside another of
+    /// them are left out.
+    pub fn of(full: &Documents, folders: &[Uuid]) -> Self {
+        let mut roots: Vec<Uuid> = vec![];
+        for id in folders {
+            if full.get(id).is_some_and(Document::is_folder)
+                && !roots.contains(id)
+            {
+                roots.push(*id);
+            }
+        }
+        let nested = |id: &Uuid| {
+            roots.iter().any(|root| {
+                root != id && full.is_ancestor_of(root, id).unwrap_or(true)
+            })
+        };
+        let mut covered = vec![];
+        let mut documents = Documents::default();
+        for &id in roots.iter().filter(|id| !nested(id)) {
+            let (ancestors, path) =
+                match (full.ancestors(&id), full.path_of(&id)) {
+                    (Ok(ancestors), Some(path)) => (ancestors, path),
+                    _ => continue,
+                };
+            let above = std::iter::once(None)
+                .chain(ancestors.iter().map(|folder| Some(folder.id)));
+            for parent in above {
+                for d in full.get_children(&parent) {
+                    documents.insert(d.clone());
+                }
+            }
+            for (_, d) in full.descendants(Parent::Folder(id)) {
+                documents.insert(d.clone());
+            }
+            covered.push(Covered { id, path });
+        }
+        Self::new(covered, documents)
+    }
+
+    // Works out what `documents` holds all of from what's covered: the
+    // root, the folders leading down to each covered folder, and everything
+    // below one. That's documents as well as folders, as a listing can give
+    // a document children, and listing it lists those.
+    fn new(covered: Vec<Covered>, documents: Documents) -> Self {
+        let mut whole = HashSet::new();
+        for root in &covered {
+            if let Ok(ancestors) = documents.ancestors(&root.id) {
+                whole.insert(None);
+                whole.extend(ancestors.iter().map(|folder| Some(folder.id)));
+                whole.insert(Some(root.id));
+                whole.extend(
+                    documents
+                        .descendants(Parent::Folder(root.id))
+                        .map(|(_, d)| Some(d.id)),
+                );
+            }
+        }
+        PartialListing {
+            covered,
+            documents,
+            whole,
+        }
+    }
+
+    /// The folders it holds everything below, in the order they were
+    /// warmed.
+    pub fn covered(&self) -> &[Covered] {
+        &self.covered
+    }
+
+    /// The paths of the folders it covers, as messages list them.
+    pub fn covered_paths(&self) -> String {
+        let paths: Vec<&str> =
+            self.covered.iter().map(|c| c.path.as_str()).collect();
+        paths.join(", ")
+    }
+
+    pub fn documents(&self) -> &Documents {
+        &self.documents
+    }
+
+    /// Whether every document in `folder`, or at the root for `None`, is
+    /// in it: those in a covered folder, and in the folders above one.
+    /// `folder` may be a document, which is held whole if nothing it has in
+    /// it can be missing.
+    pub fn holds_whole(&self, folder: Option<Uuid>) -> bool {
+        self.whole.contains(&folder)
+    }
+
+    /// What `ls --shallow` needs of it to list `paths`, as [`shallow`].
+    pub fn shallow(
+        &self,
+        paths: &[CloudPath],
+        normalized: bool,
+    ) -> Option<Documents> {
+        shallow(
+            &self.documents,
+            &|folder| self.holds_whole(folder),
+            paths,
+            normalized,
+        )
+    }
+}
+
+// How a partial listing is saved: an object, where a whole listing is an
+// array, so that neither is ever read as the other.
+#[derive(Serialize)]
+struct Saved<'a> {
+    partial: &'a [Covered],
+    documents: &'a Documents,
+}
+
+#[derive(Deserialize)]
+struct Loaded {
+    partial: Vec<Covered>,
+    documents: Documents,
+}
+
+impl Serialize for PartialListing {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Saved {
+            partial: &self.covered,
+            documents: &self.documents,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PartialListing {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let loaded = Loaded::deserialize(deserializer)?;
+        Ok(PartialListing::new(loaded.partial, loaded.documents))
+    }
+}
+
+// Whether `name`, as written in a path, names something called
+// `visible_name`, as `ResolvedTree::same_name` has it.
+fn same_name(name: &str, visible_name: &str, normalized: bool) -> bool {
+    name == visible_name
+        || normalized && name_key(name, true) == name_key(visible_name, true)
+}
+
+/// `documents` cut down to what `ls --shallow` shows of `paths`, with names
+/// matched as `--normalize-paths` does if `normalized`: what's in each
+/// folder listed, the folders leading down to it, and everything looked at
+/// finding it, so that looking each path up again finds the same and
+/// fails the same way.
+///
+/// `whole` says which folders, or the root for `None`, `documents` holds
+/// everything in. If finding a path looked in any other, something
+/// `documents` hasn't got might have matched too, and if one is such a
+/// folder, or a document in one, what's in it might not all be there, so
+/// it's `None`, for the listing to be fetched instead. The same goes for
+/// the trash, and for ids `documents` hasn't got.
+pub fn shallow(
+    documents: &Documents,
+    whole: &dyn Fn(Option<Uuid>) -> bool,
+    paths: &[CloudPath],
+    normalized: bool,
+) -> Option<Documents> {
+    let complete = Cell::new(true);
+    let seen = RefCell::new(vec![]);
+    let named = |parent: Option<Uuid>, name: &str| {
+        if !whole(parent) {
+            complete.set(false);
+        }
+        let found: Vec<&Document> = documents
+            .get_children(&parent)
+            .into_iter()
+            .filter(|d| same_name(name, &d.visible_name, normalized))
+            .collect();
+        seen.borrow_mut().extend(found.iter().copied());
+        found
+    };
+    let mut listed = vec![];
+    for path in paths {
+        let found = match path {
+            CloudPath::Trash(_) => return None,
+            CloudPath::Id(id) => Ok(Resolved::Document(documents.get(id)?)),
+            CloudPath::Tree(_) => lookup_with(documents, path, &named),
+        };
+        if !complete.get() {
+            return None;
+        }
+        match found {
+            Ok(Resolved::Root) => listed.push(None),
+            // Even a document is listed: what the listing has in it is
+            // shown, so it has to be held whole too.
+            Ok(Resolved::Document(d)) => {
+                seen.borrow_mut().push(d);
+                listed.push(Some(d.id));
+            }
+            Ok(Resolved::Trash) => return None,
+            // Nothing or too much was found, among documents all of which
+            // are there, so looking again fails the same way.
+            Err(_) => {}
+        }
+    }
+    if !listed.iter().all(|&folder| whole(folder)) {
+        return None;
+    }
+    let mut kept = Documents::default();
+    for d in seen.into_inner() {
+        for ancestor in documents.ancestors(&d.id).ok()? {
+            kept.insert(ancestor.clone());
+        }
+        kept.insert(d.clone());
+    }
+    for folder in listed {
+        for d in documents.get_children(&folder) {
+            kept.insert(d.clone());
+        }
+    }
+    Some(kept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{self, ListOptions};
+    use crate::resolved::ResolvedTree;
+    use crate::testutil::listing;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    fn path(s: &str) -> CloudPath {
+        s.parse().unwrap()
+    }
+
+    fn account() -> Documents {
+        listing(&[
+            (1, "Work", None, "CollectionType"),
+            (2, "Plans", Some(1), "DocumentType"),
+            (3, "Clients", Some(1), "CollectionType"),
+            (4, "Acme", Some(3), "DocumentType"),
+            (5, "Home", None, "CollectionType"),
+            (6, "Recipes", Some(5), "DocumentType"),
+            (7, "Inbox", None, "DocumentType"),
+            (8, "Old", Some(5), "CollectionType"),
+            (9, "Taxes", Some(8), "DocumentType"),
+        ])
+    }
+
+    fn covered(partial: &PartialListing) -> Vec<&str> {
+        partial.covered().iter().map(|c| c.path.as_str()).collect()
+    }
+
+    #[test]
+    fn cut() {
+        let full = account();
+        let partial = PartialListing::of(&full, &[id(3)]);
+        assert_eq!(covered(&partial), ["Work/Clients"]);
+        let mut ids: Vec<u128> =
+            partial.documents().iter().map(|d| d.id.as_u128()).collect();
+        ids.sort();
+        // What's in Clients, and beside it and Work, but not what's in Home.
+        assert_eq!(ids, [1, 2, 3, 4, 5, 7]);
+        assert!(partial.holds_whole(None));
+        assert!(partial.holds_whole(Some(id(1))));
+        assert!(partial.holds_whole(Some(id(3))));
+        assert!(!partial.holds_whole(Some(id(5))));
+
+        let shallow = |p: &str| partial.shallow(&[path(p)], false);
+        assert!(shallow("/").is_some());
+        assert!(shallow("Work/Clients").is_some());
+        assert!(shallow("Work/Clients/Acme").is_some());
+        // Nothing's missing from Work, so that's so.
+        assert!(shallow("Work/Nothing").is_some());
+        assert!(shallow("Home").is_none());
+        assert!(shallow("Home/Old").is_none());
+        assert!(shallow("trash:").is_none());
+        assert!(partial.shallow(&[CloudPath::Id(id(9))], false).is_none());
+
+        // Listing Clients needs only it, what's in it, and Work.
+        let kept = shallow("Work/Clients").unwrap();
+        let mut ids: Vec<u128> = kept.iter().map(|d| d.id.as_u128()).collect();
+        ids.sort();
+        assert_eq!(ids, [1, 3, 4]);
+    }
+
+    // A listing can put documents in a document, and `ls` of it lists
+    // them, so they're only answered for if they're all there.
+    #[test]
+    fn document_with_children() {
+        let full = listing(&[
+            (1, "Work", None, "CollectionType"),
+            (2, "Plans", Some(1), "DocumentType"),
+            (3, "Draft", Some(2), "DocumentType"),
+            (4, "Home", None, "CollectionType"),
+            (5, "Recipes", None, "DocumentType"),
+            (6, "Cakes", Some(5), "DocumentType"),
+        ]);
+        let partial = PartialListing::of(&full, &[id(1)]);
+        let tree = ResolvedTree::with_normalized(full.clone(), false);
+        let options = ListOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        for p in &[CloudPath::Id(id(2)), path("Work/Plans")] {
+            let kept = partial.shallow(std::slice::from_ref(p), false).unwrap();
+            let kept = ResolvedTree::with_normalized(kept, false);
+            assert_eq!(
+                render::ls(&kept, p, options),
+                render::ls(&tree, p, options)
+            );
+            assert_eq!(render::ls(&kept, p, options).len(), 1);
+        }
+        // Recipes is beside Work, but what's in it wasn't kept.
+        assert!(partial.shallow(&[path("Recipes")], false).is_none());
+        assert!(partial.shallow(&[CloudPath::Id(id(5))], false).is_none());
+    }
+
+    #[test]
+    fn merged() {
+        let full = account();
+        let partial = PartialListing::of(&full, &[id(3)]);
+        let folders: Vec<Uuid> =
+            partial.covered().iter().map(|c| c.id).collect();
+        let both =
+            PartialListing::of(&full, &[&folders[..], &[id(8)]].concat());
+        assert_eq!(covered(&both), ["Work/Clients", "Home/Old"]);
+        assert!(both.shallow(&[path("Home/Old")], false).is_some());
+        // Home itself is above Old, so it's whole, but not what's in Home.
+        assert!(both.shallow(&[path("Home")], false).is_some());
+
+        // Warming a folder around one already warmed covers it.
+        let around = PartialListing::of(&full, &[id(3), id(8), id(1)]);
+        assert_eq!(covered(&around), ["Home/Old", "Work"]);
+        // As does warming one inside it.
+        let inside = PartialListing::of(&full, &[id(1), id(3)]);
+        assert_eq!(covered(&inside), ["Work"]);
+
+        // A folder since deleted, or a document, isn't covered.
+        let mut later = full.clone();
+        later.remove(&id(8));
+        let gone = PartialListing::of(&later, &[id(3), id(8), id(7)]);
+        assert_eq!(covered(&gone), ["Work/Clients"]);
+    }
+
+    #[test]
+    fn saved() {
+        let partial = PartialListing::of(&account(), &[id(3), id(8)]);
+        let json = serde_json::to_vec(&partial).unwrap();
+        let loaded: PartialListing = serde_json::from_slice(&json).unwrap();
+        assert_eq!(loaded, partial);
+        // Neither is read as the other.
+        assert!(serde_json::from_slice::<Documents>(&json).is_err());
+        let whole = serde_json::to_vec(&account()).unwrap();
+        assert!(serde_json::from_slice::<PartialListing>(&whole).is_err());
+    }
+
+    // A random number, by xorshift64.
+    fn next(seed: &mut u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed
+    }
+
+    fn pick<T: Copy>(seed: &mut u64, items: &[T]) -> T {
+        items[next(seed) as usize % items.len()]
+    }
+
+    // Names which collide, differ only in case, or have a `/` in them, so
+    // that lookups meet every way a path can be ambiguous.
+    const NAMES: [&str; 6] = ["a", "A", "b", "a/b", "b/a", "c"];
+
+    // A listing of `size` documents, each in a folder listed before it or
+    // at the root.
+    fn random_listing(seed: &mut u64, size: u128) -> Documents {
+        let mut folders = vec![None];
+        let mut entries = vec![];
+        for n in 1..=size {
+            let parent = pick(seed, &folders);
+            let folder = next(seed) & 1 == 0;
+            if folder {
+                folders.push(Some(n));
+            }
+            let doc_type = if folder {
+                "CollectionType"
+            } else {
+                "DocumentType"
+            };
+            entries.push((n, pick(seed, &NAMES), parent, doc_type));
+        }
+        listing(&entries)
+    }
+
+    // Moves, renames, removes or adds a document, as happens between one
+    // warming and the next. Removing a folder removes what's in it, as the
+    // cloud does.
+    fn change(seed: &mut u64, full: &mut Documents, size: u128) {
+        let n = next(seed) as u128 % (size + 5) + 1;
+        // In id order, so the same seed makes the same changes every run.
+        let mut folders: Vec<Option<Uuid>> = full
+            .iter()
+            .filter(|d| d.is_folder())
+            .map(|d| Some(d.id))
+            .collect();
+        folders.sort();
+        folders.insert(0, None);
+        match full.get(&id(n)).cloned() {
+            Some(mut d) => match next(seed) % 3 {
+                0 => {
+                    let parent = pick(seed, &folders);
+                    // Not into itself, nor anywhere that can't be told.
+                    let inside = parent.is_some_and(|p| {
+                        p == d.id
+                            || full.is_ancestor_of(&d.id, &p).unwrap_or(true)
+                    });
+                    if !inside {
+                        d.parent = parent;
+                        full.insert(d);
+                    }
+                }
+                1 => {
+                    d.visible_name = pick(seed, &NAMES).into();
+                    full.insert(d);
+                }
+                _ => {
+                    let inside: Vec<Uuid> = full
+                        .descendants(Parent::Folder(d.id))
+                        .map(|(_, d)| d.id)
+                        .collect();
+                    for id in inside.iter().chain(std::iter::once(&d.id)) {
+                        full.remove(id);
+                    }
+                }
+            },
+            None => {
+                let name = pick(seed, &NAMES);
+                let parent = pick(seed, &folders).map(|p| p.as_u128());
+                let doc_type = pick(seed, &["CollectionType", "DocumentType"]);
+                let added = listing(&[(n, name, parent, doc_type)]);
+                full.insert(added.get(&id(n)).unwrap().clone());
+            }
+        }
+    }
+
+    // A path which might find something: one of a document's, taken apart
+    // at every `/`, or written in another case; made up of names; or an id.
+    fn random_path(seed: &mut u64, full: &Documents, size: u128) -> CloudPath {
+        let n = next(seed) as u128 % (size + 5) + 1;
+        match (next(seed) % 4, full.ancestors(&id(n))) {
+            (0, _) => CloudPath::Id(id(n)),
+            (1, _) => {
+                let len = next(seed) as usize % 3;
+                let names = (0..len).map(|_| pick(seed, &NAMES).into());
+                CloudPath::Tree(names.collect())
+            }
+            (_, Ok(ancestors)) => {
+                let d = full.get(&id(n)).unwrap();
+                let mut names = vec![];
+                for d in ancestors.iter().rev().chain(std::iter::once(&d)) {
+                    match next(seed) % 3 {
+                        0 => names.push(d.visible_name.to_uppercase()),
+                        1 => names.extend(
+                            d.visible_name.split('/').map(String::from),
+                        ),
+                        _ => names.push(d.visible_name.to_string()),
+                    }
+                }
+                CloudPath::Tree(names)
+            }
+            (_, Err(_)) => CloudPath::root(),
+        }
+    }
+
+    // A partial listing never answers a lookup other than as the whole one
+    // would: whatever it lists, it lists as the whole listing does, and what
+    // it can't be sure of it refuses. Many random accounts are warmed a few
+    // folders at a time, with changes between, and looked up in at random.
+    #[test]
+    fn never_incomplete() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let size = 40;
+        let (mut answered, mut refused) = (0, 0);
+        for _ in 0..200 {
+            let mut full = random_listing(&mut seed, size);
+            let mut partial = PartialListing::of(&full, &[]);
+            for _ in 0..3 {
+                for _ in 0..next(&mut seed) % 4 {
+                    change(&mut seed, &mut full, size);
+                }
+                let mut folders: Vec<Uuid> =
+                    partial.covered().iter().map(|c| c.id).collect();
+                folders.push(id(next(&mut seed) as u128 % size + 1));
+                partial = PartialListing::of(&full, &folders);
+                // As saved and read back.
+                let json = serde_json::to_vec(&partial).unwrap();
+                partial = serde_json::from_slice(&json).unwrap();
+
+                // What it holds is as the whole listing has it, and what it
+                // says it holds all of, it does.
+                for d in partial.documents().iter() {
+                    assert_eq!(Some(d), full.get(&d.id));
+                }
+                for folder in partial.whole.iter() {
+                    let ids = |docs: &Documents| -> Vec<Uuid> {
+                        let children = docs.get_children(folder);
+                        children.iter().map(|d| d.id).collect()
+                    };
+                    assert_eq!(ids(partial.documents()), ids(&full));
+                }
+                // The folders warmed can always be listed from it.
+                for root in partial.covered() {
+                    let by_id = [CloudPath::Id(root.id)];
+                    assert!(partial.shallow(&by_id, false).is_some());
+                }
+
+                for &normalized in &[false, true] {
+                    let tree =
+                        ResolvedTree::with_normalized(full.clone(), normalized);
+                    for _ in 0..20 {
+                        let path = random_path(&mut seed, &full, size);
+                        let paths = [path.clone()];
+                        let kept = match partial.shallow(&paths, normalized) {
+                            Some(kept) => kept,
+                            None => {
+                                refused += 1;
+                                continue;
+                            }
+                        };
+                        answered += 1;
+                        let kept =
+                            ResolvedTree::with_normalized(kept, normalized);
+                        for &paths in &[false, true] {
+                            let options = ListOptions {
+                                max_depth: Some(1),
+                                paths,
+                                ..Default::default()
+                            };
+                            assert_eq!(
+                                render::ls(&kept, &path, options),
+                                render::ls(&tree, &path, options),
+                                "{:?}",
+                                path
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        // Both ways were taken often enough to mean something.
+        assert!(answered > 2000, "{}", answered);
+        assert!(refused > 2000, "{}", refused);
+    }
+
+    // A whole listing answers everything but the trash.
+    #[test]
+    fn whole() {
+        let full = account();
+        let tree = ResolvedTree::with_normalized(full.clone(), false);
+        for p in &["/", "Work", "Home/Old", "Home/Nothing", "Inbox"] {
+            let kept = shallow(&full, &|_| true, &[path(p)], false).unwrap();
+            let kept = ResolvedTree::with_normalized(kept, false);
+            let options = ListOptions {
+                max_depth: Some(1),
+                ..Default::default()
+            };
+            assert_eq!(
+                render::ls(&kept, &path(p), options),
+                render::ls(&tree, &path(p), options)
+            );
+        }
+        assert!(shallow(&full, &|_| true, &[path("trash:")], false).is_none());
+    }
+}
@@ -0,0 +1,369 @@
+//! Showing the thumbnail of a document's page, as done by `peek`: inline in
+//! the terminal, in the system's image viewer, or saved to a file.
+
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use remarkable_cloud_api::Client;
+
+use crate::commands::Output;
+use crate::resolved::ResolvedTree;
+use crate::{document_at, msg, CliResult};
+
+/// The ways of sending a terminal an image to show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// iTerm2's, which WezTerm also understands.
+    Iterm,
+    Sixel,
+}
+
+pub const PROTOCOL_VALUES: &[&str] = &["iterm", "sixel"];
+
+impl std::str::FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "iterm" => Ok(Protocol::Iterm),
+            "sixel" => Ok(Protocol::Sixel),
+            _ => Err(msg!(PROTOCOL_UNKNOWN, protocol = format!("{:?}", s))),
+        }
+    }
+}
+
+/// Whether this build can show images in the terminal.
+pub const INLINE_BUILT: bool = cfg!(feature = "inline-images");
+
+/// The protocol the terminal understands, as far as the environment
+/// variables `var` looks up say.
+pub fn detect<F>(var: F) -> Option<Protocol>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let program = var("TERM_PROGRAM").unwrap_or_default();
+    if program == "iTerm.app"
+        || program == "WezTerm"
+        || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+    {
+        return Some(Protocol::Iterm);
+    }
+    let term = var("TERM").unwrap_or_default();
+    if term.contains("sixel")
+        || term.starts_with("mlterm")
+        || term.starts_with("foot")
+        || term.starts_with("yaft")
+    {
+        return Some(Protocol::Sixel);
+    }
+    None
+}
+
+/// What `peek` was asked to do with the thumbnail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Request {
+    /// Show it in the terminal, with the protocol given rather than the one
+    /// detected, if any.
+    Inline(Option<Protocol>),
+    Open,
+    Save(PathBuf),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    Inline(Protocol),
+    Open,
+    Save(PathBuf),
+}
+
+/// What to do for `request`. Showing the thumbnail inline needs this build
+/// to be able to, and stdout to be a `terminal` which understands one of
+/// the protocols, unless one was given. Failing that, the thumbnail is
+/// opened in the image viewer instead, with a note saying why.
+pub fn choose(
+    request: Request,
+    built: bool,
+    terminal: bool,
+    detected: Option<Protocol>,
+) -> (Action, Option<String>) {
+    let forced = match request {
+        Request::Inline(forced) => forced,
+        Request::Open => return (Action::Open, None),
+        Request::Save(path) => return (Action::Save(path), None),
+    };
+    let why = if !built {
+        msg!(INLINE_NOT_BUILT)
+    } else if let Some(protocol) = forced {
+        return (Action::Inline(protocol), None);
+    } else if !terminal {
+        msg!(INLINE_NOT_TERMINAL)
+    } else if let Some(protocol) = detected {
+        return (Action::Inline(protocol), None);
+    } else {
+        msg!(INLINE_UNDETECTED)
+    };
+    (Action::Open, Some(why))
+}
+
+/// Does `action` with the thumbnail `jpeg`, drawing it on `screen` inline,
+/// and returning what was done.
+pub fn show(
+    action: &Action,
+    jpeg: &[u8],
+    screen: &mut dyn Write,
+) -> CliResult<String> {
+    match action {
+        Action::Save(path) => {
+            std::fs::write(path, jpeg)?;
+            Ok(msg!(PEEK_SAVED, path = path.display().to_string()))
+        }
+        Action::Open => {
+            let mut file = tempfile::Builder::new()
+                .prefix("remarkable-peek-")
+                .suffix(".jpg")
+                .tempfile()?;
+            file.write_all(jpeg)?;
+            // Kept, as the viewer reads it after this has exited.
+            let (_, path) = file.keep()?;
+            open(&path)?;
+            Ok(msg!(PEEK_OPENED, path = path.display().to_string()))
+        }
+        Action::Inline(protocol) => {
+            screen.write_all(&inline(*protocol, jpeg)?)?;
+            screen.flush()?;
+            Ok(String::new())
+        }
+    }
+}
+
+fn open(path: &Path) -> CliResult<()> {
+    let viewer = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(viewer)
+        .arg(path)
+        .spawn()
+        .map_err(|e| {
+            msg!(
+                PEEK_VIEWER_FAILED,
+                viewer = viewer,
+                error = e.to_string(),
+                path = path.display().to_string(),
+            )
+        })?;
+    Ok(())
+}
+
+#[cfg(not(feature = "inline-images"))]
+fn inline(_: Protocol, _: &[u8]) -> CliResult<Vec<u8>> {
+    Err(msg!(INLINE_NOT_BUILT).into())
+}
+
+#[cfg(feature = "inline-images")]
+fn inline(protocol: Protocol, jpeg: &[u8]) -> CliResult<Vec<u8>> {
+    Ok(match protocol {
+        Protocol::Iterm => format!(
+            "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07\n",
+            jpeg.len(),
+            base64::encode(jpeg)
+        )
+        .into_bytes(),
+        Protocol::Sixel => {
+            let (width, height, luma) = decode(jpeg)?;
+            sixel(width, height, &luma).into_bytes()
+        }
+    })
+}
+
+// The JPEG's width, height and brightness of each pixel.
+#[cfg(feature = "inline-images")]
+fn decode(jpeg: &[u8]) -> CliResult<(usize, usize, Vec<u8>)> {
+    use jpeg_decoder::PixelFormat;
+
+    let mut decoder = jpeg_decoder::Decoder::new(jpeg);
+    let pixels = decoder.decode()?;
+    let info = decoder.info().ok_or_else(|| msg!(THUMBNAIL_NOT_JPEG))?;
+    let luma = match info.pixel_format {
+        PixelFormat::L8 => pixels,
+        PixelFormat::L16 => pixels.chunks(2).map(|p| p[0]).collect(),
+        PixelFormat::RGB24 => pixels
+            .chunks(3)
+            .map(|p| {
+                let (r, g, b) = (p[0] as u32, p[1] as u32, p[2] as u32);
+                ((r * 299 + g * 587 + b * 114) / 1000) as u8
+            })
+            .collect(),
+        PixelFormat::CMYK32 => pixels
+            .chunks(4)
+            .map(|p| {
+                ((255 - p[0].max(p[1]).max(p[2])) as u32 * (255 - p[3]) as u32
+                    / 255) as u8
+            })
+            .collect(),
+    };
+    Ok((info.width as usize, info.height as usize, luma))
+}
+
+// Encodes an image as sixels, in sixteen shades of grey, which is all a
+// thumbnail of a page needs.
+#[cfg(feature = "inline-images")]
+fn sixel(width: usize, height: usize, luma: &[u8]) -> String {
+    const SHADES: usize = 16;
+    let shade = |x: usize, y: usize| {
+        (luma[y * width + x] as usize * (SHADES - 1) + 127) / 255
+    };
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for i in 0..SHADES {
+        let percent = i * 100 / (SHADES - 1);
+        out += &format!("#{};2;{};{};{}", i, percent, percent, percent);
+    }
+    for top in (0..height).step_by(6) {
+        let rows = (height - top).min(6);
+        let mut first = true;
+        for s in 0..SHADES {
+            let bits: Vec<u8> = (0..width)
+                .map(|x| {
+                    (0..rows)
+                        .filter(|r| shade(x, top + r) == s)
+                        .fold(0, |bits, r| bits | 1 << r)
+                })
+                .collect();
+            if bits.iter().all(|b| *b == 0) {
+                continue;
+            }
+            if !first {
+                out.push('$');
+            }
+            first = false;
+            out += &format!("#{}", s);
+            let mut x = 0;
+            while x < bits.len() {
+                let run =
+                    bits[x..].iter().take_while(|b| **b == bits[x]).count();
+                let c = (63 + bits[x]) as char;
+                if run > 3 {
+                    out += &format!("!{}{}", run, c);
+                } else {
+                    out.extend(std::iter::repeat_n(c, run));
+                }
+                x += run;
+            }
+        }
+        out.push('-');
+    }
+    out + "\x1b\\"
+}
+
+/// Shows the thumbnail of the page the document at `path` is open at, as
+/// `request` asks if this terminal and build can, inline on `screen`.
+/// What was done is reported to `out`.
+pub async fn peek(
+    client: &Client,
+    documents: &ResolvedTree,
+    path: &str,
+    request: Request,
+    screen: &mut dyn Write,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let doc = document_at(documents, &path.parse()?)?;
+    let (index, jpeg) = client
+        .thumbnail(doc, None)
+        .await?
+        .ok_or_else(|| msg!(NO_THUMBNAILS, path = format!("{:?}", path)))?;
+    let (action, why) = choose(
+        request,
+        INLINE_BUILT,
+        std::io::stdout().is_terminal(),
+        detect(|v| std::env::var(v).ok()),
+    );
+    if let Some(why) = why {
+        out.warn(&msg!(PEEK_OPENING_INSTEAD, why = why));
+    }
+    let done = show(&action, &jpeg, screen)?;
+    if !done.is_empty() {
+        out.note(&msg!(PEEK_PAGE, page = index + 1, done = done));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn detected(vars: &[(&str, &str)]) -> Option<Protocol> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        detect(|v| vars.get(v).map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn terminals() {
+        assert_eq!(detected(&[]), None);
+        assert_eq!(detected(&[("TERM", "xterm-256color")]), None);
+        assert_eq!(
+            detected(&[("TERM_PROGRAM", "iTerm.app")]),
+            Some(Protocol::Iterm)
+        );
+        // As seen over ssh from iTerm2.
+        assert_eq!(
+            detected(&[("TERM", "xterm"), ("LC_TERMINAL", "iTerm2")]),
+            Some(Protocol::Iterm)
+        );
+        assert_eq!(detected(&[("TERM", "foot-extra")]), Some(Protocol::Sixel));
+    }
+
+    #[test]
+    fn fallbacks() {
+        use Action::*;
+
+        let sixel = Some(Protocol::Sixel);
+        let iterm = Some(Protocol::Iterm);
+        let inline = Request::Inline(None);
+        assert_eq!(
+            choose(inline.clone(), true, true, sixel),
+            (Inline(Protocol::Sixel), None)
+        );
+        for (built, terminal, detected) in &[
+            (false, true, sixel),
+            (true, false, sixel),
+            (true, true, None),
+        ] {
+            let (action, why) =
+                choose(inline.clone(), *built, *terminal, *detected);
+            assert_eq!(action, Open);
+            assert!(why.is_some());
+        }
+        // A protocol given is used whatever the terminal looks like, as
+        // long as the build can.
+        let forced = Request::Inline(iterm);
+        assert_eq!(
+            choose(forced.clone(), true, false, None),
+            (Inline(Protocol::Iterm), None)
+        );
+        assert_eq!(choose(forced, false, true, iterm).0, Open);
+        let path = PathBuf::from("page.jpg");
+        assert_eq!(
+            choose(Request::Save(path.clone()), true, true, iterm),
+            (Save(path), None)
+        );
+        assert_eq!(choose(Request::Open, true, true, iterm), (Open, None));
+    }
+
+    #[cfg(feature = "inline-images")]
+    #[test]
+    fn sixels() {
+        // Two columns, black then white, seven rows: a band of six and a
+        // band of one.
+        let luma: Vec<u8> =
+            (0..14).map(|i| if i % 2 == 0 { 0 } else { 255 }).collect();
+        let out = sixel(2, 7, &luma);
+        assert!(out.starts_with("\x1bPq\"1;1;2;7#0;2;0;0;0"));
+        assert!(out.ends_with("#0@?$#15?@-\x1b\\"), "{:?}", out);
+        assert!(out.contains("#0~?$#15?~-"), "{:?}", out);
+    }
+}
@@ -0,0 +1,130 @@
+//! Plans saved while a long command works, so that one interrupted can be
+//! carried on with `--resume-plan` rather than planned again.
+//!
+//! A plan is the list of what a command worked out it has to do, saved as
+//! JSON along with [`PLAN_VERSION`]. The command saves it again as it gets
+//! through it, and removes it once it's done. One saved with another
+//! version isn't resumed, as its items needn't mean the same.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use remarkable_cloud_api::write_atomically;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::Output;
+use crate::{msg, CliResult};
+
+/// The version of the plan format written, and the only one resumed.
+pub const PLAN_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Saved<'a, T> {
+    plan_version: u32,
+    items: &'a [T],
+}
+
+#[derive(Deserialize)]
+struct Version {
+    #[serde(default)]
+    plan_version: u32,
+}
+
+#[derive(Deserialize)]
+struct Loaded<T> {
+    items: Vec<T>,
+}
+
+/// The items of the plan saved at `path`, none if there isn't one. Fails
+/// if it was saved with another version.
+pub fn load<T: DeserializeOwned>(path: &Path) -> CliResult<Option<Vec<T>>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let version: Version = serde_json::from_slice(&data)?;
+    if version.plan_version != PLAN_VERSION {
+        return Err(msg!(
+            PLAN_OTHER_VERSION,
+            path = path.display().to_string(),
+            found = version.plan_version,
+            expected = PLAN_VERSION,
+        )
+        .into());
+    }
+    let loaded: Loaded<T> = serde_json::from_slice(&data)?;
+    Ok(Some(loaded.items))
+}
+
+/// The items of the plan saved at `path`, to be resumed, as `load` gives
+/// them. If there isn't one, `out` is told it's planned afresh.
+pub fn resume<T: DeserializeOwned>(
+    path: &Path,
+    out: &mut dyn Output,
+) -> CliResult<Option<Vec<T>>> {
+    let saved = load(path)?;
+    if saved.is_none() {
+        out.note(&msg!(PLAN_NONE, path = path.display().to_string()));
+    }
+    Ok(saved)
+}
+
+/// Saves `items` as the plan at `path`, replacing any there.
+pub fn save<T: Serialize>(path: &Path, items: &[T]) -> CliResult<()> {
+    let saved = Saved {
+        plan_version: PLAN_VERSION,
+        items,
+    };
+    write_atomically(path, &serde_json::to_vec_pretty(&saved)?)?;
+    Ok(())
+}
+
+/// Removes the plan at `path`, once everything in it is done.
+pub fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        assert_eq!(load::<String>(&path).unwrap(), None);
+
+        save(&path, &["a".to_string(), "b".to_string()]).unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved["plan_version"], PLAN_VERSION);
+        assert_eq!(
+            load::<String>(&path).unwrap(),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+
+        remove(&path).unwrap();
+        remove(&path).unwrap();
+        assert_eq!(load::<String>(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn other_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        // Plans from before there was a version are of none.
+        for saved in
+            &[r#"{"plan_version": 2, "items": []}"#, r#"{"items": []}"#]
+        {
+            fs::write(&path, saved).unwrap();
+            let e = load::<String>(&path).unwrap_err().to_string();
+            assert!(e.contains("can't resume"), "{}", e);
+        }
+    }
+}
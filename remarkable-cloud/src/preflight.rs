@@ -0,0 +1,411 @@
+//! Checks on a command line made as soon as it's parsed, before anything is
+//! read from the cloud or written locally: flags which can't go together,
+//! and values such as folders and output files which can't work. Every
+//! problem is reported at once, each with what to do instead.
+//!
+//! The checks are the rows of [`RULES`]. A command with rules has all its
+//! flags declared, either in a rule or in [`INDEPENDENT`], so that a flag
+//! added later has to be thought about here too.
+
+use std::fmt;
+use std::path::Path;
+
+use remarkable_cloud_api::CloudPath;
+
+use crate::messages::{self, Message};
+use crate::{msg, push};
+
+/// What breaks a rule.
+pub enum Check {
+    /// The flags can't all be given at once, for the reason given, which
+    /// says which to drop.
+    Together(&'static Message),
+    /// Each value given for the flag must be fine, or this says why not.
+    Value(fn(&clap::ArgMatches, &str) -> Option<String>),
+}
+
+pub struct Rule {
+    /// The command, and its subcommand if it has one, as in "export feed".
+    pub command: &'static str,
+    /// The flags the rule is about, as written on the command line, or
+    /// `<name>` for an argument without a flag.
+    pub flags: &'static [&'static str],
+    pub check: Check,
+    /// Arguments breaking the rule, after the command, for the tests.
+    pub example: &'static [&'static str],
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        command: "pull",
+        flags: &["--raw-zip", "--format"],
+        check: Check::Together(&messages::PREFLIGHT_RAW_ZIP_FORMAT),
+        example: &["--raw-zip", "--format", "ink-svg", "Notes"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--to"],
+        check: Check::Value(folder),
+        example: &["--to", "trash:/", "a.pdf"],
+    },
+    Rule {
+        command: "push",
+        flags: &["<files>"],
+        check: Check::Value(pushable),
+        example: &["/nonexistent/a.pdf"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--name"],
+        check: Check::Value(pushable_name),
+        example: &["--stdin", "--name", "Paper.txt"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--to"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_TO),
+        example: &["--resume", "--to", "Books"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--on-conflict"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_ON_CONFLICT),
+        example: &["--resume", "--on-conflict", "skip"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--recursive"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_RECURSIVE),
+        example: &["--resume", "--recursive"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--create-missing"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_CREATE_MISSING),
+        example: &["--resume", "--create-missing"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--queue", "--create-missing"],
+        check: Check::Together(&messages::PREFLIGHT_QUEUE_CREATE_MISSING),
+        example: &["--queue", "--create-missing", "a.pdf"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--strict"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_STRICT),
+        example: &["--resume", "--strict"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--queue", "--strict"],
+        check: Check::Together(&messages::PREFLIGHT_QUEUE_STRICT),
+        example: &["--queue", "--strict", "a.pdf"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--stdin", "--recursive"],
+        check: Check::Together(&messages::PREFLIGHT_STDIN_RECURSIVE),
+        example: &["--stdin", "--name", "a.pdf", "--recursive"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--optimize"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_OPTIMIZE),
+        example: &["--resume", "--optimize"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--queue", "--optimize"],
+        check: Check::Together(&messages::PREFLIGHT_QUEUE_OPTIMIZE),
+        example: &["--queue", "--optimize", "a.pdf"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--stdin", "--optimize"],
+        check: Check::Together(&messages::PREFLIGHT_STDIN_OPTIMIZE),
+        example: &["--stdin", "--name", "a.pdf", "--optimize"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--resume", "--verify"],
+        check: Check::Together(&messages::PREFLIGHT_RESUME_VERIFY),
+        example: &["--resume", "--verify"],
+    },
+    Rule {
+        command: "push",
+        flags: &["--queue", "--verify"],
+        check: Check::Together(&messages::PREFLIGHT_QUEUE_VERIFY),
+        example: &["--queue", "--verify", "a.pdf"],
+    },
+    Rule {
+        command: "peek",
+        flags: &["--output"],
+        check: Check::Value(output_file),
+        example: &["--output", "/", "Notes"],
+    },
+    Rule {
+        command: "export feed",
+        flags: &["--output"],
+        check: Check::Value(output_file),
+        example: &["--output", "/nonexistent/feed.xml"],
+    },
+    Rule {
+        command: "export feed",
+        flags: &["--since"],
+        check: Check::Value(output_file),
+        example: &["--since", "/"],
+    },
+    Rule {
+        command: "backup",
+        flags: &["--output"],
+        check: Check::Value(output_file),
+        example: &["--output", "/nonexistent/backup.tar.zst"],
+    },
+    Rule {
+        command: "restore",
+        flags: &["--into"],
+        check: Check::Value(folder),
+        example: &["--into", "uuid:nope", "backup.tar.zst"],
+    },
+    Rule {
+        command: "restore",
+        flags: &["<archive>"],
+        check: Check::Value(existing_file),
+        example: &["/nonexistent/backup.tar.zst"],
+    },
+    Rule {
+        command: "restore",
+        flags: &["--pick"],
+        check: Check::Value(existing_dir),
+        example: &["--pick", "/nonexistent"],
+    },
+    Rule {
+        command: "restore",
+        flags: &["--pick", "--keep-ids"],
+        check: Check::Together(&messages::PREFLIGHT_PICK_KEEP_IDS),
+        example: &["--pick", "/", "--keep-ids"],
+    },
+    Rule {
+        command: "restore",
+        flags: &["--version", "--date"],
+        check: Check::Together(&messages::PREFLIGHT_VERSION_DATE),
+        example: &["--pick", "/", "--version", "3", "--date", "2024-01-31"],
+    },
+];
+
+/// The flags of each command with rules which no rule is about, leaving
+/// out those every command takes.
+pub const INDEPENDENT: &[(&str, &[&str])] = &[
+    (
+        "pull",
+        &[
+            "--name-template",
+            "--id",
+            "--all-matches",
+            "--recursive",
+            "--preserve-times",
+            "--no-preserve-times",
+            "--tag",
+            "--bookmarked",
+            "--output",
+        ],
+    ),
+    (
+        "push",
+        &["--max-memory", "--downsample-dpi", "--verify-max-size"],
+    ),
+    ("peek", &["--open", "--inline", "--inline-protocol"]),
+    ("export feed", &[]),
+    ("backup", &["--resume", "--resume-plan", "--reproducible"]),
+    ("restore", &["--keep-ids", "--strict"]),
+];
+
+/// Everything wrong with a command line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problems(pub Vec<String>);
+
+impl fmt::Display for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0[..] {
+            [problem] => write!(f, "{}", problem),
+            problems => {
+                write!(f, "{}", msg!(PROBLEMS, count = problems.len()))?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Problems {}
+
+/// Checks `matches` against the rules for the command it's for.
+pub fn check(matches: &clap::ArgMatches) -> Result<(), Problems> {
+    let (command, matches) = command(matches);
+    let mut problems = vec![];
+    for rule in RULES.iter().filter(|r| r.command == command) {
+        problems.extend(broken(rule, matches));
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Problems(problems))
+    }
+}
+
+/// The command and subcommand `matches` are for, as `Rule::command` has
+/// them, and the arguments given to the last of them.
+pub fn command<'a>(
+    matches: &'a clap::ArgMatches<'a>,
+) -> (String, &'a clap::ArgMatches<'a>) {
+    let mut words = vec![];
+    let mut matches = matches;
+    while let (name, Some(sub)) = matches.subcommand() {
+        words.push(name);
+        matches = sub;
+    }
+    (words.join(" "), matches)
+}
+
+// How `matches` break `rule`, if they do.
+fn broken(rule: &Rule, matches: &clap::ArgMatches) -> Vec<String> {
+    let name = |flag: &str| {
+        flag.trim_start_matches("--")
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .to_string()
+    };
+    match rule.check {
+        Check::Together(why) => {
+            if rule.flags.iter().all(|f| matches.is_present(name(f))) {
+                vec![msg!(
+                    FLAGS_TOGETHER,
+                    flags = rule.flags.join(" and "),
+                    why = messages::render(why, &[]),
+                )]
+            } else {
+                vec![]
+            }
+        }
+        Check::Value(check) => {
+            let flag = rule.flags[0];
+            let values = matches.values_of(name(flag)).into_iter().flatten();
+            values
+                .filter_map(|value| {
+                    let problem = check(matches, value)?;
+                    Some(if flag.starts_with("--") {
+                        format!("{} {:?}: {}", flag, value, problem)
+                    } else {
+                        format!("{:?}: {}", value, problem)
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+// A folder to put documents in.
+fn folder(_: &clap::ArgMatches, value: &str) -> Option<String> {
+    match value.parse::<CloudPath>() {
+        Err(e) => Some(e.to_string()),
+        Ok(CloudPath::Trash(_)) => Some(msg!(PREFLIGHT_TRASH)),
+        Ok(_) => None,
+    }
+}
+
+// A local file or directory for push to upload.
+fn pushable(matches: &clap::ArgMatches, value: &str) -> Option<String> {
+    let path = Path::new(value);
+    if !path.exists() {
+        Some(msg!(PREFLIGHT_NO_SUCH_FILE))
+    } else if path.is_dir() {
+        if matches.is_present("recursive") {
+            None
+        } else {
+            Some(msg!(PREFLIGHT_PUSH_DIRECTORY))
+        }
+    } else {
+        pushable_name(matches, value)
+    }
+}
+
+// The name of a file push can upload.
+fn pushable_name(_: &clap::ArgMatches, value: &str) -> Option<String> {
+    match push::file_type(Path::new(value)) {
+        Some(_) => None,
+        None => Some(msg!(PREFLIGHT_NOT_PUSHABLE)),
+    }
+}
+
+// A file to be written, which may already exist.
+fn output_file(_: &clap::ArgMatches, value: &str) -> Option<String> {
+    let path = Path::new(value);
+    if path.is_dir() {
+        return Some(msg!(PREFLIGHT_OUTPUT_DIRECTORY));
+    }
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+            let dir = dir.display().to_string();
+            Some(msg!(PREFLIGHT_NO_SUCH_PARENT, dir = dir))
+        }
+        _ => None,
+    }
+}
+
+// A file to be read.
+fn existing_file(_: &clap::ArgMatches, value: &str) -> Option<String> {
+    let path = Path::new(value);
+    if path.is_file() {
+        None
+    } else if path.exists() {
+        Some(msg!(PREFLIGHT_NOT_A_FILE))
+    } else {
+        Some(msg!(PREFLIGHT_NO_SUCH_FILE))
+    }
+}
+
+// A directory to be read.
+fn existing_dir(_: &clap::ArgMatches, value: &str) -> Option<String> {
+    let path = Path::new(value);
+    if path.is_dir() {
+        None
+    } else if path.exists() {
+        Some(msg!(PREFLIGHT_NOT_A_DIRECTORY))
+    } else {
+        Some(msg!(PREFLIGHT_NO_SUCH_DIRECTORY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problems_display() {
+        let one = Problems(vec!["a".into()]);
+        assert_eq!(one.to_string(), "a");
+        let two = Problems(vec!["a".into(), "b".into()]);
+        assert_eq!(
+            two.to_string(),
+            "2 problems with the command line:\n  a\n  b"
+        );
+    }
+
+    #[test]
+    fn output_files() {
+        let m = clap::ArgMatches::default();
+        assert!(output_file(&m, "feed.xml").is_none());
+        assert!(output_file(&m, "/").is_some());
+        assert!(output_file(&m, "/nonexistent/feed.xml").is_some());
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("feed.xml");
+        assert!(output_file(&m, file.to_str().unwrap()).is_none());
+        std::fs::write(&file, b"").unwrap();
+        assert!(output_file(&m, file.to_str().unwrap()).is_none());
+        let inside = file.join("x");
+        assert!(output_file(&m, inside.to_str().unwrap()).is_some());
+    }
+}
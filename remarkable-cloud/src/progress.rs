@@ -0,0 +1,120 @@
+//! A running count on stderr for commands which download many documents to
+//! look inside them, and the phases of those which transfer many. Nothing is
+//! shown unless stderr is a terminal, or with --quiet.
+
+use std::io::{self, IsTerminal, Write};
+
+use crate::commands::Output;
+use crate::msg;
+use crate::observer::{Event, Observer, Phase};
+
+pub struct Progress {
+    label: String,
+    done: usize,
+    total: usize,
+    shown: bool,
+}
+
+impl Progress {
+    pub fn new(label: String, total: usize) -> Self {
+        Progress {
+            label,
+            done: 0,
+            total,
+            shown: io::stderr().is_terminal() && crate::quiet_level() == 0,
+        }
+    }
+
+    /// Counts one more document as done.
+    pub fn tick(&mut self) {
+        self.set(self.done + 1);
+    }
+
+    /// Counts `done` as done, for counts of something other than
+    /// documents.
+    pub fn set(&mut self, done: usize) {
+        self.done = done;
+        if self.shown {
+            eprint!("\r{} {}/{}", self.label, self.done, self.total);
+            io::stderr().flush().ok();
+        }
+    }
+
+    /// Passes `message` on to `out` as a warning, clear of the count.
+    pub fn warn(&self, out: &mut dyn Output, message: &str) {
+        self.clear();
+        out.warn(message);
+    }
+
+    /// Removes the count, so that output can go to a terminal shared with
+    /// stderr. The count is back at the next tick.
+    pub fn clear(&self) {
+        if self.shown {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Shows on stderr which phase a long command has reached.
+pub struct PhaseDisplay;
+
+impl Observer for PhaseDisplay {
+    fn observe(&mut self, event: &Event) {
+        if let Event::Phase(phase) = event {
+            if io::stderr().is_terminal() && crate::quiet_level() == 0 {
+                eprintln!("{}", describe(*phase));
+            }
+        }
+    }
+}
+
+fn describe(phase: Phase) -> String {
+    match phase {
+        Phase::Listing => msg!(PHASE_LISTING),
+        Phase::Planning { compared } => {
+            msg!(PHASE_PLANNING, compared = group_digits(compared))
+        }
+        Phase::Resuming { checked } => {
+            msg!(PHASE_RESUMING, checked = group_digits(checked))
+        }
+        Phase::Transferring { documents } => {
+            msg!(PHASE_TRANSFERRING, documents = group_digits(documents))
+        }
+    }
+}
+
+/// `n` with its digits in groups of three, as in 1,243.
+pub fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(1243), "1,243");
+        assert_eq!(group_digits(1_000_000), "1,000,000");
+        assert_eq!(
+            describe(Phase::Planning { compared: 1243 }),
+            "Planning (1,243 documents compared)\u{2026}"
+        );
+    }
+}
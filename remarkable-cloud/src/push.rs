@@ -0,0 +1,1207 @@
+//! Uploading local PDFs and EPUBs, as done by `push`.
+//!
+//! Every upload is recorded in a journal before anything is sent, and the
+//! record is rewritten after each stage, so that `push --resume` can pick up
+//! an upload interrupted by a crash or a lost connection. The upload is
+//! finished if the file it came from is unchanged, and otherwise rolled back
+//! by dropping the reservation, which leaves nothing visible behind.
+//! Uploads read from stdin can only be finished once their blob is stored,
+//! as there's nothing to read them from again.
+//!
+//! Archives of large files are built in a temporary file rather than in
+//! memory, and sent from there a piece at a time. [`MemoryBudget`] says how
+//! large is large, and how many archives are built and sent at once.
+//!
+//! With `push --verify`, each finished upload is downloaded again and the
+//! file in it hashed, as it arrives, to check the cloud stored what was
+//! sent; see [`verify`].
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use remarkable_cloud_api::{
+    lock_path, tidy_name, write_atomically, BlobSource, Client, Conflict,
+    DocType, Document, DocumentArchiveBuilder, Documents, Error, Upload,
+    UploadStage,
+};
+use remarkable_data_formats::content::Content;
+use remarkable_data_formats::pagedata::PageData;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::backup;
+use crate::progress::Progress;
+use crate::{msg, scan, CliResult, PUSH_CONCURRENCY};
+
+/// Input read from stdin is kept in memory up to this size, and spooled to
+/// a temporary file beyond it.
+const STDIN_MEMORY_LIMIT: usize = 8 * 1024 * 1024;
+
+/// How many pieces of a download being verified are held while they wait
+/// to be hashed.
+const VERIFY_BUFFERED_CHUNKS: usize = 4;
+
+/// Archives of files up to this size are built in memory, and of larger
+/// ones in a temporary file, unless a `MemoryBudget` calls for less.
+pub const ARCHIVE_MEMORY_LIMIT: u64 = 8 * 1024 * 1024;
+
+// What an archive being built and sent takes besides what's in it: the
+// buffers it's compressed, read and sent through.
+const ARCHIVE_OVERHEAD: u64 = 1024 * 1024;
+
+/// How much memory pushing may take for archives, as `--max-memory` sets
+/// it: how many are built and sent at once, and how large a file may be
+/// before its archive is built in a temporary file instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryBudget {
+    pub concurrency: usize,
+    pub spill_above: u64,
+}
+
+impl MemoryBudget {
+    /// A budget of `max` bytes, or the defaults with no limit. However
+    /// little is given, one archive is still sent at a time.
+    pub fn new(max: Option<u64>) -> Self {
+        let max = match max {
+            Some(max) => max,
+            None => {
+                return MemoryBudget {
+                    concurrency: PUSH_CONCURRENCY,
+                    spill_above: ARCHIVE_MEMORY_LIMIT,
+                }
+            }
+        };
+        // An archive built in memory is held up to three times over: the
+        // file read in, the archive, and the copy being sent.
+        let per_archive = 3 * ARCHIVE_MEMORY_LIMIT + ARCHIVE_OVERHEAD;
+        let concurrency =
+            (max / per_archive).clamp(1, PUSH_CONCURRENCY as u64) as usize;
+        let share = max / concurrency as u64;
+        MemoryBudget {
+            concurrency,
+            spill_above: (share.saturating_sub(ARCHIVE_OVERHEAD) / 3)
+                .min(ARCHIVE_MEMORY_LIMIT),
+        }
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        MemoryBudget::new(None)
+    }
+}
+
+/// A document's archive, ready to upload.
+pub enum Packaged {
+    Memory(Vec<u8>),
+    /// Built in a temporary file, which goes when this does.
+    Spilled {
+        file: tempfile::NamedTempFile,
+        size: u64,
+    },
+}
+
+impl Packaged {
+    pub fn blob(&self) -> BlobSource<'_> {
+        match self {
+            Packaged::Memory(zip) => BlobSource::Memory(zip),
+            Packaged::Spilled { file, size } => BlobSource::File {
+                path: file.path(),
+                size: *size,
+            },
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub upload: Upload,
+    /// The file being uploaded, or `None` if it was read from stdin or
+    /// optimized before it was uploaded.
+    pub source: Option<PathBuf>,
+    /// The SHA-256 of the source when the upload started, in hex.
+    pub sha256: String,
+}
+
+/// What to do when pushing a document whose name is already taken by one
+/// in the same folder.
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OnConflict {
+    /// Leave the existing document alone and upload nothing.
+    Skip,
+    /// Upload as a new version of the existing document.
+    Update,
+    /// Upload as a new document with a numbered name, as in "report (2)".
+    Rename,
+    /// Upload as a new document of the same name anyway.
+    Duplicate,
+}
+
+/// The choices `OnConflict` parses from.
+pub const ON_CONFLICT_VALUES: &[&str] =
+    &["skip", "update", "rename", "duplicate"];
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "skip" => Ok(OnConflict::Skip),
+            "update" => Ok(OnConflict::Update),
+            "rename" => Ok(OnConflict::Rename),
+            "duplicate" => Ok(OnConflict::Duplicate),
+            _ => Err(msg!(
+                VALUE_NOT_ONE_OF,
+                value = format!("{:?}", s),
+                values = ON_CONFLICT_VALUES.join(", "),
+            )),
+        }
+    }
+}
+
+/// Where a pushed document goes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A new document in `parent`, or the root, named `name` or else after
+    /// the file.
+    New {
+        parent: Option<Uuid>,
+        name: Option<String>,
+    },
+    /// A new version of an existing document, which keeps its name and
+    /// place.
+    Update(Document),
+}
+
+impl Target {
+    /// A new document in `parent`, named after the file.
+    pub fn new_in(parent: Option<Uuid>) -> Self {
+        Target::New { parent, name: None }
+    }
+}
+
+/// Asks on `input` what to do about pushing `name` over `conflict`. Anything
+/// but one of the choices, or its first letter, means skip.
+pub fn ask(
+    name: &str,
+    conflict: &Conflict,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<OnConflict> {
+    let existing = &conflict.existing;
+    let modified = existing.modified_client.format("%Y-%m-%d %H:%M");
+    writeln!(
+        output,
+        "{}",
+        msg!(
+            PUSH_ASK_EXISTING,
+            name = format!("{:?}", existing.visible_name),
+            version = existing.version,
+            modified = modified.to_string(),
+        )
+    )?;
+    let question = msg!(
+        PUSH_ASK,
+        name = name,
+        free = format!("{:?}", conflict.free_name),
+    );
+    write!(output, "{} ", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(ON_CONFLICT_VALUES
+        .iter()
+        .find(|v| !answer.is_empty() && v.starts_with(answer.as_str()))
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(OnConflict::Skip))
+}
+
+/// Works out where pushing `name` into `parent` should put it, or `None` to
+/// skip it. A name taken by a document already in the folder is dealt with
+/// as `on_conflict` says if given, and otherwise as `ask` answers.
+pub fn target(
+    documents: &Documents,
+    parent: Option<Uuid>,
+    name: &str,
+    on_conflict: Option<OnConflict>,
+    ask: &mut dyn FnMut(&Conflict) -> io::Result<OnConflict>,
+) -> CliResult<Option<Target>> {
+    let conflict = match documents.conflict_for(parent.into(), &stem(name)?) {
+        Some(conflict) => conflict,
+        None => return Ok(Some(Target::new_in(parent))),
+    };
+    let choice = match on_conflict {
+        Some(choice) => choice,
+        None => ask(&conflict)?,
+    };
+    Ok(match choice {
+        OnConflict::Skip => None,
+        OnConflict::Update => Some(Target::Update(conflict.existing.clone())),
+        OnConflict::Rename => Some(Target::New {
+            parent,
+            name: Some(conflict.free_name),
+        }),
+        OnConflict::Duplicate => Some(Target::new_in(parent)),
+    })
+}
+
+/// A directory holding one file per unfinished upload.
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf) -> Self {
+        Journal { dir }
+    }
+
+    fn path(&self, id: &Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// Records `entry`, replacing any earlier record of the same upload. The
+    /// record is written aside and renamed into place, so a crash leaves
+    /// either the old or the new one.
+    pub fn save(&self, entry: &JournalEntry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(&entry.upload.id);
+        write_atomically(&path, &serde_json::to_vec_pretty(entry)?)
+    }
+
+    pub fn remove(&self, id: &Uuid) -> io::Result<()> {
+        let path = self.path(id);
+        fs::remove_file(&path)?;
+        // Only the one upload ever writes its record, so nobody else can be
+        // waiting on the lock.
+        match fs::remove_file(lock_path(&path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every recorded upload, oldest first.
+    pub fn entries(&self) -> io::Result<Vec<JournalEntry>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut entries = vec![];
+        for f in fs::read_dir(&self.dir)? {
+            let path = f?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                let modified = fs::metadata(&path)?.modified()?;
+                let entry = serde_json::from_slice(&fs::read(&path)?)?;
+                entries.push((modified, entry));
+            }
+        }
+        entries.sort_by_key(|(modified, _)| *modified);
+        Ok(entries.into_iter().map(|(_, e)| e).collect())
+    }
+}
+
+/// The type of document the file `name` is pushed as, going by its
+/// extension, or `None` if it can't be pushed.
+pub fn file_type(name: &Path) -> Option<&'static str> {
+    let ext = name.extension()?.to_str()?.to_lowercase();
+    match ext.as_str() {
+        "pdf" => Some("pdf"),
+        "epub" => Some("epub"),
+        _ => None,
+    }
+}
+
+/// Checks that `data` starts the way a file of `file_type` does, so that a
+/// misnamed file is refused rather than uploaded as a broken document.
+fn check_contents(
+    name: &str,
+    file_type: &str,
+    data: &mut dyn Read,
+) -> CliResult<()> {
+    let magic: &[u8] = match file_type {
+        "pdf" => b"%PDF-",
+        _ => b"PK\x03\x04",
+    };
+    let mut header = vec![0; magic.len()];
+    let n = data.read(&mut header)?;
+    if header[..n] != *magic {
+        let name = format!("{:?}", name);
+        return Err(if file_type == "pdf" {
+            msg!(PUSH_NOT_PDF, name = name)
+        } else {
+            msg!(PUSH_NOT_EPUB, name = name)
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// Copies everything left in `src` to `dst`, hashing it on the way.
+fn copy_hashed(src: &mut dyn Read, dst: &mut dyn Write) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = src.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        dst.write_all(&buf[..n])?;
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+// The error for a file that's neither a PDF nor an EPUB.
+fn unsupported(name: &str) -> String {
+    msg!(PUSH_NEITHER, name = format!("{:?}", name))
+}
+
+/// Checks the PDF or EPUB at `path` can be pushed, returning its SHA-256.
+pub fn check(path: &Path) -> CliResult<String> {
+    let name = path.to_string_lossy();
+    let file_type = file_type(path).ok_or_else(|| unsupported(&name))?;
+    let mut file = fs::File::open(path)?;
+    check_contents(&name, file_type, &mut file)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(copy_hashed(&mut file, &mut io::sink())?)
+}
+
+/// Checks a PDF or EPUB named `name` and wraps it in the archive the cloud
+/// stores documents as. Returns the archive and the SHA-256 of `data`.
+pub fn package<R: Read + Seek>(
+    id: &Uuid,
+    name: &str,
+    data: &mut R,
+) -> CliResult<(Vec<u8>, String)> {
+    let file_type =
+        file_type(Path::new(name)).ok_or_else(|| unsupported(name))?;
+    check_contents(name, file_type, data)?;
+    data.seek(SeekFrom::Start(0))?;
+    let mut payload = vec![];
+    let sha256 = copy_hashed(data, &mut payload)?;
+    let builder = match file_type {
+        "pdf" => builder(file_type).payload_pdf(payload),
+        _ => builder(file_type).payload_epub(payload),
+    };
+    Ok((builder.build(*id)?, sha256))
+}
+
+// The archive of a document pushed from a file of `file_type`, but for the
+// file itself.
+fn builder(file_type: &str) -> DocumentArchiveBuilder {
+    DocumentArchiveBuilder::new()
+        .content(Content {
+            file_type: file_type.to_string(),
+            ..Default::default()
+        })
+        .pagedata(PageData::default())
+}
+
+/// As `package`, for the file at `path`. The archive of a file larger than
+/// `spill_above` is built in a temporary file, with the file copied into it
+/// rather than read into memory.
+pub fn package_file(
+    id: &Uuid,
+    path: &Path,
+    spill_above: u64,
+) -> CliResult<(Packaged, String)> {
+    let name = path.to_string_lossy();
+    let mut file = fs::File::open(path)?;
+    if file.metadata()?.len() <= spill_above {
+        let (zip, sha256) = package(id, &name, &mut file)?;
+        return Ok((Packaged::Memory(zip), sha256));
+    }
+    let file_type = file_type(path).ok_or_else(|| unsupported(&name))?;
+    check_contents(&name, file_type, &mut file)?;
+    file.seek(SeekFrom::Start(0))?;
+    let sha256 = copy_hashed(&mut file, &mut io::sink())?;
+    let builder = match file_type {
+        "pdf" => builder(file_type).payload_pdf_file(path),
+        _ => builder(file_type).payload_epub_file(path),
+    };
+    let file = builder.build_to(*id, tempfile::NamedTempFile::new()?)?;
+    let size = file.as_file().metadata()?.len();
+    Ok((Packaged::Spilled { file, size }, sha256))
+}
+
+// Reads the source of an upload and packages it, or returns `None` if it's
+// gone or no longer what was being uploaded.
+fn read_source(entry: &JournalEntry) -> CliResult<Option<Packaged>> {
+    let source = match &entry.source {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+    if let Err(e) = fs::metadata(source) {
+        return match e.kind() {
+            io::ErrorKind::NotFound => Ok(None),
+            _ => Err(e.into()),
+        };
+    }
+    match package_file(&entry.upload.id, source, ARCHIVE_MEMORY_LIMIT) {
+        Ok((zip, sha256)) if sha256 == entry.sha256 => Ok(Some(zip)),
+        _ => Ok(None),
+    }
+}
+
+// The name a document pushed from the file `name` is given, tidied as the
+// client would tidy it, so that it's what's looked for among the names
+// already there and the same whichever system the file came from.
+fn stem(name: &str) -> CliResult<String> {
+    Ok(Path::new(name)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .map(tidy_name)
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| msg!(NO_USABLE_NAME, name = format!("{:?}", name)))?)
+}
+
+/// The upload pushing the file `name` to `target` makes.
+pub fn upload_for(name: &str, target: &Target) -> CliResult<Upload> {
+    let stem = stem(name)?;
+    Ok(match target {
+        Target::New {
+            parent,
+            name: visible_name,
+        } => Upload::new(
+            Uuid::new_v4(),
+            1,
+            *parent,
+            visible_name.as_deref().unwrap_or(&stem),
+            DocType::Document,
+        ),
+        Target::Update(doc) => Upload::next_version(doc),
+    })
+}
+
+/// Plans the upload of `data` to `target`, recording it in the journal.
+/// `name` is the file it came from, whose extension says whether it's a
+/// PDF or an EPUB. Returns the entry and the archive to upload.
+pub fn start<R: Read + Seek>(
+    journal: &Journal,
+    name: &str,
+    source: Option<PathBuf>,
+    data: &mut R,
+    target: &Target,
+) -> CliResult<(JournalEntry, Vec<u8>)> {
+    let upload = upload_for(name, target)?;
+    let (zip, sha256) = package(&upload.id, name, data)?;
+    let entry = JournalEntry {
+        upload,
+        source,
+        sha256,
+    };
+    journal.save(&entry)?;
+    Ok((entry, zip))
+}
+
+/// Makes `folders`, given relative to `parent` and each after the one
+/// holding it, reusing any already there as `mkdir -p` does. Returns the id
+/// of each, and those which had to be created.
+pub async fn make_folders(
+    client: &Client,
+    documents: &Documents,
+    parent: Option<Uuid>,
+    folders: &[PathBuf],
+) -> CliResult<(HashMap<PathBuf, Uuid>, Vec<PathBuf>)> {
+    let mut ids: HashMap<PathBuf, Uuid> = HashMap::new();
+    let mut created = vec![];
+    for folder in folders {
+        let name = folder
+            .file_name()
+            .ok_or_else(|| {
+                msg!(NO_USABLE_NAME, name = format!("{:?}", folder))
+            })?
+            .to_string_lossy();
+        let parent = match folder.parent() {
+            Some(p) if !p.as_os_str().is_empty() => Some(ids[p]),
+            _ => parent,
+        };
+        let id = match backup::existing_folder(documents, parent, &name) {
+            Some(id) => id,
+            None => {
+                let id = Uuid::new_v4();
+                backup::create_folder(client, id, parent, &name).await?;
+                created.push(folder.clone());
+                id
+            }
+        };
+        ids.insert(folder.clone(), id);
+    }
+    Ok((ids, created))
+}
+
+/// What pushing `files` adds, as `TreeLimits::check_planned` takes it: the
+/// folders made for what's in directories, if `recursive`, and the name of
+/// each document, below those it goes in.
+pub fn planned(
+    files: &[PathBuf],
+    recursive: bool,
+) -> CliResult<Vec<Vec<String>>> {
+    let names = |path: &Path| -> Vec<String> {
+        path.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect()
+    };
+    let mut planned = vec![];
+    for file in files {
+        if !file.is_dir() {
+            planned.push(vec![stem(&file.to_string_lossy())?]);
+        } else if recursive {
+            let scan = scan::scan(file)?;
+            planned.extend(scan.folders.iter().map(|f| names(f)));
+            for file in &scan.files {
+                let mut path = file.parent().map(names).unwrap_or_default();
+                path.push(stem(&file.to_string_lossy())?);
+                planned.push(path);
+            }
+        }
+    }
+    Ok(planned)
+}
+
+/// Reads all of `input`, keeping it in memory if it's small and spooling it
+/// to a temporary file if not.
+fn read_input(input: &mut dyn Read) -> io::Result<tempfile::SpooledTempFile> {
+    let mut spooled = tempfile::spooled_tempfile(STDIN_MEMORY_LIMIT);
+    io::copy(input, &mut spooled)?;
+    spooled.seek(SeekFrom::Start(0))?;
+    Ok(spooled)
+}
+
+/// Carries out the next stage of an upload, then records the progress, or
+/// drops the record once the upload is done.
+pub async fn advance(
+    client: &Client,
+    journal: &Journal,
+    entry: &mut JournalEntry,
+    zip: BlobSource<'_>,
+) -> CliResult<()> {
+    client.advance_upload_from(&mut entry.upload, zip).await?;
+    if entry.upload.is_done() {
+        journal.remove(&entry.upload.id)?;
+    } else {
+        journal.save(entry)?;
+    }
+    Ok(())
+}
+
+/// Uploads `source` to `target`, returning the record of the finished
+/// upload. Its archive is built in a temporary file if it's larger than
+/// `spill_above`.
+pub async fn push(
+    client: &Client,
+    journal: &Journal,
+    source: &Path,
+    target: &Target,
+    spill_above: u64,
+) -> CliResult<JournalEntry> {
+    // Refused before anything is journalled, so nothing is left to resume.
+    if client.is_read_only() {
+        return Err(Error::ReadOnly.into());
+    }
+    let upload = upload_for(&source.to_string_lossy(), target)?;
+    let (zip, sha256) = package_file(&upload.id, source, spill_above)?;
+    let entry = JournalEntry {
+        upload,
+        source: Some(source.canonicalize()?),
+        sha256,
+    };
+    journal.save(&entry)?;
+    finish(client, journal, entry, zip.blob()).await
+}
+
+/// Uploads what's read from `input` to `target`, as the file `name`,
+/// returning the record of the finished upload.
+pub async fn push_input(
+    client: &Client,
+    journal: &Journal,
+    name: &str,
+    input: &mut dyn Read,
+    target: &Target,
+) -> CliResult<JournalEntry> {
+    if client.is_read_only() {
+        return Err(Error::ReadOnly.into());
+    }
+    let mut data = read_input(input)?;
+    let (entry, zip) = start(journal, name, None, &mut data, target)?;
+    finish(client, journal, entry, BlobSource::Memory(&zip)).await
+}
+
+/// Uploads `pdf`, made from the file `source` by optimizing it, to
+/// `target`. As it can't be made again just as it was, an interrupted
+/// upload of it is rolled back by `resume` unless its blob was stored.
+pub async fn push_optimized(
+    client: &Client,
+    journal: &Journal,
+    source: &Path,
+    pdf: &[u8],
+    target: &Target,
+) -> CliResult<JournalEntry> {
+    if client.is_read_only() {
+        return Err(Error::ReadOnly.into());
+    }
+    let name = source.to_string_lossy();
+    let mut data = io::Cursor::new(pdf);
+    let (entry, zip) = start(journal, &name, None, &mut data, target)?;
+    finish(client, journal, entry, BlobSource::Memory(&zip)).await
+}
+
+async fn finish(
+    client: &Client,
+    journal: &Journal,
+    mut entry: JournalEntry,
+    zip: BlobSource<'_>,
+) -> CliResult<JournalEntry> {
+    // Shown while a large blob goes up in chunks.
+    let mut progress = None;
+    let mib = |bytes: u64| (bytes / (1024 * 1024)) as usize;
+    while !entry.upload.is_done() {
+        advance(client, journal, &mut entry, zip).await?;
+        if let Some((sent, size)) = entry.upload.progress() {
+            progress
+                .get_or_insert_with(|| {
+                    Progress::new(msg!(PROGRESS_UPLOADED), mib(size))
+                })
+                .set(mib(sent));
+        }
+    }
+    Ok(entry)
+}
+
+/// What `push --verify` checks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Verify {
+    /// Files larger than this are pushed without being read back, if given.
+    pub max_size: Option<u64>,
+}
+
+impl Verify {
+    /// Whether a file of `size` bytes is read back once it's pushed.
+    pub fn covers(&self, size: u64) -> bool {
+        self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// Reads back the document `entry` uploaded, checking that the file in it
+/// has the SHA-256 recorded before it was sent. `name` is what the upload
+/// is called in the error it fails with otherwise, which says to push it
+/// again.
+pub async fn verify(
+    client: &Client,
+    entry: &JournalEntry,
+    name: &str,
+) -> CliResult<()> {
+    let upload = &entry.upload;
+    let file_type =
+        file_type(Path::new(name)).ok_or_else(|| unsupported(name))?;
+    // The file is named for the document, with the extension of its type.
+    let payload = format!("{}.{}", upload.id, file_type);
+    let found = match stored_sha256(client, &upload.id, &payload).await {
+        Ok(found) => found,
+        Err(e) => {
+            let error = e.to_string();
+            return Err(msg!(
+                PUSH_VERIFY_UNREADABLE,
+                path = name,
+                error = error
+            )
+            .into());
+        }
+    };
+    match found {
+        Some(found) if found == entry.sha256 => Ok(()),
+        Some(found) => Err(msg!(
+            PUSH_VERIFY_MISMATCH,
+            path = name,
+            found = found,
+            sent = &entry.sha256,
+        )
+        .into()),
+        None => {
+            Err(msg!(PUSH_VERIFY_MISSING, path = name, file = payload).into())
+        }
+    }
+}
+
+// The SHA-256 of the file `payload` in the archive the cloud has for `id`,
+// in hex, or `None` if there's no such file in it. The archive is read as
+// it's downloaded, by a blocking task fed its pieces as they arrive, so it's
+// never held whole or written anywhere.
+async fn stored_sha256(
+    client: &Client,
+    id: &Uuid,
+    payload: &str,
+) -> CliResult<Option<String>> {
+    let doc = client.get_document_by_id(id).await?;
+    let mut stream = client.blob_stream(&doc).await?;
+    let (mut chunks, received) =
+        tokio::sync::mpsc::channel(VERIFY_BUFFERED_CHUNKS);
+    let payload = payload.to_string();
+    let handle = tokio::runtime::Handle::current();
+    let hashed = tokio::task::spawn_blocking(move || {
+        let mut reader = ChunkReader {
+            chunks: received,
+            chunk: io::Cursor::new(vec![]),
+            handle,
+        };
+        payload_sha256(&mut reader, &payload).map_err(|e| e.to_string())
+    });
+    while let Some(chunk) = futures_util::StreamExt::next(&mut stream).await {
+        // Once the hashing's done, nothing more is wanted of the download.
+        if chunks.send(chunk?.to_vec()).await.is_err() {
+            break;
+        }
+    }
+    drop(chunks);
+    Ok(hashed.await.map_err(|e| e.to_string())??)
+}
+
+// Reads what's sent down `chunks`, waiting for each piece as it's needed,
+// so that a download can be read by what only reads synchronously.
+struct ChunkReader {
+    chunks: tokio::sync::mpsc::Receiver<Vec<u8>>,
+    chunk: io::Cursor<Vec<u8>>,
+    handle: tokio::runtime::Handle,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.chunk.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let chunks = &mut self.chunks;
+            match self.handle.block_on(chunks.recv()) {
+                Some(chunk) => self.chunk = io::Cursor::new(chunk),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+// The SHA-256 of the file `name` in the archive read from `reader`, from
+// start to end, or `None` if it isn't in it.
+fn payload_sha256<R: Read>(
+    reader: &mut R,
+    name: &str,
+) -> zip::result::ZipResult<Option<String>> {
+    while let Some(mut file) = zip::read::read_zipfile_from_stream(reader)? {
+        if file.name() == name {
+            return Ok(Some(copy_hashed(&mut file, &mut io::sink())?));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Finished,
+    /// The source changed or went away, or was stdin, before the blob was
+    /// stored.
+    RolledBack,
+}
+
+/// Finishes or rolls back every upload in the journal.
+pub async fn resume(
+    client: &Client,
+    journal: &Journal,
+) -> CliResult<Vec<(JournalEntry, Outcome)>> {
+    let mut outcomes = vec![];
+    for mut entry in journal.entries()? {
+        // Once the blob is stored only the metadata is left to set, which
+        // doesn't need the source.
+        let zip = match entry.upload.stage {
+            UploadStage::Started | UploadStage::Reserved => {
+                read_source(&entry)?
+            }
+            UploadStage::BlobPut | UploadStage::Done => {
+                Some(Packaged::Memory(vec![]))
+            }
+        };
+        let outcome = match zip {
+            Some(zip) => {
+                while !entry.upload.is_done() {
+                    advance(client, journal, &mut entry, zip.blob()).await?;
+                }
+                Outcome::Finished
+            }
+            None => {
+                journal.remove(&entry.upload.id)?;
+                Outcome::RolledBack
+            }
+        };
+        outcomes.push((entry, outcome));
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use remarkable_cloud_api::testing::FakeCloud;
+    use remarkable_cloud_api::Parent;
+
+    use super::*;
+
+    async fn setup() -> (FakeCloud, Client, tempfile::TempDir, Journal) {
+        let cloud = FakeCloud::start().await;
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().join("uploads"));
+        (cloud, client, dir, journal)
+    }
+
+    fn write_source(dir: &tempfile::TempDir, contents: &[u8]) -> PathBuf {
+        let path = dir.path().join("Dune.pdf");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn start_file(journal: &Journal, source: &Path) -> (JournalEntry, Vec<u8>) {
+        let mut file = fs::File::open(source).unwrap();
+        let name = source.to_string_lossy();
+        let target = Target::new_in(None);
+        start(
+            journal,
+            &name,
+            Some(source.to_path_buf()),
+            &mut file,
+            &target,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn spilled_archives() {
+        let (cloud, client, dir, journal) = setup().await;
+        let source = write_source(&dir, b"%PDF-1.4 and then some");
+        let id = push(&client, &journal, &source, &Target::new_in(None), 0)
+            .await
+            .unwrap()
+            .upload
+            .id;
+        let file = &mut fs::File::open(&source).unwrap();
+        let (zip, _) = package(&id, "Dune.pdf", file).unwrap();
+        assert_eq!(cloud.document(&id).unwrap().blob, zip);
+        assert!(journal.entries().unwrap().is_empty());
+
+        assert_eq!(MemoryBudget::new(None), MemoryBudget::default());
+        let mib = 1024 * 1024;
+        let budget = MemoryBudget::new(Some(200 * mib));
+        assert_eq!(budget.concurrency, PUSH_CONCURRENCY);
+        assert_eq!(budget.spill_above, ARCHIVE_MEMORY_LIMIT);
+        let budget = MemoryBudget::new(Some(50 * mib));
+        assert_eq!(budget.concurrency, 2);
+        assert_eq!(budget.spill_above, ARCHIVE_MEMORY_LIMIT);
+        let budget = MemoryBudget::new(Some(10 * mib));
+        assert_eq!(budget.concurrency, 1);
+        assert_eq!(budget.spill_above, 3 * mib);
+    }
+
+    #[tokio::test]
+    async fn push_document() {
+        let (cloud, client, dir, journal) = setup().await;
+        let books = cloud.add_folder("Books", None);
+        let source = write_source(&dir, b"%PDF-1.4");
+        let id = push(
+            &client,
+            &journal,
+            &source,
+            &Target::new_in(Some(books)),
+            ARCHIVE_MEMORY_LIMIT,
+        )
+        .await
+        .unwrap()
+        .upload
+        .id;
+
+        let docs = client.get_documents().await.unwrap();
+        let doc = docs.resolve("Books/Dune").unwrap().unwrap();
+        assert_eq!(doc.id, id);
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(
+            cloud.document(&id).unwrap().blob,
+        ))
+        .unwrap();
+        let mut pdf = vec![];
+        io::Read::read_to_end(
+            &mut archive.by_name(&format!("{}.pdf", id)).unwrap(),
+            &mut pdf,
+        )
+        .unwrap();
+        assert_eq!(pdf, b"%PDF-1.4");
+        assert!(journal.entries().unwrap().is_empty());
+
+        let notes = dir.path().join("notes.txt");
+        assert!(push(
+            &client,
+            &journal,
+            &notes,
+            &Target::new_in(None),
+            ARCHIVE_MEMORY_LIMIT
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn verified() {
+        let (cloud, client, dir, journal) = setup().await;
+        let source = write_source(&dir, b"%PDF-1.4 read back");
+        let target = Target::new_in(None);
+        let spill = ARCHIVE_MEMORY_LIMIT;
+        let entry = push(&client, &journal, &source, &target, spill)
+            .await
+            .unwrap();
+        verify(&client, &entry, "Dune.pdf").await.unwrap();
+
+        // Stored with a byte of the PDF changed, the archive no longer reads.
+        let name = format!("{}.pdf", entry.upload.id);
+        cloud.modify(&entry.upload.id, |d| {
+            let at = d
+                .blob
+                .windows(name.len())
+                .position(|w| w == name.as_bytes())
+                .unwrap();
+            d.blob[at + name.len() + 2] ^= 0xff;
+        });
+        let e = verify(&client, &entry, "Dune.pdf").await.unwrap_err();
+        assert!(e.to_string().contains("couldn't be read back"), "{}", e);
+
+        let verify = Verify { max_size: Some(10) };
+        assert!(verify.covers(10));
+        assert!(!verify.covers(11));
+        assert!(Verify::default().covers(u64::MAX));
+    }
+
+    #[test]
+    fn stem_tidied() {
+        assert_eq!(stem("/books/ Dune .pdf").unwrap(), "Dune");
+        // As macOS writes it, decomposed.
+        assert_eq!(stem("Cafe\u{301}.pdf").unwrap(), "Caf\u{e9}");
+        assert!(stem(" .pdf").is_err());
+    }
+
+    // Interrupts a push after each number of stages, then resumes it.
+    #[tokio::test]
+    async fn resume_after_each_stage() {
+        for stages in 0..3 {
+            let (cloud, client, dir, journal) = setup().await;
+            let source = write_source(&dir, b"%PDF-1.4");
+            let (mut entry, zip) = start_file(&journal, &source);
+            for _ in 0..stages {
+                advance(&client, &journal, &mut entry, (&zip).into())
+                    .await
+                    .unwrap();
+            }
+            drop(entry);
+
+            let outcomes = resume(&client, &journal).await.unwrap();
+            assert_eq!(outcomes.len(), 1, "after {} stages", stages);
+            assert_eq!(outcomes[0].1, Outcome::Finished);
+            let docs = client.get_documents().await.unwrap();
+            assert_eq!(docs.len(), 1, "after {} stages", stages);
+            assert!(docs.resolve("Dune").unwrap().is_some());
+            assert!(journal.entries().unwrap().is_empty());
+            drop(cloud);
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_after_status_set() {
+        // The status went through, but the process died before noting it.
+        let (_cloud, client, dir, journal) = setup().await;
+        let source = write_source(&dir, b"%PDF-1.4");
+        let (mut entry, zip) = start_file(&journal, &source);
+        for _ in 0..2 {
+            advance(&client, &journal, &mut entry, (&zip).into())
+                .await
+                .unwrap();
+        }
+        let mut upload = entry.upload.clone();
+        client.advance_upload(&mut upload, &zip).await.unwrap();
+        assert!(upload.is_done());
+
+        let outcomes = resume(&client, &journal).await.unwrap();
+        assert_eq!(outcomes[0].1, Outcome::Finished);
+        assert_eq!(client.get_documents().await.unwrap().len(), 1);
+        assert!(journal.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn roll_back_changed_source() {
+        let (_cloud, client, dir, journal) = setup().await;
+        let source = write_source(&dir, b"%PDF-1.4");
+        let (mut entry, zip) = start_file(&journal, &source);
+        advance(&client, &journal, &mut entry, (&zip).into())
+            .await
+            .unwrap();
+        write_source(&dir, b"%PDF-1.5");
+
+        let outcomes = resume(&client, &journal).await.unwrap();
+        assert_eq!(outcomes[0].1, Outcome::RolledBack);
+        assert!(client.get_documents().await.unwrap().is_empty());
+        assert!(journal.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn push_from_input() {
+        let (cloud, client, _dir, journal) = setup().await;
+        let id = push_input(
+            &client,
+            &journal,
+            "Paper.pdf",
+            &mut &b"%PDF-1.7 from a pipe"[..],
+            &Target::new_in(None),
+        )
+        .await
+        .unwrap()
+        .upload
+        .id;
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(&*docs.get(&id).unwrap().visible_name, "Paper");
+        assert!(!cloud.document(&id).unwrap().blob.is_empty());
+
+        // The same checks as for files.
+        for (name, data) in &[
+            ("Paper", &b"%PDF-1.7"[..]),
+            ("Paper.txt", b"%PDF-1.7"),
+            ("Paper.pdf", b"<html>"),
+            ("Paper.epub", b"%PDF-1.7"),
+            ("Paper.pdf", b""),
+        ] {
+            let mut input = *data;
+            let target = Target::new_in(None);
+            let result =
+                push_input(&client, &journal, name, &mut input, &target).await;
+            assert!(result.is_err(), "{} {:?}", name, data);
+        }
+        assert_eq!(client.get_documents().await.unwrap().len(), 1);
+        assert!(journal.entries().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn name_conflicts() {
+        let (cloud, client, dir, journal) = setup().await;
+        let source = write_source(&dir, b"%PDF-1.4");
+        let first = push(
+            &client,
+            &journal,
+            &source,
+            &Target::new_in(None),
+            ARCHIVE_MEMORY_LIMIT,
+        )
+        .await;
+        let first = first.unwrap().upload.id;
+        cloud.modify(&first, |d| d.bookmarked = true);
+        let docs = client.get_documents().await.unwrap();
+
+        let choose = |choice| {
+            let mut asked = None;
+            let target = target(&docs, None, "Dune.pdf", None, &mut |c| {
+                asked = Some(c.free_name.clone());
+                Ok(choice)
+            })
+            .unwrap();
+            assert_eq!(asked.as_deref(), Some("Dune (2)"));
+            target
+        };
+        assert_eq!(choose(OnConflict::Skip), None);
+        assert_eq!(choose(OnConflict::Duplicate), Some(Target::new_in(None)));
+        let renamed = choose(OnConflict::Rename).unwrap();
+        push(&client, &journal, &source, &renamed, ARCHIVE_MEMORY_LIMIT)
+            .await
+            .unwrap();
+        let update = choose(OnConflict::Update).unwrap();
+        assert_eq!(update, Target::Update(docs.get(&first).unwrap().clone()));
+        write_source(&dir, b"%PDF-1.5");
+        push(&client, &journal, &source, &update, ARCHIVE_MEMORY_LIMIT)
+            .await
+            .unwrap();
+
+        let docs = client.get_documents().await.unwrap();
+        assert_eq!(docs.len(), 2);
+        let dune = docs.resolve("Dune").unwrap().unwrap();
+        assert_eq!((dune.id, dune.version), (first, 2));
+        assert!(dune.bookmarked);
+        assert!(docs.resolve("Dune (2)").unwrap().is_some());
+
+        // --on-conflict answers without asking, and a free name needs
+        // neither.
+        let mut never =
+            |_: &Conflict| -> io::Result<OnConflict> { panic!("asked") };
+        let given = Some(OnConflict::Skip);
+        assert_eq!(
+            target(&docs, None, "Dune.pdf", given, &mut never).unwrap(),
+            None
+        );
+        let free = target(&docs, None, "Emma.pdf", None, &mut never).unwrap();
+        assert_eq!(free, Some(Target::new_in(None)));
+    }
+
+    #[test]
+    fn ask_choices() {
+        let docs =
+            crate::testutil::listing(&[(1, "Dune", None, "DocumentType")]);
+        let conflict = docs.conflict_for(Parent::Root, "Dune").unwrap();
+        for (answer, expected) in &[
+            ("u\n", OnConflict::Update),
+            ("Rename\n", OnConflict::Rename),
+            ("d\n", OnConflict::Duplicate),
+            ("\n", OnConflict::Skip),
+            ("x\n", OnConflict::Skip),
+        ] {
+            let mut output = vec![];
+            let choice =
+                ask("Dune.pdf", &conflict, &mut answer.as_bytes(), &mut output)
+                    .unwrap();
+            assert_eq!(choice, *expected, "{:?}", answer);
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.contains("[r]ename to \"Dune (2)\""), "{}", output);
+        }
+        assert_eq!("update".parse(), Ok(OnConflict::Update));
+        assert!("overwrite".parse::<OnConflict>().is_err());
+    }
+
+    #[test]
+    fn spools_large_input() {
+        let small = read_input(&mut &b"%PDF-1.7"[..]).unwrap();
+        assert!(!small.is_rolled());
+        let big = vec![b'x'; STDIN_MEMORY_LIMIT + 1];
+        let mut spooled = read_input(&mut &big[..]).unwrap();
+        assert!(spooled.is_rolled());
+        let mut back = vec![];
+        spooled.read_to_end(&mut back).unwrap();
+        assert_eq!(back, big);
+    }
+
+    #[tokio::test]
+    async fn roll_back_input() {
+        let (_cloud, client, _dir, journal) = setup().await;
+        let mut data = read_input(&mut &b"%PDF-1.7"[..]).unwrap();
+        let (mut entry, zip) = start(
+            &journal,
+            "Paper.pdf",
+            None,
+            &mut data,
+            &Target::new_in(None),
+        )
+        .unwrap();
+        advance(&client, &journal, &mut entry, (&zip).into())
+            .await
+            .unwrap();
+
+        let outcomes = resume(&client, &journal).await.unwrap();
+        assert_eq!(outcomes[0].1, Outcome::RolledBack);
+        assert!(client.get_documents().await.unwrap().is_empty());
+    }
+}
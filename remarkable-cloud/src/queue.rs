@@ -0,0 +1,732 @@
+//! Uploads queued to be done later, as by `push --queue` and `queue run`,
+//! for connections which can't be relied on.
+//!
+//! Queueing a file checks it and records it, without going near the cloud.
+//! The queue is a file of JSON lines, each the whole of a job as of some
+//! change to it, the last for a job being where it's at. Appending a line is
+//! all a change takes, so a crash loses at most the line being written,
+//! which is ignored when the queue is read.
+//!
+//! The upload a job makes, its document's id and version, is recorded before
+//! anything is sent, and again after each stage. A job found part way
+//! through is taken as done if the listing already has that version, as when
+//! the crash came after the upload but before it was recorded, and is
+//! otherwise carried on with; the source's SHA-256 makes sure what's carried
+//! on with is the file as it was queued.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{
+    lock_path, replace_locked, CancellationToken, Client, Error, FileLock,
+    Upload, UploadStage,
+};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::lock::{LockMode, ProfileLock};
+use crate::mappings::Mappings;
+use crate::mutations::MutationLog;
+use crate::push::{self, OnConflict};
+use crate::resolved::ResolvedTree;
+use crate::{destination, msg, CliResult};
+
+/// The file in the config directory holding the queue.
+pub const QUEUE_FILE: &str = "queue.jsonl";
+
+/// How long `queue run --forever` waits before looking for new jobs once
+/// it's through the queue.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// How long `queue run --forever` first waits before retrying jobs which
+/// failed on errors which may go away, doubling each time up to the most.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(15 * 60);
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for a run to start it.
+    Pending,
+    /// Being uploaded as `upload`, which is as far as its stage says.
+    Uploading { upload: Upload },
+    /// Uploaded as `version` of `document`.
+    Done { document: Uuid, version: u64 },
+    /// Not uploaded, as `on_conflict` said to skip it.
+    Skipped,
+    /// Given up on, as trying again wouldn't help.
+    Failed { error: String },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Job {
+    pub id: Uuid,
+    pub source: PathBuf,
+    /// The SHA-256 of the source when it was queued, in hex.
+    pub sha256: String,
+    /// The folder to push into, as given, or `None` for the root.
+    pub to: Option<String>,
+    /// What to do if the name is taken, or `None` to fail the job.
+    pub on_conflict: Option<OnConflict>,
+    pub queued: DateTime<Utc>,
+    #[serde(flatten)]
+    pub state: JobState,
+    /// How many runs have failed on the job with errors which may go away.
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Job {
+    /// Whether the job is still to be done.
+    pub fn is_waiting(&self) -> bool {
+        matches!(self.state, JobState::Pending | JobState::Uploading { .. })
+    }
+}
+
+pub struct Queue {
+    path: PathBuf,
+}
+
+impl Queue {
+    pub fn new(path: PathBuf) -> Self {
+        Queue { path }
+    }
+
+    /// Records `job` as it is now.
+    pub fn record(&self, job: &Job) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // So as not to append to a queue another process is compacting.
+        let _lock = FileLock::exclusive(&lock_path(&self.path))?;
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        let mut line = vec![];
+        // Finishes off a line left half written, so as not to run on from
+        // it.
+        if file.seek(SeekFrom::End(0))? > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.push(b'\n');
+            }
+        }
+        serde_json::to_writer(&mut line, job)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+
+    /// Every job, as last recorded, in the order they were queued.
+    pub fn jobs(&self) -> io::Result<Vec<Job>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut jobs: Vec<Job> = vec![];
+        for line in data.split(|b| *b == b'\n') {
+            // Anything unreadable is a line a crash cut short.
+            let job: Job = match serde_json::from_slice(line) {
+                Ok(job) => job,
+                Err(_) => continue,
+            };
+            match jobs.iter_mut().find(|j| j.id == job.id) {
+                Some(earlier) => *earlier = job,
+                None => jobs.push(job),
+            }
+        }
+        Ok(jobs)
+    }
+
+    /// Rewrites the queue with just the jobs still to be done or which
+    /// failed. The new queue is written aside and renamed into place, so a
+    /// crash leaves either the old or the new one, holding its lock
+    /// throughout so that no job recorded meanwhile is lost.
+    pub fn compact(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let _lock = FileLock::exclusive(&lock_path(&self.path))?;
+        let mut data = vec![];
+        for job in self.jobs()? {
+            if job.is_waiting() || matches!(job.state, JobState::Failed { .. })
+            {
+                serde_json::to_writer(&mut data, &job)?;
+                data.push(b'\n');
+            }
+        }
+        if data.is_empty() && !self.path.exists() {
+            return Ok(());
+        }
+        replace_locked(&self.path, &data)
+    }
+}
+
+/// Checks `source` can be pushed and queues it to go into the folder `to`.
+pub fn enqueue(
+    queue: &Queue,
+    source: &Path,
+    to: Option<&str>,
+    on_conflict: Option<OnConflict>,
+) -> CliResult<Job> {
+    let sha256 = push::check(source)?;
+    let source = source.canonicalize()?;
+    let queued = queue.jobs()?.into_iter().find(|j| {
+        j.is_waiting() && j.sha256 == sha256 && j.to.as_deref() == to
+    });
+    if let Some(job) = queued {
+        return Err(msg!(
+            QUEUE_ALREADY_QUEUED,
+            source = format!("{:?}", source),
+            job = job.id.to_string(),
+        )
+        .into());
+    }
+    let job = Job {
+        id: Uuid::new_v4(),
+        source,
+        sha256,
+        to: to.map(str::to_string),
+        on_conflict,
+        queued: Utc::now(),
+        state: JobState::Pending,
+        attempts: 0,
+        last_error: None,
+    };
+    queue.record(&job)?;
+    Ok(job)
+}
+
+/// Queues each of `files`, as `push --queue` does, to go into `to` or else
+/// where `mappings` say, telling `out` where each goes.
+pub fn enqueue_all<'a>(
+    queue: &Queue,
+    files: impl Iterator<Item = &'a Path>,
+    to: Option<&str>,
+    mappings: &Mappings,
+    on_conflict: Option<OnConflict>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let cwd = std::env::current_dir()?;
+    for file in files {
+        let to = to.or_else(|| {
+            let choice = mappings.choose(file, &cwd)?;
+            out.note(&choice.note(file));
+            Some(choice.folder)
+        });
+        let job = enqueue(queue, file, to, on_conflict)?;
+        out.note(&msg!(
+            QUEUED,
+            file = file.display().to_string(),
+            job = job.id.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Reports the jobs still waiting, and those which failed, to `out`.
+pub fn list(queue: &Queue, out: &mut dyn Output) -> CliResult<()> {
+    for job in queue.jobs()? {
+        if job.is_waiting() || matches!(job.state, JobState::Failed { .. }) {
+            out.line(&describe(&job));
+        }
+    }
+    Ok(())
+}
+
+/// A line of `queue list` for `job`.
+pub fn describe(job: &Job) -> String {
+    let state = match &job.state {
+        JobState::Pending => msg!(JOB_PENDING),
+        JobState::Uploading { .. } => msg!(JOB_UPLOADING),
+        JobState::Done { .. } => msg!(JOB_DONE),
+        JobState::Skipped => msg!(JOB_SKIPPED),
+        JobState::Failed { .. } => msg!(JOB_FAILED),
+    };
+    let mut line = format!(
+        "{}  {:<9}  {} -> {}",
+        job.id,
+        state,
+        job.source.display(),
+        job.to.as_deref().unwrap_or("/")
+    );
+    if let JobState::Failed { error } = &job.state {
+        line.push_str(&format!(": {}", error));
+    } else if let Some(error) = &job.last_error {
+        let attempts =
+            msg!(JOB_ATTEMPTS, attempts = job.attempts, error = error,);
+        line.push_str(&format!(" {}", attempts));
+    }
+    line
+}
+
+/// What came of the jobs one `run` tried.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunReport {
+    pub done: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Jobs left waiting, having failed on errors which may go away.
+    pub retry: usize,
+}
+
+// Whether trying again later may get past `e`.
+fn is_transient(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<Error>().is_some_and(|e| e.is_transient())
+}
+
+// Whether `e` means the run itself should stop.
+fn is_stop(e: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        e.downcast_ref::<Error>(),
+        Some(Error::Cancelled) | Some(Error::DeadlineExceeded)
+    )
+}
+
+/// Tries every job waiting in the queue once, dropping those done before
+/// from it first.
+pub async fn run(
+    client: &Client,
+    queue: &Queue,
+    mutations: &MutationLog,
+    out: &mut dyn Output,
+) -> CliResult<RunReport> {
+    queue.compact()?;
+    let mut report = RunReport::default();
+    let jobs: Vec<Job> =
+        queue.jobs()?.into_iter().filter(Job::is_waiting).collect();
+    if jobs.is_empty() {
+        return Ok(report);
+    }
+    let documents = ResolvedTree::new(client.get_documents().await?);
+    for mut job in jobs {
+        let name = job.source.display().to_string();
+        match run_job(client, &documents, queue, &mut job).await {
+            Ok(Some(upload)) => {
+                mutations.record_upload(
+                    upload.id,
+                    &upload.visible_name,
+                    upload.version,
+                    out,
+                );
+                out.note(&msg!(PUSHED, path = &name));
+                report.done += 1;
+            }
+            Ok(None) => {
+                out.note(&msg!(QUEUE_NAME_TAKEN, name = &name));
+                report.skipped += 1;
+            }
+            Err(e) if is_stop(&*e) => return Err(e),
+            Err(e) if is_transient(&*e) => {
+                out.warn(&msg!(
+                    QUEUE_WILL_RETRY,
+                    name = &name,
+                    error = e.to_string(),
+                ));
+                job.attempts += 1;
+                job.last_error = Some(e.to_string());
+                queue.record(&job)?;
+                report.retry += 1;
+            }
+            Err(e) => {
+                out.warn(&msg!(
+                    QUEUE_FAILED,
+                    name = &name,
+                    error = e.to_string(),
+                ));
+                job.state = JobState::Failed {
+                    error: e.to_string(),
+                };
+                queue.record(&job)?;
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+// Takes `job` as far as it'll go, recording each step in `queue`. Returns
+// the finished upload, or `None` if the job was skipped.
+async fn run_job(
+    client: &Client,
+    documents: &ResolvedTree,
+    queue: &Queue,
+    job: &mut Job,
+) -> CliResult<Option<Upload>> {
+    let name = job.source.to_string_lossy().to_string();
+    let mut upload = match job.state.clone() {
+        JobState::Pending => {
+            let parent = match &job.to {
+                Some(to) => destination(documents, &to.parse()?)?.folder(),
+                None => None,
+            };
+            let target = push::target(
+                documents,
+                parent,
+                &name,
+                job.on_conflict,
+                &mut |conflict| {
+                    Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        msg!(
+                            QUEUE_CONFLICT,
+                            name =
+                                format!("{:?}", conflict.existing.visible_name),
+                        ),
+                    ))
+                },
+            )?;
+            let target = match target {
+                Some(target) => target,
+                None => {
+                    job.state = JobState::Skipped;
+                    queue.record(job)?;
+                    return Ok(None);
+                }
+            };
+            let upload = push::upload_for(&name, &target)?;
+            job.state = JobState::Uploading {
+                upload: upload.clone(),
+            };
+            queue.record(job)?;
+            upload
+        }
+        JobState::Uploading { upload } => {
+            // Finished, with only that not recorded.
+            if documents
+                .get(&upload.id)
+                .is_some_and(|d| d.version >= upload.version)
+            {
+                job.state = JobState::Done {
+                    document: upload.id,
+                    version: upload.version,
+                };
+                queue.record(job)?;
+                return Ok(Some(upload));
+            }
+            upload
+        }
+        _ => return Ok(None),
+    };
+    // Once the blob is stored only the metadata is left to set, which
+    // doesn't need the source.
+    let zip = match upload.stage {
+        UploadStage::Started | UploadStage::Reserved => {
+            let mut file = fs::File::open(&job.source)?;
+            let (zip, sha256) = push::package(&upload.id, &name, &mut file)?;
+            if sha256 != job.sha256 {
+                return Err(msg!(
+                    QUEUE_SOURCE_CHANGED,
+                    name = format!("{:?}", name)
+                )
+                .into());
+            }
+            zip
+        }
+        UploadStage::BlobPut | UploadStage::Done => vec![],
+    };
+    while !upload.is_done() {
+        client.advance_upload(&mut upload, &zip).await?;
+        job.state = if upload.is_done() {
+            JobState::Done {
+                document: upload.id,
+                version: upload.version,
+            }
+        } else {
+            JobState::Uploading {
+                upload: upload.clone(),
+            }
+        };
+        queue.record(job)?;
+    }
+    Ok(Some(upload))
+}
+
+/// Runs the queue again and again, waiting longer between runs while jobs
+/// keep failing on errors which may go away, until `cancellation` is
+/// cancelled. The profile's lock is held for each run but not between
+/// them, so that other commands can get in.
+pub async fn run_forever(
+    client: &Client,
+    queue: &Queue,
+    mutations: &MutationLog,
+    lock: &ProfileLock,
+    cancellation: &CancellationToken,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut retry_delay = RETRY_DELAY;
+    loop {
+        let held = lock.acquire(LockMode::Exclusive, None, out).await?;
+        let result = run(client, queue, mutations, out).await;
+        drop(held);
+        let wait = match result {
+            Ok(report) if report.retry == 0 => {
+                retry_delay = RETRY_DELAY;
+                POLL_INTERVAL
+            }
+            Err(e) if !is_transient(&*e) => return Err(e),
+            Ok(_) | Err(_) => {
+                let wait = retry_delay;
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                let shown = humantime::format_duration(wait).to_string();
+                out.note(&msg!(TRYING_AGAIN, wait = shown));
+                wait
+            }
+        };
+        let delay = tokio::time::delay_for(wait);
+        let cancelled = cancellation.cancelled();
+        futures_util::pin_mut!(cancelled);
+        if let futures_util::future::Either::Right(_) =
+            futures_util::future::select(delay, cancelled).await
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs the queue once, as `queue run` does, failing if anything was left
+/// unpushed.
+pub async fn run_and_report(
+    client: &Client,
+    queue: &Queue,
+    mutations: &MutationLog,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let report = run(client, queue, mutations, out).await?;
+    out.note(&msg!(
+        QUEUE_RUN,
+        done = report.done,
+        skipped = report.skipped,
+        failed = report.failed,
+        retry = report.retry,
+    ));
+    if report.failed > 0 || report.retry > 0 {
+        return Err(msg!(QUEUE_NOT_EMPTIED).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use remarkable_cloud_api::testing::FakeCloud;
+
+    use super::*;
+    use crate::commands::Capture;
+    use crate::mutations::MUTATIONS_LOG;
+    use crate::summary::TransferReport;
+
+    const UPLOAD_REQUEST: &str = "/document-storage/json/2/upload/request";
+
+    struct Setup {
+        cloud: FakeCloud,
+        client: Client,
+        dir: tempfile::TempDir,
+        queue: Queue,
+        mutations: MutationLog,
+    }
+
+    impl Setup {
+        async fn new() -> Setup {
+            let cloud = FakeCloud::start().await;
+            let mut client = cloud.client();
+            client.refresh_token().await.unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let queue = Queue::new(dir.path().join(QUEUE_FILE));
+            let mutations = MutationLog::new(dir.path().join(MUTATIONS_LOG));
+            Setup {
+                cloud,
+                client,
+                dir,
+                queue,
+                mutations,
+            }
+        }
+
+        fn source(&self, name: &str, contents: &[u8]) -> PathBuf {
+            let path = self.dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        }
+
+        async fn run(&self) -> RunReport {
+            let mut out = Capture::new(TransferReport::new());
+            run(&self.client, &self.queue, &self.mutations, &mut out)
+                .await
+                .unwrap()
+        }
+
+        fn uploads(&self) -> usize {
+            let requests = self.cloud.requests();
+            requests.iter().filter(|r| r.path == UPLOAD_REQUEST).count()
+        }
+
+        async fn names(&self) -> Vec<String> {
+            let docs = self.client.get_documents().await.unwrap();
+            let mut names: Vec<String> =
+                docs.iter().map(|d| d.visible_name.to_string()).collect();
+            names.sort();
+            names
+        }
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn queued_and_run() {
+        let s = Setup::new().await;
+        let dune = s.source("Dune.pdf", b"%PDF-dune");
+        s.cloud.add_folder("Books", None);
+        let requests = s.cloud.requests().len();
+        let job = enqueue(&s.queue, &dune, Some("Books"), None).unwrap();
+        assert_eq!(job.state, JobState::Pending);
+        // Nothing is sent until the queue is run.
+        assert_eq!(s.cloud.requests().len(), requests);
+        let again = enqueue(&s.queue, &dune, Some("Books"), None);
+        assert!(again.unwrap_err().to_string().contains("already queued"));
+        let notes = s.source("Notes.txt", b"notes");
+        assert!(enqueue(&s.queue, &notes, None, None).is_err());
+
+        let report = s.run().await;
+        assert_eq!(report.done, 1);
+        assert_eq!(s.names().await, vec!["Books", "Dune"]);
+        let jobs = s.queue.jobs().unwrap();
+        assert!(matches!(jobs[0].state, JobState::Done { .. }));
+        assert_eq!(s.mutations.entries().unwrap().len(), 1);
+
+        // Done jobs are dropped, and not done again.
+        assert_eq!(s.run().await, RunReport::default());
+        assert_eq!(s.uploads(), 1);
+        assert!(s.queue.jobs().unwrap().is_empty());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn failures() {
+        let s = Setup::new().await;
+        s.cloud.add_document("Dune", None, vec![]);
+        let dune = s.source("Dune.pdf", b"%PDF-dune");
+        let taken = enqueue(&s.queue, &dune, None, None).unwrap();
+        let hyperion = s.source("Hyperion.pdf", b"%PDF-hyperion");
+        let changed = enqueue(&s.queue, &hyperion, None, None).unwrap();
+        fs::write(&hyperion, b"%PDF-hyperion, revised").unwrap();
+        let emma = s.source("Emma.pdf", b"%PDF-emma");
+        let retried = enqueue(&s.queue, &emma, None, None).unwrap();
+
+        // The cloud is unreachable for every attempt at the third upload.
+        for _ in 0..4 {
+            s.cloud.fail_next(UPLOAD_REQUEST, false);
+        }
+        // The first two fail before anything is sent.
+        let report = s.run().await;
+        assert_eq!((report.failed, report.retry), (2, 1));
+        let jobs = s.queue.jobs().unwrap();
+        let state = |id| &jobs.iter().find(|j| j.id == id).unwrap().state;
+        match state(taken.id) {
+            JobState::Failed { error } => {
+                assert!(error.contains("--on-conflict"), "{}", error)
+            }
+            other => panic!("{:?}", other),
+        }
+        match state(changed.id) {
+            JobState::Failed { error } => {
+                assert!(error.contains("changed since"), "{}", error)
+            }
+            other => panic!("{:?}", other),
+        }
+        let retried_job = jobs.iter().find(|j| j.id == retried.id).unwrap();
+        assert!(retried_job.is_waiting());
+        assert_eq!(retried_job.attempts, 1);
+        assert!(retried_job.last_error.is_some());
+        assert!(describe(retried_job).contains("1 failed attempts"));
+
+        // Failures are kept for inspection, while the rest carries on.
+        let report = s.run().await;
+        assert_eq!((report.done, report.failed), (1, 0));
+        assert_eq!(s.names().await, vec!["Dune", "Emma"]);
+        let jobs = s.queue.jobs().unwrap();
+        assert_eq!(jobs.len(), 3);
+        assert!(describe(&jobs[0]).contains("failed"));
+    }
+
+    // Each stage of an upload, as a crash would leave it recorded.
+    async fn interrupted(s: &Setup, stages: usize) -> Job {
+        let dune = s.source("Dune.pdf", b"%PDF-dune");
+        let mut job = enqueue(&s.queue, &dune, None, None).unwrap();
+        let name = dune.to_string_lossy();
+        let mut upload =
+            push::upload_for(&name, &push::Target::new_in(None)).unwrap();
+        let (zip, _) = push::package(
+            &upload.id,
+            &name,
+            &mut fs::File::open(&dune).unwrap(),
+        )
+        .unwrap();
+        for _ in 0..stages {
+            s.client.advance_upload(&mut upload, &zip).await.unwrap();
+        }
+        job.state = JobState::Uploading { upload };
+        s.queue.record(&job).unwrap();
+        job
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn crash_recovery() {
+        // Stopped before the reservation, after it, after the blob, and
+        // after the whole upload but before it was recorded.
+        for stages in 0..4 {
+            let s = Setup::new().await;
+            let job = interrupted(&s, stages).await;
+            let uploads = s.uploads();
+            let report = s.run().await;
+            assert_eq!(report.done, 1, "after {} stages", stages);
+            assert_eq!(s.names().await, vec!["Dune"], "after {}", stages);
+            let expected = if stages == 0 { 1 } else { uploads };
+            assert_eq!(s.uploads(), expected, "after {} stages", stages);
+            let jobs = s.queue.jobs().unwrap();
+            assert!(
+                matches!(jobs[0].state, JobState::Done { .. }),
+                "after {} stages: {:?}",
+                stages,
+                jobs
+            );
+            assert_eq!(jobs[0].id, job.id);
+        }
+
+        // A file changed mid-upload isn't finished with what it is now.
+        let s = Setup::new().await;
+        interrupted(&s, 1).await;
+        fs::write(s.dir.path().join("Dune.pdf"), b"%PDF-other").unwrap();
+        assert_eq!(s.run().await.failed, 1);
+        assert!(s.names().await.is_empty());
+    }
+
+    #[test]
+    fn torn_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = Queue::new(dir.path().join(QUEUE_FILE));
+        let source = dir.path().join("Dune.pdf");
+        fs::write(&source, b"%PDF-dune").unwrap();
+        let mut job = enqueue(&queue, &source, None, None).unwrap();
+        // A crash part way through writing the next line.
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(QUEUE_FILE))
+            .unwrap();
+        file.write_all(br#"{"id": "#).unwrap();
+        assert_eq!(queue.jobs().unwrap(), vec![job.clone()]);
+
+        job.state = JobState::Failed {
+            error: "gone".to_string(),
+        };
+        queue.record(&job).unwrap();
+        assert_eq!(queue.jobs().unwrap(), vec![job.clone()]);
+        queue.compact().unwrap();
+        assert_eq!(queue.jobs().unwrap(), vec![job]);
+    }
+}
@@ -0,0 +1,226 @@
+//! `--redact`, which hides the names and ids of documents in everything a
+//! run prints or logs, so the output can be shared in a bug report.
+//!
+//! Each name and id is replaced by a hash of it, salted afresh for each
+//! run, so the same document reads the same everywhere in one run's output
+//! and the structure of the tree can still be followed. Types, sizes and
+//! times are left alone. Ids become other UUIDs, so JSON output still
+//! parses as it did.
+//!
+//! The fields in [`crate::columns`] are redacted as they're rendered, and
+//! every line through the binary's `Output` and the JSON log is scrubbed
+//! of the names and ids in the listing, so output added later is covered
+//! by default. Where a command prints something else itself, it passes it
+//! through [`text`].
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use remarkable_cloud_api::Documents;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+// Where to write the map once the run is over, if anywhere.
+static MAP_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Hashes names and ids with one salt, remembering what it has hashed.
+pub struct Redactor {
+    salt: [u8; 16],
+    /// What each name and id in the listing becomes, for scrubbing text.
+    known: Mutex<BTreeMap<String, String>>,
+}
+
+impl Redactor {
+    pub fn new(salt: [u8; 16]) -> Self {
+        Redactor {
+            salt,
+            known: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn hash(&self, kind: &str, value: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update(kind.as_bytes());
+        hasher.update(value.as_bytes());
+        hasher.finalize().into()
+    }
+
+    /// What `name` is shown as.
+    pub fn name(&self, name: &str) -> String {
+        let hash = self.hash("name", name);
+        let token: String =
+            hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+        let token = format!("name-{}", token);
+        self.remember(name, &token);
+        token
+    }
+
+    /// What `id` is shown as.
+    pub fn id(&self, id: &Uuid) -> Uuid {
+        let hash = self.hash("id", &id.to_string());
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&hash[..16]);
+        let token = Uuid::from_bytes(bytes);
+        self.remember(&id.to_string(), &token.to_string());
+        token
+    }
+
+    fn remember(&self, original: &str, token: &str) {
+        if !original.is_empty() {
+            let mut known = self.known.lock().unwrap();
+            known.insert(original.to_string(), token.to_string());
+        }
+    }
+
+    /// Takes note of the name and id of everything in `documents`, so
+    /// that `text` can find them.
+    pub fn learn(&self, documents: &Documents) {
+        for d in documents.iter() {
+            self.name(&d.visible_name);
+            self.id(&d.id);
+        }
+    }
+
+    /// `text` with every name and id noted so far replaced, the longest
+    /// first where they overlap.
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let known = self.known.lock().unwrap();
+        let mut originals: Vec<&String> = known
+            .keys()
+            .filter(|original| text.contains(original.as_str()))
+            .collect();
+        if originals.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        originals.sort_by_key(|original| std::cmp::Reverse(original.len()));
+        let mut redacted = String::with_capacity(text.len());
+        let mut rest = text;
+        while !rest.is_empty() {
+            match originals.iter().find(|o| rest.starts_with(o.as_str())) {
+                Some(original) => {
+                    redacted.push_str(&known[original.as_str()]);
+                    rest = &rest[original.len()..];
+                }
+                None => {
+                    let c = rest.chars().next().unwrap();
+                    redacted.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        Cow::Owned(redacted)
+    }
+
+    /// What each name and id shown stands for, by what it was shown as.
+    pub fn map(&self) -> BTreeMap<String, String> {
+        let known = self.known.lock().unwrap();
+        known
+            .iter()
+            .map(|(original, token)| (token.clone(), original.clone()))
+            .collect()
+    }
+}
+
+/// Turns redaction on for the rest of the run, with a new salt, to write
+/// the map to `map_path` at the end, if given, with [`finish`].
+pub fn enable(map_path: Option<PathBuf>) {
+    let salt = *Uuid::new_v4().as_bytes();
+    let _ = REDACTOR.set(Redactor::new(salt));
+    if let Some(path) = map_path {
+        let _ = MAP_PATH.set(path);
+    }
+}
+
+/// The redactor, if redaction is on.
+pub fn active() -> Option<&'static Redactor> {
+    REDACTOR.get()
+}
+
+/// What `name` is shown as: itself unless redaction is on.
+pub fn name(name: &str) -> Cow<'_, str> {
+    match active() {
+        Some(r) => Cow::Owned(r.name(name)),
+        None => Cow::Borrowed(name),
+    }
+}
+
+/// What `id` is shown as: itself unless redaction is on.
+pub fn id(id: &Uuid) -> Uuid {
+    active().map_or(*id, |r| r.id(id))
+}
+
+/// `text` with the names and ids of the listing in it replaced, if
+/// redaction is on.
+pub fn text(text: &str) -> Cow<'_, str> {
+    match active() {
+        Some(r) => r.text(text),
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Takes note of the names and ids in `documents`, if redaction is on.
+pub fn learn(documents: &Documents) {
+    if let Some(r) = active() {
+        r.learn(documents);
+    }
+}
+
+/// Writes what each name and id shown stands for to `path`, as JSON, so
+/// that answers about the redacted output can be read back.
+pub fn write_map(path: &Path) -> std::io::Result<()> {
+    let map = active().map(Redactor::map).unwrap_or_default();
+    let mut data = serde_json::to_vec_pretty(&map)?;
+    data.push(b'\n');
+    fs::write(path, data)
+}
+
+/// Writes the map where `enable` was told to, if anywhere, once nothing
+/// more is to be shown.
+pub fn finish() -> std::io::Result<()> {
+    match MAP_PATH.get() {
+        Some(path) => write_map(path),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::listing;
+
+    #[test]
+    fn consistent() {
+        let docs = listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+            (3, "Dune Messiah", Some(1), "DocumentType"),
+        ]);
+        let redactor = Redactor::new([7; 16]);
+        redactor.learn(&docs);
+        let dune = redactor.name("Dune");
+        assert!(dune.starts_with("name-"), "{}", dune);
+        assert_eq!(redactor.name("Dune"), dune);
+        assert_ne!(redactor.name("Dune Messiah"), dune);
+        let id = Uuid::from_u128(2);
+        assert_ne!(redactor.id(&id), id);
+        assert_eq!(redactor.id(&id), redactor.id(&id));
+
+        let line = format!("Books/Dune Messiah and Books/Dune {}", id);
+        let redacted = redactor.text(&line);
+        assert!(!redacted.contains("Dune"), "{}", redacted);
+        assert!(!redacted.contains("Books"), "{}", redacted);
+        assert!(!redacted.contains(&id.to_string()), "{}", redacted);
+        assert!(redacted.contains(&format!("/{} ", dune)), "{}", redacted);
+        assert!(redacted.contains(&redactor.id(&id).to_string()));
+        assert_eq!(redactor.text("nothing known"), "nothing known");
+
+        // Another run hashes differently.
+        assert_ne!(Redactor::new([8; 16]).name("Dune"), dune);
+        assert_eq!(redactor.map()[&dune], "Dune");
+    }
+}
@@ -0,0 +1,284 @@
+//! Rendering the document tree as lines of text, as done by `ls`.
+
+use remarkable_cloud_api::{CloudPath, Document, Parent};
+
+use crate::columns::{self, Column};
+use crate::resolved::ResolvedTree;
+use crate::sort::{Collation, SortOrder};
+use crate::{content, locate, Location};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ListOptions {
+    /// How many levels below the starting folder to show, or `None` for all
+    /// of them.
+    pub max_depth: Option<usize>,
+    /// Whether to show each document's full path alone, rather than the
+    /// tree.
+    pub paths: bool,
+    /// Only documents of this type, by the content cache, and folders.
+    pub content_type: Option<&'static str>,
+    /// The order of the documents in each folder, or of the paths.
+    pub sort: SortOrder,
+}
+
+impl ListOptions {
+    // Whether `d`, `depth` levels below where the listing starts, is shown.
+    fn shows(&self, depth: usize, d: &Document) -> bool {
+        self.max_depth.is_none_or(|max| depth < max)
+            && self.content_type.is_none_or(|t| {
+                d.is_folder() || content::is_type(d, t) == Some(true)
+            })
+    }
+}
+
+/// A line for everything below `start`, indented by two spaces for each
+/// level below it, or with `options.paths` the full path of each in path
+/// order, in `options.sort`.
+pub fn tree(
+    docs: &ResolvedTree,
+    start: Parent,
+    options: ListOptions,
+) -> Vec<String> {
+    if options.paths {
+        return listed(docs, start, options)
+            .into_iter()
+            .map(|(path, d)| Column::Path.text(&path, d))
+            .collect();
+    }
+    let collation = Collation::new(options.sort);
+    let names = |a: &str, b: &str| collation.names(a, b);
+    docs.descendants_by(start, &names)
+        .filter(|(depth, d)| options.shows(*depth, d))
+        .map(|(depth, d)| {
+            format!(
+                "{}{} {}",
+                "  ".repeat(depth),
+                Column::Name.text("", d),
+                Column::Id.text("", d)
+            )
+        })
+        .collect()
+}
+
+// Everything below `start` as far down as `options` goes, with its full
+// path, in path order.
+fn listed(
+    docs: &ResolvedTree,
+    start: Parent,
+    options: ListOptions,
+) -> Vec<(String, &Document)> {
+    let mut listed: Vec<(String, &Document)> = docs
+        .descendants(start)
+        .filter(|(depth, d)| options.shows(*depth, d))
+        .filter_map(|(_, d)| Some((path_of(docs, start, d)?, d)))
+        .collect();
+    Collation::new(options.sort).sort_paths(&mut listed);
+    listed
+}
+
+// The path of `d`, which is below `start`. Those in the trash have no path
+// from the root, so they're given one from the trash.
+fn path_of(docs: &ResolvedTree, start: Parent, d: &Document) -> Option<String> {
+    if start != Parent::Trash {
+        return docs.path_of(&d.id);
+    }
+    let ancestors = docs.ancestors(&d.id).ok()?;
+    let names = ancestors
+        .iter()
+        .rev()
+        .chain(std::iter::once(&d))
+        .map(|d| d.visible_name.to_string())
+        .collect();
+    Some(CloudPath::Trash(names).to_string())
+}
+
+// Where `ls` starts for `path`, or the line saying why it can't.
+fn start(docs: &ResolvedTree, path: &CloudPath) -> Result<Parent, String> {
+    match locate(docs, path) {
+        Ok(Location::Root) => Ok(Parent::Root),
+        Ok(Location::Trash) => Ok(Parent::Trash),
+        Ok(Location::Document(d)) => Ok(Parent::Folder(d.id)),
+        Ok(Location::Missing(e)) | Err(e) => Err(e.to_string()),
+    }
+}
+
+/// What `ls` prints for `path`: the tree below it, or why it can't be
+/// listed.
+pub fn ls(
+    docs: &ResolvedTree,
+    path: &CloudPath,
+    options: ListOptions,
+) -> Vec<String> {
+    match start(docs, path) {
+        Ok(start) => tree(docs, start, options),
+        Err(line) => vec![line],
+    }
+}
+
+/// How many documents `ls` would list below `path` but for
+/// `options.content_type`, as their contents aren't cached.
+pub fn unknown_content(
+    docs: &ResolvedTree,
+    path: &CloudPath,
+    options: ListOptions,
+) -> usize {
+    let (start, content_type) = match (start(docs, path), options.content_type)
+    {
+        (Ok(start), Some(content_type)) => (start, content_type),
+        _ => return 0,
+    };
+    docs.descendants(start)
+        .filter(|(depth, _)| options.max_depth.is_none_or(|max| *depth < max))
+        .filter(|(_, d)| content::is_type(d, content_type).is_none())
+        .count()
+}
+
+/// What `ls --fields` prints for `path`: a row of `columns` for each
+/// document below it, in path order, or why it can't be listed.
+pub fn ls_fields(
+    docs: &ResolvedTree,
+    path: &CloudPath,
+    options: ListOptions,
+    columns: &[Column],
+) -> Vec<String> {
+    match start(docs, path) {
+        Ok(start) => listed(docs, start, options)
+            .into_iter()
+            .map(|(path, d)| columns::tsv_row(columns, &path, d))
+            .collect(),
+        Err(line) => vec![line],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    fn path(s: &str) -> CloudPath {
+        s.parse().unwrap()
+    }
+
+    fn docs() -> ResolvedTree {
+        ResolvedTree::new(listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+            (3, "Sci-fi", Some(1), "CollectionType"),
+            (4, "Hyperion", Some(3), "DocumentType"),
+            (5, "Notes", None, "DocumentType"),
+        ]))
+    }
+
+    fn names(lines: Vec<String>) -> Vec<String> {
+        // Drop the ids, keeping the indentation.
+        lines
+            .into_iter()
+            .map(|l| l.rsplit_once(' ').unwrap().0.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn indentation() {
+        let docs = docs();
+        let lines = tree(&docs, Parent::Root, ListOptions::default());
+        assert_eq!(lines[0], format!("Books {}", Uuid::from_u128(1)));
+        assert_eq!(
+            names(lines),
+            vec!["Books", "  Dune", "  Sci-fi", "    Hyperion", "Notes"]
+        );
+        let books = tree(
+            &docs,
+            Parent::Folder(Uuid::from_u128(1)),
+            ListOptions::default(),
+        );
+        assert_eq!(names(books), vec!["Dune", "Sci-fi", "  Hyperion"]);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let docs = docs();
+        let depth = |max_depth| {
+            let options = ListOptions {
+                max_depth,
+                ..Default::default()
+            };
+            names(ls(&docs, &path("/"), options))
+        };
+        assert_eq!(depth(Some(1)), vec!["Books", "Notes"]);
+        assert_eq!(
+            depth(Some(2)),
+            vec!["Books", "  Dune", "  Sci-fi", "Notes"]
+        );
+        assert_eq!(depth(Some(0)), Vec::<String>::new());
+        assert_eq!(depth(None).len(), 5);
+    }
+
+    #[test]
+    fn flat_paths() {
+        let docs = docs();
+        let options = ListOptions {
+            max_depth: None,
+            paths: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ls(&docs, &path("/"), options),
+            vec![
+                "Books",
+                "Books/Dune",
+                "Books/Sci-fi",
+                "Books/Sci-fi/Hyperion",
+                "Notes"
+            ]
+        );
+        let shallow = ListOptions {
+            max_depth: Some(1),
+            ..options
+        };
+        assert_eq!(
+            ls(&docs, &path("Books"), shallow),
+            vec!["Books/Dune", "Books/Sci-fi"]
+        );
+    }
+
+    #[test]
+    fn fields() {
+        let docs = docs();
+        let columns = Column::parse_list("path,type,name").unwrap();
+        let options = ListOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            ls_fields(&docs, &path("/"), options, &columns),
+            vec![
+                "Books\tCollectionType\tBooks",
+                "Books/Dune\tDocumentType\tDune",
+                "Books/Sci-fi\tCollectionType\tSci-fi",
+                "Notes\tDocumentType\tNotes",
+            ]
+        );
+        assert_eq!(
+            ls_fields(&docs, &path("Emma"), options, &columns),
+            vec!["Couldn't find \"/Emma\": nothing named \"Emma\" is at the root"]
+        );
+    }
+
+    #[test]
+    fn not_found() {
+        let docs = docs();
+        let options = ListOptions::default();
+        assert_eq!(
+            ls(&docs, &path("Books/Emma"), options),
+            vec![
+                "Couldn't find \"/Books/Emma\": nothing named \"Emma\" is in \
+                 Books"
+            ]
+        );
+        assert_eq!(ls(&docs, &path("Books/Sci-fi"), options).len(), 1);
+        // A document lists as empty, as it has nothing below it.
+        assert!(ls(&docs, &path("Notes"), options).is_empty());
+    }
+}
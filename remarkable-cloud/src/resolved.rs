@@ -0,0 +1,371 @@
+//! A listing indexed for the lookups a command makes over and over.
+//!
+//! Commands which act on many paths look up the same folders again and
+//! again: every target of a glob, every file of a push plan, shares its
+//! leading components with the others. `Documents` scans the whole listing
+//! for each component and walks up to the root for each path it prints.
+//! [`ResolvedTree`] indexes children by name once, and remembers each path
+//! it works out, so each lookup costs about as much as its own components.
+//! It derefs to the `Documents` it wraps for everything else.
+//!
+//! Under `--normalize-paths` (see [`normalize_paths`]), names are indexed
+//! and looked up by `name_key`, so a path typed in either Unicode form, or
+//! in another case, finds the document.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use remarkable_cloud_api::{
+    join_path, lookup_with, name_key, CloudPath, Document, Documents, Error,
+    Resolved, Result,
+};
+use uuid::Uuid;
+
+static NORMALIZE_PATHS: AtomicBool = AtomicBool::new(false);
+
+/// Has trees made from here on match names in paths ignoring Unicode
+/// normalization and case, as `--normalize-paths` asks.
+pub fn normalize_paths(on: bool) {
+    NORMALIZE_PATHS.store(on, Ordering::Relaxed)
+}
+
+/// Whether trees made now match names as `normalize_paths` asks.
+pub fn normalizing() -> bool {
+    NORMALIZE_PATHS.load(Ordering::Relaxed)
+}
+
+// What a name is indexed by.
+fn index_key(name: &str, normalized: bool) -> String {
+    if normalized {
+        name_key(name, true)
+    } else {
+        name.to_string()
+    }
+}
+
+pub struct ResolvedTree {
+    documents: Documents,
+    children: HashMap<(Option<Uuid>, String), Vec<Uuid>>,
+    paths: RefCell<HashMap<Uuid, Option<String>>>,
+    normalized: bool,
+    cache_age: Option<Duration>,
+}
+
+impl Deref for ResolvedTree {
+    type Target = Documents;
+
+    fn deref(&self) -> &Documents {
+        &self.documents
+    }
+}
+
+impl ResolvedTree {
+    pub fn new(documents: Documents) -> Self {
+        Self::with_normalized(documents, normalizing())
+    }
+
+    /// A tree of `documents` as read from the listing cache, saved `age`
+    /// ago.
+    pub fn from_cache(documents: Documents, age: Duration) -> Self {
+        ResolvedTree {
+            cache_age: Some(age),
+            ..Self::new(documents)
+        }
+    }
+
+    pub(crate) fn with_normalized(
+        documents: Documents,
+        normalized: bool,
+    ) -> Self {
+        let mut children: HashMap<_, Vec<Uuid>> = HashMap::new();
+        for d in documents.iter() {
+            let key = index_key(&d.visible_name, normalized);
+            children.entry((d.parent, key)).or_default().push(d.id);
+        }
+        ResolvedTree {
+            documents,
+            children,
+            paths: RefCell::new(HashMap::new()),
+            normalized,
+            cache_age: None,
+        }
+    }
+
+    /// How old the cached listing this was made from is, if it was served
+    /// from the cache rather than fetched.
+    pub fn cache_age(&self) -> Option<Duration> {
+        self.cache_age
+    }
+
+    /// Whether `name`, as written in a path, names something called
+    /// `visible_name`: exactly, or under `--normalize-paths` ignoring
+    /// normalization and case.
+    pub fn same_name(&self, name: &str, visible_name: &str) -> bool {
+        name == visible_name
+            || self.normalized
+                && name_key(name, true) == name_key(visible_name, true)
+    }
+
+    /// Whether names in paths are matched ignoring normalization and case.
+    pub fn normalizes_paths(&self) -> bool {
+        self.normalized
+    }
+
+    /// As `Documents::resolve`.
+    pub fn resolve<S: AsRef<str>>(&self, path: S) -> Result<Option<&Document>> {
+        let written = path.as_ref();
+        match written.parse().and_then(|path| self.lookup(&path)) {
+            Ok(Resolved::Document(d)) => Ok(Some(d)),
+            Ok(_) | Err(Error::PathNotFound { .. }) => Ok(None),
+            Err(Error::AmbiguousPath { matches, .. }) => {
+                Err(Error::AmbiguousPath {
+                    path: written.to_string(),
+                    matches,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// As `Documents::lookup`.
+    pub fn lookup(&self, path: &CloudPath) -> Result<Resolved<'_>> {
+        lookup_with(&self.documents, path, &|parent, name| {
+            let key = index_key(name, self.normalized);
+            let ids = self.children.get(&(parent, key));
+            let ids = ids.map(Vec::as_slice).unwrap_or_default();
+            ids.iter().filter_map(|id| self.documents.get(id)).collect()
+        })
+    }
+
+    /// As `Documents::get_by_path`.
+    pub fn get_by_path(&self, path: &Path) -> Option<&Document> {
+        self.resolve(path.to_string_lossy()).ok().flatten()
+    }
+
+    /// As `Documents::path_of`.
+    pub fn path_of(&self, uuid: &Uuid) -> Option<String> {
+        let mut paths = self.paths.borrow_mut();
+        // Walk up until reaching the root, a path already known, or
+        // something that means there's no path.
+        let mut chain = vec![];
+        let mut current = *uuid;
+        let mut base = loop {
+            if let Some(known) = paths.get(&current) {
+                break known.clone();
+            }
+            let doc = match self.documents.get(&current) {
+                Some(doc) => doc,
+                None => break None,
+            };
+            chain.push(doc);
+            if chain.len() > self.documents.len() {
+                break None;
+            }
+            match doc.parent {
+                Some(parent) => current = parent,
+                None => break Some(String::new()),
+            }
+        };
+        for doc in chain.into_iter().rev() {
+            base = base.map(|prefix| {
+                let name = join_path(&[&doc.visible_name]);
+                if prefix.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", prefix, name)
+                }
+            });
+            paths.insert(doc.id, base.clone());
+        }
+        base
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use remarkable_cloud_api::Parent;
+
+    use super::*;
+    use crate::testutil::listing;
+
+    fn tree() -> ResolvedTree {
+        ResolvedTree::new(listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+            (3, "Books/Dune", None, "DocumentType"),
+            (4, "Sci-fi", Some(1), "CollectionType"),
+            (5, "Hyperion", Some(4), "DocumentType"),
+            // A cycle, which has no path.
+            (6, "Loop", Some(7), "CollectionType"),
+            (7, "Back", Some(6), "CollectionType"),
+            (8, "Orphan", Some(99), "DocumentType"),
+        ]))
+    }
+
+    // Both ways of looking up must agree on everything.
+    #[test]
+    fn same_as_documents() {
+        let tree = tree();
+        let docs: &Documents = &tree;
+        for id in 1..=9 {
+            let id = Uuid::from_u128(id);
+            assert_eq!(tree.path_of(&id), docs.path_of(&id), "{}", id);
+            // Again, from what's remembered.
+            assert_eq!(tree.path_of(&id), docs.path_of(&id), "{}", id);
+        }
+        for path in &[
+            "Books",
+            "Books/Sci-fi/Hyperion",
+            "Books\\/Dune",
+            "/Books//Sci-fi/",
+            "Books/Emma",
+            "Loop",
+            "",
+        ] {
+            let ids = |r: Result<Option<&Document>>| r.unwrap().map(|d| d.id);
+            assert_eq!(ids(tree.resolve(path)), ids(docs.resolve(path)));
+            let path: CloudPath = path.parse().unwrap();
+            assert_eq!(
+                tree.lookup(&path).map_err(|e| e.to_string()),
+                docs.lookup(&path).map_err(|e| e.to_string())
+            );
+        }
+        assert!(matches!(
+            tree.resolve("Books/Dune"),
+            Err(Error::AmbiguousPath { .. })
+        ));
+        assert_eq!(tree.path_of(&Uuid::from_u128(3)).unwrap(), "Books\\/Dune");
+    }
+
+    #[test]
+    fn normalized() {
+        let docs = listing(&[
+            (1, "Jobs", None, "CollectionType"),
+            (2, "Résumé", Some(1), "DocumentType"),
+            (3, "Re\u{301}sume\u{301} (old)", Some(1), "DocumentType"),
+        ]);
+        let found = |tree: &ResolvedTree, path: &str| {
+            tree.resolve(path).unwrap().map(|d| d.id.as_u128())
+        };
+        let exact = ResolvedTree::with_normalized(docs.clone(), false);
+        assert_eq!(found(&exact, "Jobs/Résumé"), Some(2));
+        assert_eq!(found(&exact, "Jobs/Re\u{301}sume\u{301}"), None);
+        assert_eq!(found(&exact, "jobs/résumé (OLD)"), None);
+        assert!(!exact.same_name("résumé", "Résumé"));
+
+        let normalized = ResolvedTree::with_normalized(docs, true);
+        assert_eq!(found(&normalized, "Jobs/Re\u{301}sume\u{301}"), Some(2));
+        assert_eq!(found(&normalized, "jobs/résumé (OLD)"), Some(3));
+        assert_eq!(found(&normalized, "Jobs/Resume"), None);
+        assert!(normalized.same_name("re\u{301}sume\u{301}", "Résumé"));
+        // Paths are still given as they are in the cloud.
+        assert_eq!(
+            normalized.path_of(&Uuid::from_u128(2)).unwrap(),
+            "Jobs/Résumé"
+        );
+    }
+
+    // Times the lookups of planning a push of 1000 local files into a
+    // listing of 5000 documents, with and without the index. Run with
+    // `cargo test --release -- --ignored --nocapture push_plan`.
+    #[test]
+    #[ignore]
+    fn push_plan_benchmark() {
+        let mut entries = vec![];
+        for folder in 0..50u128 {
+            let id = 1 + folder * 101;
+            entries.push((
+                id,
+                format!("Folder {}", folder),
+                None,
+                "CollectionType",
+            ));
+            for doc in 1..=100 {
+                let name = format!("Doc {}", doc);
+                entries.push((id + doc, name, Some(id), "DocumentType"));
+            }
+        }
+        let entries: Vec<(u128, &str, Option<u128>, &str)> = entries
+            .iter()
+            .map(|(id, name, parent, t)| (*id, name.as_str(), *parent, *t))
+            .collect();
+        let tree = ResolvedTree::new(listing(&entries));
+        assert_eq!(tree.len(), 5050);
+        let local: Vec<String> = (0..1000)
+            .map(|i| format!("Folder {}/Doc {}", i % 50, 1 + i / 10))
+            .collect();
+
+        // For each file: find its folder, find it, and name where it goes.
+        fn plan<F, P>(local: &[String], resolve: F, path_of: P) -> usize
+        where
+            F: Fn(&str) -> Option<Uuid>,
+            P: Fn(&Uuid) -> Option<String>,
+        {
+            let mut found = 0;
+            for file in local {
+                let folder = &file[..file.rfind('/').unwrap()];
+                let folder = resolve(folder).unwrap();
+                if let Some(id) = resolve(file) {
+                    found += path_of(&id).is_some() as usize;
+                }
+                path_of(&folder).unwrap();
+            }
+            found
+        }
+
+        let docs: &Documents = &tree;
+        let start = Instant::now();
+        let plain = plan(
+            &local,
+            |p| docs.resolve(p).unwrap().map(|d| d.id),
+            |id| docs.path_of(id),
+        );
+        let plain_time = start.elapsed();
+        let start = Instant::now();
+        let indexed = plan(
+            &local,
+            |p| tree.resolve(p).unwrap().map(|d| d.id),
+            |id| tree.path_of(id),
+        );
+        let indexed_time = start.elapsed();
+        assert_eq!(plain, indexed);
+        println!(
+            "push plan of {} files over {} documents: {:?} scanning, {:?} indexed",
+            local.len(),
+            tree.len(),
+            plain_time,
+            indexed_time
+        );
+
+        // `ls --paths`: the path of everything, in order.
+        let fresh = ResolvedTree::new(docs.clone());
+        let start = Instant::now();
+        let mut plain: Vec<String> = docs
+            .descendants(Parent::Root)
+            .filter_map(|(_, d)| docs.path_of(&d.id))
+            .collect();
+        plain.sort();
+        let plain_time = start.elapsed();
+        let start = Instant::now();
+        let options = crate::render::ListOptions {
+            max_depth: None,
+            paths: true,
+            ..Default::default()
+        };
+        let indexed = crate::render::tree(&fresh, Parent::Root, options);
+        let indexed_time = start.elapsed();
+        assert_eq!(plain, indexed);
+        println!(
+            "flat listing of {} paths: {:?} scanning, {:?} indexed",
+            indexed.len(),
+            plain_time,
+            indexed_time
+        );
+    }
+}
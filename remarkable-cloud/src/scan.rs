@@ -0,0 +1,107 @@
+//! Finding the files to push in a local directory.
+//!
+//! A `.remarkableignore` file in the directory, or in any below it, lists
+//! gitignore-style patterns for files and folders to leave out, relative to
+//! where it is. Hidden files are always left out.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::push;
+use crate::CliResult;
+
+pub const IGNORE_FILE: &str = ".remarkableignore";
+
+/// What's in a directory to push, with paths relative to it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Scan {
+    /// The folders to create, each after the one holding it. Only those
+    /// with something to push inside them are included.
+    pub folders: Vec<PathBuf>,
+    /// The PDFs and EPUBs, in path order.
+    pub files: Vec<PathBuf>,
+    /// Files which aren't ignored but can't be pushed either.
+    pub unsupported: Vec<PathBuf>,
+}
+
+/// Walks `root`, skipping anything its ignore files match.
+pub fn scan(root: &Path) -> CliResult<Scan> {
+    let walk = ignore::WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(true)
+        .parents(false)
+        .add_custom_ignore_filename(IGNORE_FILE)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    let mut scan = Scan::default();
+    let mut folders = BTreeSet::new();
+    for entry in walk {
+        let entry = entry?;
+        if entry.file_type().is_some_and(|t| t.is_dir()) {
+            continue;
+        }
+        let path = entry.path().strip_prefix(root)?.to_path_buf();
+        if push::file_type(&path).is_none() {
+            scan.unsupported.push(path);
+            continue;
+        }
+        let inside = path.ancestors().skip(1);
+        folders.extend(
+            inside
+                .filter(|f| !f.as_os_str().is_empty())
+                .map(Path::to_path_buf),
+        );
+        scan.files.push(path);
+    }
+    scan.folders = folders.into_iter().collect();
+    scan.folders.sort_by_key(|f| f.components().count());
+    Ok(scan)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn nested_ignores() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = [
+            (IGNORE_FILE, "drafts/\n*.draft.pdf\n"),
+            ("a.pdf", ""),
+            ("a.draft.pdf", ""),
+            ("notes.txt", ""),
+            (".hidden.pdf", ""),
+            ("drafts/b.pdf", ""),
+            ("Books/Emma.epub", ""),
+            ("Books/Old/Dune.pdf", ""),
+            ("Books/Old/Dune.draft.pdf", ""),
+            ("Books/Old/cover.jpg", ""),
+            ("Books/Old/.remarkableignore", "!*.draft.pdf\n*.jpg\n"),
+            ("Empty/ignored.draft.pdf", ""),
+        ];
+        for (name, contents) in &files {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        let paths = |names: &[&str]| -> Vec<PathBuf> {
+            names.iter().map(PathBuf::from).collect()
+        };
+        assert_eq!(
+            scan(dir.path()).unwrap(),
+            Scan {
+                folders: paths(&["Books", "Books/Old"]),
+                files: paths(&[
+                    "Books/Emma.epub",
+                    "Books/Old/Dune.draft.pdf",
+                    "Books/Old/Dune.pdf",
+                    "a.pdf",
+                ]),
+                unsupported: paths(&["notes.txt"]),
+            }
+        );
+    }
+}
@@ -0,0 +1,502 @@
+//! `serve`: a small HTTP API onto a profile, so that other programs on the
+//! machine can list documents and have them pulled or pushed without
+//! credentials of their own.
+//!
+//! Every request needs the token kept in `serve-token` in the config
+//! directory, as `Authorization: Bearer <token>`. Pulls and pushes are jobs,
+//! carried out one at a time in the order they were asked for, each holding
+//! the profile's lock while it runs; `GET /jobs/:id` says how they went.
+//! `GET /` lists the routes.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use remarkable_cloud_api::{CancellationToken, Client, Document};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use zip::ZipArchive;
+
+use crate::columns::{self, Column};
+use crate::commands::{
+    self, Capture, ListingOptions, Output, PullOptions, PushOptions,
+};
+use crate::lock::{LockMode, ProfileLock};
+use crate::mutations::MutationLog;
+use crate::summary::TransferReport;
+use crate::{destination, msg, push, CliResult};
+
+/// The file in the config directory holding the token requests need.
+pub const TOKEN_FILE: &str = "serve-token";
+
+/// Where `serve` listens unless told otherwise.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:7345";
+
+pub struct Route {
+    pub method: &'static str,
+    pub path: &'static str,
+    pub description: &'static str,
+}
+
+/// What `serve` answers, as `GET /` lists it.
+pub const ROUTES: &[Route] = &[
+    Route {
+        method: "GET",
+        path: "/",
+        description: "These routes",
+    },
+    Route {
+        method: "GET",
+        path: "/documents",
+        description: "Every document and folder, as objects with the \
+                      fields of ls --json",
+    },
+    Route {
+        method: "GET",
+        path: "/documents/:id/payload",
+        description: "The PDF or EPUB of a document",
+    },
+    Route {
+        method: "POST",
+        path: "/pull",
+        description: "Queues a pull of {\"paths\": [...], \"ids\": [...], \
+                      \"dir\": \"...\", \"recursive\": false}, answering \
+                      {\"id\": ...}",
+    },
+    Route {
+        method: "POST",
+        path: "/push",
+        description: "Queues a push of {\"files\": [...], \"to\": \"...\"}, \
+                      answering {\"id\": ...}",
+    },
+    Route {
+        method: "GET",
+        path: "/jobs/:id",
+        description: "How a pull or push is going: its \"state\" is queued, \
+                      running, done or failed",
+    },
+];
+
+/// A pull asked for with `POST /pull`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PullJob {
+    #[serde(default)]
+    pub paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub ids: Vec<Uuid>,
+    /// Where the files are written, which the server has to be able to
+    /// write to.
+    pub dir: PathBuf,
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+/// A push asked for with `POST /push`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PushJob {
+    pub files: Vec<PathBuf>,
+    /// The folder to push into, or the root.
+    pub to: Option<String>,
+}
+
+#[derive(Debug)]
+enum Job {
+    Pull(PullJob),
+    Push(PushJob),
+}
+
+/// How a job is going, as `GET /jobs/:id` gives it.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done {
+        notes: Vec<String>,
+        warnings: Vec<String>,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+pub struct ServeOptions {
+    pub listen: SocketAddr,
+    pub token: String,
+    pub listing: ListingOptions,
+    pub journal: push::Journal,
+    pub mutations: MutationLog,
+    pub lock: ProfileLock,
+}
+
+// What the request handlers share.
+struct Shared {
+    client: Arc<Client>,
+    token: String,
+    jobs: Mutex<HashMap<Uuid, JobState>>,
+    queue: mpsc::UnboundedSender<(Uuid, Job)>,
+}
+
+/// Reads the token in `path`, first writing a new one there, readable only
+/// by its owner, if there's none.
+pub fn token(path: &Path) -> io::Result<String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    match options.open(path) {
+        Ok(mut file) => {
+            let token = format!(
+                "{}{}",
+                Uuid::new_v4().to_simple(),
+                Uuid::new_v4().to_simple()
+            );
+            writeln!(file, "{}", token)?;
+            Ok(token)
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            let mut token = String::new();
+            std::fs::File::open(path)?.read_to_string(&mut token)?;
+            Ok(token.trim().to_string())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Serves until `cancellation` is cancelled, telling `out` where it listens
+/// first. Jobs run here rather than on the handlers' tasks.
+pub async fn serve(
+    client: Client,
+    options: ServeOptions,
+    cancellation: &CancellationToken,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let (queue, jobs) = mpsc::unbounded_channel();
+    let shared = Arc::new(Shared {
+        client: Arc::new(client),
+        token: options.token.clone(),
+        jobs: Mutex::new(HashMap::new()),
+        queue,
+    });
+    let service_shared = shared.clone();
+    let make_service = make_service_fn(move |_| {
+        let shared = service_shared.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(shared.clone(), req)
+            }))
+        }
+    });
+    let server = hyper::Server::try_bind(&options.listen)?.serve(make_service);
+    let address = server.local_addr().to_string();
+    out.line(&msg!(SERVE_LISTENING, address = address));
+    let server = server.with_graceful_shutdown(cancellation.cancelled());
+    let worker = work(&shared, jobs, &options);
+    futures_util::pin_mut!(server, worker);
+    if let futures_util::future::Either::Left((result, _)) =
+        futures_util::future::select(server, worker).await
+    {
+        result?;
+    }
+    Ok(())
+}
+
+// Carries out the jobs queued, one at a time.
+async fn work(
+    shared: &Shared,
+    mut jobs: mpsc::UnboundedReceiver<(Uuid, Job)>,
+    options: &ServeOptions,
+) {
+    while let Some((id, job)) = jobs.recv().await {
+        shared.set(id, JobState::Running);
+        let mut out = Capture::new(TransferReport::new());
+        let state = match run_job(&shared.client, job, options, &mut out).await
+        {
+            Ok(()) => JobState::Done {
+                notes: out.notes,
+                warnings: out.warnings,
+            },
+            Err(e) => JobState::Failed {
+                error: e.to_string(),
+            },
+        };
+        shared.set(id, state);
+    }
+}
+
+async fn run_job(
+    client: &Client,
+    job: Job,
+    options: &ServeOptions,
+    out: &mut Capture<TransferReport>,
+) -> CliResult<()> {
+    match job {
+        Job::Pull(pull) => {
+            let _held =
+                options.lock.acquire(LockMode::Shared, None, out).await?;
+            let documents =
+                commands::list_documents(client, &options.listing, out).await?;
+            let pull = PullOptions {
+                paths: pull.paths,
+                ids: pull.ids,
+                recursive: pull.recursive,
+                dir: pull.dir,
+                ..Default::default()
+            };
+            commands::pull(client, &documents, &pull, out).await
+        }
+        Job::Push(push) => {
+            let _held =
+                options.lock.acquire(LockMode::Exclusive, None, out).await?;
+            let documents =
+                commands::list_documents(client, &options.listing, out).await?;
+            let parent = match &push.to {
+                Some(to) => destination(&documents, &to.parse()?)?.folder(),
+                None => None,
+            };
+            let push = PushOptions {
+                files: push.files,
+                parent,
+                recursive: false,
+                on_conflict: None,
+                memory: push::MemoryBudget::default(),
+                optimize: None,
+                verify: None,
+            };
+            commands::push(
+                client,
+                &options.journal,
+                &options.mutations,
+                &documents,
+                &push,
+                out,
+            )
+            .await
+        }
+    }
+}
+
+impl Shared {
+    fn set(&self, id: Uuid, state: JobState) {
+        self.jobs.lock().unwrap().insert(id, state);
+    }
+
+    fn submit(&self, job: Job) -> Response<Body> {
+        let id = Uuid::new_v4();
+        self.set(id, JobState::Queued);
+        match self.queue.send((id, job)) {
+            Ok(()) => {
+                json(StatusCode::ACCEPTED, &serde_json::json!({ "id": id }))
+            }
+            Err(_) => error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &msg!(SERVE_SHUTTING_DOWN),
+            ),
+        }
+    }
+
+    // Whether `req` has the token, compared by hash so that how long the
+    // comparison takes gives nothing away.
+    fn authorized(&self, req: &Request<Body>) -> bool {
+        let given = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        Sha256::digest(given.as_bytes())
+            == Sha256::digest(self.token.as_bytes())
+    }
+}
+
+async fn handle(
+    shared: Arc<Shared>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if !shared.authorized(&req) {
+        return Ok(error(
+            StatusCode::UNAUTHORIZED,
+            &msg!(SERVE_UNAUTHORIZED, file = TOKEN_FILE),
+        ));
+    }
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let response = match (&method, segments.as_slice()) {
+        (&Method::GET, [""]) => {
+            let routes: Vec<serde_json::Value> = ROUTES
+                .iter()
+                .map(|r| {
+                    serde_json::json!({
+                        "method": r.method,
+                        "path": r.path,
+                        "description": r.description,
+                    })
+                })
+                .collect();
+            json(StatusCode::OK, &routes)
+        }
+        (&Method::GET, ["documents"]) => documents(&shared.client).await,
+        (&Method::GET, ["documents", id, "payload"]) => match id.parse() {
+            Ok(id) => payload(&shared.client, &id).await,
+            Err(_) => error(StatusCode::NOT_FOUND, &msg!(SERVE_NO_DOCUMENT)),
+        },
+        (&Method::POST, ["pull"]) => match body(req).await {
+            Ok(job) => shared.submit(Job::Pull(job)),
+            Err(response) => response,
+        },
+        (&Method::POST, ["push"]) => match body(req).await {
+            Ok(job) => shared.submit(Job::Push(job)),
+            Err(response) => response,
+        },
+        (&Method::GET, ["jobs", id]) => {
+            let state = id
+                .parse()
+                .ok()
+                .and_then(|id| shared.jobs.lock().unwrap().get(&id).cloned());
+            match state {
+                Some(state) => json(StatusCode::OK, &state),
+                None => error(StatusCode::NOT_FOUND, &msg!(SERVE_NO_JOB)),
+            }
+        }
+        _ => error(StatusCode::NOT_FOUND, &msg!(SERVE_NO_ROUTE)),
+    };
+    Ok(response)
+}
+
+async fn documents(client: &Client) -> Response<Body> {
+    let docs = match client.get_documents().await {
+        Ok(docs) => docs,
+        Err(e) => return error(StatusCode::BAD_GATEWAY, &e.to_string()),
+    };
+    let listing: Vec<serde_json::Value> = docs
+        .iter()
+        .map(|doc| {
+            let path = docs.path_of(&doc.id).unwrap_or_default();
+            serde_json::Value::Object(columns::json(&Column::ALL, &path, doc))
+        })
+        .collect();
+    json(StatusCode::OK, &listing)
+}
+
+async fn payload(client: &Client, id: &Uuid) -> Response<Body> {
+    let doc = match client.get_document_by_id(id).await {
+        Ok(doc) => doc,
+        Err(remarkable_cloud_api::Error::EmptyResult) => {
+            return error(StatusCode::NOT_FOUND, &msg!(SERVE_NO_DOCUMENT))
+        }
+        Err(e) => return error(StatusCode::BAD_GATEWAY, &e.to_string()),
+    };
+    match payload_of(client, &doc).await {
+        Ok(Some((content_type, data))) => {
+            let mut response = Response::new(Body::from(data));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, content_type.parse().unwrap());
+            response
+        }
+        Ok(None) => error(StatusCode::NOT_FOUND, &msg!(SERVE_NO_PAYLOAD)),
+        Err(e) => error(StatusCode::BAD_GATEWAY, &e.to_string()),
+    }
+}
+
+// The PDF or EPUB in `doc`'s archive, with its content type.
+async fn payload_of(
+    client: &Client,
+    doc: &Document,
+) -> CliResult<Option<(&'static str, Vec<u8>)>> {
+    let blob = client.download_blob(doc).await?;
+    let mut zip = ZipArchive::new(io::Cursor::new(blob))?;
+    let kinds = [
+        (".epub", "application/epub+zip"),
+        (".pdf", "application/pdf"),
+    ];
+    for (ext, content_type) in &kinds {
+        let name = zip.file_names().find(|n| n.ends_with(ext));
+        if let Some(name) = name.map(str::to_string) {
+            let mut data = vec![];
+            zip.by_name(&name)?.read_to_end(&mut data)?;
+            return Ok(Some((content_type, data)));
+        }
+    }
+    Ok(None)
+}
+
+// The JSON body of `req`, or the response saying what's wrong with it.
+async fn body<T: serde::de::DeserializeOwned>(
+    req: Request<Body>,
+) -> Result<T, Response<Body>> {
+    let data = hyper::body::to_bytes(req.into_body())
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))?;
+    serde_json::from_slice(&data)
+        .map_err(|e| error(StatusCode::BAD_REQUEST, &e.to_string()))
+}
+
+fn json<T: Serialize + ?Sized>(
+    status: StatusCode,
+    value: &T,
+) -> Response<Body> {
+    let mut response =
+        Response::new(Body::from(serde_json::to_vec(value).unwrap()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Body> {
+    json(status, &serde_json::json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TOKEN_FILE);
+        let first = token(&path).unwrap();
+        assert_eq!(first.len(), 64);
+        assert_eq!(token(&path).unwrap(), first);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn jobs() {
+        let pull: PullJob =
+            serde_json::from_str(r#"{"paths": ["Books"], "dir": "/tmp"}"#)
+                .unwrap();
+        assert_eq!(pull.paths, vec![PathBuf::from("Books")]);
+        assert!(!pull.recursive);
+        assert!(serde_json::from_str::<PullJob>(r#"{"paths": []}"#).is_err());
+        assert!(serde_json::from_str::<PushJob>(
+            r#"{"files": [], "folder": "Books"}"#
+        )
+        .is_err());
+        assert_eq!(
+            serde_json::to_value(JobState::Failed {
+                error: "no".to_string()
+            })
+            .unwrap(),
+            serde_json::json!({"state": "failed", "error": "no"})
+        );
+    }
+}
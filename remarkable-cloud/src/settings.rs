@@ -0,0 +1,140 @@
+//! Settings kept in `settings.json` in the config directory, for what
+//! should hold whatever the command line says.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use remarkable_cloud_api::{
+    TreeLimits, DEFAULT_MAX_CHILDREN, DEFAULT_MAX_DEPTH, DEFAULT_MAX_NAME_LEN,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::layout::Layout;
+use crate::mappings::PushSettings;
+use crate::msg;
+
+#[derive(Deserialize, Serialize, Default, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct Settings {
+    /// Never change anything in the cloud, as with `--read-only`. The flag
+    /// can't turn this off.
+    pub read_only: bool,
+    /// Names longer than this are warned about on upload and rename, 250
+    /// if not set.
+    pub max_name_length: Option<usize>,
+    /// Cut names longer than `max_name_length` down to it rather than
+    /// only warning.
+    pub truncate_names: bool,
+    /// Anything `push` or `restore` would put inside more folders than
+    /// this is warned about, and `fsck` reports what already is; 10 if not
+    /// set.
+    pub max_folder_depth: Option<usize>,
+    /// The same for folders holding more items than this, 2000 if not set.
+    pub max_folder_items: Option<usize>,
+    /// Where `push` puts files when it isn't given `--to`.
+    pub push: PushSettings,
+    /// The key `watch --webhook` signs what it sends with, which receivers
+    /// check it against.
+    pub webhook_secret: Option<String>,
+    /// How `sync pull` lays out a directory it syncs for the first time,
+    /// mirror if not set. Those synced before keep theirs unless pulled
+    /// with `--relayout`.
+    pub sync_layout: Option<Layout>,
+}
+
+impl Settings {
+    /// Reads the settings at `path`, or the defaults if there's no file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Settings::default())
+            }
+            Err(e) => {
+                return Err(msg!(
+                    FILE_UNREADABLE,
+                    path = format!("{:?}", path),
+                    error = e.to_string(),
+                ))
+            }
+        };
+        serde_json::from_slice(&data).map_err(|e| {
+            msg!(
+                FILE_UNPARSABLE,
+                path = format!("{:?}", path),
+                error = e.to_string(),
+            )
+        })
+    }
+
+    /// The settings as `setup` first writes them: every one there is, set
+    /// to what leaving it out means, so the file shows what can be changed.
+    pub fn starter() -> Self {
+        Settings {
+            max_name_length: Some(DEFAULT_MAX_NAME_LEN),
+            max_folder_depth: Some(DEFAULT_MAX_DEPTH),
+            max_folder_items: Some(DEFAULT_MAX_CHILDREN),
+            ..Settings::default()
+        }
+    }
+
+    /// The limits the folder tree is checked against.
+    pub fn tree_limits(&self) -> TreeLimits {
+        TreeLimits {
+            max_depth: self.max_folder_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+            max_children: self.max_folder_items.unwrap_or(DEFAULT_MAX_CHILDREN),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        assert_eq!(Settings::load(&path).unwrap(), Settings::default());
+
+        fs::write(&path, r#"{"read_only": true}"#).unwrap();
+        assert!(Settings::load(&path).unwrap().read_only);
+
+        fs::write(&path, "{}").unwrap();
+        assert!(!Settings::load(&path).unwrap().read_only);
+
+        fs::write(&path, r#"{"max_name_length": 100, "truncate_names": true}"#)
+            .unwrap();
+        let settings = Settings::load(&path).unwrap();
+        assert_eq!(settings.max_name_length, Some(100));
+        assert!(settings.truncate_names);
+        assert_eq!(settings.tree_limits(), TreeLimits::default());
+
+        fs::write(&path, r#"{"max_folder_depth": 4}"#).unwrap();
+        let limits = Settings::load(&path).unwrap().tree_limits();
+        assert_eq!(limits.max_depth, 4);
+        assert_eq!(limits.max_children, DEFAULT_MAX_CHILDREN);
+
+        fs::write(&path, r#"{"push": {"mappings": {"~/papers": "/Papers"}}}"#)
+            .unwrap();
+        let push = Settings::load(&path).unwrap().push;
+        assert_eq!(push.mappings["~/papers"], "/Papers");
+        assert_eq!(push.default_folder, None);
+
+        fs::write(&path, r#"{"sync_layout": "by-date"}"#).unwrap();
+        let settings = Settings::load(&path).unwrap();
+        assert_eq!(settings.sync_layout, Some(Layout::ByDate));
+
+        fs::write(&path, r#"{"read_only": "yes"}"#).unwrap();
+        assert!(Settings::load(&path).is_err());
+
+        // The starter settings read back as they were written, and mean
+        // the same as no settings at all.
+        let starter = Settings::starter();
+        fs::write(&path, serde_json::to_vec(&starter).unwrap()).unwrap();
+        let settings = Settings::load(&path).unwrap();
+        assert_eq!(settings, starter);
+        assert_eq!(settings.tree_limits(), TreeLimits::default());
+    }
+}
@@ -0,0 +1,315 @@
+//! `setup`, which takes someone from nothing to a client that works:
+//! registering this computer with their account, checking the documents can
+//! be listed, then writing starter settings and shell completions.
+//!
+//! Each step is skipped when it's already done, or when the answer is no,
+//! so running `setup` again is safe. With `--defaults` every question takes
+//! its default answer, for scripted installs.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use remarkable_cloud_api::Client;
+
+use crate::msg;
+use crate::settings::Settings;
+use crate::CliResult;
+
+/// Where to get the one-time code `setup` registers with.
+pub const CODE_URL: &str = "https://my.remarkable.com/device/desktop/connect";
+
+/// Asks `setup`'s questions on `input`, or with `defaults`, answers them
+/// itself, saying what it answered.
+pub struct Prompt<'a> {
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    defaults: bool,
+}
+
+impl<'a> Prompt<'a> {
+    pub fn new(
+        input: &'a mut dyn BufRead,
+        output: &'a mut dyn Write,
+        defaults: bool,
+    ) -> Self {
+        Prompt {
+            input,
+            output,
+            defaults,
+        }
+    }
+
+    pub fn say(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", line)
+    }
+
+    /// Asks a question answered yes or no. No answer means `default`.
+    pub fn yes(&mut self, question: &str, default: bool) -> io::Result<bool> {
+        let hint = if default { "[Y/n]" } else { "[y/N]" };
+        let answer = self.answer(&format!("{} {}", question, hint))?;
+        Ok(match answer.to_lowercase().as_str() {
+            "" => default,
+            "y" | "yes" => true,
+            _ => false,
+        })
+    }
+
+    /// Asks for a line of text, `None` if left blank.
+    pub fn line(&mut self, question: &str) -> io::Result<Option<String>> {
+        let answer = self.answer(question)?;
+        Ok(Some(answer).filter(|a| !a.is_empty()))
+    }
+
+    fn answer(&mut self, question: &str) -> io::Result<String> {
+        write!(self.output, "{} ", question)?;
+        if self.defaults {
+            writeln!(self.output)?;
+            return Ok(String::new());
+        }
+        self.output.flush()?;
+        let mut answer = String::new();
+        self.input.read_line(&mut answer)?;
+        Ok(answer.trim().to_string())
+    }
+}
+
+/// Registers this computer through `client` with the one-time `code`,
+/// against `endpoint` if given.
+pub async fn register(
+    prompt: &mut Prompt<'_>,
+    client: &mut Client,
+    code: &str,
+    endpoint: Option<&str>,
+) -> CliResult<()> {
+    if let Some(endpoint) = endpoint {
+        client.state().set_endpoint(endpoint.to_string());
+    }
+    if let Err(e) = client.register(code).await {
+        return Err(msg!(SETUP_NOT_REGISTERED, error = e.to_string()).into());
+    }
+    prompt.say(&msg!(SETUP_REGISTERED))?;
+    Ok(())
+}
+
+/// Lists the documents through `client`, to show signing in worked.
+pub async fn signed_in(
+    prompt: &mut Prompt<'_>,
+    client: &Client,
+) -> CliResult<()> {
+    let documents = client.get_documents().await?;
+    prompt.say(&msg!(SETUP_SIGNED_IN, count = documents.len()))?;
+    Ok(())
+}
+
+/// The one-time code to register with: `given`, or if not yet
+/// `registered`, one asked for, or `None` to leave registering be.
+pub fn code(
+    prompt: &mut Prompt,
+    registered: bool,
+    given: Option<&str>,
+) -> io::Result<Option<String>> {
+    if let Some(code) = given {
+        return Ok(Some(code.to_string()));
+    }
+    if registered {
+        prompt.say(&msg!(SETUP_ALREADY_REGISTERED))?;
+        return Ok(None);
+    }
+    prompt.say(&msg!(SETUP_GET_CODE, url = CODE_URL))?;
+    let code = prompt.line(&msg!(SETUP_ASK_CODE))?;
+    if code.is_none() {
+        prompt.say(&msg!(SETUP_CODE_SKIPPED))?;
+    }
+    Ok(code)
+}
+
+/// Says where the credentials are kept, there being no keyring to offer to
+/// keep them in instead.
+pub fn credentials(prompt: &mut Prompt, state_path: &Path) -> io::Result<()> {
+    prompt.say(&msg!(
+        SETUP_CREDENTIALS_FILE,
+        path = state_path.display().to_string()
+    ))
+}
+
+/// Writes `Settings::starter` to `path`, unless there are settings there
+/// already or the answer is no.
+pub fn write_settings(prompt: &mut Prompt, path: &Path) -> CliResult<()> {
+    if path.exists() {
+        let path = path.display().to_string();
+        prompt.say(&msg!(SETUP_SETTINGS_KEPT, path = path))?;
+        return Ok(());
+    }
+    let shown = path.display().to_string();
+    let question = msg!(SETUP_ASK_SETTINGS, path = &shown);
+    if !prompt.yes(&question, true)? {
+        prompt.say(&msg!(SETUP_SETTINGS_SKIPPED))?;
+        return Ok(());
+    }
+    let mut data = serde_json::to_vec_pretty(&Settings::starter())?;
+    data.push(b'\n');
+    fs::write(path, data)?;
+    prompt.say(&msg!(SETUP_SETTINGS_WRITTEN, path = shown))?;
+    Ok(())
+}
+
+/// The shell `shell`, a path as in `$SHELL`, names, and where completions
+/// for it are installed, if it's one clap writes them for.
+pub fn completions_path(
+    shell: &str,
+    home_dir: &Path,
+    data_dir: &Path,
+    config_dir: &Path,
+) -> Option<(clap::Shell, PathBuf)> {
+    match Path::new(shell).file_name()?.to_str()? {
+        "bash" => Some((
+            clap::Shell::Bash,
+            data_dir.join("bash-completion/completions/remarkable-cloud"),
+        )),
+        "zsh" => {
+            Some((clap::Shell::Zsh, home_dir.join(".zfunc/_remarkable-cloud")))
+        }
+        "fish" => Some((
+            clap::Shell::Fish,
+            config_dir.join("fish/completions/remarkable-cloud.fish"),
+        )),
+        _ => None,
+    }
+}
+
+/// Installs the completions `generate` writes for the shell `$SHELL` names,
+/// or says there are none for it.
+pub fn completions<F>(prompt: &mut Prompt, generate: F) -> CliResult<()>
+where
+    F: FnOnce(clap::Shell) -> Vec<u8>,
+{
+    let shell = std::env::var("SHELL").unwrap_or_default();
+    let place = directories::BaseDirs::new().and_then(|dirs| {
+        completions_path(
+            &shell,
+            dirs.home_dir(),
+            dirs.data_dir(),
+            dirs.config_dir(),
+        )
+    });
+    match place {
+        Some((shell, path)) => {
+            install_completions(prompt, &path, &generate(shell))
+        }
+        None => Ok(prompt.say(&msg!(
+            SETUP_NO_COMPLETIONS,
+            shell = format!("{:?}", shell)
+        ))?),
+    }
+}
+
+/// Writes `completions` to `path` if the answer is yes, unless they're
+/// there already.
+pub fn install_completions(
+    prompt: &mut Prompt,
+    path: &Path,
+    completions: &[u8],
+) -> CliResult<()> {
+    if fs::read(path).ok().as_deref() == Some(completions) {
+        let path = path.display().to_string();
+        prompt.say(&msg!(SETUP_COMPLETIONS_INSTALLED, path = path))?;
+        return Ok(());
+    }
+    let shown = path.display().to_string();
+    let question = msg!(SETUP_ASK_COMPLETIONS, path = shown);
+    if !prompt.yes(&question, false)? {
+        prompt.say(&msg!(SETUP_COMPLETIONS_SKIPPED))?;
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, completions)?;
+    prompt.say(&msg!(SETUP_COMPLETIONS_WRITTEN))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompted(
+        script: &str,
+        defaults: bool,
+        f: impl FnOnce(&mut Prompt),
+    ) -> String {
+        let mut input = script.as_bytes();
+        let mut output = vec![];
+        f(&mut Prompt::new(&mut input, &mut output, defaults));
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn answers() {
+        let output = prompted("\nno\n  y \n", false, |p| {
+            assert!(p.yes("Go?", true).unwrap());
+            assert!(!p.yes("Go?", true).unwrap());
+            assert!(p.yes("Go?", false).unwrap());
+            // Nothing left to read, as when stdin is closed.
+            assert!(!p.yes("Go?", false).unwrap());
+        });
+        assert!(output.starts_with("Go? [Y/n] Go? [Y/n] Go? [y/N]"));
+
+        let output = prompted("", true, |p| {
+            assert!(p.yes("Go?", true).unwrap());
+            assert_eq!(p.line("Code:").unwrap(), None);
+        });
+        assert_eq!(output, "Go? [Y/n] \nCode: \n");
+
+        prompted(" abc \n", false, |p| {
+            assert_eq!(code(p, false, None).unwrap().unwrap(), "abc");
+            assert_eq!(code(p, true, None).unwrap(), None);
+            assert_eq!(code(p, true, Some("x")).unwrap().unwrap(), "x");
+        });
+    }
+
+    #[test]
+    fn once_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = dir.path().join("settings.json");
+        let completions = dir.path().join("completions/remarkable-cloud");
+
+        prompted("n\n", false, |p| {
+            write_settings(p, &settings).unwrap();
+            install_completions(p, &completions, b"complete").unwrap();
+        });
+        assert!(!settings.exists());
+        assert!(!completions.exists());
+
+        prompted("\ny\n", false, |p| {
+            write_settings(p, &settings).unwrap();
+            install_completions(p, &completions, b"complete").unwrap();
+        });
+        assert_eq!(Settings::load(&settings).unwrap(), Settings::starter());
+        assert_eq!(fs::read(&completions).unwrap(), b"complete");
+
+        // Done already, so nothing is asked.
+        fs::write(&settings, "{}").unwrap();
+        let output = prompted("", false, |p| {
+            write_settings(p, &settings).unwrap();
+            install_completions(p, &completions, b"complete").unwrap();
+        });
+        assert!(!output.contains('?'), "{}", output);
+        assert_eq!(fs::read(&settings).unwrap(), b"{}");
+    }
+
+    #[test]
+    fn shells() {
+        let (home, data, config) =
+            (Path::new("/h"), Path::new("/d"), Path::new("/c"));
+        let (shell, path) =
+            completions_path("/usr/bin/zsh", home, data, config).unwrap();
+        assert!(matches!(shell, clap::Shell::Zsh));
+        assert_eq!(path, Path::new("/h/.zfunc/_remarkable-cloud"));
+        let (_, path) = completions_path("bash", home, data, config).unwrap();
+        assert!(path.starts_with(data));
+        assert!(completions_path("/bin/tcsh", home, data, config).is_none());
+        assert!(completions_path("", home, data, config).is_none());
+    }
+}
@@ -0,0 +1,226 @@
+//! The orders `ls` and `find` list documents in, for `--sort`.
+//!
+//! Natural order, the default, compares the numbers in names by their
+//! value. Locale order sorts as the user's language does, in builds with
+//! the `locale-sort` feature. Code point order compares names byte by
+//! byte, as listings always were, and is the one to rely on in scripts:
+//! it's the same on every machine and in every version, and is what JSON
+//! output always uses.
+
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use remarkable_cloud_api::natural_cmp;
+
+use crate::msg;
+
+/// What `--sort` takes.
+pub const SORT_VALUES: &[&str] = &["natural", "locale", "codepoint"];
+
+/// Whether this build can sort by the user's locale.
+pub const LOCALE_BUILT: bool = cfg!(feature = "locale-sort");
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Numbers by their value, so "Meeting 2" comes before "Meeting 10".
+    #[default]
+    Natural,
+    /// As the language `LC_ALL`, `LC_COLLATE` or `LANG` names sorts.
+    Locale,
+    /// Byte by byte.
+    Codepoint,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "natural" => Ok(SortOrder::Natural),
+            "locale" if LOCALE_BUILT => Ok(SortOrder::Locale),
+            "locale" => Err(msg!(SORT_LOCALE_NOT_BUILT)),
+            "codepoint" => Ok(SortOrder::Codepoint),
+            _ => Err(msg!(SORT_UNKNOWN, order = format!("{:?}", s))),
+        }
+    }
+}
+
+/// Compares names and paths in a `SortOrder`. Names which only differ in
+/// ways the order ignores are put in code point order, so distinct names
+/// are never equal.
+pub struct Collation {
+    order: SortOrder,
+    #[cfg(feature = "locale-sort")]
+    collator: Option<icu_collator::Collator>,
+}
+
+impl Collation {
+    pub fn new(order: SortOrder) -> Self {
+        Collation {
+            order,
+            #[cfg(feature = "locale-sort")]
+            collator: match order {
+                SortOrder::Locale => Some(collator(&locale_from_env())),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn names(&self, a: &str, b: &str) -> Ordering {
+        match self.order {
+            SortOrder::Natural => natural_cmp(a, b),
+            #[cfg(feature = "locale-sort")]
+            SortOrder::Locale => match &self.collator {
+                Some(collator) => collator.compare(a, b).then_with(|| a.cmp(b)),
+                None => a.cmp(b),
+            },
+            _ => a.cmp(b),
+        }
+    }
+
+    /// Compares paths a folder at a time, so everything in a folder comes
+    /// right after it, as in the tree. In code point order they're
+    /// compared whole, as they always were.
+    pub fn paths(&self, a: &str, b: &str) -> Ordering {
+        if self.order == SortOrder::Codepoint {
+            return a.cmp(b);
+        }
+        let mut a_parts = a.split('/');
+        let mut b_parts = b.split('/');
+        loop {
+            match (a_parts.next(), b_parts.next()) {
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(x), Some(y)) => match self.names(x, y) {
+                    Ordering::Equal => continue,
+                    unequal => return unequal,
+                },
+            }
+        }
+    }
+
+    /// Sorts `found` by the paths it's paired with.
+    pub fn sort_paths<T>(&self, found: &mut [(String, T)]) {
+        found.sort_by(|a, b| self.paths(&a.0, &b.0));
+    }
+}
+
+// The language the environment asks for, as a BCP 47 tag such as "de-DE":
+// "de_DE.UTF-8" becomes "de-DE", and "C" and "POSIX" none in particular.
+#[cfg_attr(not(feature = "locale-sort"), allow(dead_code))]
+fn locale_tag(value: &str) -> String {
+    let tag = value.split(['.', '@']).next().unwrap_or("");
+    match tag {
+        "" | "C" | "POSIX" => "und".to_string(),
+        tag => tag.replace('_', "-"),
+    }
+}
+
+#[cfg(feature = "locale-sort")]
+fn locale_from_env() -> String {
+    ["LC_ALL", "LC_COLLATE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map_or_else(|| "und".to_string(), |value| locale_tag(&value))
+}
+
+// A collator for `tag`, or the root locale's if there's none for it.
+#[cfg(feature = "locale-sort")]
+fn collator(tag: &str) -> icu_collator::Collator {
+    let root = icu_locid::Locale::UND;
+    let locale = icu_locid::Locale::try_from_bytes(tag.as_bytes())
+        .unwrap_or_else(|_| root.clone());
+    let options = icu_collator::CollatorOptions::new();
+
+    icu_collator::Collator::try_new(&(&locale).into(), options)
+        .or_else(|_| icu_collator::Collator::try_new(&(&root).into(), options))
+        .expect("the root collation is built in")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(order: SortOrder, paths: &[&str]) -> Vec<String> {
+        let mut found: Vec<(String, ())> =
+            paths.iter().map(|p| (p.to_string(), ())).collect();
+        Collation::new(order).sort_paths(&mut found);
+        found.into_iter().map(|(p, _)| p).collect()
+    }
+
+    #[test]
+    fn paths() {
+        let paths = [
+            "Meeting 10",
+            "Meeting 2",
+            "Books 2/Emma",
+            "Books/Dune 10",
+            "Books/Dune 9",
+            "Books",
+        ];
+        assert_eq!(
+            sorted(SortOrder::Natural, &paths),
+            [
+                "Books",
+                "Books/Dune 9",
+                "Books/Dune 10",
+                "Books 2/Emma",
+                "Meeting 2",
+                "Meeting 10"
+            ]
+        );
+        assert_eq!(
+            sorted(SortOrder::Codepoint, &paths),
+            [
+                "Books",
+                "Books 2/Emma",
+                "Books/Dune 10",
+                "Books/Dune 9",
+                "Meeting 10",
+                "Meeting 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!("natural".parse(), Ok(SortOrder::Natural));
+        assert_eq!("codepoint".parse(), Ok(SortOrder::Codepoint));
+        assert_eq!("locale".parse::<SortOrder>().is_ok(), LOCALE_BUILT);
+        assert!("random".parse::<SortOrder>().is_err());
+        assert_eq!(locale_tag("de_DE.UTF-8"), "de-DE");
+        assert_eq!(locale_tag("sv_SE@euro"), "sv-SE");
+        assert_eq!(locale_tag("C.UTF-8"), "und");
+        assert_eq!(locale_tag("POSIX"), "und");
+    }
+
+    #[cfg(feature = "locale-sort")]
+    #[test]
+    fn locales() {
+        let by = |tag: &str, names: &[&str]| -> Vec<String> {
+            let collation = Collation {
+                order: SortOrder::Locale,
+                collator: Some(collator(tag)),
+            };
+            let mut names: Vec<&str> = names.to_vec();
+            names.sort_by(|a, b| collation.names(a, b));
+            names.into_iter().map(String::from).collect()
+        };
+        let names = ["zebra", "Äpfel", "apple", "Zürich", "Ångström"];
+        assert_eq!(
+            by("en", &names),
+            ["Ångström", "Äpfel", "apple", "zebra", "Zürich"]
+        );
+        // Swedish sorts Å and Ä after Z.
+        assert_eq!(
+            by("sv-SE", &names),
+            ["apple", "zebra", "Zürich", "Ångström", "Äpfel"]
+        );
+        // An unknown tag falls back to the root collation.
+        assert_eq!(by("not a locale", &names), by("und", &names));
+        // Equal to the collator, but still in a fixed order.
+        assert_eq!(by("en", &["a", "a"]), ["a", "a"]);
+    }
+}
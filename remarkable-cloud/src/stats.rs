@@ -0,0 +1,220 @@
+//! How much is drawn in documents, for `stats --ink`: the totals of each
+//! with a row of one character per page showing how much is on it, or the
+//! numbers of every page as JSON.
+
+use futures_util::StreamExt;
+use remarkable_cloud_api::{Client, CloudPath, Document, Parent};
+use remarkable_data_formats::stats::PageStats;
+use uuid::Uuid;
+
+use crate::cache;
+use crate::commands::Output;
+use crate::progress::Progress;
+use crate::resolved::ResolvedTree;
+use crate::{locate, msg, CliResult, Location, DETAILS_CONCURRENCY};
+
+// From least to most ink on a page, relative to the page with the most.
+const LEVELS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// A page with nothing on it, and one that couldn't be read.
+const BLANK: char = ' ';
+const UNREADABLE: char = '?';
+
+/// One character per page for how much ink is on it, relative to the page
+/// with the most.
+pub fn sparkline(pages: &[Option<PageStats>]) -> String {
+    let most = pages
+        .iter()
+        .flatten()
+        .map(|p| p.ink_length)
+        .fold(0.0, f64::max);
+    pages
+        .iter()
+        .map(|page| match page {
+            None => UNREADABLE,
+            Some(p) if p.ink_length <= 0.0 => BLANK,
+            Some(p) => {
+                let level = (p.ink_length / most * LEVELS.len() as f64).ceil();
+                LEVELS[(level as usize).clamp(1, LEVELS.len()) - 1]
+            }
+        })
+        .collect()
+}
+
+/// The lines shown for the document at `path` with `pages`: its totals,
+/// the tools used, the sparkline, and which pages couldn't be read, which
+/// are left out of the totals.
+pub fn report(path: &str, pages: &[Option<PageStats>]) -> Vec<String> {
+    let total = PageStats::total(pages.iter().flatten());
+    let mut lines = vec![msg!(
+        STATS_REPORT,
+        path = path,
+        pages = pages.len(),
+        strokes = total.strokes,
+        ink = format!("{:.0}", total.ink_length),
+        layers = total.layers_used,
+    )];
+    if !total.pens.is_empty() {
+        let pens: Vec<String> = total
+            .pens
+            .iter()
+            .map(|(pen, count)| format!("{} {}", pen, count))
+            .collect();
+        lines.push(format!("  {}", pens.join(", ")));
+    }
+    if !pages.is_empty() {
+        lines.push(format!("  |{}|", sparkline(pages)));
+    }
+    for (index, page) in pages.iter().enumerate() {
+        if page.is_none() {
+            let unread = msg!(STATS_PAGE_UNREADABLE, page = index + 1);
+            lines.push(format!("  {}", unread));
+        }
+    }
+    lines
+}
+
+/// The numbers of every page of `doc`, at `path`, and their totals.
+pub fn json(
+    path: &str,
+    doc: &Document,
+    pages: &[Option<PageStats>],
+) -> serde_json::Value {
+    let per_page: Vec<serde_json::Value> = pages
+        .iter()
+        .enumerate()
+        .map(|(index, page)| match page {
+            Some(stats) => {
+                let mut value = serde_json::to_value(stats).unwrap();
+                value["page"] = (index + 1).into();
+                value
+            }
+            None => serde_json::json!({
+                "page": index + 1,
+                "unreadable": true,
+            }),
+        })
+        .collect();
+    serde_json::json!({
+        "path": path,
+        "id": doc.id,
+        "pages": per_page,
+        "total": PageStats::total(pages.iter().flatten()),
+        "unreadable_pages": pages.iter().filter(|p| p.is_none()).count(),
+    })
+}
+
+/// Reads the documents at `paths`, and those in the folders there, and
+/// reports how much is drawn in each to `out`, as JSON with `json`. Fails if
+/// any couldn't be downloaded.
+pub async fn stats(
+    client: &Client,
+    documents: &ResolvedTree,
+    paths: &[CloudPath],
+    json: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut found = vec![];
+    for path in paths {
+        match locate(documents, path)? {
+            Location::Document(d) if d.is_folder() => found.extend(
+                documents
+                    .descendants(Parent::Folder(d.id))
+                    .map(|(_, d)| d)
+                    .filter(|d| !d.is_folder()),
+            ),
+            Location::Document(d) => found.push(d),
+            Location::Root | Location::Trash => {
+                out.line(&msg!(NOT_A_DOCUMENT, path = path.to_string()))
+            }
+            Location::Missing(e) => out.line(&e.to_string()),
+        }
+    }
+    let ids: Vec<Uuid> = found.iter().map(|d| d.id).collect();
+    let mut progress = Progress::new(msg!(PROGRESS_READ), ids.len());
+    let mut read = client.ink_stats_bulk(&ids, DETAILS_CONCURRENCY);
+    let mut unread: usize = 0;
+    for d in found {
+        let path = documents.path_of(&d.id).unwrap_or_default();
+        let result = read.next().await.expect("a result for each id");
+        progress.tick();
+        match result {
+            Ok(pages) => {
+                progress.clear();
+                if json {
+                    out.line(
+                        &cache::annotate(
+                            self::json(&path, d, &pages),
+                            documents.cache_age(),
+                        )
+                        .to_string(),
+                    );
+                } else {
+                    for line in report(&path, &pages) {
+                        out.line(&line);
+                    }
+                }
+            }
+            Err(e) => {
+                unread += 1;
+                let warning =
+                    msg!(DOWNLOAD_FAILED, path = &path, error = e.to_string());
+                progress.warn(out, &warning)
+            }
+        }
+    }
+    progress.clear();
+    if unread > 0 {
+        return Err(
+            msg!(STATS_UNREAD, failed = unread, count = ids.len()).into()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inked(ink_length: f64) -> Option<PageStats> {
+        let mut pens = std::collections::BTreeMap::new();
+        pens.insert("ballpoint".to_string(), 2);
+        Some(PageStats {
+            strokes: 2,
+            ink_length,
+            layers_used: 1,
+            pens,
+        })
+    }
+
+    #[test]
+    fn sparklines() {
+        let pages = [
+            inked(100.0),
+            Some(PageStats::default()),
+            None,
+            inked(800.0),
+            inked(1.0),
+        ];
+        assert_eq!(sparkline(&pages), "▁ ?█▁");
+        assert_eq!(sparkline(&[]), "");
+        assert_eq!(sparkline(&[None]), "?");
+    }
+
+    #[test]
+    fn reports() {
+        let pages = [inked(100.0), None, inked(50.0)];
+        assert_eq!(
+            report("Journal", &pages),
+            [
+                "Journal: 3 pages, 4 strokes, 150 px of ink, 1 layers",
+                "  ballpoint 4",
+                "  |█?▄|",
+                "  page 2 couldn't be read",
+            ]
+        );
+        assert_eq!(
+            report("Empty", &[]),
+            ["Empty: 0 pages, 0 strokes, 0 px of ink, 0 layers"]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! What `auth status` prints about the client's setup.
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{
+    Capabilities, Client, ClientDiagnostics, WireDialect,
+};
+
+use crate::commands::Output;
+use crate::summary::format_bytes;
+use crate::{msg, CliResult};
+
+fn expiry(expires: Option<DateTime<Utc>>, now: DateTime<Utc>) -> String {
+    let expires = match expires {
+        Some(expires) => expires,
+        None => return msg!(STATUS_UNKNOWN),
+    };
+    match (expires - now).to_std() {
+        Ok(left) if !left.is_zero() => {
+            let left = std::time::Duration::from_secs(left.as_secs());
+            msg!(
+                STATUS_EXPIRES_IN,
+                at = expires.to_rfc3339(),
+                left = humantime::format_duration(left).to_string(),
+            )
+        }
+        _ => msg!(STATUS_EXPIRED, at = expires.to_rfc3339()),
+    }
+}
+
+/// A line for each thing worth knowing in `diagnostics`.
+pub fn lines(
+    diagnostics: &ClientDiagnostics,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let or_none = |value: &Option<String>| {
+        value.clone().unwrap_or_else(|| msg!(STATUS_NONE))
+    };
+    let retry = humantime::format_duration(diagnostics.upload_retry_delay);
+    vec![
+        msg!(STATUS_ENDPOINT, endpoint = &diagnostics.endpoint),
+        msg!(STATUS_ACCOUNT, account = or_none(&diagnostics.user_id)),
+        msg!(
+            STATUS_TOKEN_EXPIRES,
+            expires = expiry(diagnostics.user_token_expires, now)
+        ),
+        msg!(
+            STATUS_DEVICE_TOKEN,
+            token = or_none(&diagnostics.device_token_fingerprint)
+        ),
+        msg!(STATUS_READ_ONLY, read_only = yes_no(diagnostics.read_only)),
+        msg!(
+            STATUS_UPLOADS,
+            attempts = diagnostics.upload_attempts,
+            retry = retry.to_string(),
+        ),
+    ]
+}
+
+fn yes_no(b: bool) -> String {
+    if b {
+        msg!(STATUS_YES)
+    } else {
+        msg!(STATUS_NO)
+    }
+}
+
+/// A line for each of `capabilities`, as `auth status` and `doctor` print
+/// them.
+pub fn capability_lines(capabilities: &Capabilities) -> Vec<String> {
+    let dialect = match capabilities.dialect {
+        WireDialect::Official => msg!(STATUS_OFFICIAL),
+        WireDialect::Rmfakecloud => "rmfakecloud".to_string(),
+    };
+    let largest = capabilities
+        .max_blob_size
+        .map_or_else(|| msg!(STATUS_UNKNOWN), format_bytes);
+    vec![
+        msg!(
+            STATUS_PROTOCOL,
+            generation = capabilities.generation.to_string()
+        ),
+        msg!(STATUS_DIALECT, dialect = dialect),
+        msg!(
+            STATUS_NOTIFICATIONS,
+            notifications = yes_no(capabilities.notifications)
+        ),
+        msg!(
+            STATUS_UPLOADS_SUPPORTED,
+            uploads = yes_no(capabilities.uploads)
+        ),
+        msg!(STATUS_LARGEST_BLOB, size = largest),
+    ]
+}
+
+/// Reports how `client` is signed in and what the cloud offers to `out`, as
+/// `auth status` does, and with `check`, how long the cloud takes to answer.
+pub async fn auth(
+    client: &Client,
+    check: bool,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    for line in lines(&client.diagnostics(), Utc::now()) {
+        out.line(&line);
+    }
+    for line in capability_lines(&client.capabilities().await?) {
+        out.line(&line);
+    }
+    if check {
+        let latency = client.ping().await?;
+        let latency =
+            std::time::Duration::from_millis(latency.as_millis() as u64);
+        let latency = humantime::format_duration(latency).to_string();
+        out.line(&msg!(STATUS_REACHED, latency = latency));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use remarkable_cloud_api::Generation;
+
+    use super::*;
+
+    #[test]
+    fn status_lines() {
+        let now = "2024-05-01T12:00:00Z".parse().unwrap();
+        let mut diagnostics = ClientDiagnostics {
+            endpoint: "https://document-storage.example".to_string(),
+            user_token_expires: Some("2024-05-01T13:30:00Z".parse().unwrap()),
+            user_id: Some("auth0|someone".to_string()),
+            device_token_fingerprint: Some("0123456789abcdef".to_string()),
+            read_only: false,
+            upload_attempts: 4,
+            upload_retry_delay: Duration::from_millis(250),
+        };
+        assert_eq!(
+            lines(&diagnostics, now),
+            vec![
+                "Endpoint: https://document-storage.example",
+                "Account: auth0|someone",
+                "Token expires: 2024-05-01T13:30:00+00:00, in 1h 30m",
+                "Device token: 0123456789abcdef",
+                "Read-only: no",
+                "Uploads: 4 attempts, retrying after 250ms",
+            ]
+        );
+
+        diagnostics.user_token_expires = Some(now);
+        diagnostics.user_id = None;
+        let lines = lines(&diagnostics, now);
+        assert_eq!(lines[1], "Account: none");
+        assert!(lines[2].ends_with("(expired)"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn capabilities() {
+        let legacy =
+            Capabilities::of(Generation::Legacy, WireDialect::Official);
+        assert_eq!(
+            capability_lines(&legacy),
+            vec![
+                "Protocol: legacy",
+                "Dialect: official",
+                "Notifications: yes",
+                "Uploads supported: yes",
+                "Largest known-good blob: 100.0 MiB",
+            ]
+        );
+        let moved =
+            Capabilities::of(Generation::Sync15, WireDialect::Rmfakecloud);
+        let lines = capability_lines(&moved);
+        assert_eq!(lines[0], "Protocol: sync15");
+        assert_eq!(lines[3], "Uploads supported: no");
+        assert_eq!(lines[4], "Largest known-good blob: unknown");
+    }
+}
@@ -0,0 +1,239 @@
+//! The summary printed at the end of a command which transfers documents.
+//!
+//! [`TransferReport`] tallies the same events as the JSON log. A transfer
+//! which has started but not finished when the summary is taken, because the
+//! command failed or was interrupted, counts as failed: a half-written file
+//! is not a transferred one.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::msg;
+use crate::observer::{Event, Observer};
+
+const INTERRUPTED: &str = "interrupted";
+
+pub struct TransferReport {
+    start: Instant,
+    transferred: usize,
+    bytes: u64,
+    skipped: usize,
+    failed: Vec<(PathBuf, String)>,
+    in_flight: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub transferred: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub skipped: usize,
+    /// The path of each transfer which failed and why.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl Default for TransferReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferReport {
+    pub fn new() -> Self {
+        TransferReport {
+            start: Instant::now(),
+            transferred: 0,
+            bytes: 0,
+            skipped: 0,
+            failed: vec![],
+            in_flight: vec![],
+        }
+    }
+
+    /// Whether nothing has been transferred, skipped or failed, in which
+    /// case there's nothing worth summarizing.
+    pub fn is_empty(&self) -> bool {
+        self.transferred == 0
+            && self.skipped == 0
+            && self.failed.is_empty()
+            && self.in_flight.is_empty()
+    }
+
+    fn finished(&mut self, path: &PathBuf) {
+        if let Some(i) = self.in_flight.iter().position(|p| p == path) {
+            self.in_flight.remove(i);
+        }
+    }
+
+    /// The summary so far, counting transfers still under way as failed.
+    pub fn summary(&self) -> Summary {
+        let mut failed = self.failed.clone();
+        failed.extend(
+            self.in_flight
+                .iter()
+                .map(|p| (p.clone(), INTERRUPTED.to_string())),
+        );
+        Summary {
+            transferred: self.transferred,
+            bytes: self.bytes,
+            elapsed: self.start.elapsed(),
+            skipped: self.skipped,
+            failed,
+        }
+    }
+}
+
+impl Observer for TransferReport {
+    fn observe(&mut self, event: &Event) {
+        match event {
+            Event::Phase(_) => {}
+            Event::Started { path } => self.in_flight.push(path.clone()),
+            Event::Pulled { path, bytes, .. } => {
+                self.finished(path);
+                self.transferred += 1;
+                self.bytes += bytes;
+            }
+            Event::Skipped { path, .. } => {
+                self.finished(path);
+                self.skipped += 1;
+            }
+            Event::Failed {
+                path,
+                category,
+                message,
+                ..
+            } => {
+                self.finished(path);
+                self.failed
+                    .push((path.clone(), format!("{}: {}", category, message)));
+            }
+        }
+    }
+}
+
+/// A byte count in the largest binary unit that keeps it at least 1.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// The lines of the summary, the totals first and then each failure.
+pub fn render(summary: &Summary) -> Vec<String> {
+    let mut totals = msg!(
+        SUMMARY_TRANSFERRED,
+        count = summary.transferred,
+        bytes = format_bytes(summary.bytes),
+        secs = format!("{:.1}", summary.elapsed.as_secs_f64()),
+    );
+    let secs = summary.elapsed.as_secs_f64();
+    if summary.bytes > 0 && secs > 0.0 {
+        let speed = (summary.bytes as f64 / secs) as u64;
+        totals.push_str(&format!(" ({}/s)", format_bytes(speed)));
+    }
+    totals.push_str(&msg!(
+        SUMMARY_SKIPPED_FAILED,
+        skipped = summary.skipped,
+        failed = summary.failed.len(),
+    ));
+    let mut lines = vec![totals];
+    for (path, reason) in &summary.failed {
+        lines.push(format!("  {}: {}", path.display(), reason));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn started(path: &str) -> Event {
+        Event::Started {
+            path: PathBuf::from(path),
+        }
+    }
+
+    #[test]
+    fn tally() {
+        let mut report = TransferReport::new();
+        assert!(report.is_empty());
+        report.observe(&started("Dune"));
+        report.observe(&Event::Pulled {
+            path: PathBuf::from("Dune"),
+            id: Uuid::nil(),
+            output: PathBuf::from("Dune.pdf"),
+            modified: chrono::Utc::now(),
+            bytes: 2048,
+            sha256: String::new(),
+            elapsed: Duration::from_millis(5),
+        });
+        report.observe(&started("Emma"));
+        report.observe(&Event::Skipped {
+            path: PathBuf::from("Emma"),
+            id: None,
+            reason: "not found".to_string(),
+        });
+        report.observe(&started("Notes"));
+        report.observe(&Event::Failed {
+            path: PathBuf::from("Notes"),
+            id: None,
+            category: "http",
+            message: "503".to_string(),
+            elapsed: Duration::from_millis(5),
+        });
+        report.observe(&started("Hyperion"));
+
+        let summary = report.summary();
+        assert_eq!(summary.transferred, 1);
+        assert_eq!(summary.bytes, 2048);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(
+            summary.failed,
+            vec![
+                (PathBuf::from("Notes"), "http: 503".to_string()),
+                (PathBuf::from("Hyperion"), "interrupted".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.0 MiB");
+
+        let summary = Summary {
+            transferred: 2,
+            bytes: 4 * 1024 * 1024,
+            elapsed: Duration::from_secs(2),
+            skipped: 1,
+            failed: vec![(PathBuf::from("Books/Dune"), "interrupted".into())],
+        };
+        assert_eq!(
+            render(&summary),
+            vec![
+                "Transferred 2 files, 4.0 MiB in 2.0s (2.0 MiB/s); 1 skipped, 1 failed",
+                "  Books/Dune: interrupted",
+            ]
+        );
+        let nothing = Summary {
+            transferred: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&nothing),
+            vec!["Transferred 1 file, 0 B in 0.0s; 0 skipped, 0 failed"]
+        );
+    }
+}
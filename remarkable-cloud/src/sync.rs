@@ -0,0 +1,2362 @@
+//! Keeping a local directory in step with a cloud folder, for `sync`.
+//!
+//! A synced directory holds a manifest, [`MANIFEST_NAME`], recording the
+//! cloud folder it's synced with and, for each file, the document it was
+//! last synced with and how both were then. [`Plan::analyze`] sets the
+//! directory and the cloud as they are now against the manifest, to find
+//! what changed on either side since. [`pull`] brings what changed in the
+//! cloud into the directory, where its layout (see [`crate::layout`]) puts
+//! it. While it does, it keeps what it planned in [`PLAN_NAME`], so that one
+//! interrupted can be carried on with `--resume-plan`, planning again only
+//! what's changed since.
+//!
+//! Documents are matched to files by id once synced. Before then, a file and
+//! a document are taken to be the same if the file's path, without its
+//! extension, is the document's path in the folder. Paths are compared by
+//! `name_key`, so they needn't be in the same Unicode form, nor where local
+//! filesystems ignore case (see `naming::case_insensitive`), the same case.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{
+    is_future, name_key, replace_locked, write_atomically, Client, CloudPath,
+    Document, DocumentDetails, Parent, DEFAULT_SKEW_TOLERANCE,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::commands::{self, Output};
+use crate::exporters;
+use crate::layout::{self, Layout, LayoutStrategy};
+use crate::mutations::MutationLog;
+use crate::naming;
+use crate::observer::{Event, Phase};
+use crate::push::{self, Target};
+use crate::resolved::ResolvedTree;
+use crate::{locate, msg, plan as saved_plan, scan, CliResult, Location};
+
+/// The manifest's name in the directory. As a hidden file, it's never
+/// taken for one to sync.
+pub const MANIFEST_NAME: &str = ".remarkable-sync.json";
+
+/// What a directory was last synced with.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The cloud folder, as a path.
+    pub folder: String,
+    /// How `sync pull` lays out documents in the directory. Manifests
+    /// written before it was kept are of mirrored directories.
+    #[serde(default)]
+    pub layout: Layout,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A file and the document it was last synced with, as both were then.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// The file's path in the directory, with `/` between folders.
+    pub path: String,
+    /// The path in Unicode Normalization Form C, which it's matched to
+    /// files by. Missing from manifests written before it was kept.
+    #[serde(default)]
+    pub key: String,
+    pub id: Uuid,
+    pub version: u64,
+    pub modified: DateTime<Utc>,
+    pub size: u64,
+    /// The file's modification time, in seconds since the Unix epoch.
+    pub mtime: i64,
+    pub sha256: String,
+}
+
+impl ManifestEntry {
+    // What the entry's file is matched by, folding case with `fold_case`.
+    fn path_key(&self, fold_case: bool) -> String {
+        match self.key.as_str() {
+            "" => name_key(&self.path, fold_case),
+            key => name_key(key, fold_case),
+        }
+    }
+}
+
+impl Manifest {
+    /// The manifest of the directory `dir`, none if it's never been
+    /// synced.
+    pub fn load(dir: &Path) -> CliResult<Option<Manifest>> {
+        match fs::read(dir.join(MANIFEST_NAME)) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The manifest of the directory `dir`, which must have been synced.
+    pub fn load_synced(dir: &Path) -> CliResult<Manifest> {
+        Self::load(dir)?.ok_or_else(|| {
+            msg!(
+                SYNC_NOT_SYNCED,
+                dir = dir.display().to_string(),
+                manifest = MANIFEST_NAME,
+            )
+            .into()
+        })
+    }
+
+    /// Replaces the manifest of `dir`.
+    pub fn save(&self, dir: &Path) -> CliResult<()> {
+        let data = serde_json::to_vec_pretty(self)?;
+        write_atomically(&dir.join(MANIFEST_NAME), &data)?;
+        Ok(())
+    }
+}
+
+/// A file in the directory as it is now.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalFile {
+    pub path: String,
+    pub size: u64,
+    pub mtime: i64,
+}
+
+/// The files in `dir` a sync would look at, as `push` finds them, with
+/// paths relative to it.
+pub fn local_files(dir: &Path) -> CliResult<Vec<LocalFile>> {
+    let scan = scan::scan(dir)?;
+    let mut files = vec![];
+    for path in scan.files.iter().chain(&scan.unsupported) {
+        let meta = fs::metadata(dir.join(path))?;
+        files.push(LocalFile {
+            path: slashed(path),
+            size: meta.len(),
+            mtime: filetime::FileTime::from_last_modification_time(&meta)
+                .unix_seconds(),
+        });
+    }
+    Ok(files)
+}
+
+// `path` with `/` between its parts, whatever the platform.
+fn slashed(path: &Path) -> String {
+    let parts: Vec<_> = path.iter().map(|p| p.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+/// The documents below `folder`, with their paths in it.
+pub fn cloud_documents(
+    documents: &ResolvedTree,
+    folder: Parent,
+) -> Vec<(String, &Document)> {
+    let mut folders: Vec<&str> = vec![];
+    let mut found = vec![];
+    for (depth, d) in documents.descendants(folder) {
+        folders.truncate(depth);
+        if d.is_folder() {
+            folders.push(&d.visible_name);
+        } else {
+            let mut path = folders.join("/");
+            if !path.is_empty() {
+                path.push('/');
+            }
+            path.push_str(&d.visible_name);
+            found.push((path, d));
+        }
+    }
+    found
+}
+
+/// The SHA-256 of the file at `path`, in hex.
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// The entry recording that the file at `path` in `dir` is in step with
+// `doc`, as both are now.
+fn synced_entry(
+    dir: &Path,
+    path: &str,
+    doc: &Document,
+) -> io::Result<ManifestEntry> {
+    let file = dir.join(path);
+    let meta = fs::metadata(&file)?;
+    Ok(ManifestEntry {
+        path: path.to_string(),
+        key: name_key(path, false),
+        id: doc.id,
+        version: doc.version,
+        modified: doc.modified_client,
+        size: meta.len(),
+        mtime: filetime::FileTime::from_last_modification_time(&meta)
+            .unix_seconds(),
+        sha256: sha256_file(&file)?,
+    })
+}
+
+/// How one side is now, against the manifest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// As when last synced.
+    Unchanged,
+    /// Changed since, or there when it wasn't synced.
+    Changed,
+    /// Not there.
+    Absent,
+}
+
+/// What differs for a file and its document.
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Change {
+    Clean,
+    LocalChanged,
+    CloudChanged,
+    /// Changed on both sides, or there on both without ever being synced.
+    Conflict,
+    LocalOnly,
+    CloudOnly,
+    LocalDeleted,
+    CloudDeleted,
+    /// Synced once but gone from both sides.
+    BothDeleted,
+}
+
+impl Change {
+    /// What differs, given how each side is and whether the pair is in the
+    /// manifest. What isn't in the manifest can't be unchanged, so is
+    /// taken as changed.
+    pub fn classify(tracked: bool, local: Side, cloud: Side) -> Change {
+        use Side::*;
+        if !tracked {
+            return match (local, cloud) {
+                (Absent, Absent) => Change::Clean,
+                (_, Absent) => Change::LocalOnly,
+                (Absent, _) => Change::CloudOnly,
+                _ => Change::Conflict,
+            };
+        }
+        match (local, cloud) {
+            (Unchanged, Unchanged) => Change::Clean,
+            (Changed, Unchanged) => Change::LocalChanged,
+            (Unchanged, Changed) => Change::CloudChanged,
+            (Absent, Unchanged) => Change::LocalDeleted,
+            (Unchanged, Absent) => Change::CloudDeleted,
+            (Absent, Absent) => Change::BothDeleted,
+            (Changed, Changed) | (Absent, Changed) | (Changed, Absent) => {
+                Change::Conflict
+            }
+        }
+    }
+
+    /// The heading `sync status` lists these under.
+    pub fn heading(self) -> String {
+        match self {
+            Change::Clean => msg!(SYNC_UNCHANGED),
+            Change::LocalChanged => msg!(SYNC_CHANGED_HERE),
+            Change::CloudChanged => msg!(SYNC_CHANGED_IN_CLOUD),
+            Change::Conflict => msg!(SYNC_CHANGED_BOTH),
+            Change::LocalOnly => msg!(SYNC_ONLY_HERE),
+            Change::CloudOnly => msg!(SYNC_ONLY_IN_CLOUD),
+            Change::LocalDeleted => msg!(SYNC_DELETED_HERE),
+            Change::CloudDeleted => msg!(SYNC_DELETED_IN_CLOUD),
+            Change::BothDeleted => msg!(SYNC_DELETED_BOTH),
+        }
+    }
+}
+
+/// A file or document, and what differs for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    /// The file's path, or for a document only in the cloud, its path in
+    /// the folder.
+    pub path: String,
+    pub id: Option<Uuid>,
+    pub change: Change,
+    /// How, if it's a conflict.
+    pub conflict: Option<ConflictKind>,
+}
+
+/// What differs between a directory and the cloud, against the manifest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Everything on either side or in the manifest, in path order.
+    pub items: Vec<Item>,
+}
+
+impl Plan {
+    /// Sets `local` and `cloud` against `manifest`, at `now`. `hash` gives the
+    /// SHA-256 of a file by its path. It's only called for files whose
+    /// modification time changed but size didn't, to tell whether what's in
+    /// them changed too. Paths differing only in case are the same with
+    /// `fold_case`.
+    pub fn analyze(
+        manifest: &Manifest,
+        local: &[LocalFile],
+        cloud: &[(String, &Document)],
+        now: DateTime<Utc>,
+        fold_case: bool,
+        hash: &mut dyn FnMut(&str) -> io::Result<String>,
+    ) -> io::Result<Plan> {
+        let key = |path: &str| name_key(path, fold_case);
+        let files: HashMap<String, &LocalFile> =
+            local.iter().map(|f| (key(&f.path), f)).collect();
+        let docs: HashMap<Uuid, &Document> =
+            cloud.iter().map(|(_, d)| (d.id, *d)).collect();
+        let mut items = vec![];
+        let mut seen_files = HashSet::new();
+        let mut seen_docs = HashSet::new();
+        for entry in &manifest.entries {
+            let entry_key = entry.path_key(fold_case);
+            let file = files.get(&entry_key);
+            let local = match file {
+                None => Side::Absent,
+                Some(f) if f.size == entry.size && f.mtime == entry.mtime => {
+                    Side::Unchanged
+                }
+                Some(f) if f.size == entry.size => {
+                    if hash(&f.path)? == entry.sha256 {
+                        Side::Unchanged
+                    } else {
+                        Side::Changed
+                    }
+                }
+                Some(_) => Side::Changed,
+            };
+            let cloud = match docs.get(&entry.id) {
+                None => Side::Absent,
+                Some(d) => cloud_side(entry, d, now),
+            };
+            seen_files.insert(entry_key);
+            seen_docs.insert(entry.id);
+            items.push(Item {
+                path: entry.path.clone(),
+                id: Some(entry.id),
+                change: Change::classify(true, local, cloud),
+                conflict: ConflictKind::classify(true, local, cloud),
+            });
+        }
+        // The rest, by the paths they'd have without extensions.
+        let untracked_docs: HashMap<String, &Document> = cloud
+            .iter()
+            .filter(|(_, d)| !seen_docs.contains(&d.id))
+            .map(|(path, d)| (key(path), *d))
+            .collect();
+        for file in local {
+            if seen_files.contains(&key(&file.path)) {
+                continue;
+            }
+            let doc = untracked_docs.get(&key(stem(&file.path)));
+            if let Some(d) = doc {
+                seen_docs.insert(d.id);
+            }
+            let cloud = doc.map_or(Side::Absent, |_| Side::Changed);
+            items.push(Item {
+                path: file.path.clone(),
+                id: doc.map(|d| d.id),
+                change: Change::classify(false, Side::Changed, cloud),
+                conflict: ConflictKind::classify(false, Side::Changed, cloud),
+            });
+        }
+        for (path, doc) in cloud {
+            if !seen_docs.contains(&doc.id) {
+                items.push(Item {
+                    path: path.clone(),
+                    id: Some(doc.id),
+                    change: Change::CloudOnly,
+                    conflict: None,
+                });
+            }
+        }
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Plan { items })
+    }
+
+    /// Whether nothing differs.
+    pub fn is_clean(&self) -> bool {
+        self.items.iter().all(|i| i.change == Change::Clean)
+    }
+
+    /// How many differ.
+    pub fn differences(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|i| i.change != Change::Clean)
+            .count()
+    }
+}
+
+// How `doc` is now against `entry`. A new version is always a change; a
+// new time modified only if neither time is in the future, as is any a
+// client whose clock is ahead stamps, since then there's no telling which
+// is later.
+fn cloud_side(
+    entry: &ManifestEntry,
+    doc: &Document,
+    now: DateTime<Utc>,
+) -> Side {
+    let future = |t| is_future(t, now, DEFAULT_SKEW_TOLERANCE);
+    if doc.version != entry.version {
+        Side::Changed
+    } else if doc.modified_client == entry.modified
+        || future(doc.modified_client)
+        || future(entry.modified)
+    {
+        Side::Unchanged
+    } else {
+        Side::Changed
+    }
+}
+
+// `path` without the extension of its last part.
+fn stem(path: &str) -> &str {
+    let name = path.rfind('/').map_or(0, |i| i + 1);
+    match path[name..].rfind('.') {
+        Some(dot) if dot > 0 => &path[..name + dot],
+        _ => path,
+    }
+}
+
+impl fmt::Display for Plan {
+    /// A section per kind of change, as `git status` lists them, each with
+    /// how many there are and their paths.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut changes: Vec<Change> = self
+            .items
+            .iter()
+            .map(|i| i.change)
+            .filter(|c| *c != Change::Clean)
+            .collect();
+        changes.sort();
+        changes.dedup();
+        for (n, change) in changes.into_iter().enumerate() {
+            if n > 0 {
+                writeln!(f)?;
+            }
+            let paths: Vec<&str> = self
+                .items
+                .iter()
+                .filter(|i| i.change == change)
+                .map(|i| i.path.as_str())
+                .collect();
+            writeln!(f, "{} ({}):", change.heading(), paths.len())?;
+            for path in paths {
+                writeln!(f, "    {}", path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The folder `dir` is synced with, or `None` if it's gone from the cloud.
+pub fn synced_folder(
+    dir: &Path,
+    manifest: &Manifest,
+    documents: &ResolvedTree,
+) -> CliResult<Option<Parent>> {
+    match locate(documents, &manifest.folder.parse()?)? {
+        Location::Root => Ok(Some(Parent::Root)),
+        Location::Document(d) if d.is_folder() => {
+            Ok(Some(Parent::Folder(d.id)))
+        }
+        Location::Document(_) | Location::Trash => Err(msg!(
+            SYNC_NOT_A_FOLDER,
+            dir = dir.display().to_string(),
+            folder = manifest.folder.to_string(),
+        )
+        .into()),
+        Location::Missing(_) => Ok(None),
+    }
+}
+
+// The folder `dir` is synced with, failing if it's gone from the cloud.
+fn present_folder(
+    dir: &Path,
+    manifest: &Manifest,
+    documents: &ResolvedTree,
+) -> CliResult<Parent> {
+    synced_folder(dir, manifest, documents)?.ok_or_else(|| {
+        msg!(
+            SYNC_FOLDER_GONE,
+            dir = dir.display().to_string(),
+            folder = manifest.folder.to_string(),
+        )
+        .into()
+    })
+}
+
+// The documents below the folder `dir` is synced with, none if it's gone
+// from the cloud.
+fn folder_documents<'a>(
+    dir: &Path,
+    manifest: &Manifest,
+    documents: &'a ResolvedTree,
+) -> CliResult<Vec<(String, &'a Document)>> {
+    Ok(match synced_folder(dir, manifest, documents)? {
+        Some(folder) => cloud_documents(documents, folder),
+        // Gone from the cloud, and everything in it with it.
+        None => vec![],
+    })
+}
+
+/// Sets `dir` and what's in the cloud below the manifest's folder against
+/// its manifest, at `now`.
+pub fn status(
+    dir: &Path,
+    manifest: &Manifest,
+    documents: &ResolvedTree,
+    now: DateTime<Utc>,
+) -> CliResult<Plan> {
+    let cloud = folder_documents(dir, manifest, documents)?;
+    compare(dir, manifest, &cloud, now)
+}
+
+// Sets `dir` and `cloud`, the documents below its folder, against
+// `manifest`, at `now`.
+fn compare(
+    dir: &Path,
+    manifest: &Manifest,
+    cloud: &[(String, &Document)],
+    now: DateTime<Utc>,
+) -> CliResult<Plan> {
+    let local = local_files(dir)?;
+    let root = PathBuf::from(dir);
+    let mut hash = |path: &str| sha256_file(&root.join(path));
+    let fold_case = naming::case_insensitive();
+    Ok(Plan::analyze(
+        manifest, &local, cloud, now, fold_case, &mut hash,
+    )?)
+}
+
+// Plans again for the documents `ids` alone, as `compare` does, for when
+// they've moved on since a plan was saved. `cloud` is the documents below
+// the folder now. Files of other documents are left out, as are any items
+// which aren't of `ids`.
+fn replan(
+    dir: &Path,
+    manifest: &Manifest,
+    cloud: &[(String, &Document)],
+    ids: &HashSet<Uuid>,
+) -> CliResult<Vec<Item>> {
+    let fold_case = naming::case_insensitive();
+    let (theirs, others): (Vec<ManifestEntry>, Vec<ManifestEntry>) = manifest
+        .entries
+        .iter()
+        .cloned()
+        .partition(|e| ids.contains(&e.id));
+    let others: HashSet<String> =
+        others.iter().map(|e| e.path_key(fold_case)).collect();
+    let scoped = Manifest {
+        entries: theirs,
+        ..manifest.clone()
+    };
+    let local: Vec<LocalFile> = local_files(dir)?
+        .into_iter()
+        .filter(|f| !others.contains(&name_key(&f.path, fold_case)))
+        .collect();
+    let cloud: Vec<(String, &Document)> = cloud
+        .iter()
+        .filter(|(_, d)| ids.contains(&d.id))
+        .cloned()
+        .collect();
+    let root = PathBuf::from(dir);
+    let mut hash = |path: &str| sha256_file(&root.join(path));
+    let plan = Plan::analyze(
+        &scoped,
+        &local,
+        &cloud,
+        Utc::now(),
+        fold_case,
+        &mut hash,
+    )?;
+    Ok(plan
+        .items
+        .into_iter()
+        .filter(|i| i.id.is_some_and(|id| ids.contains(&id)))
+        .collect())
+}
+
+// Whether the file at `path` in `dir` is the size and age `manifest` last
+// recorded, or it has no record of it.
+fn as_recorded(dir: &Path, manifest: &Manifest, path: &str) -> bool {
+    let entry = match manifest.entries.iter().find(|e| e.path == path) {
+        Some(entry) => entry,
+        None => return true,
+    };
+    match fs::metadata(dir.join(path)) {
+        Ok(meta) => {
+            let mtime = filetime::FileTime::from_last_modification_time(&meta);
+            meta.len() == entry.size && mtime.unix_seconds() == entry.mtime
+        }
+        Err(_) => false,
+    }
+}
+
+/// The journal `sync resolve` keeps in the directory while it works, so a
+/// resolve which is interrupted can be carried on. It's saved as a
+/// [`crate::plan`], of [`Resolution`]s. Hidden, like the manifest.
+pub const RESOLVE_JOURNAL_NAME: &str = ".remarkable-resolve.json";
+
+/// What `--strategy` takes.
+pub const STRATEGY_VALUES: &[&str] = &["keep-cloud", "keep-local", "keep-both"];
+
+/// How a file and its document conflict.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictKind {
+    /// Both changed since they were last synced.
+    BothChanged,
+    /// Both there without ever having been synced.
+    BothAdded,
+    /// The file changed, and the document is gone.
+    ChangedHereDeletedThere,
+    /// The file is gone, and the document changed.
+    DeletedHereChangedThere,
+}
+
+impl ConflictKind {
+    /// How the pair conflicts, given what `Change::classify` is given, or
+    /// `None` if it doesn't.
+    pub fn classify(
+        tracked: bool,
+        local: Side,
+        cloud: Side,
+    ) -> Option<ConflictKind> {
+        if Change::classify(tracked, local, cloud) != Change::Conflict {
+            return None;
+        }
+        Some(match (tracked, local, cloud) {
+            (false, _, _) => ConflictKind::BothAdded,
+            (true, _, Side::Absent) => ConflictKind::ChangedHereDeletedThere,
+            (true, Side::Absent, _) => ConflictKind::DeletedHereChangedThere,
+            (true, _, _) => ConflictKind::BothChanged,
+        })
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            ConflictKind::BothChanged => msg!(CONFLICT_BOTH_CHANGED),
+            ConflictKind::BothAdded => msg!(CONFLICT_BOTH_ADDED),
+            ConflictKind::ChangedHereDeletedThere => {
+                msg!(CONFLICT_CHANGED_HERE_DELETED_THERE)
+            }
+            ConflictKind::DeletedHereChangedThere => {
+                msg!(CONFLICT_DELETED_HERE_CHANGED_THERE)
+            }
+        }
+    }
+}
+
+/// Which side of a conflict wins.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    KeepCloud,
+    KeepLocal,
+    /// Keep the cloud's at the file's path, and the file as a copy beside
+    /// it, uploaded as a document of its own.
+    KeepBoth,
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep-cloud" => Ok(Strategy::KeepCloud),
+            "keep-local" => Ok(Strategy::KeepLocal),
+            "keep-both" => Ok(Strategy::KeepBoth),
+            _ => Err(msg!(STRATEGY_UNKNOWN, strategy = format!("{:?}", s))),
+        }
+    }
+}
+
+/// One thing done to settle a conflict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Writes the document's PDF or EPUB over the file.
+    Pull,
+    /// Uploads the file as a new version of the document.
+    Update,
+    /// Uploads the file as a new document.
+    PushNew,
+    /// Copies the file aside and uploads the copy as a new document.
+    PushCopy,
+    RemoveFile,
+    TrashDocument,
+}
+
+impl Strategy {
+    /// What settles a conflict of `kind` this way. Where one side is gone,
+    /// keeping both keeps the one that's left.
+    pub fn steps(self, kind: ConflictKind) -> &'static [Step] {
+        use ConflictKind::*;
+        match (self, kind) {
+            (Strategy::KeepCloud, ChangedHereDeletedThere) => {
+                &[Step::RemoveFile]
+            }
+            (Strategy::KeepCloud, _) => &[Step::Pull],
+            (Strategy::KeepLocal, ChangedHereDeletedThere) => &[Step::PushNew],
+            (Strategy::KeepLocal, DeletedHereChangedThere) => {
+                &[Step::TrashDocument]
+            }
+            (Strategy::KeepLocal, _) => &[Step::Update],
+            (Strategy::KeepBoth, ChangedHereDeletedThere) => &[Step::PushNew],
+            (Strategy::KeepBoth, DeletedHereChangedThere) => &[Step::Pull],
+            (Strategy::KeepBoth, _) => &[Step::PushCopy, Step::Pull],
+        }
+    }
+}
+
+/// Asks on `input` which side of the conflict at `path` to keep. Anything
+/// but one of the choices, or its first letter, leaves it unresolved.
+pub fn ask(
+    path: &str,
+    kind: ConflictKind,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<Option<Strategy>> {
+    let question = msg!(SYNC_ASK, path = path, kind = kind.describe());
+    write!(output, "{} ", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(match answer.as_str() {
+        "c" | "cloud" => Some(Strategy::KeepCloud),
+        "l" | "local" => Some(Strategy::KeepLocal),
+        "b" | "both" => Some(Strategy::KeepBoth),
+        _ => None,
+    })
+}
+
+/// How a conflict is to be settled, as the resolve journal keeps it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub path: String,
+    pub id: Option<Uuid>,
+    pub kind: ConflictKind,
+    pub strategy: Strategy,
+    /// The document's version when this was decided. One above it means
+    /// the file was already uploaded over it.
+    pub version: Option<u64>,
+    /// Where keeping both copies the file to.
+    pub copy: Option<String>,
+    pub done: bool,
+}
+
+impl Resolution {
+    // Whether the document being at `version`, none if it's gone, is as
+    // this was decided on, or as a step of it done before an interruption
+    // leaves it.
+    fn accounts_for(&self, version: Option<u64>) -> bool {
+        let steps = self.strategy.steps(self.kind);
+        version == self.version
+            || (steps.contains(&Step::Update)
+                && version == self.version.map(|v| v + 1))
+            || (steps.contains(&Step::TrashDocument) && version.is_none())
+    }
+}
+
+/// The resolutions `sync resolve` decided on, in the order they're
+/// carried out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResolveJournal {
+    pub resolutions: Vec<Resolution>,
+}
+
+impl ResolveJournal {
+    /// The journal left in `dir` by a resolve which didn't finish, if any.
+    pub fn load(dir: &Path) -> CliResult<Option<ResolveJournal>> {
+        let saved = saved_plan::load(&dir.join(RESOLVE_JOURNAL_NAME))?;
+        Ok(saved.map(|resolutions| ResolveJournal { resolutions }))
+    }
+
+    pub fn save(&self, dir: &Path) -> CliResult<()> {
+        saved_plan::save(&dir.join(RESOLVE_JOURNAL_NAME), &self.resolutions)
+    }
+
+    /// Removes the journal from `dir`, once everything in it is done.
+    pub fn remove(dir: &Path) -> io::Result<()> {
+        saved_plan::remove(&dir.join(RESOLVE_JOURNAL_NAME))
+    }
+
+    // Checks the resolutions left to carry out against `documents`, planning
+    // again those whose documents have moved on since they were decided on.
+    // Those which no longer conflict are left out, and the rest carried out
+    // as decided, for how they conflict now. `cloud` is the documents below
+    // the folder.
+    fn recheck(
+        &mut self,
+        dir: &Path,
+        manifest: &Manifest,
+        documents: &ResolvedTree,
+        cloud: &[(String, &Document)],
+        out: &mut dyn Output,
+    ) -> CliResult<()> {
+        let version = |id: &Uuid| documents.get(id).map(|d| d.version);
+        let moved: HashSet<Uuid> = self
+            .resolutions
+            .iter()
+            .filter(|r| !r.done)
+            .filter_map(|r| r.id.filter(|id| !r.accounts_for(version(id))))
+            .collect();
+        if moved.is_empty() {
+            return Ok(());
+        }
+        let items = replan(dir, manifest, cloud, &moved)?;
+        let copies: Vec<String> = self
+            .resolutions
+            .iter()
+            .filter_map(|r| r.copy.as_deref())
+            .map(|c| stem(c).to_string())
+            .collect();
+        let taken = |name: &str| {
+            cloud.iter().any(|(p, _)| p == name)
+                || copies.contains(&name.into())
+        };
+        let mut resolutions = vec![];
+        for r in self.resolutions.drain(..) {
+            let id = match r.id {
+                Some(id) if moved.contains(&id) => id,
+                _ => {
+                    resolutions.push(r);
+                    continue;
+                }
+            };
+            out.note(&msg!(PLAN_MOVED, path = &r.path));
+            let item = items.iter().find(|i| i.id == Some(id));
+            let kind = match item.and_then(|i| i.conflict) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let copy = match r.strategy.steps(kind) {
+                [Step::PushCopy, ..] => {
+                    r.copy.clone().or_else(|| Some(copy_path(&r.path, &taken)))
+                }
+                _ => None,
+            };
+            resolutions.push(Resolution {
+                kind,
+                version: version(&id),
+                copy,
+                ..r
+            });
+        }
+        self.resolutions = resolutions;
+        self.save(dir)
+    }
+}
+
+/// Where keeping both copies the file at `path`: beside it, with
+/// " (local)" after its name, numbered if `taken` says a path, without its
+/// extension, is in use.
+pub fn copy_path(path: &str, taken: &dyn Fn(&str) -> bool) -> String {
+    let base = stem(path);
+    let ext = &path[base.len()..];
+    (1..)
+        .map(|n| match n {
+            1 => format!("{} (local)", base),
+            n => format!("{} (local {})", base, n),
+        })
+        .find(|name| !taken(name))
+        .map(|name| name + ext)
+        .expect("a free name")
+}
+
+/// What a resolve did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ResolveReport {
+    pub resolved: usize,
+    /// Conflicts left as they were, for want of a strategy.
+    pub skipped: usize,
+    /// Whether it carried on one which was interrupted.
+    pub resumed: bool,
+}
+
+// What settling conflicts needs at hand.
+struct Resolving<'a> {
+    client: &'a Client,
+    uploads: &'a push::Journal,
+    mutations: &'a MutationLog,
+    dir: &'a Path,
+    documents: &'a ResolvedTree,
+    /// The synced folder, `None` for the root.
+    folder: Option<Uuid>,
+    /// The documents below it, by their paths there.
+    cloud: HashMap<String, &'a Document>,
+    /// Folders made below it this run, which `documents` doesn't have.
+    made: HashMap<PathBuf, Uuid>,
+}
+
+impl Resolving<'_> {
+    // Writes the original of the document `id` over the file at `path`.
+    async fn pull(&self, path: &str, id: Option<Uuid>) -> CliResult<()> {
+        let doc = id
+            .and_then(|id| self.documents.get(&id))
+            .ok_or_else(|| msg!(SYNC_DOCUMENT_GONE, path = path))?;
+        let source = commands::fetch_document(self.client, doc).await?;
+        let ext = Path::new(path).extension().unwrap_or_default();
+        let exporter =
+            exporters::find(&ext.to_string_lossy())
+                .filter(|e| e.supports(&source))
+                .ok_or_else(|| {
+                    msg!(
+                        SYNC_NO_FORMAT,
+                        path = path,
+                        format = format!("{:?}", ext),
+                    )
+                })?;
+        let mut files = vec![];
+        exporter.export(&source, &mut files)?;
+        let data = files.pop().map(|f| f.data).unwrap_or_default();
+        replace_locked(&self.dir.join(path), &data)?;
+        Ok(())
+    }
+
+    // Uploads the file at `path` as a new version of `id`, unless it's
+    // past `version` already.
+    async fn update(
+        &self,
+        path: &str,
+        id: Option<Uuid>,
+        version: Option<u64>,
+        out: &mut dyn Output,
+    ) -> CliResult<()> {
+        let doc = id
+            .and_then(|id| self.documents.get(&id))
+            .ok_or_else(|| msg!(SYNC_DOCUMENT_GONE, path = path))?;
+        if version.is_some_and(|v| doc.version > v) {
+            return Ok(());
+        }
+        let target = Target::Update(doc.clone());
+        self.upload(path, &target, out).await?;
+        Ok(())
+    }
+
+    // Uploads the file at `path` as a new document, where its path says,
+    // unless there's one there already. Returns its id.
+    async fn push_new(
+        &mut self,
+        path: &str,
+        out: &mut dyn Output,
+    ) -> CliResult<Uuid> {
+        if let Some(doc) = self.cloud.get(stem(path)) {
+            return Ok(doc.id);
+        }
+        let mut parent = self.folder;
+        if let Some(folders) = Path::new(path).parent() {
+            let chain: Vec<PathBuf> = folders
+                .ancestors()
+                .filter(|f| !f.as_os_str().is_empty())
+                .map(PathBuf::from)
+                .collect();
+            for folder in chain.into_iter().rev() {
+                let id = match self.made.get(&folder) {
+                    Some(id) => *id,
+                    None => {
+                        let name = PathBuf::from(folder.file_name().unwrap());
+                        let (ids, _) = push::make_folders(
+                            self.client,
+                            self.documents,
+                            parent,
+                            std::slice::from_ref(&name),
+                        )
+                        .await?;
+                        ids[&name]
+                    }
+                };
+                self.made.insert(folder, id);
+                parent = Some(id);
+            }
+        }
+        self.upload(path, &Target::new_in(parent), out).await
+    }
+
+    async fn upload(
+        &self,
+        path: &str,
+        target: &Target,
+        out: &mut dyn Output,
+    ) -> CliResult<Uuid> {
+        let upload = push::push(
+            self.client,
+            self.uploads,
+            &self.dir.join(path),
+            target,
+            push::ARCHIVE_MEMORY_LIMIT,
+        )
+        .await?
+        .upload;
+        self.mutations.record_upload(
+            upload.id,
+            &upload.visible_name,
+            upload.version,
+            out,
+        );
+        Ok(upload.id)
+    }
+
+    async fn trash(
+        &self,
+        id: Option<Uuid>,
+        out: &mut dyn Output,
+    ) -> CliResult<()> {
+        // Those in the trash already aren't listed.
+        let doc = match id.and_then(|id| self.documents.get(&id)) {
+            Some(doc) => doc,
+            None => return Ok(()),
+        };
+        let change = self
+            .client
+            .move_document(doc, Parent::Trash, &doc.visible_name)
+            .await?;
+        self.mutations.record_change(&change, None, out);
+        Ok(())
+    }
+
+    // Carries out `resolution`, recording the outcome in `manifest`. Each
+    // step checks whether it was done before, so one interrupted can be
+    // carried out again.
+    async fn settle(
+        &mut self,
+        resolution: &Resolution,
+        manifest: &mut Manifest,
+        out: &mut dyn Output,
+    ) -> CliResult<()> {
+        let path = resolution.path.as_str();
+        let mut id = resolution.id;
+        for step in resolution.strategy.steps(resolution.kind) {
+            match step {
+                Step::Pull => self.pull(path, id).await?,
+                Step::Update => {
+                    self.update(path, id, resolution.version, out).await?
+                }
+                Step::PushNew => id = Some(self.push_new(path, out).await?),
+                Step::PushCopy => {
+                    let copy = resolution.copy.as_deref().unwrap_or_default();
+                    if !self.dir.join(copy).exists() {
+                        fs::copy(self.dir.join(path), self.dir.join(copy))?;
+                    }
+                    let copy_id = self.push_new(copy, out).await?;
+                    self.record(manifest, copy, copy_id).await?;
+                }
+                Step::RemoveFile => {
+                    match fs::remove_file(self.dir.join(path)) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                            return Err(e.into())
+                        }
+                        _ => {}
+                    }
+                }
+                Step::TrashDocument => {
+                    self.trash(id, out).await?;
+                    id = None;
+                }
+            }
+        }
+        match id {
+            Some(id) if self.dir.join(path).exists() => {
+                self.record(manifest, path, id).await?
+            }
+            _ => manifest.entries.retain(|e| e.path != path),
+        }
+        Ok(())
+    }
+
+    // Records in `manifest` that the file at `path` is in step with the
+    // document `id`, as both are now.
+    async fn record(
+        &self,
+        manifest: &mut Manifest,
+        path: &str,
+        id: Uuid,
+    ) -> CliResult<()> {
+        let doc = self.client.get_document_by_id(&id).await?;
+        let entry = synced_entry(self.dir, path, &doc)?;
+        manifest
+            .entries
+            .retain(|e| e.path != entry.path && e.key != entry.key);
+        manifest.entries.push(entry);
+        manifest.entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(())
+    }
+}
+
+/// Settles the conflicts between `dir` and its cloud folder: each as
+/// `strategy` says, or else as `out` is asked. What's decided is journaled
+/// in the directory before anything is done, and if a journal is already
+/// there, what it has left is carried out instead. The manifest is saved
+/// after each conflict is settled.
+#[allow(clippy::too_many_arguments)]
+pub async fn resolve(
+    client: &Client,
+    uploads: &push::Journal,
+    mutations: &MutationLog,
+    dir: &Path,
+    manifest: &mut Manifest,
+    documents: &ResolvedTree,
+    strategy: Option<Strategy>,
+    out: &mut dyn Output,
+) -> CliResult<ResolveReport> {
+    let folder = present_folder(dir, manifest, documents)?;
+    let cloud = cloud_documents(documents, folder);
+    let mut report = ResolveReport::default();
+    let mut journal = match ResolveJournal::load(dir)? {
+        Some(mut journal) => {
+            report.resumed = true;
+            let undone = journal.resolutions.iter().filter(|r| !r.done);
+            out.observe(&Event::Phase(Phase::Resuming {
+                checked: undone.count(),
+            }));
+            journal.recheck(dir, manifest, documents, &cloud, out)?;
+            journal
+        }
+        None => {
+            out.observe(&Event::Phase(Phase::Planning {
+                compared: cloud.len(),
+            }));
+            let plan = compare(dir, manifest, &cloud, Utc::now())?;
+            let taken = |name: &str| {
+                cloud.iter().any(|(p, _)| p == name)
+                    || plan.items.iter().any(|i| stem(&i.path) == name)
+            };
+            let mut journal = ResolveJournal::default();
+            for item in &plan.items {
+                let kind = match item.conflict {
+                    Some(kind) => kind,
+                    None => continue,
+                };
+                let strategy = match strategy {
+                    Some(s) => s,
+                    None => match out.on_sync_conflict(&item.path, kind)? {
+                        Some(s) => s,
+                        None => {
+                            report.skipped += 1;
+                            continue;
+                        }
+                    },
+                };
+                let copy = match strategy.steps(kind) {
+                    [Step::PushCopy, ..] => {
+                        let chosen: Vec<String> = journal
+                            .resolutions
+                            .iter()
+                            .filter_map(|r| r.copy.as_deref())
+                            .map(|c| stem(c).to_string())
+                            .collect();
+                        let taken = |name: &str| {
+                            taken(name) || chosen.contains(&name.into())
+                        };
+                        Some(copy_path(&item.path, &taken))
+                    }
+                    _ => None,
+                };
+                let version = item
+                    .id
+                    .and_then(|id| documents.get(&id))
+                    .map(|d| d.version);
+                journal.resolutions.push(Resolution {
+                    path: item.path.clone(),
+                    id: item.id,
+                    kind,
+                    strategy,
+                    version,
+                    copy,
+                    done: false,
+                });
+            }
+            if journal.resolutions.is_empty() {
+                return Ok(report);
+            }
+            journal.save(dir)?;
+            journal
+        }
+    };
+    let mut resolving = Resolving {
+        client,
+        uploads,
+        mutations,
+        dir,
+        documents,
+        folder: match folder {
+            Parent::Folder(id) => Some(id),
+            _ => None,
+        },
+        cloud: cloud.iter().map(|(p, d)| (p.clone(), *d)).collect(),
+        made: HashMap::new(),
+    };
+    let undone = journal.resolutions.iter().filter(|r| !r.done);
+    out.observe(&Event::Phase(Phase::Transferring {
+        documents: undone.count(),
+    }));
+    for n in 0..journal.resolutions.len() {
+        let resolution = journal.resolutions[n].clone();
+        if resolution.done {
+            continue;
+        }
+        resolving.settle(&resolution, manifest, out).await?;
+        manifest.save(dir)?;
+        journal.resolutions[n].done = true;
+        journal.save(dir)?;
+        report.resolved += 1;
+        let steps = resolution.strategy.steps(resolution.kind);
+        out.note(&match &resolution.copy {
+            Some(copy) => {
+                msg!(SYNC_KEPT_BOTH, path = &resolution.path, copy = copy,)
+            }
+            None if steps == [Step::Pull] || steps == [Step::RemoveFile] => {
+                msg!(SYNC_KEPT_CLOUD, path = &resolution.path)
+            }
+            None => msg!(SYNC_KEPT_LOCAL, path = &resolution.path),
+        });
+    }
+    ResolveJournal::remove(dir)?;
+    Ok(report)
+}
+
+/// Where `sync pull --relayout` moves files to while it works, so that
+/// none is moved onto another not yet moved. Hidden, like the manifest.
+pub const RELAYOUT_DIR_NAME: &str = ".remarkable-relayout";
+
+/// What a pull did.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PullReport {
+    pub pulled: usize,
+    /// Files removed as their documents were deleted in the cloud.
+    pub removed: usize,
+    /// Documents with no PDF or EPUB to pull.
+    pub skipped: usize,
+    /// Files left for `sync resolve`, having changed on both sides.
+    pub conflicts: usize,
+}
+
+// Where `strategy` puts the document `details` describes: its path, then
+// those of its links, each with `ext`. Any `taken` says is in use get the
+// start of the document's id added.
+fn placed(
+    strategy: &dyn LayoutStrategy,
+    details: &DocumentDetails,
+    ext: &str,
+    taken: &dyn Fn(&str) -> bool,
+) -> Vec<String> {
+    let id = details.document.id;
+    let mut paths: Vec<String> = vec![];
+    let wanted = std::iter::once(strategy.local_path(details))
+        .chain(strategy.links(details));
+    for path in wanted {
+        let mut file = path.with_ext(ext);
+        if taken(&file) {
+            file = path.qualified(&id).with_ext(ext);
+        }
+        if !paths.contains(&file) {
+            paths.push(file);
+        }
+    }
+    paths
+}
+
+// Writes `data` to the first of `paths` in `dir`, and hardlinks the rest to
+// it, making the folders they're in.
+fn write_linked(dir: &Path, paths: &[String], data: &[u8]) -> io::Result<()> {
+    for path in paths {
+        if let Some(folder) = dir.join(path).parent() {
+            fs::create_dir_all(folder)?;
+        }
+    }
+    let first = dir.join(&paths[0]);
+    replace_locked(&first, data)?;
+    for path in &paths[1..] {
+        remove_file(&dir.join(path))?;
+        fs::hard_link(&first, dir.join(path))?;
+    }
+    Ok(())
+}
+
+// Removes the file at `path`, if it's there.
+fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Removes the folders the file at `path` in `dir` was in, from the
+// innermost out, as far as they're empty.
+fn remove_empty_folders(dir: &Path, path: &str) {
+    let mut folder = Path::new(path).parent();
+    while let Some(f) = folder.filter(|f| !f.as_os_str().is_empty()) {
+        if fs::remove_dir(dir.join(f)).is_err() {
+            break;
+        }
+        folder = f.parent();
+    }
+}
+
+impl Manifest {
+    // Replaces the entries of the document `id` with `entries`.
+    fn set_entries(&mut self, id: Uuid, entries: Vec<ManifestEntry>) {
+        self.entries.retain(|e| {
+            e.id != id && !entries.iter().any(|n| n.path == e.path)
+        });
+        self.entries.extend(entries);
+        self.entries.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+}
+
+/// The plan `sync pull` keeps in the directory while it works, as a
+/// [`crate::plan`] of [`PlannedPull`]s. Hidden, like the manifest.
+pub const PLAN_NAME: &str = ".remarkable-plan.json";
+
+/// A file or document a pull planned for, as its plan keeps it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PlannedPull {
+    pub path: String,
+    pub id: Option<Uuid>,
+    pub change: Change,
+    /// The document's version when this was planned, none if it was gone.
+    pub version: Option<u64>,
+    pub done: bool,
+}
+
+// What of `items` a pull acts on, at the versions they are at in `cloud`.
+fn planned_pulls(
+    items: Vec<Item>,
+    cloud: &[(String, &Document)],
+) -> Vec<PlannedPull> {
+    let versions: HashMap<Uuid, u64> =
+        cloud.iter().map(|(_, d)| (d.id, d.version)).collect();
+    items
+        .into_iter()
+        .filter(|i| {
+            matches!(
+                i.change,
+                Change::Conflict
+                    | Change::CloudDeleted
+                    | Change::CloudOnly
+                    | Change::CloudChanged
+            )
+        })
+        .map(|i| PlannedPull {
+            version: i.id.and_then(|id| versions.get(&id).copied()),
+            path: i.path,
+            id: i.id,
+            change: i.change,
+            done: false,
+        })
+        .collect()
+}
+
+// Checks what's left to do of the saved plan `planned` against `cloud`, the
+// documents below the folder now, and the files in `dir` against
+// `manifest`. Documents which have moved on since, or whose files have, are
+// planned again, as are those which weren't planned for but have changed
+// since.
+fn recheck(
+    dir: &Path,
+    manifest: &Manifest,
+    cloud: &[(String, &Document)],
+    planned: Vec<PlannedPull>,
+    out: &mut dyn Output,
+) -> CliResult<Vec<PlannedPull>> {
+    let versions: HashMap<Uuid, u64> =
+        cloud.iter().map(|(_, d)| (d.id, d.version)).collect();
+    let moved: HashSet<Uuid> = planned
+        .iter()
+        .filter(|p| !p.done && p.change != Change::Conflict)
+        .filter(|p| {
+            p.id.and_then(|id| versions.get(&id).copied()) != p.version
+                || !as_recorded(dir, manifest, &p.path)
+        })
+        .filter_map(|p| p.id)
+        .collect();
+    let planned_ids: HashSet<Uuid> =
+        planned.iter().filter_map(|p| p.id).collect();
+    let synced = |id: &Uuid, version: u64| {
+        manifest
+            .entries
+            .iter()
+            .any(|e| e.id == *id && e.version == version)
+    };
+    let unplanned: HashSet<Uuid> = cloud
+        .iter()
+        .filter(|(_, d)| !synced(&d.id, d.version))
+        .map(|(_, d)| d.id)
+        .chain(
+            manifest
+                .entries
+                .iter()
+                .filter(|e| !versions.contains_key(&e.id))
+                .map(|e| e.id),
+        )
+        .filter(|id| !planned_ids.contains(id))
+        .collect();
+    if moved.is_empty() && unplanned.is_empty() {
+        return Ok(planned);
+    }
+    let mut kept = vec![];
+    for p in planned {
+        match p.id {
+            Some(id) if moved.contains(&id) => {
+                if !p.done {
+                    out.note(&msg!(PLAN_MOVED, path = &p.path));
+                }
+            }
+            _ => kept.push(p),
+        }
+    }
+    let ids = moved.union(&unplanned).copied().collect();
+    kept.extend(planned_pulls(replan(dir, manifest, cloud, &ids)?, cloud));
+    kept.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(kept)
+}
+
+/// Brings what changed in the cloud since `dir` was last synced into it,
+/// where `manifest.layout` puts it, and removes the files of documents
+/// deleted there. What changed here too is left for `sync resolve`. The
+/// manifest is saved after each document, and so is the plan, in
+/// [`PLAN_NAME`], until the pull is done. With `resume_plan`, the plan an
+/// interrupted pull saved is carried on rather than planning again, but for
+/// what's changed since.
+pub async fn pull(
+    client: &Client,
+    dir: &Path,
+    manifest: &mut Manifest,
+    documents: &ResolvedTree,
+    resume_plan: bool,
+    out: &mut dyn Output,
+) -> CliResult<PullReport> {
+    let folder = present_folder(dir, manifest, documents)?;
+    let cloud = cloud_documents(documents, folder);
+    let plan_path = dir.join(PLAN_NAME);
+    let saved: Option<Vec<PlannedPull>> = if resume_plan {
+        saved_plan::resume(&plan_path, out)?
+    } else {
+        None
+    };
+    let mut planned = match saved {
+        Some(saved) => {
+            let undone = saved.iter().filter(|p| !p.done);
+            out.observe(&Event::Phase(Phase::Resuming {
+                checked: undone.count(),
+            }));
+            recheck(dir, manifest, &cloud, saved, out)?
+        }
+        None => {
+            out.observe(&Event::Phase(Phase::Planning {
+                compared: cloud.len(),
+            }));
+            let plan = compare(dir, manifest, &cloud, Utc::now())?;
+            planned_pulls(plan.items, &cloud)
+        }
+    };
+    saved_plan::save(&plan_path, &planned)?;
+    let fold_case = naming::case_insensitive();
+    let strategy =
+        layout::strategy(manifest.layout, documents, folder, fold_case);
+    // A document is only pulled if none of its files conflict.
+    let conflicted: HashSet<Uuid> = planned
+        .iter()
+        .filter(|p| p.change == Change::Conflict)
+        .filter_map(|p| p.id)
+        .collect();
+    let pulls: HashSet<Uuid> = planned
+        .iter()
+        .filter(|p| !p.done)
+        .filter(|p| {
+            matches!(p.change, Change::CloudOnly | Change::CloudChanged)
+        })
+        .filter_map(|p| p.id)
+        .filter(|id| !conflicted.contains(id))
+        .collect();
+    out.observe(&Event::Phase(Phase::Transferring {
+        documents: pulls.len(),
+    }));
+    let mut report = PullReport::default();
+    for n in 0..planned.len() {
+        let item = planned[n].clone();
+        if item.done {
+            continue;
+        }
+        let id = match (item.change, item.id) {
+            (Change::Conflict, _) => {
+                report.conflicts += 1;
+                continue;
+            }
+            (Change::CloudDeleted, _) => {
+                remove_file(&dir.join(&item.path))?;
+                remove_empty_folders(dir, &item.path);
+                manifest.entries.retain(|e| e.path != item.path);
+                manifest.save(dir)?;
+                planned[n].done = true;
+                saved_plan::save(&plan_path, &planned)?;
+                report.removed += 1;
+                out.note(&msg!(SYNC_REMOVED, path = &item.path));
+                continue;
+            }
+            (_, Some(id)) if pulls.contains(&id) => id,
+            _ => continue,
+        };
+        // Each of the document's files is done with it.
+        for p in planned.iter_mut().filter(|p| p.id == Some(id)) {
+            p.done = true;
+        }
+        let doc = documents
+            .get(&id)
+            .ok_or_else(|| msg!(SYNC_DOCUMENT_GONE, path = &item.path))?;
+        let source = commands::fetch_document(client, doc).await?;
+        let exporter = ["epub", "pdf"]
+            .iter()
+            .filter_map(|name| exporters::find(name))
+            .find(|e| e.supports(&source));
+        let exporter = match exporter {
+            Some(exporter) => exporter,
+            None => {
+                saved_plan::save(&plan_path, &planned)?;
+                report.skipped += 1;
+                out.note(&msg!(SYNC_NOTHING_TO_PULL, path = &item.path));
+                continue;
+            }
+        };
+        let mut files = vec![];
+        exporter.export(&source, &mut files)?;
+        let data = files.pop().map(|f| f.data).unwrap_or_default();
+        let old: Vec<String> = manifest
+            .entries
+            .iter()
+            .filter(|e| e.id == id)
+            .map(|e| e.path.clone())
+            .collect();
+        // Where other documents' files are, or files never synced.
+        let taken = |path: &str| {
+            let key = name_key(path, fold_case);
+            let tracked = manifest
+                .entries
+                .iter()
+                .find(|e| e.path_key(fold_case) == key);
+            match tracked {
+                Some(e) => e.id != id,
+                None => dir.join(path).exists(),
+            }
+        };
+        let paths =
+            placed(&*strategy, &source.details, exporter.name(), &taken);
+        write_linked(dir, &paths, &data)?;
+        for path in old.iter().filter(|p| !paths.contains(p)) {
+            remove_file(&dir.join(path))?;
+            remove_empty_folders(dir, path);
+        }
+        let doc = &source.details.document;
+        let entries = paths
+            .iter()
+            .map(|path| synced_entry(dir, path, doc))
+            .collect::<io::Result<_>>()?;
+        manifest.set_entries(id, entries);
+        manifest.save(dir)?;
+        saved_plan::save(&plan_path, &planned)?;
+        report.pulled += 1;
+        out.note(&msg!(SYNC_PULLED, path = &paths[0]));
+    }
+    saved_plan::remove(&plan_path)?;
+    Ok(report)
+}
+
+/// Moves the files in `dir` to where `to` puts them, and records that it's
+/// laid out so in `manifest`. Files are moved aside, into
+/// [`RELAYOUT_DIR_NAME`], before any is put in its place, and the manifest
+/// is saved after each move, so a relayout which is interrupted is carried
+/// on by running it again. The files of documents gone from the cloud are
+/// left where they are. Returns how many documents' files were moved.
+pub async fn relayout(
+    client: &Client,
+    dir: &Path,
+    manifest: &mut Manifest,
+    documents: &ResolvedTree,
+    to: Layout,
+    out: &mut dyn Output,
+) -> CliResult<usize> {
+    let folder = present_folder(dir, manifest, documents)?;
+    let fold_case = naming::case_insensitive();
+    let strategy = layout::strategy(to, documents, folder, fold_case);
+    let cloud: HashMap<Uuid, &Document> = cloud_documents(documents, folder)
+        .into_iter()
+        .map(|(_, d)| (d.id, d))
+        .collect();
+    let key = |path: &str| name_key(path, fold_case);
+    // The paths given out so far, starting with those of files left where
+    // they are.
+    let mut claimed = HashSet::new();
+    for entry in &manifest.entries {
+        if !cloud.contains_key(&entry.id) {
+            claimed.insert(entry.path_key(fold_case));
+            out.note(&msg!(SYNC_LEFT_IN_PLACE, path = &entry.path));
+        }
+    }
+    let untracked: HashSet<String> = local_files(dir)?
+        .iter()
+        .map(|f| key(&f.path))
+        .filter(|k| {
+            !manifest.entries.iter().any(|e| e.path_key(fold_case) == *k)
+        })
+        .collect();
+    let mut seen = HashSet::new();
+    let mut moves: Vec<(Uuid, Vec<String>)> = vec![];
+    for entry in &manifest.entries {
+        let doc = match cloud.get(&entry.id) {
+            Some(doc) if seen.insert(entry.id) => *doc,
+            _ => continue,
+        };
+        let details = if strategy.needs_content() {
+            client.document_details(&doc.id).await?
+        } else {
+            layout::listed(doc)
+        };
+        let ext = Path::new(&entry.path).extension().unwrap_or_default();
+        let taken = |path: &str| {
+            claimed.contains(&key(path)) || untracked.contains(&key(path))
+        };
+        let paths =
+            placed(&*strategy, &details, &ext.to_string_lossy(), &taken);
+        claimed.extend(paths.iter().map(|p| key(p)));
+        let mut now: Vec<&str> = manifest
+            .entries
+            .iter()
+            .filter(|e| e.id == doc.id)
+            .map(|e| e.path.as_str())
+            .collect();
+        let mut wanted: Vec<&str> = paths.iter().map(|p| p.as_str()).collect();
+        now.sort_unstable();
+        wanted.sort_unstable();
+        if now != wanted {
+            moves.push((doc.id, paths));
+        }
+    }
+    // Each document's files moved aside, as one.
+    let staging = dir.join(RELAYOUT_DIR_NAME);
+    for (id, _) in &moves {
+        let entries: Vec<ManifestEntry> = manifest
+            .entries
+            .iter()
+            .filter(|e| e.id == *id)
+            .cloned()
+            .collect();
+        let mut staged = entries[0].clone();
+        if let Some(kept) = entries.iter().find(|e| dir.join(&e.path).exists())
+        {
+            let ext = Path::new(&kept.path).extension().unwrap_or_default();
+            let path = format!(
+                "{}/{}.{}",
+                RELAYOUT_DIR_NAME,
+                id,
+                ext.to_string_lossy()
+            );
+            fs::create_dir_all(&staging)?;
+            fs::rename(dir.join(&kept.path), dir.join(&path))?;
+            for entry in &entries {
+                remove_file(&dir.join(&entry.path))?;
+                remove_empty_folders(dir, &entry.path);
+            }
+            staged = ManifestEntry {
+                key: name_key(&path, false),
+                path,
+                ..kept.clone()
+            };
+        }
+        manifest.set_entries(*id, vec![staged]);
+        manifest.save(dir)?;
+    }
+    // And put where they go. Those deleted here stay so, recorded at their
+    // new paths.
+    for (id, paths) in &moves {
+        let staged = manifest.entries.iter().find(|e| e.id == *id).cloned();
+        let staged = staged.expect("a staged entry");
+        let from = dir.join(&staged.path);
+        if from.exists() {
+            for path in paths {
+                if let Some(folder) = dir.join(path).parent() {
+                    fs::create_dir_all(folder)?;
+                }
+            }
+            let first = dir.join(&paths[0]);
+            fs::rename(&from, &first)?;
+            for path in &paths[1..] {
+                fs::hard_link(&first, dir.join(path))?;
+            }
+        }
+        let entries = paths
+            .iter()
+            .map(|path| ManifestEntry {
+                path: path.clone(),
+                key: name_key(path, false),
+                ..staged.clone()
+            })
+            .collect();
+        manifest.set_entries(*id, entries);
+        manifest.save(dir)?;
+    }
+    manifest.layout = to;
+    manifest.save(dir)?;
+    let _ = fs::remove_dir(&staging);
+    Ok(moves.len())
+}
+
+/// What `sync pull` is asked for, besides the directory.
+pub struct PullOptions {
+    /// The cloud folder to sync with, needed the first time.
+    pub folder: Option<CloudPath>,
+    /// The layout asked for, which must be the directory's unless
+    /// `relayout`.
+    pub layout: Option<Layout>,
+    /// Whether to move what's synced already into the layout asked for,
+    /// or without one, the default.
+    pub relayout: bool,
+    /// The layout of directories synced for the first time without one
+    /// being asked for, from the settings.
+    pub default_layout: Option<Layout>,
+    /// Whether to carry on the plan an interrupted pull saved, see [`pull`].
+    pub resume_plan: bool,
+}
+
+/// Syncs `dir` with its folder for `sync pull`: sets it up the first time,
+/// relays it out if asked to, and then [`pull`]s into it. `manifest` is its
+/// manifest, none if it's never been synced.
+pub async fn pull_dir(
+    client: &Client,
+    dir: &Path,
+    manifest: Option<Manifest>,
+    documents: &ResolvedTree,
+    options: &PullOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let mut manifest = match (manifest, &options.folder) {
+        (Some(manifest), Some(asked))
+            if asked.to_string() != manifest.folder =>
+        {
+            return Err(msg!(
+                SYNC_OTHER_FOLDER,
+                dir = dir.display().to_string(),
+                folder = &manifest.folder,
+                asked = asked.to_string(),
+            )
+            .into());
+        }
+        (Some(manifest), _) => manifest,
+        (None, Some(asked)) => {
+            fs::create_dir_all(dir)?;
+            Manifest {
+                folder: asked.to_string(),
+                layout: options
+                    .layout
+                    .or(options.default_layout)
+                    .unwrap_or_default(),
+                entries: vec![],
+            }
+        }
+        (None, None) => {
+            return Err(
+                msg!(SYNC_NO_FOLDER, dir = dir.display().to_string()).into()
+            )
+        }
+    };
+    let wanted = if options.relayout {
+        options.layout.or(options.default_layout)
+    } else {
+        options.layout
+    };
+    match wanted {
+        Some(wanted) if wanted == manifest.layout => {}
+        Some(wanted) if options.relayout => {
+            let moved =
+                relayout(client, dir, &mut manifest, documents, wanted, out)
+                    .await?;
+            out.note(&msg!(
+                SYNC_RELAID,
+                count = moved,
+                dir = dir.display().to_string(),
+                layout = wanted.name(),
+            ));
+        }
+        Some(wanted) => {
+            return Err(msg!(
+                SYNC_LAYOUT_CHANGED,
+                dir = dir.display().to_string(),
+                layout = manifest.layout.name(),
+                asked = wanted.name(),
+            )
+            .into())
+        }
+        None => {}
+    }
+    manifest.save(dir)?;
+    let report = pull(
+        client,
+        dir,
+        &mut manifest,
+        documents,
+        options.resume_plan,
+        out,
+    )
+    .await?;
+    if report.conflicts > 0 {
+        out.note(&msg!(SYNC_CONFLICTS_LEFT, count = report.conflicts));
+    } else if report.pulled + report.removed + report.skipped == 0 {
+        out.note(&msg!(SYNC_UP_TO_DATE, folder = &manifest.folder));
+    }
+    Ok(())
+}
+
+/// Settles the conflicts in `dir` for `sync resolve`, carrying on a resolve
+/// interrupted before, then fails if any were left as they were.
+#[allow(clippy::too_many_arguments)]
+pub async fn resolve_dir(
+    client: &Client,
+    uploads: &push::Journal,
+    mutations: &MutationLog,
+    dir: &Path,
+    mut manifest: Manifest,
+    documents: &ResolvedTree,
+    strategy: Option<Strategy>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    if ResolveJournal::load(dir)?.is_some() {
+        out.note(&msg!(RESOLVE_RESUMED));
+        if strategy.is_some() {
+            out.warn(&msg!(RESOLVE_STRATEGY_IGNORED));
+        }
+    }
+    let report = resolve(
+        client,
+        uploads,
+        mutations,
+        dir,
+        &mut manifest,
+        documents,
+        strategy,
+        out,
+    )
+    .await?;
+    if report.resolved == 0 && report.skipped == 0 {
+        out.note(&msg!(RESOLVE_NOTHING, dir = dir.display().to_string()));
+    }
+    if report.skipped > 0 {
+        return Err(msg!(RESOLVE_LEFT, count = report.skipped).into());
+    }
+    Ok(())
+}
+
+/// Reports what differs between `dir` and the cloud since it was last
+/// synced to `out`, for `sync status`, failing if anything does.
+pub fn check_dir(
+    dir: &Path,
+    manifest: &Manifest,
+    documents: &ResolvedTree,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let cloud = folder_documents(dir, manifest, documents)?;
+    out.observe(&Event::Phase(Phase::Planning {
+        compared: cloud.len(),
+    }));
+    let plan = compare(dir, manifest, &cloud, Utc::now())?;
+    if !plan.is_clean() {
+        for line in plan.to_string().lines() {
+            out.line(line);
+        }
+        let n = plan.differences();
+        return Err(msg!(SYNC_PATHS_DIFFER, count = n).into());
+    }
+    out.note(&msg!(
+        SYNC_IN_STEP,
+        dir = dir.display().to_string(),
+        folder = manifest.folder.to_string(),
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::Capture;
+    use crate::observer::Observers;
+    use crate::testutil::listing;
+
+    const SIDES: [Side; 3] = [Side::Unchanged, Side::Changed, Side::Absent];
+
+    #[test]
+    fn matrix() {
+        use Change::*;
+        // Rows are how the file is, columns how the document is, in the
+        // order of SIDES.
+        let tracked = [
+            [Clean, CloudChanged, CloudDeleted],
+            [LocalChanged, Conflict, Conflict],
+            [LocalDeleted, Conflict, BothDeleted],
+        ];
+        let untracked = [
+            [Conflict, Conflict, LocalOnly],
+            [Conflict, Conflict, LocalOnly],
+            [CloudOnly, CloudOnly, Clean],
+        ];
+        for (l, local) in SIDES.iter().enumerate() {
+            for (c, cloud) in SIDES.iter().enumerate() {
+                assert_eq!(
+                    Change::classify(true, *local, *cloud),
+                    tracked[l][c],
+                    "tracked, {:?} here, {:?} in the cloud",
+                    local,
+                    cloud
+                );
+                assert_eq!(
+                    Change::classify(false, *local, *cloud),
+                    untracked[l][c],
+                    "untracked, {:?} here, {:?} in the cloud",
+                    local,
+                    cloud
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn conflicts() {
+        use ConflictKind::*;
+        use Side::*;
+        assert_eq!(
+            ConflictKind::classify(true, Changed, Changed),
+            Some(BothChanged)
+        );
+        assert_eq!(
+            ConflictKind::classify(true, Changed, Absent),
+            Some(ChangedHereDeletedThere)
+        );
+        assert_eq!(
+            ConflictKind::classify(true, Absent, Changed),
+            Some(DeletedHereChangedThere)
+        );
+        assert_eq!(
+            ConflictKind::classify(false, Changed, Changed),
+            Some(BothAdded)
+        );
+        // Only conflicts have a kind.
+        for local in &SIDES {
+            for cloud in &SIDES {
+                for tracked in &[true, false] {
+                    assert_eq!(
+                        ConflictKind::classify(*tracked, *local, *cloud)
+                            .is_some(),
+                        Change::classify(*tracked, *local, *cloud)
+                            == Change::Conflict
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strategies() {
+        use ConflictKind::*;
+        use Step::*;
+        let kinds = [
+            BothChanged,
+            BothAdded,
+            ChangedHereDeletedThere,
+            DeletedHereChangedThere,
+        ];
+        let expected: [(Strategy, [&[Step]; 4]); 3] = [
+            (
+                Strategy::KeepCloud,
+                [&[Pull], &[Pull], &[RemoveFile], &[Pull]],
+            ),
+            (
+                Strategy::KeepLocal,
+                [&[Update], &[Update], &[PushNew], &[TrashDocument]],
+            ),
+            (
+                Strategy::KeepBoth,
+                [&[PushCopy, Pull], &[PushCopy, Pull], &[PushNew], &[Pull]],
+            ),
+        ];
+        for (strategy, steps) in expected.iter() {
+            for (kind, steps) in kinds.iter().zip(steps.iter()) {
+                assert_eq!(
+                    strategy.steps(*kind),
+                    *steps,
+                    "{:?} for {:?}",
+                    strategy,
+                    kind
+                );
+            }
+        }
+        for value in STRATEGY_VALUES {
+            assert!(value.parse::<Strategy>().is_ok(), "{}", value);
+        }
+        let mut answers = &b"b\n"[..];
+        let mut asked = vec![];
+        let strategy = ask("Dune.pdf", BothChanged, &mut answers, &mut asked);
+        assert_eq!(strategy.unwrap(), Some(Strategy::KeepBoth));
+        assert!(String::from_utf8(asked)
+            .unwrap()
+            .contains("changed on both"));
+        let mut answers = &b"\n"[..];
+        let strategy = ask("Dune.pdf", BothAdded, &mut answers, &mut vec![]);
+        assert_eq!(strategy.unwrap(), None);
+    }
+
+    #[test]
+    fn copies() {
+        let none = |_: &str| false;
+        assert_eq!(
+            copy_path("Books/Dune.pdf", &none),
+            "Books/Dune (local).pdf"
+        );
+        assert_eq!(copy_path("Notes", &none), "Notes (local)");
+        let taken =
+            |name: &str| ["Dune (local)", "Dune (local 2)"].contains(&name);
+        assert_eq!(copy_path("Dune.pdf", &taken), "Dune (local 3).pdf");
+        let resolution = Resolution {
+            path: "Dune.pdf".to_string(),
+            id: None,
+            kind: ConflictKind::BothAdded,
+            strategy: Strategy::KeepBoth,
+            version: Some(2),
+            copy: Some("Dune (local).pdf".to_string()),
+            done: false,
+        };
+        let dir =
+            std::env::temp_dir().join(format!("resolve-{}", Uuid::new_v4()));
+        fs::create_dir(&dir).unwrap();
+        assert_eq!(ResolveJournal::load(&dir).unwrap(), None);
+        let journal = ResolveJournal {
+            resolutions: vec![resolution],
+        };
+        journal.save(&dir).unwrap();
+        assert_eq!(ResolveJournal::load(&dir).unwrap(), Some(journal));
+        ResolveJournal::remove(&dir).unwrap();
+        ResolveJournal::remove(&dir).unwrap();
+        assert_eq!(ResolveJournal::load(&dir).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn analyze() {
+        let docs = listing(&[
+            (1, "Same", None, "DocumentType"),
+            (2, "Edited", None, "DocumentType"),
+            (3, "Both", None, "DocumentType"),
+            (5, "New", None, "DocumentType"),
+            (6, "Touched", None, "DocumentType"),
+        ]);
+        let doc = |n| docs.get(&Uuid::from_u128(n)).unwrap();
+        let entry = |n: u128, path: &str| ManifestEntry {
+            path: path.to_string(),
+            key: String::new(),
+            id: Uuid::from_u128(n),
+            version: doc(1).version,
+            modified: doc(1).modified_client,
+            size: 10,
+            mtime: 100,
+            sha256: "old".to_string(),
+        };
+        let mut edited = doc(2).clone();
+        edited.version += 1;
+        let mut both = doc(3).clone();
+        both.version += 1;
+        let manifest = Manifest {
+            folder: "/".to_string(),
+            layout: Layout::Mirror,
+            entries: vec![
+                entry(1, "Same.pdf"),
+                entry(2, "Edited.pdf"),
+                entry(3, "Both.pdf"),
+                entry(4, "Gone.pdf"),
+                entry(6, "Touched.pdf"),
+            ],
+        };
+        let file = |path: &str, size, mtime| LocalFile {
+            path: path.to_string(),
+            size,
+            mtime,
+        };
+        let local = [
+            file("Same.pdf", 10, 100),
+            file("Edited.pdf", 10, 100),
+            file("Both.pdf", 12, 200),
+            file("Gone.pdf", 10, 100),
+            file("Touched.pdf", 10, 300),
+            file("Books/Dune.pdf", 5, 1),
+            file("New.epub", 5, 1),
+        ];
+        let cloud = [
+            ("Same".to_string(), doc(1)),
+            ("Edited".to_string(), &edited),
+            ("Both".to_string(), &both),
+            ("New".to_string(), doc(5)),
+            ("Touched".to_string(), doc(6)),
+        ];
+        let mut hashed = vec![];
+        let mut hash = |path: &str| {
+            hashed.push(path.to_string());
+            Ok("old".to_string())
+        };
+        let now = doc(1).modified_client;
+        let plan =
+            Plan::analyze(&manifest, &local, &cloud, now, false, &mut hash)
+                .unwrap();
+        let changes: Vec<(&str, Change)> = plan
+            .items
+            .iter()
+            .map(|i| (i.path.as_str(), i.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("Books/Dune.pdf", Change::LocalOnly),
+                ("Both.pdf", Change::Conflict),
+                ("Edited.pdf", Change::CloudChanged),
+                ("Gone.pdf", Change::CloudDeleted),
+                ("New.epub", Change::Conflict),
+                ("Same.pdf", Change::Clean),
+                ("Touched.pdf", Change::Clean),
+            ]
+        );
+        // Only the file whose time changed but size didn't is read.
+        assert_eq!(hashed, ["Touched.pdf"]);
+        assert_eq!(plan.differences(), 5);
+        assert_eq!(
+            plan.to_string(),
+            "Changed in the cloud (1):\n    Edited.pdf\n\n\
+             Changed on both sides (2):\n    Both.pdf\n    New.epub\n\n\
+             Only here (1):\n    Books/Dune.pdf\n\n\
+             Deleted in the cloud (1):\n    Gone.pdf\n"
+        );
+    }
+
+    #[test]
+    fn normalized_paths() {
+        let docs = listing(&[
+            (1, "Résumé", None, "DocumentType"),
+            (2, "Notes", None, "DocumentType"),
+        ]);
+        let doc = |n| docs.get(&Uuid::from_u128(n)).unwrap();
+        // Synced from macOS, whose file names are decomposed.
+        let manifest = Manifest {
+            folder: "/".to_string(),
+            layout: Layout::Mirror,
+            entries: vec![ManifestEntry {
+                path: "Re\u{301}sume\u{301}.pdf".to_string(),
+                key: "Résumé.pdf".to_string(),
+                id: doc(1).id,
+                version: doc(1).version,
+                modified: doc(1).modified_client,
+                size: 10,
+                mtime: 100,
+                sha256: "old".to_string(),
+            }],
+        };
+        let file = |path: &str| LocalFile {
+            path: path.to_string(),
+            size: 10,
+            mtime: 100,
+        };
+        let local = [file("Résumé.pdf"), file("notes.pdf")];
+        let cloud = [
+            ("Résumé".to_string(), doc(1)),
+            ("Notes".to_string(), doc(2)),
+        ];
+        let now = doc(1).modified_client;
+        let changes = |fold_case| {
+            let mut hash = |_: &str| Ok("old".to_string());
+            Plan::analyze(&manifest, &local, &cloud, now, fold_case, &mut hash)
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|i| (i.path, i.change))
+                .collect::<Vec<_>>()
+        };
+        let clean = ("Re\u{301}sume\u{301}.pdf".to_string(), Change::Clean);
+        assert_eq!(
+            changes(false),
+            [
+                ("Notes".to_string(), Change::CloudOnly),
+                clean.clone(),
+                ("notes.pdf".to_string(), Change::LocalOnly),
+            ]
+        );
+        assert_eq!(
+            changes(true),
+            [clean, ("notes.pdf".to_string(), Change::Conflict)]
+        );
+    }
+
+    #[test]
+    fn skewed() {
+        let docs = listing(&[(1, "Dune", None, "DocumentType")]);
+        let synced = docs.get(&Uuid::from_u128(1)).unwrap();
+        let now = synced.modified_client + chrono::Duration::days(1);
+        let entry = ManifestEntry {
+            path: "Dune.pdf".to_string(),
+            key: "Dune.pdf".to_string(),
+            id: synced.id,
+            version: synced.version,
+            modified: synced.modified_client,
+            size: 0,
+            mtime: 0,
+            sha256: String::new(),
+        };
+        let side = |version: u64, modified: DateTime<Utc>| {
+            let mut doc = synced.clone();
+            doc.version = version;
+            doc.modified_client = modified;
+            cloud_side(&entry, &doc, now)
+        };
+        let v = synced.version;
+        let hours = chrono::Duration::hours;
+        assert_eq!(side(v, synced.modified_client), Side::Unchanged);
+        // Restamped by a clock a year ahead, at the same version: there's no
+        // knowing when that was, and the version says nothing changed.
+        assert_eq!(side(v, now + chrono::Duration::days(365)), Side::Unchanged);
+        // But a new version is a change whatever it's stamped.
+        assert_eq!(
+            side(v + 1, now + chrono::Duration::days(365)),
+            Side::Changed
+        );
+        // Restamped by a clock behind, which is in the past, so counts.
+        assert_eq!(side(v, synced.modified_client - hours(30)), Side::Changed);
+        assert_eq!(
+            side(v + 1, synced.modified_client - hours(30)),
+            Side::Changed
+        );
+        // A little ahead is within what clocks differ by.
+        assert_eq!(side(v, now + chrono::Duration::minutes(2)), Side::Changed);
+    }
+
+    #[test]
+    fn stems() {
+        assert_eq!(stem("Books/Dune.pdf"), "Books/Dune");
+        assert_eq!(stem("a.b/c"), "a.b/c");
+        assert_eq!(stem("x.tar.gz"), "x.tar");
+        assert_eq!(stem(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn accounted_for() {
+        let resolution = |kind, strategy| Resolution {
+            path: "Dune.pdf".to_string(),
+            id: Some(Uuid::from_u128(1)),
+            kind,
+            strategy,
+            version: Some(3),
+            copy: None,
+            done: false,
+        };
+        let keep_local =
+            resolution(ConflictKind::BothChanged, Strategy::KeepLocal);
+        assert!(keep_local.accounts_for(Some(3)));
+        // Uploaded before it was interrupted.
+        assert!(keep_local.accounts_for(Some(4)));
+        assert!(!keep_local.accounts_for(Some(5)));
+        assert!(!keep_local.accounts_for(None));
+        let keep_cloud =
+            resolution(ConflictKind::BothChanged, Strategy::KeepCloud);
+        assert!(!keep_cloud.accounts_for(Some(4)));
+        // Trashed before it was interrupted.
+        let trash = resolution(
+            ConflictKind::DeletedHereChangedThere,
+            Strategy::KeepLocal,
+        );
+        assert!(trash.accounts_for(None));
+    }
+
+    #[test]
+    fn rechecks() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = listing(&[
+            (1, "Dune", None, "DocumentType"),
+            (2, "Emma", None, "DocumentType"),
+            (3, "Ubik", None, "DocumentType"),
+            (4, "Edited", None, "DocumentType"),
+        ]);
+        let doc = |n| docs.get(&Uuid::from_u128(n)).unwrap();
+        let cloud: Vec<(String, &Document)> =
+            ["Dune", "Emma", "Ubik", "Edited"]
+                .iter()
+                .enumerate()
+                .map(|(n, name)| (name.to_string(), doc(n as u128 + 1)))
+                .collect();
+        // Edited.pdf was synced at version 0, and has been edited here
+        // since it was planned to be pulled.
+        fs::write(dir.path().join("Edited.pdf"), b"edited").unwrap();
+        let manifest = Manifest {
+            folder: "/".to_string(),
+            layout: Layout::Mirror,
+            entries: vec![ManifestEntry {
+                path: "Edited.pdf".to_string(),
+                key: String::new(),
+                id: Uuid::from_u128(4),
+                version: 0,
+                modified: doc(4).modified_client,
+                size: 1,
+                mtime: 0,
+                sha256: String::new(),
+            }],
+        };
+        let planned = |n, path: &str, change, version| PlannedPull {
+            path: path.to_string(),
+            id: Some(Uuid::from_u128(n)),
+            change,
+            version: Some(version),
+            done: false,
+        };
+        let mut out = Capture::new(Observers::new());
+
+        // Dune is as planned, Emma has moved on since, and Ubik wasn't
+        // planned for.
+        let saved = vec![
+            planned(1, "Dune", Change::CloudOnly, 1),
+            planned(4, "Edited.pdf", Change::CloudChanged, 1),
+            planned(2, "Emma", Change::CloudOnly, 0),
+        ];
+        let rechecked =
+            recheck(dir.path(), &manifest, &cloud, saved, &mut out).unwrap();
+        assert_eq!(
+            rechecked,
+            vec![
+                planned(1, "Dune", Change::CloudOnly, 1),
+                planned(4, "Edited.pdf", Change::Conflict, 1),
+                planned(2, "Emma", Change::CloudOnly, 1),
+                planned(3, "Ubik", Change::CloudOnly, 1),
+            ]
+        );
+        assert_eq!(
+            out.notes,
+            vec![
+                "Edited.pdf changed since it was planned; planning it again",
+                "Emma changed since it was planned; planning it again",
+            ]
+        );
+
+        // Once nothing has, it's as it was.
+        out.notes.clear();
+        let again =
+            recheck(dir.path(), &manifest, &cloud, rechecked.clone(), &mut out)
+                .unwrap();
+        assert_eq!(again, rechecked);
+        assert!(out.notes.is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+//! Picking the documents a mutating command such as `mv` or `rm` acts on.
+
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+use remarkable_cloud_api::{
+    Client, CloudPath, Document, Error, Parent, Resolved,
+};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::glob::Pattern;
+use crate::messages::{self, Message};
+use crate::resolved::ResolvedTree;
+use crate::{msg, CliResult};
+
+/// Expands each of `patterns` against the listing, in order and without
+/// repeats. A pattern without wildcards names exactly the document at that
+/// path. A pattern which matches nothing is an error unless `allow_empty`,
+/// when it's pointed out on `out`.
+pub fn expand<'a>(
+    documents: &'a ResolvedTree,
+    patterns: &[&str],
+    allow_empty: bool,
+    out: &mut dyn Output,
+) -> Result<Vec<(String, &'a Document)>, String> {
+    let mut seen = HashSet::new();
+    let mut targets = vec![];
+    for p in patterns {
+        let pattern: Pattern = p.parse()?;
+        let found = if pattern.is_literal() {
+            let path: CloudPath =
+                p.parse().map_err(|e: Error| e.to_string())?;
+            match documents.lookup(&path) {
+                // Those in the trash have no path from the root.
+                Ok(Resolved::Document(d)) => vec![(
+                    documents.path_of(&d.id).unwrap_or(path.to_string()),
+                    d,
+                )],
+                Ok(_) | Err(Error::PathNotFound { .. }) => vec![],
+                Err(e) => return Err(e.to_string()),
+            }
+        } else {
+            pattern.expand(documents)
+        };
+        if found.is_empty() {
+            if !allow_empty {
+                return Err(msg!(
+                    MATCHED_NOTHING_STOP,
+                    pattern = format!("{:?}", p)
+                ));
+            }
+            out.warn(&msg!(MATCHED_NOTHING, pattern = format!("{:?}", p)));
+        }
+        for (path, doc) in found {
+            if seen.insert(doc.id) {
+                targets.push((path, doc));
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Checks before acting on more than one document: lists them on `out`,
+/// then asks it for confirmation unless `assume_yes`.
+pub fn confirm(
+    action: &Message,
+    targets: &[(String, &Document)],
+    assume_yes: bool,
+    out: &mut dyn Output,
+) -> io::Result<bool> {
+    if targets.len() <= 1 {
+        return Ok(true);
+    }
+    confirm_each(action, targets, assume_yes, out)
+}
+
+/// As `confirm`, but asking even about a single document, for commands
+/// which choose what to act on themselves.
+pub fn confirm_each(
+    action: &Message,
+    targets: &[(String, &Document)],
+    assume_yes: bool,
+    out: &mut dyn Output,
+) -> io::Result<bool> {
+    out.line(&messages::render(
+        action,
+        &[("count", targets.len().into())],
+    ));
+    for (path, _) in targets {
+        out.line(&format!("  {}", path));
+    }
+    if assume_yes {
+        return Ok(true);
+    }
+    out.confirm(&format!("{} ", msg!(CONFIRM_CONTINUE)))
+}
+
+/// Asks `question` on `output`, taking a yes on `input` as confirmation.
+pub fn ask(
+    question: &str,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<bool> {
+    write!(output, "{}", question)?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// `targets` followed by everything below them which isn't among them
+/// already, for commands which act on folders along with their contents.
+pub fn with_contents<'a>(
+    documents: &'a ResolvedTree,
+    targets: &[(String, &'a Document)],
+) -> Vec<(String, &'a Document)> {
+    let mut seen: HashSet<Uuid> = targets.iter().map(|(_, d)| d.id).collect();
+    let mut all = targets.to_vec();
+    for (_, doc) in targets {
+        for (_, d) in documents.descendants(Parent::Folder(doc.id)) {
+            if seen.insert(d.id) {
+                let path = documents.path_of(&d.id).unwrap_or_default();
+                all.push((path, d));
+            }
+        }
+    }
+    all
+}
+
+/// Those of `targets` which aren't below any of the others.
+pub fn topmost<'a>(
+    documents: &ResolvedTree,
+    targets: &[(String, &'a Document)],
+) -> Vec<&'a Document> {
+    let ids: HashSet<Uuid> = targets.iter().map(|(_, d)| d.id).collect();
+    targets
+        .iter()
+        .filter(|(_, doc)| {
+            let mut parent = doc.parent;
+            // Bounded, should the parents go round in a circle.
+            for _ in 0..documents.len() {
+                match parent {
+                    Some(id) if ids.contains(&id) => return false,
+                    Some(id) => {
+                        parent = documents.get(&id).and_then(|d| d.parent)
+                    }
+                    None => break,
+                }
+            }
+            true
+        })
+        .map(|(_, d)| *d)
+        .collect()
+}
+
+/// Checks that none of `targets`, taken from a cached listing, has changed
+/// in the cloud since, so that acting on them doesn't undo someone else's
+/// changes.
+pub async fn check_unchanged(
+    client: &Client,
+    targets: &[(String, &Document)],
+) -> CliResult<()> {
+    for (path, doc) in targets {
+        let current = match client.get_document_by_id(&doc.id).await {
+            Ok(current) => current,
+            Err(Error::EmptyResult) => {
+                return Err(msg!(
+                    CACHED_TARGET_GONE,
+                    path = format!("{:?}", path)
+                )
+                .into())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if current.version != doc.version {
+            return Err(changed_since_cache(path, doc, &current).into());
+        }
+    }
+    Ok(())
+}
+
+// The error for a target at `path` which is `current` now, but was `doc`
+// when the listing was cached.
+fn changed_since_cache(
+    path: &str,
+    doc: &Document,
+    current: &Document,
+) -> String {
+    msg!(
+        CACHED_TARGET_CHANGED,
+        path = format!("{:?}", path),
+        old = doc.version,
+        new = current.version,
+    )
+}
+
+/// Checks, as `check_unchanged` does, that none of `targets`, taken from
+/// the trash of a cached listing and what's in the folders there, has
+/// changed since. The cloud doesn't answer for trashed documents by id, so
+/// the listing is fetched to look them up in.
+pub async fn check_unchanged_in_trash(
+    client: &Client,
+    targets: &[(String, &Document)],
+) -> CliResult<()> {
+    let current = client.get_documents().await?;
+    for (path, doc) in targets {
+        let found = current
+            .get(&doc.id)
+            .or_else(|| current.trashed().find(|d| d.id == doc.id));
+        let current = match found {
+            Some(current) => current,
+            None => {
+                return Err(msg!(
+                    CACHED_TARGET_DELETED,
+                    path = format!("{:?}", path)
+                )
+                .into())
+            }
+        };
+        if current.version != doc.version {
+            return Err(changed_since_cache(path, doc, current).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use remarkable_cloud_api::testing::FakeCloud;
+
+    use super::*;
+    use crate::commands::Capture;
+    use crate::observer::Observers;
+    use crate::testutil::listing;
+
+    fn documents() -> ResolvedTree {
+        ResolvedTree::new(listing(&[
+            (1, "Work", None, "CollectionType"),
+            (2, "scans", Some(1), "CollectionType"),
+            (3, "2023-01", Some(2), "DocumentType"),
+            (4, "2023-02", Some(2), "DocumentType"),
+            (5, "2024-01", Some(2), "DocumentType"),
+            (6, "Home", None, "CollectionType"),
+            (7, "scans", Some(6), "CollectionType"),
+            (8, "2023-01", Some(7), "DocumentType"),
+        ]))
+    }
+
+    fn paths(targets: &[(String, &Document)]) -> Vec<String> {
+        targets.iter().map(|(p, _)| p.clone()).collect()
+    }
+
+    #[test]
+    fn expansion() {
+        let docs = documents();
+        let mut out = Capture::new(Observers::new());
+        let found =
+            expand(&docs, &["Work/scans/2023-*"], false, &mut out).unwrap();
+        assert_eq!(
+            paths(&found),
+            vec!["Work/scans/2023-01", "Work/scans/2023-02"]
+        );
+
+        // Overlapping patterns don't repeat documents.
+        let found = expand(
+            &docs,
+            &["Work/scans/2024-01", "*/scans/20*"],
+            false,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&found),
+            vec![
+                "Work/scans/2024-01",
+                "Home/scans/2023-01",
+                "Work/scans/2023-01",
+                "Work/scans/2023-02",
+            ]
+        );
+
+        assert!(expand(&docs, &["Work/scans/2025-*"], false, &mut out)
+            .unwrap_err()
+            .contains("--allow-empty"));
+        assert!(expand(&docs, &["Work/missing"], false, &mut out).is_err());
+        let found = expand(
+            &docs,
+            &["Work/scans/2025-*", "Home/scans/*"],
+            true,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(paths(&found), vec!["Home/scans/2023-01"]);
+        assert_eq!(out.warnings, vec!["\"Work/scans/2025-*\" matched nothing"]);
+    }
+
+    #[test]
+    fn contents() {
+        let docs = documents();
+        let mut out = Capture::new(Observers::new());
+        let found =
+            expand(&docs, &["Work/scans/2024-01", "Work"], false, &mut out)
+                .unwrap();
+        assert_eq!(
+            paths(&with_contents(&docs, &found)),
+            vec![
+                "Work/scans/2024-01",
+                "Work",
+                "Work/scans",
+                "Work/scans/2023-01",
+                "Work/scans/2023-02",
+            ]
+        );
+        let names: Vec<&str> = topmost(&docs, &found)
+            .iter()
+            .map(|d| &*d.visible_name)
+            .collect();
+        assert_eq!(names, vec!["Work"]);
+    }
+
+    #[test]
+    fn confirmation() {
+        let docs = documents();
+        let check = |pattern: &str, assume_yes: bool, yes: bool| {
+            let mut out = Capture::new(Observers::new());
+            out.yes = yes;
+            let targets = expand(&docs, &[pattern], false, &mut out).unwrap();
+            let confirmed = confirm(
+                &messages::CONFIRM_TRASH,
+                &targets,
+                assume_yes,
+                &mut out,
+            )
+            .unwrap();
+            (confirmed, out.lines)
+        };
+
+        // A single match goes ahead without asking.
+        assert_eq!(check("Work/scans/2024-*", false, false), (true, vec![]));
+
+        let (confirmed, lines) = check("Work/scans/2023-*", false, true);
+        assert!(confirmed);
+        assert_eq!(
+            lines,
+            vec![
+                "This will trash 2 documents:",
+                "  Work/scans/2023-01",
+                "  Work/scans/2023-02",
+                "Continue? [y/N] ",
+            ]
+        );
+        assert!(!check("Work/scans/2023-*", false, false).0);
+
+        // --yes still lists what's being done, but doesn't wait.
+        let (confirmed, lines) = check("Work/scans/2023-*", true, false);
+        assert!(confirmed);
+        assert!(lines.contains(&"  Work/scans/2023-02".to_string()));
+        assert!(!lines.iter().any(|l| l.starts_with("Continue?")));
+    }
+
+    #[test]
+    fn asking() {
+        let answer = |given: &str| {
+            let mut output = vec![];
+            let yes =
+                ask("Continue? [y/N] ", &mut given.as_bytes(), &mut output)
+                    .unwrap();
+            assert_eq!(output, b"Continue? [y/N] ");
+            yes
+        };
+        assert!(answer("y\n"));
+        assert!(answer("YES\n"));
+        assert!(!answer("n\n"));
+        assert!(!answer("\n"));
+        assert!(!answer(""));
+    }
+
+    #[tokio::test]
+    async fn changed_since_cache() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let emma = cloud.add_document("Emma", None, vec![]);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let cached = ResolvedTree::new(client.get_documents().await.unwrap());
+        let mut out = Capture::new(Observers::new());
+        let targets = expand(&cached, &["*"], false, &mut out).unwrap();
+        check_unchanged(&client, &targets).await.unwrap();
+
+        // The tablet syncs a change between listing and acting.
+        cloud.modify(&emma, |d| d.version = 3);
+        let err = check_unchanged(&client, &targets).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"Emma\": document changed since cache (v1 \u{2192} v3), re-run \
+             without --cached"
+        );
+        let current = client.get_documents().await.unwrap();
+        let newer = current.newer_than(&cached);
+        assert_eq!(newer.len(), 1);
+        assert_eq!(newer[0].id, emma);
+
+        cloud.modify(&dune, |d| d.trashed = true);
+        let targets = expand(&cached, &["Dune"], false, &mut out).unwrap();
+        let err = check_unchanged(&client, &targets).await.unwrap_err();
+        assert!(err.to_string().contains("trashed since cache"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn trash_changed_since_cache() {
+        let cloud = FakeCloud::start().await;
+        let dune = cloud.add_document("Dune", None, vec![]);
+        let emma = cloud.add_document("Emma", None, vec![]);
+        cloud.modify(&dune, |d| d.trashed = true);
+        cloud.modify(&emma, |d| d.trashed = true);
+        let mut client = cloud.client();
+        client.refresh_token().await.unwrap();
+        let cached = client.get_documents().await.unwrap();
+        let targets: Vec<(String, &Document)> = cached
+            .trashed()
+            .map(|d| (d.visible_name.to_string(), d))
+            .collect();
+        check_unchanged_in_trash(&client, &targets).await.unwrap();
+
+        // Taken out of the trash on the tablet.
+        cloud.modify(&emma, |d| {
+            d.trashed = false;
+            d.version = 2;
+        });
+        let err = check_unchanged_in_trash(&client, &targets)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"Emma\": document changed since cache (v1 \u{2192} v2), re-run \
+             without --cached"
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! Output file name templates, as given to `--name-template`.
+//!
+//! A template is literal text interspersed with placeholders in braces, such
+//! as `{name}-{page:03}.{ext}`. Numeric placeholders accept a width after a
+//! colon, padded with zeros if the width has a leading zero and spaces
+//! otherwise. Literal braces are written doubled: `{{` and `}}`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use uuid::Uuid;
+
+use crate::msg;
+use crate::naming;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Id,
+    Page,
+    Pages,
+    Version,
+    Date,
+    Ext,
+}
+
+impl Field {
+    pub const ALL: [Field; 7] = [
+        Field::Name,
+        Field::Id,
+        Field::Page,
+        Field::Pages,
+        Field::Version,
+        Field::Date,
+        Field::Ext,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Id => "id",
+            Field::Page => "page",
+            Field::Pages => "pages",
+            Field::Version => "version",
+            Field::Date => "date",
+            Field::Ext => "ext",
+        }
+    }
+
+    fn from_name(s: &str) -> Option<Field> {
+        Field::ALL.iter().copied().find(|f| f.name() == s)
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Page | Field::Pages | Field::Version)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TemplateError {
+    /// Byte offset into the template at which the problem was found.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (message, position) = (&self.message, self.position);
+        write!(
+            f,
+            "{}",
+            msg!(TEMPLATE_AT, message = message, position = position)
+        )
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field {
+        field: Field,
+        position: usize,
+        width: usize,
+        zero_pad: bool,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+/// The values placeholders are filled from. Values that don't apply to the
+/// operation at hand are left as `None`.
+#[derive(Default)]
+pub struct Values<'a> {
+    pub name: Option<&'a str>,
+    pub id: Option<Uuid>,
+    pub page: Option<u64>,
+    pub pages: Option<u64>,
+    pub version: Option<u64>,
+    pub date: Option<chrono::NaiveDate>,
+    pub ext: Option<&'a str>,
+}
+
+impl<'a> Values<'a> {
+    fn get(&self, field: Field) -> Option<Value<'a>> {
+        match field {
+            Field::Name => self.name.map(Value::Str),
+            Field::Id => self.id.map(|i| Value::String(i.to_string())),
+            Field::Page => self.page.map(Value::Number),
+            Field::Pages => self.pages.map(Value::Number),
+            Field::Version => self.version.map(Value::Number),
+            Field::Date => self
+                .date
+                .map(|d| Value::String(d.format("%Y-%m-%d").to_string())),
+            Field::Ext => self.ext.map(Value::Str),
+        }
+    }
+}
+
+enum Value<'a> {
+    Str(&'a str),
+    String(String),
+    Number(u64),
+}
+
+fn parse_placeholder(
+    body: &str,
+    position: usize,
+) -> Result<Segment, TemplateError> {
+    let (name, spec) = match body.find(':') {
+        Some(i) => (&body[..i], Some(&body[i + 1..])),
+        None => (body, None),
+    };
+    let field = Field::from_name(name).ok_or_else(|| TemplateError {
+        position,
+        message: msg!(
+            TEMPLATE_UNKNOWN,
+            placeholder = format!("{{{}}}", name),
+            expected = Field::ALL
+                .iter()
+                .map(|f| format!("{{{}}}", f.name()))
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+    })?;
+    let (width, zero_pad) = match spec {
+        None => (0, false),
+        Some(spec) => {
+            if !field.is_numeric() {
+                return Err(TemplateError {
+                    position,
+                    message: msg!(
+                        TEMPLATE_NO_WIDTH,
+                        placeholder = format!("{{{}}}", name)
+                    ),
+                });
+            }
+            let width = spec.parse::<usize>().map_err(|_| TemplateError {
+                position,
+                message: msg!(
+                    TEMPLATE_WIDTH_INVALID,
+                    width = format!("{:?}", spec),
+                    placeholder = format!("{{{}}}", name),
+                ),
+            })?;
+            (width, spec.starts_with('0'))
+        }
+    };
+    Ok(Segment::Field {
+        field,
+        position,
+        width,
+        zero_pad,
+    })
+}
+
+impl FromStr for Template {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = s.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let end = match s[i..].find('}') {
+                        Some(end) => i + end,
+                        None => {
+                            return Err(TemplateError {
+                                position: i,
+                                message: msg!(
+                                    TEMPLATE_UNCLOSED,
+                                    brace = "{",
+                                    escaped = "{{"
+                                ),
+                            })
+                        }
+                    };
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(literal.split_off(0)));
+                    }
+                    segments.push(parse_placeholder(&s[i + 1..end], i)?);
+                    while chars.peek().is_some_and(|&(j, _)| j <= end) {
+                        chars.next();
+                    }
+                }
+                '}' => {
+                    return Err(TemplateError {
+                        position: i,
+                        message: msg!(
+                            TEMPLATE_UNMATCHED,
+                            brace = "}",
+                            escaped = "}}"
+                        ),
+                    })
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+}
+
+impl Template {
+    /// Checks that the template only uses placeholders from `available`, so
+    /// that mistakes are reported before any work is done.
+    pub fn check_available(
+        &self,
+        available: &[Field],
+    ) -> Result<(), TemplateError> {
+        for segment in &self.segments {
+            if let Segment::Field {
+                field, position, ..
+            } = segment
+            {
+                if !available.contains(field) {
+                    return Err(TemplateError {
+                        position: *position,
+                        message: msg!(
+                            TEMPLATE_UNAVAILABLE,
+                            placeholder = format!("{{{}}}", field.name())
+                        ),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, values: &Values) -> Result<String, TemplateError> {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Field {
+                    field,
+                    position,
+                    width,
+                    zero_pad,
+                } => match values.get(*field) {
+                    None => {
+                        return Err(TemplateError {
+                            position: *position,
+                            message: msg!(
+                                TEMPLATE_NO_VALUE,
+                                placeholder = format!("{{{}}}", field.name())
+                            ),
+                        })
+                    }
+                    Some(Value::Str(s)) => out.push_str(s),
+                    Some(Value::String(s)) => out.push_str(&s),
+                    Some(Value::Number(n)) if *zero_pad => {
+                        out.push_str(&format!("{:0w$}", n, w = width))
+                    }
+                    Some(Value::Number(n)) => {
+                        out.push_str(&format!("{:w$}", n, w = width))
+                    }
+                },
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Tracks the names rendered during a run, to catch templates which give
+/// several outputs the same name. Names which would be the same file, as
+/// `naming::local_key` tells, count as the same.
+#[derive(Default)]
+pub struct NameRegistry {
+    seen: HashSet<String>,
+}
+
+impl NameRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Records `name` as used, failing if it already was.
+    pub fn claim(&mut self, name: &str) -> Result<(), String> {
+        if self.seen.insert(naming::local_key(name)) {
+            Ok(())
+        } else {
+            Err(msg!(
+                TEMPLATE_REPEATED,
+                name = format!("{:?}", name),
+                id = "{id}",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, values: &Values) -> String {
+        template
+            .parse::<Template>()
+            .unwrap()
+            .render(values)
+            .unwrap()
+    }
+
+    #[test]
+    fn placeholders() {
+        let id = Uuid::new_v4();
+        let values = Values {
+            name: Some("Notes"),
+            id: Some(id),
+            page: Some(7),
+            pages: Some(120),
+            version: Some(3),
+            date: Some(chrono::NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()),
+            ext: Some("png"),
+        };
+        assert_eq!(render("{name}-{page:03}.{ext}", &values), "Notes-007.png");
+        assert_eq!(render("{page}of{pages}", &values), "7of120");
+        assert_eq!(render("[{page:3}]", &values), "[  7]");
+        assert_eq!(render("{id}", &values), id.to_string());
+        assert_eq!(render("{date}_v{version}", &values), "2024-06-01_v3");
+        assert_eq!(render("plain", &values), "plain");
+        assert_eq!(render("", &values), "");
+    }
+
+    #[test]
+    fn escaped_braces() {
+        let values = Values {
+            name: Some("n"),
+            ..Default::default()
+        };
+        assert_eq!(render("{{{name}}}", &values), "{n}");
+        assert_eq!(render("{{name}}", &values), "{name}");
+        assert_eq!(render("a}}b{{c", &values), "a}b{c");
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |s: &str| s.parse::<Template>().unwrap_err();
+        let e = err("{name}-{pgae}");
+        assert_eq!(e.position, 7);
+        assert!(e.message.contains("{pgae}"));
+        assert!(e.message.contains("{page}"));
+        assert_eq!(err("{name").position, 0);
+        assert_eq!(err("name}").position, 4);
+        assert!(err("{name:03}").message.contains("width"));
+        assert!(err("{page:x}").message.contains("width"));
+        assert!(err("{}").message.contains("Unknown"));
+    }
+
+    #[test]
+    fn availability() {
+        let t: Template = "{name}-{page}.{ext}".parse().unwrap();
+        assert!(t.check_available(&Field::ALL).is_ok());
+        let e = t.check_available(&[Field::Name, Field::Ext]).unwrap_err();
+        assert_eq!(e.position, 7);
+        assert!(e.message.contains("{page}"));
+        assert!(t
+            .render(&Values {
+                name: Some("n"),
+                ..Default::default()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn collisions() {
+        let t: Template = "{name}.{ext}".parse().unwrap();
+        let mut registry = NameRegistry::new();
+        let results: Vec<_> = (1..=3)
+            .map(|page| {
+                let name = t
+                    .render(&Values {
+                        name: Some("Notes"),
+                        page: Some(page),
+                        ext: Some("svg"),
+                        ..Default::default()
+                    })
+                    .unwrap();
+                registry.claim(&name)
+            })
+            .collect();
+        assert!(results[0].is_ok());
+        assert!(results[1].as_ref().unwrap_err().contains("Notes.svg"));
+        assert!(results[2].is_err());
+
+        let t: Template = "{name}-{page:02}.{ext}".parse().unwrap();
+        let mut registry = NameRegistry::new();
+        for page in 1..=3 {
+            let name = t
+                .render(&Values {
+                    name: Some("Notes"),
+                    page: Some(page),
+                    ext: Some("svg"),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert!(registry.claim(&name).is_ok());
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the tests of several modules.
+
+use remarkable_cloud_api::Documents;
+use uuid::Uuid;
+
+/// Builds a listing from `(id, name, parent, type)` entries, with small
+/// integer ids standing in for UUIDs.
+pub fn listing(entries: &[(u128, &str, Option<u128>, &str)]) -> Documents {
+    let entries: Vec<serde_json::Value> = entries
+        .iter()
+        .map(|(id, name, parent, doc_type)| {
+            serde_json::json!({
+                "ID": Uuid::from_u128(*id),
+                "Version": 1,
+                "VissibleName": name,
+                "Type": doc_type,
+                "Parent": parent
+                    .map(|p| Uuid::from_u128(p).to_string())
+                    .unwrap_or_default(),
+                "CurrentPage": 0,
+                "Bookmarked": false,
+                "Message": "",
+                "ModifiedClient": "2024-01-01T00:00:00Z",
+                "BlobURLGet": "",
+                "BlobURLGetExpires": "0001-01-01T00:00:00Z",
+            })
+        })
+        .collect();
+    serde_json::from_value(serde_json::Value::Array(entries)).unwrap()
+}
@@ -0,0 +1,134 @@
+//! Emptying the trash, of everything or of what's been there a while, for
+//! `trash empty` and `trash prune`.
+//!
+//! Documents in a trashed folder stay in it rather than going to the trash
+//! themselves, so they're deleted along with it.
+
+use std::collections::HashSet;
+
+use remarkable_cloud_api::{join_path, Client, Document, Documents, Parent};
+
+use crate::commands::Output;
+use crate::help::Example;
+use crate::manage::delete_subtrees;
+use crate::messages;
+use crate::mutations::MutationLog;
+use crate::{msg, targets, CliResult};
+
+/// The examples `trash --help` shows.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        command: "remarkable-cloud trash 'Inbox/*'",
+        description: "Moves everything in Inbox to the trash, asking first",
+    },
+    Example {
+        command: "remarkable-cloud trash prune --older-than 30d --dry-run",
+        description: "Shows what's been in the trash over 30 days",
+    },
+    Example {
+        command: "remarkable-cloud trash prune --older-than 2w --keep-latest 10 --yes",
+        description: "Deletes what's been in the trash over two weeks, but \
+                      for the 10 most recently trashed",
+    },
+    Example {
+        command: "remarkable-cloud trash empty",
+        description: "Deletes everything in the trash for good, asking first",
+    },
+];
+
+/// What's shown in place of the trash in paths.
+pub const TRASH: &str = "Trash";
+
+/// The documents and folders in the trash which were trashed before
+/// `cutoff`, or whenever for `None`, leaving out the `keep_latest` most
+/// recently trashed of all. Oldest first.
+pub fn candidates(
+    documents: &Documents,
+    cutoff: Option<chrono::DateTime<chrono::Utc>>,
+    keep_latest: usize,
+) -> Vec<&Document> {
+    let mut all: Vec<&Document> = documents.trashed().collect();
+    all.sort_by_key(|d| (d.modified_client, d.id));
+    let kept: HashSet<_> =
+        all.iter().rev().take(keep_latest).map(|d| d.id).collect();
+    let older = match cutoff {
+        Some(cutoff) => documents.trashed_older_than(cutoff),
+        None => all,
+    };
+    older
+        .into_iter()
+        .filter(|d| !kept.contains(&d.id))
+        .collect()
+}
+
+/// `trashed` each followed by everything in it, with the paths they're
+/// shown with, under [`TRASH`].
+pub fn with_contents<'a>(
+    documents: &'a Documents,
+    trashed: &[&'a Document],
+) -> Vec<(String, &'a Document)> {
+    let mut all = vec![];
+    for doc in trashed {
+        let mut names = vec![TRASH, &*doc.visible_name];
+        all.push((join_path(&names), *doc));
+        for (depth, d) in documents.descendants(Parent::Folder(doc.id)) {
+            names.truncate(depth + 2);
+            names.push(&d.visible_name);
+            all.push((join_path(&names), d));
+        }
+    }
+    all
+}
+
+/// Which of what's in the trash `empty` deletes, and how.
+pub struct EmptyOptions {
+    /// Only what was trashed before then, if given.
+    pub cutoff: Option<chrono::DateTime<chrono::Utc>>,
+    /// How many of the most recently trashed to leave.
+    pub keep_latest: usize,
+    /// Whether to only say what would be deleted.
+    pub dry_run: bool,
+    /// Whether the listing is the cached one, so that what's to be deleted
+    /// is checked against the cloud first.
+    pub cached: bool,
+    /// Whether to go ahead without asking.
+    pub yes: bool,
+}
+
+/// Deletes for good what's in the trash, as `options` picks it, along with
+/// everything in the folders among it, reporting to `out` and asking it first
+/// unless `options` says not to.
+pub async fn empty(
+    client: &Client,
+    mutations: &MutationLog,
+    documents: &Documents,
+    options: &EmptyOptions,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let roots = candidates(documents, options.cutoff, options.keep_latest);
+    let selected = with_contents(documents, &roots);
+    if selected.is_empty() {
+        out.note(&msg!(NOTHING_TO_DELETE));
+        return Ok(());
+    }
+    if options.dry_run {
+        for (path, _) in &selected {
+            out.line(&msg!(WOULD_DELETE, path = path));
+        }
+        return Ok(());
+    }
+    if options.cached {
+        targets::check_unchanged_in_trash(client, &selected).await?;
+    }
+    let confirmed = targets::confirm_each(
+        &messages::CONFIRM_DELETE,
+        &selected,
+        options.yes,
+        out,
+    )?;
+    if !confirmed {
+        out.line(&msg!(NOTHING_DONE));
+        return Ok(());
+    }
+    delete_subtrees(client, mutations, documents, &roots, &selected, out).await
+}
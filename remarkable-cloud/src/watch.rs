@@ -0,0 +1,467 @@
+//! `watch`: reporting documents as they're added, updated and removed.
+//!
+//! The cloud's listing is fetched every `--interval` and compared with what
+//! was last reported, which is kept in [`STATE_FILE`] in the config
+//! directory, so changes made while `watch` wasn't running are reported
+//! when it next looks. A change is only reported once the document has
+//! stayed as it is for `--debounce`, so one written in several steps is
+//! reported once. Folders are followed, so paths stay right, but not
+//! reported on.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use remarkable_cloud_api::{CancellationToken, Client, Document, Documents};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::cache::ListingCache;
+use crate::commands::Output;
+use crate::help::Example;
+use crate::webhook::{Deliveries, Delivery};
+use crate::{msg, CliResult};
+
+/// The file in the config directory holding the listing as last reported.
+pub const STATE_FILE: &str = "watch-state.json";
+
+/// How long `watch` waits between looks, unless told otherwise.
+pub const DEFAULT_INTERVAL: &str = "30s";
+
+/// How long a document has to stay as it is before a change to it is
+/// reported, unless told otherwise.
+pub const DEFAULT_DEBOUNCE: &str = "10s";
+
+// How long to wait after the cloud couldn't be reached, at first and at
+// most.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// The examples `watch --help` shows; see also `help webhooks`.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        command: "remarkable-cloud watch",
+        description: "Prints each change as a line of JSON, until \
+                      interrupted",
+    },
+    Example {
+        command: "remarkable-cloud watch --once",
+        description: "Prints what's changed since watch last looked",
+    },
+    Example {
+        command: "remarkable-cloud watch --webhook \
+                  http://localhost:8123/api/webhook/remarkable",
+        description: "Sends each change to a webhook, signed with the \
+                      webhook_secret in settings.json",
+    },
+    Example {
+        command: "remarkable-cloud watch --replay-spool",
+        description: "Sends the changes which couldn't be delivered before",
+    },
+];
+
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Added,
+    Updated,
+    /// Deleted, or moved to the trash.
+    Removed,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Added => "added",
+            EventKind::Updated => "updated",
+            EventKind::Removed => "removed",
+        }
+    }
+}
+
+/// A change to a document, as `watch` prints it and webhooks are sent it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Event {
+    pub event: EventKind,
+    pub id: Uuid,
+    pub name: String,
+    pub path: String,
+    /// The document's version once changed, or for one removed the last
+    /// version seen of it.
+    pub version: u64,
+    /// When the change was first seen.
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Event {
+    /// A few words on the change, as "updated Books/Dune (version 3)".
+    pub fn describe(&self) -> String {
+        msg!(
+            WATCH_EVENT,
+            event = self.event.as_str(),
+            path = &self.path,
+            version = self.version,
+        )
+    }
+}
+
+// A change seen but not yet reported, and when the document was last seen
+// changing.
+struct Pending {
+    event: Event,
+    document: Option<Document>,
+    since: Instant,
+}
+
+/// Works out what to report from the listings fetched one after another.
+pub struct Watcher {
+    reported: Documents,
+    pending: BTreeMap<Uuid, Pending>,
+    debounce: Duration,
+}
+
+impl Watcher {
+    /// A watcher reporting changes since `reported`, once they've stayed
+    /// put for `debounce`.
+    pub fn new(reported: Documents, debounce: Duration) -> Self {
+        Watcher {
+            reported,
+            pending: BTreeMap::new(),
+            debounce,
+        }
+    }
+
+    /// The listing as last reported, to be saved for the next run. Changes
+    /// not yet reported aren't in it, so they're seen again then.
+    pub fn reported(&self) -> &Documents {
+        &self.reported
+    }
+
+    /// Compares `documents`, fetched `now`, at `timestamp`, with what was
+    /// reported before, returning the changes which have settled, in the
+    /// order they were first seen.
+    pub fn observe(
+        &mut self,
+        documents: &Documents,
+        now: Instant,
+        timestamp: DateTime<Utc>,
+    ) -> Vec<Event> {
+        let diff = documents.diff(&self.reported);
+        let mut changed = BTreeMap::new();
+        for doc in diff.added.iter().chain(&diff.updated) {
+            if doc.is_folder() {
+                self.reported.insert((*doc).clone());
+                continue;
+            }
+            let event = match self.reported.get(&doc.id) {
+                Some(_) => EventKind::Updated,
+                None => EventKind::Added,
+            };
+            let path = documents
+                .path_of(&doc.id)
+                .unwrap_or_else(|| doc.visible_name.to_string());
+            changed.insert(
+                doc.id,
+                (
+                    Event {
+                        event,
+                        id: doc.id,
+                        name: doc.visible_name.to_string(),
+                        path,
+                        version: doc.version,
+                        timestamp,
+                    },
+                    Some((*doc).clone()),
+                ),
+            );
+        }
+        for id in &diff.removed {
+            let doc = match self.reported.get(id) {
+                Some(doc) if !doc.is_folder() => doc,
+                _ => {
+                    self.reported.remove(id);
+                    continue;
+                }
+            };
+            let path = self
+                .reported
+                .path_of(id)
+                .unwrap_or_else(|| doc.visible_name.to_string());
+            let event = Event {
+                event: EventKind::Removed,
+                id: *id,
+                name: doc.visible_name.to_string(),
+                path,
+                version: doc.version,
+                timestamp,
+            };
+            changed.insert(*id, (event, None));
+        }
+
+        // Whatever changed back, or went before it was reported, is
+        // forgotten; whatever changed again starts waiting afresh.
+        self.pending.retain(|id, _| changed.contains_key(id));
+        for (id, (event, document)) in changed {
+            let same = self.pending.get(&id).is_some_and(|p| {
+                (p.event.event, p.event.version) == (event.event, event.version)
+            });
+            if !same {
+                self.pending.insert(
+                    id,
+                    Pending {
+                        event,
+                        document,
+                        since: now,
+                    },
+                );
+            }
+        }
+
+        let settled: Vec<Uuid> = self
+            .pending
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.since) >= self.debounce)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut events = vec![];
+        for id in settled {
+            let pending = self.pending.remove(&id).unwrap();
+            match pending.document {
+                Some(doc) => self.reported.insert(doc),
+                None => {
+                    self.reported.remove(&id);
+                }
+            }
+            events.push(pending.event);
+        }
+        events.sort_by(|a, b| {
+            (a.timestamp, &a.path, a.id).cmp(&(b.timestamp, &b.path, b.id))
+        });
+        events
+    }
+}
+
+/// What `watch` was asked to do.
+pub struct WatchOptions {
+    pub interval: Duration,
+    pub debounce: Duration,
+    /// Where to send changes; if none, they're printed as lines of JSON.
+    pub webhooks: Vec<String>,
+    /// Looks once, reporting everything changed since the last look.
+    pub once: bool,
+    /// Where the listing as last reported is kept.
+    pub state: ListingCache,
+}
+
+/// Reports changes until cancelled, or after one look with `once`, waiting
+/// longer between tries while the cloud can't be reached.
+pub async fn watch(
+    client: &Client,
+    options: &WatchOptions,
+    mut deliveries: Option<&mut Deliveries>,
+    cancellation: &CancellationToken,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    // Looking once, nothing will be seen again, so nothing waits.
+    let debounce = if options.once {
+        Duration::default()
+    } else {
+        options.debounce
+    };
+    let mut watcher = options
+        .state
+        .load()
+        .map(|reported| Watcher::new(reported, debounce));
+    let mut retry_delay = RETRY_DELAY;
+    loop {
+        let wait = match client.get_documents().await {
+            Ok(documents) => {
+                retry_delay = RETRY_DELAY;
+                let watcher = watcher.get_or_insert_with(|| {
+                    let n = documents.iter().filter(|d| !d.is_folder()).count();
+                    let note = msg!(WATCH_STARTED, count = n);
+                    // Kept off stdout when the changes are printed there.
+                    if options.webhooks.is_empty() {
+                        out.warn(&note);
+                    } else {
+                        out.note(&note);
+                    }
+                    Watcher::new(documents.clone(), debounce)
+                });
+                let events =
+                    watcher.observe(&documents, Instant::now(), Utc::now());
+                for event in &events {
+                    report(event, options, deliveries.as_deref_mut(), out)
+                        .await?;
+                }
+                // Saved once reported, so a change is seen again if it
+                // wasn't; webhooks aren't sent it twice regardless.
+                options.state.save(watcher.reported())?;
+                options.interval
+            }
+            Err(e) if e.is_transient() && !options.once => {
+                let wait = retry_delay;
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                out.warn(&msg!(
+                    WATCH_UNLISTED,
+                    error = e.to_string(),
+                    wait = humantime::format_duration(wait).to_string(),
+                ));
+                wait
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if options.once {
+            return Ok(());
+        }
+        let delay = tokio::time::delay_for(wait);
+        let cancelled = cancellation.cancelled();
+        futures_util::pin_mut!(cancelled);
+        if let futures_util::future::Either::Right(_) =
+            futures_util::future::select(delay, cancelled).await
+        {
+            return Ok(());
+        }
+    }
+}
+
+// Prints `event`, or sends it to each webhook.
+async fn report(
+    event: &Event,
+    options: &WatchOptions,
+    deliveries: Option<&mut Deliveries>,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let deliveries = match deliveries {
+        Some(deliveries) => deliveries,
+        None => {
+            out.line(&serde_json::to_string(event)?);
+            return Ok(());
+        }
+    };
+    for url in &options.webhooks {
+        match deliveries.send(url, event).await? {
+            Delivery::Delivered => out.note(&msg!(
+                WEBHOOK_SENT,
+                event = event.describe(),
+                url = url,
+            )),
+            Delivery::Duplicate => out.note(&msg!(
+                WEBHOOK_DUPLICATE,
+                event = event.describe(),
+                url = url,
+            )),
+            Delivery::Spooled(reason) => out.warn(&msg!(
+                WEBHOOK_SPOOLED,
+                event = event.describe(),
+                url = url,
+                reason = reason,
+            )),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::listing;
+
+    fn summary(events: &[Event]) -> Vec<String> {
+        events.iter().map(Event::describe).collect()
+    }
+
+    #[test]
+    fn observe() {
+        let earlier = listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+            (3, "Emma", Some(1), "DocumentType"),
+        ]);
+        let debounce = Duration::from_secs(10);
+        let mut watcher = Watcher::new(earlier.clone(), debounce);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let now = Utc::now();
+
+        assert!(watcher.observe(&earlier, at(0), now).is_empty());
+        let mut later = earlier.clone();
+        let mut dune = later.get(&uuid(2)).unwrap().clone();
+        dune.version += 1;
+        later.insert(dune.clone());
+        later.remove(&uuid(3));
+        let mut hyperion = dune.clone();
+        hyperion.id = uuid(4);
+        hyperion.visible_name = "Hyperion".into();
+        later.insert(hyperion);
+        // Seen, but not settled yet.
+        assert!(watcher.observe(&later, at(1), now).is_empty());
+        assert!(watcher.observe(&later, at(5), now).is_empty());
+        assert_eq!(
+            summary(&watcher.observe(&later, at(11), now)),
+            [
+                "updated Books/Dune (version 2)",
+                "removed Books/Emma (version 1)",
+                "added Books/Hyperion (version 2)",
+            ]
+        );
+        assert!(watcher.observe(&later, at(30), now).is_empty());
+        assert_eq!(watcher.reported(), &later);
+
+        // Changing again starts the wait again.
+        dune.version += 1;
+        later.insert(dune.clone());
+        assert!(watcher.observe(&later, at(31), now).is_empty());
+        dune.version += 1;
+        later.insert(dune.clone());
+        assert!(watcher.observe(&later, at(40), now).is_empty());
+        assert_eq!(
+            summary(&watcher.observe(&later, at(50), now)),
+            ["updated Books/Dune (version 4)"]
+        );
+
+        // Gone before it settled, so never reported.
+        let mut brief = dune.clone();
+        brief.id = uuid(5);
+        later.insert(brief);
+        assert!(watcher.observe(&later, at(60), now).is_empty());
+        later.remove(&uuid(5));
+        assert!(watcher.observe(&later, at(80), now).is_empty());
+    }
+
+    #[test]
+    fn folders() {
+        let earlier = listing(&[
+            (1, "Books", None, "CollectionType"),
+            (2, "Dune", Some(1), "DocumentType"),
+        ]);
+        let mut watcher = Watcher::new(earlier.clone(), Duration::default());
+        let mut later = earlier.clone();
+        let mut books = later.get(&uuid(1)).unwrap().clone();
+        books.visible_name = "Novels".into();
+        books.version += 1;
+        later.insert(books);
+        let now = Instant::now();
+        assert!(watcher.observe(&later, now, Utc::now()).is_empty());
+        later.remove(&uuid(2));
+        assert_eq!(
+            summary(&watcher.observe(&later, now, Utc::now())),
+            ["removed Novels/Dune (version 1)"]
+        );
+    }
+
+    fn uuid(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+}
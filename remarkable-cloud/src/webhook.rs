@@ -0,0 +1,526 @@
+//! Sending what `watch` sees to webhooks, as `help webhooks` describes.
+//!
+//! Each event is POSTed as JSON, signed with the HMAC-SHA256 of the body,
+//! keyed with `webhook_secret` from settings.json, in [`SIGNATURE_HEADER`].
+//! A delivery which fails is tried again after a second, then two, four and
+//! so on; one which still fails is spooled in [`SPOOL_DIR`] in the config
+//! directory, for `watch --replay-spool` to send once the receiver is back.
+//!
+//! Which versions of which documents each URL has been sent are kept in
+//! [`DELIVERED_FILE`], and nothing is sent to a URL twice: not by a replay
+//! of what a later `watch` already sent, nor by a `watch` seeing again what
+//! was sent before it was stopped. Receivers can rely on the event, id and
+//! version to tell deliveries apart should one be sent again regardless, as
+//! when a receiver takes an event but its answer is lost.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac, NewMac};
+use remarkable_cloud_api::{replace_locked, write_atomically};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::commands::Output;
+use crate::watch::{Event, EventKind};
+use crate::{msg, CliResult};
+
+/// The header carrying a delivery's signature, as `sha256=` and the HMAC in
+/// hex.
+pub const SIGNATURE_HEADER: &str = "X-Remarkable-Signature";
+
+/// The directory in the config directory holding undelivered events.
+pub const SPOOL_DIR: &str = "webhook-spool";
+
+/// The file in the config directory recording what's been delivered.
+pub const DELIVERED_FILE: &str = "webhook-delivered.json";
+
+/// How many times a delivery is tried again before it's spooled, unless
+/// told otherwise.
+pub const DEFAULT_RETRIES: u32 = 3;
+
+// How long to wait before trying a delivery again the first time; each try
+// after waits twice as long as the one before.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The signature of `body`, as `SIGNATURE_HEADER` carries it.
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret)
+        .expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+// The error for a file at `path` which isn't the JSON it should be.
+fn unparsable(path: &Path, e: serde_json::Error) -> String {
+    msg!(
+        FILE_UNPARSABLE,
+        path = format!("{:?}", path),
+        error = e.to_string(),
+    )
+}
+
+/// The highest version of each document each URL has been sent, for each
+/// kind of event.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+pub struct Delivered {
+    urls: BTreeMap<String, BTreeMap<Uuid, BTreeMap<EventKind, u64>>>,
+}
+
+impl Delivered {
+    /// Reads what's been delivered from `path`, or nothing if there's no
+    /// file.
+    pub fn load(path: &Path) -> CliResult<Self> {
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)
+                .map_err(|e| unparsable(path, e))?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Ok(Delivered::default())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        write_atomically(path, &serde_json::to_vec(self)?)
+    }
+
+    /// Whether `url` has been sent `event`, or the same kind of event for a
+    /// later version of the document.
+    pub fn contains(&self, url: &str, event: &Event) -> bool {
+        self.urls
+            .get(url)
+            .and_then(|ids| ids.get(&event.id))
+            .and_then(|kinds| kinds.get(&event.event))
+            .is_some_and(|version| *version >= event.version)
+    }
+
+    pub fn insert(&mut self, url: &str, event: &Event) {
+        let version = self
+            .urls
+            .entry(url.to_string())
+            .or_default()
+            .entry(event.id)
+            .or_default()
+            .entry(event.event)
+            .or_default();
+        *version = event.version.max(*version);
+    }
+}
+
+/// An event which couldn't be delivered, and where it was going.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Spooled {
+    pub url: String,
+    pub event: Event,
+}
+
+/// The events waiting to be replayed, a file each.
+pub struct Spool {
+    dir: PathBuf,
+}
+
+impl Spool {
+    pub fn new(dir: PathBuf) -> Self {
+        Spool { dir }
+    }
+
+    /// Keeps `spooled` for later. The same event for the same URL is only
+    /// kept once.
+    pub fn add(&self, spooled: &Spooled) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let url = format!("{:x}", Sha256::digest(spooled.url.as_bytes()));
+        let name = format!(
+            "{}-{}-{}-{}.json",
+            spooled.event.id,
+            spooled.event.version,
+            spooled.event.event.as_str(),
+            &url[..8]
+        );
+        // Renamed into place whole, so it needs no lock file left beside it.
+        replace_locked(&self.dir.join(name), &serde_json::to_vec(spooled)?)
+    }
+
+    /// What's spooled, each with the file it's in, in the order the events
+    /// were seen.
+    pub fn entries(&self) -> CliResult<Vec<(PathBuf, Spooled)>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = vec![];
+        for entry in dir {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let spooled: Spooled = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| unparsable(&path, e))?;
+            entries.push((path, spooled));
+        }
+        entries.sort_by(|(a_path, a), (b_path, b)| {
+            (a.event.timestamp, a_path).cmp(&(b.event.timestamp, b_path))
+        });
+        Ok(entries)
+    }
+}
+
+/// What became of an event sent to a webhook.
+#[derive(Debug, PartialEq)]
+pub enum Delivery {
+    Delivered,
+    /// The URL had been sent it already.
+    Duplicate,
+    /// It couldn't be delivered, for the reason given, and was spooled.
+    Spooled(String),
+}
+
+/// How `watch --replay-spool` went.
+#[derive(Debug, Default, PartialEq)]
+pub struct ReplayReport {
+    pub delivered: usize,
+    /// Those delivered before they were replayed, and dropped.
+    pub duplicates: usize,
+    /// Those which couldn't be delivered again, and are still spooled.
+    pub failed: usize,
+}
+
+/// Sends events to webhooks, keeping track of what's been delivered and
+/// spooling what can't be.
+pub struct Deliveries {
+    http: reqwest::Client,
+    secret: Vec<u8>,
+    retries: u32,
+    first_retry_delay: Duration,
+    delivered: Delivered,
+    delivered_path: PathBuf,
+    spool: Spool,
+}
+
+impl Deliveries {
+    /// Deliveries signed with `secret`, each tried `retries` more times
+    /// before it's spooled, keeping their records in `config_dir`.
+    pub fn new(
+        config_dir: &Path,
+        secret: &str,
+        retries: u32,
+    ) -> CliResult<Self> {
+        let delivered_path = config_dir.join(DELIVERED_FILE);
+        Ok(Deliveries {
+            http: reqwest::Client::new(),
+            secret: secret.as_bytes().to_vec(),
+            retries,
+            first_retry_delay: FIRST_RETRY_DELAY,
+            delivered: Delivered::load(&delivered_path)?,
+            delivered_path,
+            spool: Spool::new(config_dir.join(SPOOL_DIR)),
+        })
+    }
+
+    /// Sends `event` to `url` unless it's been sent there already, spooling
+    /// it if it can't be.
+    pub async fn send(
+        &mut self,
+        url: &str,
+        event: &Event,
+    ) -> CliResult<Delivery> {
+        if self.delivered.contains(url, event) {
+            return Ok(Delivery::Duplicate);
+        }
+        match self.post(url, event).await {
+            Ok(()) => {
+                self.record(url, event)?;
+                Ok(Delivery::Delivered)
+            }
+            Err(reason) => {
+                self.spool.add(&Spooled {
+                    url: url.to_string(),
+                    event: event.clone(),
+                })?;
+                Ok(Delivery::Spooled(reason))
+            }
+        }
+    }
+
+    /// Sends everything spooled to where it was going, dropping what's been
+    /// delivered since and leaving what still can't be.
+    pub async fn replay(&mut self) -> CliResult<ReplayReport> {
+        let mut report = ReplayReport::default();
+        for (path, spooled) in self.spool.entries()? {
+            let (url, event) = (&spooled.url, &spooled.event);
+            if self.delivered.contains(url, event) {
+                report.duplicates += 1;
+            } else if self.post(url, event).await.is_ok() {
+                self.record(url, event)?;
+                report.delivered += 1;
+            } else {
+                report.failed += 1;
+                continue;
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok(report)
+    }
+
+    // Records that `url` has been sent `event`.
+    fn record(&mut self, url: &str, event: &Event) -> io::Result<()> {
+        self.delivered.insert(url, event);
+        self.delivered.save(&self.delivered_path)
+    }
+
+    // POSTs `event` to `url`, trying again as the module describes, and
+    // returning why the last try failed if they all did.
+    async fn post(&self, url: &str, event: &Event) -> Result<(), String> {
+        let body = serde_json::to_vec(event).map_err(|e| e.to_string())?;
+        let signature = sign(&self.secret, &body);
+        let mut delay = self.first_retry_delay;
+        let mut tries = 0;
+        loop {
+            let sent = self
+                .http
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .body(body.clone())
+                .send()
+                .await;
+            let failure = match sent {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => msg!(
+                    WEBHOOK_ANSWERED,
+                    url = url,
+                    status = response.status().to_string(),
+                ),
+                Err(e) => e.to_string(),
+            };
+            if tries == self.retries {
+                return Err(failure);
+            }
+            tries += 1;
+            tokio::time::delay_for(delay).await;
+            delay *= 2;
+        }
+    }
+}
+
+/// Replays the spool, as `watch --replay-spool` does, telling `out` how it
+/// went and failing if anything in it still can't be delivered.
+pub async fn replay_spool(
+    deliveries: &mut Deliveries,
+    out: &mut dyn Output,
+) -> CliResult<()> {
+    let report = deliveries.replay().await?;
+    out.note(&msg!(
+        SPOOL_REPLAYED,
+        delivered = report.delivered,
+        duplicates = report.duplicates,
+        failed = report.failed,
+    ));
+    if report.failed > 0 {
+        return Err(msg!(SPOOL_UNDELIVERED).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use chrono::Utc;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, StatusCode};
+
+    use super::*;
+
+    // A delivery's signature and body.
+    type Taken = (String, Vec<u8>);
+
+    // A receiver taking the deliveries it's sent, unless `failing` says to
+    // turn the next one away.
+    #[derive(Clone, Default)]
+    struct Receiver {
+        taken: Arc<Mutex<Vec<Taken>>>,
+        failing: Arc<Mutex<Vec<bool>>>,
+    }
+
+    impl Receiver {
+        fn start(&self) -> String {
+            let receiver = self.clone();
+            let make = make_service_fn(move |_| {
+                let receiver = receiver.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req| {
+                        let receiver = receiver.clone();
+                        async move { Ok::<_, Infallible>(receiver.take(req).await) }
+                    }))
+                }
+            });
+            let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+            let server = hyper::Server::bind(&addr).serve(make);
+            let url = format!("http://{}/hook", server.local_addr());
+            tokio::spawn(server);
+            url
+        }
+
+        async fn take(&self, req: Request<Body>) -> Response<Body> {
+            let signature = req.headers()[SIGNATURE_HEADER]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+            let fail = {
+                let mut failing = self.failing.lock().unwrap();
+                if failing.is_empty() {
+                    false
+                } else {
+                    failing.remove(0)
+                }
+            };
+            let status = if fail {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                self.taken.lock().unwrap().push((signature, body.to_vec()));
+                StatusCode::NO_CONTENT
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        }
+
+        fn fail(&self, pattern: &[bool]) {
+            *self.failing.lock().unwrap() = pattern.to_vec();
+        }
+
+        fn taken(&self) -> Vec<Event> {
+            self.taken
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(signature, body)| {
+                    assert_eq!(signature, &sign(b"s3cret", body));
+                    serde_json::from_slice(body).unwrap()
+                })
+                .collect()
+        }
+    }
+
+    fn event(kind: EventKind, n: u128, version: u64) -> Event {
+        Event {
+            event: kind,
+            id: Uuid::from_u128(n),
+            name: "Dune".to_string(),
+            path: "Books/Dune".to_string(),
+            version,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn deliveries(dir: &Path, retries: u32) -> Deliveries {
+        let mut deliveries = Deliveries::new(dir, "s3cret", retries).unwrap();
+        deliveries.first_retry_delay = Duration::from_millis(10);
+        deliveries
+    }
+
+    #[test]
+    fn signs() {
+        // As RFC 4231's second test case has it.
+        let signature = sign(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            signature,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn delivered() {
+        let mut delivered = Delivered::default();
+        let url = "http://localhost/hook";
+        delivered.insert(url, &event(EventKind::Updated, 1, 3));
+        assert!(delivered.contains(url, &event(EventKind::Updated, 1, 3)));
+        assert!(delivered.contains(url, &event(EventKind::Updated, 1, 2)));
+        assert!(!delivered.contains(url, &event(EventKind::Updated, 1, 4)));
+        assert!(!delivered.contains(url, &event(EventKind::Removed, 1, 3)));
+        assert!(!delivered.contains(url, &event(EventKind::Updated, 2, 3)));
+        assert!(!delivered
+            .contains("http://elsewhere", &event(EventKind::Updated, 1, 3)));
+        // Never back to an earlier version.
+        delivered.insert(url, &event(EventKind::Updated, 1, 1));
+        assert!(delivered.contains(url, &event(EventKind::Updated, 1, 3)));
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn retries_then_spools() {
+        let receiver = Receiver::default();
+        let url = receiver.start();
+        let dir = tempfile::tempdir().unwrap();
+        let mut deliveries = deliveries(dir.path(), 2);
+
+        let dune = event(EventKind::Updated, 1, 2);
+        receiver.fail(&[true, true]);
+        assert_eq!(
+            deliveries.send(&url, &dune).await.unwrap(),
+            Delivery::Delivered
+        );
+        assert_eq!(
+            deliveries.send(&url, &dune).await.unwrap(),
+            Delivery::Duplicate
+        );
+
+        let emma = event(EventKind::Added, 2, 1);
+        receiver.fail(&[true, true, true]);
+        match deliveries.send(&url, &emma).await.unwrap() {
+            Delivery::Spooled(reason) => {
+                assert!(reason.contains("503"), "{}", reason)
+            }
+            delivery => panic!("{:?}", delivery),
+        }
+        let spool = Spool::new(dir.path().join(SPOOL_DIR));
+        assert_eq!(spool.entries().unwrap().len(), 1);
+
+        // What's recorded holds for a later run.
+        let mut deliveries = self::deliveries(dir.path(), 0);
+        receiver.fail(&[true]);
+        assert_eq!(
+            deliveries.replay().await.unwrap(),
+            ReplayReport {
+                failed: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            deliveries.replay().await.unwrap(),
+            ReplayReport {
+                delivered: 1,
+                ..Default::default()
+            }
+        );
+        assert!(spool.entries().unwrap().is_empty());
+        assert_eq!(receiver.taken(), [dune.clone(), emma.clone()]);
+
+        // Spooled again after it was delivered, as when a run is stopped
+        // between the two.
+        spool
+            .add(&Spooled {
+                url: url.clone(),
+                event: emma,
+            })
+            .unwrap();
+        assert_eq!(
+            deliveries.replay().await.unwrap(),
+            ReplayReport {
+                duplicates: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(receiver.taken().len(), 2);
+        assert!(spool.entries().unwrap().is_empty());
+    }
+}
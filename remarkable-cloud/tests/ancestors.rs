@@ -0,0 +1,46 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn breadcrumbs_and_cycles() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let scifi = cloud.add_folder("Sci-fi", Some(books));
+    cloud.add_document("Dune", Some(scifi), vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["info", "Books/Sci-fi/Dune"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("In: Books > Sci-fi\n"), "{}", stdout);
+
+    for dest in &["Books/Sci-fi", "Books"] {
+        let args = ["mv", "Books", dest];
+        let output = run(&cloud, home.path(), &args, b"").await;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("into itself"), "{}", stderr);
+    }
+    assert_eq!(cloud.document(&books).unwrap().parent, None);
+
+    // Folders beside each other are fine.
+    let args = ["mv", "Books/Sci-fi", "/"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let moved = cloud.document(&scifi).unwrap();
+    assert_eq!((moved.parent, moved.version), (None, 2));
+
+    // And back again, a version on.
+    let args = ["mv", "Sci-fi", "Books"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let moved = cloud.document(&scifi).unwrap();
+    assert_eq!((moved.parent, moved.version), (Some(books), 3));
+    let args = ["info", "Books/Sci-fi/Dune"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("In: Books > Sci-fi\n"), "{}", stdout);
+}
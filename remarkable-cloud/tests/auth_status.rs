@@ -0,0 +1,23 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn auth_status() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["auth", "status", "--check"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Account: auth0|fake-user"), "{}", stdout);
+    assert!(stdout.contains("Reached the cloud in"), "{}", stdout);
+    assert!(stdout.contains("Uploads supported: yes"), "{}", stdout);
+    assert!(!stdout.contains("fake-device-token"), "{}", stdout);
+}
@@ -0,0 +1,120 @@
+use std::process::Output;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::await_document::{Found, TIMED_OUT_EXIT_CODE};
+
+mod common;
+use common::run;
+
+fn found(output: &Output) -> Found {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn waits_for_appearance() {
+    let cloud = FakeCloud::start().await;
+    let scans = cloud.add_folder("Scans", None);
+    let home = tempfile::tempdir().unwrap();
+    let args = ["await-document", "Scans/receipt", "--interval", "100ms"];
+
+    // Uploaded by something else a little after the wait starts.
+    let (output, id) =
+        futures_util::join!(run(&cloud, home.path(), &args, b""), async {
+            tokio::time::delay_for(Duration::from_millis(500)).await;
+            cloud.add_document("receipt", Some(scans), vec![])
+        });
+    assert_eq!(
+        found(&output),
+        Found {
+            id,
+            version: 1,
+            path: "Scans/receipt".to_string(),
+        }
+    );
+
+    // Already there, it's found straight away.
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(found(&output).id, id);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn waits_for_version() {
+    let cloud = FakeCloud::start().await;
+    let id = cloud.add_document("receipt", None, vec![]);
+    // The first time the new version is listed, fetching the document by id
+    // still gives the old one, as the cloud does while it catches up.
+    let lagging = Arc::new(AtomicUsize::new(0));
+    let counted = lagging.clone();
+    cloud.set_listing_rewrite(move |doc| {
+        let by_id = doc["BlobURLGet"].as_str().is_some_and(|u| !u.is_empty());
+        if by_id
+            && doc["Version"] == 2
+            && counted.fetch_add(1, Ordering::SeqCst) == 0
+        {
+            doc["Version"] = 1.into();
+        }
+    });
+    let home = tempfile::tempdir().unwrap();
+    let args = [
+        "await-document",
+        "receipt",
+        "--min-version",
+        "2",
+        "--interval",
+        "100ms",
+    ];
+    let (output, _) =
+        futures_util::join!(run(&cloud, home.path(), &args, b""), async {
+            tokio::time::delay_for(Duration::from_millis(500)).await;
+            cloud.modify(&id, |d| d.version = 2);
+        });
+    assert_eq!(found(&output).version, 2);
+    assert_eq!(found(&output).id, id);
+    assert!(lagging.load(Ordering::SeqCst) >= 2);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("/receipt is listed at version 2"),
+        "{}",
+        stderr
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn times_out() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_document("receipt", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["await-document", "Scans/receipt", "--timeout", "1"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(output.status.code(), Some(TIMED_OUT_EXIT_CODE));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Nothing came to /Scans/receipt in 1s"),
+        "{}",
+        stderr
+    );
+
+    let args = [
+        "await-document",
+        "receipt",
+        "--min-version",
+        "3",
+        "--timeout",
+        "0",
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(output.status.code(), Some(TIMED_OUT_EXIT_CODE));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("/receipt was still at version 1"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use remarkable_cloud_api::testing::{FakeCloud, FakeDocument};
+use remarkable_cloud_api::DocType;
+
+mod common;
+use common::run;
+
+// Set to rewrite the stored archive after a deliberate change to the format.
+const UPDATE_VAR: &str = "UPDATE_GOLDEN";
+
+fn golden_path() -> &'static Path {
+    Path::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/reproducible.tar.zst"
+    ))
+}
+
+fn insert(
+    cloud: &FakeCloud,
+    id: u128,
+    name: &str,
+    parent: Option<u128>,
+    doc_type: DocType,
+) {
+    cloud.insert(FakeDocument {
+        id: uuid::Uuid::from_u128(id),
+        version: 3,
+        visible_name: name.to_string(),
+        parent: parent.map(uuid::Uuid::from_u128),
+        trashed: false,
+        blob: if doc_type == DocType::Document {
+            format!("zip of {}", name).into_bytes()
+        } else {
+            vec![]
+        },
+        doc_type,
+        current_page: 0,
+        bookmarked: false,
+        modified_client: "2023-04-01T09:30:00Z".parse().unwrap(),
+    });
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn reproducible_backup() {
+    let cloud = FakeCloud::start().await;
+    insert(&cloud, 1, "Books", None, DocType::Collection);
+    insert(&cloud, 2, "Dune", Some(1), DocType::Document);
+    insert(&cloud, 3, "Notes", None, DocType::Document);
+    let home = tempfile::tempdir().unwrap();
+
+    let mut archives = vec![];
+    for name in &["first.tar.zst", "second.tar.zst"] {
+        let output = home.path().join(name);
+        let args = ["backup", "--reproducible", "-o", output.to_str().unwrap()];
+        let result = run(&cloud, home.path(), &args, b"").await;
+        assert!(
+            result.status.success(),
+            "{}",
+            String::from_utf8_lossy(&result.stderr)
+        );
+        archives.push(std::fs::read(&output).unwrap());
+    }
+    assert!(archives[0] == archives[1]);
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        std::fs::write(golden_path(), &archives[0]).unwrap();
+    }
+    assert!(
+        archives[0] == std::fs::read(golden_path()).unwrap(),
+        "backup differs from {}; set {} to update it",
+        golden_path().display(),
+        UPDATE_VAR
+    );
+
+    // Leaving a document out would give a different archive.
+    cloud.fail_next(&format!("/blob/{}", uuid::Uuid::from_u128(3)), false);
+    let output = home.path().join("third.tar.zst");
+    let args = ["backup", "--reproducible", "-o", output.to_str().unwrap()];
+    let result = run(&cloud, home.path(), &args, b"").await;
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("needs them all"), "{}", stderr);
+    assert!(!output.exists());
+    assert!(!home.path().join("third.tar.zst.partial").exists());
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use filetime::FileTime;
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::{lock_path, FileLock};
+
+mod common;
+use common::run;
+
+// Makes the cached listing under `home` look `age` old.
+fn age_cache(home: &Path, age: Duration) {
+    let cache = home.join("cache/remarkable-cloud/listing.json");
+    let then = FileTime::from_system_time(SystemTime::now() - age);
+    filetime::set_file_mtime(cache, then).unwrap();
+}
+
+fn listings(cloud: &FakeCloud) -> usize {
+    cloud
+        .requests()
+        .iter()
+        .filter(|r| r.path.ends_with("/docs") && r.query.is_empty())
+        .count()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn stale_cache_refreshed() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    cloud.add_document("Dune", Some(books), vec![]);
+    cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let args = ["ls", "-r", "--paths", "--cached"];
+    let output = run(&cloud, home.path(), &args[..3], b"").await;
+    assert!(output.status.success());
+    let before = output.stdout;
+
+    // A fresh cache is just read.
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(output.stdout, before);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(listings(&cloud), 1);
+
+    // A stale one is shown as it is, and refreshed after.
+    cloud.add_document("Dune Messiah", Some(books), vec![]);
+    age_cache(home.path(), Duration::from_secs(3 * 3600 + 60));
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(output.stdout, before);
+    assert_eq!(stderr, "(cache was 3h old; refreshed for next run)\n");
+    assert_eq!(listings(&cloud), 2);
+
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Books/Dune Messiah"), "{}", stdout);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    // Scripts are told how old the listing is.
+    age_cache(home.path(), Duration::from_secs(2 * 3600));
+    let args = [
+        "find",
+        "--duplicates-of",
+        "Books/Dune",
+        "--by-name",
+        "--json",
+        "--cached",
+        "--no-refresh",
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    let age = json["cache_age_seconds"].as_u64().unwrap();
+    assert!((2 * 3600..2 * 3600 + 60).contains(&age), "{}", age);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(listings(&cloud), 2);
+
+    // Without --cached, the listing is fetched and there's no age to give.
+    let output = run(&cloud, home.path(), &args[..5], b"").await;
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("cache_age_seconds").is_none(), "{}", json);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn refresh_skipped_when_locked() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    assert!(output.status.success());
+    age_cache(home.path(), Duration::from_secs(3 * 3600 + 60));
+    let cache = home.path().join("cache/remarkable-cloud/listing.json");
+    let saved_at = std::fs::metadata(&cache).unwrap().modified().unwrap();
+
+    // Another process is reading the cache.
+    let reading = FileLock::shared(&lock_path(&cache)).unwrap();
+    let output = run(&cloud, home.path(), &["ls", "--cached"], b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("3h old; not refreshed, as another process"),
+        "{}",
+        stderr
+    );
+    drop(reading);
+    let modified = std::fs::metadata(&cache).unwrap().modified().unwrap();
+    assert_eq!(modified, saved_at);
+}
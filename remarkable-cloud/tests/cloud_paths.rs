@@ -0,0 +1,56 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+// The same ways of writing a path work whichever command they're given to.
+#[tokio::test(threaded_scheduler)]
+async fn same_grammar_everywhere() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", None, vec![]);
+    let old = cloud.add_document("Old notes", None, vec![]);
+    cloud.modify(&old, |d| d.trashed = true);
+    let home = tempfile::tempdir().unwrap();
+    let stdout = |output: &std::process::Output| {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let args = ["ls", "--paths", "trash:/"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "trash:/Old notes\n");
+
+    let by_id = format!("uuid:{}", dune);
+    let output = run(&cloud, home.path(), &["info", &by_id], b"").await;
+    assert!(stdout(&output).contains("Dune"), "{}", stdout(&output));
+
+    let by_id = format!("uuid:{}", books);
+    let args = ["mv", "/Dune", &by_id];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(cloud.document(&dune).unwrap().parent, Some(books));
+
+    let output = run(&cloud, home.path(), &["ls", "Books/Dnue"], b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Couldn't find \"/Books/Dnue\": nothing named \"Dnue\" is in Books\n"
+    );
+    let output =
+        run(&cloud, home.path(), &["mv", "Books/Dune", "A/B"], b"").await;
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("nothing named \"A\" is at the root"),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let output = run(&cloud, home.path(), &["ls", "Books/../Dune"], b"").await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Invalid path \"Books/../Dune\" at character 7"));
+}
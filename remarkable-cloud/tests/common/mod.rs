@@ -0,0 +1,94 @@
+//! Running the CLI binary against a fake cloud.
+
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::ClientState;
+
+// Runs the CLI against `cloud` with its config and cache kept under `home`,
+// feeding it `input`.
+#[allow(dead_code)]
+pub async fn run(
+    cloud: &FakeCloud,
+    home: &Path,
+    args: &[&str],
+    input: &'static [u8],
+) -> Output {
+    run_at(&cloud.url(), home, args, input).await
+}
+
+// Runs the CLI as `run` does, against whatever is at `url`.
+#[allow(dead_code)]
+pub async fn run_at(
+    url: &str,
+    home: &Path,
+    args: &[&str],
+    input: &'static [u8],
+) -> Output {
+    let mut command = command(url, home, args);
+    tokio::task::spawn_blocking(move || {
+        let mut child = command.spawn().unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        child.wait_with_output().unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+// The command `run_at` runs, with its config and cache kept under `home`,
+// signed in to whatever is at `url`. It runs in `home` too, so that nothing
+// it writes to relative paths ends up in the source tree.
+pub fn command(url: &str, home: &Path, args: &[&str]) -> Command {
+    let config = home.join("config").join("remarkable-cloud");
+    std::fs::create_dir_all(&config).unwrap();
+    let mut state = ClientState::new();
+    state.set_endpoint(url.to_string());
+    state.set_device_token("fake-device-token".to_string());
+    state
+        .save_to_path(&config.join("client_state.json"))
+        .unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_remarkable-cloud"));
+    command
+        .args(args)
+        .current_dir(home)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        // Messages are compared in English whatever the machine's locale.
+        .env("LC_ALL", "C")
+        .env(
+            "REMARKABLE_AUTH_URL",
+            format!("{}/token/json/2/user/new", url),
+        )
+        .env(
+            "REMARKABLE_REGISTER_URL",
+            format!("{}/token/json/2/device/new", url),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+// What `output` printed, once it's checked the command succeeded.
+#[allow(dead_code)]
+pub fn stdout(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+// A zip archive of `files`, each a name and what's in it, as a document's
+// blob is.
+#[allow(dead_code)]
+pub fn archive<N: AsRef<str>>(files: &[(N, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    for (name, data) in files {
+        zip.start_file(name.as_ref(), Default::default()).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
@@ -0,0 +1,89 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use uuid::Uuid;
+
+mod common;
+use common::{run, stdout};
+
+// An archive of the document `id`, with `content` as its .content and
+// `pages` drawn pages.
+fn archive(id: Uuid, content: &str, pages: usize) -> Vec<u8> {
+    let mut files = vec![(format!("{}.content", id), content.as_bytes())];
+    for n in 0..pages {
+        files.push((format!("{}/{}.rm", id, n), b""));
+    }
+    common::archive(&files)
+}
+
+fn add(
+    cloud: &FakeCloud,
+    name: &str,
+    parent: Option<Uuid>,
+    content: &str,
+    pages: usize,
+) -> Uuid {
+    let id = cloud.add_document(name, parent, vec![]);
+    let blob = archive(id, content, pages);
+    cloud.modify(&id, |d| d.blob = blob);
+    id
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn filters_on_cached_contents() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let pdf = r#"{"fileType": "pdf", "pageCount": 412}"#;
+    let dune = add(&cloud, "Dune", Some(books), pdf, 0);
+    add(&cloud, "Emma", Some(books), r#"{"fileType": "epub"}"#, 0);
+    add(&cloud, "Journal", Some(books), r#"{"fileType": ""}"#, 2);
+    add(&cloud, "Later", None, r#"{"fileType": "pdf"}"#, 0);
+    let home = tempfile::tempdir().unwrap();
+
+    // Downloading a document for anything caches its contents.
+    let output =
+        run(&cloud, home.path(), &["info", "--json", "Books/Dune"], b"").await;
+    stdout(&output);
+    let args = ["find", "--content-type", "pdf", "--cached"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(stdout(&output), "Books/Dune\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 documents weren't checked"), "{}", stderr);
+
+    let args = ["cache", "warm", "--folder", "Books"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(stdout(&output).starts_with("Cached 2 documents (1 already"));
+    let args = [
+        "ls",
+        "-r",
+        "--fields",
+        "path,content-type,pages",
+        "--cached",
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Books\t\t\n\
+         Books/Dune\tpdf\t412\n\
+         Books/Emma\tepub\t?\n\
+         Books/Journal\tnotebook\t2\n\
+         Later\t?\t?\n"
+    );
+
+    // Once a document is updated, what was cached of it is no use.
+    cloud.modify(&dune, |d| d.version += 1);
+    let args = ["find", "--content-type", "pdf"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(stdout(&output), "");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 documents weren't checked"), "{}", stderr);
+
+    let output = run(&cloud, home.path(), &["cache", "warm"], b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Cached 2 documents (2 already cached, 0 unreadable); forgot 1 \
+         removed or changed since\n"
+    );
+    let args = ["ls", "-r", "--paths", "--content-type", "pdf", "--cached"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(stdout(&output), "Books\nBooks/Dune\nLater\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
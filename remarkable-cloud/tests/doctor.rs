@@ -0,0 +1,36 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn doctor() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_document("Dune", None, b"zip".to_vec());
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(&cloud, home.path(), &["doctor"], b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    let table = stdout.lines().take_while(|l| !l.is_empty()).count();
+    assert_eq!(table, 6, "{}", stdout);
+    assert!(stdout.contains("Protocol: legacy"), "{}", stdout);
+    assert!(stdout.contains("Dialect: official"), "{}", stdout);
+
+    cloud.break_endpoint("/document-storage/json/2/docs", 404);
+    let output = run(&cloud, home.path(), &["doctor"], b"").await;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("listing   endpoint gone  HTTP status client error"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Look for a newer release"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("1 of 5 checks found problems"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,74 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::digest::DIGEST_BUILT;
+use uuid::Uuid;
+
+mod common;
+use common::{archive, run, stdout};
+
+// A notebook of `pages` pages, with the tags in `tags` put on them, each as
+// the page's index and how many days ago.
+fn add(cloud: &FakeCloud, name: &str, pages: usize, tags: &[(usize, i64)]) {
+    let id = cloud.add_document(name, None, vec![]);
+    let page_ids: Vec<String> = (0..pages)
+        .map(|i| Uuid::from_u128(100 + i as u128).to_string())
+        .collect();
+    let now = chrono::Utc::now().timestamp_millis();
+    let tags: Vec<serde_json::Value> = tags
+        .iter()
+        .map(|(page, days)| {
+            serde_json::json!({
+                "name": "Todo",
+                "pageId": page_ids[*page],
+                "timestamp": now - days * 24 * 60 * 60 * 1000,
+            })
+        })
+        .collect();
+    let content = serde_json::json!({
+        "fileType": "notebook",
+        "pageCount": pages,
+        "pages": page_ids,
+        "pageTags": tags,
+    });
+    let blob =
+        archive(&[(format!("{}.content", id), content.to_string().as_bytes())]);
+    cloud.modify(&id, |d| d.blob = blob);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn gathers_bookmarked_pages() {
+    let cloud = FakeCloud::start().await;
+    add(&cloud, "Journal", 3, &[(1, 1), (2, 30)]);
+    add(&cloud, "Ideas", 2, &[(0, 2)]);
+    add(&cloud, "Untagged", 2, &[]);
+    let home = tempfile::tempdir().unwrap();
+    let digest = home.path().join("weekly.pdf");
+    let digest_arg = digest.to_str().unwrap();
+
+    let args = ["export", "digest", "--since", "7d", "-o", digest_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    if !DIGEST_BUILT {
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("digest feature"), "{}", stderr);
+        return;
+    }
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "Wrote 2 bookmarked pages from 2 documents to {}\n",
+            digest_arg
+        )
+    );
+    assert!(std::fs::read(&digest).unwrap().starts_with(b"%PDF-"));
+
+    let args = ["export", "digest", "-o", digest_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(stdout(&output).starts_with("Wrote 3 bookmarked pages"));
+
+    let args = ["export", "digest", "--since", "1h", "-o", digest_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        stdout(&output),
+        "No pages were bookmarked in the last 1h, so there's no digest\n"
+    );
+}
@@ -0,0 +1,49 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn export_feed() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let feed = home.path().join("feed.xml");
+    let state = home.path().join("feed-state.json");
+    let args = [
+        "export",
+        "feed",
+        "--since",
+        state.to_str().unwrap(),
+        "-o",
+        feed.to_str().unwrap(),
+    ];
+
+    // Nothing is new the first time.
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let written = std::fs::read_to_string(&feed).unwrap();
+    assert!(written.starts_with("<?xml"), "{}", written);
+    assert!(!written.contains("<entry>"), "{}", written);
+    assert!(state.exists());
+
+    let emma = cloud.add_document("Emma", None, vec![]);
+    cloud.modify(&dune, |d| d.version += 1);
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let written = std::fs::read_to_string(&feed).unwrap();
+    assert_eq!(written.matches("<entry>").count(), 2, "{}", written);
+    assert!(written.contains(&format!("{}/2</id>", dune)), "{}", written);
+    assert!(written.contains(&format!("{}/1</id>", emma)), "{}", written);
+    assert!(written.contains("<title>Books/Dune</title>"), "{}", written);
+
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let written = std::fs::read_to_string(&feed).unwrap();
+    assert!(!written.contains("<entry>"), "{}", written);
+}
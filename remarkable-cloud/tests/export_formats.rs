@@ -0,0 +1,57 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+#[tokio::test(threaded_scheduler)]
+async fn export_with_fallback() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let novels = cloud.add_folder("Novels", Some(books));
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    cloud.modify(&dune, |d| {
+        d.blob = archive(&[(format!("{}.pdf", dune), b"dune")])
+    });
+    let emma = cloud.add_document("Emma", Some(novels), vec![]);
+    let emma_zip = archive(&[(format!("{}.epub", emma), b"emma")]);
+    cloud.modify(&emma, |d| d.blob = emma_zip.clone());
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("out");
+    let dir_arg = dir.to_str().unwrap();
+
+    // Emma is an EPUB, so without a fallback it's left out.
+    let args = ["export", "pdf", "-r", "Books", "-o", dir_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(std::fs::read(dir.join("Books/Dune.pdf")).unwrap(), b"dune");
+    assert!(!dir.join("Books/Novels").exists());
+    let all = format!("{}{}", String::from_utf8_lossy(&output.stdout), stderr);
+    assert!(all.contains("can't be exported as pdf"), "{}", all);
+
+    let args = [
+        "export",
+        "pdf",
+        "-r",
+        "Books",
+        "-o",
+        dir_arg,
+        "--fallback",
+        "zip",
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        std::fs::read(dir.join("Books/Novels/Emma.zip")).unwrap(),
+        emma_zip
+    );
+
+    let args = ["export", "epub", "Books", "-o", dir_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let all = String::from_utf8_lossy(&output.stdout);
+    assert!(all.contains("export what's in it with -r"), "{}", all);
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+use std::process::Output;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+// A notebook of two blank pages, with a .metadata naming it Journal.
+fn journal() -> Vec<u8> {
+    let id = uuid::Uuid::from_u128(1);
+    archive(&[
+        (
+            format!("{}.metadata", id),
+            br#"{"visibleName": "Journal", "type": "DocumentType"}"#,
+        ),
+        (
+            format!("{}.content", id),
+            br#"{"fileType": "notebook", "pageCount": 2, "pages": ["a", "b"]}"#,
+        ),
+    ])
+}
+
+// An EPUB with a highlight, and no .metadata.
+fn emma() -> Vec<u8> {
+    let id = uuid::Uuid::from_u128(2);
+    archive(&[
+        (
+            format!("{}.content", id),
+            br#"{"fileType": "epub", "pages": ["a", "b"]}"#,
+        ),
+        (format!("{}.epub", id), b"emma"),
+        (
+            format!("{}.highlights/b.json", id),
+            br#"{"highlights": [[{"text": "Emma Woodhouse", "color": 3}]]}"#,
+        ),
+    ])
+}
+
+// Runs the CLI under `home` with no account set up, and nothing to reach.
+fn run_signed_out(home: &Path, args: &[&str]) -> Output {
+    let mut command = common::command("http://127.0.0.1:9", home, args);
+    let state = home.join("config/remarkable-cloud/client_state.json");
+    std::fs::remove_file(state).unwrap();
+    command.output().unwrap()
+}
+
+// The files in `dir` and below it, relative to `dir`, sorted.
+fn files(dir: &Path) -> Vec<String> {
+    let mut found = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(next) = dirs.pop() {
+        for entry in std::fs::read_dir(next).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                let relative = path.strip_prefix(dir).unwrap();
+                found.push(relative.to_string_lossy().into_owned());
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+#[test]
+fn every_format_from_zips() {
+    let home = tempfile::tempdir().unwrap();
+    let saved = home.path().join("saved");
+    std::fs::create_dir(&saved).unwrap();
+    std::fs::write(saved.join("journal.zip"), journal()).unwrap();
+    std::fs::write(saved.join("emma-v3.zip"), emma()).unwrap();
+    let zips = [saved.join("journal.zip"), saved.join("emma-v3.zip")];
+    let zips: Vec<&str> = zips.iter().map(|z| z.to_str().unwrap()).collect();
+
+    let expected: &[(&str, &[&str])] = &[
+        ("pdf", &["Journal.zip", "emma-v3.zip"]),
+        ("epub", &["Journal.zip", "emma-v3.epub"]),
+        ("zip", &["Journal.zip", "emma-v3.zip"]),
+        ("svg", &["Journal-1.svg", "Journal-2.svg", "emma-v3.zip"]),
+        ("ink-pdf", &["Journal.pdf", "emma-v3.zip"]),
+        ("md", &["Journal.md", "emma-v3.md"]),
+        ("csv-highlights", &["Journal.zip", "emma-v3.csv"]),
+    ];
+    for (format, names) in expected {
+        let out = home.path().join(format);
+        let args = [
+            "export",
+            format,
+            "--from-zip",
+            zips[0],
+            "--from-zip",
+            zips[1],
+            "--fallback",
+            "zip",
+            "-o",
+            out.to_str().unwrap(),
+        ];
+        let output = run_signed_out(home.path(), &args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{}: {}", format, stderr);
+        assert_eq!(files(&out), *names, "{}", format);
+    }
+    let md = std::fs::read_to_string(home.path().join("md/emma-v3.md"));
+    assert_eq!(md.unwrap(), "# emma-v3\n\n## Page 2\n\n> Emma Woodhouse\n");
+    let epub = std::fs::read(home.path().join("epub/emma-v3.epub"));
+    assert_eq!(epub.unwrap(), b"emma");
+
+    // A directory of them keeps its folders.
+    let nested = saved.join("Books/Novels");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::rename(saved.join("emma-v3.zip"), nested.join("emma-v3.zip"))
+        .unwrap();
+    std::fs::write(saved.join("notes.txt"), b"not an archive").unwrap();
+    let out = home.path().join("from-dir");
+    let args = [
+        "export",
+        "md",
+        "--from-dir",
+        saved.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+    ];
+    let output = run_signed_out(home.path(), &args);
+    assert!(output.status.success());
+    assert_eq!(files(&out), ["Books/Novels/emma-v3.md", "Journal.md"]);
+
+    // Something that isn't the archive of a document stops the export.
+    let args = ["export", "zip", "--from-zip", "Cargo.toml"];
+    let output = run_signed_out(home.path(), &args);
+    assert!(!output.status.success());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn from_backup() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let emma_id = cloud.add_document("Emma", Some(books), vec![]);
+    let blob = emma();
+    cloud.modify(&emma_id, |d| d.blob = blob.clone());
+    let home = tempfile::tempdir().unwrap();
+    let backup = home.path().join("account.tar.zst");
+    let args = ["backup", "-o", backup.to_str().unwrap()];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+
+    // Somewhere else, with no account.
+    let elsewhere = tempfile::tempdir().unwrap();
+    let out = elsewhere.path().join("out");
+    let args = [
+        "export",
+        "csv-highlights",
+        "--from-dir",
+        backup.to_str().unwrap(),
+        "-o",
+        out.to_str().unwrap(),
+    ];
+    let output = run_signed_out(elsewhere.path(), &args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(files(&out), ["Books/Emma.csv"]);
+}
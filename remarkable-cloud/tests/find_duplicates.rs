@@ -0,0 +1,56 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+fn paper() -> Vec<u8> {
+    archive(&[("p/p.pdf", b"%PDF-1.4")])
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn find_duplicates() {
+    let cloud = FakeCloud::start().await;
+    let old = cloud.add_folder("Old", None);
+    let original = cloud.add_document("Paper", None, paper());
+    let copy = cloud.add_document("Reupload", Some(old), paper());
+    cloud.add_document("Paper", Some(old), vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["find", "--duplicates-of", "Paper"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    assert!(
+        lines[0].starts_with(&format!("Old/Reupload\t{}\t1\t", copy)),
+        "{}",
+        stdout
+    );
+
+    let args = ["find", "--duplicates-of", "Paper", "--by-name", "--json"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let found: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(found["path"], "Old/Paper");
+    assert_ne!(found["id"], original.to_string());
+
+    let args = [
+        "find",
+        "--duplicates-of",
+        "Old/Reupload",
+        "--limit-folders",
+        "Old",
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let args = ["find", "--duplicates-of", "Old"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+}
@@ -0,0 +1,60 @@
+use std::fs;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+// With limits of two folders deep and three items in a folder, pushing
+// four things into A/B, one of them in a folder of its own, goes past
+// both: a warning, unless --strict, when nothing is pushed.
+#[tokio::test(threaded_scheduler)]
+async fn push_past_the_limits() {
+    let cloud = FakeCloud::start().await;
+    let a = cloud.add_folder("A", None);
+    cloud.add_folder("B", Some(a));
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join("config").join("remarkable-cloud");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(
+        config.join("settings.json"),
+        r#"{"max_folder_depth": 2, "max_folder_items": 3}"#,
+    )
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    for name in &["C/Deep.pdf", "One.pdf", "Two.pdf", "Three.pdf"] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, PAPER).unwrap();
+    }
+    let dir = dir.path().to_str().unwrap();
+    let too_deep = "A/B/C/Deep is inside 3 folders, more than the 2 the \
+                    tablet handles well";
+    let too_wide = "A/B holds 4 items, more than the 3 the tablet handles \
+                    well";
+
+    let args = ["push", "-r", "--strict", dir, "--to", "A/B"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(too_deep), "{}", stderr);
+    assert!(stderr.contains(too_wide), "{}", stderr);
+    assert!(stderr.contains("2 places would go past"), "{}", stderr);
+    let output = run(&cloud, home.path(), &["fsck"], b"").await;
+    assert!(output.status.success());
+
+    let args = ["push", "-r", dir, "--to", "A/B"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(too_deep), "{}", stderr);
+
+    let output = run(&cloud, home.path(), &["fsck"], b"").await;
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}\n{}\n", too_wide, too_deep)
+    );
+}
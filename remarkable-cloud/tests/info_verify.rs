@@ -0,0 +1,75 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::DocumentArchiveBuilder;
+use remarkable_data_formats::content::Content;
+
+mod common;
+use common::run;
+
+fn pdf(id: uuid::Uuid) -> Vec<u8> {
+    DocumentArchiveBuilder::new()
+        .content(Content {
+            file_type: "pdf".to_string(),
+            ..Default::default()
+        })
+        .payload_pdf(b"%PDF-1.4".to_vec())
+        .build(id)
+        .unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn info_verify() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    cloud.modify(&dune, |d| d.blob = pdf(dune));
+    let notes = cloud.add_document("Notes", Some(books), vec![]);
+    cloud.modify(&notes, |d| {
+        let mut blob = pdf(notes);
+        blob.truncate(blob.len() - 10);
+        d.blob = blob;
+    });
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(
+        &cloud,
+        home.path(),
+        &["info", "--verify", "Books/Dune"],
+        b"",
+    )
+    .await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines[..5],
+        [
+            "Books/Dune: intact",
+            "  zip      ok",
+            "  entries  ok",
+            "  content  ok",
+            "  pages    ok",
+        ]
+    );
+    assert!(lines[5].starts_with("  hash     "), "{}", lines[5]);
+    assert_eq!(
+        lines[6],
+        "Verified 1 documents: 1 intact, 0 damaged, 0 not downloaded"
+    );
+
+    let output =
+        run(&cloud, home.path(), &["info", "--verify", "Books"], b"").await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("use -r"));
+
+    let args = ["info", "--verify", "-r", "Books"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Books/Notes: damaged\n  zip      FAILED"));
+    assert!(stdout.contains("  hash     unavailable\n"));
+    assert!(stdout.ends_with(
+        "Verified 2 documents: 1 intact, 1 damaged, 0 not downloaded\n"
+    ));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("1 of 2 documents failed verification"));
+}
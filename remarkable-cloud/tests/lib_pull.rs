@@ -0,0 +1,287 @@
+//! Running commands through the library rather than the binary.
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::cache::ListingCache;
+use remarkable_cloud_cli::commands::{
+    self, Capture, ListingOptions, PullFormat, PullGroup, PullOptions,
+};
+use remarkable_cloud_cli::summary::TransferReport;
+
+mod common;
+use common::archive;
+
+fn pdf(contents: &[u8]) -> Vec<u8> {
+    archive(&[("p/p.pdf", contents)])
+}
+
+// A PDF with tags, as the document `id`.
+fn tagged_pdf(id: uuid::Uuid, contents: &[u8], tags: &[&str]) -> Vec<u8> {
+    let tags: Vec<_> = tags
+        .iter()
+        .map(|name| serde_json::json!({ "name": name, "timestamp": 1 }))
+        .collect();
+    let content = serde_json::json!({ "fileType": "pdf", "tags": tags });
+    archive(&[
+        (format!("{}.content", id), content.to_string().as_bytes()),
+        (format!("{}.pdf", id), contents),
+    ])
+}
+
+// A notebook of `pages` pages with nothing drawn on them.
+fn notebook(id: uuid::Uuid, pages: usize) -> Vec<u8> {
+    let keys: Vec<String> = (0..pages).map(|n| format!("p{}", n)).collect();
+    let content = serde_json::json!({ "fileType": "notebook", "pages": keys });
+    archive(&[(format!("{}.content", id), content.to_string().as_bytes())])
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn recursive_pull() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let scifi = cloud.add_folder("Sci-fi", Some(books));
+    cloud.add_folder("Empty", Some(scifi));
+    cloud.add_document("Dune", Some(books), pdf(b"dune"));
+    cloud.add_document("Hyperion", Some(scifi), pdf(b"hyperion"));
+    cloud.add_document("Notes", None, pdf(b"notes"));
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("out");
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let listing = ListingOptions {
+        verbose: false,
+        use_cache: false,
+        cache: ListingCache::new(home.path().join("listing.json")),
+        refresh: None,
+    };
+    let mut out = Capture::new(TransferReport::new());
+    let documents = commands::list_documents(&client, &listing, &mut out)
+        .await
+        .unwrap();
+
+    let options = PullOptions {
+        paths: vec!["Books".into()],
+        recursive: true,
+        dir: dir.clone(),
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(dir.join("Books/Dune.pdf")).unwrap(), b"dune");
+    assert_eq!(
+        std::fs::read(dir.join("Books/Sci-fi/Hyperion.pdf")).unwrap(),
+        b"hyperion"
+    );
+    assert!(!dir.join("Notes.pdf").exists());
+    assert!(!dir.join("Books/Sci-fi/Empty").exists());
+    assert!(out.lines.is_empty(), "{:?}", out.lines);
+    let summary = out.observer.summary();
+    assert_eq!((summary.transferred, summary.skipped), (2, 0));
+
+    // Without `recursive`, a folder is skipped.
+    let mut out = Capture::new(TransferReport::new());
+    let options = PullOptions {
+        paths: vec!["Books/Sci-fi".into()],
+        dir,
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(
+        out.lines,
+        vec!["\"Books/Sci-fi\" is a folder; pull what's in it with -r"]
+    );
+    assert_eq!(out.observer.summary().skipped, 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn preserves_times() {
+    let cloud = FakeCloud::start().await;
+    let dune = cloud.add_document("Dune", None, pdf(b"dune"));
+    let modified: chrono::DateTime<chrono::Utc> =
+        "2021-06-01T08:30:15.250Z".parse().unwrap();
+    cloud.modify(&dune, |d| d.modified_client = modified);
+    let home = tempfile::tempdir().unwrap();
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let listing = ListingOptions {
+        verbose: false,
+        use_cache: false,
+        cache: ListingCache::new(home.path().join("listing.json")),
+        refresh: None,
+    };
+    let mut out = Capture::new(TransferReport::new());
+    let documents = commands::list_documents(&client, &listing, &mut out)
+        .await
+        .unwrap();
+    let mtime = |options: &PullOptions| {
+        let path = options.dir.join("Dune.pdf");
+        let mtime = std::fs::metadata(path).unwrap().modified().unwrap();
+        chrono::DateTime::<chrono::Utc>::from(mtime)
+    };
+
+    let options = PullOptions {
+        paths: vec!["Dune".into()],
+        dir: home.path().join("kept"),
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(mtime(&options).timestamp(), modified.timestamp());
+    assert!(out.warnings.is_empty(), "{:?}", out.warnings);
+
+    let options = PullOptions {
+        paths: vec!["Dune".into()],
+        dir: home.path().join("now"),
+        preserve_times: false,
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    assert!(mtime(&options) > modified);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn ink_formats() {
+    let cloud = FakeCloud::start().await;
+    let notes = cloud.add_document("Notes", None, vec![]);
+    cloud.modify(&notes, |d| d.blob = notebook(notes, 12));
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("out");
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let listing = ListingOptions {
+        verbose: false,
+        use_cache: false,
+        cache: ListingCache::new(home.path().join("listing.json")),
+        refresh: None,
+    };
+    let mut out = Capture::new(TransferReport::new());
+    let documents = commands::list_documents(&client, &listing, &mut out)
+        .await
+        .unwrap();
+
+    let options = PullOptions {
+        paths: vec!["Notes".into()],
+        dir: dir.clone(),
+        format: PullFormat::InkSvg,
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names.len(), 12);
+    assert_eq!(names[0], "Notes-01.svg");
+    assert_eq!(names[11], "Notes-12.svg");
+    let svg = std::fs::read_to_string(dir.join("Notes-01.svg")).unwrap();
+    assert!(svg.contains("viewBox=\"0 0 1404 1872\""), "{}", svg);
+    assert_eq!(out.observer.summary().transferred, 12);
+
+    let options = PullOptions {
+        paths: vec!["Notes".into()],
+        dir: dir.clone(),
+        format: PullFormat::InkPdf,
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    let pdf = std::fs::read(dir.join("Notes.pdf")).unwrap();
+    assert!(pdf.starts_with(b"%PDF-"));
+    assert_eq!(remarkable_data_formats::pdf::page_boxes(&pdf).len(), 12);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn grouped_pull() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let tagged = [
+        ("Dune", Some(books), &["a"][..]),
+        ("Both", None, &["a", "b"][..]),
+        ("Plan", None, &["b", "c"][..]),
+        ("Untagged", None, &[][..]),
+    ];
+    for (name, parent, tags) in &tagged {
+        let id = cloud.add_document(name, *parent, vec![]);
+        let blob = tagged_pdf(id, name.as_bytes(), tags);
+        cloud.modify(&id, |d| d.blob = blob);
+        if *name == "Untagged" {
+            cloud.modify(&id, |d| d.bookmarked = true);
+        }
+    }
+    let home = tempfile::tempdir().unwrap();
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let listing = ListingOptions {
+        verbose: false,
+        use_cache: false,
+        cache: ListingCache::new(home.path().join("listing.json")),
+        refresh: None,
+    };
+    let mut out = Capture::new(TransferReport::new());
+    let documents = commands::list_documents(&client, &listing, &mut out)
+        .await
+        .unwrap();
+    let names = |dir: &std::path::Path| {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    };
+
+    // One tag goes straight into the directory.
+    let dir = home.path().join("a");
+    let options = PullOptions {
+        groups: vec![PullGroup::Tag("a".into())],
+        dir: dir.clone(),
+        ..Default::default()
+    };
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(names(&dir), vec!["Both.pdf", "Dune.pdf"]);
+    assert_eq!(out.observer.summary().transferred, 2);
+
+    // Several each get a directory, and what's in both is linked into both.
+    let dir = home.path().join("exports");
+    let options = PullOptions {
+        groups: vec![
+            PullGroup::Tag("a".into()),
+            PullGroup::Tag("b".into()),
+            PullGroup::Bookmarked,
+        ],
+        dir: dir.clone(),
+        ..Default::default()
+    };
+    let mut out = Capture::new(TransferReport::new());
+    commands::pull(&client, &documents, &options, &mut out)
+        .await
+        .unwrap();
+    assert_eq!(names(&dir), vec!["a", "b", "bookmarked"]);
+    assert_eq!(names(&dir.join("a")), vec!["Both.pdf", "Dune.pdf"]);
+    assert_eq!(names(&dir.join("b")), vec!["Both.pdf", "Plan.pdf"]);
+    assert_eq!(names(&dir.join("bookmarked")), vec!["Untagged.pdf"]);
+    assert_eq!(std::fs::read(dir.join("b/Both.pdf")).unwrap(), b"Both");
+    assert_eq!(out.observer.summary().transferred, 4);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode = |p: &str| std::fs::metadata(dir.join(p)).unwrap().ino();
+        assert_eq!(inode("a/Both.pdf"), inode("b/Both.pdf"));
+        assert_ne!(inode("a/Dune.pdf"), inode("b/Plan.pdf"));
+    }
+}
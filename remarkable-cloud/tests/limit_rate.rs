@@ -0,0 +1,97 @@
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+// 1 MiB through --limit-rate 100k.
+const SIZE: usize = 1024 * 1024;
+const RATE: &str = "100k";
+const RATE_BYTES: usize = 100 * 1024;
+
+// Checks a transfer of SIZE bytes took as long as RATE allows, give or take
+// a quarter. The limiter starts with a second's worth to spend in a burst,
+// so only the rest waits on it.
+fn assert_limited(elapsed: Duration) {
+    let expected = (SIZE - RATE_BYTES) as f64 / RATE_BYTES as f64;
+    let elapsed = elapsed.as_secs_f64();
+    assert!(
+        (expected * 0.75..=expected * 1.25).contains(&elapsed),
+        "took {:.2}s rather than about {:.2}s",
+        elapsed,
+        expected
+    );
+}
+
+// A PDF's worth of bytes that won't compress, so as much goes over the wire.
+fn incompressible() -> Vec<u8> {
+    let mut bytes = b"%PDF-1.4\n".to_vec();
+    let mut x: u32 = 0x1234_5678;
+    while bytes.len() < SIZE {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        bytes.extend_from_slice(&x.to_le_bytes());
+    }
+    bytes.extend_from_slice(b"\n%%EOF\n");
+    bytes
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn limits_downloads() {
+    let cloud = FakeCloud::start().await;
+    let pdf = incompressible();
+    cloud.add_document("Dune", None, archive(&[("p/p.pdf", &pdf[..])]));
+    let home = tempfile::tempdir().unwrap();
+    let out = home.path().join("out");
+    std::fs::create_dir(&out).unwrap();
+
+    let out_arg = out.to_str().unwrap();
+    let start = Instant::now();
+    let args = ["--limit-rate", RATE, "pull", "-o", out_arg, "Dune"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let elapsed = start.elapsed();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_limited(elapsed);
+    assert_eq!(std::fs::read(out.join("Dune.pdf")).unwrap(), pdf);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn limits_uploads() {
+    let cloud = FakeCloud::start().await;
+    let pdf = incompressible();
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("Dune.pdf");
+    std::fs::write(&path, &pdf).unwrap();
+
+    let start = Instant::now();
+    let args = ["--limit-rate", RATE, "push", path.to_str().unwrap()];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let elapsed = start.elapsed();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_limited(elapsed);
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    let doc = docs.resolve("Dune").unwrap().unwrap();
+    let blob = cloud.document(&doc.id).unwrap().blob;
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(blob)).unwrap();
+    let mut uploaded = vec![];
+    archive
+        .by_name(&format!("{}.pdf", doc.id))
+        .unwrap()
+        .read_to_end(&mut uploaded)
+        .unwrap();
+    assert_eq!(uploaded, pdf);
+}
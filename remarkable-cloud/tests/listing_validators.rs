@@ -0,0 +1,35 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn listing_revalidated_between_runs() {
+    let cloud = FakeCloud::start().await;
+    let dune = cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    for _ in 0..2 {
+        let output = run(&cloud, home.path(), &["ls"], b"").await;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("Dune "), "{}", stdout);
+    }
+    let listings: Vec<_> = cloud
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.ends_with("/docs") && r.query.is_empty())
+        .collect();
+    assert_eq!(listings.len(), 2);
+    assert!(listings[1].headers.contains_key("if-none-match"));
+
+    // Renamed on the tablet.
+    cloud.modify(&dune, |d| d.visible_name = "Dune (1965)".to_string());
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Dune (1965) "), "{}", stdout);
+}
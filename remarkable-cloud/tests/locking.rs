@@ -0,0 +1,104 @@
+use std::path::Path;
+use std::time::Duration;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const UPDATE_STATUS: &str = "/document-storage/json/2/upload/update-status";
+
+// Every JSON file under `dir`, and every line of every JSON lines file,
+// parses.
+fn assert_valid(dir: &Path) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let data = || std::fs::read(&path).unwrap();
+        match path.extension().and_then(|e| e.to_str()) {
+            _ if path.is_dir() => assert_valid(&path),
+            Some("json") => {
+                let parsed =
+                    serde_json::from_slice::<serde_json::Value>(&data());
+                assert!(parsed.is_ok(), "{:?}", path);
+            }
+            Some("jsonl") => {
+                for line in
+                    data().split(|b| *b == b'\n').filter(|l| !l.is_empty())
+                {
+                    let parsed =
+                        serde_json::from_slice::<serde_json::Value>(line);
+                    assert!(parsed.is_ok(), "{:?}", path);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_runs() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", None, vec![]);
+    let notes = cloud.add_document("Notes", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+    // Fills in the state and the cache.
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    assert!(output.status.success());
+
+    // The second waits for the first to finish.
+    cloud.delay_next(UPDATE_STATUS, Duration::from_millis(1500));
+    let first = run(&cloud, home.path(), &["mv", "Dune", "Books"], b"");
+    let second = async {
+        tokio::time::delay_for(Duration::from_millis(500)).await;
+        let args = ["--wait-lock", "30", "mv", "Notes", "Books"];
+        run(&cloud, home.path(), &args, b"").await
+    };
+    let (first, second) = tokio::join!(first, second);
+    assert!(first.status.success());
+    assert!(second.status.success(), "{:?}", second);
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(
+        stderr
+            .starts_with("Waiting for another remarkable-cloud process (pid "),
+        "{}",
+        stderr
+    );
+    let books = cloud.document(&dune).unwrap().parent;
+    assert_eq!(cloud.document(&notes).unwrap().parent, books);
+    assert_valid(home.path());
+
+    // Without --wait-lock, even a command which only reads gives up.
+    cloud.delay_next(UPDATE_STATUS, Duration::from_millis(1500));
+    let first = run(&cloud, home.path(), &["mv", "Books/Dune", "/"], b"");
+    let second = async {
+        tokio::time::delay_for(Duration::from_millis(500)).await;
+        run(&cloud, home.path(), &["ls"], b"").await
+    };
+    let (first, second) = tokio::join!(first, second);
+    assert!(first.status.success());
+    assert!(!second.status.success());
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(
+        stderr
+            .contains("another remarkable-cloud process holds the lock (pid "),
+        "{}",
+        stderr
+    );
+    assert_valid(home.path());
+
+    // Commands which only read run alongside each other.
+    cloud.delay_next(
+        "/document-storage/json/2/docs",
+        Duration::from_millis(1500),
+    );
+    let first = run(&cloud, home.path(), &["ls"], b"");
+    let second = async {
+        tokio::time::delay_for(Duration::from_millis(500)).await;
+        run(&cloud, home.path(), &["ls"], b"").await
+    };
+    let (first, second) = tokio::join!(first, second);
+    assert!(first.status.success());
+    assert!(second.status.success());
+    assert!(second.stderr.is_empty());
+}
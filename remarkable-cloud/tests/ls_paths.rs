@@ -0,0 +1,33 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn flat_listing() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    cloud.add_document("Dune", Some(books), vec![]);
+    cloud.add_document("Notes\nfor later", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(&cloud, home.path(), &["ls", "-r", "--paths"], b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Books\nBooks/Dune\nNotes\nfor later\n"
+    );
+
+    let args = ["ls", "-r", "--paths", "--print0"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Books\0Books/Dune\0Notes\nfor later\0");
+
+    // NUL separation only makes sense for paths.
+    let output = run(&cloud, home.path(), &["ls", "--print0"], b"").await;
+    assert!(!output.status.success());
+}
@@ -0,0 +1,71 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{run, run_at, stdout};
+
+// The address of a port nothing is listening on.
+fn dead_url() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn shallow_from_warmed_folder() {
+    let cloud = FakeCloud::start().await;
+    let work = cloud.add_folder("Work", None);
+    let clients = cloud.add_folder("Clients", Some(work));
+    cloud.add_document("Acme", Some(clients), vec![]);
+    cloud.add_document("Plans", Some(work), vec![]);
+    let house = cloud.add_folder("Home", None);
+    cloud.add_document("Recipes", Some(house), vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["cache", "warm", "--folder", "/Work"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let printed = stdout(&output);
+    assert!(
+        printed.contains("Cached the listing of what's in Work only"),
+        "{}",
+        printed
+    );
+
+    // Work, and the root above it, are answered without asking the cloud.
+    let before = cloud.requests().len();
+    let args = ["ls", "--shallow", "/Work"];
+    let shallow = stdout(&run(&cloud, home.path(), &args, b"").await);
+    assert!(shallow.starts_with("Clients "), "{}", shallow);
+    assert!(shallow.contains("\nPlans "), "{}", shallow);
+    assert!(!shallow.contains("Acme"), "{}", shallow);
+    let args = ["ls", "--shallow"];
+    let root = stdout(&run(&cloud, home.path(), &args, b"").await);
+    assert!(root.starts_with("Home "), "{}", root);
+    assert_eq!(cloud.requests().len(), before);
+
+    // Offline, it still lists Work, but won't stand in for everything.
+    let dead = dead_url();
+    let args = ["ls", "--shallow", "/Work"];
+    let output = run_at(&dead, home.path(), &args, b"").await;
+    assert_eq!(stdout(&output), shallow);
+    let args = ["ls", "-r"];
+    let output = run_at(&dead, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("only part of the listing is cached, what's in Work"),
+        "{}",
+        stderr
+    );
+    let args = ["--cached", "find", "Recipes"];
+    let output = run_at(&dead, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+
+    // What's in Home wasn't cached, so it's fetched.
+    let args = ["ls", "--shallow", "/Home"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(stdout(&output).starts_with("Recipes "));
+    assert!(cloud.requests().len() > before);
+
+    // And it was listed as it would have been anyway.
+    let output = run(&cloud, home.path(), &["ls", "/Work"], b"").await;
+    assert_eq!(stdout(&output), shallow);
+}
@@ -0,0 +1,23 @@
+use std::time::{Duration, Instant};
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+#[tokio::test(threaded_scheduler)]
+async fn max_time() {
+    let cloud = FakeCloud::start().await;
+    let blob = archive(&[("p/p.pdf", b"dune")]);
+    let id = cloud.add_document("Dune", None, blob);
+    cloud.delay_next(&format!("/blob/{}", id), Duration::from_secs(10));
+    let home = tempfile::tempdir().unwrap();
+
+    let start = Instant::now();
+    let args = ["--max-time", "1s", "pull", "Dune"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("time allowed ran out"), "{}", stderr);
+}
@@ -0,0 +1,71 @@
+use std::process::Output;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::messages::I18N_BUILT;
+
+mod common;
+use common::{command, run};
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn in_german() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_folder("Scans", None);
+    let home = tempfile::tempdir().unwrap();
+    let out = home.path().join("out");
+    let out = out.to_str().unwrap();
+
+    let args = ["--lang", "de", "pull", "-o", out, "Scans"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    if !I18N_BUILT {
+        assert!(!output.status.success());
+        assert!(
+            stderr(&output).contains("i18n feature"),
+            "{}",
+            stderr(&output)
+        );
+        return;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("ist ein Ordner"), "{}", stdout);
+
+    // Picked up from the environment too.
+    let url = cloud.url();
+    let args = ["pull", "-o", out, "Scans"];
+    let mut german = command(&url, home.path(), &args);
+    german.env("LC_ALL", "de_DE.UTF-8");
+    let output = tokio::task::spawn_blocking(move || german.output().unwrap())
+        .await
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("ist ein Ordner"), "{}", stdout);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn unknown_languages() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_folder("Scans", None);
+    let home = tempfile::tempdir().unwrap();
+
+    // Asked for by name, one there are no messages in is an error.
+    let output = run(&cloud, home.path(), &["--lang", "xx", "ls"], b"").await;
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("There are no messages in xx"),
+        "{}",
+        stderr(&output)
+    );
+
+    // From the environment, it's English instead.
+    let args = ["ls"];
+    let mut unknown = command(&cloud.url(), home.path(), &args);
+    unknown.env("LC_ALL", "xx_XX.UTF-8");
+    let output = tokio::task::spawn_blocking(move || unknown.output().unwrap())
+        .await
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Scans"));
+}
@@ -0,0 +1,34 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn migrated_account() {
+    let cloud = FakeCloud::start().await;
+    cloud.set_migrated(true);
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("newer sync service"), "{}", stderr);
+    assert!(stderr.contains("github.com"), "{}", stderr);
+    assert!(!stderr.contains("JsonError"), "{}", stderr);
+
+    // What the account can do is still told.
+    let output = run(&cloud, home.path(), &["auth", "status"], b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Protocol: sync15"), "{}", stdout);
+    assert!(stdout.contains("Uploads supported: no"), "{}", stdout);
+
+    // An empty account is just empty.
+    cloud.set_migrated(false);
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::naming::CASE_INSENSITIVE_VAR;
+use uuid::Uuid;
+
+mod common;
+use common::{archive, run};
+
+fn pdf(contents: &[u8]) -> Vec<u8> {
+    archive(&[("p/p.pdf", contents)])
+}
+
+// Pulls the folder Jobs into `out` below `home`, as if local file names
+// ignored case or not, returning the names of the files written.
+fn pull_jobs(
+    cloud: &FakeCloud,
+    home: &Path,
+    out: &str,
+    fold: &str,
+) -> Vec<String> {
+    let dir = home.join(out);
+    let args = ["pull", "-o", dir.to_str().unwrap(), "-r", "Jobs"];
+    let output = common::command(&cloud.url(), home, &args)
+        .env(CASE_INSENSITIVE_VAR, fold)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut names: Vec<String> = std::fs::read_dir(dir.join("Jobs"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn names_validated() {
+    let cloud = FakeCloud::start().await;
+    let dune = cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["mv", "--yes", "Dune", "Dune (1965)\u{7}"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(cloud.document(&dune).unwrap().visible_name, "Dune (1965)");
+
+    // Path components are trimmed, so there's nothing but control
+    // characters to get a blank name from.
+    let args = ["mv", "--yes", "Dune (1965)", "\u{1}"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid name"), "{}", stderr);
+    assert_eq!(cloud.document(&dune).unwrap().visible_name, "Dune (1965)");
+
+    let args = ["--no-validate-names", "mv", "--yes", "Dune (1965)", "\u{1}"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(cloud.document(&dune).unwrap().visible_name, "\u{1}");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn colliding_names_kept_apart() {
+    let cloud = FakeCloud::start().await;
+    let jobs = cloud.add_folder("Jobs", None);
+    // The same name from Linux and from macOS, and two differing in case.
+    let composed = cloud.add_document("Résumé", Some(jobs), pdf(b"a"));
+    let decomposed =
+        cloud.add_document("Re\u{301}sume\u{301}", Some(jobs), pdf(b"b"));
+    let lower = cloud.add_document("notes", Some(jobs), pdf(b"c"));
+    let upper = cloud.add_document("Notes", Some(jobs), pdf(b"d"));
+    let home = tempfile::tempdir().unwrap();
+    let short = |id: Uuid| id.to_string()[..8].to_string();
+
+    let sorted = |mut names: Vec<String>| {
+        names.sort();
+        names
+    };
+    let resumes = vec![
+        format!("Résumé (Jobs, {}).pdf", short(composed)),
+        format!("Re\u{301}sume\u{301} (Jobs, {}).pdf", short(decomposed)),
+    ];
+    assert_eq!(
+        pull_jobs(&cloud, home.path(), "exact", "0"),
+        sorted(
+            [&resumes[..], &["notes.pdf".into(), "Notes.pdf".into()]].concat()
+        )
+    );
+    let notes = vec![
+        format!("notes (Jobs, {}).pdf", short(lower)),
+        format!("Notes (Jobs, {}).pdf", short(upper)),
+    ];
+    assert_eq!(
+        pull_jobs(&cloud, home.path(), "folded", "1"),
+        sorted([resumes, notes].concat())
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn normalized_paths() {
+    let cloud = FakeCloud::start().await;
+    let resumes = cloud.add_folder("Résumés", None);
+    let cv = cloud.add_document("CV", Some(resumes), vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let find = |path: &'static str, normalize: bool| {
+        let mut args = vec!["find", "--fields", "id", path];
+        if normalize {
+            args.insert(0, "--normalize-paths");
+        }
+        args
+    };
+
+    // Typed on macOS, decomposed, and in lower case.
+    for path in &["Re\u{301}sume\u{301}s", "résumés"] {
+        let output = run(&cloud, home.path(), &find(path, false), b"").await;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!stdout.contains(&cv.to_string()), "{}", stdout);
+
+        let output = run(&cloud, home.path(), &find(path, true), b"").await;
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(stdout.trim(), cv.to_string());
+    }
+
+    let args = ["find", "--fields", "id", "--path", "re\u{301}*/cv"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "");
+    let args = [&["--normalize-paths"], &args[..]].concat();
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        cv.to_string()
+    );
+}
@@ -0,0 +1,56 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+// Notes are set, found and shown by the path of their document, and pruned
+// once it's deleted.
+#[tokio::test(threaded_scheduler)]
+async fn set_search_and_prune() {
+    let cloud = FakeCloud::start().await;
+    let contracts = cloud.add_folder("Contracts", None);
+    let acme = cloud.add_document("Acme", Some(contracts), vec![]);
+    let globex = cloud.add_document("Globex", Some(contracts), vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let stdout = |output: &std::process::Output| {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    for (path, text) in &[
+        ("Contracts/Acme", "Signed copy, sent 2024-03-01"),
+        ("Contracts/Globex", "Draft"),
+    ] {
+        let args = ["note", "set", path, text];
+        let output = run(&cloud, home.path(), &args, b"").await;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let args = ["note", "show", "Contracts/Acme"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(stdout(&output), "Signed copy, sent 2024-03-01\n");
+
+    let args = ["note", "search", "SIGNED"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Contracts/Acme: Signed copy, sent 2024-03-01\n"
+    );
+
+    let args = ["find", "--with-notes", "draft", "Contracts"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(stdout(&output), "Contracts/Globex\n");
+
+    cloud.modify(&acme, |d| d.trashed = true);
+    let output = run(&cloud, home.path(), &["note", "prune"], b"").await;
+    assert_eq!(stdout(&output), "No notes to prune\n");
+
+    let by_id = format!("uuid:{}", globex);
+    let output = run(&cloud, home.path(), &["rm", &by_id], b"").await;
+    assert!(output.status.success());
+    let output = run(&cloud, home.path(), &["note", "prune"], b"").await;
+    assert_eq!(stdout(&output), format!("Removed the note on {}\n", globex));
+}
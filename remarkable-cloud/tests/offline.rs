@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{run, run_at};
+
+// The address of a port nothing is listening on.
+fn dead_url() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn offline_fallback() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    cloud.add_document("Dune", Some(books), vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let output = run(&cloud, home.path(), &["ls", "-r", "--paths"], b"").await;
+    assert!(output.status.success());
+    let online = output.stdout;
+
+    let dead = dead_url();
+    let output =
+        run_at(&dead, home.path(), &["ls", "-r", "--paths"], b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(output.stdout, online);
+    assert!(
+        stderr.starts_with("offline \u{2014} showing cached data from "),
+        "{}",
+        stderr
+    );
+
+    let output = run_at(&dead, home.path(), &["find", "Books"], b"").await;
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Books/Dune\n");
+
+    // Changes need the cloud, and say so without waiting around.
+    let start = Instant::now();
+    let args = ["mv", "Books/Dune", "/"];
+    let output = run_at(&dead, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    assert!(start.elapsed() < Duration::from_secs(5));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Can't reach the reMarkable cloud"),
+        "{}",
+        stderr
+    );
+
+    // With nothing cached, there's nothing to show.
+    let empty = tempfile::tempdir().unwrap();
+    let output = run_at(&dead, empty.path(), &["ls"], b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Can't reach the reMarkable cloud"),
+        "{}",
+        stderr
+    );
+}
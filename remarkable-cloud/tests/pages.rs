@@ -0,0 +1,69 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::{list_pages, PageInfo};
+
+mod common;
+use common::{archive, run};
+
+fn notebook(id: uuid::Uuid) -> Vec<u8> {
+    let content =
+        br#"{"fileType": "notebook", "pageCount": 2, "pages": ["a", "b"]}"#;
+    archive(&[
+        (format!("{}.content", id), content),
+        (format!("{}.pagedata", id), b"Blank\nP Lines medium\n"),
+        (format!("{}.thumbnails/b.jpg", id), b"jpeg"),
+    ])
+}
+
+fn ids(pages: &[PageInfo]) -> Vec<&str> {
+    pages.iter().map(|p| p.id.as_deref().unwrap()).collect()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn rearrange_pages() {
+    let cloud = FakeCloud::start().await;
+    let notes = cloud.add_document("Notes", None, vec![]);
+    cloud.modify(&notes, |d| d.blob = notebook(notes));
+    let home = tempfile::tempdir().unwrap();
+
+    let output =
+        run(&cloud, home.path(), &["pages", "list", "Notes"], b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1  a  Blank  0 strokes\n2  b  P Lines medium  0 strokes\n"
+    );
+
+    let args = ["pages", "reorder", "Notes", "--order", "2,1", "--dry-run"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("1 (was 2)  b"), "{}", stdout);
+    assert_eq!(cloud.document(&notes).unwrap().version, 1);
+
+    // An order has to give every page.
+    let args = ["pages", "reorder", "Notes", "--order", "2"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+
+    let args = ["pages", "reorder", "Notes", "--order", "2-1"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let stored = cloud.document(&notes).unwrap();
+    assert_eq!(stored.version, 2);
+    assert_eq!(
+        ids(&list_pages(notes, &stored.blob).unwrap()),
+        vec!["b", "a"]
+    );
+
+    let args = ["pages", "delete", "Notes", "--pages", "1"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let stored = cloud.document(&notes).unwrap();
+    let pages = list_pages(notes, &stored.blob).unwrap();
+    assert_eq!(ids(&pages), vec!["a"]);
+    assert_eq!(pages[0].template.as_deref(), Some("Blank"));
+}
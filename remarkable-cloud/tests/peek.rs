@@ -0,0 +1,64 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+// A notebook of three pages named by id, each with a thumbnail saying which
+// page it is.
+fn notebook(id: uuid::Uuid) -> Vec<u8> {
+    let pages = ["p-a", "p-b", "p-c"];
+    let content = serde_json::json!({
+        "fileType": "notebook",
+        "pageCount": 3,
+        "pages": pages,
+    });
+    let content = content.to_string();
+    let jpegs: Vec<String> =
+        (0..pages.len()).map(|i| format!("page {}", i)).collect();
+    let mut files = vec![(format!("{}.content", id), content.as_bytes())];
+    for (page, jpeg) in pages.iter().zip(&jpegs) {
+        files
+            .push((format!("{}.thumbnails/{}.jpg", id, page), jpeg.as_bytes()));
+    }
+    archive(&files)
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn peek() {
+    let cloud = FakeCloud::start().await;
+    let id = cloud.add_document("Quick sheets", None, vec![]);
+    cloud.modify(&id, |d| {
+        d.blob = notebook(id);
+        d.current_page = 1;
+    });
+    let empty = cloud.add_document("Empty", None, vec![]);
+    cloud.modify(&empty, |d| d.blob = notebook(uuid::Uuid::nil()));
+    let home = tempfile::tempdir().unwrap();
+    let out = home.path().join("page.jpg");
+
+    let args = ["peek", "Quick sheets", "-o", out.to_str().unwrap()];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(std::fs::read(&out).unwrap(), b"page 1");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Page 2: Saved to "), "{}", stdout);
+
+    // Past the last page, as after pages were deleted elsewhere.
+    cloud.modify(&id, |d| d.current_page = 7);
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(std::fs::read(&out).unwrap(), b"page 0");
+
+    let args = ["peek", "Empty", "-o", out.to_str().unwrap()];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("has no thumbnails"), "{}", stderr);
+
+    let output = run(&cloud, home.path(), &["peek", "Quick sheets"], b"").await;
+    assert!(!output.status.success());
+}
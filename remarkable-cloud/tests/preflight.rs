@@ -0,0 +1,32 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+// Every problem is reported, before anything is asked of the cloud.
+#[tokio::test(threaded_scheduler)]
+async fn reported_before_connecting() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let args = [
+        "push",
+        "--to",
+        "trash:/",
+        "--stdin",
+        "--name",
+        "Paper.txt",
+        "--recursive",
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Error: 3 problems with the command line:\n"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--to \"trash:/\": documents can't be put"));
+    assert!(stderr.contains("--name \"Paper.txt\": only PDFs and EPUBs"));
+    assert!(stderr.contains("--stdin and --recursive can't be used"));
+    assert!(cloud.requests().is_empty());
+}
@@ -0,0 +1,57 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+// Files over --chunked-above go up over a resumable session where the
+// cloud offers one, and whole where it doesn't.
+#[tokio::test(threaded_scheduler)]
+async fn chunked_above() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let sessions = |cloud: &FakeCloud| {
+        let requests = cloud.requests();
+        requests
+            .iter()
+            .filter(|r| r.path.starts_with("/session/"))
+            .count()
+    };
+    let args = [
+        "push",
+        "--stdin",
+        "--name",
+        "Paper.pdf",
+        "--chunked-above",
+        "100",
+    ];
+
+    let output = run(&cloud, home.path(), &args, PAPER).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(sessions(&cloud), 0);
+
+    cloud.set_resumable(true);
+    let args = [&args[..], &["--on-conflict", "duplicate"]].concat();
+    let output = run(&cloud, home.path(), &args, PAPER).await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(sessions(&cloud), 1);
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    let blobs: Vec<Vec<u8>> = docs
+        .iter()
+        .map(|d| cloud.document(&d.id).unwrap().blob)
+        .collect();
+    assert_eq!(blobs.len(), 2);
+    assert_eq!(blobs[0].len(), blobs[1].len());
+}
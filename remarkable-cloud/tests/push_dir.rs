@@ -0,0 +1,67 @@
+use std::fs;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+#[tokio::test(threaded_scheduler)]
+async fn push_a_directory() {
+    let cloud = FakeCloud::start().await;
+    let papers = cloud.add_folder("Papers", None);
+    let physics = cloud.add_folder("Physics", Some(papers));
+    let home = tempfile::tempdir().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    for name in &[
+        "Physics/Gravity.pdf",
+        "Physics/Quantum/Spin.pdf",
+        "Physics/draft.pdf",
+        "Intro.pdf",
+    ] {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, PAPER).unwrap();
+    }
+    fs::write(dir.path().join("Physics/.remarkableignore"), "draft.pdf\n")
+        .unwrap();
+    fs::write(dir.path().join("refs.bib"), "").unwrap();
+    let dir = dir.path().to_str().unwrap();
+
+    // Without -r a directory is refused.
+    let output = run(&cloud, home.path(), &["push", dir], b"").await;
+    assert!(!output.status.success());
+
+    let args = ["push", "-r", dir, "--to", "/Papers"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("neither PDFs nor EPUBs"), "{}", stdout);
+    assert!(stdout.contains("refs.bib"), "{}", stdout);
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    let mut paths: Vec<String> =
+        docs.iter().filter_map(|d| docs.path_of(&d.id)).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "Papers",
+            "Papers/Intro",
+            "Papers/Physics",
+            "Papers/Physics/Gravity",
+            "Papers/Physics/Quantum",
+            "Papers/Physics/Quantum/Spin",
+        ]
+    );
+    // The existing folder is used rather than a second one made.
+    let gravity = docs.resolve("Papers/Physics/Gravity").unwrap().unwrap();
+    assert_eq!(gravity.parent, Some(physics));
+}
@@ -0,0 +1,97 @@
+use std::fs;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+#[tokio::test(threaded_scheduler)]
+async fn push_by_settings() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_folder("Papers", None);
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join("config").join("remarkable-cloud");
+    fs::create_dir_all(&config).unwrap();
+    fs::write(
+        config.join("settings.json"),
+        r#"{"push": {
+            "mappings": {"~/papers": "/Papers", "~/scans": "/Inbox/Scans"},
+            "default_folder": "/Unsorted"
+        }}"#,
+    )
+    .unwrap();
+    for name in &["papers/Gravity.pdf", "scans/Receipt.pdf", "Loose.pdf"] {
+        let path = home.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, PAPER).unwrap();
+    }
+    let file = |name: &str| home.path().join(name).display().to_string();
+    let files = [
+        file("papers/Gravity.pdf"),
+        file("scans/Receipt.pdf"),
+        file("Loose.pdf"),
+    ];
+
+    // Nothing is pushed while any folder is missing.
+    let mut args = vec!["push"];
+    args.extend(files.iter().map(String::as_str));
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "These folders from the push settings don't exist: \
+             /Inbox/Scans, /Unsorted; make them with --create-missing"
+        ),
+        "{}",
+        stderr
+    );
+
+    args.push("--create-missing");
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!(
+            "{} goes to /Papers, by the push mapping for ~/papers",
+            files[0]
+        )),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("by the default push folder"), "{}", stdout);
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    let mut paths: Vec<String> =
+        docs.iter().filter_map(|d| docs.path_of(&d.id)).collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "Inbox",
+            "Inbox/Scans",
+            "Inbox/Scans/Receipt",
+            "Papers",
+            "Papers/Gravity",
+            "Unsorted",
+            "Unsorted/Loose",
+        ]
+    );
+
+    // --to still wins.
+    let args = ["push", "--to", "/Papers", &files[2]];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let docs = client.get_documents().await.unwrap();
+    assert!(docs
+        .iter()
+        .any(|d| docs.path_of(&d.id).as_deref() == Some("Papers/Loose")));
+}
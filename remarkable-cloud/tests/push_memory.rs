@@ -0,0 +1,98 @@
+//! Pushing many large files within `--max-memory`, measured by counting
+//! what the whole process allocates. This is the only test here, so
+//! nothing else is allocating meanwhile but the fake cloud.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::cache::ListingCache;
+use remarkable_cloud_cli::commands::{
+    self, Capture, ListingOptions, PushOptions,
+};
+use remarkable_cloud_cli::mutations::MutationLog;
+use remarkable_cloud_cli::push::{Journal, MemoryBudget};
+use remarkable_cloud_cli::summary::TransferReport;
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let now = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        PEAK.fetch_max(now + layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const MIB: usize = 1024 * 1024;
+
+#[tokio::test(threaded_scheduler)]
+async fn large_files_within_budget() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    // Mostly zeros, so what the fake cloud keeps of them is small.
+    let mut files = vec![];
+    for n in 0..6 {
+        let path = home.path().join(format!("Scan {}.pdf", n));
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(b"%PDF-1.4\n").unwrap();
+        for _ in 0..24 {
+            file.write_all(&[0; MIB]).unwrap();
+        }
+        files.push(path);
+    }
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let listing = ListingOptions {
+        verbose: false,
+        use_cache: false,
+        cache: ListingCache::new(home.path().join("listing.json")),
+        refresh: None,
+    };
+    let mut out = Capture::new(TransferReport::new());
+    let documents = commands::list_documents(&client, &listing, &mut out)
+        .await
+        .unwrap();
+    let journal = Journal::new(home.path().join("uploads"));
+    let mutations = MutationLog::new(home.path().join("mutations.json"));
+    let budget = 16 * MIB;
+    let options = PushOptions {
+        files,
+        parent: None,
+        recursive: false,
+        on_conflict: None,
+        memory: MemoryBudget::new(Some(budget as u64)),
+        optimize: None,
+        verify: None,
+    };
+
+    let baseline = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+    commands::push(
+        &client, &journal, &mutations, &documents, &options, &mut out,
+    )
+    .await
+    .unwrap();
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+    assert!(peak < budget, "{} MiB at most", peak / MIB);
+
+    assert_eq!(out.notes.len(), 6, "{:?}", out.notes);
+    let documents = client.get_documents().await.unwrap();
+    for n in 0..6 {
+        let name = format!("Scan {}", n);
+        assert!(documents.resolve(&name).unwrap().is_some(), "{}", name);
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Read;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::optimize::OPTIMIZE_BUILT;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+// The PDF of the document `name` in the root.
+async fn uploaded(cloud: &FakeCloud, name: &str) -> Vec<u8> {
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    let doc = docs.resolve(name).unwrap().unwrap();
+    let blob = cloud.document(&doc.id).unwrap().blob;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(blob)).unwrap();
+    let mut pdf = vec![];
+    archive
+        .by_name(&format!("{}.pdf", doc.id))
+        .unwrap()
+        .read_to_end(&mut pdf)
+        .unwrap();
+    pdf
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn pushes_what_it_cant_optimize_as_it_is() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let broken = home.path().join("Broken.pdf");
+    std::fs::write(&broken, b"%PDF-1.4 and then nothing").unwrap();
+    let paper = home.path().join("Paper.pdf");
+    std::fs::write(&paper, PAPER).unwrap();
+
+    let args = [
+        "push",
+        "--optimize",
+        broken.to_str().unwrap(),
+        paper.to_str().unwrap(),
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !OPTIMIZE_BUILT {
+        assert!(!output.status.success());
+        assert!(stderr.contains("optimize feature"), "{}", stderr);
+        return;
+    }
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Pushing"), "{}", stderr);
+    assert!(stderr.contains("Broken.pdf as it is"), "{}", stderr);
+    assert_eq!(
+        uploaded(&cloud, "Broken").await,
+        b"%PDF-1.4 and then nothing"
+    );
+    assert_eq!(uploaded(&cloud, "Paper").await, PAPER);
+}
+
+#[test]
+fn downsample_needs_optimize() {
+    let home = tempfile::tempdir().unwrap();
+    let output = common::command(
+        "http://127.0.0.1:9",
+        home.path(),
+        &["push", "--downsample-dpi", "200", "a.pdf"],
+    )
+    .output()
+    .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--optimize"), "{}", stderr);
+}
+
+// A one-page PDF with its page's content uncompressed and a stream nothing
+// uses.
+#[cfg(feature = "optimize")]
+fn bloated(path: &std::path::Path) {
+    use lopdf::{dictionary, Document, Object, Stream};
+
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let content = b"BT /F1 24 Tf 72 700 Td (Attention) Tj ET\n".repeat(200);
+    let content_id = doc.add_object(
+        Stream::new(dictionary! {}, content).with_compression(false),
+    );
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content_id,
+        "Resources" => dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        },
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        }),
+    );
+    doc.add_object(
+        Stream::new(dictionary! {}, vec![0; 50_000]).with_compression(false),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+    doc.save(path).unwrap();
+}
+
+#[cfg(feature = "optimize")]
+#[tokio::test(threaded_scheduler)]
+async fn optimizes() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let path = home.path().join("Attention.pdf");
+    bloated(&path);
+    let original = std::fs::read(&path).unwrap();
+
+    let args = ["push", "--optimize", path.to_str().unwrap()];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Optimized "), "{}", stdout);
+    assert!(stdout.contains("Attention.pdf: 57.4 KiB to "), "{}", stdout);
+
+    let pdf = uploaded(&cloud, "Attention").await;
+    assert!(pdf.len() * 10 < original.len(), "{}", pdf.len());
+    let doc = lopdf::Document::load_mem(&pdf).unwrap();
+    assert_eq!(doc.get_pages().len(), 1);
+    assert!(doc.extract_text(&[1]).unwrap().contains("Attention"));
+    // The file itself is left alone.
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+}
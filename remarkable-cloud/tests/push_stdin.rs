@@ -0,0 +1,104 @@
+use std::io::Read;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+#[tokio::test(threaded_scheduler)]
+async fn push_from_stdin() {
+    let cloud = FakeCloud::start().await;
+    let papers = cloud.add_folder("Papers", None);
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(
+        &cloud,
+        home.path(),
+        &["push", "--stdin", "--name", "Paper.pdf", "--to", "/Papers"],
+        PAPER,
+    )
+    .await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    let doc = docs.resolve("Papers/Paper").unwrap().unwrap();
+    assert_eq!(doc.parent, Some(papers));
+    let blob = cloud.document(&doc.id).unwrap().blob;
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(blob)).unwrap();
+    let mut uploaded = vec![];
+    archive
+        .by_name(&format!("{}.pdf", doc.id))
+        .unwrap()
+        .read_to_end(&mut uploaded)
+        .unwrap();
+    assert_eq!(uploaded, PAPER);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn push_from_stdin_checks_input() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+
+    // --name is needed to know what's being uploaded.
+    let output = run(&cloud, home.path(), &["push", "--stdin"], PAPER).await;
+    assert!(!output.status.success());
+
+    let output = run(
+        &cloud,
+        home.path(),
+        &["push", "--stdin", "--name", "Paper.pdf"],
+        b"<html>not a pdf</html>",
+    )
+    .await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("doesn't look like a PDF"), "{}", stderr);
+    assert!(cloud.requests().iter().all(|r| !r.path.contains("upload")));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn push_into_a_document() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_document("Paper", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(
+        &cloud,
+        home.path(),
+        &["push", "--stdin", "--name", "Other.pdf", "--to", "/Paper"],
+        PAPER,
+    )
+    .await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("it isn't a folder"), "{}", stderr);
+    assert!(cloud.requests().iter().all(|r| !r.path.contains("upload")));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn push_from_stdin_over_a_taken_name() {
+    let cloud = FakeCloud::start().await;
+    let paper = cloud.add_document("Paper", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let args = ["push", "--stdin", "--name", "Paper.pdf"];
+
+    // Without a terminal to ask on, the default is to skip.
+    let output = run(&cloud, home.path(), &args, PAPER).await;
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--on-conflict"), "{}", stderr);
+    assert!(cloud.requests().iter().all(|r| !r.path.contains("upload")));
+
+    let update = [&args[..], &["--on-conflict", "update"]].concat();
+    let output = run(&cloud, home.path(), &update, PAPER).await;
+    assert!(output.status.success());
+    assert_eq!(cloud.document(&paper).unwrap().version, 2);
+}
@@ -0,0 +1,108 @@
+use std::io::{Cursor, Read, Write};
+use std::process::Output;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+// The archive `blob` with `extra` added to the end of each PDF in it, as a
+// cloud which stored something other than what it was sent might.
+fn tamper(blob: &mut Vec<u8>, extra: &[u8]) {
+    let mut archive = zip::ZipArchive::new(Cursor::new(blob.clone())).unwrap();
+    let mut out = zip::ZipWriter::new(Cursor::new(vec![]));
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).unwrap();
+        let mut data = vec![];
+        file.read_to_end(&mut data).unwrap();
+        if file.name().ends_with(".pdf") {
+            data.extend_from_slice(extra);
+        }
+        out.start_file(file.name(), Default::default()).unwrap();
+        out.write_all(&data).unwrap();
+    }
+    *blob = out.finish().unwrap().into_inner();
+}
+
+fn said(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn verified_push() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let paper = home.path().join("Paper.pdf");
+    std::fs::write(&paper, PAPER).unwrap();
+    let paper = paper.to_str().unwrap();
+
+    let output =
+        run(&cloud, home.path(), &["push", "--verify", paper], b"").await;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(said(&output).contains("1 uploaded, 1 of them verified"));
+
+    // Too large to be worth reading back, it's only uploaded.
+    let args = [
+        "push",
+        "--verify",
+        "--verify-max-size",
+        "100",
+        "--on-conflict",
+        "duplicate",
+        paper,
+    ];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    let stdout = said(&output);
+    assert!(stdout.contains("Didn't read back"), "{}", stdout);
+    assert!(
+        stdout.contains("1 uploaded, 0 of them verified"),
+        "{}",
+        stdout
+    );
+
+    // Without --verify, nothing is downloaded.
+    let before = cloud.requests().len();
+    let args = ["push", "--on-conflict", "duplicate", paper];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert!(!said(&output).contains("verified"));
+    let downloads = cloud.requests()[before..]
+        .iter()
+        .filter(|r| r.path.starts_with("/blob/"))
+        .count();
+    assert_eq!(downloads, 0);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn mismatch_detected() {
+    let cloud = FakeCloud::start().await;
+    cloud.set_blob_rewrite(|blob| tamper(blob, b"\n% not what was sent"));
+    let home = tempfile::tempdir().unwrap();
+    let paper = home.path().join("Paper.pdf");
+    std::fs::write(&paper, PAPER).unwrap();
+    let paper = paper.to_str().unwrap();
+
+    let output =
+        run(&cloud, home.path(), &["push", "--verify", paper], b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("was uploaded, but the cloud's copy has SHA-256"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("--on-conflict update"), "{}", stderr);
+    // It was uploaded all the same, so it's said to have been.
+    assert!(said(&output).contains("Pushed"));
+
+    // What's read from stdin is checked too.
+    let args = ["push", "--verify", "--stdin", "--name", "Notes.pdf"];
+    let output = run(&cloud, home.path(), &args, PAPER).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Notes.pdf was uploaded, but"), "{}", stderr);
+}
@@ -0,0 +1,53 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+fn changes(cloud: &FakeCloud) -> usize {
+    cloud
+        .requests()
+        .iter()
+        .filter(|r| r.method.as_str() == "PUT" || r.method.as_str() == "DELETE")
+        .count()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn read_only_flag() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    for args in &[
+        &["--read-only", "push", "--stdin", "--name", "Paper.pdf"][..],
+        &["--read-only", "trash", "--yes", "Dune"],
+        &["--read-only", "mv", "--yes", "Dune", "Dune (1965)"],
+    ] {
+        let output = run(&cloud, home.path(), args, PAPER).await;
+        assert!(!output.status.success(), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("ReadOnly"), "{:?}: {}", args, stderr);
+    }
+    assert_eq!(changes(&cloud), 0);
+
+    // Listing is fine.
+    let output = run(&cloud, home.path(), &["--read-only", "ls"], b"").await;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Dune"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn read_only_setting() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join("config").join("remarkable-cloud");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(config.join("settings.json"), r#"{"read_only": true}"#)
+        .unwrap();
+
+    let args = ["push", "--stdin", "--name", "Paper.pdf"];
+    let output = run(&cloud, home.path(), &args, PAPER).await;
+    assert!(!output.status.success());
+    assert_eq!(changes(&cloud), 0);
+}
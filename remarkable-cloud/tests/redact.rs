@@ -0,0 +1,88 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn redacted() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    let notes = cloud.add_document("Private notes", None, vec![]);
+    let copy = cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let map = home.path().join("map.json");
+    let map_arg = map.to_str().unwrap();
+    let secrets = [
+        "Books".to_string(),
+        "Dune".to_string(),
+        "Private".to_string(),
+        books.to_string(),
+        dune.to_string(),
+        notes.to_string(),
+        copy.to_string(),
+    ];
+
+    let commands: &[&[&str]] = &[
+        &["ls", "-r"],
+        &["ls", "-r", "--paths"],
+        &["ls", "-r", "--fields", "name,id,path,type"],
+        &["info", "Books/Dune"],
+        &["find"],
+        &[
+            "find",
+            "--duplicates-of",
+            "Books/Dune",
+            "--by-name",
+            "--json",
+        ],
+        &["info", "Books/Nowhere"],
+    ];
+    for args in commands {
+        let mut args = args.to_vec();
+        args.extend(&["--redact", "--redact-map", map_arg]);
+        let output = run(&cloud, home.path(), &args, b"").await;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "{:?}: {}", args, stderr);
+        assert!(!stdout.trim().is_empty(), "{:?}", args);
+        for secret in &secrets {
+            assert!(
+                !stdout.contains(secret.as_str()),
+                "{:?}: {}",
+                args,
+                stdout
+            );
+            assert!(
+                !stderr.contains(secret.as_str()),
+                "{:?}: {}",
+                args,
+                stderr
+            );
+        }
+    }
+
+    // The structure stays: the same document reads the same throughout.
+    let args = ["ls", "-r", "--fields", "name,path,type", "--redact"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let rows: Vec<Vec<&str>> =
+        stdout.lines().map(|l| l.split('\t').collect()).collect();
+    let folder = rows.iter().find(|r| r[2] == "CollectionType").unwrap();
+    assert!(
+        rows.iter()
+            .any(|r| r[1] == format!("{}/{}", folder[0], r[0])),
+        "{}",
+        stdout
+    );
+
+    // The map tells what the last run's hashes stand for.
+    let map: std::collections::BTreeMap<String, String> =
+        serde_json::from_slice(&std::fs::read(&map).unwrap()).unwrap();
+    assert!(map.values().any(|v| v == "Dune"), "{:?}", map);
+    assert!(map.values().any(|v| *v == dune.to_string()), "{:?}", map);
+
+    // Without --redact, nothing is hidden.
+    let output = run(&cloud, home.path(), &["ls", "-r"], b"").await;
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Dune"));
+}
@@ -0,0 +1,83 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_api::DocumentArchiveBuilder;
+use remarkable_data_formats::content::Content;
+use remarkable_data_formats::metadata::Metadata;
+
+mod common;
+use common::run;
+
+// The archive of `id` as it was at `version`, on the `day`th of January.
+fn archive(id: uuid::Uuid, version: u64, day: u32) -> Vec<u8> {
+    DocumentArchiveBuilder::new()
+        .content(Content {
+            file_type: "pdf".to_string(),
+            ..Default::default()
+        })
+        .metadata(Metadata {
+            visible_name: "Dune".to_string(),
+            version,
+            last_modified: format!("2024-01-{:02}T09:00:00Z", day).parse().ok(),
+            ..Default::default()
+        })
+        .payload_pdf(format!("dune {}", version).into_bytes())
+        .build(id)
+        .unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn restore_pick() {
+    let cloud = FakeCloud::start().await;
+    let dune = cloud.add_document("Dune", None, vec![]);
+    cloud.modify(&dune, |d| d.version = 5);
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("dune");
+    std::fs::create_dir(&dir).unwrap();
+    for version in 1..=3 {
+        let name = format!("Dune-{}.zip", version);
+        let zip = archive(dune, version, version as u32);
+        std::fs::write(dir.join(name), zip).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), b"not an archive").unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let args = ["restore", "--pick", dir_arg, "--version", "2"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let restored = cloud.document(&dune).unwrap();
+    assert_eq!(restored.version, 6);
+    assert_eq!(restored.blob, archive(dune, 2, 2));
+
+    let output =
+        run(&cloud, home.path(), &["restore", "--pick", dir_arg], b"3\n").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.starts_with(
+            "  1) Dune-1.zip: Dune, version 1, modified 2024-01-01 09:00\n"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Restored Dune-3.zip as version 7 of Dune"));
+    assert_eq!(cloud.document(&dune).unwrap().blob, archive(dune, 3, 3));
+
+    // Once it's gone, it comes back as a new document.
+    cloud.modify(&dune, |d| d.trashed = true);
+    let args = ["restore", "--pick", dir_arg, "--date", "2024-01-02"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Restored Dune-2.zip as a new document, Dune"));
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Dune"));
+
+    let args = ["restore", "--pick", dir_arg, "--version", "9"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("no archive is of version 9"), "{}", stderr);
+}
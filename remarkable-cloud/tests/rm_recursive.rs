@@ -0,0 +1,59 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn rm_recursive() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let scifi = cloud.add_folder("Sci-fi", Some(books));
+    let dune = cloud.add_document("Dune", Some(scifi), vec![]);
+    let notes = cloud.add_document("Notes", Some(books), vec![]);
+    let other = cloud.add_document("Other", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(&cloud, home.path(), &["rm", "Books"], b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("isn't empty"), "{}", stderr);
+
+    // Everything is listed before anything goes.
+    let output = run(&cloud, home.path(), &["rm", "-r", "Books"], b"n\n").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("This will permanently delete 4 documents:\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("  Books/Sci-fi/Dune\n"), "{}", stdout);
+    assert!(cloud.document(&dune).is_some());
+
+    let output =
+        run(&cloud, home.path(), &["rm", "-r", "Books", "--yes"], b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let deleted: Vec<&str> = stdout
+        .lines()
+        .filter(|l| l.starts_with("Deleted "))
+        .collect();
+    assert_eq!(
+        deleted,
+        [
+            "Deleted Books/Sci-fi/Dune",
+            "Deleted Books/Notes",
+            "Deleted Books/Sci-fi",
+            "Deleted Books",
+        ]
+    );
+    for id in &[books, scifi, dune, notes] {
+        assert!(cloud.document(id).is_none());
+    }
+    assert!(cloud.document(&other).is_some());
+    let log = home.path().join("config/remarkable-cloud/mutations.log");
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 4);
+}
@@ -0,0 +1,181 @@
+use std::io::{self, BufRead};
+use std::time::Duration;
+
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, command};
+
+const PAPER: &[u8] = include_bytes!("fixtures/paper.pdf");
+
+fn pdf(contents: &[u8]) -> Vec<u8> {
+    archive(&[("p/p.pdf", contents)])
+}
+
+// Waits for the job `id` to finish, returning how it went.
+async fn finished(
+    http: &reqwest::Client,
+    url: &str,
+    token: &str,
+    id: &str,
+) -> serde_json::Value {
+    loop {
+        let job: serde_json::Value = http
+            .get(&format!("{}/jobs/{}", url, id))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if job["state"] != "queued" && job["state"] != "running" {
+            return job;
+        }
+        tokio::time::delay_for(Duration::from_millis(50)).await;
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn serve() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), pdf(b"dune"));
+    let home = tempfile::tempdir().unwrap();
+
+    // Only loopback addresses, unless told otherwise.
+    let args = ["serve", "--listen", "0.0.0.0:0"];
+    let output = command(&cloud.url(), home.path(), &args).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--allow-remote"), "{}", stderr);
+
+    let args = ["serve", "--listen", "127.0.0.1:0"];
+    let mut child = command(&cloud.url(), home.path(), &args).spawn().unwrap();
+    let mut stdout = io::BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    let url = line
+        .trim()
+        .strip_prefix("Listening on ")
+        .unwrap()
+        .to_string();
+    let token = std::fs::read_to_string(
+        home.path().join("config/remarkable-cloud/serve-token"),
+    )
+    .unwrap();
+    let token = token.trim();
+    let http = reqwest::Client::new();
+
+    let response = http
+        .get(&format!("{}/documents", url))
+        .bearer_auth("wrong")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let routes: Vec<serde_json::Value> = http
+        .get(&url)
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(routes.iter().any(|r| r["path"] == "/jobs/:id"));
+
+    let listing: Vec<serde_json::Value> = http
+        .get(&format!("{}/documents", url))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let paths: Vec<&str> = listing
+        .iter()
+        .map(|d| d["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths.len(), 2);
+    assert!(paths.contains(&"Books/Dune"), "{:?}", paths);
+
+    let response = http
+        .get(&format!("{}/documents/{}/payload", url, dune))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "application/pdf");
+    assert_eq!(&response.bytes().await.unwrap()[..], b"dune");
+
+    let out = tempfile::tempdir().unwrap();
+    let job: serde_json::Value = http
+        .post(&format!("{}/pull", url))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "paths": ["Books/Dune"],
+            "dir": out.path(),
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job = finished(&http, &url, token, job["id"].as_str().unwrap()).await;
+    assert_eq!(job["state"], "done", "{}", job);
+    assert_eq!(std::fs::read(out.path().join("Dune.pdf")).unwrap(), b"dune");
+
+    let paper = out.path().join("Paper.pdf");
+    std::fs::write(&paper, PAPER).unwrap();
+    let job: serde_json::Value = http
+        .post(&format!("{}/push", url))
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "files": [paper], "to": "Books" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job = finished(&http, &url, token, job["id"].as_str().unwrap()).await;
+    assert_eq!(job["state"], "done", "{}", job);
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let docs = client.get_documents().await.unwrap();
+    assert!(docs
+        .iter()
+        .any(|d| docs.path_of(&d.id).as_deref() == Some("Books/Paper")));
+
+    let job: serde_json::Value = http
+        .post(&format!("{}/push", url))
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "files": [paper], "to": "Nowhere" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job = finished(&http, &url, token, job["id"].as_str().unwrap()).await;
+    assert_eq!(job["state"], "failed");
+    assert_eq!(
+        job["error"],
+        "Couldn't find \"/Nowhere\": nothing named \"Nowhere\" is at the root"
+    );
+
+    let response = http
+        .post(&format!("{}/pull", url))
+        .bearer_auth(token)
+        .body("{}")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
@@ -0,0 +1,112 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::Output;
+
+use remarkable_cloud_api::testing::{FakeCloud, ONE_TIME_CODE};
+
+mod common;
+use common::{command, stdout};
+
+// Runs `setup` as someone using bash would, answering with `input`, with
+// nothing registered unless `registered`.
+async fn setup(
+    cloud: &FakeCloud,
+    home: &Path,
+    args: &[&str],
+    registered: bool,
+    input: String,
+) -> Output {
+    let args: Vec<&str> =
+        std::iter::once("setup").chain(args.to_vec()).collect();
+    let mut command = command(&cloud.url(), home, &args);
+    command
+        .env("SHELL", "/bin/bash")
+        .env("XDG_DATA_HOME", home.join("data"));
+    if !registered {
+        let state = home.join("config/remarkable-cloud/client_state.json");
+        std::fs::remove_file(state).unwrap();
+    }
+    tokio::task::spawn_blocking(move || {
+        let mut child = command.spawn().unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().unwrap()
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn first_run() {
+    let cloud = FakeCloud::start().await;
+    cloud.add_document("Dune", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join("config/remarkable-cloud");
+    let completions = home
+        .path()
+        .join("data/bash-completion/completions/remarkable-cloud");
+
+    // A mistyped code registers nothing.
+    let args = ["--endpoint", &cloud.url()];
+    let output = setup(&cloud, home.path(), &args, false, "oops\n".into());
+    let output = output.await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Couldn't register"), "{}", stderr);
+
+    let input = format!("{}\n\ny\n", ONE_TIME_CODE);
+    let output = setup(&cloud, home.path(), &args, false, input).await;
+    let out = stdout(&output);
+    assert!(out.contains("https://my.remarkable.com/device"), "{}", out);
+    assert!(out.contains("Registered this computer."), "{}", out);
+    assert!(out.contains("holds 1 documents"), "{}", out);
+    let state = std::fs::read_to_string(config.join("client_state.json"));
+    assert!(state.unwrap().contains("fake-device-token"));
+    let settings = std::fs::read_to_string(config.join("settings.json"));
+    assert!(settings.unwrap().contains("\"max_folder_depth\": 10"));
+    let script = std::fs::read_to_string(&completions).unwrap();
+    assert!(script.contains("remarkable-cloud"), "{}", script);
+
+    // Everything is done already, so nothing is asked or changed.
+    std::fs::write(config.join("settings.json"), "{}").unwrap();
+    let output = setup(&cloud, home.path(), &[], true, String::new()).await;
+    let out = stdout(&output);
+    assert!(!out.contains('?'), "{}", out);
+    assert!(out.contains("already registered"), "{}", out);
+    assert!(out.contains("Keeping the settings"), "{}", out);
+    assert!(out.contains("already installed"), "{}", out);
+    let settings = std::fs::read_to_string(config.join("settings.json"));
+    assert_eq!(settings.unwrap(), "{}");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn defaults() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join("config/remarkable-cloud");
+
+    // Without a code there's nothing to register with, and completions
+    // are only installed when asked for.
+    let args = ["--defaults"];
+    let output = setup(&cloud, home.path(), &args, false, String::new());
+    let out = stdout(&output.await);
+    assert!(out.contains("Skipped registering"), "{}", out);
+    assert!(out.contains("Skipped shell completions"), "{}", out);
+    assert!(!config.join("client_state.json").exists());
+    assert!(config.join("settings.json").exists());
+
+    let args = [
+        "--defaults",
+        "--endpoint",
+        &cloud.url(),
+        "--code",
+        ONE_TIME_CODE,
+    ];
+    let output = setup(&cloud, home.path(), &args, false, String::new());
+    let out = stdout(&output.await);
+    assert!(out.contains("holds 0 documents"), "{}", out);
+}
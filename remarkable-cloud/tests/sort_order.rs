@@ -0,0 +1,60 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::sort::LOCALE_BUILT;
+
+mod common;
+use common::{run, stdout};
+
+#[tokio::test(threaded_scheduler)]
+async fn natural_by_default() {
+    let cloud = FakeCloud::start().await;
+    let meetings = cloud.add_folder("Meetings", None);
+    for name in &["Meeting 10", "Meeting 2", "Meeting 1"] {
+        cloud.add_document(name, Some(meetings), vec![]);
+    }
+    cloud.add_folder("Meetings 2", None);
+    let home = tempfile::tempdir().unwrap();
+
+    let output = run(&cloud, home.path(), &["ls", "-r", "--paths"], b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Meetings\n\
+         Meetings/Meeting 1\n\
+         Meetings/Meeting 2\n\
+         Meetings/Meeting 10\n\
+         Meetings 2\n"
+    );
+    let output = run(&cloud, home.path(), &["ls", "Meetings"], b"").await;
+    let names: Vec<String> = stdout(&output)
+        .lines()
+        .map(|l| l.rsplit_once(' ').unwrap().0.to_string())
+        .collect();
+    assert_eq!(names, ["Meeting 1", "Meeting 2", "Meeting 10"]);
+    let output =
+        run(&cloud, home.path(), &["find", "--path", "Meetings/*"], b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Meetings/Meeting 1\nMeetings/Meeting 2\nMeetings/Meeting 10\n"
+    );
+
+    // Byte by byte, as scripts may rely on.
+    let args = ["ls", "-r", "--paths", "--sort", "codepoint"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Meetings\n\
+         Meetings 2\n\
+         Meetings/Meeting 1\n\
+         Meetings/Meeting 10\n\
+         Meetings/Meeting 2\n"
+    );
+    let args = ["find", "--path", "Meetings/*", "--sort", "codepoint"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        stdout(&output),
+        "Meetings/Meeting 1\nMeetings/Meeting 10\nMeetings/Meeting 2\n"
+    );
+
+    let args = ["ls", "--sort", "locale"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(output.status.success(), LOCALE_BUILT);
+}
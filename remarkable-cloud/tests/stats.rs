@@ -0,0 +1,64 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::{archive, run};
+
+// A page with one ballpoint stroke, 50px long.
+fn page() -> Vec<u8> {
+    let mut buf =
+        format!("{:<43}", "reMarkable .lines file, version=5").into_bytes();
+    for n in &[1u32, 1, 15, 0, 0] {
+        buf.extend_from_slice(&n.to_le_bytes());
+    }
+    buf.extend_from_slice(&2f32.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend_from_slice(&2u32.to_le_bytes());
+    for v in &[
+        0f32, 0.0, 0.0, 0.0, 2.0, 1.0, 30.0, 40.0, 0.0, 0.0, 2.0, 1.0,
+    ] {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+    buf
+}
+
+// A notebook whose first page is drawn on, second is blank and third can't
+// be read.
+fn notebook(id: uuid::Uuid) -> Vec<u8> {
+    let content = br#"{"fileType": "notebook", "pages": ["a", "b", "c"]}"#;
+    archive(&[
+        (format!("{}.content", id), content),
+        (format!("{}/a.rm", id), &page()),
+        (format!("{}/c.rm", id), b"garbage"),
+    ])
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn ink_stats() {
+    let cloud = FakeCloud::start().await;
+    let journals = cloud.add_folder("Journals", None);
+    let journal = cloud.add_document("2024", Some(journals), vec![]);
+    cloud.modify(&journal, |d| d.blob = notebook(journal));
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["stats", "--ink", "Journals"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Journals/2024: 3 pages, 1 strokes, 50 px of ink, 1 layers\n  \
+         ballpoint 1\n  |█ ?|\n  page 3 couldn't be read\n"
+    );
+
+    let args = ["stats", "--ink", "--json", "Journals/2024"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let json: serde_json::Value =
+        serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["total"]["strokes"], 1);
+    assert_eq!(json["unreadable_pages"], 1);
+    assert_eq!(json["pages"][1]["strokes"], 0);
+    assert_eq!(json["pages"][2]["unreadable"], true);
+}
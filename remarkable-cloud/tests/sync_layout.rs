@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::layout::Layout;
+use remarkable_cloud_cli::sync::{self, Manifest};
+use uuid::Uuid;
+
+mod common;
+use common::{archive, run, stdout};
+
+const PDF: &[u8] = b"%PDF-1.4 from the cloud";
+
+// A document holding a PDF, with `tags` on it, last modified on `date`.
+fn add(
+    cloud: &FakeCloud,
+    name: &str,
+    parent: Uuid,
+    date: &str,
+    tags: &[&str],
+) -> Uuid {
+    let id = cloud.add_document(name, Some(parent), vec![]);
+    let tags: Vec<serde_json::Value> = tags
+        .iter()
+        .map(|t| serde_json::json!({"name": t, "timestamp": 1}))
+        .collect();
+    let content = serde_json::json!({"fileType": "pdf", "tags": tags});
+    let blob = archive(&[
+        (format!("{}.pdf", id), PDF),
+        (format!("{}.content", id), content.to_string().as_bytes()),
+    ]);
+    let modified = format!("{}T12:00:00Z", date).parse().unwrap();
+    cloud.modify(&id, |d| {
+        d.blob = blob;
+        d.modified_client = modified;
+    });
+    id
+}
+
+// The files in `dir` a sync looks at, sorted.
+fn files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = sync::local_files(dir)
+        .unwrap()
+        .into_iter()
+        .map(|f| f.path)
+        .collect();
+    files.sort();
+    files
+}
+
+async fn assert_in_step(cloud: &FakeCloud, home: &Path, dir: &Path) {
+    let args = ["sync", "status", dir.to_str().unwrap()];
+    let output = run(cloud, home, &args, b"").await;
+    assert!(stdout(&output).contains("in step"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn mirror_to_by_date() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let scifi = cloud.add_folder("SciFi", Some(books));
+    add(&cloud, "Dune", scifi, "2023-11-05", &[]);
+    let emma = add(&cloud, "Emma", books, "2024-02-10", &[]);
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("books");
+    let dir_arg = dir.to_str().unwrap();
+
+    let args = ["sync", "pull", dir_arg, "/Books"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let printed = stdout(&output);
+    assert!(printed.contains("Pulled SciFi/Dune.pdf"), "{}", printed);
+    assert_eq!(files(&dir), ["Emma.pdf", "SciFi/Dune.pdf"]);
+    assert_eq!(std::fs::read(dir.join("Emma.pdf")).unwrap(), PDF);
+    assert_in_step(&cloud, home.path(), &dir).await;
+
+    // Pulling with another layout would pull everything again beside
+    // what's there, so it's refused.
+    let args = ["sync", "pull", dir_arg, "--layout", "by-date"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is laid out mirror"), "{}", stderr);
+    assert_eq!(files(&dir), ["Emma.pdf", "SciFi/Dune.pdf"]);
+
+    let args = ["sync", "pull", dir_arg, "--layout", "by-date", "--relayout"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let printed = stdout(&output);
+    assert!(
+        printed.contains("Moved the files of 2 documents"),
+        "{}",
+        printed
+    );
+    assert_eq!(files(&dir), ["2023/11/Dune.pdf", "2024/02/Emma.pdf"]);
+    assert!(!dir.join("SciFi").exists());
+    assert!(!dir.join(sync::RELAYOUT_DIR_NAME).exists());
+    let manifest = Manifest::load(&dir).unwrap().unwrap();
+    assert_eq!(manifest.layout, Layout::ByDate);
+    assert_in_step(&cloud, home.path(), &dir).await;
+
+    // The layout is kept, so a document changed later moves with it.
+    let march = "2024-03-01T00:00:00Z".parse().unwrap();
+    cloud.modify(&emma, |d| {
+        d.version += 1;
+        d.modified_client = march;
+    });
+    let args = ["sync", "pull", dir_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(stdout(&output).contains("Pulled 2024/03/Emma.pdf"));
+    assert_eq!(files(&dir), ["2023/11/Dune.pdf", "2024/03/Emma.pdf"]);
+    assert_in_step(&cloud, home.path(), &dir).await;
+
+    // Notebooks have no file to pull.
+    cloud.add_document("Sketches", Some(books), vec![]);
+    let output = run(&cloud, home.path(), &args, b"").await;
+    let printed = stdout(&output);
+    assert!(printed.contains("Skipped Sketches"), "{}", printed);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn by_tag() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    add(&cloud, "Dune", books, "2023-11-05", &["SciFi", "Classics"]);
+    add(&cloud, "Emma", books, "2024-02-10", &[]);
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("books");
+    let dir_arg = dir.to_str().unwrap();
+
+    let args = ["sync", "pull", dir_arg, "/Books", "--layout", "by-tag"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    stdout(&output);
+    assert_eq!(
+        files(&dir),
+        ["Classics/Dune.pdf", "Emma.pdf", "SciFi/Dune.pdf"]
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let inode =
+            |path: &str| std::fs::metadata(dir.join(path)).unwrap().ino();
+        assert_eq!(inode("SciFi/Dune.pdf"), inode("Classics/Dune.pdf"));
+    }
+    assert_in_step(&cloud, home.path(), &dir).await;
+
+    // Nothing changed, so nothing's pulled again.
+    let args = ["sync", "pull", dir_arg];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(stdout(&output).contains("Nothing to pull from /Books"));
+
+    // And all in the directory itself, the links gone.
+    let args = ["sync", "pull", dir_arg, "--layout", "flat", "--relayout"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    stdout(&output);
+    assert_eq!(files(&dir), ["Dune.pdf", "Emma.pdf"]);
+    assert_in_step(&cloud, home.path(), &dir).await;
+}
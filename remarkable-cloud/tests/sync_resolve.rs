@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::time::UNIX_EPOCH;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::layout::Layout;
+use remarkable_cloud_cli::sync::{self, Manifest, ManifestEntry};
+use uuid::Uuid;
+
+mod common;
+use common::{run, stdout};
+
+const LOCAL: &[u8] = b"%PDF-1.4 edited here";
+const CLOUD: &[u8] = b"%PDF-1.4 edited on the tablet";
+
+fn archive(id: Uuid, pdf: &[u8]) -> Vec<u8> {
+    common::archive(&[(format!("{}.pdf", id), pdf)])
+}
+
+fn add(cloud: &FakeCloud, name: &str, parent: Uuid, pdf: &[u8]) -> Uuid {
+    let id = cloud.add_document(name, Some(parent), vec![]);
+    let blob = archive(id, pdf);
+    cloud.modify(&id, |d| d.blob = blob);
+    id
+}
+
+// An entry for `path` as synced with `id` at the version it's at now, when
+// the file was of another size.
+fn entry(cloud: &FakeCloud, dir: &Path, path: &str, id: Uuid) -> ManifestEntry {
+    let fake = cloud.document(&id).unwrap();
+    let mtime = std::fs::metadata(dir.join(path))
+        .map(|m| m.modified().unwrap())
+        .unwrap_or(UNIX_EPOCH);
+    ManifestEntry {
+        path: path.to_string(),
+        key: path.to_string(),
+        id,
+        version: fake.version,
+        modified: fake.modified_client,
+        size: 1,
+        mtime: mtime.duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+        sha256: String::new(),
+    }
+}
+
+// A directory synced with /Books, where Dune.pdf changed on both sides,
+// Emma.pdf was deleted here and changed in the cloud, and Hyperion.pdf was
+// added on both sides.
+struct Conflicted {
+    dir: PathBuf,
+    dune: Uuid,
+    emma: Uuid,
+    hyperion: Uuid,
+}
+
+fn conflicted(cloud: &FakeCloud, home: &Path) -> Conflicted {
+    let books = cloud.add_folder("Books", None);
+    let dune = add(cloud, "Dune", books, CLOUD);
+    let emma = add(cloud, "Emma", books, CLOUD);
+    let hyperion = add(cloud, "Hyperion", books, CLOUD);
+    let dir = home.join("books");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("Dune.pdf"), LOCAL).unwrap();
+    std::fs::write(dir.join("Hyperion.pdf"), LOCAL).unwrap();
+    let manifest = Manifest {
+        folder: "/Books".to_string(),
+        layout: Layout::Mirror,
+        entries: vec![
+            entry(cloud, &dir, "Dune.pdf", dune),
+            entry(cloud, &dir, "Emma.pdf", emma),
+        ],
+    };
+    manifest.save(&dir).unwrap();
+    cloud.modify(&dune, |d| d.version += 1);
+    cloud.modify(&emma, |d| d.version += 1);
+    Conflicted {
+        dir,
+        dune,
+        emma,
+        hyperion,
+    }
+}
+
+// The PDF in the document `id`'s archive.
+fn pdf_of(cloud: &FakeCloud, id: Uuid) -> Vec<u8> {
+    let blob = cloud.document(&id).unwrap().blob;
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(blob)).unwrap();
+    let mut file = zip.by_name(&format!("{}.pdf", id)).unwrap();
+    let mut data = vec![];
+    std::io::Read::read_to_end(&mut file, &mut data).unwrap();
+    data
+}
+
+// The documents named `name`, wherever they are.
+async fn named(cloud: &FakeCloud, name: &str) -> Vec<Uuid> {
+    let mut client = cloud.client();
+    client.refresh_token().await.unwrap();
+    let documents = client.get_documents().await.unwrap();
+    documents
+        .iter()
+        .filter(|d| &*d.visible_name == name)
+        .map(|d| d.id)
+        .collect()
+}
+
+// Resolves `dir`, with `strategy` if given.
+async fn resolve(
+    cloud: &FakeCloud,
+    home: &Path,
+    dir: &Path,
+    strategy: Option<&str>,
+) -> Output {
+    let mut args = vec!["sync", "resolve", dir.to_str().unwrap()];
+    if let Some(strategy) = strategy {
+        args.extend(&["--strategy", strategy]);
+    }
+    run(cloud, home, &args, b"").await
+}
+
+async fn assert_in_step(cloud: &FakeCloud, home: &Path, dir: &Path) {
+    let args = ["sync", "status", dir.to_str().unwrap()];
+    let output = run(cloud, home, &args, b"").await;
+    assert!(stdout(&output).contains("in step"));
+    assert!(!dir.join(sync::RESOLVE_JOURNAL_NAME).exists());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keep_cloud() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let c = conflicted(&cloud, home.path());
+
+    // Without a strategy or a terminal to ask on, nothing is done.
+    let output = resolve(&cloud, home.path(), &c.dir, None).await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("3 conflicts left"), "{}", stderr);
+    assert!(
+        stderr.contains("Emma.pdf, which was deleted here"),
+        "{}",
+        stderr
+    );
+
+    let output = resolve(&cloud, home.path(), &c.dir, Some("keep-cloud")).await;
+    assert_eq!(
+        stdout(&output),
+        "Kept the cloud's Dune.pdf\n\
+         Kept the cloud's Emma.pdf\n\
+         Kept the cloud's Hyperion.pdf\n"
+    );
+    for name in &["Dune.pdf", "Emma.pdf", "Hyperion.pdf"] {
+        assert_eq!(std::fs::read(c.dir.join(name)).unwrap(), CLOUD);
+    }
+    assert_in_step(&cloud, home.path(), &c.dir).await;
+
+    let output = resolve(&cloud, home.path(), &c.dir, None).await;
+    assert!(stdout(&output).contains("Nothing in"));
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keep_local() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let c = conflicted(&cloud, home.path());
+    let version = cloud.document(&c.dune).unwrap().version;
+
+    let output = resolve(&cloud, home.path(), &c.dir, Some("keep-local")).await;
+    stdout(&output);
+    assert_eq!(cloud.document(&c.dune).unwrap().version, version + 1);
+    assert_eq!(pdf_of(&cloud, c.dune), LOCAL);
+    assert_eq!(pdf_of(&cloud, c.hyperion), LOCAL);
+    // Deleted here, so deleted there too.
+    assert!(cloud.document(&c.emma).unwrap().trashed);
+    assert!(!c.dir.join("Emma.pdf").exists());
+    assert_in_step(&cloud, home.path(), &c.dir).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn keep_both() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let c = conflicted(&cloud, home.path());
+    // A name the copy of Hyperion can't take.
+    let books = cloud.document(&c.dune).unwrap().parent;
+    add(&cloud, "Hyperion (local)", books.unwrap(), CLOUD);
+
+    let output = resolve(&cloud, home.path(), &c.dir, Some("keep-both")).await;
+    assert_eq!(
+        stdout(&output),
+        "Kept both of Dune.pdf: the cloud's there, and this one as \
+         Dune (local).pdf\n\
+         Kept the cloud's Emma.pdf\n\
+         Kept both of Hyperion.pdf: the cloud's there, and this one as \
+         Hyperion (local 2).pdf\n"
+    );
+    assert_eq!(std::fs::read(c.dir.join("Dune.pdf")).unwrap(), CLOUD);
+    assert_eq!(
+        std::fs::read(c.dir.join("Dune (local).pdf")).unwrap(),
+        LOCAL
+    );
+    assert_eq!(std::fs::read(c.dir.join("Emma.pdf")).unwrap(), CLOUD);
+    let copies = named(&cloud, "Dune (local)").await;
+    assert_eq!(copies.len(), 1);
+    assert_eq!(cloud.document(&copies[0]).unwrap().parent, books);
+    assert_eq!(pdf_of(&cloud, copies[0]), LOCAL);
+    let copies = named(&cloud, "Hyperion (local 2)").await;
+    assert_eq!(pdf_of(&cloud, copies[0]), LOCAL);
+    let args = ["sync", "status", c.dir.to_str().unwrap()];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Only in the cloud (1):\n    Hyperion (local)\n"
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn interrupted() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let c = conflicted(&cloud, home.path());
+
+    // Dune's copy is uploaded, but the cloud's Dune can't be pulled.
+    cloud.fail_next(&format!("/blob/{}", c.dune), false);
+    let output = resolve(&cloud, home.path(), &c.dir, Some("keep-both")).await;
+    assert!(!output.status.success());
+    assert!(c.dir.join(sync::RESOLVE_JOURNAL_NAME).exists());
+    assert_eq!(std::fs::read(c.dir.join("Dune.pdf")).unwrap(), LOCAL);
+    assert_eq!(named(&cloud, "Dune (local)").await.len(), 1);
+
+    // Carried on as decided, whatever's asked now, without uploading the
+    // copy again.
+    let output = resolve(&cloud, home.path(), &c.dir, Some("keep-local")).await;
+    let printed = stdout(&output);
+    assert!(printed.starts_with("Carrying on"), "{}", printed);
+    assert!(printed.contains("Kept both of Hyperion.pdf"), "{}", printed);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Ignored"));
+    assert_eq!(std::fs::read(c.dir.join("Dune.pdf")).unwrap(), CLOUD);
+    assert_eq!(named(&cloud, "Dune (local)").await.len(), 1);
+    assert_in_step(&cloud, home.path(), &c.dir).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn interrupted_then_changed() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let c = conflicted(&cloud, home.path());
+    cloud.fail_next(&format!("/blob/{}", c.dune), false);
+    let output = resolve(&cloud, home.path(), &c.dir, Some("keep-both")).await;
+    assert!(!output.status.success());
+
+    // Hyperion changed in the cloud since it was decided on, so how it
+    // conflicts is looked at again, but it's still kept both ways.
+    cloud.modify(&c.hyperion, |d| d.version += 1);
+    let output = resolve(&cloud, home.path(), &c.dir, None).await;
+    let printed = stdout(&output);
+    assert!(
+        printed.contains(
+            "Hyperion.pdf changed since it was planned; planning it again"
+        ),
+        "{}",
+        printed
+    );
+    assert!(printed.contains("Kept both of Hyperion.pdf"), "{}", printed);
+    assert_eq!(std::fs::read(c.dir.join("Hyperion.pdf")).unwrap(), CLOUD);
+    assert_in_step(&cloud, home.path(), &c.dir).await;
+}
@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::sync;
+use uuid::Uuid;
+
+mod common;
+use common::{archive, run, stdout};
+
+const PDF: &[u8] = b"%PDF-1.4 from the cloud";
+const EDITED: &[u8] = b"%PDF-1.4 edited on the tablet";
+
+fn add(cloud: &FakeCloud, name: &str, parent: Uuid) -> Uuid {
+    let id = cloud.add_document(name, Some(parent), vec![]);
+    let blob = archive(&[(format!("{}.pdf", id), PDF)]);
+    cloud.modify(&id, |d| d.blob = blob);
+    id
+}
+
+// A folder Books in the cloud holding Dune, Emma and Hyperion, and the
+// directory it's to be pulled into.
+struct Books {
+    folder: Uuid,
+    dune: Uuid,
+    emma: Uuid,
+    hyperion: Uuid,
+    dir: PathBuf,
+}
+
+fn books(cloud: &FakeCloud, home: &Path) -> Books {
+    let folder = cloud.add_folder("Books", None);
+    Books {
+        folder,
+        dune: add(cloud, "Dune", folder),
+        emma: add(cloud, "Emma", folder),
+        hyperion: add(cloud, "Hyperion", folder),
+        dir: home.join("books"),
+    }
+}
+
+async fn pull(cloud: &FakeCloud, home: &Path, args: &[&str]) -> String {
+    let output = run(cloud, home, args, b"").await;
+    stdout(&output)
+}
+
+fn downloads(cloud: &FakeCloud, id: Uuid) -> usize {
+    let path = format!("/blob/{}", id);
+    cloud.requests().iter().filter(|r| r.path == path).count()
+}
+
+async fn assert_in_step(cloud: &FakeCloud, home: &Path, dir: &Path) {
+    let args = ["sync", "status", dir.to_str().unwrap()];
+    let output = run(cloud, home, &args, b"").await;
+    assert!(stdout(&output).contains("in step"));
+    assert!(!dir.join(sync::PLAN_NAME).exists());
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn before_planning() {
+    // Interrupted before a plan was saved, so there's none to carry on.
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let b = books(&cloud, home.path());
+    let dir_arg = b.dir.to_str().unwrap();
+
+    let args = ["sync", "pull", dir_arg, "/Books", "--resume-plan"];
+    let printed = pull(&cloud, home.path(), &args).await;
+    assert!(printed.starts_with("There's no saved plan"), "{}", printed);
+    assert!(printed.contains("Pulled Hyperion.pdf"), "{}", printed);
+    assert_in_step(&cloud, home.path(), &b.dir).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn before_transferring() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let b = books(&cloud, home.path());
+    let dir_arg = b.dir.to_str().unwrap();
+
+    // Planned, but nothing pulled.
+    cloud.fail_next(&format!("/blob/{}", b.dune), false);
+    let args = ["sync", "pull", dir_arg, "/Books"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    assert!(b.dir.join(sync::PLAN_NAME).exists());
+
+    let args = ["sync", "pull", dir_arg, "--resume-plan"];
+    let printed = pull(&cloud, home.path(), &args).await;
+    assert_eq!(
+        printed,
+        "Pulled Dune.pdf\nPulled Emma.pdf\nPulled Hyperion.pdf\n"
+    );
+    assert_in_step(&cloud, home.path(), &b.dir).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn while_transferring() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let b = books(&cloud, home.path());
+    let dir_arg = b.dir.to_str().unwrap();
+
+    // Dune is pulled, then the pull is cut off at Emma.
+    cloud.fail_next(&format!("/blob/{}", b.emma), false);
+    let args = ["sync", "pull", dir_arg, "/Books"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    assert_eq!(std::fs::read(b.dir.join("Dune.pdf")).unwrap(), PDF);
+    assert!(!b.dir.join("Emma.pdf").exists());
+
+    // Since, Hyperion has changed, and Ubik has been added.
+    let blob = archive(&[(format!("{}.pdf", b.hyperion), EDITED)]);
+    cloud.modify(&b.hyperion, |d| {
+        d.version += 1;
+        d.blob = blob;
+    });
+    add(&cloud, "Ubik", b.folder);
+
+    let args = ["sync", "pull", dir_arg, "--resume-plan"];
+    let printed = pull(&cloud, home.path(), &args).await;
+    assert_eq!(
+        printed,
+        "Hyperion changed since it was planned; planning it again\n\
+         Pulled Emma.pdf\n\
+         Pulled Hyperion.pdf\n\
+         Pulled Ubik.pdf\n"
+    );
+    assert_eq!(downloads(&cloud, b.dune), 1);
+    assert_eq!(std::fs::read(b.dir.join("Hyperion.pdf")).unwrap(), EDITED);
+    assert_in_step(&cloud, home.path(), &b.dir).await;
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn plan_of_another_version() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let b = books(&cloud, home.path());
+    let dir_arg = b.dir.to_str().unwrap();
+    std::fs::create_dir(&b.dir).unwrap();
+    std::fs::write(
+        b.dir.join(sync::PLAN_NAME),
+        r#"{"plan_version": 99, "items": []}"#,
+    )
+    .unwrap();
+
+    let args = ["sync", "pull", dir_arg, "/Books", "--resume-plan"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is of version 99"), "{}", stderr);
+
+    // Without --resume-plan, it's planned afresh.
+    let args = ["sync", "pull", dir_arg, "/Books"];
+    pull(&cloud, home.path(), &args).await;
+    assert_in_step(&cloud, home.path(), &b.dir).await;
+}
@@ -0,0 +1,71 @@
+use std::time::UNIX_EPOCH;
+
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::layout::Layout;
+use remarkable_cloud_cli::sync::{Manifest, ManifestEntry};
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn sync_status() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    let home = tempfile::tempdir().unwrap();
+    let dir = home.path().join("books");
+    std::fs::create_dir(&dir).unwrap();
+    std::fs::write(dir.join("Dune.pdf"), b"dune").unwrap();
+    let meta = std::fs::metadata(dir.join("Dune.pdf")).unwrap();
+    let fake = cloud.document(&dune).unwrap();
+    let manifest = Manifest {
+        folder: "/Books".to_string(),
+        layout: Layout::Mirror,
+        entries: vec![ManifestEntry {
+            path: "Dune.pdf".to_string(),
+            key: "Dune.pdf".to_string(),
+            id: dune,
+            version: fake.version,
+            modified: fake.modified_client,
+            size: 4,
+            mtime: meta
+                .modified()
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64,
+            sha256: String::new(),
+        }],
+    };
+    manifest.save(&dir).unwrap();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output =
+        run(&cloud, home.path(), &["sync", "status", dir_arg], b"").await;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("in step"));
+
+    cloud.modify(&dune, |d| d.version += 1);
+    cloud.add_document("Emma", Some(books), vec![]);
+    std::fs::write(dir.join("Hyperion.epub"), b"hyperion").unwrap();
+    let output =
+        run(&cloud, home.path(), &["sync", "status", dir_arg], b"").await;
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Changed in the cloud (1):\n    Dune.pdf\n\n\
+         Only here (1):\n    Hyperion.epub\n\n\
+         Only in the cloud (1):\n    Emma\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 paths differ"));
+
+    let output =
+        run(&cloud, home.path(), &["sync", "status", "elsewhere"], b"").await;
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("hasn't been synced")
+    );
+}
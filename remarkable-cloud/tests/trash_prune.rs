@@ -0,0 +1,130 @@
+use remarkable_cloud_api::testing::FakeCloud;
+use uuid::Uuid;
+
+mod common;
+use common::run;
+
+// Puts `id` in the trash `days` ago.
+fn trash(cloud: &FakeCloud, id: &Uuid, days: i64) {
+    cloud.modify(id, |d| {
+        d.trashed = true;
+        d.modified_client = chrono::Utc::now() - chrono::Duration::days(days);
+    });
+}
+
+fn deleted(output: &std::process::Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|l| l.starts_with("Deleted "))
+        .map(String::from)
+        .collect()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn trash_prune() {
+    let cloud = FakeCloud::start().await;
+    let old = cloud.add_folder("Old", None);
+    let inside = cloud.add_document("Inside", Some(old), vec![]);
+    let older = cloud.add_document("Older", None, vec![]);
+    let recent = cloud.add_document("Recent", None, vec![]);
+    let kept = cloud.add_document("Kept", None, vec![]);
+    trash(&cloud, &old, 40);
+    trash(&cloud, &older, 60);
+    trash(&cloud, &recent, 5);
+    let home = tempfile::tempdir().unwrap();
+
+    let args = ["trash", "prune", "--older-than", "30d", "--dry-run"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Would delete Trash/Older\n\
+         Would delete Trash/Old\n\
+         Would delete Trash/Old/Inside\n"
+    );
+
+    // Asked about even when there's only one.
+    let args = [
+        "trash",
+        "prune",
+        "--older-than",
+        "30d",
+        "--keep-latest",
+        "2",
+    ];
+    let output = run(&cloud, home.path(), &args, b"n\n").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(
+            "This will permanently delete 1 documents:\n  Trash/Older\n"
+        ),
+        "{}",
+        stdout
+    );
+    assert!(cloud.document(&older).is_some());
+
+    let output = run(&cloud, home.path(), &args, b"y\n").await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.ends_with("[y/N] Deleted Trash/Older\n"),
+        "{}",
+        stdout
+    );
+    assert!(cloud.document(&older).is_none());
+
+    let args = ["trash", "prune", "--older-than", "30d", "--yes"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(output.status.success());
+    assert_eq!(
+        deleted(&output),
+        ["Deleted Trash/Old/Inside", "Deleted Trash/Old"]
+    );
+    assert!(cloud.document(&inside).is_none());
+    assert!(cloud.document(&recent).is_some());
+
+    let output = run(&cloud, home.path(), &["trash", "empty", "-y"], b"").await;
+    assert!(output.status.success());
+    assert_eq!(deleted(&output), ["Deleted Trash/Recent"]);
+    assert!(cloud.document(&recent).is_none());
+    assert!(cloud.document(&kept).is_some());
+
+    let output = run(&cloud, home.path(), &["trash", "empty"], b"").await;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Nothing to delete\n"
+    );
+
+    // Trashing by path still works alongside.
+    let output = run(&cloud, home.path(), &["trash", "Kept"], b"").await;
+    assert!(output.status.success());
+    assert!(cloud.document(&kept).unwrap().trashed);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn cached_prune_checks_first() {
+    let cloud = FakeCloud::start().await;
+    let old = cloud.add_document("Old", None, vec![]);
+    let older = cloud.add_document("Older", None, vec![]);
+    trash(&cloud, &old, 40);
+    trash(&cloud, &older, 60);
+    let home = tempfile::tempdir().unwrap();
+    let output = run(&cloud, home.path(), &["ls"], b"").await;
+    assert!(output.status.success());
+
+    // Taken back out of the trash on the tablet since the listing was
+    // cached, so it mustn't be deleted.
+    cloud.modify(&old, |d| {
+        d.trashed = false;
+        d.version += 1;
+    });
+    let args = ["--cached", "trash", "empty", "--yes"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("changed since cache"), "{}", stderr);
+    assert!(deleted(&output).is_empty());
+    assert!(cloud.document(&old).is_some());
+    assert!(cloud.document(&older).is_some());
+}
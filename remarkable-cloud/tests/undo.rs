@@ -0,0 +1,65 @@
+use remarkable_cloud_api::testing::FakeCloud;
+
+mod common;
+use common::run;
+
+#[tokio::test(threaded_scheduler)]
+async fn undo_moves() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    let notes = cloud.add_document("Notes", None, vec![]);
+    let old = cloud.add_document("Old", None, vec![]);
+    let home = tempfile::tempdir().unwrap();
+
+    for args in [
+        &["mv", "Books/Dune", "/Dune (1965)"][..],
+        &["trash", "Notes", "--yes"],
+        &["rm", "Old", "--yes"],
+    ] {
+        let output = run(&cloud, home.path(), args, b"").await;
+        assert!(
+            output.status.success(),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    assert!(cloud.document(&old).is_none());
+    let log = home.path().join("config/remarkable-cloud/mutations.log");
+    assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 4);
+
+    // Opened on the tablet since it was trashed.
+    cloud.modify(&notes, |d| d.version += 1);
+    let output = run(&cloud, home.path(), &["undo", "--last", "3"], b"").await;
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert!(
+        lines[0].ends_with(": deletions can't be undone"),
+        "{}",
+        stdout
+    );
+    assert!(
+        lines[1].ends_with(": changed since, and now at version 3"),
+        "{}",
+        stdout
+    );
+    assert!(lines[2].starts_with(&format!("Undid {} v2: ", dune)));
+    let doc = cloud.document(&dune).unwrap();
+    assert_eq!(
+        (doc.parent, doc.visible_name.as_str()),
+        (Some(books), "Dune")
+    );
+    assert!(cloud.document(&notes).unwrap().trashed);
+
+    // An undone change isn't undone again.
+    let output = run(&cloud, home.path(), &["undo"], b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("deletions can't be undone"), "{}", stdout);
+    let output = run(&cloud, home.path(), &["undo", "--last", "9"], b"").await;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 2, "{}", stdout);
+    assert_eq!(cloud.document(&dune).unwrap().version, 3);
+}
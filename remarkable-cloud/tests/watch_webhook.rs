@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Output;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use remarkable_cloud_api::testing::FakeCloud;
+use remarkable_cloud_cli::watch::Event;
+use remarkable_cloud_cli::webhook::{self, SIGNATURE_HEADER};
+
+mod common;
+use common::run;
+
+// A delivery's signature and body.
+type Taken = (String, Vec<u8>);
+
+// A webhook receiver, turning deliveries away while `failing` says to.
+#[derive(Clone, Default)]
+struct Receiver {
+    taken: Arc<Mutex<Vec<Taken>>>,
+    failing: Arc<Mutex<Vec<bool>>>,
+}
+
+impl Receiver {
+    fn start(&self) -> String {
+        let receiver = self.clone();
+        let make = make_service_fn(move |_| {
+            let receiver = receiver.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let receiver = receiver.clone();
+                    async move { Ok::<_, Infallible>(receiver.take(req).await) }
+                }))
+            }
+        });
+        let addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let server = hyper::Server::bind(&addr).serve(make);
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    async fn take(&self, req: Request<Body>) -> Response<Body> {
+        let signature = req.headers()[SIGNATURE_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let fail = {
+            let mut failing = self.failing.lock().unwrap();
+            !failing.is_empty() && failing.remove(0)
+        };
+        let status = if fail {
+            StatusCode::BAD_GATEWAY
+        } else {
+            self.taken.lock().unwrap().push((signature, body.to_vec()));
+            StatusCode::OK
+        };
+        Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn fail(&self, pattern: &[bool]) {
+        *self.failing.lock().unwrap() = pattern.to_vec();
+    }
+
+    // What's been delivered, as "event name version", checking each was
+    // signed and none was delivered twice.
+    fn taken(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.taken
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(signature, body)| {
+                assert_eq!(signature, &webhook::sign(b"s3cret", body));
+                let event: Event = serde_json::from_slice(body).unwrap();
+                assert!(seen.insert((event.event, event.id, event.version)));
+                format!(
+                    "{} {} {}",
+                    event.event.as_str(),
+                    event.path,
+                    event.version
+                )
+            })
+            .collect()
+    }
+}
+
+// What the command said, on stdout and stderr, once it's succeeded.
+fn said(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(output.status.success(), "{}", stderr);
+    String::from_utf8_lossy(&output.stdout).to_string() + &stderr
+}
+
+fn spooled(home: &Path) -> Vec<String> {
+    let dir = home
+        .join("config/remarkable-cloud")
+        .join(webhook::SPOOL_DIR);
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn delivers_each_change_once() {
+    let cloud = FakeCloud::start().await;
+    let books = cloud.add_folder("Books", None);
+    let dune = cloud.add_document("Dune", Some(books), vec![]);
+    let receiver = Receiver::default();
+    let url = receiver.start();
+    let home = tempfile::tempdir().unwrap();
+    let config = home.path().join("config/remarkable-cloud");
+    fs::create_dir_all(&config).unwrap();
+    let watch = ["watch", "--once", "--webhook", &url];
+
+    // Without a secret there's nothing to sign with.
+    let output = run(&cloud, home.path(), &watch, b"").await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("webhook_secret"));
+    fs::write(
+        config.join("settings.json"),
+        r#"{"webhook_secret":"s3cret"}"#,
+    )
+    .unwrap();
+
+    // The first look only takes note of what's there.
+    let output = run(&cloud, home.path(), &watch, b"").await;
+    assert!(said(&output).contains("Watching 1 document;"));
+    assert!(receiver.taken().is_empty());
+
+    // Turned away once, then taken.
+    cloud.modify(&dune, |d| d.version = 2);
+    let emma = cloud.add_document("Emma", Some(books), vec![]);
+    receiver.fail(&[true]);
+    let args = ["watch", "--once", "--webhook", &url, "--retries", "2"];
+    said(&run(&cloud, home.path(), &args, b"").await);
+    assert_eq!(
+        receiver.taken(),
+        ["updated Books/Dune 2", "added Books/Emma 1"]
+    );
+
+    // Nothing new, so nothing sent.
+    said(&run(&cloud, home.path(), &args, b"").await);
+    assert_eq!(receiver.taken().len(), 2);
+
+    // With the receiver down, the change is spooled.
+    cloud.modify(&emma, |d| {
+        d.trashed = true;
+        d.version = 2;
+    });
+    receiver.fail(&[true; 4]);
+    let args = ["watch", "--once", "--webhook", &url, "--retries", "0"];
+    let output = run(&cloud, home.path(), &args, b"").await;
+    assert!(said(&output).contains("Couldn't send removed Books/Emma"));
+    let spool = spooled(home.path());
+    assert_eq!(spool.len(), 1);
+    let spool_file = config.join(webhook::SPOOL_DIR).join(&spool[0]);
+    let saved = fs::read(&spool_file).unwrap();
+
+    // Sent while the receiver is still down, so it stays spooled.
+    let replay = ["watch", "--replay-spool", "--retries", "0"];
+    let output = run(&cloud, home.path(), &replay, b"").await;
+    assert!(!output.status.success());
+    assert_eq!(spooled(home.path()), spool);
+    receiver.fail(&[]);
+    let output = run(&cloud, home.path(), &replay, b"").await;
+    assert!(
+        said(&output).contains("1 delivered, 0 sent before, 0 still failing")
+    );
+    assert!(spooled(home.path()).is_empty());
+
+    // Spooled again, it's known to have been delivered.
+    fs::write(&spool_file, saved).unwrap();
+    let output = run(&cloud, home.path(), &replay, b"").await;
+    assert!(
+        said(&output).contains("0 delivered, 1 sent before, 0 still failing")
+    );
+    assert!(spooled(home.path()).is_empty());
+    said(&run(&cloud, home.path(), &args, b"").await);
+    assert_eq!(
+        receiver.taken(),
+        [
+            "updated Books/Dune 2",
+            "added Books/Emma 1",
+            "removed Books/Emma 1"
+        ]
+    );
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn prints_changes() {
+    let cloud = FakeCloud::start().await;
+    let home = tempfile::tempdir().unwrap();
+    let watch = ["watch", "--once"];
+    let output = run(&cloud, home.path(), &watch, b"").await;
+    assert!(said(&output).contains("Watching 0 documents"));
+    assert!(output.stdout.is_empty());
+
+    let dune = cloud.add_document("Dune", None, vec![]);
+    let output = run(&cloud, home.path(), &watch, b"").await;
+    assert!(output.status.success());
+    let lines: Vec<Event> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].id, dune);
+    assert_eq!(lines[0].describe(), "added Dune (version 1)");
+}
@@ -0,0 +1,122 @@
+//! The `.content` file describing what a document is made of.
+//!
+//! As with `.metadata`, fields this crate doesn't know about are kept.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    /// `pdf` or `epub` for imported documents, and `notebook` or empty for
+    /// notebooks.
+    #[serde(default)]
+    pub file_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<u64>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A tag on one page of a document, which is how pages are bookmarked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageTag {
+    pub name: String,
+    /// The id of the page, as `pages` lists it.
+    pub page_id: String,
+    /// When the page was tagged, if it says.
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl Content {
+    pub fn parse(data: &[u8]) -> Result<Content> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// The names of the tags put on the document on the tablet, kept in
+    /// `tags` as objects with a `name` and the time it was tagged.
+    pub fn tags(&self) -> Vec<&str> {
+        let tags = self.other.get("tags").and_then(|t| t.as_array());
+        tags.into_iter()
+            .flatten()
+            .filter_map(|t| t.get("name")?.as_str())
+            .collect()
+    }
+
+    /// The tags put on pages on the tablet, kept in `pageTags` by firmware
+    /// 3 and later. Entries without a name or a page are left out.
+    pub fn page_tags(&self) -> Vec<PageTag> {
+        let tags = self.other.get("pageTags").and_then(|t| t.as_array());
+        tags.into_iter()
+            .flatten()
+            .filter_map(|t| {
+                Some(PageTag {
+                    name: t.get("name")?.as_str()?.to_string(),
+                    page_id: t.get("pageId")?.as_str()?.to_string(),
+                    timestamp: t
+                        .get("timestamp")
+                        .and_then(|ms| ms.as_i64())
+                        .and_then(|ms| Utc.timestamp_millis_opt(ms).single()),
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let content = Content::parse(
+            br#"{"fileType": "pdf", "pageCount": 412, "orientation": "portrait"}"#,
+        )
+        .unwrap();
+        assert_eq!(content.file_type, "pdf");
+        assert_eq!(content.page_count, Some(412));
+        assert_eq!(content.other["orientation"], "portrait");
+        assert_eq!(Content::parse(b"{}").unwrap(), Content::default());
+        assert!(content.tags().is_empty());
+    }
+
+    #[test]
+    fn tags() {
+        let content = Content::parse(
+            br#"{"tags": [{"name": "projectX", "timestamp": 1690000000000},
+                         {"timestamp": 1}, {"name": "to read"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(content.tags(), vec!["projectX", "to read"]);
+    }
+
+    #[test]
+    fn page_tags() {
+        let content = Content::parse(
+            br#"{"pageTags": [
+                    {"name": "Todo", "pageId": "a", "timestamp": 1712345678901},
+                    {"name": "Quote", "pageId": "b"},
+                    {"name": "No page", "timestamp": 1},
+                    {"pageId": "c", "timestamp": 1}]}"#,
+        )
+        .unwrap();
+        let tags = content.page_tags();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(
+            (tags[0].name.as_str(), tags[0].page_id.as_str()),
+            ("Todo", "a")
+        );
+        assert_eq!(
+            tags[0].timestamp.unwrap().to_rfc3339(),
+            "2024-04-05T19:34:38.901+00:00"
+        );
+        assert_eq!(tags[1].timestamp, None);
+        assert!(Content::default().page_tags().is_empty());
+    }
+}
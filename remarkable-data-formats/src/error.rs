@@ -0,0 +1,29 @@
+use std::result;
+
+use derive_more::{Display, Error};
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug, Display, Error, PartialEq)]
+pub enum Error {
+    /// The data isn't in a version of the format we understand.
+    #[display(fmt = "Unsupported format: {:?}", header)]
+    UnsupportedFormat { header: String },
+    /// The data ended before a complete file had been read.
+    #[display(fmt = "Unexpected end of data at byte {}", offset)]
+    Truncated { offset: usize },
+    /// Data which doesn't have the layout its format calls for.
+    #[display(fmt = "Malformed data at byte {}: {}", offset, message)]
+    Malformed { offset: usize, message: String },
+    /// A JSON file which couldn't be parsed.
+    #[display(fmt = "Invalid JSON: {}", message)]
+    InvalidJson { message: String },
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::InvalidJson {
+            message: e.to_string(),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! The text highlighted on a page of a PDF or EPUB, kept by newer firmware
+//! in a file per page in the document's `.highlights` folder:
+//!
+//! ```json
+//! {"highlights": [[{"text": "...", "start": 1234, "length": 12, "color": 3}]]}
+//! ```
+//!
+//! Each inner list is the highlights made with one stroke of the
+//! highlighter; they're flattened here, in the order of the text.
+
+use serde::Deserialize;
+
+use crate::error::Result;
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Highlight {
+    pub text: String,
+    /// Where the text starts, in characters from the start of the page's
+    /// text.
+    #[serde(default)]
+    pub start: u64,
+    #[serde(default)]
+    pub length: u64,
+    /// The highlighter's color, as the tablet numbers them.
+    #[serde(default)]
+    pub color: u32,
+}
+
+#[derive(Deserialize)]
+struct File {
+    #[serde(default)]
+    highlights: Vec<Vec<Highlight>>,
+}
+
+/// The highlights in one page's file, in the order of the text.
+pub fn parse(data: &[u8]) -> Result<Vec<Highlight>> {
+    let file: File = serde_json::from_slice(data)?;
+    let mut highlights: Vec<Highlight> =
+        file.highlights.into_iter().flatten().collect();
+    highlights.sort_by_key(|h| h.start);
+    Ok(highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattened_in_order() {
+        let data = br#"{"highlights": [
+            [{"text": "second", "start": 40, "length": 6, "color": 3}],
+            [{"text": "first", "start": 2, "length": 5, "color": 4},
+             {"text": "bare"}]
+        ]}"#;
+        let texts: Vec<String> =
+            parse(data).unwrap().into_iter().map(|h| h.text).collect();
+        assert_eq!(texts, ["bare", "first", "second"]);
+        assert_eq!(parse(b"{}").unwrap(), vec![]);
+        assert!(parse(b"[").is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! What was drawn on pages, by itself, for laying over a rendering of the
+//! pages made some other way: as SVG, one page at a time, or as a PDF of
+//! them all, either way with nothing behind the strokes.
+//!
+//! Strokes are recorded in the tablet's screen coordinates, 1404 by 1872
+//! from the top left, or from the top middle in version 6. The tablet shows
+//! a PDF page as large as fits the screen, against its top and centred
+//! across it, and `PageTransform` undoes that to put strokes back on the
+//! page in its own size, in points.
+
+use std::fmt::Write;
+
+use crate::lines::{Page, Stroke};
+use crate::pdf::PageBox;
+
+pub const TABLET_WIDTH: f64 = 1404.0;
+pub const TABLET_HEIGHT: f64 = 1872.0;
+
+// Tools which rub out rather than draw, whose strokes aren't shown.
+pub(crate) const ERASERS: &[u32] = &[6, 8];
+// Tools drawing see-through strokes.
+const HIGHLIGHTERS: &[u32] = &[5, 18];
+const HIGHLIGHTER_OPACITY: f64 = 0.4;
+
+/// Maps points on the tablet's screen to points on a page, as the tablet
+/// shows it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageTransform {
+    page: PageBox,
+    /// Screen pixels to a point.
+    scale: f64,
+    /// Pixels between the left of the screen and the left of the page.
+    left: f64,
+}
+
+impl PageTransform {
+    /// The transform for a page with the box `page`, as found by
+    /// `pdf::page_boxes`.
+    pub fn new(page: PageBox) -> Self {
+        let scale =
+            (TABLET_WIDTH / page.width()).min(TABLET_HEIGHT / page.height());
+        PageTransform {
+            page,
+            scale,
+            left: (TABLET_WIDTH - page.width() * scale) / 2.0,
+        }
+    }
+
+    /// The transform for pages not of a PDF, as in notebooks, which are the
+    /// screen itself, a point to a pixel.
+    pub fn tablet() -> Self {
+        PageTransform::new(PageBox {
+            x0: 0.0,
+            y0: 0.0,
+            x1: TABLET_WIDTH,
+            y1: TABLET_HEIGHT,
+        })
+    }
+
+    pub fn page(&self) -> PageBox {
+        self.page
+    }
+
+    /// How many screen pixels make a point on the page.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// Where the screen point `(x, y)` is on the page, in points from its
+    /// top left corner, with y going down as in SVG.
+    pub fn to_page(&self, x: f64, y: f64) -> (f64, f64) {
+        ((x - self.left) / self.scale, y / self.scale)
+    }
+
+    /// Where the screen point `(x, y)` is in the page's PDF user space,
+    /// with y going up.
+    pub fn to_pdf(&self, x: f64, y: f64) -> (f64, f64) {
+        let (x, y) = self.to_page(x, y);
+        (self.page.x0 + x, self.page.y1 - y)
+    }
+}
+
+// The screen points of `stroke`, from the top left whatever the version.
+fn screen_points<'a>(
+    page: &Page,
+    stroke: &'a Stroke,
+) -> impl Iterator<Item = (f64, f64)> + 'a {
+    let dx = if page.version >= 6 {
+        TABLET_WIDTH / 2.0
+    } else {
+        0.0
+    };
+    stroke
+        .segments
+        .iter()
+        .map(move |s| (s.x as f64 + dx, s.y as f64))
+}
+
+// The stroke's width in screen pixels: the average of its segments', which
+// carry the pressure, or its own if they have none.
+fn screen_width(stroke: &Stroke) -> f64 {
+    let widths: Vec<f64> = stroke
+        .segments
+        .iter()
+        .map(|s| s.width as f64)
+        .filter(|w| *w > 0.0)
+        .collect();
+    if widths.is_empty() {
+        stroke.width as f64
+    } else {
+        widths.iter().sum::<f64>() / widths.len() as f64
+    }
+}
+
+// The stroke's colour, as red, green and blue from 0 to 255.
+fn color(stroke: &Stroke) -> (u8, u8, u8) {
+    match stroke.color {
+        1 | 8 => (128, 128, 128),
+        2 => (255, 255, 255),
+        3 | 9 => (255, 235, 59),
+        4 => (76, 175, 80),
+        5 => (233, 30, 99),
+        6 => (33, 150, 243),
+        7 => (244, 67, 54),
+        _ if HIGHLIGHTERS.contains(&stroke.pen) => (255, 235, 59),
+        _ => (0, 0, 0),
+    }
+}
+
+fn drawn(stroke: &Stroke) -> bool {
+    !ERASERS.contains(&stroke.pen) && !stroke.segments.is_empty()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// What's drawn on `page`, as an SVG the size of the page `transform` is
+/// for, in points, with a transparent background. Each layer is a group
+/// with its name from `layer_names` as its id, or "Layer n" past the end
+/// of them.
+pub fn svg(
+    page: &Page,
+    layer_names: &[String],
+    transform: &PageTransform,
+) -> String {
+    let (width, height) = (transform.page.width(), transform.page.height());
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" \
+         height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = width,
+        h = height
+    );
+    for (i, layer) in page.layers.iter().enumerate() {
+        let name = match layer_names.get(i) {
+            Some(name) => escape(name),
+            None => format!("Layer {}", i + 1),
+        };
+        writeln!(out, "  <g id=\"{}\">", name).unwrap();
+        for stroke in layer.strokes.iter().filter(|s| drawn(s)) {
+            let points: Vec<String> = screen_points(page, stroke)
+                .map(|(x, y)| {
+                    let (x, y) = transform.to_page(x, y);
+                    format!("{:.2},{:.2}", x, y)
+                })
+                .collect();
+            let (r, g, b) = color(stroke);
+            let opacity = if HIGHLIGHTERS.contains(&stroke.pen) {
+                format!(" stroke-opacity=\"{}\"", HIGHLIGHTER_OPACITY)
+            } else {
+                String::new()
+            };
+            writeln!(
+                out,
+                "    <polyline points=\"{}\" fill=\"none\" \
+                 stroke=\"#{:02x}{:02x}{:02x}\"{} stroke-width=\"{:.2}\" \
+                 stroke-linecap=\"round\" stroke-linejoin=\"round\"/>",
+                points.join(" "),
+                r,
+                g,
+                b,
+                opacity,
+                screen_width(stroke) / transform.scale
+            )
+            .unwrap();
+        }
+        out.push_str("  </g>\n");
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+// The drawing operators for what's on `page`, in PDF user space.
+fn pdf_content(page: &Page, transform: &PageTransform) -> String {
+    let mut out = String::from("1 J 1 j\n");
+    for layer in &page.layers {
+        for stroke in layer.strokes.iter().filter(|s| drawn(s)) {
+            let (r, g, b) = color(stroke);
+            let highlight = HIGHLIGHTERS.contains(&stroke.pen);
+            out.push_str(if highlight {
+                "q /Highlight gs\n"
+            } else {
+                "q\n"
+            });
+            writeln!(
+                out,
+                "{:.3} {:.3} {:.3} RG {:.2} w",
+                r as f64 / 255.0,
+                g as f64 / 255.0,
+                b as f64 / 255.0,
+                screen_width(stroke) / transform.scale
+            )
+            .unwrap();
+            for (i, (x, y)) in screen_points(page, stroke).enumerate() {
+                let (x, y) = transform.to_pdf(x, y);
+                let op = if i == 0 { "m" } else { "l" };
+                writeln!(out, "{:.2} {:.2} {}", x, y, op).unwrap();
+            }
+            out.push_str("S Q\n");
+        }
+    }
+    out
+}
+
+/// What's drawn on each of `pages` as a page of a PDF, the size of the page
+/// its transform is for, with nothing behind it. Layers are drawn in
+/// order, but without their names.
+pub fn pdf(pages: &[(&Page, PageTransform)]) -> Vec<u8> {
+    let mut out: Vec<u8> = b"%PDF-1.4\n".to_vec();
+    let mut offsets = vec![];
+    let mut object = |out: &mut Vec<u8>, body: &[u8]| {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+        out.extend_from_slice(body);
+        out.extend_from_slice(b"\nendobj\n");
+    };
+    // The catalog and page tree come first, then each page and its
+    // drawing, so page n is object 2n + 1.
+    object(&mut out, b"<< /Type /Catalog /Pages 2 0 R >>");
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", 2 * i + 3))
+        .collect();
+    object(
+        &mut out,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .as_bytes(),
+    );
+    for (i, (page, transform)) in pages.iter().enumerate() {
+        let b = transform.page;
+        object(
+            &mut out,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [{} {} {} {}] \
+                 /Resources << /ExtGState << /Highlight << /CA {} >> >> >> \
+                 /Contents {} 0 R >>",
+                b.x0,
+                b.y0,
+                b.x1,
+                b.y1,
+                HIGHLIGHTER_OPACITY,
+                2 * i + 4
+            )
+            .as_bytes(),
+        );
+        let content = pdf_content(page, transform);
+        object(
+            &mut out,
+            format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len() + 1,
+                content
+            )
+            .as_bytes(),
+        );
+    }
+    let xref = out.len();
+    let mut trailer =
+        format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1);
+    for offset in &offsets {
+        writeln!(trailer, "{:010} 00000 n ", offset).unwrap();
+    }
+    write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        offsets.len() + 1,
+        xref
+    )
+    .unwrap();
+    out.extend_from_slice(trailer.as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{Layer, Segment};
+    use crate::pdf::page_boxes;
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 0.01 && (a.1 - b.1).abs() < 0.01
+    }
+
+    #[test]
+    fn transforms() {
+        // The tablet's own pages are its screen.
+        let tablet = PageTransform::tablet();
+        assert_eq!(tablet.to_page(100.0, 200.0), (100.0, 200.0));
+        assert_eq!(tablet.to_pdf(100.0, 200.0), (100.0, 1672.0));
+
+        // Portrait A4 is narrower than the screen, so fills its height and
+        // is centred across it.
+        let a4 =
+            PageTransform::new(PageBox::from_corners([0.0, 0.0, 595.0, 842.0]));
+        let scale = 1872.0 / 842.0;
+        assert!((a4.scale() - scale).abs() < 1e-9);
+        let left = (1404.0 - 595.0 * scale) / 2.0;
+        assert!(close(a4.to_page(left, 0.0), (0.0, 0.0)));
+        assert!(close(a4.to_page(1404.0 - left, 1872.0), (595.0, 842.0)));
+        assert!(close(a4.to_pdf(left, 0.0), (0.0, 842.0)));
+        assert!(close(a4.to_pdf(702.0, 936.0), (297.5, 421.0)));
+
+        // Landscape fills the width, against the top.
+        let wide =
+            PageTransform::new(PageBox::from_corners([0.0, 0.0, 842.0, 595.0]));
+        assert!((wide.scale() - 1404.0 / 842.0).abs() < 1e-9);
+        assert!(close(wide.to_page(0.0, 0.0), (0.0, 0.0)));
+        assert!(close(
+            wide.to_page(1404.0, 595.0 * wide.scale()),
+            (842.0, 595.0)
+        ));
+        assert!(close(wide.to_pdf(1404.0, 0.0), (842.0, 595.0)));
+
+        // A cropped page shows only its crop box, which is offset in the
+        // page's user space.
+        let cropped = PageTransform::new(PageBox::from_corners([
+            50.0, 50.0, 545.0, 792.0,
+        ]));
+        let scale = 1872.0 / 742.0;
+        let left = (1404.0 - 495.0 * scale) / 2.0;
+        assert!(close(cropped.to_page(left, 0.0), (0.0, 0.0)));
+        assert!(close(cropped.to_pdf(left, 0.0), (50.0, 792.0)));
+        assert!(close(cropped.to_pdf(1404.0 - left, 1872.0), (545.0, 50.0)));
+    }
+
+    fn stroke(pen: u32, points: &[(f32, f32)]) -> Stroke {
+        Stroke {
+            pen,
+            color: 0,
+            width: 2.0,
+            segments: points
+                .iter()
+                .map(|(x, y)| Segment {
+                    x: *x,
+                    y: *y,
+                    speed: 0.0,
+                    direction: 0.0,
+                    width: 4.0,
+                    pressure: 1.0,
+                })
+                .collect(),
+        }
+    }
+
+    fn page(version: u8) -> Page {
+        Page {
+            version,
+            layers: vec![
+                Layer {
+                    strokes: vec![stroke(2, &[(0.0, 0.0), (702.0, 936.0)])],
+                },
+                Layer {
+                    strokes: vec![
+                        stroke(18, &[(0.0, 100.0), (10.0, 100.0)]),
+                        stroke(6, &[(5.0, 5.0)]),
+                    ],
+                },
+            ],
+            text: vec![],
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn svgs() {
+        let names = ["Sketch <1>".to_string()];
+        let svg = svg(&page(5), &names, &PageTransform::tablet());
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"1404\" \
+             height=\"1872\" viewBox=\"0 0 1404 1872\">\n"
+        ));
+        assert!(svg.contains("<g id=\"Sketch &lt;1&gt;\">"));
+        assert!(svg.contains("<g id=\"Layer 2\">"));
+        assert!(svg.contains(
+            "<polyline points=\"0.00,0.00 702.00,936.00\" fill=\"none\" \
+             stroke=\"#000000\" stroke-width=\"4.00\""
+        ));
+        assert!(svg.contains("stroke-opacity=\"0.4\""));
+        // Erasers leave nothing, and there's no background.
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(!svg.contains("<rect"));
+
+        // Version 6 measures from the middle.
+        let svg = super::svg(&page(6), &[], &PageTransform::tablet());
+        assert!(svg.contains("points=\"702.00,0.00 1404.00,936.00\""));
+    }
+
+    #[test]
+    fn pdfs() {
+        let a4 = PageBox::from_corners([0.0, 0.0, 595.0, 842.0]);
+        let landscape = PageBox::from_corners([0.0, 0.0, 842.0, 595.0]);
+        let (p, q) = (page(5), page(5));
+        let data = pdf(&[
+            (&p, PageTransform::new(a4)),
+            (&q, PageTransform::new(landscape)),
+        ]);
+        assert_eq!(page_boxes(&data), [a4, landscape]);
+        let text = String::from_utf8_lossy(&data);
+        assert!(text.contains("297.50 421.00 l"));
+        assert_eq!(text.matches("/Highlight gs").count(), 2);
+        assert!(text.ends_with("%%EOF\n"));
+        // The cross-reference table points at each object.
+        let xref = text.find("\nxref\n").unwrap() + 1;
+        let offsets: Vec<usize> = text[xref..]
+            .lines()
+            .skip(3)
+            .take(6)
+            .map(|l| l[..10].parse().unwrap())
+            .collect();
+        for (i, offset) in offsets.iter().enumerate() {
+            assert!(text[*offset..].starts_with(&format!("{} 0 obj", i + 1)));
+        }
+    }
+}
@@ -0,0 +1,12 @@
+mod error;
+pub use crate::error::{Error, Result};
+
+pub mod content;
+pub mod highlights;
+pub mod ink;
+pub mod lines;
+pub mod metadata;
+pub mod pagedata;
+pub mod pdf;
+pub mod stats;
+pub mod timefmt;
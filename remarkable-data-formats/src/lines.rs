@@ -0,0 +1,293 @@
+//! The `.rm` files holding what was drawn on a page, in versions 3, 5 and 6
+//! of the format.
+//!
+//! A file is a fixed 43-byte header naming its version. In versions 3 and 5
+//! the page's layers follow, each a list of strokes, each a list of
+//! segments, and all numbers are little-endian 32-bit integers or floats.
+//! Version 6, written by firmware 3 and later, is quite different; see
+//! `scene`.
+
+use crate::error::{Error, Result};
+
+mod scene;
+
+const HEADER_LEN: usize = 43;
+const HEADER_PREFIX: &str = "reMarkable .lines file, version=";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    pub version: u8,
+    pub layers: Vec<Layer>,
+    /// The text typed onto the page, from version 6 on.
+    pub text: Vec<TextBlock>,
+    /// What was left out of the page because it couldn't be read, such as
+    /// text in a newer version than this parser knows.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Layer {
+    pub strokes: Vec<Stroke>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub pen: u32,
+    pub color: u32,
+    pub width: f32,
+    pub segments: Vec<Segment>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub x: f32,
+    pub y: f32,
+    pub speed: f32,
+    pub direction: f32,
+    pub width: f32,
+    pub pressure: f32,
+}
+
+/// A box of typed text, at `x` and `y` on the page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextBlock {
+    pub x: f64,
+    pub y: f64,
+    pub width: f32,
+    /// The lines of text in reading order.
+    pub paragraphs: Vec<Paragraph>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paragraph {
+    pub text: String,
+    pub style: ParagraphStyle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParagraphStyle {
+    Basic,
+    Plain,
+    Heading,
+    Bold,
+    Bullet,
+    Bullet2,
+    Checkbox,
+    Checked,
+    Unknown(u8),
+}
+
+impl From<u8> for ParagraphStyle {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => ParagraphStyle::Basic,
+            1 => ParagraphStyle::Plain,
+            2 => ParagraphStyle::Heading,
+            3 => ParagraphStyle::Bold,
+            4 => ParagraphStyle::Bullet,
+            5 => ParagraphStyle::Bullet2,
+            6 => ParagraphStyle::Checkbox,
+            7 => ParagraphStyle::Checked,
+            code => ParagraphStyle::Unknown(code),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        match self.data.get(self.offset..self.offset + n) {
+            Some(bytes) => {
+                self.offset += n;
+                Ok(bytes)
+            }
+            None => Err(Error::Truncated {
+                offset: self.data.len(),
+            }),
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    // Counts read from the file are only trusted as far as there is data
+    // left to back them, so a corrupt count can't cause a huge allocation.
+    fn capacity(&self, count: u32, item_size: usize) -> usize {
+        (count as usize).min((self.data.len() - self.offset) / item_size)
+    }
+}
+
+impl Page {
+    pub fn parse(data: &[u8]) -> Result<Page> {
+        let header = data.get(..HEADER_LEN).unwrap_or(data);
+        let header = String::from_utf8_lossy(header);
+        let version = match header.strip_prefix(HEADER_PREFIX).map(str::trim) {
+            Some("3") => 3,
+            Some("5") => 5,
+            Some("6") => 6,
+            _ => {
+                return Err(Error::UnsupportedFormat {
+                    header: header.trim_end().to_string(),
+                })
+            }
+        };
+        let mut r = Reader {
+            data,
+            offset: HEADER_LEN,
+        };
+        if version == 6 {
+            return scene::parse(&mut r);
+        }
+        let layer_count = r.u32()?;
+        let mut layers = Vec::with_capacity(r.capacity(layer_count, 4));
+        for _ in 0..layer_count {
+            let stroke_count = r.u32()?;
+            let mut strokes = Vec::with_capacity(r.capacity(stroke_count, 20));
+            for _ in 0..stroke_count {
+                let pen = r.u32()?;
+                let color = r.u32()?;
+                let _unknown = r.u32()?;
+                let width = r.f32()?;
+                if version >= 5 {
+                    let _unknown = r.u32()?;
+                }
+                let segment_count = r.u32()?;
+                let mut segments =
+                    Vec::with_capacity(r.capacity(segment_count, 24));
+                for _ in 0..segment_count {
+                    segments.push(Segment {
+                        x: r.f32()?,
+                        y: r.f32()?,
+                        speed: r.f32()?,
+                        direction: r.f32()?,
+                        width: r.f32()?,
+                        pressure: r.f32()?,
+                    });
+                }
+                strokes.push(Stroke {
+                    pen,
+                    color,
+                    width,
+                    segments,
+                });
+            }
+            layers.push(Layer { strokes });
+        }
+        Ok(Page {
+            version,
+            layers,
+            text: vec![],
+            warnings: vec![],
+        })
+    }
+
+    pub fn stroke_count(&self) -> usize {
+        self.layers.iter().map(|l| l.strokes.len()).sum()
+    }
+
+    /// Whether nothing at all is drawn or typed on the page.
+    pub fn is_empty(&self) -> bool {
+        self.stroke_count() == 0
+            && self.text.iter().all(|t| t.paragraphs.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: u8) -> Vec<u8> {
+        format!("{}{:<11}", HEADER_PREFIX, version).into_bytes()
+    }
+
+    fn push_u32(buf: &mut Vec<u8>, v: u32) {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn push_f32(buf: &mut Vec<u8>, v: f32) {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    // One layer holding one two-segment stroke.
+    fn one_stroke(version: u8) -> Vec<u8> {
+        let mut buf = header(version);
+        push_u32(&mut buf, 1);
+        push_u32(&mut buf, 1);
+        push_u32(&mut buf, 2); // pen
+        push_u32(&mut buf, 0); // color
+        push_u32(&mut buf, 0);
+        push_f32(&mut buf, 2.0);
+        if version >= 5 {
+            push_u32(&mut buf, 0);
+        }
+        push_u32(&mut buf, 2);
+        for i in 0..2 {
+            for v in &[i as f32, 10.0, 0.5, 0.0, 2.0, 0.8] {
+                push_f32(&mut buf, *v);
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn parse_versions() {
+        for version in &[3, 5] {
+            let page = Page::parse(&one_stroke(*version)).unwrap();
+            assert_eq!(page.version, *version);
+            assert_eq!(page.stroke_count(), 1);
+            let stroke = &page.layers[0].strokes[0];
+            assert_eq!(stroke.pen, 2);
+            assert_eq!(stroke.width, 2.0);
+            assert_eq!(stroke.segments.len(), 2);
+            assert_eq!(stroke.segments[1].x, 1.0);
+            assert_eq!(stroke.segments[1].pressure, 0.8);
+        }
+    }
+
+    #[test]
+    fn empty_page() {
+        let mut buf = header(5);
+        push_u32(&mut buf, 2);
+        push_u32(&mut buf, 0);
+        push_u32(&mut buf, 0);
+        let page = Page::parse(&buf).unwrap();
+        assert_eq!(page.layers.len(), 2);
+        assert!(page.is_empty());
+    }
+
+    #[test]
+    fn bad_data() {
+        let data = one_stroke(5);
+        assert_eq!(
+            Page::parse(&data[..data.len() - 3]),
+            Err(Error::Truncated {
+                offset: data.len() - 3
+            })
+        );
+        assert!(matches!(
+            Page::parse(&header(7)),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+        assert!(matches!(
+            Page::parse(b"%PDF-1.4"),
+            Err(Error::UnsupportedFormat { .. })
+        ));
+
+        // A layer claiming far more strokes than there is data for.
+        let mut buf = header(5);
+        push_u32(&mut buf, 1);
+        push_u32(&mut buf, u32::MAX);
+        assert!(matches!(Page::parse(&buf), Err(Error::Truncated { .. })));
+    }
+}
@@ -0,0 +1,570 @@
+//! Version 6 of the `.rm` format, written by firmware 3 and later.
+//!
+//! After the header, the file is a series of blocks, each with its length,
+//! the oldest version of the format which can read it, its own version and
+//! its type. Inside a block most values are tagged with an index and their
+//! kind, and nested values are subblocks with a length of their own, so
+//! that a reader can skip what it doesn't know. Only strokes and typed text
+//! are read here; every other kind of block is skipped.
+//!
+//! Typed text is kept as a list of characters, each saying which character
+//! it was typed after, so that the tablet and the cloud can merge edits.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use super::{
+    Layer, Page, Paragraph, ParagraphStyle, Reader, Segment, Stroke, TextBlock,
+};
+use crate::error::{Error, Result};
+
+const LINE_ITEM: u8 = 0x05;
+const ROOT_TEXT: u8 = 0x07;
+
+// The newest version of each block read here.
+const LINE_VERSION: u8 = 2;
+const TEXT_VERSION: u8 = 1;
+
+// The kinds of tagged value.
+const ID: u8 = 0xF;
+const LENGTH4: u8 = 0xC;
+const BYTE8: u8 = 0x8;
+const BYTE4: u8 = 0x4;
+
+// More deleted characters than any page could have held.
+const MAX_DELETED: u32 = 1 << 20;
+
+/// Names a stroke, a character and so on: the author, and a counter.
+type CrdtId = (u8, u64);
+
+/// What the first character on the page is typed after.
+const START: CrdtId = (0, 0);
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(buf))
+    }
+
+    fn varuint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= u64::from(byte & 0x7f) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.offset
+    }
+
+    // The next `len` bytes, read on their own, so that reading past them is
+    // an error and they are skipped here whatever is read of them.
+    fn sub(&mut self, len: usize) -> Result<Reader<'a>> {
+        let start = self.offset;
+        self.take(len)?;
+        Ok(Reader {
+            data: &self.data[..start + len],
+            offset: start,
+        })
+    }
+
+    fn has_tag(&self, index: u64, kind: u8) -> bool {
+        let mut peek = Reader {
+            data: self.data,
+            offset: self.offset,
+        };
+        peek.varuint().ok() == Some(index << 4 | u64::from(kind))
+    }
+
+    fn tag(&mut self, index: u64, kind: u8) -> Result<()> {
+        let offset = self.offset;
+        let tag = self.varuint()?;
+        if tag != index << 4 | u64::from(kind) {
+            return Err(Error::Malformed {
+                offset,
+                message: format!(
+                    "expected value {} of kind {:#x}, found tag {:#x}",
+                    index, kind, tag
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn crdt_id(&mut self) -> Result<CrdtId> {
+        Ok((self.u8()?, self.varuint()?))
+    }
+
+    fn id(&mut self, index: u64) -> Result<CrdtId> {
+        self.tag(index, ID)?;
+        self.crdt_id()
+    }
+
+    fn int(&mut self, index: u64) -> Result<u32> {
+        self.tag(index, BYTE4)?;
+        self.u32()
+    }
+
+    fn float(&mut self, index: u64) -> Result<f32> {
+        self.tag(index, BYTE4)?;
+        self.f32()
+    }
+
+    fn double(&mut self, index: u64) -> Result<f64> {
+        self.tag(index, BYTE8)?;
+        self.f64()
+    }
+
+    fn subblock(&mut self, index: u64) -> Result<Reader<'a>> {
+        self.tag(index, LENGTH4)?;
+        let len = self.u32()? as usize;
+        self.sub(len)
+    }
+}
+
+/// Reads the blocks following the header. Every stroke is put in a single
+/// layer, since layers are only one kind of group in this version.
+pub(super) fn parse(r: &mut Reader) -> Result<Page> {
+    let mut strokes = vec![];
+    let mut text = vec![];
+    let mut warnings = vec![];
+    while r.remaining() > 0 {
+        let len = r.u32()? as usize;
+        let _unknown = r.u8()?;
+        let min_version = r.u8()?;
+        let version = r.u8()?;
+        let kind = r.u8()?;
+        let mut block = r.sub(len)?;
+        match kind {
+            LINE_ITEM if min_version > LINE_VERSION => warnings.push(format!(
+                "Left out a stroke in version {} of the format",
+                version
+            )),
+            LINE_ITEM => strokes.extend(line_item(&mut block, version)?),
+            ROOT_TEXT if min_version > TEXT_VERSION => warnings.push(format!(
+                "Left out text in version {} of the format",
+                version
+            )),
+            ROOT_TEXT => match root_text(&mut block) {
+                Ok(block) => text.push(block),
+                Err(e) => {
+                    warnings.push(format!("Left out unreadable text: {}", e))
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(Page {
+        version: 6,
+        layers: vec![Layer { strokes }],
+        text,
+        warnings,
+    })
+}
+
+// A stroke, or nothing if it has been erased.
+fn line_item(r: &mut Reader, version: u8) -> Result<Option<Stroke>> {
+    let _parent = r.id(1)?;
+    let _id = r.id(2)?;
+    let _left = r.id(3)?;
+    let _right = r.id(4)?;
+    let deleted = r.int(5)?;
+    if deleted > 0 || !r.has_tag(6, LENGTH4) {
+        return Ok(None);
+    }
+    let mut value = r.subblock(6)?;
+    let _item_type = value.u8()?;
+    let pen = value.int(1)?;
+    let color = value.int(2)?;
+    let width = value.double(3)? as f32;
+    let _starting_length = value.float(4)?;
+    let mut points = value.subblock(5)?;
+    // Version 1 has the segments of earlier versions; version 2 packs them.
+    let point_size = if version == 1 { 24 } else { 14 };
+    let mut segments = Vec::with_capacity(points.remaining() / point_size);
+    for _ in 0..points.remaining() / point_size {
+        segments.push(if version == 1 {
+            Segment {
+                x: points.f32()?,
+                y: points.f32()?,
+                speed: points.f32()?,
+                direction: points.f32()?,
+                width: points.f32()?,
+                pressure: points.f32()?,
+            }
+        } else {
+            let x = points.f32()?;
+            let y = points.f32()?;
+            let speed = points.u16()?;
+            let width = points.u16()?;
+            let direction = points.u8()?;
+            let pressure = points.u8()?;
+            Segment {
+                x,
+                y,
+                speed: f32::from(speed) / 4.0,
+                direction: f32::from(direction) * 2.0 * PI / 255.0,
+                width: f32::from(width) / 4.0,
+                pressure: f32::from(pressure) / 255.0,
+            }
+        });
+    }
+    Ok(Some(Stroke {
+        pen,
+        color,
+        width,
+        segments,
+    }))
+}
+
+// One character of typed text, or of what's left of one: a deleted
+// character, or a change of formatting within a line, has no `value`.
+struct Char {
+    id: CrdtId,
+    left: CrdtId,
+    value: Option<char>,
+}
+
+fn root_text(r: &mut Reader) -> Result<TextBlock> {
+    let _id = r.id(1)?;
+    let mut contents = r.subblock(2)?;
+    let mut items = contents.subblock(1)?.subblock(1)?;
+    let mut chars = vec![];
+    for _ in 0..items.varuint()? {
+        text_item(&mut items, &mut chars)?;
+    }
+    let mut formats = contents.subblock(2)?.subblock(1)?;
+    let mut styles = HashMap::new();
+    for _ in 0..formats.varuint()? {
+        let char_id = formats.crdt_id()?;
+        let _timestamp = formats.id(1)?;
+        let mut format = formats.subblock(2)?;
+        let _kind = format.u8()?;
+        styles.insert(char_id, ParagraphStyle::from(format.u8()?));
+    }
+    let mut position = r.subblock(3)?;
+    let x = position.double(1)?;
+    let y = position.double(2)?;
+    let width = r.float(4)?;
+    Ok(TextBlock {
+        x,
+        y,
+        width,
+        paragraphs: paragraphs(&in_order(chars), &styles),
+    })
+}
+
+// Reads a run of characters typed together. Each has the id after the one
+// before it, and was typed after it.
+fn text_item(r: &mut Reader, chars: &mut Vec<Char>) -> Result<()> {
+    let mut item = r.subblock(0)?;
+    let (author, counter) = item.id(2)?;
+    let left = item.id(3)?;
+    let _right = item.id(4)?;
+    let deleted = item.int(5)?;
+    let mut values = vec![];
+    if deleted > MAX_DELETED {
+        return Err(Error::Malformed {
+            offset: item.offset,
+            message: format!("{} deleted characters", deleted),
+        });
+    } else if deleted > 0 {
+        values.resize(deleted as usize, None);
+    } else if item.has_tag(6, LENGTH4) {
+        let mut string = item.subblock(6)?;
+        let len = string.varuint()? as usize;
+        let _is_ascii = string.u8()?;
+        let bytes = string.take(len)?;
+        if string.has_tag(2, BYTE4) {
+            values.push(None);
+        } else {
+            values.extend(String::from_utf8_lossy(bytes).chars().map(Some));
+        }
+    }
+    for (i, value) in values.into_iter().enumerate() {
+        let id = (author, counter + i as u64);
+        let left = if i == 0 { left } else { (author, id.1 - 1) };
+        chars.push(Char { id, left, value });
+    }
+    Ok(())
+}
+
+// Puts the characters in reading order: each one after the character it
+// was typed after, and the most recently typed first where several were
+// typed after the same one. Any typed after a character which isn't there
+// go at the end.
+fn in_order(chars: Vec<Char>) -> Vec<Char> {
+    let mut after: HashMap<CrdtId, Vec<usize>> = HashMap::new();
+    for (i, c) in chars.iter().enumerate() {
+        after.entry(c.left).or_default().push(i);
+    }
+    for next in after.values_mut() {
+        next.sort_by_key(|&i| Reverse(chars[i].id));
+    }
+    let mut unplaced: Vec<usize> = (0..chars.len()).collect();
+    unplaced.sort_by_key(|&i| chars[i].id);
+    let mut placed = vec![false; chars.len()];
+    let mut order = vec![];
+    let mut stack: Vec<usize> = vec![];
+    let mut roots = after.get(&START).cloned().unwrap_or_default();
+    roots.extend(unplaced);
+    for root in roots {
+        if placed[root] {
+            continue;
+        }
+        stack.push(root);
+        while let Some(i) = stack.pop() {
+            if placed[i] {
+                continue;
+            }
+            placed[i] = true;
+            order.push(i);
+            if let Some(next) = after.get(&chars[i].id) {
+                stack.extend(next.iter().rev());
+            }
+        }
+    }
+    let mut chars: Vec<Option<Char>> = chars.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| chars[i].take()).collect()
+}
+
+// Splits the text into lines. A line's style is keyed by the id of the
+// newline before it, or `START` for the first line.
+fn paragraphs(
+    chars: &[Char],
+    styles: &HashMap<CrdtId, ParagraphStyle>,
+) -> Vec<Paragraph> {
+    let style = |id| styles.get(&id).copied().unwrap_or(ParagraphStyle::Plain);
+    let mut paragraphs = vec![];
+    let mut current = Paragraph {
+        text: String::new(),
+        style: style(START),
+    };
+    for c in chars {
+        match c.value {
+            Some('\n') => {
+                let next = Paragraph {
+                    text: String::new(),
+                    style: style(c.id),
+                };
+                paragraphs.push(std::mem::replace(&mut current, next));
+            }
+            Some(value) => current.text.push(value),
+            None => {}
+        }
+    }
+    if !current.text.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::HEADER_PREFIX;
+    use super::*;
+
+    fn varuint(buf: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        buf.push(v as u8);
+    }
+
+    fn tag(buf: &mut Vec<u8>, index: u64, kind: u8) {
+        varuint(buf, index << 4 | u64::from(kind));
+    }
+
+    fn id(buf: &mut Vec<u8>, index: u64, (author, counter): CrdtId) {
+        tag(buf, index, ID);
+        buf.push(author);
+        varuint(buf, counter);
+    }
+
+    fn int(buf: &mut Vec<u8>, index: u64, v: u32) {
+        tag(buf, index, BYTE4);
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn double(buf: &mut Vec<u8>, index: u64, v: f64) {
+        tag(buf, index, BYTE8);
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn subblock(buf: &mut Vec<u8>, index: u64, contents: &[u8]) {
+        tag(buf, index, LENGTH4);
+        buf.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        buf.extend_from_slice(contents);
+    }
+
+    fn block(buf: &mut Vec<u8>, kind: u8, min_version: u8, contents: &[u8]) {
+        buf.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&[0, min_version, min_version, kind]);
+        buf.extend_from_slice(contents);
+    }
+
+    fn page() -> Vec<u8> {
+        format!("{}{:<11}", HEADER_PREFIX, 6).into_bytes()
+    }
+
+    // A two-segment stroke in the packed layout of version 2.
+    fn line() -> Vec<u8> {
+        let mut line = vec![];
+        id(&mut line, 1, (0, 11));
+        id(&mut line, 2, (1, 20));
+        id(&mut line, 3, (0, 0));
+        id(&mut line, 4, (0, 0));
+        int(&mut line, 5, 0);
+        let mut value = vec![3];
+        int(&mut value, 1, 2);
+        int(&mut value, 2, 0);
+        double(&mut value, 3, 2.0);
+        int(&mut value, 4, 0);
+        let mut points = vec![];
+        for x in &[1.0f32, 2.0] {
+            points.extend_from_slice(&x.to_le_bytes());
+            points.extend_from_slice(&10f32.to_le_bytes());
+            points.extend_from_slice(&8u16.to_le_bytes());
+            points.extend_from_slice(&12u16.to_le_bytes());
+            points.extend_from_slice(&[0, 255]);
+        }
+        subblock(&mut value, 5, &points);
+        subblock(&mut line, 6, &value);
+        line
+    }
+
+    fn text_item(buf: &mut Vec<u8>, item_id: CrdtId, left: CrdtId, text: &str) {
+        let mut item = vec![];
+        id(&mut item, 2, item_id);
+        id(&mut item, 3, left);
+        id(&mut item, 4, (0, 0));
+        int(&mut item, 5, 0);
+        let mut string = vec![];
+        varuint(&mut string, text.len() as u64);
+        string.push(1);
+        string.extend_from_slice(text.as_bytes());
+        subblock(&mut item, 6, &string);
+        subblock(buf, 0, &item);
+    }
+
+    // "Title" as a heading, then "Hello world", with "Hello" typed after
+    // "world" was.
+    fn text() -> Vec<u8> {
+        let mut items = vec![];
+        varuint(&mut items, 3);
+        text_item(&mut items, (1, 16), (0, 0), "Title\n");
+        text_item(&mut items, (1, 22), (1, 21), "world");
+        text_item(&mut items, (1, 30), (1, 21), "Hello ");
+        let mut formats = vec![];
+        varuint(&mut formats, 2);
+        for (char_id, style) in &[((0, 0), 2), ((1, 21), 1)] {
+            formats.push(char_id.0);
+            varuint(&mut formats, char_id.1);
+            id(&mut formats, 1, (1, 1));
+            subblock(&mut formats, 2, &[17, *style]);
+        }
+
+        let mut contents = vec![];
+        let mut wrapped = vec![];
+        subblock(&mut wrapped, 1, &items);
+        subblock(&mut contents, 1, &wrapped);
+        let mut wrapped = vec![];
+        subblock(&mut wrapped, 1, &formats);
+        subblock(&mut contents, 2, &wrapped);
+
+        let mut text = vec![];
+        id(&mut text, 1, (0, 0));
+        subblock(&mut text, 2, &contents);
+        let mut position = vec![];
+        double(&mut position, 1, -468.0);
+        double(&mut position, 2, 234.0);
+        subblock(&mut text, 3, &position);
+        tag(&mut text, 4, BYTE4);
+        text.extend_from_slice(&936f32.to_le_bytes());
+        text
+    }
+
+    #[test]
+    fn strokes_and_text() {
+        let mut data = page();
+        block(&mut data, 0x0A, 0, &[1, 2, 3]);
+        block(&mut data, LINE_ITEM, 2, &line());
+        block(&mut data, ROOT_TEXT, 1, &text());
+        let page = Page::parse(&data).unwrap();
+        assert_eq!(page.version, 6);
+        assert!(page.warnings.is_empty(), "{:?}", page.warnings);
+        assert_eq!(page.stroke_count(), 1);
+        let stroke = &page.layers[0].strokes[0];
+        assert_eq!((stroke.pen, stroke.width), (2, 2.0));
+        assert_eq!(stroke.segments.len(), 2);
+        assert_eq!(stroke.segments[1].x, 2.0);
+        assert_eq!(stroke.segments[1].speed, 2.0);
+        assert_eq!(stroke.segments[1].width, 3.0);
+        assert_eq!(stroke.segments[1].pressure, 1.0);
+
+        assert_eq!(page.text.len(), 1);
+        let text = &page.text[0];
+        assert_eq!((text.x, text.y, text.width), (-468.0, 234.0, 936.0));
+        assert_eq!(
+            text.paragraphs,
+            vec![
+                Paragraph {
+                    text: "Title".to_string(),
+                    style: ParagraphStyle::Heading,
+                },
+                Paragraph {
+                    text: "Hello world".to_string(),
+                    style: ParagraphStyle::Plain,
+                },
+            ]
+        );
+        assert!(!page.is_empty());
+    }
+
+    #[test]
+    fn unreadable_text() {
+        let mut data = page();
+        block(&mut data, LINE_ITEM, 2, &line());
+        block(&mut data, ROOT_TEXT, 9, b"from the future");
+        let mut broken = text();
+        broken.truncate(broken.len() - 6);
+        block(&mut data, ROOT_TEXT, 1, &broken);
+        let page = Page::parse(&data).unwrap();
+        assert_eq!(page.stroke_count(), 1);
+        assert!(page.text.is_empty());
+        assert_eq!(page.warnings.len(), 2, "{:?}", page.warnings);
+        assert!(page.warnings[0].contains("version 9"));
+
+        // Only text is left out; a short block is still an error.
+        data.truncate(data.len() - 1);
+        assert!(matches!(Page::parse(&data), Err(Error::Truncated { .. })));
+    }
+
+    #[test]
+    fn empty_page() {
+        let page = Page::parse(&page()).unwrap();
+        assert!(page.is_empty());
+        assert!(page.warnings.is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+//! The `.metadata` file newer firmware keeps with each document, holding
+//! much the same as its entry in the cloud listing, and a few things the
+//! listing doesn't.
+//!
+//! Fields this crate doesn't know about are kept as they are, in their
+//! order, so a file can be parsed, changed and written back without losing
+//! anything.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::timefmt;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    #[serde(default)]
+    pub visible_name: String,
+    #[serde(rename = "type", default)]
+    pub doc_type: String,
+    /// The id of the containing folder, empty at the root.
+    #[serde(default)]
+    pub parent: String,
+    /// Written as milliseconds since the epoch, in a string.
+    #[serde(
+        with = "timefmt::optional_millis",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_modified: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub version: u64,
+    /// Whether the document is starred on the home screen. Older firmware
+    /// leaves this out, and marks starred documents as bookmarked in the
+    /// listing instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+    /// Set by the tablet once the document is in step with the cloud.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced: Option<bool>,
+    /// Set by the tablet when the contents changed since it last synced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<bool>,
+    /// Set by the tablet when this file changed since it last synced.
+    #[serde(
+        rename = "metadatamodified",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub metadata_modified: Option<bool>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Metadata {
+    pub fn parse(data: &[u8]) -> Result<Metadata> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let data = br#"{
+            "deleted": false,
+            "lastModified": "1606813364402",
+            "metadatamodified": false,
+            "modified": false,
+            "parent": "3a1f0e2c-5d0b-4a9e-9c64-0c1d8f3e2b11",
+            "pinned": true,
+            "synced": true,
+            "type": "DocumentType",
+            "version": 12,
+            "visibleName": "Dune"
+        }"#;
+        let mut metadata = Metadata::parse(data).unwrap();
+        assert_eq!(metadata.visible_name, "Dune");
+        assert_eq!(metadata.version, 12);
+        assert_eq!(metadata.pinned, Some(true));
+        assert_eq!(metadata.synced, Some(true));
+        assert_eq!(metadata.metadata_modified, Some(false));
+        let last_modified = metadata.last_modified.unwrap();
+        assert_eq!(last_modified.timestamp_millis(), 1606813364402);
+
+        metadata.pinned = Some(false);
+        let written = Metadata::parse(&metadata.to_vec()).unwrap();
+        assert_eq!(written, metadata);
+        assert_eq!(written.other.len(), 1);
+        let written = String::from_utf8(metadata.to_vec()).unwrap();
+        assert!(written.contains(r#""lastModified": "1606813364402""#));
+    }
+
+    #[test]
+    fn either_time_format() {
+        let millis = Metadata::parse(include_bytes!(
+            "../tests/fixtures/metadata_millis.json"
+        ))
+        .unwrap();
+        let rfc3339 = Metadata::parse(include_bytes!(
+            "../tests/fixtures/metadata_rfc3339.json"
+        ))
+        .unwrap();
+        assert!(millis.last_modified.is_some());
+        assert_eq!(millis.last_modified, rfc3339.last_modified);
+        // Written back as the tablet writes it.
+        let written = Metadata::parse(&rfc3339.to_vec()).unwrap();
+        assert_eq!(written.last_modified, millis.last_modified);
+        assert!(String::from_utf8(rfc3339.to_vec())
+            .unwrap()
+            .contains(r#""lastModified": "1712345678901""#));
+
+        let empty = Metadata::parse(br#"{"lastModified": ""}"#).unwrap();
+        assert_eq!(empty.last_modified, None);
+        assert!(Metadata::parse(br#"{"lastModified": "soon"}"#).is_err());
+    }
+
+    #[test]
+    fn older_firmware() {
+        let metadata =
+            Metadata::parse(br#"{"visibleName": "Dune", "version": 3}"#)
+                .unwrap();
+        assert_eq!(metadata.pinned, None);
+        let written = String::from_utf8(metadata.to_vec()).unwrap();
+        assert!(!written.contains("pinned"), "{}", written);
+        assert!(Metadata::parse(b"[]").is_err());
+    }
+}
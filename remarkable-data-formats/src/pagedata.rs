@@ -0,0 +1,39 @@
+//! The `.pagedata` file naming the template behind each page of a document,
+//! one per line, such as `Blank` or `P Lines medium`.
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PageData {
+    pub templates: Vec<String>,
+}
+
+impl PageData {
+    pub fn parse(data: &[u8]) -> PageData {
+        PageData {
+            templates: String::from_utf8_lossy(data)
+                .lines()
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.templates
+            .iter()
+            .flat_map(|t| t.bytes().chain(Some(b'\n')))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let pagedata = PageData::parse(b"Blank\nP Lines medium\n");
+        assert_eq!(pagedata.templates, vec!["Blank", "P Lines medium"]);
+        assert_eq!(pagedata.to_vec(), b"Blank\nP Lines medium\n");
+        assert_eq!(PageData::parse(b""), PageData::default());
+        assert!(PageData::default().to_vec().is_empty());
+    }
+}
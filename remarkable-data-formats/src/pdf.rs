@@ -0,0 +1,323 @@
+//! Just enough of PDF to find how big each page is, for lining up what was
+//! drawn on a PDF with its pages.
+//!
+//! Only objects written out whole in the file are read, not those packed
+//! into the compressed object streams PDF 1.5 allows. In a file written
+//! that way `page_boxes` finds no pages, and callers fall back to the size
+//! of the tablet's screen.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+
+/// A rectangle in PDF user space, in points, with y going up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PageBox {
+    pub x0: f64,
+    pub y0: f64,
+    pub x1: f64,
+    pub y1: f64,
+}
+
+impl PageBox {
+    /// The box from `[a, b, c, d]` as a PDF writes it, with the corners
+    /// in either order.
+    pub fn from_corners(corners: [f64; 4]) -> PageBox {
+        let [a, b, c, d] = corners;
+        PageBox {
+            x0: a.min(c),
+            y0: b.min(d),
+            x1: a.max(c),
+            y1: b.max(d),
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> f64 {
+        self.y1 - self.y0
+    }
+}
+
+/// The part of each page of `pdf` which is shown, in page order: its crop
+/// box, or its media box if it has none, either inherited from the page
+/// tree as PDF allows. Empty if the pages can't be found.
+pub fn page_boxes(pdf: &[u8]) -> Vec<PageBox> {
+    let objects = objects(pdf);
+    let root =
+        match rfind(pdf, b"/Root").and_then(|at| reference(&pdf[at + 5..])) {
+            Some(root) => root,
+            None => return vec![],
+        };
+    let pages = match objects
+        .get(&root)
+        .and_then(|catalog| value(catalog, b"/Pages"))
+        .and_then(reference)
+    {
+        Some(pages) => pages,
+        None => return vec![],
+    };
+    let mut boxes = vec![];
+    let mut seen = HashSet::new();
+    walk(&objects, pages, Inherited::default(), &mut seen, &mut boxes);
+    boxes
+}
+
+// The boxes a node of the page tree passes down to the pages below it.
+#[derive(Clone, Copy, Default)]
+struct Inherited {
+    media: Option<PageBox>,
+    crop: Option<PageBox>,
+}
+
+fn walk(
+    objects: &HashMap<u32, &[u8]>,
+    id: u32,
+    mut inherited: Inherited,
+    seen: &mut HashSet<u32>,
+    boxes: &mut Vec<PageBox>,
+) {
+    // A page tree which leads back on itself is only walked once.
+    if !seen.insert(id) {
+        return;
+    }
+    let dict = match objects.get(&id) {
+        Some(dict) => *dict,
+        None => return,
+    };
+    if let Some(media) = value(dict, b"/MediaBox").and_then(rectangle) {
+        inherited.media = Some(media);
+    }
+    if let Some(crop) = value(dict, b"/CropBox").and_then(rectangle) {
+        inherited.crop = Some(crop);
+    }
+    match value(dict, b"/Kids") {
+        Some(kids) => {
+            for kid in references(kids) {
+                walk(objects, kid, inherited, seen, boxes);
+            }
+        }
+        None => {
+            if let Some(page) = inherited.crop.or(inherited.media) {
+                boxes.push(page);
+            }
+        }
+    }
+}
+
+// The dictionary of each object in `pdf` by its number, as far as any
+// stream it has. A later object of the same number, from an update
+// appended to the file, replaces an earlier one.
+fn objects(pdf: &[u8]) -> HashMap<u32, &[u8]> {
+    let mut objects = HashMap::new();
+    let mut from = 0;
+    while let Some(at) = find(&pdf[from..], b" obj").map(|i| i + from) {
+        from = at + 4;
+        let number = match object_number(&pdf[..at]) {
+            Some(number) => number,
+            None => continue,
+        };
+        let body = &pdf[from..];
+        let end = [&b"endobj"[..], b"stream"]
+            .iter()
+            .filter_map(|end| find(body, end))
+            .min()
+            .unwrap_or(body.len());
+        objects.insert(number, &body[..end]);
+    }
+    objects
+}
+
+// The number of the object whose header `before` ends with, as in
+// "12 0".
+fn object_number(before: &[u8]) -> Option<u32> {
+    let text =
+        String::from_utf8_lossy(&before[before.len().saturating_sub(24)..]);
+    let mut words = text.split_ascii_whitespace().rev();
+    words.next()?.parse::<u32>().ok()?;
+    words.next()?.parse().ok()
+}
+
+// What follows the key `key` at the top level of the dictionary `dict`.
+fn value<'a>(dict: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < dict.len() {
+        match dict[i] {
+            b'<' if dict.get(i + 1) == Some(&b'<') => {
+                depth += 1;
+                i += 2;
+                continue;
+            }
+            b'>' if dict.get(i + 1) == Some(&b'>') => {
+                depth -= 1;
+                i += 2;
+                continue;
+            }
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            b'(' => {
+                i = skip_string(dict, i);
+                continue;
+            }
+            b'/' if depth == 1 && dict[i..].starts_with(key) => {
+                let end = i + key.len();
+                let delimited =
+                    dict.get(end).is_none_or(|b| !b.is_ascii_alphanumeric());
+                if delimited {
+                    return Some(&dict[end..]);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// Where the string starting at `start` ends, after its closing parenthesis.
+fn skip_string(data: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < data.len() {
+        match data[i] {
+            b'\\' => i += 1,
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    data.len()
+}
+
+// The object referred to at the start of `data`, as "12 0 R".
+fn reference(data: &[u8]) -> Option<u32> {
+    let text = leading_text(data, 32);
+    let mut words = text.split_ascii_whitespace();
+    let number = words.next()?.parse().ok()?;
+    words.next()?.parse::<u32>().ok()?;
+    if words.next()?.starts_with('R') {
+        Some(number)
+    } else {
+        None
+    }
+}
+
+// The objects referred to in the array at the start of `data`.
+fn references(data: &[u8]) -> Vec<u32> {
+    let inside = match array(data) {
+        Some(inside) => inside,
+        None => return vec![],
+    };
+    let words: Vec<&str> = inside.split_ascii_whitespace().collect();
+    words
+        .windows(3)
+        .filter(|w| w[2] == "R")
+        .filter_map(|w| w[0].parse().ok())
+        .collect()
+}
+
+// The rectangle in the array at the start of `data`.
+fn rectangle(data: &[u8]) -> Option<PageBox> {
+    let numbers: Vec<f64> = array(data)?
+        .split_ascii_whitespace()
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let corners: [f64; 4] = numbers.try_into().ok()?;
+    let page = PageBox::from_corners(corners);
+    if page.width() > 0.0 && page.height() > 0.0 {
+        Some(page)
+    } else {
+        None
+    }
+}
+
+// What's inside the brackets of the array at the start of `data`.
+fn array(data: &[u8]) -> Option<String> {
+    let start = data.iter().position(|b| !b.is_ascii_whitespace())?;
+    if data[start] != b'[' {
+        return None;
+    }
+    let end = data[start..].iter().position(|b| *b == b']')? + start;
+    Some(String::from_utf8_lossy(&data[start + 1..end]).into_owned())
+}
+
+fn leading_text(data: &[u8], len: usize) -> String {
+    String::from_utf8_lossy(&data[..data.len().min(len)])
+        .replace(|c: char| "/<>[]".contains(c), " ")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A PDF of pages with the given dictionary entries each, under a page
+    // tree with `tree` entries.
+    pub(crate) fn pdf(tree: &str, pages: &[&str]) -> Vec<u8> {
+        let mut out = String::from("%PDF-1.4\n");
+        out.push_str("1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n");
+        let kids: Vec<String> =
+            (0..pages.len()).map(|i| format!("{} 0 R", i + 3)).collect();
+        out.push_str(&format!(
+            "2 0 obj\n<< /Type /Pages /Kids [{}] /Count {} {} >>\nendobj\n",
+            kids.join(" "),
+            pages.len(),
+            tree
+        ));
+        for (i, page) in pages.iter().enumerate() {
+            out.push_str(&format!(
+                "{} 0 obj\n<< /Type /Page /Parent 2 0 R \
+                 /Resources << /Font << /F1 << /Type /Font >> >> >> {} >>\n\
+                 stream\n[1 2 3 4] /MediaBox\nendstream\nendobj\n",
+                i + 3,
+                page
+            ));
+        }
+        out.push_str("trailer\n<< /Size 9 /Root 1 0 R >>\n%%EOF\n");
+        out.into_bytes()
+    }
+
+    #[test]
+    fn boxes() {
+        let a4 = PageBox::from_corners([0.0, 0.0, 595.0, 842.0]);
+        let data = pdf(
+            "/MediaBox [0 0 595 842]",
+            &[
+                "",
+                "/MediaBox [0 0 842 595]",
+                "/CropBox [50 792 545 50]",
+                "/MediaBox [0 0 0 0]",
+            ],
+        );
+        assert_eq!(
+            page_boxes(&data),
+            [
+                a4,
+                PageBox::from_corners([0.0, 0.0, 842.0, 595.0]),
+                PageBox::from_corners([50.0, 50.0, 545.0, 792.0]),
+                a4,
+            ]
+        );
+        let cropped = page_boxes(&data)[2];
+        assert_eq!((cropped.width(), cropped.height()), (495.0, 742.0));
+
+        assert!(page_boxes(b"not a pdf").is_empty());
+        let no_tree = pdf("", &["/Contents 9 0 R"]);
+        assert!(page_boxes(&no_tree).is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! How much was drawn on pages: strokes, the length of their lines, the
+//! layers they're on and the tools they were drawn with, per page and
+//! summed over a notebook.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::ink::ERASERS;
+use crate::lines::{Page, Stroke};
+
+/// What a page has drawn on it.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct PageStats {
+    pub strokes: usize,
+    /// The length of the lines drawn, in screen pixels, leaving out those
+    /// of erasers.
+    pub ink_length: f64,
+    /// The number of layers with a stroke on them. Summed over pages, it's
+    /// the most any page uses.
+    pub layers_used: usize,
+    /// The number of strokes drawn with each tool, named by `pen_name`.
+    pub pens: BTreeMap<String, usize>,
+}
+
+impl From<&Page> for PageStats {
+    fn from(page: &Page) -> Self {
+        let mut stats = PageStats::default();
+        for layer in &page.layers {
+            if !layer.strokes.is_empty() {
+                stats.layers_used += 1;
+            }
+            for stroke in &layer.strokes {
+                stats.strokes += 1;
+                if !ERASERS.contains(&stroke.pen) {
+                    stats.ink_length += length(stroke);
+                }
+                *stats.pens.entry(pen_name(stroke.pen)).or_default() += 1;
+            }
+        }
+        stats
+    }
+}
+
+impl PageStats {
+    /// Adds what's on another page to this.
+    pub fn add(&mut self, other: &PageStats) {
+        self.strokes += other.strokes;
+        self.ink_length += other.ink_length;
+        self.layers_used = self.layers_used.max(other.layers_used);
+        for (pen, count) in &other.pens {
+            *self.pens.entry(pen.clone()).or_default() += count;
+        }
+    }
+
+    /// The sum of `pages`.
+    pub fn total<'a, I>(pages: I) -> PageStats
+    where
+        I: IntoIterator<Item = &'a PageStats>,
+    {
+        let mut total = PageStats::default();
+        for page in pages {
+            total.add(page);
+        }
+        total
+    }
+}
+
+/// The name of the tool a stroke's `pen` is, as the tablet shows it, or
+/// "pen {n}" for one this doesn't know. Versions of a tool from before and
+/// after firmware 2.x go by the same name.
+pub fn pen_name(pen: u32) -> String {
+    let name = match pen {
+        0 | 12 => "paintbrush",
+        1 | 14 => "pencil",
+        2 | 15 => "ballpoint",
+        3 | 16 => "marker",
+        4 | 17 => "fineliner",
+        5 | 18 => "highlighter",
+        6 => "eraser",
+        7 | 13 => "mechanical pencil",
+        8 => "erase area",
+        21 => "calligraphy",
+        23 => "shader",
+        _ => return format!("pen {}", pen),
+    };
+    name.to_string()
+}
+
+// The length of the line through a stroke's points.
+fn length(stroke: &Stroke) -> f64 {
+    stroke
+        .segments
+        .windows(2)
+        .map(|w| {
+            let dx = f64::from(w[1].x - w[0].x);
+            let dy = f64::from(w[1].y - w[0].y);
+            dx.hypot(dy)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lines::{Layer, Segment};
+
+    fn stroke(pen: u32, points: &[(f32, f32)]) -> Stroke {
+        Stroke {
+            pen,
+            color: 0,
+            width: 2.0,
+            segments: points
+                .iter()
+                .map(|(x, y)| Segment {
+                    x: *x,
+                    y: *y,
+                    speed: 0.0,
+                    direction: 0.0,
+                    width: 2.0,
+                    pressure: 1.0,
+                })
+                .collect(),
+        }
+    }
+
+    fn page(layers: Vec<Vec<Stroke>>) -> Page {
+        Page {
+            version: 5,
+            layers: layers
+                .into_iter()
+                .map(|strokes| Layer { strokes })
+                .collect(),
+            text: vec![],
+            warnings: vec![],
+        }
+    }
+
+    #[test]
+    fn page_stats() {
+        let drawn = page(vec![
+            vec![
+                stroke(15, &[(0.0, 0.0), (3.0, 4.0), (3.0, 14.0)]),
+                stroke(2, &[(5.0, 5.0)]),
+            ],
+            vec![],
+            vec![stroke(6, &[(0.0, 0.0), (100.0, 0.0)])],
+        ]);
+        let stats = PageStats::from(&drawn);
+        assert_eq!(stats.strokes, 3);
+        assert_eq!(stats.ink_length, 15.0);
+        assert_eq!(stats.layers_used, 2);
+        assert_eq!(stats.pens["ballpoint"], 2);
+        assert_eq!(stats.pens["eraser"], 1);
+        assert_eq!(PageStats::from(&page(vec![])), PageStats::default());
+    }
+
+    #[test]
+    fn totals() {
+        let one = PageStats::from(&page(vec![vec![stroke(
+            17,
+            &[(0.0, 0.0), (0.0, 10.0)],
+        )]]));
+        let two = PageStats::from(&page(vec![
+            vec![stroke(17, &[(0.0, 0.0), (0.0, 5.0)])],
+            vec![stroke(99, &[(0.0, 0.0), (0.0, 1.0)])],
+        ]));
+        let total = PageStats::total(&[one, two]);
+        assert_eq!(total.strokes, 3);
+        assert_eq!(total.ink_length, 16.0);
+        assert_eq!(total.layers_used, 2);
+        assert_eq!(total.pens["fineliner"], 2);
+        assert_eq!(total.pens["pen 99"], 1);
+    }
+}
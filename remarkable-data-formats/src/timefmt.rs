@@ -0,0 +1,184 @@
+//! The two ways times are written: RFC 3339, as in the cloud listing, and
+//! milliseconds since the epoch as a string ("1712345678901"), as in
+//! `.metadata` files and some newer endpoints.
+//!
+//! Either is accepted wherever a time is read, as are epochs in seconds,
+//! which some third-party tools write. What's written is the form the file
+//! or endpoint calls for, through the serde helpers here, used as
+//! `#[serde(with = "timefmt::millis")]` and so on.
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+
+/// Parses a time in any of the forms it may be written in.
+pub fn parse(s: &str) -> Result<DateTime<Utc>, String> {
+    if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) {
+        let n: i64 = s
+            .parse()
+            .map_err(|_| format!("timestamp out of range: {}", s))?;
+        // Millisecond epochs have had 13 digits since 2001, while second
+        // epochs won't reach 12 digits for thousands of years.
+        let millis = if s.len() >= 12 { n } else { n * 1000 };
+        Utc.timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| format!("timestamp out of range: {}", s))
+    } else {
+        DateTime::parse_from_rfc3339(s)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| format!("invalid time {:?}: {}", s, e))
+    }
+}
+
+/// `t` as milliseconds since the epoch, as `.metadata` files have it.
+/// Anything finer than a millisecond is dropped.
+pub fn to_millis(t: &DateTime<Utc>) -> String {
+    t.timestamp_millis().to_string()
+}
+
+/// `t` in RFC 3339, as the listing has it.
+pub fn to_rfc3339(t: &DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn deserialize_str<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    serde::Deserialize::deserialize(deserializer)
+}
+
+/// Written in RFC 3339, read in any form.
+pub mod rfc3339 {
+    use super::*;
+
+    pub fn serialize<S>(
+        t: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&to_rfc3339(t))
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        parse(&deserialize_str(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Written as a string of milliseconds since the epoch, read in any form.
+pub mod millis {
+    use super::*;
+
+    pub fn serialize<S>(
+        t: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&to_millis(t))
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        parse(&deserialize_str(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// As `millis`, with an empty string read as no time. Meant to go with
+/// `default` and `skip_serializing_if = "Option::is_none"`.
+pub mod optional_millis {
+    use super::*;
+
+    pub fn serialize<S>(
+        t: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match t {
+            Some(t) => millis::serialize(t, serializer),
+            None => serializer.serialize_str(""),
+        }
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = deserialize_str(deserializer)?;
+        if s.is_empty() {
+            return Ok(None);
+        }
+        parse(&s).map(Some).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient() {
+        let expected = "2020-12-01T09:02:44+00:00";
+        let parsed = |s: &str| parse(s).map(|t| t.to_rfc3339());
+        assert_eq!(parsed("2020-12-01T09:02:44Z").unwrap(), expected);
+        assert_eq!(parsed("2020-12-01T09:02:44.000Z").unwrap(), expected);
+        assert_eq!(parsed("2020-12-01T10:02:44+01:00").unwrap(), expected);
+        assert_eq!(parsed("1606813364").unwrap(), expected);
+        assert_eq!(parsed("1606813364000").unwrap(), expected);
+        assert!(parsed("").is_err());
+        assert!(parsed("yesterday").is_err());
+        assert!(parsed("-1606813364").is_err());
+        assert!(parsed("99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn emitted() {
+        let t = parse("2024-04-05T19:34:38.901Z").unwrap();
+        assert_eq!(to_millis(&t), "1712345678901");
+        assert_eq!(to_rfc3339(&t), "2024-04-05T19:34:38.901Z");
+        // The same as chrono's own serialization, which the listing cache
+        // was written with.
+        assert_eq!(
+            serde_json::to_value(to_rfc3339(&t)).unwrap(),
+            serde_json::to_value(t).unwrap()
+        );
+    }
+
+    // Every millisecond time survives being written and read back in
+    // either form, checked over a spread of times from 1973, before which
+    // epochs in milliseconds are too short to tell from ones in seconds,
+    // to 2100.
+    #[test]
+    fn round_trips() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let start = 100_000_000_000;
+        let end = parse("2100-01-01T00:00:00Z").unwrap().timestamp_millis();
+        let mut samples = vec![start, start + 1, end - 1];
+        for _ in 0..10_000 {
+            // xorshift64
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            samples.push(start + (seed % (end - start) as u64) as i64);
+        }
+        for millis in samples {
+            let t = Utc.timestamp_millis_opt(millis).unwrap();
+            assert_eq!(parse(&to_millis(&t)).unwrap(), t, "{}", millis);
+            assert_eq!(parse(&to_rfc3339(&t)).unwrap(), t, "{}", millis);
+        }
+    }
+}